@@ -0,0 +1,43 @@
+//! Picks the aruco api of the installed opencv.
+//! Opencv 4.7 and later have charuco boards in objdetect, older versions only in the contrib aruco module.
+//! The version is taken from IMAGE_PROC_OPENCV_VERSION when set, otherwise from pkg-config.
+
+use std::process::Command;
+
+/// The first opencv version with ArucoDetector and CharucoDetector in objdetect
+const OBJDETECT_ARUCO: (u32, u32) = (4, 7);
+
+fn opencv_version() -> Option<(u32, u32)> {
+    let version = match std::env::var("IMAGE_PROC_OPENCV_VERSION") {
+        Ok(v) => v,
+        Err(_) => {
+            let out = Command::new("pkg-config")
+                .args(["--modversion", "opencv4"])
+                .output()
+                .ok()?;
+            if !out.status.success() {
+                return None;
+            }
+            String::from_utf8(out.stdout).ok()?
+        }
+    };
+    let mut parts = version.trim().split('.');
+    let major = parts.next()?.parse().ok()?;
+    let minor = parts.next().unwrap_or("0").parse().ok()?;
+    Some((major, minor))
+}
+
+fn main() {
+    println!("cargo::rustc-check-cfg=cfg(ocv_objdetect_aruco)");
+    println!("cargo::rerun-if-changed=build.rs");
+    println!("cargo::rerun-if-env-changed=IMAGE_PROC_OPENCV_VERSION");
+    println!("cargo::rerun-if-env-changed=PKG_CONFIG_PATH");
+    match opencv_version() {
+        Some(v) if v >= OBJDETECT_ARUCO => println!("cargo::rustc-cfg=ocv_objdetect_aruco"),
+        Some(_) => {}
+        // Without a version the contrib module is assumed, it is still there in newer versions
+        None => println!(
+            "cargo::warning=Could not find the opencv version, using the contrib aruco module"
+        ),
+    }
+}
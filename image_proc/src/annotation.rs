@@ -0,0 +1,359 @@
+//! A layer of annotations drawn over the displayed image, for marking up findings
+
+use std::io::{Read, Write};
+
+use eframe::egui::{Color32, ColorImage, Pos2, Rect, Stroke};
+
+/// A shape drawn on the annotation layer, with coordinates in image pixels
+#[derive(Clone, Debug, serde::Serialize, serde::Deserialize)]
+pub enum AnnotationShape {
+    Arrow([f32; 2], [f32; 2]),
+    Rectangle([f32; 2], [f32; 2]),
+    Text([f32; 2], String),
+    Freehand(Vec<[f32; 2]>),
+}
+
+/// A single annotation
+#[derive(Clone, Debug, serde::Serialize, serde::Deserialize)]
+pub struct Annotation {
+    pub shape: AnnotationShape,
+    /// The color in rgb
+    pub color: [u8; 3],
+    /// The line width in image pixels, text is ten times this tall
+    pub width: f32,
+}
+
+/// A set of annotations that can be saved separately from the image
+#[derive(Clone, Debug, Default, serde::Serialize, serde::Deserialize)]
+pub struct AnnotationLayer {
+    pub annotations: Vec<Annotation>,
+}
+
+impl AnnotationLayer {
+    /// Load a layer from a file
+    pub fn load(path: &std::path::Path) -> Option<Self> {
+        let mut f = std::fs::File::open(path).ok()?;
+        let mut c = Vec::new();
+        f.read_to_end(&mut c).ok()?;
+        bincode::serde::decode_from_slice(&c, bincode::config::standard())
+            .ok()
+            .map(|(l, _)| l)
+    }
+
+    /// Save the layer to a file
+    pub fn save(&self, path: &std::path::Path) -> std::io::Result<()> {
+        let data = bincode::serde::encode_to_vec(self, bincode::config::standard())
+            .map_err(std::io::Error::other)?;
+        let mut f = std::fs::File::create(path)?;
+        f.write_all(&data)
+    }
+
+    /// Draw the annotations into the pixels of a copy of the image
+    pub fn flatten(&self, img: &ColorImage) -> Option<ColorImage> {
+        let mut m = crate::convert::color_image_to_mat(img)?;
+        let pt = |p: &[f32; 2]| opencv::core::Point::new(p[0].round() as i32, p[1].round() as i32);
+        for a in &self.annotations {
+            let color = opencv::core::Scalar::new(
+                a.color[0] as f64,
+                a.color[1] as f64,
+                a.color[2] as f64,
+                255.0,
+            );
+            let thickness = (a.width.round() as i32).max(1);
+            let line_type = opencv::imgproc::LINE_AA;
+            match &a.shape {
+                AnnotationShape::Arrow(from, to) => {
+                    opencv::imgproc::arrowed_line(
+                        &mut m,
+                        pt(from),
+                        pt(to),
+                        color,
+                        thickness,
+                        line_type,
+                        0,
+                        0.25,
+                    )
+                    .ok()?;
+                }
+                AnnotationShape::Rectangle(p0, p1) => {
+                    opencv::imgproc::rectangle_points(
+                        &mut m,
+                        pt(p0),
+                        pt(p1),
+                        color,
+                        thickness,
+                        line_type,
+                        0,
+                    )
+                    .ok()?;
+                }
+                AnnotationShape::Text(p, text) => {
+                    let scale = 10.0 * a.width as f64 / 22.0;
+                    let origin = opencv::core::Point::new(
+                        p[0].round() as i32,
+                        (p[1] + 10.0 * a.width).round() as i32,
+                    );
+                    opencv::imgproc::put_text(
+                        &mut m,
+                        text,
+                        origin,
+                        opencv::imgproc::FONT_HERSHEY_SIMPLEX,
+                        scale,
+                        color,
+                        thickness,
+                        line_type,
+                        false,
+                    )
+                    .ok()?;
+                }
+                AnnotationShape::Freehand(points) => {
+                    for w in points.windows(2) {
+                        opencv::imgproc::line(
+                            &mut m,
+                            pt(&w[0]),
+                            pt(&w[1]),
+                            color,
+                            thickness,
+                            line_type,
+                            0,
+                        )
+                        .ok()?;
+                    }
+                }
+            }
+        }
+        crate::convert::mat_to_color_image(&m)
+    }
+}
+
+/// The kinds of annotations that can be drawn
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Tool {
+    Arrow,
+    Rectangle,
+    Text,
+    Freehand,
+}
+
+/// Maps between image pixels and screen positions for the displayed image
+struct Transform {
+    rect: Rect,
+    size: [usize; 2],
+}
+
+impl Transform {
+    fn to_image(&self, p: Pos2) -> [f32; 2] {
+        [
+            (p.x - self.rect.min.x) / self.rect.width() * self.size[0] as f32,
+            (p.y - self.rect.min.y) / self.rect.height() * self.size[1] as f32,
+        ]
+    }
+
+    fn to_screen(&self, p: &[f32; 2]) -> Pos2 {
+        Pos2::new(
+            self.rect.min.x + p[0] / self.size[0] as f32 * self.rect.width(),
+            self.rect.min.y + p[1] / self.size[1] as f32 * self.rect.height(),
+        )
+    }
+
+    /// The number of screen points per image pixel
+    fn scale(&self) -> f32 {
+        self.rect.width() / self.size[0] as f32
+    }
+}
+
+/// The annotation layer along with the state of the drawing tools
+pub struct AnnotationTool {
+    pub layer: AnnotationLayer,
+    tool: Option<Tool>,
+    color: [u8; 3],
+    width: f32,
+    text: String,
+    visible: bool,
+    in_progress: Option<AnnotationShape>,
+}
+
+impl Default for AnnotationTool {
+    fn default() -> Self {
+        Self {
+            layer: Default::default(),
+            tool: None,
+            color: [255, 0, 0],
+            width: 3.0,
+            text: String::new(),
+            visible: true,
+            in_progress: None,
+        }
+    }
+}
+
+impl AnnotationTool {
+    /// Show the annotation toolbar, image is the image that would be flattened for export
+    pub fn show_toolbar(&mut self, ui: &mut eframe::egui::Ui, image: Option<&ColorImage>) {
+        ui.horizontal(|ui| {
+            ui.label(tr!("annotation.annotate"));
+            ui.selectable_value(&mut self.tool, None, tr!("annotation.off"));
+            ui.selectable_value(&mut self.tool, Some(Tool::Arrow), tr!("annotation.arrow"));
+            ui.selectable_value(
+                &mut self.tool,
+                Some(Tool::Rectangle),
+                tr!("annotation.rectangle"),
+            );
+            ui.selectable_value(&mut self.tool, Some(Tool::Text), tr!("annotation.text"));
+            ui.selectable_value(
+                &mut self.tool,
+                Some(Tool::Freehand),
+                tr!("annotation.freehand"),
+            );
+            ui.color_edit_button_srgb(&mut self.color);
+            ui.add(
+                eframe::egui::DragValue::new(&mut self.width)
+                    .range(1.0..=50.0)
+                    .prefix(tr!("annotation.width")),
+            );
+            if self.tool == Some(Tool::Text) {
+                ui.text_edit_singleline(&mut self.text);
+            }
+            ui.checkbox(&mut self.visible, tr!("annotation.show"));
+            if ui.button(tr!("annotation.undo")).clicked() {
+                self.layer.annotations.pop();
+            }
+            if ui.button(tr!("annotation.clear")).clicked() {
+                self.layer.annotations.clear();
+            }
+            if ui.button(tr!("annotation.save")).clicked() {
+                let f = rfd::FileDialog::new()
+                    .add_filter("Annotations", &["annotations"])
+                    .set_directory("./")
+                    .save_file();
+                if let Some(f) = f {
+                    if let Err(e) = self.layer.save(&f) {
+                        println!("Failed to save annotations {:?}", e);
+                    }
+                }
+            }
+            if ui.button(tr!("annotation.load")).clicked() {
+                let f = rfd::FileDialog::new()
+                    .add_filter("Annotations", &["annotations"])
+                    .set_directory("./")
+                    .pick_file();
+                if let Some(l) = f.and_then(|f| AnnotationLayer::load(&f)) {
+                    self.layer = l;
+                }
+            }
+            if let Some(img) = image {
+                if ui.button(tr!("annotation.export")).clicked() {
+                    let f = rfd::FileDialog::new()
+                        .add_filter("Image", &["png", "jpg"])
+                        .set_directory("./")
+                        .save_file();
+                    if let Some(f) = f {
+                        if let Some(flat) = self.layer.flatten(img) {
+                            if let Err(e) = crate::convert::save_color_image(&f, &flat) {
+                                println!("Failed to export annotated image {:?}", e);
+                            }
+                        }
+                    }
+                }
+            }
+        });
+    }
+
+    /// Draw the annotations into a copy of the image if they are being shown
+    pub fn flatten_visible(&self, img: ColorImage) -> ColorImage {
+        if self.visible && !self.layer.annotations.is_empty() {
+            self.layer.flatten(&img).unwrap_or(img)
+        } else {
+            img
+        }
+    }
+
+    /// Handle drawing on the displayed image and paint the layer over it.
+    /// response is the response of the image widget, size is the size of the image in pixels.
+    pub fn interact(
+        &mut self,
+        ui: &eframe::egui::Ui,
+        response: &eframe::egui::Response,
+        size: [usize; 2],
+    ) {
+        let t = Transform {
+            rect: response.rect,
+            size,
+        };
+        if let (Some(tool), Some(pos)) = (self.tool, response.interact_pointer_pos()) {
+            let p = t.to_image(pos);
+            if tool == Tool::Text {
+                if response.clicked() && !self.text.is_empty() {
+                    self.push(AnnotationShape::Text(p, self.text.clone()));
+                }
+            } else if response.drag_started() {
+                self.in_progress = Some(match tool {
+                    Tool::Arrow => AnnotationShape::Arrow(p, p),
+                    Tool::Rectangle => AnnotationShape::Rectangle(p, p),
+                    Tool::Freehand | Tool::Text => AnnotationShape::Freehand(vec![p]),
+                });
+            } else if response.dragged() {
+                match &mut self.in_progress {
+                    Some(AnnotationShape::Arrow(_, b)) | Some(AnnotationShape::Rectangle(_, b)) => {
+                        *b = p
+                    }
+                    Some(AnnotationShape::Freehand(points)) => points.push(p),
+                    _ => {}
+                }
+            }
+        }
+        if response.drag_stopped() {
+            if let Some(s) = self.in_progress.take() {
+                self.push(s);
+            }
+        }
+        if !self.visible {
+            return;
+        }
+        let painter = ui.painter_at(response.rect);
+        let current = self.in_progress.as_ref().map(|s| Annotation {
+            shape: s.clone(),
+            color: self.color,
+            width: self.width,
+        });
+        for a in self.layer.annotations.iter().chain(current.iter()) {
+            let color = Color32::from_rgb(a.color[0], a.color[1], a.color[2]);
+            let stroke = Stroke::new(a.width * t.scale(), color);
+            match &a.shape {
+                AnnotationShape::Arrow(from, to) => {
+                    let from = t.to_screen(from);
+                    painter.arrow(from, t.to_screen(to) - from, stroke);
+                }
+                AnnotationShape::Rectangle(p0, p1) => {
+                    painter.rect_stroke(
+                        Rect::from_two_pos(t.to_screen(p0), t.to_screen(p1)),
+                        0.0,
+                        stroke,
+                        eframe::egui::StrokeKind::Middle,
+                    );
+                }
+                AnnotationShape::Text(p, text) => {
+                    painter.text(
+                        t.to_screen(p),
+                        eframe::egui::Align2::LEFT_TOP,
+                        text,
+                        eframe::egui::FontId::proportional(10.0 * a.width * t.scale()),
+                        color,
+                    );
+                }
+                AnnotationShape::Freehand(points) => {
+                    let points = points.iter().map(|p| t.to_screen(p)).collect();
+                    painter.add(eframe::egui::Shape::line(points, stroke));
+                }
+            }
+        }
+    }
+
+    fn push(&mut self, shape: AnnotationShape) {
+        self.layer.annotations.push(Annotation {
+            shape,
+            color: self.color,
+            width: self.width,
+        });
+    }
+}
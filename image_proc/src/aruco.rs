@@ -0,0 +1,338 @@
+//! The aruco markers and charuco boards of opencv behind one interface.
+//! Opencv 4.7 moved them from the contrib aruco module into objdetect and deprecated the old functions.
+//! The build script sets the `ocv_objdetect_aruco` cfg when the installed opencv has the objdetect versions,
+//! otherwise the contrib aruco module is used.
+
+use opencv::core::{Mat, Point2f, Point3f, Size, Vector};
+#[cfg(ocv_objdetect_aruco)]
+use opencv::objdetect::{
+    ArucoDetectorTraitConst, BoardTraitConst, CharucoBoardTraitConst, CharucoDetectorTraitConst,
+};
+
+/// The outlines of markers, each the four corners of a marker in pixels
+pub type Outlines = Vector<Vector<Point2f>>;
+
+/// A set of markers that can be told apart
+#[cfg(ocv_objdetect_aruco)]
+pub type Dictionary = opencv::objdetect::Dictionary;
+/// A set of markers that can be told apart
+#[cfg(not(ocv_objdetect_aruco))]
+pub type Dictionary = opencv::core::Ptr<opencv::aruco::Dictionary>;
+
+/// A chessboard with a marker in each white square
+#[cfg(ocv_objdetect_aruco)]
+pub type CharucoBoard = opencv::objdetect::CharucoBoard;
+/// A chessboard with a marker in each white square
+#[cfg(not(ocv_objdetect_aruco))]
+pub type CharucoBoard = opencv::core::Ptr<opencv::aruco::CharucoBoard>;
+
+// The predefined dictionaries, the values are the same in both modules
+pub const DICT_4X4_1000: i32 = 3;
+pub const DICT_5X5_1000: i32 = 7;
+pub const DICT_6X6_1000: i32 = 11;
+pub const DICT_7X7_1000: i32 = 15;
+pub const DICT_APRILTAG_36H11: i32 = 20;
+
+/// The dictionaries that boards can be made from, with their names
+pub const DICTIONARIES: [(i32, &str); 4] = [
+    (DICT_4X4_1000, "4x4"),
+    (DICT_5X5_1000, "5x5"),
+    (DICT_6X6_1000, "6x6"),
+    (DICT_7X7_1000, "7x7"),
+];
+
+/// One of the predefined dictionaries, like DICT_6X6_1000
+pub fn dictionary(id: i32) -> opencv::Result<Dictionary> {
+    #[cfg(ocv_objdetect_aruco)]
+    {
+        opencv::objdetect::get_predefined_dictionary_i32(id)
+    }
+    #[cfg(not(ocv_objdetect_aruco))]
+    {
+        opencv::aruco::Dictionary::get(id)
+    }
+}
+
+/// A board of squares_x by squares_y squares, the lengths are in meters
+pub fn charuco_board(
+    squares_x: i32,
+    squares_y: i32,
+    square_length: f32,
+    marker_length: f32,
+    dictionary: &Dictionary,
+) -> opencv::Result<CharucoBoard> {
+    #[cfg(ocv_objdetect_aruco)]
+    {
+        opencv::objdetect::CharucoBoard::new_def(
+            Size::new(squares_x, squares_y),
+            square_length,
+            marker_length,
+            dictionary,
+        )
+    }
+    #[cfg(not(ocv_objdetect_aruco))]
+    {
+        opencv::aruco::CharucoBoard::create(
+            squares_x,
+            squares_y,
+            square_length,
+            marker_length,
+            dictionary,
+        )
+    }
+}
+
+/// A board like charuco_board with the marker ids starting at first_id instead of 0,
+/// so it can be told apart from a board of the same dictionary in the same image
+pub fn charuco_board_from_id(
+    squares_x: i32,
+    squares_y: i32,
+    square_length: f32,
+    marker_length: f32,
+    dictionary: &Dictionary,
+    first_id: i32,
+) -> opencv::Result<CharucoBoard> {
+    // A marker is in every other square
+    let ids: Vector<i32> = (first_id..first_id + squares_x * squares_y / 2).collect();
+    #[cfg(ocv_objdetect_aruco)]
+    {
+        opencv::objdetect::CharucoBoard::new(
+            Size::new(squares_x, squares_y),
+            square_length,
+            marker_length,
+            dictionary,
+            &ids,
+        )
+    }
+    #[cfg(not(ocv_objdetect_aruco))]
+    {
+        let mut board = opencv::aruco::CharucoBoard::create(
+            squares_x,
+            squares_y,
+            square_length,
+            marker_length,
+            dictionary,
+        )?;
+        opencv::aruco::BoardTrait::set_ids(&mut board, ids);
+        Ok(board)
+    }
+}
+
+/// The layout of a charuco board as plain numbers, so each thread needing the board can make its own
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct CharucoParams {
+    /// The number of squares across the board
+    pub squares_x: i32,
+    /// The number of squares down the board
+    pub squares_y: i32,
+    /// The side length of a square in meters
+    pub square_length: f32,
+    /// The side length of a marker in meters
+    pub marker_length: f32,
+    /// The predefined dictionary of the markers, like DICT_6X6_1000
+    pub dictionary: i32,
+    /// The id of the first marker, see charuco_board_from_id
+    pub first_id: i32,
+}
+
+impl CharucoParams {
+    /// Make the board and its dictionary
+    pub fn make(&self) -> opencv::Result<(CharucoBoard, Dictionary)> {
+        let d = dictionary(self.dictionary)?;
+        let board = if self.first_id == 0 {
+            charuco_board(
+                self.squares_x,
+                self.squares_y,
+                self.square_length,
+                self.marker_length,
+                &d,
+            )?
+        } else {
+            charuco_board_from_id(
+                self.squares_x,
+                self.squares_y,
+                self.square_length,
+                self.marker_length,
+                &d,
+                self.first_id,
+            )?
+        };
+        Ok((board, d))
+    }
+}
+
+/// Draw a board into an image of a size, with a margin and a border around each marker in bits
+pub fn draw_board(
+    board: &mut CharucoBoard,
+    size: Size,
+    margin: i32,
+    border_bits: i32,
+) -> opencv::Result<Mat> {
+    let mut img = Mat::default();
+    #[cfg(ocv_objdetect_aruco)]
+    board.generate_image(size, &mut img, margin, border_bits)?;
+    #[cfg(not(ocv_objdetect_aruco))]
+    opencv::aruco::CharucoBoardTrait::draw(board, size, &mut img, margin, border_bits)?;
+    Ok(img)
+}
+
+/// Draw a single marker of a dictionary into a square image, with a border in bits
+pub fn draw_marker(
+    dictionary: &Dictionary,
+    id: i32,
+    side: i32,
+    border_bits: i32,
+) -> opencv::Result<Mat> {
+    let mut img = Mat::default();
+    #[cfg(ocv_objdetect_aruco)]
+    opencv::objdetect::generate_image_marker(dictionary, id, side, &mut img, border_bits)?;
+    #[cfg(not(ocv_objdetect_aruco))]
+    opencv::aruco::draw_marker(dictionary, id, side, &mut img, border_bits)?;
+    Ok(img)
+}
+
+/// The positions of the chessboard corners of a board, in meters on the board
+pub fn chessboard_corners(board: &CharucoBoard) -> opencv::Result<Vector<Point3f>> {
+    #[cfg(ocv_objdetect_aruco)]
+    {
+        board.get_chessboard_corners()
+    }
+    #[cfg(not(ocv_objdetect_aruco))]
+    {
+        Ok(opencv::aruco::CharucoBoardTraitConst::chessboard_corners(
+            board,
+        ))
+    }
+}
+
+/// Find the markers of a dictionary in an image, returning the outlines and ids of the markers and the rejected outlines
+pub fn detect_markers(
+    img: &Mat,
+    dictionary: &Dictionary,
+) -> opencv::Result<(Outlines, Vector<i32>, Outlines)> {
+    let mut markers = Outlines::new();
+    let mut ids = Vector::<i32>::new();
+    let mut rejected = Outlines::new();
+    #[cfg(ocv_objdetect_aruco)]
+    {
+        let detector = opencv::objdetect::ArucoDetector::new(
+            dictionary,
+            &opencv::objdetect::DetectorParameters::default()?,
+            opencv::objdetect::RefineParameters::new_def()?,
+        )?;
+        detector.detect_markers(img, &mut markers, &mut ids, &mut rejected)?;
+    }
+    #[cfg(not(ocv_objdetect_aruco))]
+    opencv::aruco::detect_markers(
+        img,
+        dictionary,
+        &mut markers,
+        &mut ids,
+        &opencv::aruco::DetectorParameters::create()?,
+        &mut rejected,
+    )?;
+    Ok((markers, ids, rejected))
+}
+
+/// Find the chessboard corners of a board between markers that were already found, returning the corners and their ids
+pub fn interpolate_corners(
+    markers: &Outlines,
+    marker_ids: &Vector<i32>,
+    img: &Mat,
+    board: &CharucoBoard,
+) -> opencv::Result<(Vector<Point2f>, Vector<i32>)> {
+    let mut corners = Vector::<Point2f>::new();
+    let mut ids = Vector::<i32>::new();
+    #[cfg(ocv_objdetect_aruco)]
+    {
+        // The detector only searches for markers itself when it is given none
+        let detector = opencv::objdetect::CharucoDetector::new_def(board)?;
+        let mut markers = markers.clone();
+        let mut marker_ids = marker_ids.clone();
+        detector.detect_board(img, &mut corners, &mut ids, &mut markers, &mut marker_ids)?;
+    }
+    #[cfg(not(ocv_objdetect_aruco))]
+    opencv::aruco::interpolate_corners_charuco_def(
+        markers,
+        marker_ids,
+        img,
+        board,
+        &mut corners,
+        &mut ids,
+    )?;
+    Ok((corners, ids))
+}
+
+/// Draw chessboard corners into an image
+pub fn draw_corners(
+    img: &mut Mat,
+    corners: &Vector<Point2f>,
+    color: opencv::core::Scalar,
+) -> opencv::Result<()> {
+    #[cfg(ocv_objdetect_aruco)]
+    {
+        opencv::objdetect::draw_detected_corners_charuco(
+            img,
+            corners,
+            &opencv::core::no_array(),
+            color,
+        )
+    }
+    #[cfg(not(ocv_objdetect_aruco))]
+    {
+        opencv::aruco::draw_detected_corners_charuco(img, corners, &opencv::core::no_array(), color)
+    }
+}
+
+/// Calibrate a camera from the chessboard corners found in views of a board, returning the rms reprojection error
+#[allow(clippy::too_many_arguments)]
+pub fn calibrate_camera_charuco(
+    corners: &Vector<Vector<Point2f>>,
+    ids: &Vector<Vector<i32>>,
+    board: &CharucoBoard,
+    size: Size,
+    camera_matrix: &mut Mat,
+    dist_coeffs: &mut Mat,
+    flags: i32,
+    criteria: opencv::core::TermCriteria,
+) -> opencv::Result<f64> {
+    #[cfg(ocv_objdetect_aruco)]
+    {
+        // The board gives the points on the board of the corners, then it is an ordinary calibration
+        let mut object = Vector::<Vector<Point3f>>::new();
+        let mut image = Vector::<Vector<Point2f>>::new();
+        for (c, i) in corners.iter().zip(ids.iter()) {
+            let mut o = Vector::<Point3f>::new();
+            let mut p = Vector::<Point2f>::new();
+            board.match_image_points(&c, &i, &mut o, &mut p)?;
+            object.push(o);
+            image.push(p);
+        }
+        opencv::calib3d::calibrate_camera(
+            &object,
+            &image,
+            size,
+            camera_matrix,
+            dist_coeffs,
+            &mut opencv::core::no_array(),
+            &mut opencv::core::no_array(),
+            flags,
+            criteria,
+        )
+    }
+    #[cfg(not(ocv_objdetect_aruco))]
+    {
+        opencv::aruco::calibrate_camera_charuco(
+            corners,
+            ids,
+            board,
+            size,
+            camera_matrix,
+            dist_coeffs,
+            &mut opencv::core::no_array(),
+            &mut opencv::core::no_array(),
+            flags,
+            criteria,
+        )
+    }
+}
@@ -0,0 +1,245 @@
+//! A log of the significant actions taken in the program, kept for traceability of how results were produced
+
+use std::{
+    io::Write,
+    path::{Path, PathBuf},
+};
+
+/// The kinds of files written by the program
+#[derive(Clone, Copy, Debug, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FileKind {
+    Still,
+    View,
+    Montage,
+    Board,
+    Calibration,
+    /// A frame captured for calibration
+    Capture,
+    Report,
+    /// A directory of frames, like the contents of the ring buffer
+    Frames,
+}
+
+impl FileKind {
+    fn name(&self) -> String {
+        match self {
+            FileKind::Still => tr!("audit.still"),
+            FileKind::View => tr!("audit.view"),
+            FileKind::Montage => tr!("audit.montage"),
+            FileKind::Board => tr!("audit.board"),
+            FileKind::Calibration => tr!("audit.calibration"),
+            FileKind::Capture => tr!("audit.capture"),
+            FileKind::Report => tr!("audit.report"),
+            FileKind::Frames => tr!("audit.frames"),
+        }
+    }
+}
+
+/// Something that was done
+#[derive(Clone, Debug, serde::Serialize, serde::Deserialize)]
+#[serde(tag = "action", rename_all = "snake_case")]
+pub enum Event {
+    CameraOpened {
+        camera: i32,
+        source: String,
+    },
+    CameraClosed {
+        camera: i32,
+        source: String,
+    },
+    /// A frame was captured for calibration
+    FrameCaptured {
+        camera: Option<i32>,
+        /// The number of captures held after this one
+        count: usize,
+    },
+    CalibrationRun {
+        camera: String,
+        /// A description of the calibration board
+        board: String,
+        images: usize,
+        /// The rms reprojection error in pixels, None when the calibration failed
+        rms: Option<f64>,
+    },
+    CalibrationLoaded {
+        path: PathBuf,
+        /// How the contents of the file were checked
+        verification: String,
+    },
+    FileWritten {
+        kind: FileKind,
+        path: PathBuf,
+    },
+}
+
+impl Event {
+    /// A description of the event for the user
+    fn describe(&self) -> String {
+        match self {
+            Event::CameraOpened { source, .. } => tr!("audit.camera_opened", name = source),
+            Event::CameraClosed { source, .. } => tr!("audit.camera_closed", name = source),
+            Event::FrameCaptured { count, .. } => tr!("audit.frame_captured", count = count),
+            Event::CalibrationRun {
+                camera,
+                board,
+                images,
+                rms: Some(rms),
+            } => tr!(
+                "audit.calibration_run",
+                camera = camera,
+                board = board,
+                images = images,
+                rms = format!("{:.4}", rms)
+            ),
+            Event::CalibrationRun {
+                camera,
+                board,
+                images,
+                rms: None,
+            } => tr!(
+                "audit.calibration_failed",
+                camera = camera,
+                board = board,
+                images = images
+            ),
+            Event::CalibrationLoaded { path, verification } => tr!(
+                "audit.calibration_loaded",
+                path = path.display(),
+                verification = verification
+            ),
+            Event::FileWritten { kind, path } => {
+                tr!(
+                    "audit.file_written",
+                    kind = kind.name(),
+                    path = path.display()
+                )
+            }
+        }
+    }
+}
+
+/// A single line of the log
+#[derive(Clone, Debug, serde::Serialize, serde::Deserialize)]
+pub struct AuditEntry {
+    /// When it happened, in rfc 3339 format
+    pub timestamp: String,
+    #[serde(flatten)]
+    pub event: Event,
+}
+
+/// The log, stored as one json object per line so it is only ever appended to
+pub struct AuditLog {
+    file: std::fs::File,
+    path: PathBuf,
+    /// Every entry, oldest first
+    entries: Vec<AuditEntry>,
+    /// Only show entries containing this text
+    filter: String,
+    error: Option<String>,
+}
+
+impl AuditLog {
+    /// The name of the log file in the working directory
+    pub const FILE: &str = "audit_log.jsonl";
+
+    /// Open the log in a directory, creating it if needed
+    pub fn open(dir: &Path) -> std::io::Result<Self> {
+        std::fs::create_dir_all(dir)?;
+        let path = dir.join(Self::FILE);
+        // Lines that can no longer be read are skipped rather than losing the whole log
+        let entries = std::fs::read_to_string(&path)
+            .unwrap_or_default()
+            .lines()
+            .filter_map(|l| serde_json::from_str(l).ok())
+            .collect();
+        let file = std::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&path)?;
+        Ok(Self {
+            file,
+            path,
+            entries,
+            filter: String::new(),
+            error: None,
+        })
+    }
+
+    /// Add an event to the log
+    pub fn record(&mut self, event: Event) -> std::io::Result<()> {
+        let entry = AuditEntry {
+            timestamp: chrono::Local::now().to_rfc3339(),
+            event,
+        };
+        let mut line = serde_json::to_string(&entry).map_err(std::io::Error::other)?;
+        line.push('\n');
+        self.file.write_all(line.as_bytes())?;
+        self.file.flush()?;
+        self.entries.push(entry);
+        Ok(())
+    }
+
+    /// The contents of the log file, for exporting it with other results
+    pub fn contents(&self) -> std::io::Result<Vec<u8>> {
+        std::fs::read(&self.path)
+    }
+
+    /// Show the log, newest first
+    pub fn show(&mut self, ui: &mut eframe::egui::Ui) {
+        ui.horizontal(|ui| {
+            ui.label(tr!("audit.filter"));
+            ui.text_edit_singleline(&mut self.filter);
+            if ui.button(tr!("audit.export")).clicked() {
+                if let Some(f) = rfd::FileDialog::new()
+                    .add_filter("JSON lines", &["jsonl"])
+                    .set_file_name(Self::FILE)
+                    .set_directory("./")
+                    .save_file()
+                {
+                    self.error = self
+                        .contents()
+                        .and_then(|c| std::fs::write(&f, c))
+                        .err()
+                        .map(|e| e.to_string());
+                }
+            }
+        });
+        if let Some(e) = &self.error {
+            ui.colored_label(eframe::egui::Color32::RED, e);
+        }
+        ui.label(self.path.display().to_string());
+        let filter = self.filter.to_lowercase();
+        let entries: Vec<(&AuditEntry, String)> = self
+            .entries
+            .iter()
+            .rev()
+            .map(|e| (e, e.event.describe()))
+            .filter(|(e, d)| {
+                filter.is_empty()
+                    || d.to_lowercase().contains(&filter)
+                    || e.timestamp.contains(&filter)
+            })
+            .collect();
+        if entries.is_empty() {
+            ui.label(tr!("audit.empty"));
+            return;
+        }
+        eframe::egui::ScrollArea::vertical()
+            .max_height(400.0)
+            .show(ui, |ui| {
+                eframe::egui::Grid::new("audit_log")
+                    .striped(true)
+                    .show(ui, |ui| {
+                        ui.strong(tr!("history.time"));
+                        ui.strong(tr!("audit.action"));
+                        ui.end_row();
+                        for (e, d) in entries {
+                            ui.monospace(&e.timestamp);
+                            ui.label(d);
+                            ui.end_row();
+                        }
+                    });
+            });
+    }
+}
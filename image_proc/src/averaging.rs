@@ -0,0 +1,185 @@
+//! Averaging of consecutive camera frames to reduce noise
+
+use std::collections::VecDeque;
+
+use eframe::egui::ecolor::{gamma_u8_from_linear_f32, linear_f32_from_gamma_u8};
+use opencv::core::{MatTraitConst, MatTraitConstManual, MatTraitManual};
+
+/// How frames from a camera are averaged
+#[derive(Clone, Copy, Debug, PartialEq, serde::Serialize)]
+pub enum Averaging {
+    /// Frames are passed through unchanged
+    Off,
+    /// The mean of the specified number of most recent frames
+    RunningMean(usize),
+    /// An exponential moving average, with the specified weight given to the newest frame
+    Exponential(f64),
+}
+
+impl Averaging {
+    /// The name of the averaging mode as shown in the ui
+    pub fn name(&self) -> String {
+        match self {
+            Averaging::Off => tr!("averaging.off"),
+            Averaging::RunningMean(_) => tr!("averaging.running_mean"),
+            Averaging::Exponential(_) => tr!("averaging.exponential"),
+        }
+    }
+
+    /// Show the controls for selecting the averaging mode, returns true when it changed
+    pub fn show(&mut self, ui: &mut eframe::egui::Ui) -> bool {
+        let old = *self;
+        eframe::egui::ComboBox::from_label(tr!("averaging.label"))
+            .selected_text(self.name())
+            .show_ui(ui, |ui| {
+                if ui
+                    .selectable_label(matches!(self, Averaging::Off), tr!("averaging.off"))
+                    .clicked()
+                {
+                    *self = Averaging::Off;
+                }
+                if ui
+                    .selectable_label(
+                        matches!(self, Averaging::RunningMean(_)),
+                        tr!("averaging.running_mean"),
+                    )
+                    .clicked()
+                    && !matches!(self, Averaging::RunningMean(_))
+                {
+                    *self = Averaging::RunningMean(8);
+                }
+                if ui
+                    .selectable_label(
+                        matches!(self, Averaging::Exponential(_)),
+                        tr!("averaging.exponential"),
+                    )
+                    .clicked()
+                    && !matches!(self, Averaging::Exponential(_))
+                {
+                    *self = Averaging::Exponential(0.2);
+                }
+            });
+        match self {
+            Averaging::Off => {}
+            Averaging::RunningMean(n) => {
+                ui.add(eframe::egui::Slider::new(n, 2..=64).text(tr!("averaging.frames")));
+            }
+            Averaging::Exponential(a) => {
+                ui.add(eframe::egui::Slider::new(a, 0.01..=1.0).text(tr!("averaging.weight")));
+            }
+        }
+        old != *self
+    }
+}
+
+/// Averages the frames from a single camera
+#[derive(Debug)]
+pub struct FrameAverager {
+    mode: Averaging,
+    /// Average in linear light instead of on the gamma encoded values
+    linear: bool,
+    frames: VecDeque<opencv::core::Mat>,
+    accumulator: Option<opencv::core::Mat>,
+}
+
+impl FrameAverager {
+    pub fn new(mode: Averaging, linear: bool) -> Self {
+        Self {
+            mode,
+            linear,
+            frames: VecDeque::new(),
+            accumulator: None,
+        }
+    }
+
+    /// Change the averaging mode, discarding any previous frames
+    pub fn set_mode(&mut self, mode: Averaging, linear: bool) {
+        self.mode = mode;
+        self.linear = linear;
+        self.frames.clear();
+        self.accumulator = None;
+    }
+
+    /// Add a frame, returning the averaged frame
+    pub fn process(&mut self, img: opencv::core::Mat) -> opencv::core::Mat {
+        if self.mode == Averaging::Off {
+            return img;
+        }
+        if let Some(acc) = &self.accumulator {
+            if acc.size().ok() != img.size().ok() || acc.channels() != img.channels() {
+                self.set_mode(self.mode, self.linear);
+            }
+        }
+        self.accumulate(&img).unwrap_or(img)
+    }
+
+    fn accumulate(&mut self, img: &opencv::core::Mat) -> Option<opencv::core::Mat> {
+        let mut f = opencv::core::Mat::default();
+        if self.linear && img.depth() == opencv::core::CV_8U {
+            let lut: Vec<f32> = (0..=255u8).map(linear_f32_from_gamma_u8).collect();
+            let lut = opencv::core::Mat::from_slice(&lut).ok()?.try_clone().ok()?;
+            opencv::core::lut(img, &lut, &mut f).ok()?;
+        } else {
+            img.convert_to(&mut f, opencv::core::CV_32F, 1.0, 0.0)
+                .ok()?;
+        }
+        let scale = match self.mode {
+            Averaging::Off => return None,
+            Averaging::RunningMean(n) => {
+                if let Some(acc) = &mut self.accumulator {
+                    opencv::imgproc::accumulate(&f, acc, &opencv::core::no_array()).ok()?;
+                } else {
+                    self.accumulator = Some(f.clone());
+                }
+                self.frames.push_back(f);
+                while self.frames.len() > n {
+                    if let (Some(old), Some(acc)) = (self.frames.pop_front(), &self.accumulator) {
+                        let mut diff = opencv::core::Mat::default();
+                        opencv::core::subtract(acc, &old, &mut diff, &opencv::core::no_array(), -1)
+                            .ok()?;
+                        self.accumulator = Some(diff);
+                    }
+                }
+                1.0 / self.frames.len() as f64
+            }
+            Averaging::Exponential(alpha) => {
+                if let Some(acc) = &mut self.accumulator {
+                    opencv::imgproc::accumulate_weighted(&f, acc, alpha, &opencv::core::no_array())
+                        .ok()?;
+                } else {
+                    self.accumulator = Some(f);
+                }
+                1.0
+            }
+        };
+        let mut out = opencv::core::Mat::default();
+        if self.linear && img.depth() == opencv::core::CV_8U {
+            let mut mean = opencv::core::Mat::default();
+            self.accumulator
+                .as_ref()?
+                .convert_to(&mut mean, -1, scale, 0.0)
+                .ok()?;
+            out = opencv::core::Mat::new_size_with_default(
+                img.size().ok()?,
+                img.typ(),
+                opencv::core::Scalar::all(0.0),
+            )
+            .ok()?;
+            let values = mean.data_bytes().ok()?;
+            for (o, v) in out
+                .data_bytes_mut()
+                .ok()?
+                .iter_mut()
+                .zip(values.chunks_exact(4))
+            {
+                *o = gamma_u8_from_linear_f32(f32::from_ne_bytes([v[0], v[1], v[2], v[3]]));
+            }
+        } else {
+            self.accumulator
+                .as_ref()?
+                .convert_to(&mut out, img.typ(), scale, 0.0)
+                .ok()?;
+        }
+        Some(out)
+    }
+}
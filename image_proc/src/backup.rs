@@ -0,0 +1,331 @@
+//! Uploading calibration results to network storage, so they survive the computer being reinstalled
+
+use std::io::Write;
+
+/// Where backups are uploaded to
+#[derive(Clone, Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+pub enum BackupTarget {
+    /// A WebDAV server, files are uploaded below the url
+    WebDav {
+        url: String,
+        username: String,
+        password: String,
+    },
+    /// An S3 compatible object store
+    S3 {
+        endpoint: String,
+        region: String,
+        bucket: String,
+        access_key: String,
+        secret_key: String,
+        /// Prepended to the name of every uploaded object
+        prefix: String,
+    },
+    /// A server reachable with sftp
+    Sftp {
+        host: String,
+        port: u16,
+        username: String,
+        password: String,
+        directory: String,
+        /// The sha256 fingerprint of the key of the server in hex, for servers not in the known hosts of the user
+        #[serde(default)]
+        host_key: String,
+    },
+}
+
+impl BackupTarget {
+    fn name(&self) -> &'static str {
+        match self {
+            BackupTarget::WebDav { .. } => "WebDAV",
+            BackupTarget::S3 { .. } => "S3",
+            BackupTarget::Sftp { .. } => "SFTP",
+        }
+    }
+
+    /// A default instance of every kind of target
+    fn all() -> [BackupTarget; 3] {
+        [
+            BackupTarget::WebDav {
+                url: String::new(),
+                username: String::new(),
+                password: String::new(),
+            },
+            BackupTarget::S3 {
+                endpoint: String::new(),
+                region: "us-east-1".to_string(),
+                bucket: String::new(),
+                access_key: String::new(),
+                secret_key: String::new(),
+                prefix: String::new(),
+            },
+            BackupTarget::Sftp {
+                host: String::new(),
+                port: 22,
+                username: String::new(),
+                password: String::new(),
+                directory: String::new(),
+                host_key: String::new(),
+            },
+        ]
+    }
+
+    /// Upload files into a folder of the target
+    fn upload(&self, folder: &str, files: &[BackupFile]) -> Result<(), String> {
+        match self {
+            BackupTarget::WebDav {
+                url,
+                username,
+                password,
+            } => {
+                let base = format!("{}/{}", url.trim_end_matches('/'), folder);
+                let auth = (!username.is_empty()).then(|| {
+                    format!(
+                        "Basic {}",
+                        base64::Engine::encode(
+                            &base64::engine::general_purpose::STANDARD,
+                            format!("{}:{}", username, password)
+                        )
+                    )
+                });
+                let mut mkcol = ureq::http::Request::builder().method("MKCOL").uri(&base);
+                if let Some(a) = &auth {
+                    mkcol = mkcol.header("Authorization", a);
+                }
+                // The server answers 405 when the folder already exists, which is not an error
+                match ureq::run(mkcol.body(()).map_err(|e| e.to_string())?) {
+                    Ok(_) | Err(ureq::Error::StatusCode(405)) => {}
+                    Err(e) => return Err(format!("{}: {}", base, e)),
+                }
+                for f in files {
+                    let mut put = ureq::put(format!("{}/{}", base, f.name));
+                    if let Some(a) = &auth {
+                        put = put.header("Authorization", a);
+                    }
+                    put.send(&f.data[..])
+                        .map_err(|e| format!("{}: {}", f.name, e))?;
+                }
+                Ok(())
+            }
+            BackupTarget::S3 {
+                endpoint,
+                region,
+                bucket,
+                access_key,
+                secret_key,
+                prefix,
+            } => {
+                let region = s3::Region::Custom {
+                    region: region.clone(),
+                    endpoint: endpoint.clone(),
+                };
+                let credentials = s3::creds::Credentials::new(
+                    Some(access_key.as_str()),
+                    Some(secret_key.as_str()),
+                    None,
+                    None,
+                    None,
+                )
+                .map_err(|e| e.to_string())?;
+                let bucket = s3::Bucket::new(bucket, region, credentials)
+                    .map_err(|e| e.to_string())?
+                    .with_path_style();
+                for f in files {
+                    let key = format!("{}{}/{}", prefix, folder, f.name);
+                    bucket
+                        .put_object(&key, &f.data)
+                        .map_err(|e| format!("{}: {}", key, e))?;
+                }
+                Ok(())
+            }
+            BackupTarget::Sftp {
+                host,
+                port,
+                username,
+                password,
+                directory,
+                host_key,
+            } => {
+                let tcp = std::net::TcpStream::connect((host.as_str(), *port))
+                    .map_err(|e| e.to_string())?;
+                let mut session = ssh2::Session::new().map_err(|e| e.to_string())?;
+                session.set_tcp_stream(tcp);
+                session.handshake().map_err(|e| e.to_string())?;
+                check_host_key(&session, host, *port, host_key)?;
+                session
+                    .userauth_password(username, password)
+                    .map_err(|e| e.to_string())?;
+                let sftp = session.sftp().map_err(|e| e.to_string())?;
+                let dir = std::path::Path::new(directory).join(folder);
+                // The folder may already exist, which is not an error
+                let _ = sftp.mkdir(&dir, 0o755);
+                for f in files {
+                    let mut remote = sftp
+                        .create(&dir.join(&f.name))
+                        .map_err(|e| format!("{}: {}", f.name, e))?;
+                    remote
+                        .write_all(&f.data)
+                        .map_err(|e| format!("{}: {}", f.name, e))?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+/// Check the key of an sftp server against the known hosts of the user or the fingerprint in the settings.
+/// Servers matching neither are refused, so the password is not sent to whoever is in between.
+fn check_host_key(
+    session: &ssh2::Session,
+    host: &str,
+    port: u16,
+    fingerprint: &str,
+) -> Result<(), String> {
+    let (key, _) = session
+        .host_key()
+        .ok_or_else(|| tr!("backup.unknown_host_key", host = host, fingerprint = "?"))?;
+    let mut known = session.known_hosts().map_err(|e| e.to_string())?;
+    let file = std::env::var_os("HOME")
+        .or_else(|| std::env::var_os("USERPROFILE"))
+        .map(|h| std::path::Path::new(&h).join(".ssh").join("known_hosts"));
+    if let Some(file) = file.filter(|f| f.exists()) {
+        known
+            .read_file(&file, ssh2::KnownHostFileKind::OpenSSH)
+            .map_err(|e| e.to_string())?;
+    }
+    match known.check_port(host, port, key) {
+        ssh2::CheckResult::Match => return Ok(()),
+        ssh2::CheckResult::Mismatch => return Err(tr!("backup.host_key_changed", host = host)),
+        ssh2::CheckResult::NotFound | ssh2::CheckResult::Failure => {}
+    }
+    let actual = session
+        .host_key_hash(ssh2::HashType::Sha256)
+        .map(image_proc::integrity::to_hex)
+        .unwrap_or_default();
+    if !actual.is_empty() && fingerprint.trim().eq_ignore_ascii_case(&actual) {
+        Ok(())
+    } else {
+        Err(tr!(
+            "backup.unknown_host_key",
+            host = host,
+            fingerprint = actual
+        ))
+    }
+}
+
+/// The backup settings, the credentials are stored with the other settings in plain text
+#[derive(Clone, Debug, Default, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
+pub struct BackupSettings {
+    /// Where to upload to, None disables backups
+    pub target: Option<BackupTarget>,
+    /// Upload the captured images along with the calibration
+    pub include_captures: bool,
+}
+
+impl BackupSettings {
+    /// Show the backup settings for editing
+    pub fn show(&mut self, ui: &mut eframe::egui::Ui) {
+        let name = self
+            .target
+            .as_ref()
+            .map(|t| t.name().to_string())
+            .unwrap_or_else(|| tr!("backup.disabled"));
+        eframe::egui::ComboBox::from_label(tr!("backup.target"))
+            .selected_text(name)
+            .show_ui(ui, |ui| {
+                if ui
+                    .selectable_label(self.target.is_none(), tr!("backup.disabled"))
+                    .clicked()
+                {
+                    self.target = None;
+                }
+                for t in BackupTarget::all() {
+                    let selected = self
+                        .target
+                        .as_ref()
+                        .is_some_and(|c| std::mem::discriminant(c) == std::mem::discriminant(&t));
+                    if ui.selectable_label(selected, t.name()).clicked() && !selected {
+                        self.target = Some(t);
+                    }
+                }
+            });
+        let Some(target) = &mut self.target else {
+            return;
+        };
+        eframe::egui::Grid::new("backup_settings").show(ui, |ui| {
+            let text = |ui: &mut eframe::egui::Ui, name: String, v: &mut String, secret| {
+                ui.label(name);
+                ui.add(eframe::egui::TextEdit::singleline(v).password(secret));
+                ui.end_row();
+            };
+            match target {
+                BackupTarget::WebDav {
+                    url,
+                    username,
+                    password,
+                } => {
+                    text(ui, tr!("backup.url"), url, false);
+                    text(ui, tr!("backup.username"), username, false);
+                    text(ui, tr!("backup.password"), password, true);
+                }
+                BackupTarget::S3 {
+                    endpoint,
+                    region,
+                    bucket,
+                    access_key,
+                    secret_key,
+                    prefix,
+                } => {
+                    text(ui, tr!("backup.endpoint"), endpoint, false);
+                    text(ui, tr!("backup.region"), region, false);
+                    text(ui, tr!("backup.bucket"), bucket, false);
+                    text(ui, tr!("backup.access_key"), access_key, false);
+                    text(ui, tr!("backup.secret_key"), secret_key, true);
+                    text(ui, tr!("backup.prefix"), prefix, false);
+                }
+                BackupTarget::Sftp {
+                    host,
+                    port,
+                    username,
+                    password,
+                    directory,
+                    host_key,
+                } => {
+                    text(ui, tr!("backup.host"), host, false);
+                    ui.label(tr!("backup.port"));
+                    ui.add(eframe::egui::DragValue::new(port));
+                    ui.end_row();
+                    text(ui, tr!("backup.username"), username, false);
+                    text(ui, tr!("backup.password"), password, true);
+                    text(ui, tr!("backup.directory"), directory, false);
+                    text(ui, tr!("backup.host_key"), host_key, false);
+                }
+            }
+        });
+        ui.checkbox(&mut self.include_captures, tr!("backup.include_captures"));
+        ui.label(tr!("backup.plain_text"));
+    }
+}
+
+/// A file to back up
+pub struct BackupFile {
+    pub name: String,
+    pub data: Vec<u8>,
+}
+
+/// Upload files into a new folder of the backup target in a background thread, the message for the user is sent when done
+pub fn start_backup(
+    target: BackupTarget,
+    folder: String,
+    files: Vec<BackupFile>,
+    done: crossbeam::channel::Sender<crate::status::TaskResult>,
+) {
+    std::thread::spawn(move || {
+        let r = target
+            .upload(&folder, &files)
+            .map(|_| tr!("info.backup", folder = folder))
+            .map_err(|e| tr!("error.backup", error = e));
+        let _ = done.send(r);
+    });
+}
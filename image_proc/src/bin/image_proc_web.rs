@@ -0,0 +1,394 @@
+//! A viewer for reviewing the processing and undistortion of images in a web browser, without installing anything.
+//! Images and calibration files are opened from disk and processed by the pure rust part of the pipeline.
+//! Build it for the browser with `trunk build --release`, or run it on the desktop with `cargo run --bin image_proc_web`.
+
+rust_i18n::i18n!("locales", fallback = "en");
+
+/// Look up a translated user interface string, the strings are in the locales directory
+macro_rules! tr {
+    ($($arg:tt)*) => {
+        rust_i18n::t!($($arg)*).into_owned()
+    };
+}
+
+use std::sync::mpsc::{Receiver, Sender, channel};
+
+use eframe::egui::{self, ColorImage};
+use image_proc::calibration::{CalibrationData, CalibrationDataTrait};
+use image_proc::integrity::{self, Trust};
+use image_proc::pipeline::Pipeline;
+
+/// The kinds of files the viewer opens
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum FileKind {
+    Image,
+    Calibration,
+}
+
+impl FileKind {
+    fn extensions(&self) -> &'static [&'static str] {
+        match self {
+            FileKind::Image => &["jpg", "jpeg", "png", "gif"],
+            FileKind::Calibration => &["bin"],
+        }
+    }
+
+    fn filter(&self) -> String {
+        match self {
+            FileKind::Image => tr!("web.images"),
+            FileKind::Calibration => tr!("web.calibrations"),
+        }
+    }
+
+    /// The kind of a file from its name, files that are not calibrations are taken to be images
+    fn of(name: &str) -> Self {
+        let calibration = std::path::Path::new(name)
+            .extension()
+            .and_then(|e| e.to_str())
+            .is_some_and(|e| FileKind::Calibration.extensions().contains(&e));
+        if calibration {
+            FileKind::Calibration
+        } else {
+            FileKind::Image
+        }
+    }
+}
+
+/// The contents of a file that was opened
+struct OpenedFile {
+    kind: FileKind,
+    name: String,
+    data: Vec<u8>,
+}
+
+/// Ask for a file. Browsers read files asynchronously, so the contents arrive on the channel once they are read.
+#[cfg(target_arch = "wasm32")]
+fn open_file(kind: FileKind, sender: Sender<OpenedFile>, ctx: egui::Context) {
+    let dialog = rfd::AsyncFileDialog::new().add_filter(kind.filter(), kind.extensions());
+    wasm_bindgen_futures::spawn_local(async move {
+        if let Some(f) = dialog.pick_file().await {
+            let _ = sender.send(OpenedFile {
+                kind,
+                name: f.file_name(),
+                data: f.read().await,
+            });
+            ctx.request_repaint();
+        }
+    });
+}
+
+/// Ask for a file. Browsers read files asynchronously, so the contents arrive on the channel once they are read.
+#[cfg(not(target_arch = "wasm32"))]
+fn open_file(kind: FileKind, sender: Sender<OpenedFile>, ctx: egui::Context) {
+    let Some(path) = rfd::FileDialog::new()
+        .add_filter(kind.filter(), kind.extensions())
+        .pick_file()
+    else {
+        return;
+    };
+    match std::fs::read(&path) {
+        Ok(data) => {
+            let _ = sender.send(OpenedFile {
+                kind,
+                name: path
+                    .file_name()
+                    .unwrap_or_default()
+                    .to_string_lossy()
+                    .into_owned(),
+                data,
+            });
+            ctx.request_repaint();
+        }
+        Err(e) => println!("Failed to read {}: {}", path.display(), e),
+    }
+}
+
+/// Decode an image file turned the way its orientation metadata says
+fn decode_image(data: &[u8]) -> image::ImageResult<ColorImage> {
+    let reader = image::ImageReader::new(std::io::Cursor::new(data)).with_guessed_format()?;
+    let mut decoder = reader.into_decoder()?;
+    let orientation = decoder
+        .orientation()
+        .unwrap_or(image::metadata::Orientation::NoTransforms);
+    let mut img = image::DynamicImage::from_decoder(decoder)?;
+    img.apply_orientation(orientation);
+    let img = img.into_rgba8();
+    Ok(ColorImage::from_rgba_unmultiplied(
+        [img.width() as usize, img.height() as usize],
+        img.as_raw(),
+    ))
+}
+
+/// What the viewer remembers between visits
+#[derive(Default, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
+struct ReviewSettings {
+    pipeline: Pipeline,
+    /// Remove the lens distortion with the opened calibration
+    undistort: bool,
+    /// The public keys in hex whose signatures on calibrations are trusted
+    trusted_keys: Vec<String>,
+    /// Refuse calibrations that are not signed by a trusted key
+    require_signature: bool,
+}
+
+impl ReviewSettings {
+    const KEY: &str = "review_settings";
+
+    /// The signatures accepted when opening a calibration
+    fn trust(&self) -> Trust {
+        Trust {
+            keys: self
+                .trusted_keys
+                .iter()
+                .filter_map(|k| integrity::verifying_key_from_hex(k))
+                .collect(),
+            require_signature: self.require_signature,
+        }
+    }
+}
+
+struct ReviewApp {
+    settings: ReviewSettings,
+    /// The name and contents of the opened image
+    image: Option<(String, ColorImage)>,
+    /// The name of the opened calibration and the calibration
+    calibration: Option<(String, CalibrationData)>,
+    /// The image as it reaches the pipeline, kept for picking colors
+    unprocessed: Option<ColorImage>,
+    texture: Option<egui::TextureHandle>,
+    /// The public key being entered to trust
+    new_key: String,
+    /// The image needs to be processed again
+    dirty: bool,
+    error: Option<String>,
+    sender: Sender<OpenedFile>,
+    receiver: Receiver<OpenedFile>,
+}
+
+impl ReviewApp {
+    fn new(cc: &eframe::CreationContext<'_>) -> Self {
+        let (sender, receiver) = channel();
+        Self {
+            settings: cc
+                .storage
+                .and_then(|s| eframe::get_value(s, ReviewSettings::KEY))
+                .unwrap_or_default(),
+            image: None,
+            calibration: None,
+            unprocessed: None,
+            texture: None,
+            new_key: String::new(),
+            dirty: false,
+            error: None,
+            sender,
+            receiver,
+        }
+    }
+
+    fn opened(&mut self, f: OpenedFile) {
+        match f.kind {
+            FileKind::Image => match decode_image(&f.data) {
+                Ok(img) => {
+                    self.image = Some((f.name, img));
+                    self.dirty = true;
+                    self.error = None;
+                }
+                Err(e) => self.error = Some(tr!("web.bad_image", name = f.name, error = e)),
+            },
+            FileKind::Calibration => {
+                match CalibrationData::from_bytes(&f.data, &self.settings.trust()) {
+                    Ok((cd, _)) => {
+                        self.calibration = Some((f.name, cd));
+                        self.settings.undistort = true;
+                        self.dirty = true;
+                        self.error = None;
+                    }
+                    Err(e) => {
+                        self.error = Some(tr!("web.bad_calibration", name = f.name, error = e))
+                    }
+                }
+            }
+        }
+    }
+
+    /// Show the keys trusted for signing calibrations
+    fn show_signatures(&mut self, ui: &mut egui::Ui) {
+        ui.label(tr!("signing.trusted_keys"));
+        let mut remove = None;
+        for (i, k) in self.settings.trusted_keys.iter().enumerate() {
+            ui.horizontal(|ui| {
+                ui.monospace(k);
+                if ui.button(tr!("signing.remove")).clicked() {
+                    remove = Some(i);
+                }
+            });
+        }
+        if let Some(i) = remove {
+            self.settings.trusted_keys.remove(i);
+        }
+        ui.horizontal(|ui| {
+            ui.text_edit_singleline(&mut self.new_key);
+            if ui.button(tr!("signing.add")).clicked() {
+                let key = self.new_key.trim().to_lowercase();
+                if integrity::verifying_key_from_hex(&key).is_some() {
+                    if !self.settings.trusted_keys.contains(&key) {
+                        self.settings.trusted_keys.push(key);
+                    }
+                    self.new_key.clear();
+                    self.error = None;
+                } else {
+                    self.error = Some(tr!("signing.invalid_public_key"));
+                }
+            }
+        });
+        ui.checkbox(&mut self.settings.require_signature, tr!("signing.require"));
+    }
+
+    /// Run the image through the calibration and the pipeline again
+    fn process(&mut self, ctx: &egui::Context) {
+        self.dirty = false;
+        let Some((_, img)) = &self.image else {
+            return;
+        };
+        let mut img = img.clone();
+        if let (true, Some((_, cd))) = (self.settings.undistort, &self.calibration) {
+            img = cd.apply_calibration(img);
+        }
+        self.settings.pipeline.analyze(&img);
+        let processed = self.settings.pipeline.process(img.clone());
+        self.unprocessed = Some(img);
+        match &mut self.texture {
+            Some(t) => t.set(processed, egui::TextureOptions::LINEAR),
+            None => {
+                self.texture =
+                    Some(ctx.load_texture("review", processed, egui::TextureOptions::LINEAR))
+            }
+        }
+    }
+}
+
+impl eframe::App for ReviewApp {
+    fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
+        while let Ok(f) = self.receiver.try_recv() {
+            self.opened(f);
+        }
+        // Files dropped on the page, browsers give their contents and the desktop gives their paths
+        for f in ctx.input(|i| i.raw.dropped_files.clone()) {
+            let name = if f.name.is_empty() {
+                f.path
+                    .as_ref()
+                    .and_then(|p| p.file_name())
+                    .map(|n| n.to_string_lossy().into_owned())
+                    .unwrap_or_default()
+            } else {
+                f.name.clone()
+            };
+            let data = match (&f.bytes, &f.path) {
+                (Some(b), _) => b.to_vec(),
+                (None, Some(p)) => std::fs::read(p).unwrap_or_default(),
+                (None, None) => continue,
+            };
+            self.opened(OpenedFile {
+                kind: FileKind::of(&name),
+                name,
+                data,
+            });
+        }
+
+        egui::TopBottomPanel::top("files").show(ctx, |ui| {
+            ui.horizontal(|ui| {
+                if ui.button(tr!("web.open_image")).clicked() {
+                    open_file(FileKind::Image, self.sender.clone(), ctx.clone());
+                }
+                if ui.button(tr!("web.open_calibration")).clicked() {
+                    open_file(FileKind::Calibration, self.sender.clone(), ctx.clone());
+                }
+                if let Some((name, _)) = &self.calibration {
+                    if ui
+                        .checkbox(&mut self.settings.undistort, tr!("web.undistort"))
+                        .on_hover_text(name)
+                        .changed()
+                    {
+                        self.dirty = true;
+                    }
+                }
+                if let Some((name, img)) = &self.image {
+                    ui.label(format!("{} {}x{}", name, img.width(), img.height()));
+                }
+            });
+            if let Some(e) = &self.error {
+                ui.colored_label(egui::Color32::RED, e);
+            }
+        });
+
+        egui::SidePanel::left("pipeline").show(ctx, |ui| {
+            ui.collapsing(tr!("web.signatures"), |ui| self.show_signatures(ui));
+            ui.heading(tr!("web.pipeline"));
+            egui::ScrollArea::vertical().show(ui, |ui| {
+                if self.settings.pipeline.show(ui) {
+                    self.dirty = true;
+                }
+            });
+        });
+
+        if self.dirty {
+            self.process(ctx);
+        }
+
+        egui::CentralPanel::default().show(ctx, |ui| {
+            let Some(t) = &self.texture else {
+                ui.centered_and_justified(|ui| ui.label(tr!("web.drop_hint")));
+                return;
+            };
+            let r = ui.add(
+                egui::Image::new(t)
+                    .shrink_to_fit()
+                    .sense(egui::Sense::click()),
+            );
+            if self.settings.pipeline.picking() && r.clicked() {
+                if let (Some(pos), Some(img)) = (r.interact_pointer_pos(), self.unprocessed.clone())
+                {
+                    let p = (pos - r.rect.min) / r.rect.size();
+                    self.settings.pipeline.pick(img, [p.x, p.y]);
+                    self.dirty = true;
+                }
+            }
+        });
+    }
+
+    fn save(&mut self, storage: &mut dyn eframe::Storage) {
+        eframe::set_value(storage, ReviewSettings::KEY, &self.settings);
+    }
+}
+
+#[cfg(target_arch = "wasm32")]
+fn main() {
+    use wasm_bindgen::JsCast;
+
+    wasm_bindgen_futures::spawn_local(async {
+        let canvas = web_sys::window()
+            .and_then(|w| w.document())
+            .and_then(|d| d.get_element_by_id("image_proc"))
+            .and_then(|e| e.dyn_into::<web_sys::HtmlCanvasElement>().ok())
+            .expect("The page has no canvas with the id image_proc");
+        eframe::WebRunner::new()
+            .start(
+                canvas,
+                eframe::WebOptions::default(),
+                Box::new(|cc| Ok(Box::new(ReviewApp::new(cc)))),
+            )
+            .await
+            .expect("Failed to start the viewer");
+    });
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn main() {
+    eframe::run_native(
+        "Uob Image Review",
+        eframe::NativeOptions::default(),
+        Box::new(|cc| Ok(Box::new(ReviewApp::new(cc)))),
+    )
+    .unwrap();
+}
@@ -0,0 +1,353 @@
+//! Parameters of the calibration board, a charuco board, a plain chessboard or an asymmetric circle grid
+
+pub use image_proc::aruco::DICTIONARIES;
+use image_proc::calibration::Grid;
+
+/// The kind of calibration board
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub enum PatternType {
+    /// A chessboard with a marker in each white square, corners are found even when part of it is out of view
+    #[default]
+    Charuco,
+    /// A plain chessboard without markers, the whole board has to be in view
+    Chessboard,
+    /// Rows of black circles with every other row shifted, the whole grid has to be in view.
+    /// The centers of circles are found more accurately than corners in blurry close up views.
+    CircleGrid,
+}
+
+impl PatternType {
+    const ALL: [PatternType; 3] = [
+        PatternType::Charuco,
+        PatternType::Chessboard,
+        PatternType::CircleGrid,
+    ];
+
+    fn name(&self) -> String {
+        match self {
+            PatternType::Charuco => tr!("board.charuco"),
+            PatternType::Chessboard => tr!("board.chessboard"),
+            PatternType::CircleGrid => tr!("board.circle_grid"),
+        }
+    }
+}
+
+/// The layout and physical size of a calibration board
+#[derive(Clone, Copy, Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
+pub struct BoardParams {
+    /// The kind of board, the markers and the second board are only used by a charuco board
+    pub pattern: PatternType,
+    /// The number of squares across the board
+    pub squares_x: i32,
+    /// The number of squares down the board
+    pub squares_y: i32,
+    /// The side length of a square in meters, for a circle grid the distance between rows
+    pub square_length: f32,
+    /// The side length of a marker in meters, for a circle grid the diameter of the circles
+    pub marker_length: f32,
+    /// The aruco dictionary of the markers
+    pub dictionary: i32,
+    /// The target is two of these boards joined along an edge, the second with markers following those of the first
+    pub second_plane: bool,
+    /// The angle between the faces of the two boards in degrees
+    pub plane_angle: f32,
+}
+
+impl Default for BoardParams {
+    fn default() -> Self {
+        Self {
+            pattern: PatternType::Charuco,
+            squares_x: 10,
+            squares_y: 10,
+            square_length: 10.0 * 0.0254,
+            marker_length: 7.0 * 0.0254,
+            dictionary: image_proc::aruco::DICT_6X6_1000,
+            second_plane: false,
+            plane_angle: 90.0,
+        }
+    }
+}
+
+impl BoardParams {
+    /// Get the aruco dictionary for the board
+    pub fn dictionary(&self) -> Option<image_proc::aruco::Dictionary> {
+        image_proc::aruco::dictionary(self.dictionary).ok()
+    }
+
+    /// Create the board, returns None when the parameters are not valid
+    pub fn make_board(&self) -> Option<image_proc::aruco::CharucoBoard> {
+        if self.marker_length >= self.square_length || self.squares_x < 2 || self.squares_y < 2 {
+            return None;
+        }
+        let d = self.dictionary()?;
+        println!("Making charuco board");
+        let board = image_proc::aruco::charuco_board(
+            self.squares_x,
+            self.squares_y,
+            self.square_length,
+            self.marker_length,
+            &d,
+        );
+        board.ok()
+    }
+
+    /// The layout of the board for the calibration routines, which make the board again on every thread
+    pub fn charuco(&self) -> image_proc::aruco::CharucoParams {
+        image_proc::aruco::CharucoParams {
+            squares_x: self.squares_x,
+            squares_y: self.squares_y,
+            square_length: self.square_length,
+            marker_length: self.marker_length,
+            dictionary: self.dictionary,
+            first_id: 0,
+        }
+    }
+
+    /// The layout of the second board of a two board target, its markers follow those of the first board
+    pub fn second_charuco(&self) -> image_proc::aruco::CharucoParams {
+        image_proc::aruco::CharucoParams {
+            first_id: self.squares_x * self.squares_y / 2,
+            ..self.charuco()
+        }
+    }
+
+    /// Create the second board of a two board target, returns None when the parameters are not valid
+    pub fn make_second_board(&self) -> Option<image_proc::aruco::CharucoBoard> {
+        if self.marker_length >= self.square_length || self.squares_x < 2 || self.squares_y < 2 {
+            return None;
+        }
+        let d = self.dictionary()?;
+        image_proc::aruco::charuco_board_from_id(
+            self.squares_x,
+            self.squares_y,
+            self.square_length,
+            self.marker_length,
+            &d,
+            self.squares_x * self.squares_y / 2,
+        )
+        .ok()
+    }
+
+    /// The width of the board in meters
+    pub fn width(&self) -> f32 {
+        self.squares_x as f32 * self.square_length
+    }
+
+    /// The pixel size of a rendered image of the board with the given width
+    pub fn image_size(&self, width: i32) -> opencv::core::Size {
+        opencv::core::Size {
+            width,
+            height: width * self.squares_y / self.squares_x,
+        }
+    }
+
+    /// The target when it has no markers, None for a charuco board
+    pub fn grid(&self) -> Option<Grid> {
+        match self.pattern {
+            PatternType::Charuco => None,
+            PatternType::Chessboard => Some(Grid::Chessboard {
+                cols: self.squares_x,
+                rows: self.squares_y,
+                square: self.square_length,
+            }),
+            PatternType::CircleGrid => Some(Grid::AsymmetricCircles {
+                cols: self.squares_x,
+                rows: self.squares_y,
+                spacing: self.square_length,
+                diameter: self.marker_length,
+            }),
+        }
+    }
+
+    /// A short description of the board, like "10x10 25.4/17.8mm 6x6", "9x7 25.4mm chessboard"
+    /// or "4x11 20.0/15.0mm circles"
+    pub fn description(&self) -> String {
+        match self.pattern {
+            PatternType::Charuco => {}
+            PatternType::Chessboard => {
+                return format!(
+                    "{}x{} {:.1}mm chessboard",
+                    self.squares_x,
+                    self.squares_y,
+                    self.square_length * 1000.0
+                );
+            }
+            PatternType::CircleGrid => {
+                return format!(
+                    "{}x{} {:.1}/{:.1}mm circles",
+                    self.squares_x,
+                    self.squares_y,
+                    self.square_length * 1000.0,
+                    self.marker_length * 1000.0
+                );
+            }
+        }
+        let dictionary = DICTIONARIES
+            .iter()
+            .find(|d| d.0 == self.dictionary)
+            .map(|d| d.1)
+            .unwrap_or("?");
+        let description = format!(
+            "{}x{} {:.1}/{:.1}mm {}",
+            self.squares_x,
+            self.squares_y,
+            self.square_length * 1000.0,
+            self.marker_length * 1000.0,
+            dictionary
+        );
+        if self.second_plane {
+            format!("2x {} at {:.1}°", description, self.plane_angle)
+        } else {
+            description
+        }
+    }
+
+    /// The number of inner corners of the board, or of circles of a circle grid
+    pub fn corner_count(&self) -> usize {
+        let rows = match self.pattern {
+            PatternType::CircleGrid => self.squares_y,
+            _ => self.squares_y - 1,
+        };
+        (self.corners_across() * rows) as usize
+    }
+
+    /// The number of corners in a row of the board, or of circles of a circle grid.
+    /// The ids of the corners go along the rows.
+    pub fn corners_across(&self) -> i32 {
+        match self.pattern {
+            PatternType::CircleGrid => self.squares_x,
+            _ => self.squares_x - 1,
+        }
+    }
+
+    /// Show the board parameters for editing, returns true when they changed
+    pub fn show(&mut self, ui: &mut eframe::egui::Ui) -> bool {
+        let mut changed = false;
+        eframe::egui::Grid::new("board_params").show(ui, |ui| {
+            ui.label(tr!("board.pattern_type"));
+            eframe::egui::ComboBox::from_id_salt("board_pattern")
+                .selected_text(self.pattern.name())
+                .show_ui(ui, |ui| {
+                    for p in PatternType::ALL {
+                        changed |= ui
+                            .selectable_value(&mut self.pattern, p, p.name())
+                            .changed();
+                    }
+                });
+            ui.end_row();
+            let circles = self.pattern == PatternType::CircleGrid;
+            ui.label(if circles {
+                tr!("board.circles_across")
+            } else {
+                tr!("board.squares_across")
+            });
+            changed |= ui
+                .add(eframe::egui::DragValue::new(&mut self.squares_x).range(2..=50))
+                .changed();
+            ui.end_row();
+            ui.label(if circles {
+                tr!("board.circle_rows")
+            } else {
+                tr!("board.squares_down")
+            });
+            changed |= ui
+                .add(eframe::egui::DragValue::new(&mut self.squares_y).range(2..=50))
+                .changed();
+            ui.end_row();
+            let mut square = self.square_length * 1000.0;
+            ui.label(if circles {
+                tr!("board.circle_spacing")
+            } else {
+                tr!("board.square_size")
+            });
+            if ui
+                .add(
+                    eframe::egui::DragValue::new(&mut square)
+                        .range(1.0..=1000.0)
+                        .speed(0.1)
+                        .suffix(" mm"),
+                )
+                .changed()
+            {
+                self.square_length = square / 1000.0;
+                changed = true;
+            }
+            ui.end_row();
+            if self.pattern == PatternType::Chessboard {
+                return;
+            }
+            let mut marker = self.marker_length * 1000.0;
+            ui.label(if circles {
+                tr!("board.circle_size")
+            } else {
+                tr!("board.marker_size")
+            });
+            if ui
+                .add(
+                    eframe::egui::DragValue::new(&mut marker)
+                        .range(1.0..=1000.0)
+                        .speed(0.1)
+                        .suffix(" mm"),
+                )
+                .changed()
+            {
+                self.marker_length = marker / 1000.0;
+                changed = true;
+            }
+            ui.end_row();
+            if circles {
+                return;
+            }
+            ui.label(tr!("board.dictionary"));
+            let name = DICTIONARIES
+                .iter()
+                .find(|d| d.0 == self.dictionary)
+                .map(|d| d.1.to_string())
+                .unwrap_or_else(|| tr!("board.unknown"));
+            eframe::egui::ComboBox::from_id_salt("board_dictionary")
+                .selected_text(name)
+                .show_ui(ui, |ui| {
+                    for (d, name) in DICTIONARIES {
+                        changed |= ui.selectable_value(&mut self.dictionary, d, name).changed();
+                    }
+                });
+            ui.end_row();
+            ui.label(tr!("board.second_plane"));
+            changed |= ui.checkbox(&mut self.second_plane, "").changed();
+            ui.end_row();
+            if self.second_plane {
+                ui.label(tr!("board.plane_angle"));
+                changed |= ui
+                    .add(
+                        eframe::egui::DragValue::new(&mut self.plane_angle)
+                            .range(30.0..=170.0)
+                            .speed(0.1)
+                            .suffix("°"),
+                    )
+                    .changed();
+                ui.end_row();
+            }
+        });
+        match self.pattern {
+            PatternType::Charuco => {
+                if self.second_plane {
+                    ui.label(tr!("board.second_plane_hint"));
+                }
+                if self.marker_length >= self.square_length {
+                    ui.colored_label(eframe::egui::Color32::RED, tr!("board.marker_too_big"));
+                }
+            }
+            PatternType::Chessboard => {
+                ui.label(tr!("board.chessboard_hint"));
+            }
+            PatternType::CircleGrid => {
+                ui.label(tr!("board.circle_grid_hint"));
+                if self.marker_length >= self.square_length {
+                    ui.colored_label(eframe::egui::Color32::RED, tr!("board.circle_too_big"));
+                }
+            }
+        }
+        changed
+    }
+}
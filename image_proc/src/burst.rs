@@ -0,0 +1,194 @@
+//! Merging a burst of frames into one still with more resolution and less noise than a single frame
+
+use eframe::egui::ColorImage;
+use image_proc::frame::Frame;
+use opencv::core::MatTraitConst;
+
+/// Collects a burst of frames from a camera and merges them
+pub struct BurstCapture {
+    /// The number of frames in a burst
+    frames: usize,
+    /// How many times larger the merged still is than the frames
+    scale: u32,
+    /// True while frames are being collected
+    capturing: bool,
+    burst: Vec<Frame>,
+    /// The frames that were left out of the last merge because they could not be aligned
+    rejected: usize,
+    error: Option<String>,
+    /// The last merged still, for showing
+    result: Option<eframe::egui::TextureHandle>,
+}
+
+impl Default for BurstCapture {
+    fn default() -> Self {
+        Self {
+            frames: 16,
+            scale: 2,
+            capturing: false,
+            burst: Vec::new(),
+            rejected: 0,
+            error: None,
+            result: None,
+        }
+    }
+}
+
+impl BurstCapture {
+    /// Frames matching the first frame worse than this are not merged, something in the scene probably moved
+    const MIN_RESPONSE: f64 = 0.05;
+
+    /// A single channel floating point version of a frame for registration
+    fn gray(img: &opencv::core::Mat) -> opencv::Result<opencv::core::Mat> {
+        let mut gray = opencv::core::Mat::default();
+        if img.channels() == 3 {
+            opencv::imgproc::cvt_color_def(img, &mut gray, opencv::imgproc::COLOR_BGR2GRAY)?;
+        } else {
+            gray = img.clone();
+        }
+        let mut f = opencv::core::Mat::default();
+        gray.convert_to(&mut f, opencv::core::CV_32F, 1.0, 0.0)?;
+        Ok(f)
+    }
+
+    /// Align every frame of the burst to the first with subpixel accuracy,
+    /// then average them on a grid scale times finer than the frames
+    fn merge(&mut self) -> opencv::Result<opencv::core::Mat> {
+        let Some(first) = self.burst.first() else {
+            return Err(opencv::Error::new(
+                opencv::core::StsBadArg,
+                "There are no frames to merge",
+            ));
+        };
+        let size = first.size()?;
+        let big = opencv::core::Size {
+            width: size.width * self.scale as i32,
+            height: size.height * self.scale as i32,
+        };
+        let reference = Self::gray(first)?;
+        let mut window = opencv::core::Mat::default();
+        opencv::imgproc::create_hanning_window(&mut window, size, opencv::core::CV_32F)?;
+        // Monochrome cameras give single channel frames, the sum has the channels of the frames
+        let typ = if first.channels() == 1 {
+            opencv::core::CV_32FC1
+        } else {
+            opencv::core::CV_32FC3
+        };
+        let mut sum =
+            opencv::core::Mat::new_size_with_default(big, typ, opencv::core::Scalar::all(0.0))?;
+        let mut count = 0;
+        self.rejected = 0;
+        for img in &self.burst {
+            let mut response = 0.0;
+            let shift = opencv::imgproc::phase_correlate(
+                &reference,
+                &Self::gray(img)?,
+                &window,
+                &mut response,
+            )?;
+            if response < Self::MIN_RESPONSE {
+                self.rejected += 1;
+                continue;
+            }
+            let mut f = opencv::core::Mat::default();
+            img.convert_to(&mut f, opencv::core::CV_32F, 1.0, 0.0)?;
+            let mut up = opencv::core::Mat::default();
+            opencv::imgproc::resize(&f, &mut up, big, 0.0, 0.0, opencv::imgproc::INTER_CUBIC)?;
+            // Move the frame back onto the first one
+            let s = self.scale as f64;
+            let m = opencv::core::Mat::from_slice_2d(&[
+                [1.0, 0.0, -shift.x * s],
+                [0.0, 1.0, -shift.y * s],
+            ])?;
+            let mut aligned = opencv::core::Mat::default();
+            opencv::imgproc::warp_affine(
+                &up,
+                &mut aligned,
+                &m,
+                big,
+                opencv::imgproc::INTER_LINEAR,
+                opencv::core::BORDER_REFLECT,
+                Default::default(),
+            )?;
+            let mut total = opencv::core::Mat::default();
+            opencv::core::add_def(&sum, &aligned, &mut total)?;
+            sum = total;
+            count += 1;
+        }
+        if count == 0 {
+            return Err(opencv::Error::new(
+                opencv::core::StsBadArg,
+                "None of the frames could be aligned",
+            ));
+        }
+        let mut out = opencv::core::Mat::default();
+        sum.convert_to(&mut out, opencv::core::CV_8U, 1.0 / count as f64, 0.0)?;
+        Ok(out)
+    }
+
+    /// Add a frame to the burst, returns the merged still when the burst is complete
+    pub fn add_frame(&mut self, ctx: &eframe::egui::Context, img: &Frame) -> Option<ColorImage> {
+        if !self.capturing {
+            return None;
+        }
+        self.burst.push(img.clone());
+        if self.burst.len() < self.frames {
+            return None;
+        }
+        self.capturing = false;
+        let r = self.merge();
+        self.burst.clear();
+        let merged = match r {
+            Ok(m) => m,
+            Err(e) => {
+                self.error = Some(e.to_string());
+                return None;
+            }
+        };
+        let still = crate::convert::bgr_mat_to_color_image(&merged)?;
+        self.result = Some(ctx.load_texture(
+            "burst_still",
+            still.clone(),
+            eframe::egui::TextureOptions::LINEAR,
+        ));
+        Some(still)
+    }
+
+    /// Show the burst controls and the last merged still
+    pub fn show(&mut self, ui: &mut eframe::egui::Ui) {
+        ui.label(tr!("burst.instructions"));
+        ui.add(eframe::egui::Slider::new(&mut self.frames, 2..=64).text(tr!("burst.frames")));
+        ui.add(eframe::egui::Slider::new(&mut self.scale, 1..=4).text(tr!("burst.scale")));
+        ui.horizontal(|ui| {
+            if self.capturing {
+                ui.label(tr!(
+                    "burst.progress",
+                    count = self.burst.len(),
+                    total = self.frames
+                ));
+                if ui.button(tr!("burst.cancel")).clicked() {
+                    self.capturing = false;
+                    self.burst.clear();
+                }
+            } else if ui.button(tr!("burst.capture")).clicked() {
+                self.error = None;
+                self.burst.clear();
+                self.capturing = true;
+            }
+        });
+        if let Some(e) = &self.error {
+            ui.colored_label(eframe::egui::Color32::RED, e);
+        }
+        if let Some(t) = &self.result {
+            if self.rejected > 0 {
+                ui.label(tr!("burst.rejected", count = self.rejected));
+            }
+            let w = ui.available_width();
+            let st = eframe::egui::load::SizedTexture {
+                id: t.id(),
+                size: t.size_vec2() * (w / t.size_vec2().x),
+            };
+            ui.add(eframe::egui::Image::from_texture(st));
+        }
+    }
+}
@@ -0,0 +1,555 @@
+//! Camera calibration data and the charuco calibration routine
+
+use std::path::Path;
+
+#[cfg(not(target_arch = "wasm32"))]
+mod charuco;
+#[cfg(not(target_arch = "wasm32"))]
+mod fisheye;
+#[cfg(not(target_arch = "wasm32"))]
+mod grid;
+#[cfg(not(target_arch = "wasm32"))]
+mod multi_plane;
+mod telecentric;
+
+#[cfg(not(target_arch = "wasm32"))]
+pub use charuco::{
+    DetectionResult, Residual, calibrate_charuco, calibrate_charuco_cancellable, detect_board,
+    detect_charuco, detect_charuco_all, residuals, rms_error, to_gray, view_errors,
+};
+#[cfg(not(target_arch = "wasm32"))]
+pub use fisheye::calibrate_fisheye;
+#[cfg(not(target_arch = "wasm32"))]
+pub use grid::{Grid, calibrate_grid, detect_grid_all, grid_residuals};
+#[cfg(not(target_arch = "wasm32"))]
+pub use multi_plane::{calibrate_multi_plane, target_corners};
+#[cfg(not(target_arch = "wasm32"))]
+pub use telecentric::calibrate_telecentric;
+pub use telecentric::fit_telecentric;
+
+use eframe::egui::ColorImage;
+
+use crate::integrity::{self, IntegrityError, Trust, Verification};
+use crate::native::NativeCalibration;
+
+/// The opencv type of a matrix of single channel 64 bit floating point elements
+const CV_64FC1: i32 = 6;
+
+/// An opencv matrix that can be serialized
+#[derive(Clone, Debug, serde::Serialize, serde::Deserialize)]
+pub struct SaveableOpencvMat {
+    width: i32,
+    height: i32,
+    typ: i32,
+    data: Vec<u8>,
+}
+
+impl SaveableOpencvMat {
+    /// A 64 bit floating point matrix with the elements in row major order
+    pub fn from_values(cols: i32, rows: i32, values: &[f64]) -> Self {
+        Self {
+            width: cols,
+            height: rows,
+            typ: CV_64FC1,
+            data: values.iter().flat_map(|v| v.to_ne_bytes()).collect(),
+        }
+    }
+
+    /// The number of columns and rows of the matrix
+    pub fn size(&self) -> (i32, i32) {
+        (self.width, self.height)
+    }
+
+    /// The elements of a 64 bit floating point matrix, in row major order
+    pub fn values(&self) -> Vec<f64> {
+        self.data
+            .chunks_exact(8)
+            .map(|c| f64::from_ne_bytes(c.try_into().unwrap()))
+            .collect()
+    }
+}
+
+/// The error of the calibration routines, opencv's where opencv is available
+#[cfg(not(target_arch = "wasm32"))]
+pub type Error = opencv::Error;
+/// The error of the calibration routines, opencv's where opencv is available
+#[cfg(target_arch = "wasm32")]
+pub type Error = String;
+
+/// A pixel with the lens distortion removed
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct UndistortedPoint {
+    /// Where the pixel is in the undistorted image
+    pub pixel: [f64; 2],
+    /// The normalized image coordinates, the undistorted pixel with the camera matrix removed
+    pub normalized: [f64; 2],
+}
+
+#[enum_dispatch::enum_dispatch]
+pub trait CalibrationDataTrait {
+    /// Remove the lens distortion from an image
+    fn apply_calibration(&self, img: ColorImage) -> ColorImage;
+    /// Remove the lens distortion from pixel coordinates of the distorted image
+    fn undistort_points(&self, points: &[[f64; 2]]) -> Result<Vec<UndistortedPoint>, Error>;
+}
+
+/// The lens model a camera is calibrated with
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub enum CameraModel {
+    /// The pinhole model with radial and tangential distortion, for most lenses
+    #[default]
+    Pinhole,
+    /// The equidistant fisheye model, for wide angle and action cameras the pinhole model can not fit
+    Fisheye,
+    /// The affine model of telecentric lenses, where fitting the pinhole model gives absurd focal lengths
+    Telecentric,
+}
+
+/// The library a camera is calibrated with
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub enum Backend {
+    /// Opencv, which calibrates every target and lens model
+    #[default]
+    OpenCv,
+    /// The pure rust backend of the native feature, which calibrates plain chessboards with the pinhole model
+    Native,
+}
+
+/// A camera calibrated with opencv's fisheye model, the 3x3 camera matrix and the distortion coefficients k1 k2 k3 k4
+#[derive(Clone, Debug, serde::Serialize, serde::Deserialize)]
+pub struct FisheyeCalibration(pub [SaveableOpencvMat; 2]);
+
+/// A camera with a telecentric lens. The camera matrix holds the magnification in pixels per unit of the board
+/// in place of the focal lengths and the center of the distortion in place of the principal point,
+/// the distortion coefficients are k1 k2 p1 p2 k3.
+#[derive(Clone, Debug, serde::Serialize, serde::Deserialize)]
+pub struct TelecentricCalibration(pub [SaveableOpencvMat; 2]);
+
+#[enum_dispatch::enum_dispatch(CalibrationDataTrait)]
+#[derive(Clone, Debug, serde::Serialize, serde::Deserialize)]
+pub enum CalibrationData {
+    OpenCvCharuco([SaveableOpencvMat; 2]),
+    /// Made by the pure rust backend
+    Native(NativeCalibration),
+    /// Made with the fisheye model
+    OpenCvFisheye(FisheyeCalibration),
+    /// The left camera of a pair calibrated together, with where the right camera is relative to it
+    Stereo(StereoCalibration),
+    /// Made with the affine model of telecentric lenses
+    Telecentric(TelecentricCalibration),
+}
+
+impl CalibrationData {
+    /// Load calibration data from a file, files with a checksum that does not match or without a signature the trust requires are refused
+    pub fn load(path: &Path, trust: &Trust) -> Option<Self> {
+        Self::load_verified(path, trust).ok().map(|(cd, _)| cd)
+    }
+
+    /// Load calibration data from a file, checking the checksum and signature against the trusted keys
+    pub fn load_verified(
+        path: &Path,
+        trust: &Trust,
+    ) -> Result<(Self, Verification), IntegrityError> {
+        Self::from_bytes(&std::fs::read(path)?, trust)
+    }
+
+    /// Read calibration data from the contents of a calibration file, checking the checksum and signature against the trusted keys
+    pub fn from_bytes(data: &[u8], trust: &Trust) -> Result<(Self, Verification), IntegrityError> {
+        let (contents, verification) = integrity::unseal(data, trust)?;
+        let (cd, _) = bincode::serde::decode_from_slice(&contents, bincode::config::standard())
+            .map_err(|_| IntegrityError::Malformed)?;
+        Ok((cd, verification))
+    }
+
+    /// Save the calibration data to a file, with a checksum
+    pub fn save(&self, path: &Path) -> std::io::Result<()> {
+        self.save_signed(path, None)
+    }
+
+    /// Save the calibration data to a file, with a checksum and a signature when a key is given
+    pub fn save_signed(
+        &self,
+        path: &Path,
+        key: Option<&ed25519_dalek::SigningKey>,
+    ) -> std::io::Result<()> {
+        std::fs::write(path, self.sealed(key)?)
+    }
+
+    /// The contents of a calibration file, with a checksum and a signature when a key is given
+    pub fn sealed(&self, key: Option<&ed25519_dalek::SigningKey>) -> std::io::Result<Vec<u8>> {
+        let data = bincode::serde::encode_to_vec(self, bincode::config::standard())
+            .map_err(std::io::Error::other)?;
+        integrity::seal(data, key)
+    }
+
+    /// An identifier of the calibration, the sha-256 of its contents in hex.
+    /// It matches the checksum stored in a saved calibration file.
+    pub fn id(&self) -> String {
+        let data =
+            bincode::serde::encode_to_vec(self, bincode::config::standard()).unwrap_or_default();
+        integrity::to_hex(&<sha2::Sha256 as sha2::Digest>::digest(&data))
+    }
+
+    /// The 3x3 camera matrix
+    pub fn camera_matrix(&self) -> &SaveableOpencvMat {
+        match self {
+            CalibrationData::OpenCvCharuco(m) => &m[0],
+            CalibrationData::Native(n) => &n.0[0],
+            CalibrationData::OpenCvFisheye(f) => &f.0[0],
+            CalibrationData::Stereo(s) => s.left.camera_matrix(),
+            CalibrationData::Telecentric(t) => &t.0[0],
+        }
+    }
+
+    /// The distortion coefficients
+    pub fn distortion(&self) -> &SaveableOpencvMat {
+        match self {
+            CalibrationData::OpenCvCharuco(m) => &m[1],
+            CalibrationData::Native(n) => &n.0[1],
+            CalibrationData::OpenCvFisheye(f) => &f.0[1],
+            CalibrationData::Stereo(s) => s.left.distortion(),
+            CalibrationData::Telecentric(t) => &t.0[1],
+        }
+    }
+
+    /// The calibration for images scaled from one resolution to another.
+    /// Only the camera matrix changes, the distortion is relative to the image size.
+    pub fn scaled(&self, from: [u32; 2], to: [u32; 2]) -> Self {
+        let sx = to[0] as f64 / from[0] as f64;
+        let sy = to[1] as f64 / from[1] as f64;
+        self.scaled_by([sx, sy], to)
+    }
+
+    /// The calibration for images of a size scaled by a factor along each axis
+    fn scaled_by(&self, factor: [f64; 2], size: [u32; 2]) -> Self {
+        let [sx, sy] = factor;
+        let scale = |m: &[SaveableOpencvMat; 2]| {
+            let mut v = m[0].values();
+            if v.len() >= 9 {
+                v[0] *= sx;
+                v[1] *= sx;
+                v[4] *= sy;
+                // Pixel centers are half a pixel from their corner, which moves with the size of the pixels
+                v[2] = (v[2] + 0.5) * sx - 0.5;
+                v[5] = (v[5] + 0.5) * sy - 0.5;
+            }
+            let (cols, rows) = m[0].size();
+            let cm = SaveableOpencvMat::from_values(cols, rows, &v);
+            [cm, m[1].clone()]
+        };
+        match self {
+            CalibrationData::OpenCvCharuco(m) => CalibrationData::OpenCvCharuco(scale(m)),
+            CalibrationData::Native(n) => CalibrationData::Native(NativeCalibration(scale(&n.0))),
+            CalibrationData::OpenCvFisheye(f) => {
+                CalibrationData::OpenCvFisheye(FisheyeCalibration(scale(&f.0)))
+            }
+            CalibrationData::Stereo(s) => CalibrationData::Stereo(s.scaled_by(factor, size)),
+            CalibrationData::Telecentric(t) => {
+                CalibrationData::Telecentric(TelecentricCalibration(scale(&t.0)))
+            }
+        }
+    }
+
+    /// The calibration for images of a size cut out of the images it was made with, with their top left corner at offset.
+    /// Only the principal point moves, a negative offset gives the calibration of the images a crop was cut from.
+    pub fn cropped(&self, offset: [f64; 2], size: [u32; 2]) -> Self {
+        let crop = |m: &[SaveableOpencvMat; 2]| {
+            let mut v = m[0].values();
+            if v.len() >= 9 {
+                v[2] -= offset[0];
+                v[5] -= offset[1];
+            }
+            let (cols, rows) = m[0].size();
+            let cm = SaveableOpencvMat::from_values(cols, rows, &v);
+            [cm, m[1].clone()]
+        };
+        match self {
+            CalibrationData::OpenCvCharuco(m) => CalibrationData::OpenCvCharuco(crop(m)),
+            CalibrationData::Native(n) => CalibrationData::Native(NativeCalibration(crop(&n.0))),
+            CalibrationData::OpenCvFisheye(f) => {
+                CalibrationData::OpenCvFisheye(FisheyeCalibration(crop(&f.0)))
+            }
+            CalibrationData::Stereo(s) => CalibrationData::Stereo(s.cropped(offset, size)),
+            CalibrationData::Telecentric(t) => {
+                CalibrationData::Telecentric(TelecentricCalibration(crop(&t.0)))
+            }
+        }
+    }
+
+    /// The calibration for images of a size binned by a factor from the images it was made with,
+    /// each pixel being the sum of factor by factor pixels. A factor below one gives the calibration of the unbinned images.
+    pub fn binned(&self, factor: f64, size: [u32; 2]) -> Self {
+        self.scaled_by([1.0 / factor; 2], size)
+    }
+
+    /// The calibration with other distortion coefficients, in the same order as those of the calibration
+    pub fn with_distortion(&self, values: &[f64]) -> Self {
+        let replace = |m: &[SaveableOpencvMat; 2]| {
+            let (cols, rows) = m[1].size();
+            [
+                m[0].clone(),
+                SaveableOpencvMat::from_values(cols, rows, values),
+            ]
+        };
+        match self {
+            CalibrationData::OpenCvCharuco(m) => CalibrationData::OpenCvCharuco(replace(m)),
+            CalibrationData::Native(n) => CalibrationData::Native(NativeCalibration(replace(&n.0))),
+            CalibrationData::OpenCvFisheye(f) => {
+                CalibrationData::OpenCvFisheye(FisheyeCalibration(replace(&f.0)))
+            }
+            CalibrationData::Stereo(s) => {
+                let mut s = s.clone();
+                s.left = Box::new(s.left.with_distortion(values));
+                CalibrationData::Stereo(s)
+            }
+            CalibrationData::Telecentric(t) => {
+                CalibrationData::Telecentric(TelecentricCalibration(replace(&t.0)))
+            }
+        }
+    }
+
+    /// The same kind of calibration with its camera matrix and distortion replaced.
+    /// Of a stereo calibration the left camera is replaced.
+    pub fn with_intrinsics(
+        &self,
+        camera_matrix: SaveableOpencvMat,
+        distortion: SaveableOpencvMat,
+    ) -> Self {
+        let m = [camera_matrix, distortion];
+        match self {
+            CalibrationData::OpenCvCharuco(_) => CalibrationData::OpenCvCharuco(m),
+            CalibrationData::Native(_) => CalibrationData::Native(NativeCalibration(m)),
+            CalibrationData::OpenCvFisheye(_) => {
+                CalibrationData::OpenCvFisheye(FisheyeCalibration(m))
+            }
+            CalibrationData::Stereo(s) => {
+                let mut s = s.clone();
+                s.left = Box::new(s.left.with_intrinsics(m[0].clone(), m[1].clone()));
+                CalibrationData::Stereo(s)
+            }
+            CalibrationData::Telecentric(_) => {
+                CalibrationData::Telecentric(TelecentricCalibration(m))
+            }
+        }
+    }
+}
+
+/// One of the cameras of a stereo pair
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum StereoSide {
+    Left,
+    Right,
+}
+
+/// The calibration of both cameras of a pair and where they are relative to each other
+#[derive(Clone, Debug, serde::Serialize, serde::Deserialize)]
+pub struct StereoCalibration {
+    pub left: Box<CalibrationData>,
+    pub right: Box<CalibrationData>,
+    /// The rotation from the left camera coordinates to the right camera coordinates, row by row
+    pub rotation: [f64; 9],
+    /// The position of the left camera in right camera coordinates, in the units of the board
+    pub translation: [f64; 3],
+    /// The essential matrix, row by row
+    pub essential: [f64; 9],
+    /// The fundamental matrix relating undistorted pixels of the left camera to the right camera, row by row
+    pub fundamental: [f64; 9],
+    /// The rms reprojection error of the calibration in pixels
+    pub rms: f64,
+    /// The width and height of the images the calibration was made with
+    pub resolution: [u32; 2],
+}
+
+/// A stereo calibration is used as the calibration of its left camera
+impl CalibrationDataTrait for StereoCalibration {
+    fn apply_calibration(&self, img: ColorImage) -> ColorImage {
+        self.left.apply_calibration(img)
+    }
+
+    fn undistort_points(&self, points: &[[f64; 2]]) -> Result<Vec<UndistortedPoint>, Error> {
+        self.left.undistort_points(points)
+    }
+}
+
+impl StereoCalibration {
+    /// The calibration of the camera on one side
+    pub fn camera(&self, side: StereoSide) -> &CalibrationData {
+        match side {
+            StereoSide::Left => &self.left,
+            StereoSide::Right => &self.right,
+        }
+    }
+
+    /// The distance between the cameras, in the units of the board
+    pub fn baseline(&self) -> f64 {
+        self.translation.iter().map(|t| t * t).sum::<f64>().sqrt()
+    }
+
+    /// The angle of the rotation between the cameras, in degrees
+    pub fn rotation_angle(&self) -> f64 {
+        let r = &self.rotation;
+        let c = (r[0] + r[4] + r[8] - 1.0) / 2.0;
+        c.clamp(-1.0, 1.0).acos().to_degrees()
+    }
+
+    /// The rotation between the cameras as angles about the x, y and z axes in degrees, applied in that order
+    pub fn rotation_euler(&self) -> [f64; 3] {
+        let r = &self.rotation;
+        [
+            r[7].atan2(r[8]).to_degrees(),
+            (-r[6]).clamp(-1.0, 1.0).asin().to_degrees(),
+            r[3].atan2(r[0]).to_degrees(),
+        ]
+    }
+
+    /// The calibration for images of both cameras scaled from one resolution to another.
+    /// The extrinsics do not depend on the resolution, the fundamental matrix is changed to match the pixels.
+    pub fn scaled(&self, from: [u32; 2], to: [u32; 2]) -> Self {
+        let sx = to[0] as f64 / from[0] as f64;
+        let sy = to[1] as f64 / from[1] as f64;
+        self.scaled_by([sx, sy], to)
+    }
+
+    /// The calibration for images of both cameras of a size scaled by a factor along each axis
+    fn scaled_by(&self, factor: [f64; 2], size: [u32; 2]) -> Self {
+        // F' = A^T F A with A the map from pixels of the scaled images to pixels of the images scaled, between pixel centers
+        let [ax, ay] = factor.map(|s| 1.0 / s);
+        let a = [
+            [ax, 0.0, (ax - 1.0) / 2.0],
+            [0.0, ay, (ay - 1.0) / 2.0],
+            [0.0, 0.0, 1.0],
+        ];
+        let f = &self.fundamental;
+        let fundamental = std::array::from_fn(|i| {
+            let (r, c) = (i / 3, i % 3);
+            (0..3)
+                .flat_map(|k| (0..3).map(move |l| (k, l)))
+                .map(|(k, l)| a[k][r] * f[k * 3 + l] * a[l][c])
+                .sum()
+        });
+        Self {
+            left: Box::new(self.left.scaled_by(factor, size)),
+            right: Box::new(self.right.scaled_by(factor, size)),
+            rotation: self.rotation,
+            translation: self.translation,
+            essential: self.essential,
+            fundamental,
+            rms: self.rms,
+            resolution: size,
+        }
+    }
+
+    /// The calibration for crops of a size of the images of both cameras, with their top left corner at offset
+    pub fn cropped(&self, offset: [f64; 2], size: [u32; 2]) -> Self {
+        // F' = T^T F T with T the translation from pixels of the crop to pixels of the full image
+        let [ox, oy] = offset;
+        let mut f = self.fundamental;
+        for r in 0..3 {
+            f[r * 3 + 2] += f[r * 3] * ox + f[r * 3 + 1] * oy;
+        }
+        for c in 0..3 {
+            f[6 + c] += f[c] * ox + f[3 + c] * oy;
+        }
+        Self {
+            left: Box::new(self.left.cropped(offset, size)),
+            right: Box::new(self.right.cropped(offset, size)),
+            rotation: self.rotation,
+            translation: self.translation,
+            essential: self.essential,
+            fundamental: f,
+            rms: self.rms,
+            resolution: size,
+        }
+    }
+
+    /// The calibration for images of both cameras binned by a factor
+    pub fn binned(&self, factor: f64, size: [u32; 2]) -> Self {
+        self.scaled_by([1.0 / factor; 2], size)
+    }
+
+    /// The epipolar line in the other camera of an undistorted pixel of one camera,
+    /// as [a, b, c] for the line a x + b y + c = 0
+    pub fn epipolar_line(&self, side: StereoSide, point: [f64; 2]) -> [f64; 3] {
+        let f = &self.fundamental;
+        let p = [point[0], point[1], 1.0];
+        match side {
+            // F p
+            StereoSide::Left => {
+                [0, 1, 2].map(|r| f[r * 3] * p[0] + f[r * 3 + 1] * p[1] + f[r * 3 + 2] * p[2])
+            }
+            // F^T p
+            StereoSide::Right => [0, 1, 2].map(|c| f[c] * p[0] + f[3 + c] * p[1] + f[6 + c] * p[2]),
+        }
+    }
+}
+
+/// How a calibration was made, saved as a json file next to the calibration file
+#[derive(Clone, Debug, Default, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
+pub struct CalibrationMetadata {
+    /// When the calibration was done, in rfc 3339 format
+    pub timestamp: String,
+    /// The name of the camera that was calibrated
+    pub camera: String,
+    /// A description of the calibration board
+    pub board: String,
+    /// The width and height of the images the calibration was made with
+    pub resolution: Option<[u32; 2]>,
+    /// The rms reprojection error over all images, in pixels
+    pub rms: f64,
+    /// The rms reprojection error of each image, None for images with too few corners
+    pub view_errors: Vec<Option<f64>>,
+    /// Checks of the calibration against distances measured by hand, oldest first
+    pub distance_checks: Vec<DistanceCheck>,
+    /// The temperature when the calibration was done in degrees celsius, None when it was not recorded
+    pub temperature: Option<f64>,
+}
+
+/// A check of a calibration with two markers a measured distance apart
+#[derive(Clone, Debug, Default, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
+pub struct DistanceCheck {
+    /// When the check was done, in rfc 3339 format
+    pub timestamp: String,
+    /// The ids of the two markers
+    pub markers: [i32; 2],
+    /// The distance between the centers of the markers measured by hand, in millimeters
+    pub measured: f64,
+    /// The distance between the centers of the markers found with the calibration, in millimeters
+    pub computed: f64,
+}
+
+impl DistanceCheck {
+    /// How far the computed distance is from the measured one, as a percentage of the measured one
+    pub fn error_percent(&self) -> f64 {
+        (self.computed - self.measured) / self.measured * 100.0
+    }
+}
+
+impl CalibrationMetadata {
+    /// The file the metadata of a calibration file is stored in
+    pub fn sidecar(path: &Path) -> std::path::PathBuf {
+        path.with_extension("json")
+    }
+
+    /// Load the metadata of a calibration file, None when there is none
+    pub fn load(path: &Path) -> Option<Self> {
+        let c = std::fs::read_to_string(Self::sidecar(path)).ok()?;
+        serde_json::from_str(&c).ok()
+    }
+
+    /// Save the metadata of a calibration file
+    pub fn save(&self, path: &Path) -> std::io::Result<()> {
+        let c = serde_json::to_string_pretty(self).map_err(std::io::Error::other)?;
+        std::fs::write(Self::sidecar(path), c)
+    }
+}
+
+/// How the markers of a board are searched for
+#[derive(Clone, Copy, Debug, Default, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
+pub struct DetectionOptions {
+    /// Search for the markers in a copy of the image shrunk to at most this width, None searches the full image.
+    /// The corners are still refined at full resolution.
+    pub max_width: Option<u32>,
+}
@@ -0,0 +1,399 @@
+//! The charuco calibration routine and the opencv side of the calibration data
+
+use std::borrow::Borrow;
+
+use eframe::egui::ColorImage;
+use opencv::core::{
+    FileNodeTraitConst, FileStorageTraitConst, MatTraitConst, MatTraitConstManual, MatTraitManual,
+};
+use rayon::iter::{IntoParallelIterator, ParallelIterator};
+
+use super::{
+    CalibrationData, CalibrationDataTrait, DetectionOptions, SaveableOpencvMat, UndistortedPoint,
+};
+use crate::aruco::{self, CharucoBoard, CharucoParams, Dictionary, Outlines};
+use crate::cancel::CancelToken;
+
+impl From<opencv::core::Mat> for SaveableOpencvMat {
+    fn from(value: opencv::core::Mat) -> Self {
+        let s = value.size().unwrap();
+        let t = Self {
+            width: s.width,
+            height: s.height,
+            typ: value.typ(),
+            data: value.data_bytes().unwrap().to_vec(),
+        };
+        t
+    }
+}
+
+impl opencv::core::MatTraitConst for SaveableOpencvMat {
+    fn as_raw_Mat(&self) -> *const opencv::mod_prelude_sys::c_void {
+        self.data.as_ptr() as *const opencv::mod_prelude_sys::c_void
+    }
+}
+
+impl Into<opencv::core::Mat> for SaveableOpencvMat {
+    fn into(self) -> opencv::core::Mat {
+        let mut size = opencv::core::Size::default();
+        size.width = self.width;
+        size.height = self.height;
+        let mut orig = opencv::core::Mat::new_size_with_default(
+            size,
+            opencv::core::CV_64FC1,
+            Default::default(),
+        )
+        .unwrap();
+        let p = orig.data_bytes_mut().unwrap();
+        p.copy_from_slice(&self.data);
+        orig
+    }
+}
+
+impl CalibrationDataTrait for [SaveableOpencvMat; 2] {
+    fn apply_calibration(&self, img: ColorImage) -> ColorImage {
+        // Opencv works in the bgr order of the camera images, the image comes back in the order it was given
+        let Some(mat) = crate::convert::color_image_to_bgr_mat(&img) else {
+            return img;
+        };
+        let mut oimg: opencv::core::Mat = Default::default();
+        let cm: opencv::core::Mat = self[0].clone().into();
+        let dc: opencv::core::Mat = self[1].clone().into();
+        if opencv::calib3d::undistort(&mat, &mut oimg, &cm, &dc, &opencv::core::no_array()).is_err()
+        {
+            return img;
+        }
+        crate::convert::bgr_mat_to_color_image(&oimg).unwrap_or(img)
+    }
+
+    fn undistort_points(&self, points: &[[f64; 2]]) -> opencv::Result<Vec<UndistortedPoint>> {
+        let k = self[0].values();
+        if k.len() != 9 {
+            return Err(opencv::Error::new(
+                opencv::core::StsBadArg,
+                "The camera matrix is not 3x3",
+            ));
+        }
+        let src: opencv::core::Vector<opencv::core::Point2d> = points
+            .iter()
+            .map(|p| opencv::core::Point2d::new(p[0], p[1]))
+            .collect();
+        let mut dst: opencv::core::Vector<opencv::core::Point2d> = Default::default();
+        let cm: opencv::core::Mat = self[0].clone().into();
+        let dc: opencv::core::Mat = self[1].clone().into();
+        opencv::calib3d::undistort_points_def(&src, &mut dst, &cm, &dc)?;
+        Ok(dst
+            .iter()
+            .map(|n| UndistortedPoint {
+                pixel: [k[0] * n.x + k[1] * n.y + k[2], k[4] * n.y + k[5]],
+                normalized: [n.x, n.y],
+            })
+            .collect())
+    }
+}
+
+impl CalibrationData {
+    /// Load a calibration from an opencv yaml or xml file with camera_matrix and distortion_coefficients,
+    /// the names used by opencv's calibration samples
+    pub fn load_yaml(path: &std::path::Path) -> opencv::Result<Self> {
+        let fs = opencv::core::FileStorage::new_def(
+            &path.to_string_lossy(),
+            opencv::core::FileStorage_Mode::READ as i32,
+        )?;
+        let read = |name: &str| -> opencv::Result<opencv::core::Mat> {
+            let m = fs.get(name)?.mat()?;
+            if m.empty() {
+                return Err(opencv::Error::new(
+                    opencv::core::StsObjectNotFound,
+                    format!("{} has no {}", path.display(), name),
+                ));
+            }
+            let mut f = opencv::core::Mat::default();
+            m.convert_to(&mut f, opencv::core::CV_64F, 1.0, 0.0)?;
+            Ok(f)
+        };
+        let cm = read("camera_matrix")?;
+        let dc = read("distortion_coefficients")?;
+        Ok(CalibrationData::OpenCvCharuco([cm.into(), dc.into()]))
+    }
+}
+
+/// A single channel copy of an image, the markers are found in the brightness alone
+pub fn to_gray(img: &opencv::core::Mat) -> opencv::Result<opencv::core::Mat> {
+    let mut gray = opencv::core::Mat::default();
+    match img.channels() {
+        1 => img.copy_to(&mut gray)?,
+        4 => opencv::imgproc::cvt_color_def(img, &mut gray, opencv::imgproc::COLOR_BGRA2GRAY)?,
+        _ => opencv::imgproc::cvt_color_def(img, &mut gray, opencv::imgproc::COLOR_BGR2GRAY)?,
+    }
+    Ok(gray)
+}
+
+/// What was found of a charuco board in an image
+pub struct DetectionResult {
+    /// The outlines of the markers that were found
+    pub markers: Outlines,
+    /// The id of each marker that was found
+    pub marker_ids: opencv::core::Vector<i32>,
+    /// The outlines of shapes that looked like markers but were not
+    pub rejected: Outlines,
+    /// The chessboard corners between the markers, in pixels of the full image
+    pub corners: opencv::core::Vector<opencv::core::Point2f>,
+    /// The id of each chessboard corner
+    pub corner_ids: opencv::core::Vector<i32>,
+}
+
+/// Find a charuco board in an image. The markers are searched for in grayscale, shrunk as the options ask,
+/// and the chessboard corners are interpolated at full resolution. Color and monochrome images are both accepted.
+pub fn detect_board(
+    img: &opencv::core::Mat,
+    board: &CharucoBoard,
+    dictionary: &Dictionary,
+    options: DetectionOptions,
+) -> opencv::Result<DetectionResult> {
+    let gray = to_gray(img)?;
+    let scale = options
+        .max_width
+        .filter(|w| *w > 0 && gray.cols() > *w as i32)
+        .map(|w| w as f64 / gray.cols() as f64);
+    let mut small = opencv::core::Mat::default();
+    let searched = if let Some(scale) = scale {
+        opencv::imgproc::resize(
+            &gray,
+            &mut small,
+            opencv::core::Size::default(),
+            scale,
+            scale,
+            opencv::imgproc::INTER_AREA,
+        )?;
+        &small
+    } else {
+        &gray
+    };
+    let (mut markers, marker_ids, mut rejected) = aruco::detect_markers(searched, dictionary)?;
+    if let Some(scale) = scale {
+        // Back to the pixels of the full image, measured from pixel centers
+        let up = |v: f32| ((v as f64 + 0.5) / scale - 0.5) as f32;
+        let unshrink = |outlines: Outlines| -> Outlines {
+            outlines
+                .iter()
+                .map(|o| {
+                    o.iter()
+                        .map(|p| opencv::core::Point2f::new(up(p.x), up(p.y)))
+                        .collect()
+                })
+                .collect()
+        };
+        markers = unshrink(markers);
+        rejected = unshrink(rejected);
+    }
+    let (corners, corner_ids) = if marker_ids.is_empty() {
+        Default::default()
+    } else {
+        aruco::interpolate_corners(&markers, &marker_ids, &gray, board)?
+    };
+    Ok(DetectionResult {
+        markers,
+        marker_ids,
+        rejected,
+        corners,
+        corner_ids,
+    })
+}
+
+/// Find the charuco corners in an image, returning their pixels and ids.
+/// Color and monochrome images are both accepted.
+pub fn detect_charuco(
+    img: &opencv::core::Mat,
+    board: &CharucoBoard,
+    dictionary: &Dictionary,
+) -> opencv::Result<(
+    opencv::core::Vector<opencv::core::Point2f>,
+    opencv::core::Vector<i32>,
+)> {
+    let r = detect_board(img, board, dictionary, DetectionOptions::default())?;
+    Ok((r.corners, r.corner_ids))
+}
+
+/// Find the charuco corners in every image, with the images spread over all processor cores.
+/// Each thread makes its own board from the parameters, opencv boards are not shared between threads.
+/// Stops with an error soon after cancel is set.
+pub fn detect_charuco_all<M: Borrow<opencv::core::Mat> + Sync>(
+    images: &[M],
+    board: &CharucoParams,
+    options: DetectionOptions,
+    cancel: &CancelToken,
+) -> opencv::Result<
+    Vec<(
+        opencv::core::Vector<opencv::core::Point2f>,
+        opencv::core::Vector<i32>,
+    )>,
+> {
+    // The results are plain vectors while crossing threads, the opencv vectors are made afterwards
+    let found: Vec<(Vec<opencv::core::Point2f>, Vec<i32>)> = (0..images.len())
+        .into_par_iter()
+        .map_init(
+            || board.make(),
+            |made, n| {
+                cancel.check()?;
+                let (board, dictionary) = made
+                    .as_ref()
+                    .map_err(|e| opencv::Error::new(e.code, e.message.clone()))?;
+                let r = detect_board(images[n].borrow(), board, dictionary, options)?;
+                Ok((r.corners.to_vec(), r.corner_ids.to_vec()))
+            },
+        )
+        .collect::<opencv::Result<_>>()?;
+    Ok(found
+        .into_iter()
+        .map(|(c, i)| (c.into_iter().collect(), i.into_iter().collect()))
+        .collect())
+}
+
+/// Calibrate a camera from images of a charuco board, returning the calibration and the rms reprojection error
+pub fn calibrate_charuco<M: Borrow<opencv::core::Mat> + Sync>(
+    images: &[M],
+    board: &CharucoParams,
+) -> opencv::Result<(CalibrationData, f64)> {
+    calibrate_charuco_cancellable(
+        images,
+        board,
+        DetectionOptions::default(),
+        &CancelToken::new(),
+    )
+}
+
+/// Calibrate a camera like calibrate_charuco, searching for the markers as the options ask.
+/// Stops with an error soon after cancel is set.
+pub fn calibrate_charuco_cancellable<M: Borrow<opencv::core::Mat> + Sync>(
+    images: &[M],
+    board: &CharucoParams,
+    options: DetectionOptions,
+    cancel: &CancelToken,
+) -> opencv::Result<(CalibrationData, f64)> {
+    let Some(first): Option<&opencv::core::Mat> = images.first().map(Borrow::borrow) else {
+        return Err(opencv::Error::new(
+            opencv::core::StsBadArg,
+            "There are no images to calibrate with",
+        ));
+    };
+    let mut camera_matrix: opencv::core::Mat = Default::default();
+    let mut dist_coeffs: opencv::core::Mat = Default::default();
+    let mut all_corners: opencv::core::Vector<opencv::core::Vector<opencv::core::Point2f>> =
+        Default::default();
+    let mut all_ids: opencv::core::Vector<opencv::core::Vector<i32>> = Default::default();
+    // Each image is a view of the board in its own pose, images with too few corners for a pose are left out
+    for (corners, ids) in detect_charuco_all(images, board, options, cancel)? {
+        if corners.len() < 6 {
+            continue;
+        }
+        all_corners.push(corners);
+        all_ids.push(ids);
+    }
+    cancel.check()?;
+    if all_corners.is_empty() {
+        return Err(opencv::Error::new(
+            opencv::core::StsBadArg,
+            "The board was not found in any image",
+        ));
+    }
+    let criteria = opencv::core::TermCriteria {
+        typ: opencv::core::TermCriteria_Type::EPS as i32
+            + opencv::core::TermCriteria_Type::COUNT as i32,
+        max_count: 30,
+        epsilon: 0.1,
+    };
+    let size = opencv::core::Size {
+        width: first.cols(),
+        height: first.rows(),
+    };
+    let (board, _) = board.make()?;
+    let rms = aruco::calibrate_camera_charuco(
+        &all_corners,
+        &all_ids,
+        &board,
+        size,
+        &mut camera_matrix,
+        &mut dist_coeffs,
+        0,
+        criteria,
+    )?;
+    let cm: SaveableOpencvMat = camera_matrix.into();
+    let dc: SaveableOpencvMat = dist_coeffs.into();
+    Ok((CalibrationData::OpenCvCharuco([cm, dc]), rms))
+}
+
+/// How far a corner found in an image is from where the calibration puts it
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Residual {
+    /// Where the corner was found, in pixels
+    pub found: [f32; 2],
+    /// The found position minus the reprojected one, in pixels
+    pub error: [f32; 2],
+}
+
+/// The residual of every corner of each image, found by fitting the pose of the board with the calibration.
+/// Images where too few corners were found have none.
+pub fn residuals<M: Borrow<opencv::core::Mat> + Sync>(
+    images: &[M],
+    cd: &CalibrationData,
+    board: &CharucoParams,
+) -> opencv::Result<Vec<Option<Vec<Residual>>>> {
+    let board_corners = aruco::chessboard_corners(&board.make()?.0)?;
+    let mut all = Vec::with_capacity(images.len());
+    for (corners, ids) in detect_charuco_all(
+        images,
+        board,
+        DetectionOptions::default(),
+        &CancelToken::new(),
+    )? {
+        if corners.len() < 6 {
+            all.push(None);
+            continue;
+        }
+        let mut object: opencv::core::Vector<opencv::core::Point3f> = Default::default();
+        for id in ids.iter() {
+            object.push(board_corners.get(id as usize)?);
+        }
+        let Some(projected) = super::fisheye::reproject(&object, &corners, cd)? else {
+            all.push(None);
+            continue;
+        };
+        all.push(Some(
+            corners
+                .iter()
+                .zip(projected.iter())
+                .map(|(a, b)| Residual {
+                    found: [a.x, a.y],
+                    error: [a.x - b.x, a.y - b.y],
+                })
+                .collect(),
+        ));
+    }
+    Ok(all)
+}
+
+/// The rms of residuals, in pixels
+pub fn rms_error(residuals: &[Residual]) -> f64 {
+    let sum: f64 = residuals
+        .iter()
+        .map(|r| {
+            let (dx, dy) = (r.error[0] as f64, r.error[1] as f64);
+            dx * dx + dy * dy
+        })
+        .sum();
+    (sum / residuals.len().max(1) as f64).sqrt()
+}
+
+/// The rms reprojection error of each image, found by fitting the pose of the board with the calibration.
+/// Images where too few corners were found have no error.
+pub fn view_errors<M: Borrow<opencv::core::Mat> + Sync>(
+    images: &[M],
+    cd: &CalibrationData,
+    board: &CharucoParams,
+) -> opencv::Result<Vec<Option<f64>>> {
+    Ok(residuals(images, cd, board)?
+        .iter()
+        .map(|r| r.as_deref().map(rms_error))
+        .collect())
+}
@@ -0,0 +1,189 @@
+//! Calibration with opencv's fisheye model, for wide angle and action cameras whose distortion
+//! the pinhole model with polynomial distortion can not follow toward the edges of the image.
+
+use std::borrow::Borrow;
+
+use eframe::egui::ColorImage;
+use opencv::core::{MatTraitConst, Point2d, Point2f, Point3f, Vector};
+
+use super::{
+    CalibrationData, CalibrationDataTrait, DetectionOptions, FisheyeCalibration, SaveableOpencvMat,
+    UndistortedPoint, detect_charuco_all,
+};
+use crate::aruco::{self, CharucoParams};
+use crate::cancel::CancelToken;
+
+impl CalibrationDataTrait for FisheyeCalibration {
+    fn apply_calibration(&self, img: ColorImage) -> ColorImage {
+        // Opencv works in the bgr order of the camera images, the image comes back in the order it was given
+        let Some(mat) = crate::convert::color_image_to_bgr_mat(&img) else {
+            return img;
+        };
+        let cm: opencv::core::Mat = self.0[0].clone().into();
+        let dc: opencv::core::Mat = self.0[1].clone().into();
+        let mut out = opencv::core::Mat::default();
+        // The undistorted image keeps the camera matrix, so it is the same size and scale as the distorted one
+        if opencv::calib3d::fisheye_undistort_image(
+            &mat,
+            &mut out,
+            &cm,
+            &dc,
+            &cm,
+            opencv::core::Size::default(),
+        )
+        .is_err()
+        {
+            return img;
+        }
+        crate::convert::bgr_mat_to_color_image(&out).unwrap_or(img)
+    }
+
+    fn undistort_points(&self, points: &[[f64; 2]]) -> opencv::Result<Vec<UndistortedPoint>> {
+        let k = self.0[0].values();
+        if k.len() != 9 {
+            return Err(opencv::Error::new(
+                opencv::core::StsBadArg,
+                "The camera matrix is not 3x3",
+            ));
+        }
+        let src: Vector<Point2d> = points.iter().map(|p| Point2d::new(p[0], p[1])).collect();
+        let mut dst = Vector::<Point2d>::new();
+        let cm: opencv::core::Mat = self.0[0].clone().into();
+        let dc: opencv::core::Mat = self.0[1].clone().into();
+        opencv::calib3d::fisheye_undistort_points_def(&src, &mut dst, &cm, &dc)?;
+        Ok(dst
+            .iter()
+            .map(|n| UndistortedPoint {
+                pixel: [k[0] * n.x + k[1] * n.y + k[2], k[4] * n.y + k[5]],
+                normalized: [n.x, n.y],
+            })
+            .collect())
+    }
+}
+
+/// Fit the pose of a target to the points found of it with a calibration, and project the target back into the image.
+/// None when no pose fits.
+pub(super) fn reproject(
+    object: &Vector<Point3f>,
+    found: &Vector<Point2f>,
+    cd: &CalibrationData,
+) -> opencv::Result<Option<Vector<Point2f>>> {
+    let cm: opencv::core::Mat = cd.camera_matrix().clone().into();
+    let dc: opencv::core::Mat = cd.distortion().clone().into();
+    let mut rvec = opencv::core::Mat::default();
+    let mut tvec = opencv::core::Mat::default();
+    let mut projected = Vector::<Point2f>::new();
+    if let CalibrationData::Telecentric(t) = cd {
+        let object: Vec<[f64; 3]> = object
+            .iter()
+            .map(|p| [p.x as f64, p.y as f64, p.z as f64])
+            .collect();
+        let found: Vec<[f64; 2]> = found.iter().map(|p| [p.x as f64, p.y as f64]).collect();
+        return Ok(t.reproject(&object, &found).map(|p| {
+            p.iter()
+                .map(|p| Point2f::new(p[0] as f32, p[1] as f32))
+                .collect()
+        }));
+    } else if let CalibrationData::OpenCvFisheye(f) = cd {
+        // The pose is fitted to the undistorted points, which the pinhole model without distortion describes
+        let points: Vec<[f64; 2]> = found.iter().map(|p| [p.x as f64, p.y as f64]).collect();
+        let normalized: Vector<Point2f> = f
+            .undistort_points(&points)?
+            .iter()
+            .map(|u| Point2f::new(u.normalized[0] as f32, u.normalized[1] as f32))
+            .collect();
+        let identity = opencv::core::Mat::eye(3, 3, opencv::core::CV_64F)?.to_mat()?;
+        if !opencv::calib3d::solve_pnp_def(
+            object,
+            &normalized,
+            &identity,
+            &opencv::core::no_array(),
+            &mut rvec,
+            &mut tvec,
+        )? {
+            return Ok(None);
+        }
+        opencv::calib3d::fisheye_project_points_def(
+            object,
+            &mut projected,
+            &rvec,
+            &tvec,
+            &cm,
+            &dc,
+        )?;
+    } else {
+        if !opencv::calib3d::solve_pnp_def(object, found, &cm, &dc, &mut rvec, &mut tvec)? {
+            return Ok(None);
+        }
+        opencv::calib3d::project_points_def(object, &rvec, &tvec, &cm, &dc, &mut projected)?;
+    }
+    Ok(Some(projected))
+}
+
+/// Calibrate a camera with the fisheye model from images of a charuco board,
+/// returning the calibration and the rms reprojection error. Stops with an error soon after cancel is set.
+pub fn calibrate_fisheye<M: Borrow<opencv::core::Mat> + Sync>(
+    images: &[M],
+    board: &CharucoParams,
+    options: DetectionOptions,
+    cancel: &CancelToken,
+) -> opencv::Result<(CalibrationData, f64)> {
+    let Some(first): Option<&opencv::core::Mat> = images.first().map(Borrow::borrow) else {
+        return Err(opencv::Error::new(
+            opencv::core::StsBadArg,
+            "There are no images to calibrate with",
+        ));
+    };
+    let size = opencv::core::Size {
+        width: first.cols(),
+        height: first.rows(),
+    };
+    let board_corners = aruco::chessboard_corners(&board.make()?.0)?;
+    let mut object = Vector::<Vector<Point3f>>::new();
+    let mut image = Vector::<Vector<Point2f>>::new();
+    for (corners, ids) in detect_charuco_all(images, board, options, cancel)? {
+        // The fisheye calibration fits a homography to each view to start from, which needs a few corners
+        if corners.len() < 6 {
+            continue;
+        }
+        let o: Vector<Point3f> = ids
+            .iter()
+            .map(|id| board_corners.get(id as usize))
+            .collect::<opencv::Result<_>>()?;
+        object.push(o);
+        image.push(corners);
+    }
+    cancel.check()?;
+    if object.is_empty() {
+        return Err(opencv::Error::new(
+            opencv::core::StsBadArg,
+            "The board was not found in any image",
+        ));
+    }
+    let mut camera_matrix = opencv::core::Mat::default();
+    let mut dist_coeffs = opencv::core::Mat::default();
+    let criteria = opencv::core::TermCriteria {
+        typ: opencv::core::TermCriteria_Type::EPS as i32
+            + opencv::core::TermCriteria_Type::COUNT as i32,
+        max_count: 100,
+        epsilon: 1e-6,
+    };
+    let rms = opencv::calib3d::fisheye_calibrate(
+        &object,
+        &image,
+        size,
+        &mut camera_matrix,
+        &mut dist_coeffs,
+        &mut opencv::core::no_array(),
+        &mut opencv::core::no_array(),
+        opencv::calib3d::fisheye_CALIB_RECOMPUTE_EXTRINSIC
+            | opencv::calib3d::fisheye_CALIB_FIX_SKEW,
+        criteria,
+    )?;
+    let cm: SaveableOpencvMat = camera_matrix.into();
+    let dc: SaveableOpencvMat = dist_coeffs.into();
+    Ok((
+        CalibrationData::OpenCvFisheye(FisheyeCalibration([cm, dc])),
+        rms,
+    ))
+}
@@ -0,0 +1,220 @@
+//! Calibration with targets without markers, plain chessboards and asymmetric circle grids,
+//! for when a charuco board can not be printed. The whole target has to be in view for it to be found, unlike a charuco board.
+
+use std::borrow::Borrow;
+
+use opencv::core::{MatTraitConst, Point2f, Point3f, Vector};
+use rayon::iter::{IntoParallelIterator, ParallelIterator};
+
+use super::{CalibrationData, DetectionResult, Residual, SaveableOpencvMat, to_gray};
+use crate::cancel::CancelToken;
+
+/// A calibration target without markers, the lengths are in meters
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Grid {
+    /// A chessboard of cols by rows squares
+    Chessboard { cols: i32, rows: i32, square: f32 },
+    /// Rows of cols circles, every other row shifted right by spacing. The rows are spacing apart
+    /// and the circles of a row twice that, the circles are diameter across.
+    AsymmetricCircles {
+        cols: i32,
+        rows: i32,
+        spacing: f32,
+        diameter: f32,
+    },
+}
+
+impl Grid {
+    /// The points found on the target, in meters on the target.
+    /// They are in the order opencv finds them, row by row, so the index of a point is its id like on a charuco board.
+    pub fn points(&self) -> Vector<Point3f> {
+        match *self {
+            Grid::Chessboard { cols, rows, square } => (1..rows)
+                .flat_map(|y| {
+                    (1..cols).map(move |x| Point3f::new(x as f32 * square, y as f32 * square, 0.0))
+                })
+                .collect(),
+            Grid::AsymmetricCircles {
+                cols,
+                rows,
+                spacing,
+                ..
+            } => (0..rows)
+                .flat_map(|y| {
+                    (0..cols).map(move |x| {
+                        Point3f::new((2 * x + y % 2) as f32 * spacing, y as f32 * spacing, 0.0)
+                    })
+                })
+                .collect(),
+        }
+    }
+
+    /// Find the target in an image, the points are refined to a fraction of a pixel.
+    /// Color and monochrome images are both accepted. Nothing is found unless all of the target is in view.
+    pub fn detect(&self, img: &opencv::core::Mat) -> opencv::Result<DetectionResult> {
+        let gray = to_gray(img)?;
+        let mut corners = Vector::<Point2f>::new();
+        match *self {
+            Grid::Chessboard { cols, rows, .. } => {
+                let found = opencv::calib3d::find_chessboard_corners(
+                    &gray,
+                    opencv::core::Size::new(cols - 1, rows - 1),
+                    &mut corners,
+                    opencv::calib3d::CALIB_CB_ADAPTIVE_THRESH
+                        | opencv::calib3d::CALIB_CB_NORMALIZE_IMAGE
+                        | opencv::calib3d::CALIB_CB_FAST_CHECK,
+                )?;
+                if !found {
+                    corners.clear();
+                } else {
+                    let criteria = opencv::core::TermCriteria {
+                        typ: opencv::core::TermCriteria_Type::EPS as i32
+                            + opencv::core::TermCriteria_Type::COUNT as i32,
+                        max_count: 30,
+                        epsilon: 0.01,
+                    };
+                    opencv::imgproc::corner_sub_pix(
+                        &gray,
+                        &mut corners,
+                        opencv::core::Size::new(11, 11),
+                        opencv::core::Size::new(-1, -1),
+                        criteria,
+                    )?;
+                }
+            }
+            Grid::AsymmetricCircles { cols, rows, .. } => {
+                // The default blob detector misses circles bigger than 5000 pixels, which close up views have
+                let mut params = opencv::features2d::SimpleBlobDetector_Params::default()?;
+                params.max_area = (gray.cols() * gray.rows()) as f32 / (cols * rows) as f32;
+                let detector: opencv::core::Ptr<opencv::features2d::Feature2D> =
+                    opencv::features2d::SimpleBlobDetector::create(params)?.into();
+                // The centers of the blobs are already found to a fraction of a pixel
+                let found = opencv::calib3d::find_circles_grid_1(
+                    &gray,
+                    opencv::core::Size::new(cols, rows),
+                    &mut corners,
+                    opencv::calib3d::CALIB_CB_ASYMMETRIC_GRID,
+                    &detector,
+                )?;
+                if !found {
+                    corners.clear();
+                }
+            }
+        }
+        let corner_ids = (0..corners.len() as i32).collect();
+        Ok(DetectionResult {
+            markers: Default::default(),
+            marker_ids: Default::default(),
+            rejected: Default::default(),
+            corners,
+            corner_ids,
+        })
+    }
+}
+
+/// Find the target in every image, with the images spread over all processor cores.
+/// Images where the target was not found have no points. Stops with an error soon after cancel is set.
+pub fn detect_grid_all<M: Borrow<opencv::core::Mat> + Sync>(
+    images: &[M],
+    grid: Grid,
+    cancel: &CancelToken,
+) -> opencv::Result<Vec<Vector<Point2f>>> {
+    // The results are plain vectors while crossing threads, the opencv vectors are made afterwards
+    let found: Vec<Vec<Point2f>> = (0..images.len())
+        .into_par_iter()
+        .map(|n| {
+            cancel.check()?;
+            Ok(grid.detect(images[n].borrow())?.corners.to_vec())
+        })
+        .collect::<opencv::Result<_>>()?;
+    Ok(found.into_iter().map(|c| c.into_iter().collect()).collect())
+}
+
+/// Calibrate a camera from images of a target without markers,
+/// returning the calibration and the rms reprojection error. Stops with an error soon after cancel is set.
+pub fn calibrate_grid<M: Borrow<opencv::core::Mat> + Sync>(
+    images: &[M],
+    grid: Grid,
+    cancel: &CancelToken,
+) -> opencv::Result<(CalibrationData, f64)> {
+    let Some(first): Option<&opencv::core::Mat> = images.first().map(Borrow::borrow) else {
+        return Err(opencv::Error::new(
+            opencv::core::StsBadArg,
+            "There are no images to calibrate with",
+        ));
+    };
+    let size = opencv::core::Size {
+        width: first.cols(),
+        height: first.rows(),
+    };
+    let points = grid.points();
+    let mut object = Vector::<Vector<Point3f>>::new();
+    let mut image = Vector::<Vector<Point2f>>::new();
+    for found in detect_grid_all(images, grid, cancel)? {
+        if !found.is_empty() {
+            object.push(points.clone());
+            image.push(found);
+        }
+    }
+    cancel.check()?;
+    if object.is_empty() {
+        return Err(opencv::Error::new(
+            opencv::core::StsBadArg,
+            "The target was not found in any image",
+        ));
+    }
+    let mut camera_matrix = opencv::core::Mat::default();
+    let mut dist_coeffs = opencv::core::Mat::default();
+    let criteria = opencv::core::TermCriteria {
+        typ: opencv::core::TermCriteria_Type::EPS as i32
+            + opencv::core::TermCriteria_Type::COUNT as i32,
+        max_count: 30,
+        epsilon: 0.1,
+    };
+    let rms = opencv::calib3d::calibrate_camera(
+        &object,
+        &image,
+        size,
+        &mut camera_matrix,
+        &mut dist_coeffs,
+        &mut opencv::core::no_array(),
+        &mut opencv::core::no_array(),
+        0,
+        criteria,
+    )?;
+    let cm: SaveableOpencvMat = camera_matrix.into();
+    let dc: SaveableOpencvMat = dist_coeffs.into();
+    Ok((CalibrationData::OpenCvCharuco([cm, dc]), rms))
+}
+
+/// The residual of every point of each image like residuals, for a target without markers.
+/// Images where the target was not found have none.
+pub fn grid_residuals<M: Borrow<opencv::core::Mat> + Sync>(
+    images: &[M],
+    cd: &CalibrationData,
+    grid: Grid,
+) -> opencv::Result<Vec<Option<Vec<Residual>>>> {
+    let points = grid.points();
+    let mut all = Vec::with_capacity(images.len());
+    for found in detect_grid_all(images, grid, &CancelToken::new())? {
+        if found.is_empty() {
+            all.push(None);
+            continue;
+        }
+        let Some(projected) = super::fisheye::reproject(&points, &found, cd)? else {
+            all.push(None);
+            continue;
+        };
+        all.push(Some(
+            found
+                .iter()
+                .zip(projected.iter())
+                .map(|(a, b)| Residual {
+                    found: [a.x, a.y],
+                    error: [a.x - b.x, a.y - b.y],
+                })
+                .collect(),
+        ));
+    }
+    Ok(all)
+}
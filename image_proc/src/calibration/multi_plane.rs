@@ -0,0 +1,130 @@
+//! Calibration targets of two charuco boards joined along an edge at a known angle, like the inside of a corner cube.
+//! Corners that are not all in one plane pin down the focal length much better than a flat board,
+//! which barely constrains it for long lenses.
+
+use std::borrow::Borrow;
+
+use opencv::core::{MatTraitConst, Point2f, Point3f, Vector};
+
+use super::{CalibrationData, DetectionOptions, SaveableOpencvMat, detect_charuco_all};
+use crate::aruco::{self, CharucoBoard, CharucoParams};
+use crate::cancel::CancelToken;
+
+/// The chessboard corners of both boards in the coordinates of the target, in meters.
+/// The first board lies in the z = 0 plane and is width wide. The left edge of the second board is joined to
+/// the right edge of the first, folded toward the camera so the faces of the boards are angle degrees apart.
+/// An angle of 180 degrees makes one flat board, 90 degrees the inside of a corner cube.
+pub fn target_corners(
+    first: &CharucoBoard,
+    second: &CharucoBoard,
+    width: f32,
+    angle: f64,
+) -> opencv::Result<[Vector<Point3f>; 2]> {
+    let fold = std::f64::consts::PI - angle.to_radians();
+    let (s, c) = (fold.sin() as f32, fold.cos() as f32);
+    let second: Vector<Point3f> = aruco::chessboard_corners(second)?
+        .iter()
+        .map(|p| Point3f::new(width + p.x * c, p.y, -p.x * s))
+        .collect();
+    Ok([aruco::chessboard_corners(first)?, second])
+}
+
+/// Calibrate a camera from images of a target of two boards, returning the calibration and the rms reprojection error.
+/// The boards must have different markers, the angle is between their faces in degrees.
+/// Stops with an error soon after cancel is set.
+pub fn calibrate_multi_plane<M: Borrow<opencv::core::Mat> + Sync>(
+    images: &[M],
+    boards: [&CharucoParams; 2],
+    width: f32,
+    angle: f64,
+    options: DetectionOptions,
+    cancel: &CancelToken,
+) -> opencv::Result<(CalibrationData, f64)> {
+    let Some(first): Option<&opencv::core::Mat> = images.first().map(Borrow::borrow) else {
+        return Err(opencv::Error::new(
+            opencv::core::StsBadArg,
+            "There are no images to calibrate with",
+        ));
+    };
+    let size = opencv::core::Size {
+        width: first.cols(),
+        height: first.rows(),
+    };
+    let made = [boards[0].make()?.0, boards[1].make()?.0];
+    let target = target_corners(&made[0], &made[1], width, angle)?;
+    let flat = [
+        aruco::chessboard_corners(&made[0])?,
+        aruco::chessboard_corners(&made[1])?,
+    ];
+    let found = [
+        detect_charuco_all(images, boards[0], options, cancel)?,
+        detect_charuco_all(images, boards[1], options, cancel)?,
+    ];
+    cancel.check()?;
+
+    let mut object = Vector::<Vector<Point3f>>::new();
+    let mut image = Vector::<Vector<Point2f>>::new();
+    // The views of the board seen best in each image, in the plane of that board, for the first guess of the intrinsics
+    let mut flat_object = Vector::<Vector<Point3f>>::new();
+    let mut flat_image = Vector::<Vector<Point2f>>::new();
+    for n in 0..images.len() {
+        let mut o = Vector::<Point3f>::new();
+        let mut p = Vector::<Point2f>::new();
+        for b in 0..2 {
+            let (corners, ids) = &found[b][n];
+            for (c, id) in corners.iter().zip(ids.iter()) {
+                o.push(target[b].get(id as usize)?);
+                p.push(c);
+            }
+        }
+        if o.len() < 6 {
+            continue;
+        }
+        object.push(o);
+        image.push(p);
+        let b = if found[0][n].0.len() >= found[1][n].0.len() {
+            0
+        } else {
+            1
+        };
+        let (corners, ids) = &found[b][n];
+        if corners.len() >= 6 {
+            let o: Vector<Point3f> = ids
+                .iter()
+                .map(|id| flat[b].get(id as usize))
+                .collect::<opencv::Result<_>>()?;
+            flat_object.push(o);
+            flat_image.push(corners.clone());
+        }
+    }
+    if flat_object.is_empty() {
+        return Err(opencv::Error::new(
+            opencv::core::StsBadArg,
+            "Neither board was found in any image",
+        ));
+    }
+    // Opencv only calibrates with points that are not in one plane when it is given a guess to start from
+    let mut camera_matrix =
+        opencv::calib3d::init_camera_matrix_2d(&flat_object, &flat_image, size, 1.0)?;
+    let mut dist_coeffs = opencv::core::Mat::default();
+    let criteria = opencv::core::TermCriteria {
+        typ: opencv::core::TermCriteria_Type::EPS as i32
+            + opencv::core::TermCriteria_Type::COUNT as i32,
+        max_count: 30,
+        epsilon: 0.1,
+    };
+    let rms = opencv::calib3d::calibrate_camera(
+        &object,
+        &image,
+        size,
+        &mut camera_matrix,
+        &mut dist_coeffs,
+        &mut opencv::core::no_array(),
+        &mut opencv::core::no_array(),
+        opencv::calib3d::CALIB_USE_INTRINSIC_GUESS,
+        criteria,
+    )?;
+    let cm: SaveableOpencvMat = camera_matrix.into();
+    let dc: SaveableOpencvMat = dist_coeffs.into();
+    Ok((CalibrationData::OpenCvCharuco([cm, dc]), rms))
+}
@@ -0,0 +1,233 @@
+//! The affine camera model of telecentric lenses. Every ray of a telecentric lens is parallel to the optical axis,
+//! so the image is the scene seen straight on and magnified, with no perspective and no focal length.
+//! A pixel is the point across the optical axis times the magnification, moved by the lens distortion
+//! around the center of the image, which is the same as the pinhole model with the depth left out.
+
+use eframe::egui::ColorImage;
+
+use super::{CalibrationDataTrait, Error, TelecentricCalibration, UndistortedPoint};
+use crate::linalg::solve;
+use crate::native::{NativeCalibration, distort, undistort};
+
+/// An affine map from the x and y of points on a flat target to pixels, row by row
+type Affine = [[f64; 3]; 2];
+
+/// The points of a flat target and the pixels they were found at in one image
+pub type View = (Vec<[f64; 3]>, Vec<[f64; 2]>);
+
+/// The undistorted image and the normalized coordinates of points are the same as those of the pinhole model,
+/// the normalized coordinates of a telecentric lens are in the units of the board across the optical axis
+impl CalibrationDataTrait for TelecentricCalibration {
+    fn apply_calibration(&self, img: ColorImage) -> ColorImage {
+        self.0.apply_calibration(img)
+    }
+
+    fn undistort_points(&self, points: &[[f64; 2]]) -> Result<Vec<UndistortedPoint>, Error> {
+        self.0.undistort_points(points)
+    }
+}
+
+impl TelecentricCalibration {
+    fn new(magnification: f64, center: [f64; 2], distortion: [f64; 5]) -> Self {
+        let NativeCalibration(m) = NativeCalibration::new(
+            [magnification, magnification, center[0], center[1]],
+            distortion,
+        );
+        Self(m)
+    }
+
+    /// The magnification of the lens, in pixels per unit of the board
+    pub fn magnification(&self) -> f64 {
+        NativeCalibration(self.0.clone()).intrinsics()[0]
+    }
+
+    /// Fit the pose of a flat target to the pixels it was found at, and project the target back into the image.
+    /// None when too few points were found to fit a pose.
+    pub fn reproject(&self, object: &[[f64; 3]], found: &[[f64; 2]]) -> Option<Vec<[f64; 2]>> {
+        let native = NativeCalibration(self.0.clone());
+        let [m, _, cx, cy] = native.intrinsics();
+        let d = native.distortion();
+        let undistorted: Vec<[f64; 2]> = found
+            .iter()
+            .map(|p| undistort_pixel(&d, m, [cx, cy], *p))
+            .collect();
+        let h = fit_affine(object, &undistorted)?;
+        Some(
+            object
+                .iter()
+                .map(|o| distort_pixel(&d, m, [cx, cy], apply(&h, o)))
+                .collect(),
+        )
+    }
+}
+
+/// The pixel a point on the target is mapped to
+fn apply(h: &Affine, p: &[f64; 3]) -> [f64; 2] {
+    h.map(|r| r[0] * p[0] + r[1] * p[1] + r[2])
+}
+
+fn undistort_pixel(d: &[f64; 5], m: f64, c: [f64; 2], p: [f64; 2]) -> [f64; 2] {
+    let [x, y] = undistort(d, (p[0] - c[0]) / m, (p[1] - c[1]) / m);
+    [c[0] + m * x, c[1] + m * y]
+}
+
+fn distort_pixel(d: &[f64; 5], m: f64, c: [f64; 2], p: [f64; 2]) -> [f64; 2] {
+    let [x, y] = distort(d, (p[0] - c[0]) / m, (p[1] - c[1]) / m);
+    [c[0] + m * x, c[1] + m * y]
+}
+
+/// The least squares affine map from the x and y of points on a flat target to pixels
+fn fit_affine(object: &[[f64; 3]], pixels: &[[f64; 2]]) -> Option<Affine> {
+    if object.len() < 3 || object.len() != pixels.len() {
+        return None;
+    }
+    let mut a = [[0.0; 3]; 3];
+    let mut b = [[0.0; 3]; 2];
+    for (o, p) in object.iter().zip(pixels) {
+        let row = [o[0], o[1], 1.0];
+        for i in 0..3 {
+            for j in 0..3 {
+                a[i][j] += row[i] * row[j];
+            }
+            b[0][i] += row[i] * p[0];
+            b[1][i] += row[i] * p[1];
+        }
+    }
+    Some([solve(a, b[0])?, solve(a, b[1])?])
+}
+
+/// The magnification of the view an affine map was fitted to, with square pixels.
+/// The rows of the map are the magnification times the first two rows of the rotation of the target,
+/// cut to the two columns in its plane, and the rows of a rotation are at right angles and of unit length.
+fn view_magnification(h: &Affine) -> f64 {
+    let p = h[0][0] * h[0][0] + h[0][1] * h[0][1];
+    let q = h[1][0] * h[1][0] + h[1][1] * h[1][1];
+    let s = h[0][0] * h[1][0] + h[0][1] * h[1][1];
+    (((p + q) + ((p - q) * (p - q) + 4.0 * s * s).sqrt()) / 2.0).sqrt()
+}
+
+/// The distortion coefficients that best move the pixels the affine maps predict to those found.
+/// The fit is done with the coordinates scaled to about one at the corners of the image, so it stays well conditioned.
+fn fit_distortion(
+    views: &[&View],
+    affines: &[Affine],
+    m: f64,
+    c: [f64; 2],
+    scale: f64,
+) -> Option<[f64; 5]> {
+    let mut a = [[0.0; 4]; 4];
+    let mut b = [0.0; 4];
+    for ((object, found), h) in views.iter().map(|v| (&v.0, &v.1)).zip(affines) {
+        for (o, f) in object.iter().zip(found) {
+            let u = apply(h, o);
+            let [x, y] = [(u[0] - c[0]) / scale, (u[1] - c[1]) / scale];
+            let [xd, yd] = [(f[0] - c[0]) / scale, (f[1] - c[1]) / scale];
+            let r2 = x * x + y * y;
+            let rows = [
+                ([x * r2, x * r2 * r2, 2.0 * x * y, r2 + 2.0 * x * x], xd - x),
+                ([y * r2, y * r2 * r2, r2 + 2.0 * y * y, 2.0 * x * y], yd - y),
+            ];
+            for (row, e) in rows {
+                for i in 0..4 {
+                    for j in 0..4 {
+                        a[i][j] += row[i] * row[j];
+                    }
+                    b[i] += row[i] * e;
+                }
+            }
+        }
+    }
+    let [k1, k2, p1, p2] = solve(a, b)?;
+    // Back to the coordinates divided by the magnification
+    let l = scale / m;
+    Some([k1 / (l * l), k2 / (l * l * l * l), p1 / l, p2 / l, 0.0])
+}
+
+/// Calibrate a telecentric lens from the points of a flat target found in images of a size,
+/// returning the calibration and the rms reprojection error in pixels. The pixels are taken to be square and
+/// the distortion to be centered on the image. None when no view has enough points to fit.
+pub fn fit_telecentric(views: &[View], size: [u32; 2]) -> Option<(TelecentricCalibration, f64)> {
+    let views: Vec<&View> = views
+        .iter()
+        .filter(|(o, p)| o.len() >= 6 && o.len() == p.len())
+        .collect();
+    if views.is_empty() {
+        return None;
+    }
+    let c = [(size[0] as f64 - 1.0) / 2.0, (size[1] as f64 - 1.0) / 2.0];
+    let scale = c[0].hypot(c[1]).max(1.0);
+    let mut m = 0.0;
+    let mut d = [0.0; 5];
+    // The affine maps and the distortion are fitted in turn, each with the other held still
+    let mut affines = Vec::new();
+    for _ in 0..10 {
+        affines = views
+            .iter()
+            .map(|(o, p)| {
+                let undistorted: Vec<[f64; 2]> = if m > 0.0 {
+                    p.iter().map(|p| undistort_pixel(&d, m, c, *p)).collect()
+                } else {
+                    p.clone()
+                };
+                fit_affine(o, &undistorted)
+            })
+            .collect::<Option<_>>()?;
+        m = affines.iter().map(view_magnification).sum::<f64>() / affines.len() as f64;
+        if !m.is_finite() || m <= 0.0 {
+            return None;
+        }
+        d = fit_distortion(&views, &affines, m, c, scale)?;
+    }
+    let mut sum = 0.0;
+    let mut count = 0;
+    for ((object, found), h) in views.iter().map(|v| (&v.0, &v.1)).zip(&affines) {
+        for (o, f) in object.iter().zip(found) {
+            let p = distort_pixel(&d, m, c, apply(h, o));
+            sum += (p[0] - f[0]).powi(2) + (p[1] - f[1]).powi(2);
+            count += 1;
+        }
+    }
+    let rms = (sum / count as f64).sqrt();
+    Some((TelecentricCalibration::new(m, c, d), rms))
+}
+
+/// Calibrate a telecentric lens from images of a charuco board,
+/// returning the calibration and the rms reprojection error. Stops with an error soon after cancel is set.
+#[cfg(not(target_arch = "wasm32"))]
+pub fn calibrate_telecentric<M: std::borrow::Borrow<opencv::core::Mat> + Sync>(
+    images: &[M],
+    board: &crate::aruco::CharucoParams,
+    options: super::DetectionOptions,
+    cancel: &crate::cancel::CancelToken,
+) -> opencv::Result<(super::CalibrationData, f64)> {
+    use opencv::core::MatTraitConst;
+    let Some(first): Option<&opencv::core::Mat> = images.first().map(|i| i.borrow()) else {
+        return Err(opencv::Error::new(
+            opencv::core::StsBadArg,
+            "There are no images to calibrate with",
+        ));
+    };
+    let size = [first.cols() as u32, first.rows() as u32];
+    let board_corners = crate::aruco::chessboard_corners(&board.make()?.0)?;
+    let mut views = Vec::new();
+    for (corners, ids) in super::detect_charuco_all(images, board, options, cancel)? {
+        let object = ids
+            .iter()
+            .map(|id| {
+                board_corners
+                    .get(id as usize)
+                    .map(|p| [p.x as f64, p.y as f64, p.z as f64])
+            })
+            .collect::<opencv::Result<_>>()?;
+        let found = corners.iter().map(|p| [p.x as f64, p.y as f64]).collect();
+        views.push((object, found));
+    }
+    cancel.check()?;
+    let (t, rms) = fit_telecentric(&views, size).ok_or_else(|| {
+        opencv::Error::new(
+            opencv::core::StsBadArg,
+            "The board was not found well enough in any image to fit the telecentric model",
+        )
+    })?;
+    Ok((super::CalibrationData::Telecentric(t), rms))
+}
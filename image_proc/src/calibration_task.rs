@@ -0,0 +1,200 @@
+//! Running a calibration in the background, so the window stays responsive and the calibration can be cancelled
+
+use image_proc::{
+    calibration::{Backend, CalibrationData, CameraModel, DetectionOptions, Grid, Residual},
+    cancel::CancelToken,
+    frame::Frame,
+};
+
+/// What a finished calibration produced
+pub struct CalibrationOutcome {
+    /// The captures the calibration was made from, handed back to the main window
+    pub images: Vec<Frame>,
+    /// The calibration, its rms reprojection error, the error of each capture and the residual of every corner
+    pub result: opencv::Result<(CalibrationData, f64, Vec<Option<f64>>, Vec<Residual>)>,
+}
+
+/// A calibration running in another thread
+pub struct CalibrationTask {
+    /// The camera being calibrated
+    pub camera: i32,
+    cancel: CancelToken,
+    done: crossbeam::channel::Receiver<CalibrationOutcome>,
+}
+
+impl CalibrationTask {
+    /// Start calibrating from captures of a board with a lens model and a backend, setting cancel stops it
+    pub fn start(
+        camera: i32,
+        images: Vec<Frame>,
+        board: crate::board::BoardParams,
+        model: CameraModel,
+        backend: Backend,
+        options: DetectionOptions,
+        cancel: CancelToken,
+    ) -> Self {
+        let (s, r) = crossbeam::channel::bounded(1);
+        let c = cancel.clone();
+        std::thread::spawn(move || {
+            let result = calibrate(&images, &board, model, backend, options, &c);
+            let _ = s.send(CalibrationOutcome { images, result });
+        });
+        Self {
+            camera,
+            cancel,
+            done: r,
+        }
+    }
+
+    /// Ask the calibration to stop, it finishes with an error soon after
+    pub fn cancel(&self) {
+        self.cancel.cancel();
+    }
+
+    /// True after the calibration was asked to stop
+    pub fn cancelled(&self) -> bool {
+        self.cancel.is_cancelled()
+    }
+
+    /// The outcome when the calibration has finished
+    pub fn poll(&self) -> Option<CalibrationOutcome> {
+        self.done.try_recv().ok()
+    }
+}
+
+/// Calibrate and find the error of each capture and the residual of every corner.
+/// With a two board target the errors and residuals are of the first board.
+/// A chessboard or circle grid is calibrated without markers.
+/// The board is made again from its parameters on every thread that needs it, opencv boards are not shared.
+fn calibrate(
+    images: &[Frame],
+    board: &crate::board::BoardParams,
+    model: CameraModel,
+    backend: Backend,
+    options: DetectionOptions,
+    cancel: &CancelToken,
+) -> opencv::Result<(CalibrationData, f64, Vec<Option<f64>>, Vec<Residual>)> {
+    let (cd, rms, residuals) = if backend == Backend::Native {
+        let grid = board
+            .grid()
+            .filter(|g| matches!(g, Grid::Chessboard { .. }) && model == CameraModel::Pinhole)
+            .ok_or_else(|| {
+                opencv::Error::new(
+                    opencv::core::StsBadArg,
+                    "The pure rust backend calibrates plain chessboards with the pinhole model",
+                )
+            })?;
+        let (cd, rms) = calibrate_native(images, grid, cancel)?;
+        let residuals = image_proc::calibration::grid_residuals(images, &cd, grid);
+        (cd, rms, residuals)
+    } else if let Some(grid) = board.grid() {
+        if model != CameraModel::Pinhole {
+            return Err(opencv::Error::new(
+                opencv::core::StsBadArg,
+                "The fisheye and telecentric models are calibrated with a charuco board",
+            ));
+        }
+        let (cd, rms) = image_proc::calibration::calibrate_grid(images, grid, cancel)?;
+        let residuals = image_proc::calibration::grid_residuals(images, &cd, grid);
+        (cd, rms, residuals)
+    } else {
+        calibrate_charuco(images, board, model, options, cancel)?
+    };
+    let residuals = residuals.unwrap_or_default();
+    let errors = residuals
+        .iter()
+        .map(|r| r.as_deref().map(image_proc::calibration::rms_error))
+        .collect();
+    Ok((
+        cd,
+        rms,
+        errors,
+        residuals.into_iter().flatten().flatten().collect(),
+    ))
+}
+
+/// Calibrate a plain chessboard with the pure rust backend
+#[cfg(feature = "native")]
+fn calibrate_native(
+    images: &[Frame],
+    grid: Grid,
+    cancel: &CancelToken,
+) -> opencv::Result<(CalibrationData, f64)> {
+    let Grid::Chessboard { cols, rows, square } = grid else {
+        return Err(opencv::Error::new(
+            opencv::core::StsBadArg,
+            "The pure rust backend only finds plain chessboards",
+        ));
+    };
+    // The board is black and white, so the order of the color channels does not matter to finding it
+    let images: Vec<_> = images
+        .iter()
+        .filter_map(|f| image_proc::convert::mat_to_color_image(f))
+        .collect();
+    cancel.check()?;
+    image_proc::native::calibrate_chessboard(
+        &images,
+        (cols - 1) as usize,
+        (rows - 1) as usize,
+        square as f64,
+    )
+    .map_err(|e| opencv::Error::new(opencv::core::StsError, e.to_string()))
+}
+
+/// Calibrate a plain chessboard with the pure rust backend, which this build does not have
+#[cfg(not(feature = "native"))]
+fn calibrate_native(
+    _images: &[Frame],
+    _grid: Grid,
+    _cancel: &CancelToken,
+) -> opencv::Result<(CalibrationData, f64)> {
+    Err(opencv::Error::new(
+        opencv::core::StsError,
+        "The program was built without the pure rust backend",
+    ))
+}
+
+/// Calibrate with a charuco board or a two board target with a lens model, with the residuals of the first board
+#[allow(clippy::type_complexity)]
+fn calibrate_charuco(
+    images: &[Frame],
+    board: &crate::board::BoardParams,
+    model: CameraModel,
+    options: DetectionOptions,
+    cancel: &CancelToken,
+) -> opencv::Result<(
+    CalibrationData,
+    f64,
+    opencv::Result<Vec<Option<Vec<Residual>>>>,
+)> {
+    let invalid = || opencv::Error::new(opencv::core::StsBadArg, "The board is not valid");
+    board.make_board().ok_or_else(invalid)?;
+    let charuco = board.charuco();
+    // The fisheye and telecentric calibrations start from a homography or affine map of each view,
+    // which need a flat target
+    if model != CameraModel::Pinhole && board.second_plane {
+        return Err(opencv::Error::new(
+            opencv::core::StsBadArg,
+            "The fisheye and telecentric models are calibrated with a single flat board",
+        ));
+    }
+    let (cd, rms) = if model == CameraModel::Fisheye {
+        image_proc::calibration::calibrate_fisheye(images, &charuco, options, cancel)?
+    } else if model == CameraModel::Telecentric {
+        image_proc::calibration::calibrate_telecentric(images, &charuco, options, cancel)?
+    } else if board.second_plane {
+        board.make_second_board().ok_or_else(invalid)?;
+        image_proc::calibration::calibrate_multi_plane(
+            images,
+            [&charuco, &board.second_charuco()],
+            board.width(),
+            board.plane_angle as f64,
+            options,
+            cancel,
+        )?
+    } else {
+        image_proc::calibration::calibrate_charuco_cancellable(images, &charuco, options, cancel)?
+    };
+    let residuals = image_proc::calibration::residuals(images, &cd, &charuco);
+    Ok((cd, rms, residuals))
+}
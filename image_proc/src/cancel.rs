@@ -0,0 +1,59 @@
+//! Asking long running tasks in other threads to stop early
+
+use std::sync::{
+    Arc,
+    atomic::{AtomicBool, Ordering},
+};
+
+/// A flag shared between whoever started a task and the task, set to ask the task to stop.
+/// The task checks it between steps and gives up with Cancelled when it is set.
+#[derive(Clone, Debug, Default)]
+pub struct CancelToken(Arc<AtomicBool>);
+
+/// The error of a task that stopped because it was cancelled
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Cancelled;
+
+impl std::fmt::Display for Cancelled {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "The operation was cancelled")
+    }
+}
+
+impl std::error::Error for Cancelled {}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl From<Cancelled> for opencv::Error {
+    fn from(value: Cancelled) -> Self {
+        opencv::Error::new(opencv::core::StsError, value.to_string())
+    }
+}
+
+impl CancelToken {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Ask the task to stop
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+
+    /// Err when the task should stop, for use with ? between the steps of a task
+    pub fn check(&self) -> Result<(), Cancelled> {
+        if self.is_cancelled() {
+            Err(Cancelled)
+        } else {
+            Ok(())
+        }
+    }
+
+    /// True when this is the last copy of the token, so the task holding the others has ended
+    pub fn is_finished(&self) -> bool {
+        Arc::strong_count(&self.0) == 1
+    }
+}
@@ -0,0 +1,293 @@
+//! The command line interface, for running the processing without the user interface, like on a build server
+
+use std::path::{Path, PathBuf};
+
+use image_proc::calibration::{CalibrationData, CalibrationDataTrait, CalibrationMetadata};
+use image_proc::integrity::{self, Trust};
+
+use crate::board::{BoardParams, DICTIONARIES};
+use crate::pattern::Pattern;
+use crate::pipeline::Pipeline;
+
+/// Running without a subcommand opens the user interface
+#[derive(clap::Parser)]
+#[command(version, about)]
+pub struct Cli {
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(clap::Subcommand)]
+pub enum Command {
+    /// Remove the lens distortion from every image in a folder
+    Undistort(UndistortArgs),
+    /// Write a calibration pattern at a physical size, as a png image or a pdf page
+    Board(BoardArgs),
+    /// List the cameras with their backends and supported resolutions as json
+    Probe,
+    /// Check the opencv build, camera access and write access to directories, saying how to fix what fails
+    Doctor(DoctorArgs),
+}
+
+impl Command {
+    pub fn run(self) -> Result<(), String> {
+        match self {
+            Command::Undistort(a) => a.run(),
+            Command::Board(a) => a.run(),
+            Command::Probe => {
+                let cameras = crate::probe::probe();
+                println!(
+                    "{}",
+                    serde_json::to_string_pretty(&cameras).map_err(|e| e.to_string())?
+                );
+                Ok(())
+            }
+            Command::Doctor(a) => {
+                let checks = crate::doctor::run(None, &a.dirs);
+                print!("{}", crate::doctor::report(&checks));
+                let failed = checks
+                    .iter()
+                    .filter(|c| c.status == crate::doctor::Status::Failed)
+                    .count();
+                if failed > 0 {
+                    return Err(format!("{} checks failed", failed));
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+#[derive(clap::Args)]
+pub struct UndistortArgs {
+    /// The calibration, a calibration file saved by the program or an opencv yaml or xml file
+    #[arg(long)]
+    calib: PathBuf,
+    /// The folder of images to undistort
+    #[arg(long = "in")]
+    input: PathBuf,
+    /// Where the undistorted images are written
+    #[arg(long)]
+    out: PathBuf,
+    /// The file format of the written images, by its extension
+    #[arg(long, default_value = "png")]
+    format: String,
+    /// A pipeline saved as json, run on the images after undistorting them
+    #[arg(long)]
+    pipeline: Option<PathBuf>,
+    /// A public key in hex whose signatures are trusted, can be given more than once
+    #[arg(long = "trusted-key")]
+    trusted_keys: Vec<String>,
+    /// Refuse calibrations that are not signed by a trusted key
+    #[arg(long)]
+    require_signature: bool,
+}
+
+impl UndistortArgs {
+    /// The signatures accepted when loading the calibration
+    fn trust(&self) -> Result<Trust, String> {
+        let keys = self
+            .trusted_keys
+            .iter()
+            .map(|k| {
+                integrity::verifying_key_from_hex(k)
+                    .ok_or_else(|| format!("Not a valid public key: {}", k))
+            })
+            .collect::<Result<_, _>>()?;
+        Ok(Trust {
+            keys,
+            require_signature: self.require_signature,
+        })
+    }
+
+    /// Load a calibration by the extension of its file, opencv files can not be signed so they are refused when a signature is required
+    fn load_calibration(path: &Path, trust: &Trust) -> Result<CalibrationData, String> {
+        let ext = path
+            .extension()
+            .and_then(|e| e.to_str())
+            .map(str::to_lowercase);
+        match ext.as_deref() {
+            Some("yml" | "yaml" | "xml") if trust.require_signature => Err(format!(
+                "Failed to load {}: opencv files are not signed",
+                path.display()
+            )),
+            Some("yml" | "yaml" | "xml") => CalibrationData::load_yaml(path)
+                .map_err(|e| format!("Failed to load {}: {}", path.display(), e)),
+            _ => CalibrationData::load_verified(path, trust)
+                .map(|(cd, _)| cd)
+                .map_err(|e| format!("Failed to load {}: {}", path.display(), e)),
+        }
+    }
+
+    fn is_image(p: &Path) -> bool {
+        p.extension()
+            .and_then(|e| e.to_str())
+            .is_some_and(|e| ["jpg", "jpeg", "png"].contains(&e.to_lowercase().as_str()))
+    }
+
+    fn run(self) -> Result<(), String> {
+        let cd = Self::load_calibration(&self.calib, &self.trust()?)?;
+        let resolution = CalibrationMetadata::load(&self.calib).and_then(|m| m.resolution);
+        let pipeline: Pipeline = match &self.pipeline {
+            Some(p) => {
+                let c = std::fs::read_to_string(p)
+                    .map_err(|e| format!("Failed to read {}: {}", p.display(), e))?;
+                serde_json::from_str(&c)
+                    .map_err(|e| format!("Failed to read {}: {}", p.display(), e))?
+            }
+            None => Pipeline::default(),
+        };
+        let mut images: Vec<PathBuf> = std::fs::read_dir(&self.input)
+            .map_err(|e| format!("Failed to read {}: {}", self.input.display(), e))?
+            .filter_map(|e| e.ok().map(|e| e.path()))
+            .filter(|p| Self::is_image(p))
+            .collect();
+        images.sort();
+        std::fs::create_dir_all(&self.out)
+            .map_err(|e| format!("Failed to create {}: {}", self.out.display(), e))?;
+
+        let mut failed = 0;
+        for (i, path) in images.iter().enumerate() {
+            let Some(img) = crate::load_image_file(path) else {
+                println!("Failed to load {}", path.display());
+                failed += 1;
+                continue;
+            };
+            let size = [img.width() as u32, img.height() as u32];
+            // A calibration made at another resolution is scaled to the images
+            let img = match resolution {
+                Some(r) if r != size => cd.scaled(r, size).apply_calibration(img),
+                _ => cd.apply_calibration(img),
+            };
+            let img = pipeline.process(img);
+            let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+            let out = self.out.join(format!("{}.{}", stem, self.format));
+            match crate::convert::save_color_image(&out, &img) {
+                Ok(()) => println!("[{}/{}] {}", i + 1, images.len(), out.display()),
+                Err(e) => {
+                    println!("Failed to save {}: {}", out.display(), e);
+                    failed += 1;
+                }
+            }
+        }
+        if failed > 0 {
+            return Err(format!("{} of {} images failed", failed, images.len()));
+        }
+        Ok(())
+    }
+}
+
+#[derive(clap::Args)]
+pub struct DoctorArgs {
+    /// The directories that need to be writable
+    #[arg(long = "dir", default_value = ".")]
+    dirs: Vec<PathBuf>,
+}
+
+/// The kinds of calibration pattern
+#[derive(Clone, Copy, clap::ValueEnum)]
+enum PatternKind {
+    Charuco,
+    Chessboard,
+    Aprilgrid,
+    Circlegrid,
+}
+
+#[derive(clap::Args)]
+pub struct BoardArgs {
+    /// The kind of pattern
+    #[arg(long, value_enum, default_value = "charuco")]
+    pattern: PatternKind,
+    /// The number of squares across, of tags for an aprilgrid or of circles in a row for a circle grid
+    #[arg(long, default_value_t = 10, value_parser = clap::value_parser!(u32).range(2..))]
+    cols: u32,
+    /// The number of squares down, of tags for an aprilgrid or of rows for a circle grid
+    #[arg(long, default_value_t = 7, value_parser = clap::value_parser!(u32).range(2..))]
+    rows: u32,
+    /// The side length of a square, of a tag for an aprilgrid or the row spacing of a circle grid, in millimeters
+    #[arg(long, default_value_t = 30.0)]
+    square: f64,
+    /// The side length of a charuco marker or the diameter of a circle in millimeters, 70% of the square when not given
+    #[arg(long)]
+    marker: Option<f64>,
+    /// The aruco dictionary of a charuco board
+    #[arg(long, default_value = "6x6")]
+    dictionary: String,
+    /// Draw the second board of a two board charuco target, its markers follow those of the first board
+    #[arg(long)]
+    second: bool,
+    /// The gap between the tags of an aprilgrid relative to the tag size
+    #[arg(long, default_value_t = 0.3)]
+    spacing: f64,
+    /// The white margin around the pattern in millimeters, a chessboard needs at least one square of it to be found
+    #[arg(long, default_value_t = 10.0)]
+    margin: f64,
+    /// The printing resolution in dots per inch
+    #[arg(long, default_value_t = 300.0)]
+    dpi: f64,
+    /// Where the pattern is written, a pdf page when the extension is pdf and an image otherwise
+    #[arg(long)]
+    out: PathBuf,
+}
+
+impl BoardArgs {
+    fn pattern(&self) -> Result<Pattern, String> {
+        let square = self.square / 1000.0;
+        Ok(match self.pattern {
+            PatternKind::Charuco => {
+                let dictionary = DICTIONARIES
+                    .iter()
+                    .find(|d| d.1.eq_ignore_ascii_case(&self.dictionary))
+                    .ok_or_else(|| format!("Unknown dictionary {}", self.dictionary))?
+                    .0;
+                Pattern::Charuco(BoardParams {
+                    squares_x: self.cols as i32,
+                    squares_y: self.rows as i32,
+                    square_length: square as f32,
+                    marker_length: (self.marker.map(|m| m / 1000.0).unwrap_or(square * 0.7)) as f32,
+                    dictionary,
+                    second_plane: self.second,
+                    ..Default::default()
+                })
+            }
+            PatternKind::Chessboard => Pattern::Chessboard {
+                cols: self.cols,
+                rows: self.rows,
+                square,
+            },
+            PatternKind::Circlegrid => Pattern::CircleGrid {
+                cols: self.cols,
+                rows: self.rows,
+                spacing: square,
+                diameter: self.marker.map(|m| m / 1000.0).unwrap_or(square * 0.7),
+            },
+            PatternKind::Aprilgrid => Pattern::AprilGrid {
+                tags_x: self.cols,
+                tags_y: self.rows,
+                tag: square,
+                spacing: self.spacing,
+            },
+        })
+    }
+
+    fn run(self) -> Result<(), String> {
+        if self.square <= 0.0 || self.dpi <= 0.0 {
+            return Err("The squares and the resolution must be positive".into());
+        }
+        let pattern = self.pattern()?;
+        let img = pattern.render(self.dpi, self.margin / 1000.0)?;
+        crate::pattern::save(&img, self.dpi, &self.out)
+            .map_err(|e| format!("Failed to save {}: {}", self.out.display(), e))?;
+        let [w, h] = pattern.size();
+        println!(
+            "{} {:.1}x{:.1}mm, {}x{} pixels at {} dpi",
+            self.out.display(),
+            w * 1000.0 + 2.0 * self.margin,
+            h * 1000.0 + 2.0 * self.margin,
+            img.width(),
+            img.height(),
+            self.dpi
+        );
+        Ok(())
+    }
+}
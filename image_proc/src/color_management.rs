@@ -0,0 +1,201 @@
+//! Color management, converting the preview to the profile of the monitor and tagging exported images with a profile
+
+use std::path::PathBuf;
+
+use eframe::egui::{Color32, ColorImage};
+use lcms2::{Intent, PixelFormat, Profile, Transform};
+
+/// Which color profiles are used
+#[derive(Clone, Debug, Default, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
+pub struct ColorSettings {
+    /// Convert the preview from srgb to the profile of the monitor
+    pub manage_display: bool,
+    /// The profile of the monitor, None uses the one the system reports
+    pub monitor_profile: Option<PathBuf>,
+    /// The profile exported images are converted to and tagged with, None is srgb
+    pub export_profile: Option<PathBuf>,
+}
+
+/// Ask the user for an icc profile file
+fn pick_profile() -> Option<PathBuf> {
+    rfd::FileDialog::new()
+        .add_filter("ICC profile", &["icc", "icm"])
+        .pick_file()
+}
+
+/// Show the name of a profile file, with buttons to choose another or go back to the default
+fn profile_row(
+    ui: &mut eframe::egui::Ui,
+    label: String,
+    profile: &mut Option<PathBuf>,
+    default: String,
+) {
+    ui.horizontal(|ui| {
+        ui.label(label);
+        let name = profile
+            .as_ref()
+            .map(|f| f.display().to_string())
+            .unwrap_or(default);
+        ui.label(name);
+        if ui.button(tr!("settings.browse")).clicked() {
+            if let Some(f) = pick_profile() {
+                *profile = Some(f);
+            }
+        }
+        if profile.is_some() && ui.button(tr!("color.reset")).clicked() {
+            *profile = None;
+        }
+    });
+}
+
+impl ColorSettings {
+    /// Show the color settings for editing
+    pub fn show(&mut self, ui: &mut eframe::egui::Ui) {
+        ui.checkbox(&mut self.manage_display, tr!("color.manage_display"));
+        ui.add_enabled_ui(self.manage_display, |ui| {
+            profile_row(
+                ui,
+                tr!("color.monitor_profile"),
+                &mut self.monitor_profile,
+                tr!("color.system_profile"),
+            );
+        });
+        profile_row(
+            ui,
+            tr!("color.export_profile"),
+            &mut self.export_profile,
+            "sRGB".to_string(),
+        );
+    }
+}
+
+/// The icc profile of the monitor as the system reports it.
+/// On X11 this is the _ICC_PROFILE property of the root window, which is set by colord and most color managers.
+fn system_monitor_profile() -> Option<Vec<u8>> {
+    let out = std::process::Command::new("xprop")
+        .args(["-root", "_ICC_PROFILE"])
+        .output()
+        .ok()?;
+    let text = String::from_utf8(out.stdout).ok()?;
+    let (_, values) = text.split_once('=')?;
+    values
+        .split(',')
+        .map(|v| v.trim().parse::<u8>().ok())
+        .collect::<Option<Vec<u8>>>()
+        .filter(|v| !v.is_empty())
+}
+
+/// Load a profile file, None gives the fallback profile
+fn load_profile(
+    path: Option<&PathBuf>,
+    fallback: impl FnOnce() -> Option<Profile>,
+) -> Result<Profile, String> {
+    match path {
+        Some(p) => Profile::new_file(p).map_err(|e| format!("{}: {}", p.display(), e)),
+        None => fallback().ok_or_else(|| tr!("color.no_system_profile")),
+    }
+}
+
+/// The transforms built from the color settings
+#[derive(Default)]
+pub struct ColorManager {
+    /// The settings the transforms were built from
+    settings: Option<ColorSettings>,
+    display: Option<Transform<[u8; 4], [u8; 4]>>,
+    export: Option<Transform<[u8; 4], [u8; 4]>>,
+    /// The profile exported images are tagged with
+    export_icc: Vec<u8>,
+}
+
+impl ColorManager {
+    /// Rebuild the transforms when the settings changed, returns the problem with the new settings if there is one
+    pub fn update(&mut self, settings: &ColorSettings) -> Option<String> {
+        if self.settings.as_ref() == Some(settings) {
+            return None;
+        }
+        self.settings = Some(settings.clone());
+        let mut error = None;
+        self.display = None;
+        self.export = None;
+        self.export_icc = Vec::new();
+        let srgb = Profile::new_srgb();
+        if settings.manage_display {
+            let monitor = load_profile(settings.monitor_profile.as_ref(), || {
+                Profile::new_icc(&system_monitor_profile()?).ok()
+            });
+            match monitor.and_then(|m| {
+                Transform::new(
+                    &srgb,
+                    PixelFormat::RGBA_8,
+                    &m,
+                    PixelFormat::RGBA_8,
+                    Intent::Perceptual,
+                )
+                .map_err(|e| e.to_string())
+            }) {
+                Ok(t) => self.display = Some(t),
+                Err(e) => error = Some(e),
+            }
+        }
+        let export = match &settings.export_profile {
+            None => srgb.icc().map_err(|e| e.to_string()).map(|icc| (icc, None)),
+            Some(_) => load_profile(settings.export_profile.as_ref(), || None).and_then(|p| {
+                let icc = p.icc().map_err(|e| e.to_string())?;
+                let t = Transform::new(
+                    &srgb,
+                    PixelFormat::RGBA_8,
+                    &p,
+                    PixelFormat::RGBA_8,
+                    Intent::Perceptual,
+                )
+                .map_err(|e| e.to_string())?;
+                Ok((icc, Some(t)))
+            }),
+        };
+        match export {
+            Ok((icc, t)) => {
+                self.export_icc = icc;
+                self.export = t;
+            }
+            Err(e) => error = Some(e),
+        }
+        error
+    }
+
+    /// Convert an srgb image with a transform
+    fn convert(t: &Transform<[u8; 4], [u8; 4]>, img: ColorImage) -> ColorImage {
+        let src: Vec<[u8; 4]> = img.pixels.iter().map(|p| p.to_array()).collect();
+        let mut dst = vec![[0u8; 4]; src.len()];
+        t.transform_pixels(&src, &mut dst);
+        ColorImage {
+            size: img.size,
+            pixels: dst
+                .iter()
+                .map(|p| Color32::from_rgba_unmultiplied(p[0], p[1], p[2], p[3]))
+                .collect(),
+        }
+    }
+
+    /// Convert an srgb image for showing on the monitor
+    pub fn display(&self, img: ColorImage) -> ColorImage {
+        match &self.display {
+            Some(t) => Self::convert(t, img),
+            None => img,
+        }
+    }
+
+    /// Save an srgb image, converted to the export profile and tagged with it
+    pub fn save(&self, path: &std::path::Path, img: &ColorImage) -> image::ImageResult<()> {
+        let converted;
+        let img = match &self.export {
+            Some(t) => {
+                converted = Self::convert(t, img.clone());
+                &converted
+            }
+            None => img,
+        };
+        let icc = (!self.export_icc.is_empty()).then(|| self.export_icc.clone());
+        crate::convert::save_color_image_with_profile(path, img, icc)
+    }
+}
@@ -0,0 +1,156 @@
+//! False color view modes for inspecting the luminance of an image
+
+use eframe::egui::ColorImage;
+use opencv::core::{MatTraitConst, MatTraitConstManual, MatTraitManual};
+
+/// The opencv colormaps that can be used for false color display
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Colormap {
+    Autumn,
+    Bone,
+    Jet,
+    Winter,
+    Rainbow,
+    Ocean,
+    Summer,
+    Spring,
+    Cool,
+    Hsv,
+    Pink,
+    Hot,
+    Parula,
+    Magma,
+    Inferno,
+    Plasma,
+    Viridis,
+    Cividis,
+    Twilight,
+    Turbo,
+}
+
+impl Colormap {
+    /// All of the colormaps, in the order they are presented to the user
+    pub const ALL: [Colormap; 20] = [
+        Colormap::Jet,
+        Colormap::Turbo,
+        Colormap::Viridis,
+        Colormap::Inferno,
+        Colormap::Magma,
+        Colormap::Plasma,
+        Colormap::Cividis,
+        Colormap::Parula,
+        Colormap::Hot,
+        Colormap::Bone,
+        Colormap::Rainbow,
+        Colormap::Hsv,
+        Colormap::Ocean,
+        Colormap::Autumn,
+        Colormap::Winter,
+        Colormap::Summer,
+        Colormap::Spring,
+        Colormap::Cool,
+        Colormap::Pink,
+        Colormap::Twilight,
+    ];
+
+    /// The opencv constant for this colormap
+    fn cv(&self) -> i32 {
+        match self {
+            Colormap::Autumn => opencv::imgproc::COLORMAP_AUTUMN,
+            Colormap::Bone => opencv::imgproc::COLORMAP_BONE,
+            Colormap::Jet => opencv::imgproc::COLORMAP_JET,
+            Colormap::Winter => opencv::imgproc::COLORMAP_WINTER,
+            Colormap::Rainbow => opencv::imgproc::COLORMAP_RAINBOW,
+            Colormap::Ocean => opencv::imgproc::COLORMAP_OCEAN,
+            Colormap::Summer => opencv::imgproc::COLORMAP_SUMMER,
+            Colormap::Spring => opencv::imgproc::COLORMAP_SPRING,
+            Colormap::Cool => opencv::imgproc::COLORMAP_COOL,
+            Colormap::Hsv => opencv::imgproc::COLORMAP_HSV,
+            Colormap::Pink => opencv::imgproc::COLORMAP_PINK,
+            Colormap::Hot => opencv::imgproc::COLORMAP_HOT,
+            Colormap::Parula => opencv::imgproc::COLORMAP_PARULA,
+            Colormap::Magma => opencv::imgproc::COLORMAP_MAGMA,
+            Colormap::Inferno => opencv::imgproc::COLORMAP_INFERNO,
+            Colormap::Plasma => opencv::imgproc::COLORMAP_PLASMA,
+            Colormap::Viridis => opencv::imgproc::COLORMAP_VIRIDIS,
+            Colormap::Cividis => opencv::imgproc::COLORMAP_CIVIDIS,
+            Colormap::Twilight => opencv::imgproc::COLORMAP_TWILIGHT,
+            Colormap::Turbo => opencv::imgproc::COLORMAP_TURBO,
+        }
+    }
+
+    /// Map the luminance of the image through the colormap
+    pub fn apply(&self, img: &ColorImage) -> Option<ColorImage> {
+        self.apply_gray(img.size, &luminance(img))
+    }
+
+    /// Map a single channel image through the colormap
+    pub fn apply_gray(&self, size: [usize; 2], gray: &[u8]) -> Option<ColorImage> {
+        let mut src = opencv::core::Mat::new_rows_cols_with_default(
+            size[1] as i32,
+            size[0] as i32,
+            opencv::core::CV_8UC1,
+            Default::default(),
+        )
+        .ok()?;
+        src.data_bytes_mut().ok()?.copy_from_slice(gray);
+        let mut mapped = opencv::core::Mat::default();
+        opencv::imgproc::apply_color_map(&src, &mut mapped, self.cv()).ok()?;
+        let mut rgb = opencv::core::Mat::default();
+        opencv::imgproc::cvt_color_def(&mapped, &mut rgb, opencv::imgproc::COLOR_BGR2RGB).ok()?;
+        let dims = [rgb.cols() as usize, rgb.rows() as usize];
+        Some(ColorImage::from_rgb(dims, rgb.data_bytes().ok()?))
+    }
+}
+
+/// How an image is presented to the user
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ViewMode {
+    /// The image as is
+    Normal,
+    /// The luminance of the image as grayscale
+    Luminance,
+    /// The luminance of the image mapped through a colormap
+    FalseColor(Colormap),
+    /// How much processing changed each pixel, as a heatmap
+    Difference,
+}
+
+impl ViewMode {
+    /// Convert an image for display according to the view mode
+    pub fn apply(&self, img: ColorImage) -> ColorImage {
+        match self {
+            ViewMode::Normal => img,
+            ViewMode::Luminance => ColorImage::from_gray(img.size, &luminance(&img)),
+            ViewMode::FalseColor(c) => c.apply(&img).unwrap_or(img),
+            // This needs the original image as well, see apply_with_original
+            ViewMode::Difference => img,
+        }
+    }
+
+    /// Convert an image for display, original is the image before undistortion and processing.
+    /// The difference heatmap is amplified by gain.
+    pub fn apply_with_original(
+        &self,
+        img: ColorImage,
+        original: &ColorImage,
+        gain: f32,
+    ) -> ColorImage {
+        match self {
+            ViewMode::Difference => crate::compare::heatmap(original, &img, gain).unwrap_or(img),
+            _ => self.apply(img),
+        }
+    }
+
+    /// The name of the view mode as shown in the ui
+    pub fn name(&self) -> String {
+        match self {
+            ViewMode::Normal => tr!("colormap.normal"),
+            ViewMode::Luminance => tr!("colormap.luminance"),
+            ViewMode::FalseColor(c) => tr!("colormap.false_color", map = format!("{:?}", c)),
+            ViewMode::Difference => tr!("colormap.difference"),
+        }
+    }
+}
+
+pub use image_proc::pipeline::luminance;
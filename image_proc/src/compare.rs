@@ -0,0 +1,360 @@
+//! Quantitative comparison of two images
+
+use eframe::egui::ColorImage;
+use opencv::core::{MatTraitConstManual, MatTraitManual};
+
+use crate::colormap::{Colormap, luminance};
+
+/// The results of comparing two images
+pub struct ComparisonResult {
+    /// Peak signal to noise ratio in decibels
+    pub psnr: f64,
+    /// Mean structural similarity index of the luminance
+    pub ssim: f64,
+    /// The largest difference of any channel of any pixel
+    pub max_difference: u8,
+    /// The per pixel difference, amplified and mapped through a colormap
+    pub heatmap: ColorImage,
+}
+
+/// Calculate the peak signal to noise ratio between two images of the same size
+pub fn psnr(a: &ColorImage, b: &ColorImage) -> f64 {
+    let sum: f64 = a
+        .pixels
+        .iter()
+        .zip(&b.pixels)
+        .map(|(p, q)| {
+            let dr = p.r() as f64 - q.r() as f64;
+            let dg = p.g() as f64 - q.g() as f64;
+            let db = p.b() as f64 - q.b() as f64;
+            dr * dr + dg * dg + db * db
+        })
+        .sum();
+    let mse = sum / (a.pixels.len() * 3) as f64;
+    if mse == 0.0 {
+        f64::INFINITY
+    } else {
+        10.0 * (255.0 * 255.0 / mse).log10()
+    }
+}
+
+/// Gaussian blur a single channel float image with the standard ssim window
+fn blur(data: &[f32], size: [usize; 2]) -> Option<Vec<f32>> {
+    let mut m = opencv::core::Mat::new_rows_cols_with_default(
+        size[1] as i32,
+        size[0] as i32,
+        opencv::core::CV_32FC1,
+        Default::default(),
+    )
+    .ok()?;
+    m.data_typed_mut::<f32>().ok()?.copy_from_slice(data);
+    let mut out = opencv::core::Mat::default();
+    opencv::imgproc::gaussian_blur_def(&m, &mut out, opencv::core::Size::new(11, 11), 1.5).ok()?;
+    Some(out.data_typed::<f32>().ok()?.to_vec())
+}
+
+/// Calculate the mean structural similarity index of the luminance of two images of the same size
+pub fn ssim(a: &ColorImage, b: &ColorImage) -> Option<f64> {
+    const C1: f32 = (0.01 * 255.0) * (0.01 * 255.0);
+    const C2: f32 = (0.03 * 255.0) * (0.03 * 255.0);
+    let x: Vec<f32> = luminance(a).into_iter().map(f32::from).collect();
+    let y: Vec<f32> = luminance(b).into_iter().map(f32::from).collect();
+    let xx: Vec<f32> = x.iter().map(|v| v * v).collect();
+    let yy: Vec<f32> = y.iter().map(|v| v * v).collect();
+    let xy: Vec<f32> = x.iter().zip(&y).map(|(v, w)| v * w).collect();
+    let mx = blur(&x, a.size)?;
+    let my = blur(&y, a.size)?;
+    let sxx = blur(&xx, a.size)?;
+    let syy = blur(&yy, a.size)?;
+    let sxy = blur(&xy, a.size)?;
+    let total: f64 = (0..x.len())
+        .map(|i| {
+            let vx = sxx[i] - mx[i] * mx[i];
+            let vy = syy[i] - my[i] * my[i];
+            let cxy = sxy[i] - mx[i] * my[i];
+            let n = (2.0 * mx[i] * my[i] + C1) * (2.0 * cxy + C2);
+            let d = (mx[i] * mx[i] + my[i] * my[i] + C1) * (vx + vy + C2);
+            (n / d) as f64
+        })
+        .sum();
+    Some(total / x.len() as f64)
+}
+
+/// The largest difference of any channel of each pixel of two images of the same size
+pub fn difference(a: &ColorImage, b: &ColorImage) -> Vec<u8> {
+    a.pixels
+        .iter()
+        .zip(&b.pixels)
+        .map(|(p, q)| {
+            p.r()
+                .abs_diff(q.r())
+                .max(p.g().abs_diff(q.g()))
+                .max(p.b().abs_diff(q.b()))
+        })
+        .collect()
+}
+
+/// The difference between two images of the same size, amplified by gain and mapped through a colormap
+pub fn heatmap(a: &ColorImage, b: &ColorImage, gain: f32) -> Option<ColorImage> {
+    if a.size != b.size {
+        return None;
+    }
+    let amplified: Vec<u8> = difference(a, b)
+        .iter()
+        .map(|d| (*d as f32 * gain).min(255.0) as u8)
+        .collect();
+    Colormap::Jet.apply_gray(a.size, &amplified)
+}
+
+/// Compare two images, amplifying the difference heatmap by gain
+pub fn compare(a: &ColorImage, b: &ColorImage, gain: f32) -> Result<ComparisonResult, String> {
+    if a.size != b.size {
+        return Err(tr!(
+            "compare.size_differs",
+            a = format!("{}x{}", a.width(), a.height()),
+            b = format!("{}x{}", b.width(), b.height())
+        ));
+    }
+    let max_difference = difference(a, b).iter().copied().max().unwrap_or(0);
+    let heatmap = heatmap(a, b, gain).ok_or_else(|| tr!("compare.heatmap_failed"))?;
+    let ssim = ssim(a, b).ok_or_else(|| tr!("compare.ssim_failed"))?;
+    Ok(ComparisonResult {
+        psnr: psnr(a, b),
+        ssim,
+        max_difference,
+        heatmap,
+    })
+}
+
+/// What the flicker view switches between
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum FlickerSource {
+    /// Images A and B of the comparison
+    Images,
+    /// The current frame before and after the processing pipeline
+    RawProcessed,
+}
+
+/// Shows two images in the same place, switching between them on a timer or a key press
+struct FlickerView {
+    enabled: bool,
+    source: FlickerSource,
+    /// Switch automatically instead of only with the space key
+    timed: bool,
+    /// The time each image is shown for when switching automatically, in seconds
+    interval: f32,
+    /// True while the second image is shown
+    second: bool,
+    last_switch: std::time::Instant,
+    /// The textures of images A and B, cleared when either changes
+    textures: Option<[eframe::egui::TextureHandle; 2]>,
+}
+
+impl Default for FlickerView {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            source: FlickerSource::Images,
+            timed: true,
+            interval: 0.5,
+            second: false,
+            last_switch: std::time::Instant::now(),
+            textures: None,
+        }
+    }
+}
+
+impl FlickerView {
+    /// Show the flicker controls and whichever image is currently up
+    fn show(
+        &mut self,
+        ui: &mut eframe::egui::Ui,
+        images: [Option<&ColorImage>; 2],
+        frames: [Option<&ColorImage>; 2],
+    ) {
+        ui.checkbox(&mut self.enabled, tr!("compare.flicker"));
+        if !self.enabled {
+            return;
+        }
+        ui.horizontal(|ui| {
+            ui.radio_value(
+                &mut self.source,
+                FlickerSource::Images,
+                tr!("compare.flicker_images"),
+            );
+            ui.radio_value(
+                &mut self.source,
+                FlickerSource::RawProcessed,
+                tr!("compare.flicker_frames"),
+            );
+        });
+        ui.horizontal(|ui| {
+            ui.checkbox(&mut self.timed, tr!("compare.switch_every"));
+            ui.add_enabled(
+                self.timed,
+                eframe::egui::DragValue::new(&mut self.interval)
+                    .range(0.05..=5.0)
+                    .speed(0.01)
+                    .suffix(" s"),
+            );
+            ui.label(tr!("compare.or_space"));
+        });
+        let now = std::time::Instant::now();
+        let pressed = ui
+            .input_mut(|i| i.consume_key(eframe::egui::Modifiers::NONE, eframe::egui::Key::Space));
+        let due = self.timed && now.duration_since(self.last_switch).as_secs_f32() >= self.interval;
+        if pressed || due {
+            self.second = !self.second;
+            self.last_switch = now;
+        }
+        if self.timed {
+            ui.ctx()
+                .request_repaint_after(std::time::Duration::from_secs_f32(self.interval));
+        }
+        let i = self.second as usize;
+        let texture = match self.source {
+            FlickerSource::Images => {
+                let [Some(a), Some(b)] = images else {
+                    ui.label(tr!("compare.choose_images"));
+                    return;
+                };
+                let t = self.textures.get_or_insert_with(|| {
+                    [a, b].map(|img| {
+                        ui.ctx().load_texture(
+                            "flicker_image",
+                            img.clone(),
+                            eframe::egui::TextureOptions::LINEAR,
+                        )
+                    })
+                });
+                t[i].clone()
+            }
+            FlickerSource::RawProcessed => {
+                let Some(img) = frames[i] else {
+                    ui.label(tr!("compare.no_frame"));
+                    return;
+                };
+                ui.ctx().load_texture(
+                    "flicker_frame",
+                    img.clone(),
+                    eframe::egui::TextureOptions::LINEAR,
+                )
+            }
+        };
+        let names = match self.source {
+            FlickerSource::Images => ["compare.a", "compare.b"],
+            FlickerSource::RawProcessed => ["compare.raw", "compare.processed"],
+        };
+        ui.strong(tr!(names[i]));
+        // Both images are shown in the same rectangle, the size of the first, so they line up even if the sizes differ
+        let first = match self.source {
+            FlickerSource::Images => images[0],
+            FlickerSource::RawProcessed => frames[0],
+        };
+        let aspect = first
+            .map(|f| f.height() as f32 / f.width().max(1) as f32)
+            .unwrap_or(1.0);
+        let w = ui.available_width();
+        let st = eframe::egui::load::SizedTexture {
+            id: texture.id(),
+            size: eframe::egui::vec2(w, w * aspect),
+        };
+        ui.add(eframe::egui::Image::from_texture(st));
+    }
+}
+
+/// The state of the image comparison panel
+pub struct ImageComparison {
+    a: Option<ColorImage>,
+    b: Option<ColorImage>,
+    gain: f32,
+    result: Option<Result<ComparisonResult, String>>,
+    heatmap: Option<eframe::egui::TextureHandle>,
+    flicker: FlickerView,
+}
+
+impl Default for ImageComparison {
+    fn default() -> Self {
+        Self {
+            a: None,
+            b: None,
+            gain: 4.0,
+            result: None,
+            heatmap: None,
+            flicker: Default::default(),
+        }
+    }
+}
+
+impl ImageComparison {
+    /// Show the panel, current is the image currently being displayed by the application and raw is the same image before processing
+    pub fn show(
+        &mut self,
+        ui: &mut eframe::egui::Ui,
+        current: Option<&ColorImage>,
+        raw: Option<&ColorImage>,
+    ) {
+        eframe::egui::Grid::new("comparison_inputs").show(ui, |ui| {
+            for (name, slot) in [
+                (tr!("compare.image_a"), &mut self.a),
+                (tr!("compare.image_b"), &mut self.b),
+            ] {
+                ui.label(name);
+                if let Some(img) = slot {
+                    ui.label(format!("{}x{}", img.width(), img.height()));
+                } else {
+                    ui.label(tr!("compare.none"));
+                }
+                if ui.button(tr!("compare.open")).clicked() {
+                    if let Some(img) = crate::pick_image_file() {
+                        slot.replace(img);
+                        self.flicker.textures = None;
+                    }
+                }
+                if ui.button(tr!("compare.use_current")).clicked() {
+                    if let Some(img) = current {
+                        slot.replace(img.clone());
+                        self.flicker.textures = None;
+                    }
+                }
+                ui.end_row();
+            }
+        });
+        ui.add(eframe::egui::Slider::new(&mut self.gain, 1.0..=64.0).text(tr!("compare.gain")));
+        if let (Some(a), Some(b)) = (&self.a, &self.b) {
+            if ui.button(tr!("compare.compare")).clicked() {
+                let r = compare(a, b, self.gain);
+                self.heatmap = r.as_ref().ok().map(|r| {
+                    ui.ctx().load_texture(
+                        "comparison_heatmap",
+                        r.heatmap.clone(),
+                        eframe::egui::TextureOptions::LINEAR,
+                    )
+                });
+                self.result = Some(r);
+            }
+        }
+        match &self.result {
+            Some(Ok(r)) => {
+                ui.label(tr!("compare.psnr", psnr = format!("{:.2}", r.psnr)));
+                ui.label(tr!("compare.ssim", ssim = format!("{:.4}", r.ssim)));
+                ui.label(tr!("compare.max_difference", difference = r.max_difference));
+            }
+            Some(Err(e)) => {
+                ui.colored_label(eframe::egui::Color32::RED, e);
+            }
+            None => {}
+        }
+        if let Some(th) = &self.heatmap {
+            let z = ui.available_width() / th.size_vec2().x;
+            let st = eframe::egui::load::SizedTexture {
+                id: th.id(),
+                size: th.size_vec2() * z,
+            };
+            ui.add(eframe::egui::Image::from_texture(st));
+        }
+        ui.separator();
+        self.flicker
+            .show(ui, [self.a.as_ref(), self.b.as_ref()], [raw, current]);
+    }
+}
@@ -0,0 +1,123 @@
+//! Conversions between egui images and opencv matrices
+
+use eframe::egui::ColorImage;
+use opencv::core::{MatTraitConst, MatTraitConstManual, MatTraitManual};
+
+/// Copy raw pixel data into a new opencv matrix of the given type, the data must be exactly the size of the matrix
+pub fn bytes_to_mat(
+    width: usize,
+    height: usize,
+    typ: i32,
+    data: &[u8],
+) -> Option<opencv::core::Mat> {
+    let mut m = opencv::core::Mat::new_rows_cols_with_default(
+        height as i32,
+        width as i32,
+        typ,
+        Default::default(),
+    )
+    .ok()?;
+    let bytes = m.data_bytes_mut().ok()?;
+    if bytes.len() != data.len() {
+        return None;
+    }
+    bytes.copy_from_slice(data);
+    Some(m)
+}
+
+/// Convert an egui image into a 3 channel opencv matrix, in rgb order
+pub fn color_image_to_mat(img: &ColorImage) -> Option<opencv::core::Mat> {
+    let data: Vec<u8> = img
+        .pixels
+        .iter()
+        .flat_map(|p| [p.r(), p.g(), p.b()])
+        .collect();
+    bytes_to_mat(img.width(), img.height(), opencv::core::CV_8UC3, &data)
+}
+
+/// Convert an egui image into a 3 channel opencv matrix, in the bgr order of the matrices from cameras
+pub fn color_image_to_bgr_mat(img: &ColorImage) -> Option<opencv::core::Mat> {
+    let data: Vec<u8> = img
+        .pixels
+        .iter()
+        .flat_map(|p| [p.b(), p.g(), p.r()])
+        .collect();
+    bytes_to_mat(img.width(), img.height(), opencv::core::CV_8UC3, &data)
+}
+
+/// Convert a 1 or 3 channel 8 bit opencv matrix into an egui image, 3 channel matrices must be in rgb order
+pub fn mat_to_color_image(mat: &opencv::core::Mat) -> Option<ColorImage> {
+    if !mat.is_continuous() {
+        return mat_to_color_image(&mat.try_clone().ok()?);
+    }
+    let dims = [mat.cols() as usize, mat.rows() as usize];
+    let data = mat.data_bytes().ok()?;
+    match mat.channels() {
+        1 => Some(ColorImage::from_gray(dims, data)),
+        3 => Some(ColorImage::from_rgb(dims, data)),
+        _ => None,
+    }
+}
+
+/// Convert a 1 or 3 channel 8 bit opencv matrix in the bgr order of the matrices from cameras into an egui image
+pub fn bgr_mat_to_color_image(mat: &opencv::core::Mat) -> Option<ColorImage> {
+    if mat.channels() != 3 {
+        return mat_to_color_image(mat);
+    }
+    let mut rgb = opencv::core::Mat::default();
+    opencv::imgproc::cvt_color_def(mat, &mut rgb, opencv::imgproc::COLOR_BGR2RGB).ok()?;
+    mat_to_color_image(&rgb)
+}
+
+/// Save an egui image to a file, the format is determined by the extension
+pub fn save_color_image(path: &std::path::Path, img: &ColorImage) -> image::ImageResult<()> {
+    save_color_image_with_profile(path, img, None)
+}
+
+/// Save an egui image to a file with an embedded icc profile, the format is determined by the extension.
+/// Only png and jpeg files carry the profile, other formats are saved without it.
+pub fn save_color_image_with_profile(
+    path: &std::path::Path,
+    img: &ColorImage,
+    icc: Option<Vec<u8>>,
+) -> image::ImageResult<()> {
+    fn encode(
+        mut encoder: impl image::ImageEncoder,
+        img: &ColorImage,
+        data: &[u8],
+        icc: Vec<u8>,
+    ) -> image::ImageResult<()> {
+        encoder
+            .set_icc_profile(icc)
+            .map_err(image::ImageError::Unsupported)?;
+        encoder.write_image(
+            data,
+            img.width() as u32,
+            img.height() as u32,
+            image::ExtendedColorType::Rgb8,
+        )
+    }
+    let data: Vec<u8> = img
+        .pixels
+        .iter()
+        .flat_map(|p| [p.r(), p.g(), p.b()])
+        .collect();
+    let format = image::ImageFormat::from_path(path)?;
+    match (icc, format) {
+        (Some(icc), image::ImageFormat::Png) => {
+            let f = std::io::BufWriter::new(std::fs::File::create(path)?);
+            encode(image::codecs::png::PngEncoder::new(f), img, &data, icc)
+        }
+        (Some(icc), image::ImageFormat::Jpeg) => {
+            let f = std::io::BufWriter::new(std::fs::File::create(path)?);
+            encode(image::codecs::jpeg::JpegEncoder::new(f), img, &data, icc)
+        }
+        _ => image::save_buffer(
+            path,
+            &data,
+            img.width() as u32,
+            img.height() as u32,
+            image::ExtendedColorType::Rgb8,
+        ),
+    }
+}
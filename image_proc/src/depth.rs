@@ -0,0 +1,43 @@
+//! Depth images, from depth cameras, for measuring distances in the scene
+
+use std::sync::{Arc, Mutex};
+
+/// A depth image, shared between the camera thread producing it and the user interface
+pub type SharedDepth = Arc<Mutex<Option<DepthImage>>>;
+
+/// The distance to the scene for every pixel
+#[derive(Clone, Debug)]
+pub struct DepthImage {
+    /// The width and height in pixels
+    pub size: [usize; 2],
+    /// The distance of every pixel in millimeters, row by row, 0 means no measurement
+    pub millimeters: Vec<u16>,
+}
+
+impl DepthImage {
+    /// The distance at a pixel, None when there is no measurement there
+    pub fn at(&self, x: usize, y: usize) -> Option<u16> {
+        if x >= self.size[0] || y >= self.size[1] {
+            return None;
+        }
+        Some(self.millimeters[y * self.size[0] + x]).filter(|d| *d != 0)
+    }
+
+    /// Convert to a bgr image for display, near is bright and everything beyond range is black.
+    /// The result is grayscale, so the colormaps of the view modes can be applied to it.
+    pub fn to_mat(&self, range: u16) -> Option<opencv::core::Mat> {
+        let data: Vec<u8> = self
+            .millimeters
+            .iter()
+            .flat_map(|d| {
+                let v = if *d == 0 || *d >= range {
+                    0
+                } else {
+                    255 - (*d as u32 * 255 / range as u32) as u8
+                };
+                [v, v, v]
+            })
+            .collect();
+        crate::convert::bytes_to_mat(self.size[0], self.size[1], opencv::core::CV_8UC3, &data)
+    }
+}
@@ -0,0 +1,47 @@
+//! Detected calibration corners drawn over the preview as shapes, so showing them does not change the image or its texture
+
+use eframe::egui::{Color32, Pos2, Rect};
+
+/// The corners found in an image, drawn until the preview shows an image of another size
+#[derive(Default)]
+pub struct DetectionOverlay {
+    /// The corners in pixels of the image they were found in
+    corners: Vec<[f32; 2]>,
+    /// The size of the image the corners were found in
+    size: [usize; 2],
+}
+
+impl DetectionOverlay {
+    /// Show corners found in an image of a size
+    pub fn set(&mut self, size: [usize; 2], corners: Vec<[f32; 2]>) {
+        self.size = size;
+        self.corners = corners;
+    }
+
+    /// Draw the corners over the preview.
+    /// rect is where the image is shown and size is the size of the shown image in pixels.
+    pub fn paint(&mut self, ui: &eframe::egui::Ui, rect: Rect, size: [usize; 2]) {
+        if self.corners.is_empty() {
+            return;
+        }
+        if size != self.size {
+            self.corners.clear();
+            return;
+        }
+        let painter = ui.painter_at(rect);
+        let ppp = ui.ctx().pixels_per_point();
+        let to_screen = |[x, y]: [f32; 2]| {
+            crate::dpi::snap_pos(
+                Pos2::new(
+                    rect.min.x + (x + 0.5) / size[0] as f32 * rect.width(),
+                    rect.min.y + (y + 0.5) / size[1] as f32 * rect.height(),
+                ),
+                ppp,
+            )
+        };
+        let stroke = crate::dpi::stroke(1.5, Color32::RED, ppp);
+        for c in &self.corners {
+            painter.circle_stroke(to_screen(*c), 4.0, stroke);
+        }
+    }
+}
@@ -0,0 +1,198 @@
+//! Checking a calibration with two markers a tape measured distance apart.
+//! The pose of each marker is found with the calibration, the distance between them should match the measured one.
+
+use image_proc::calibration::{CalibrationData, CalibrationDataTrait, DistanceCheck};
+use opencv::core::{Mat, Point2f, Point3f, Vector};
+
+use crate::board::DICTIONARIES;
+
+/// The settings of the distance check and its last result
+pub struct DistanceTool {
+    /// The dictionary of the two markers
+    dictionary: i32,
+    /// The side length of the printed markers, in millimeters
+    marker_length: f64,
+    /// The distance between the centers of the markers measured by hand, in millimeters
+    measured: f64,
+    last: Option<Result<DistanceCheck, String>>,
+}
+
+impl Default for DistanceTool {
+    fn default() -> Self {
+        Self {
+            dictionary: image_proc::aruco::DICT_6X6_1000,
+            marker_length: 100.0,
+            measured: 1000.0,
+            last: None,
+        }
+    }
+}
+
+/// The center of a square marker in camera coordinates, in the units of the side length
+fn marker_center(
+    corners: &Vector<Point2f>,
+    side: f64,
+    cm: &Mat,
+    dc: &Mat,
+) -> opencv::Result<[f64; 3]> {
+    let h = (side / 2.0) as f32;
+    // The order of the corners of a marker found by opencv, starting at the top left and going clockwise
+    let object: Vector<Point3f> = [(-h, h), (h, h), (h, -h), (-h, -h)]
+        .into_iter()
+        .map(|(x, y)| Point3f::new(x, y, 0.0))
+        .collect();
+    let mut rvec = Vector::<f64>::new();
+    let mut tvec = Vector::<f64>::new();
+    opencv::calib3d::solve_pnp(
+        &object,
+        corners,
+        cm,
+        dc,
+        &mut rvec,
+        &mut tvec,
+        false,
+        opencv::calib3d::SOLVEPNP_IPPE_SQUARE,
+    )?;
+    Ok([tvec.get(0)?, tvec.get(1)?, tvec.get(2)?])
+}
+
+/// Find the distance between the centers of the only two markers in an image
+pub fn measure(
+    img: &Mat,
+    cd: &CalibrationData,
+    dictionary: i32,
+    marker_length: f64,
+) -> Result<([i32; 2], f64), String> {
+    if matches!(cd, CalibrationData::Telecentric(_)) {
+        return Err(tr!("distance.telecentric"));
+    }
+    let d = image_proc::aruco::dictionary(dictionary).map_err(|e| e.to_string())?;
+    let gray = image_proc::calibration::to_gray(img).map_err(|e| e.to_string())?;
+    let (markers, ids, _) =
+        image_proc::aruco::detect_markers(&gray, &d).map_err(|e| e.to_string())?;
+    if ids.len() != 2 {
+        return Err(tr!("distance.wrong_count", count = ids.len()));
+    }
+    // The markers are measured in the image of the left camera of a stereo pair
+    let cd = match cd {
+        CalibrationData::Stereo(s) => &*s.left,
+        cd => cd,
+    };
+    let cm: Mat = cd.camera_matrix().clone().into();
+    let mut centers = Vec::new();
+    if let CalibrationData::OpenCvFisheye(f) = cd {
+        // solve_pnp only knows the pinhole distortion, so the corners are undistorted with the fisheye model first
+        for m in markers.iter() {
+            let points: Vec<[f64; 2]> = m.iter().map(|p| [p.x as f64, p.y as f64]).collect();
+            let undistorted: Vector<Point2f> = f
+                .undistort_points(&points)
+                .map_err(|e| e.to_string())?
+                .iter()
+                .map(|u| Point2f::new(u.pixel[0] as f32, u.pixel[1] as f32))
+                .collect();
+            centers.push(
+                marker_center(&undistorted, marker_length, &cm, &Mat::default())
+                    .map_err(|e| e.to_string())?,
+            );
+        }
+    } else {
+        let dc: Mat = cd.distortion().clone().into();
+        for m in markers.iter() {
+            centers.push(marker_center(&m, marker_length, &cm, &dc).map_err(|e| e.to_string())?);
+        }
+    }
+    let (a, b) = (centers[0], centers[1]);
+    let distance = ((a[0] - b[0]).powi(2) + (a[1] - b[1]).powi(2) + (a[2] - b[2]).powi(2)).sqrt();
+    let mut ids = [ids.get(0).unwrap_or(0), ids.get(1).unwrap_or(0)];
+    ids.sort();
+    Ok((ids, distance))
+}
+
+impl DistanceTool {
+    pub fn dictionary(&self) -> i32 {
+        self.dictionary
+    }
+
+    pub fn marker_length(&self) -> f64 {
+        self.marker_length
+    }
+
+    /// Make a check from a measurement
+    pub fn check(&self, markers: [i32; 2], computed: f64) -> DistanceCheck {
+        DistanceCheck {
+            timestamp: chrono::Local::now().to_rfc3339(),
+            markers,
+            measured: self.measured,
+            computed,
+        }
+    }
+
+    pub fn set_result(&mut self, r: Result<DistanceCheck, String>) {
+        self.last = Some(r);
+    }
+
+    /// Show the settings and the last result, returns true when a check should be made
+    pub fn show(&mut self, ui: &mut eframe::egui::Ui, calibrated: bool) -> bool {
+        ui.label(tr!("distance.instructions"));
+        if !calibrated {
+            ui.label(tr!("distance.no_calibration"));
+            return false;
+        }
+        eframe::egui::Grid::new("distance_check").show(ui, |ui| {
+            ui.label(tr!("board.dictionary"));
+            let name = DICTIONARIES
+                .iter()
+                .find(|d| d.0 == self.dictionary)
+                .map(|d| d.1.to_string())
+                .unwrap_or_else(|| tr!("board.unknown"));
+            eframe::egui::ComboBox::from_id_salt("distance_dictionary")
+                .selected_text(name)
+                .show_ui(ui, |ui| {
+                    for (d, name) in DICTIONARIES {
+                        ui.selectable_value(&mut self.dictionary, d, name);
+                    }
+                });
+            ui.end_row();
+            ui.label(tr!("board.marker_size"));
+            ui.add(
+                eframe::egui::DragValue::new(&mut self.marker_length)
+                    .range(1.0..=2000.0)
+                    .speed(0.1)
+                    .suffix(" mm"),
+            );
+            ui.end_row();
+            ui.label(tr!("distance.measured"));
+            ui.add(
+                eframe::egui::DragValue::new(&mut self.measured)
+                    .range(1.0..=100000.0)
+                    .speed(1.0)
+                    .suffix(" mm"),
+            );
+            ui.end_row();
+        });
+        let check = ui.button(tr!("distance.check")).clicked();
+        match &self.last {
+            Some(Ok(c)) => {
+                eframe::egui::Grid::new("distance_result").show(ui, |ui| {
+                    ui.label(tr!("distance.markers"));
+                    ui.label(format!("{}, {}", c.markers[0], c.markers[1]));
+                    ui.end_row();
+                    ui.label(tr!("distance.measured"));
+                    ui.monospace(format!("{:.1} mm", c.measured));
+                    ui.end_row();
+                    ui.label(tr!("distance.computed"));
+                    ui.monospace(format!("{:.1} mm", c.computed));
+                    ui.end_row();
+                    ui.label(tr!("distance.error"));
+                    ui.monospace(format!("{:+.2} %", c.error_percent()));
+                    ui.end_row();
+                });
+            }
+            Some(Err(e)) => {
+                ui.colored_label(eframe::egui::Color32::RED, e);
+            }
+            None => {}
+        }
+        check
+    }
+}
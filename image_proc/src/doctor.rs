@@ -0,0 +1,253 @@
+//! Checks of the environment the program runs in, for finding out why something does not work.
+//! Each failed check says what to do about it.
+
+use std::path::{Path, PathBuf};
+
+/// How a check turned out
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Status {
+    Ok,
+    /// Something optional is missing
+    Warning,
+    /// Something the program needs is missing
+    Failed,
+}
+
+impl Status {
+    fn color(&self) -> eframe::egui::Color32 {
+        match self {
+            Status::Ok => eframe::egui::Color32::GREEN,
+            Status::Warning => eframe::egui::Color32::YELLOW,
+            Status::Failed => eframe::egui::Color32::RED,
+        }
+    }
+
+    fn symbol(&self) -> &'static str {
+        match self {
+            Status::Ok => "ok",
+            Status::Warning => "warning",
+            Status::Failed => "FAILED",
+        }
+    }
+}
+
+/// The result of one check
+#[derive(Clone, Debug)]
+pub struct Check {
+    pub name: String,
+    pub status: Status,
+    pub detail: String,
+    /// What to do about a warning or failure
+    pub fix: Option<String>,
+}
+
+impl Check {
+    fn ok(name: String, detail: String) -> Self {
+        Self {
+            name,
+            status: Status::Ok,
+            detail,
+            fix: None,
+        }
+    }
+
+    fn problem(name: String, status: Status, detail: String, fix: String) -> Self {
+        Self {
+            name,
+            status,
+            detail,
+            fix: Some(fix),
+        }
+    }
+}
+
+/// Find a setting of the video io section of the opencv build information, like "GStreamer: YES (1.22.0)"
+fn build_setting(info: &str, name: &str) -> Option<String> {
+    info.lines()
+        .map(str::trim)
+        .find_map(|l| l.strip_prefix(name)?.strip_prefix(':'))
+        .map(|v| v.trim().to_string())
+}
+
+fn check_opencv() -> Vec<Check> {
+    let mut checks = vec![Check::ok(
+        tr!("doctor.opencv"),
+        opencv::core::get_version_string().unwrap_or_default(),
+    )];
+
+    let aruco = image_proc::aruco::dictionary(image_proc::aruco::DICT_6X6_1000)
+        .and_then(|d| image_proc::aruco::charuco_board(5, 5, 0.04, 0.03, &d));
+    checks.push(match aruco {
+        Ok(_) => Check::ok(tr!("doctor.aruco"), tr!("doctor.available")),
+        Err(e) => Check::problem(
+            tr!("doctor.aruco"),
+            Status::Failed,
+            e.to_string(),
+            tr!("doctor.aruco_fix"),
+        ),
+    });
+
+    let info = opencv::core::get_build_information().unwrap_or_default();
+    checks.push(match build_setting(&info, "GStreamer") {
+        Some(v) if v.starts_with("YES") => Check::ok(tr!("doctor.gstreamer"), v),
+        v => Check::problem(
+            tr!("doctor.gstreamer"),
+            Status::Warning,
+            v.unwrap_or_else(|| tr!("doctor.missing")),
+            tr!("doctor.gstreamer_fix"),
+        ),
+    });
+
+    checks.push(if opencv::core::have_opencl().unwrap_or(false) {
+        Check::ok(tr!("doctor.opencl"), tr!("doctor.available"))
+    } else {
+        Check::problem(
+            tr!("doctor.opencl"),
+            Status::Warning,
+            tr!("doctor.missing"),
+            tr!("doctor.opencl_fix"),
+        )
+    });
+    checks
+}
+
+/// The camera devices that exist but can not be opened by this user
+#[cfg(target_os = "linux")]
+fn denied_devices() -> Vec<PathBuf> {
+    let Ok(dir) = std::fs::read_dir("/dev") else {
+        return Vec::new();
+    };
+    let mut denied: Vec<PathBuf> = dir
+        .filter_map(|e| e.ok().map(|e| e.path()))
+        .filter(|p| {
+            p.file_name()
+                .and_then(|n| n.to_str())
+                .is_some_and(|n| n.starts_with("video"))
+        })
+        .filter(|p| {
+            std::fs::OpenOptions::new()
+                .read(true)
+                .write(true)
+                .open(p)
+                .is_err_and(|e| e.kind() == std::io::ErrorKind::PermissionDenied)
+        })
+        .collect();
+    denied.sort();
+    denied
+}
+
+/// The camera devices that exist but can not be opened by this user
+#[cfg(not(target_os = "linux"))]
+fn denied_devices() -> Vec<PathBuf> {
+    Vec::new()
+}
+
+/// Check that cameras can be opened, found is the number of cameras already found by the program.
+/// Cameras are searched for when it is None, which fails for cameras that are already open.
+fn check_cameras(found: Option<usize>) -> Check {
+    let found = found.unwrap_or_else(|| crate::OpenCvCamera::detect().len());
+    let denied = denied_devices();
+    if !denied.is_empty() {
+        let devices: Vec<String> = denied.iter().map(|p| p.display().to_string()).collect();
+        return Check::problem(
+            tr!("doctor.cameras"),
+            Status::Failed,
+            tr!("doctor.cameras_denied", devices = devices.join(", ")),
+            tr!("doctor.cameras_denied_fix"),
+        );
+    }
+    if found == 0 {
+        return Check::problem(
+            tr!("doctor.cameras"),
+            Status::Warning,
+            tr!("doctor.cameras_none"),
+            tr!("doctor.cameras_none_fix"),
+        );
+    }
+    Check::ok(
+        tr!("doctor.cameras"),
+        tr!("doctor.cameras_found", count = found),
+    )
+}
+
+/// Check that files can be written in a directory, by writing and removing one
+fn check_directory(dir: &Path) -> Check {
+    let name = tr!("doctor.directory", path = dir.display());
+    let test = dir.join(".image_proc_doctor");
+    let r = std::fs::create_dir_all(dir)
+        .and_then(|_| std::fs::write(&test, b"test"))
+        .and_then(|_| std::fs::remove_file(&test));
+    match r {
+        Ok(()) => Check::ok(name, tr!("doctor.writable")),
+        Err(e) => Check::problem(
+            name,
+            Status::Failed,
+            e.to_string(),
+            tr!("doctor.directory_fix"),
+        ),
+    }
+}
+
+/// Run all of the checks, on the cameras already found and the directories the program writes to
+pub fn run(cameras: Option<usize>, dirs: &[PathBuf]) -> Vec<Check> {
+    let mut checks = check_opencv();
+    checks.push(check_cameras(cameras));
+    checks.extend(dirs.iter().map(|d| check_directory(d)));
+    checks
+}
+
+/// The checks as text, one per line with the fix under it
+pub fn report(checks: &[Check]) -> String {
+    let mut s = String::new();
+    for c in checks {
+        s.push_str(&format!(
+            "[{}] {}: {}\n",
+            c.status.symbol(),
+            c.name,
+            c.detail
+        ));
+        if let Some(f) = &c.fix {
+            s.push_str(&format!("    {}\n", f));
+        }
+    }
+    s
+}
+
+/// The diagnostics window
+#[derive(Default)]
+pub struct Doctor {
+    checks: Vec<Check>,
+}
+
+impl Doctor {
+    pub fn run(&mut self, cameras: usize, dirs: &[PathBuf]) {
+        self.checks = run(Some(cameras), dirs);
+    }
+
+    /// Show the results, returns true when the checks should run again
+    pub fn show(&self, ui: &mut eframe::egui::Ui) -> bool {
+        eframe::egui::Grid::new("doctor")
+            .striped(true)
+            .show(ui, |ui| {
+                for c in &self.checks {
+                    ui.colored_label(c.status.color(), c.status.symbol());
+                    ui.label(&c.name);
+                    ui.vertical(|ui| {
+                        ui.label(&c.detail);
+                        if let Some(f) = &c.fix {
+                            ui.weak(f);
+                        }
+                    });
+                    ui.end_row();
+                }
+            });
+        ui.horizontal(|ui| {
+            let again = ui.button(tr!("doctor.run_again")).clicked();
+            if ui.button(tr!("doctor.copy")).clicked() {
+                ui.ctx().copy_text(report(&self.checks));
+            }
+            again
+        })
+        .inner
+    }
+}
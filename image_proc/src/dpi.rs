@@ -0,0 +1,31 @@
+//! Drawing images and overlays sharply on high resolution displays, where a point covers more than one screen pixel
+
+use eframe::egui::{Color32, Pos2, Stroke, TextureFilter, TextureOptions, Vec2};
+
+/// The texture options for an image shown at a number of screen pixels per image pixel.
+/// Enlarged images use the nearest pixel so edges stay sharp, shrunk images are mipmapped so fine detail does not shimmer.
+pub fn texture_options(scale: f32) -> TextureOptions {
+    if scale >= 1.0 {
+        TextureOptions::NEAREST
+    } else {
+        TextureOptions {
+            mipmap_mode: Some(TextureFilter::Linear),
+            ..TextureOptions::LINEAR
+        }
+    }
+}
+
+/// Round a size in points down to a whole number of screen pixels, so the edges of an image fall on pixel boundaries
+pub fn snap_size(size: Vec2, ppp: f32) -> Vec2 {
+    (size * ppp).floor().max(Vec2::splat(1.0)) / ppp
+}
+
+/// Move a position in points to the center of the screen pixel it is in, so thin lines through it are not split over two pixels
+pub fn snap_pos(pos: Pos2, ppp: f32) -> Pos2 {
+    (((pos.to_vec2() * ppp).floor() + Vec2::splat(0.5)) / ppp).to_pos2()
+}
+
+/// A stroke a whole number of screen pixels wide, at least one, for a width in points
+pub fn stroke(width: f32, color: Color32, ppp: f32) -> Stroke {
+    Stroke::new((width * ppp).round().max(1.0) / ppp, color)
+}
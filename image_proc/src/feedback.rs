@@ -0,0 +1,141 @@
+//! Letting the user know that a capture happened, with a sound and a flash of the preview
+
+use std::{
+    path::PathBuf,
+    time::{Duration, Instant},
+};
+
+use rodio::Source;
+
+/// How captures are signalled
+#[derive(Clone, Debug, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
+pub struct FeedbackSettings {
+    /// Play a sound for every capture
+    pub sound: bool,
+    /// The sound file to play, None plays a short click
+    pub sound_file: Option<PathBuf>,
+    /// The volume of the sound, 1.0 is unchanged
+    pub volume: f32,
+    /// Flash the border of the preview for every capture
+    pub flash: bool,
+}
+
+impl Default for FeedbackSettings {
+    fn default() -> Self {
+        Self {
+            sound: true,
+            sound_file: None,
+            volume: 0.5,
+            flash: true,
+        }
+    }
+}
+
+impl FeedbackSettings {
+    /// Show the feedback settings for editing
+    pub fn show(&mut self, ui: &mut eframe::egui::Ui) {
+        ui.checkbox(&mut self.sound, tr!("feedback.sound"));
+        ui.add_enabled_ui(self.sound, |ui| {
+            ui.horizontal(|ui| {
+                let name = self
+                    .sound_file
+                    .as_ref()
+                    .map(|f| f.display().to_string())
+                    .unwrap_or_else(|| tr!("feedback.default_sound"));
+                ui.label(name);
+                if ui.button(tr!("settings.browse")).clicked() {
+                    if let Some(f) = rfd::FileDialog::new()
+                        .add_filter("Sound", &["wav", "ogg", "mp3", "flac"])
+                        .pick_file()
+                    {
+                        self.sound_file = Some(f);
+                    }
+                }
+                if self.sound_file.is_some() && ui.button(tr!("feedback.reset")).clicked() {
+                    self.sound_file = None;
+                }
+            });
+            ui.add(
+                eframe::egui::Slider::new(&mut self.volume, 0.0..=1.0).text(tr!("feedback.volume")),
+            );
+        });
+        ui.checkbox(&mut self.flash, tr!("feedback.flash"));
+    }
+}
+
+/// Plays the capture sound and keeps track of the flash
+#[derive(Default)]
+pub struct CaptureFeedback {
+    /// The audio output, opened on the first capture, None until then or when there is no audio device
+    output: Option<(rodio::OutputStream, rodio::OutputStreamHandle)>,
+    /// When the last capture happened
+    flash: Option<Instant>,
+}
+
+impl CaptureFeedback {
+    /// How long the border of the preview flashes
+    const FLASH: Duration = Duration::from_millis(300);
+
+    /// Signal that a capture happened
+    pub fn captured(&mut self, settings: &FeedbackSettings) {
+        if settings.flash {
+            self.flash = Some(Instant::now());
+        }
+        if settings.sound {
+            if let Err(e) = self.play(settings) {
+                println!("Failed to play the capture sound: {}", e);
+            }
+        }
+    }
+
+    fn play(&mut self, settings: &FeedbackSettings) -> Result<(), String> {
+        if self.output.is_none() {
+            self.output = Some(rodio::OutputStream::try_default().map_err(|e| e.to_string())?);
+        }
+        let Some((_, handle)) = &self.output else {
+            return Ok(());
+        };
+        let sink = rodio::Sink::try_new(handle).map_err(|e| e.to_string())?;
+        sink.set_volume(settings.volume);
+        match &settings.sound_file {
+            Some(f) => {
+                let file = std::fs::File::open(f).map_err(|e| e.to_string())?;
+                let source = rodio::Decoder::new(std::io::BufReader::new(file))
+                    .map_err(|e| e.to_string())?;
+                sink.append(source);
+            }
+            None => {
+                let click = rodio::source::SineWave::new(1800.0)
+                    .take_duration(Duration::from_millis(40))
+                    .fade_in(Duration::from_millis(5));
+                sink.append(click);
+            }
+        }
+        // Let the sound play out without blocking
+        sink.detach();
+        Ok(())
+    }
+
+    /// Draw the flash around the preview image, rect is where the image is shown
+    pub fn paint(&self, ui: &eframe::egui::Ui, rect: eframe::egui::Rect) {
+        let Some(t) = self.flash else {
+            return;
+        };
+        let elapsed = t.elapsed();
+        if elapsed > Self::FLASH {
+            return;
+        }
+        let alpha = 1.0 - elapsed.as_secs_f32() / Self::FLASH.as_secs_f32();
+        ui.painter().rect_stroke(
+            rect,
+            0.0,
+            crate::dpi::stroke(
+                8.0,
+                eframe::egui::Color32::WHITE.gamma_multiply(alpha),
+                ui.ctx().pixels_per_point(),
+            ),
+            eframe::egui::StrokeKind::Inside,
+        );
+    }
+}
@@ -0,0 +1,179 @@
+//! Detection of illumination flicker from the mean brightness of a camera over time
+
+use std::{collections::VecDeque, time::Instant};
+
+use egui_plot::{Line, Plot, PlotPoints};
+use opencv::core::MatTraitConst;
+
+/// The result of analyzing the brightness samples
+pub struct FlickerAnalysis {
+    /// The average frame rate of the samples
+    pub frame_rate: f64,
+    /// Magnitude of the brightness variation, relative to the mean brightness, by frequency
+    pub spectrum: Vec<[f64; 2]>,
+    /// The frequency of the strongest brightness variation
+    pub peak_frequency: f64,
+    /// The amplitude of the strongest brightness variation, relative to the mean brightness
+    pub peak_depth: f64,
+    /// The mains frequency the flicker is consistent with, if flicker was detected
+    pub mains: Option<u32>,
+}
+
+/// The frequency that a signal of frequency f appears at when sampled at fs
+fn alias_frequency(f: f64, fs: f64) -> f64 {
+    (f - (f / fs).round() * fs).abs()
+}
+
+/// Collects the mean brightness of frames from a camera and looks for flicker
+pub struct FlickerDetector {
+    samples: VecDeque<(Instant, f64)>,
+    /// The number of samples to analyze
+    pub capacity: usize,
+    /// True when frames are being sampled
+    pub running: bool,
+    analysis: Option<FlickerAnalysis>,
+}
+
+impl Default for FlickerDetector {
+    fn default() -> Self {
+        Self {
+            samples: VecDeque::new(),
+            capacity: 256,
+            running: false,
+            analysis: None,
+        }
+    }
+}
+
+impl FlickerDetector {
+    /// Add the mean brightness of a frame to the samples
+    pub fn add_frame(&mut self, img: &opencv::core::Mat) {
+        if !self.running {
+            return;
+        }
+        if let Ok(m) = opencv::core::mean(img, &opencv::core::no_array()) {
+            let brightness = if img.channels() >= 3 {
+                0.114 * m[0] + 0.587 * m[1] + 0.299 * m[2]
+            } else {
+                m[0]
+            };
+            self.samples.push_back((Instant::now(), brightness));
+            while self.samples.len() > self.capacity {
+                self.samples.pop_front();
+            }
+            if self.samples.len() == self.capacity {
+                self.analysis = self.analyze();
+            }
+        }
+    }
+
+    /// Compute the spectrum of the brightness samples, assuming they are evenly spaced in time
+    pub fn analyze(&self) -> Option<FlickerAnalysis> {
+        let n = self.samples.len();
+        if n < 16 {
+            return None;
+        }
+        let first = self.samples.front()?.0;
+        let last = self.samples.back()?.0;
+        let span = last.duration_since(first).as_secs_f64();
+        if span <= 0.0 {
+            return None;
+        }
+        let frame_rate = (n - 1) as f64 / span;
+        let mean = self.samples.iter().map(|s| s.1).sum::<f64>() / n as f64;
+        if mean <= 0.0 {
+            return None;
+        }
+        let spectrum: Vec<[f64; 2]> = (1..n / 2)
+            .map(|k| {
+                let (mut re, mut im) = (0.0, 0.0);
+                for (j, (_, v)) in self.samples.iter().enumerate() {
+                    let phase = -2.0 * std::f64::consts::PI * (k * j) as f64 / n as f64;
+                    re += (v - mean) * phase.cos();
+                    im += (v - mean) * phase.sin();
+                }
+                let amplitude = 2.0 * (re * re + im * im).sqrt() / n as f64;
+                [k as f64 * frame_rate / n as f64, amplitude / mean]
+            })
+            .collect();
+        let peak = spectrum
+            .iter()
+            .copied()
+            .max_by(|a, b| a[1].total_cmp(&b[1]))?;
+        let mut sorted: Vec<f64> = spectrum.iter().map(|s| s[1]).collect();
+        sorted.sort_by(|a, b| a.total_cmp(b));
+        let median = sorted[sorted.len() / 2];
+        let resolution = frame_rate / n as f64;
+        let significant = peak[1] > 0.002 && peak[1] > 5.0 * median;
+        let mains = if significant {
+            [50, 60].into_iter().find(|m| {
+                let light = 2.0 * *m as f64;
+                (alias_frequency(light, frame_rate) - peak[0]).abs() <= 2.0 * resolution
+            })
+        } else {
+            None
+        };
+        Some(FlickerAnalysis {
+            frame_rate,
+            spectrum,
+            peak_frequency: peak[0],
+            peak_depth: peak[1],
+            mains,
+        })
+    }
+
+    /// Show the controls and results of the detector
+    pub fn show(&mut self, ui: &mut eframe::egui::Ui) {
+        ui.horizontal(|ui| {
+            if self.running {
+                if ui.button(tr!("flicker.stop")).clicked() {
+                    self.running = false;
+                }
+            } else if ui.button(tr!("flicker.start")).clicked() {
+                self.samples.clear();
+                self.analysis = None;
+                self.running = true;
+            }
+            ui.add(
+                eframe::egui::Slider::new(&mut self.capacity, 64..=1024)
+                    .text(tr!("flicker.frames")),
+            );
+        });
+        ui.label(tr!(
+            "flicker.progress",
+            count = self.samples.len(),
+            total = self.capacity
+        ));
+        if let Some(a) = &self.analysis {
+            ui.label(tr!(
+                "flicker.frame_rate",
+                rate = format!("{:.1}", a.frame_rate)
+            ));
+            ui.label(tr!(
+                "flicker.strongest",
+                depth = format!("{:.2}", a.peak_depth * 100.0),
+                frequency = format!("{:.2}", a.peak_frequency)
+            ));
+            if let Some(m) = a.mains {
+                ui.colored_label(
+                    eframe::egui::Color32::YELLOW,
+                    tr!(
+                        "flicker.detected",
+                        mains = m,
+                        period = format!("{:.2}", 1000.0 / (2.0 * m as f64))
+                    ),
+                );
+            } else {
+                ui.label(tr!("flicker.none"));
+            }
+            let line = Line::new(PlotPoints::from(a.spectrum.clone()));
+            Plot::new("flicker_spectrum")
+                .view_aspect(2.0)
+                .x_axis_label(tr!("flicker.frequency"))
+                .y_axis_label(tr!("flicker.amplitude"))
+                .show(ui, |plot_ui| {
+                    plot_ui.line(line);
+                });
+        }
+    }
+}
@@ -0,0 +1,51 @@
+//! Frames shared between the camera thread, the processing and the window without copying their pixels
+
+use std::sync::Arc;
+
+use opencv::core::Mat;
+
+/// A frame from a camera, cloning it only adds an owner.
+/// The pixels are copied when an owner changes them while another still holds them, so owners never see each other's changes.
+#[derive(Clone, Debug)]
+pub struct Frame(Arc<Mat>);
+
+impl Frame {
+    pub fn new(mat: Mat) -> Self {
+        Self(Arc::new(mat))
+    }
+
+    /// The image for changing, copied first when the frame has other owners
+    pub fn make_mut(&mut self) -> &mut Mat {
+        Arc::make_mut(&mut self.0)
+    }
+
+    /// The image, copied when the frame has other owners
+    pub fn into_mat(self) -> Mat {
+        Arc::try_unwrap(self.0).unwrap_or_else(|m| (*m).clone())
+    }
+
+    /// True when both are the same frame, not only equal images
+    pub fn same(&self, other: &Frame) -> bool {
+        Arc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+impl From<Mat> for Frame {
+    fn from(mat: Mat) -> Self {
+        Self::new(mat)
+    }
+}
+
+impl std::ops::Deref for Frame {
+    type Target = Mat;
+
+    fn deref(&self) -> &Mat {
+        &self.0
+    }
+}
+
+impl std::borrow::Borrow<Mat> for Frame {
+    fn borrow(&self) -> &Mat {
+        &self.0
+    }
+}
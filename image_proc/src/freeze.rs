@@ -0,0 +1,109 @@
+//! Pausing the preview on one frame while the camera keeps running, with stepping through the frames that arrived since
+
+use std::collections::VecDeque;
+
+use image_proc::frame::Frame;
+use opencv::core::MatTraitConst;
+
+/// The frame the preview is paused on and the frames that arrived after it
+#[derive(Default)]
+pub struct Freeze {
+    /// The frame shown, None while not paused
+    shown: Option<Frame>,
+    /// The frames that arrived while paused, oldest first
+    queue: VecDeque<Frame>,
+    /// The memory used by the queued frames, in bytes
+    bytes: usize,
+    /// Frames that arrived after the queue was full
+    dropped: usize,
+}
+
+impl Freeze {
+    /// The most memory the queued frames may use, later frames are not kept
+    const MAX_BYTES: usize = 512 * 1024 * 1024;
+
+    pub fn paused(&self) -> bool {
+        self.shown.is_some()
+    }
+
+    /// Pause on a frame
+    pub fn pause(&mut self, frame: Frame) {
+        self.shown = Some(frame);
+        self.queue.clear();
+        self.bytes = 0;
+        self.dropped = 0;
+    }
+
+    /// Go back to showing frames as they arrive
+    pub fn resume(&mut self) {
+        self.shown = None;
+        self.queue.clear();
+        self.bytes = 0;
+        self.dropped = 0;
+    }
+
+    /// The frame to show while paused
+    pub fn shown(&self) -> Option<&Frame> {
+        self.shown.as_ref()
+    }
+
+    /// Keep a frame that arrived while paused, frames are only shared so this does not copy them
+    pub fn add_frame(&mut self, frame: &Frame) {
+        if !self.paused() {
+            return;
+        }
+        let size = frame.total() * frame.elem_size().unwrap_or(1);
+        if self.bytes + size > Self::MAX_BYTES {
+            self.dropped += 1;
+            return;
+        }
+        self.bytes += size;
+        self.queue.push_back(frame.clone());
+    }
+
+    /// Show the next frame that arrived, returns false when there is none yet
+    pub fn step(&mut self) -> bool {
+        let Some(f) = self.queue.pop_front() else {
+            return false;
+        };
+        self.bytes -= f.total() * f.elem_size().unwrap_or(1);
+        self.shown = Some(f);
+        true
+    }
+
+    /// Show the pause controls, the frame to pause on is the newest one of the camera
+    pub fn show(&mut self, ui: &mut eframe::egui::Ui, newest: Option<&Frame>) {
+        if self.paused() {
+            if ui.button(tr!("freeze.resume")).clicked() {
+                self.resume();
+                return;
+            }
+            if ui
+                .add_enabled(
+                    !self.queue.is_empty(),
+                    eframe::egui::Button::new(tr!("freeze.step")),
+                )
+                .clicked()
+            {
+                self.step();
+            }
+            ui.label(tr!("freeze.buffered", count = self.queue.len()));
+            if self.dropped > 0 {
+                ui.colored_label(
+                    eframe::egui::Color32::YELLOW,
+                    tr!("freeze.full", count = self.dropped),
+                );
+            }
+        } else if ui
+            .add_enabled(
+                newest.is_some(),
+                eframe::egui::Button::new(tr!("freeze.pause")),
+            )
+            .clicked()
+        {
+            if let Some(f) = newest {
+                self.pause(f.clone());
+            }
+        }
+    }
+}
@@ -0,0 +1,129 @@
+//! Controlling captures with a gamepad, so they can be triggered from across the room
+
+use gilrs::Button;
+
+/// Something a gamepad button does
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum GamepadAction {
+    Capture,
+    Calibrate,
+    NextCamera,
+    PreviousCamera,
+}
+
+/// Which buttons do what
+#[derive(Clone, Debug, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
+pub struct GamepadSettings {
+    pub enabled: bool,
+    pub capture: Button,
+    pub calibrate: Button,
+    pub next_camera: Button,
+    pub previous_camera: Button,
+}
+
+impl Default for GamepadSettings {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            capture: Button::South,
+            calibrate: Button::Start,
+            next_camera: Button::RightTrigger,
+            previous_camera: Button::LeftTrigger,
+        }
+    }
+}
+
+impl GamepadSettings {
+    /// The buttons that can be assigned
+    const BUTTONS: [Button; 14] = [
+        Button::South,
+        Button::East,
+        Button::North,
+        Button::West,
+        Button::LeftTrigger,
+        Button::RightTrigger,
+        Button::LeftTrigger2,
+        Button::RightTrigger2,
+        Button::Select,
+        Button::Start,
+        Button::DPadUp,
+        Button::DPadDown,
+        Button::DPadLeft,
+        Button::DPadRight,
+    ];
+
+    /// The action of a button, if it has one
+    fn action(&self, b: Button) -> Option<GamepadAction> {
+        [
+            (self.capture, GamepadAction::Capture),
+            (self.calibrate, GamepadAction::Calibrate),
+            (self.next_camera, GamepadAction::NextCamera),
+            (self.previous_camera, GamepadAction::PreviousCamera),
+        ]
+        .into_iter()
+        .find(|(button, _)| *button == b)
+        .map(|(_, a)| a)
+    }
+
+    /// Show the gamepad settings for editing
+    pub fn show(&mut self, ui: &mut eframe::egui::Ui) {
+        ui.checkbox(&mut self.enabled, tr!("gamepad.enabled"));
+        eframe::egui::Grid::new("gamepad_settings").show(ui, |ui| {
+            for (name, button) in [
+                (tr!("gamepad.capture"), &mut self.capture),
+                (tr!("gamepad.calibrate"), &mut self.calibrate),
+                (tr!("gamepad.next_camera"), &mut self.next_camera),
+                (tr!("gamepad.previous_camera"), &mut self.previous_camera),
+            ] {
+                ui.label(&name);
+                eframe::egui::ComboBox::from_id_salt(&name)
+                    .selected_text(format!("{:?}", button))
+                    .show_ui(ui, |ui| {
+                        for b in Self::BUTTONS {
+                            ui.selectable_value(button, b, format!("{:?}", b));
+                        }
+                    });
+                ui.end_row();
+            }
+        });
+    }
+}
+
+/// The connection to the gamepads
+#[derive(Default)]
+pub struct Gamepads {
+    /// None until first used, or when gamepads are not supported
+    gilrs: Option<gilrs::Gilrs>,
+    /// Gamepad support could not be started, so it is not tried again
+    unavailable: bool,
+}
+
+impl Gamepads {
+    /// The actions of the buttons pressed since the last call
+    pub fn poll(&mut self, settings: &GamepadSettings) -> Vec<GamepadAction> {
+        if !settings.enabled || self.unavailable {
+            return Vec::new();
+        }
+        if self.gilrs.is_none() {
+            match gilrs::Gilrs::new() {
+                Ok(g) => self.gilrs = Some(g),
+                Err(e) => {
+                    println!("Gamepads are not available: {}", e);
+                    self.unavailable = true;
+                    return Vec::new();
+                }
+            }
+        }
+        let Some(g) = &mut self.gilrs else {
+            return Vec::new();
+        };
+        let mut actions = Vec::new();
+        while let Some(e) = g.next_event() {
+            if let gilrs::EventType::ButtonPressed(b, _) = e.event {
+                actions.extend(settings.action(b));
+            }
+        }
+        actions
+    }
+}
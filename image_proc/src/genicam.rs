@@ -0,0 +1,157 @@
+//! Industrial cameras following the GigE Vision and USB3 Vision standards, using aravis
+
+use aravis::prelude::*;
+
+use crate::FrameSourceTrait;
+
+/// A GigE Vision or USB3 Vision camera
+pub struct GenicamCamera {
+    /// The aravis id of the camera, like "Basler-21234567"
+    id: String,
+    camera: Option<aravis::Camera>,
+    stream: Option<aravis::Stream>,
+    /// The number of reads in a row that failed
+    failures: u32,
+}
+
+impl std::fmt::Debug for GenicamCamera {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("GenicamCamera")
+            .field("id", &self.id)
+            .field("open", &self.camera.is_some())
+            .finish()
+    }
+}
+
+impl GenicamCamera {
+    /// The number of buffers given to the stream
+    const BUFFERS: usize = 4;
+
+    /// Find all of the cameras that are connected, with the name to show the user
+    pub fn detect() -> Vec<(String, GenicamCamera)> {
+        // This fails when already initialized by an earlier detection, which is fine
+        let _ = aravis::Aravis::initialize();
+        aravis::update_device_list();
+        (0..aravis::n_devices())
+            .filter_map(|i| {
+                let id = aravis::device_id(i)?.to_string();
+                let name = format!(
+                    "{} {}",
+                    aravis::device_vendor(i)
+                        .map(|v| v.to_string())
+                        .unwrap_or_default(),
+                    aravis::device_model(i)
+                        .map(|v| v.to_string())
+                        .unwrap_or_default()
+                );
+                Some((
+                    tr!("main.genicam", name = name.trim()),
+                    GenicamCamera {
+                        id,
+                        camera: None,
+                        stream: None,
+                        failures: 0,
+                    },
+                ))
+            })
+            .collect()
+    }
+
+    fn start(&mut self) -> Result<(), aravis::glib::Error> {
+        let camera = aravis::Camera::new(Some(&self.id))?;
+        // Prefer color, but not every camera supports it
+        if camera
+            .set_pixel_format(aravis::PixelFormat::BGR_8_PACKED)
+            .is_err()
+        {
+            let _ = camera.set_pixel_format(aravis::PixelFormat::MONO_8);
+        }
+        let payload = camera.payload()? as usize;
+        let stream = camera.create_stream()?;
+        for _ in 0..Self::BUFFERS {
+            stream.push_buffer(aravis::Buffer::new_allocate(payload));
+        }
+        camera.start_acquisition()?;
+        self.camera = Some(camera);
+        self.stream = Some(stream);
+        Ok(())
+    }
+
+    /// Convert a buffer from the camera to a bgr matrix like the other cameras produce, or a single channel matrix for monochrome formats
+    fn to_mat(buffer: &aravis::Buffer) -> Option<opencv::core::Mat> {
+        let w = buffer.image_width() as usize;
+        let h = buffer.image_height() as usize;
+        let data = buffer.data();
+        let format = buffer.image_pixel_format();
+        let conversion = match format {
+            aravis::PixelFormat::BGR_8_PACKED => {
+                return crate::convert::bytes_to_mat(w, h, opencv::core::CV_8UC3, data);
+            }
+            aravis::PixelFormat::RGB_8_PACKED => opencv::imgproc::COLOR_RGB2BGR,
+            // Monochrome frames stay single channel
+            aravis::PixelFormat::MONO_8 => {
+                return crate::convert::bytes_to_mat(w, h, opencv::core::CV_8UC1, data);
+            }
+            aravis::PixelFormat::BAYER_RG_8 => opencv::imgproc::COLOR_BayerRG2BGR,
+            aravis::PixelFormat::BAYER_BG_8 => opencv::imgproc::COLOR_BayerBG2BGR,
+            aravis::PixelFormat::BAYER_GR_8 => opencv::imgproc::COLOR_BayerGR2BGR,
+            aravis::PixelFormat::BAYER_GB_8 => opencv::imgproc::COLOR_BayerGB2BGR,
+            _ => return None,
+        };
+        let typ = if format == aravis::PixelFormat::RGB_8_PACKED {
+            opencv::core::CV_8UC3
+        } else {
+            opencv::core::CV_8UC1
+        };
+        let m = crate::convert::bytes_to_mat(w, h, typ, data)?;
+        let mut out = opencv::core::Mat::default();
+        opencv::imgproc::cvt_color_def(&m, &mut out, conversion).ok()?;
+        Some(out)
+    }
+}
+
+impl FrameSourceTrait for GenicamCamera {
+    fn open(&mut self) -> bool {
+        self.failures = 0;
+        if let Err(e) = self.start() {
+            println!("Failed to open camera {}: {}", self.id, e);
+            self.close();
+            return false;
+        }
+        true
+    }
+
+    fn close(&mut self) {
+        if let Some(c) = &self.camera {
+            let _ = c.stop_acquisition();
+        }
+        self.stream = None;
+        self.camera = None;
+    }
+
+    fn is_open(&self) -> bool {
+        self.camera.is_some()
+    }
+
+    fn get_image(&mut self) -> Option<opencv::core::Mat> {
+        let stream = self.stream.as_ref()?;
+        // Wait briefly so that other cameras are not held up
+        let buffer = stream.timeout_pop_buffer(1000)?;
+        let m = if buffer.status() == aravis::BufferStatus::Success {
+            Self::to_mat(&buffer)
+        } else {
+            None
+        };
+        stream.push_buffer(buffer);
+        if m.is_some() {
+            self.failures = 0;
+        } else {
+            self.failures += 1;
+        }
+        m
+    }
+
+    fn failed(&self) -> bool {
+        self.failures >= 30
+    }
+}
@@ -0,0 +1,196 @@
+//! Projecting points between the world and the image of a calibrated camera
+
+use crate::calibration::{CalibrationData, CalibrationDataTrait, Error};
+
+/// Where a camera is, as the transform from world coordinates to camera coordinates
+#[derive(Clone, Copy, Debug, Default, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct Pose {
+    /// The rotation as a rotation vector in radians, the axis scaled by the angle
+    pub rotation: [f64; 3],
+    /// The translation, in the units of the world points
+    pub translation: [f64; 3],
+}
+
+impl Pose {
+    /// The 3x3 rotation matrix, row by row
+    pub fn rotation_matrix(&self) -> [[f64; 3]; 3] {
+        let [x, y, z] = self.rotation;
+        let angle = (x * x + y * y + z * z).sqrt();
+        if angle < 1e-12 {
+            return [[1.0, 0.0, 0.0], [0.0, 1.0, 0.0], [0.0, 0.0, 1.0]];
+        }
+        let [kx, ky, kz] = [x / angle, y / angle, z / angle];
+        let (s, c) = angle.sin_cos();
+        let v = 1.0 - c;
+        [
+            [c + kx * kx * v, kx * ky * v - kz * s, kx * kz * v + ky * s],
+            [ky * kx * v + kz * s, c + ky * ky * v, ky * kz * v - kx * s],
+            [kz * kx * v - ky * s, kz * ky * v + kx * s, c + kz * kz * v],
+        ]
+    }
+
+    /// Where the camera is in world coordinates
+    pub fn camera_position(&self) -> [f64; 3] {
+        let r = self.rotation_matrix();
+        let t = self.translation;
+        // -R^T t
+        [0, 1, 2].map(|i| -(r[0][i] * t[0] + r[1][i] * t[1] + r[2][i] * t[2]))
+    }
+}
+
+/// A plane in world coordinates, the points p where normal . p = offset
+#[derive(Clone, Copy, Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct Plane {
+    pub normal: [f64; 3],
+    pub offset: f64,
+}
+
+impl Default for Plane {
+    /// The z = 0 plane, where calibration boards are usually placed
+    fn default() -> Self {
+        Self {
+            normal: [0.0, 0.0, 1.0],
+            offset: 0.0,
+        }
+    }
+}
+
+fn dot(a: [f64; 3], b: [f64; 3]) -> f64 {
+    a[0] * b[0] + a[1] * b[1] + a[2] * b[2]
+}
+
+/// The pixels that world points show up at, including the lens distortion
+#[cfg(not(target_arch = "wasm32"))]
+pub fn project_points(
+    cd: &CalibrationData,
+    points: &[[f64; 3]],
+    pose: &Pose,
+) -> opencv::Result<Vec<[f64; 2]>> {
+    if let CalibrationData::Telecentric(t) = cd {
+        return Ok(telecentric_project(t, points, pose));
+    }
+    let object: opencv::core::Vector<opencv::core::Point3d> = points
+        .iter()
+        .map(|p| opencv::core::Point3d::new(p[0], p[1], p[2]))
+        .collect();
+    let rvec = opencv::core::Vector::<f64>::from_slice(&pose.rotation);
+    let tvec = opencv::core::Vector::<f64>::from_slice(&pose.translation);
+    let cm: opencv::core::Mat = cd.camera_matrix().clone().into();
+    let dc: opencv::core::Mat = cd.distortion().clone().into();
+    let mut image: opencv::core::Vector<opencv::core::Point2d> = Default::default();
+    if let CalibrationData::OpenCvFisheye(_) = cd {
+        opencv::calib3d::fisheye_project_points_def(&object, &mut image, &rvec, &tvec, &cm, &dc)?;
+    } else {
+        opencv::calib3d::project_points_def(&object, &rvec, &tvec, &cm, &dc, &mut image)?;
+    }
+    Ok(image.iter().map(|p| [p.x, p.y]).collect())
+}
+
+/// The pixels world points show up at through a telecentric lens, the points are moved straight along the optical axis
+#[cfg(not(target_arch = "wasm32"))]
+fn telecentric_project(
+    t: &crate::calibration::TelecentricCalibration,
+    points: &[[f64; 3]],
+    pose: &Pose,
+) -> Vec<[f64; 2]> {
+    let native = crate::native::NativeCalibration(t.0.clone());
+    let [m, _, cx, cy] = native.intrinsics();
+    let d = native.distortion();
+    let r = pose.rotation_matrix();
+    points
+        .iter()
+        .map(|p| {
+            let c = [0, 1].map(|i| dot(r[i], *p) + pose.translation[i]);
+            let [x, y] = crate::native::distort(&d, c[0], c[1]);
+            [cx + m * x, cy + m * y]
+        })
+        .collect()
+}
+
+/// The world points on a plane that pixels of the distorted image see.
+/// A pixel is None when its ray is parallel to the plane or the plane is behind the camera.
+pub fn back_project_points(
+    cd: &CalibrationData,
+    pixels: &[[f64; 2]],
+    pose: &Pose,
+    plane: &Plane,
+) -> Result<Vec<Option<[f64; 3]>>, Error> {
+    let r = pose.rotation_matrix();
+    let origin = pose.camera_position();
+    let undistorted = cd.undistort_points(pixels)?;
+    let telecentric = matches!(cd, CalibrationData::Telecentric(_));
+    // R^T v, a vector in camera coordinates turned to world coordinates
+    let to_world =
+        |v: [f64; 3]| [0, 1, 2].map(|i| r[0][i] * v[0] + r[1][i] * v[1] + r[2][i] * v[2]);
+    Ok(undistorted
+        .iter()
+        .map(|u| {
+            let [x, y] = u.normalized;
+            // The rays of a telecentric lens are parallel to the optical axis, starting across it from the camera
+            let (origin, dir) = if telecentric {
+                let o = to_world([x, y, 0.0]);
+                (
+                    [0, 1, 2].map(|i| origin[i] + o[i]),
+                    to_world([0.0, 0.0, 1.0]),
+                )
+            } else {
+                (origin, to_world([x, y, 1.0]))
+            };
+            let denom = dot(plane.normal, dir);
+            if denom.abs() < 1e-12 {
+                return None;
+            }
+            let s = (plane.offset - dot(plane.normal, origin)) / denom;
+            (s > 0.0).then(|| [0, 1, 2].map(|i| origin[i] + s * dir[i]))
+        })
+        .collect())
+}
+
+/// How far points are from the straight line that fits them best
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct LineFit {
+    /// The rms distance of the points from the line
+    pub rms: f64,
+    /// The largest distance of a point from the line
+    pub max: f64,
+    /// The distance between the points furthest apart along the line
+    pub length: f64,
+}
+
+/// Fit a straight line to points, minimizing the distances at right angles to the line.
+/// None when there are fewer than 3 points.
+pub fn fit_line(points: &[[f64; 2]]) -> Option<LineFit> {
+    if points.len() < 3 {
+        return None;
+    }
+    let n = points.len() as f64;
+    let cx = points.iter().map(|p| p[0]).sum::<f64>() / n;
+    let cy = points.iter().map(|p| p[1]).sum::<f64>() / n;
+    let (mut sxx, mut sxy, mut syy) = (0.0, 0.0, 0.0);
+    for p in points {
+        let (dx, dy) = (p[0] - cx, p[1] - cy);
+        sxx += dx * dx;
+        sxy += dx * dy;
+        syy += dy * dy;
+    }
+    // The direction of the line is the principal axis of the points
+    let angle = 0.5 * (2.0 * sxy).atan2(sxx - syy);
+    let (s, c) = angle.sin_cos();
+    let mut sum = 0.0;
+    let mut max: f64 = 0.0;
+    let (mut lo, mut hi) = (f64::MAX, f64::MIN);
+    for p in points {
+        let (dx, dy) = (p[0] - cx, p[1] - cy);
+        let along = dx * c + dy * s;
+        let d = (dy * c - dx * s).abs();
+        sum += d * d;
+        max = max.max(d);
+        lo = lo.min(along);
+        hi = hi.max(along);
+    }
+    Some(LineFit {
+        rms: (sum / n).sqrt(),
+        max,
+        length: hi - lo,
+    })
+}
@@ -0,0 +1,187 @@
+//! Setting the exposure from a region of the image known to be a gray card
+
+use eframe::egui::{Color32, ColorImage, Rgba};
+
+use crate::profile::CameraProfile;
+
+/// The exposure correction measured from a gray card
+#[derive(Clone, Debug, serde::Serialize, serde::Deserialize)]
+pub struct GrayCardExposure {
+    /// The linear reflectance the card should have
+    pub target: f32,
+    /// The linear luminance the card had
+    pub measured: f32,
+    /// The exposure change that brings the card to the target, in stops
+    pub stops: f32,
+    /// The region of the card in the image, as left, top, right, bottom pixels
+    pub region: [usize; 4],
+}
+
+/// Measures a gray card selected by dragging over the preview
+pub struct GrayCardTool {
+    /// The linear reflectance of the card, 18% for a standard gray card
+    target: f32,
+    /// True while a region is being selected on the preview
+    pub selecting: bool,
+    /// The pixels where the drag started and currently is
+    drag: Option<([usize; 2], [usize; 2])>,
+    /// The last measurement
+    result: Option<GrayCardExposure>,
+    error: Option<String>,
+}
+
+impl Default for GrayCardTool {
+    fn default() -> Self {
+        Self {
+            target: 0.18,
+            selecting: false,
+            drag: None,
+            result: None,
+            error: None,
+        }
+    }
+}
+
+impl GrayCardTool {
+    /// Measure the card in the region of an image, the region is in pixels of an image of size
+    fn measure(
+        &self,
+        img: &ColorImage,
+        region: [usize; 4],
+        size: [usize; 2],
+    ) -> Result<GrayCardExposure, String> {
+        // The region is scaled in case the pipeline changed the size of the image
+        let [w, h] = img.size;
+        let sx = |x: usize| (x * w / size[0].max(1)).min(w);
+        let sy = |y: usize| (y * h / size[1].max(1)).min(h);
+        let (x0, y0, x1, y1) = (sx(region[0]), sy(region[1]), sx(region[2]), sy(region[3]));
+        let mut sum = 0.0;
+        let mut clipped = 0;
+        let mut count = 0;
+        for y in y0..y1 {
+            for x in x0..x1 {
+                let p = img.pixels[y * w + x];
+                if p.r() == 255 || p.g() == 255 || p.b() == 255 {
+                    clipped += 1;
+                }
+                let l = Rgba::from(p);
+                sum += 0.2126 * l.r() + 0.7152 * l.g() + 0.0722 * l.b();
+                count += 1;
+            }
+        }
+        if count == 0 {
+            return Err(tr!("gray_card.empty_region"));
+        }
+        if clipped * 10 > count {
+            return Err(tr!("gray_card.clipped"));
+        }
+        let measured = sum / count as f32;
+        if measured <= 0.0 {
+            return Err(tr!("gray_card.black"));
+        }
+        Ok(GrayCardExposure {
+            target: self.target,
+            measured,
+            stops: (self.target / measured).log2(),
+            region,
+        })
+    }
+
+    /// Handle selecting the card on the preview, returns the measurement when a region has been selected.
+    /// response is the response of the image widget, size is the size of the shown image in pixels and img is the image before processing.
+    pub fn interact(
+        &mut self,
+        ui: &eframe::egui::Ui,
+        response: &eframe::egui::Response,
+        size: [usize; 2],
+        img: Option<&ColorImage>,
+    ) -> Option<GrayCardExposure> {
+        if !self.selecting {
+            return None;
+        }
+        let pixel = response.interact_pointer_pos().map(|p| {
+            let p = p - response.rect.min;
+            [
+                ((p.x / response.rect.width()).clamp(0.0, 1.0) * size[0] as f32) as usize,
+                ((p.y / response.rect.height()).clamp(0.0, 1.0) * size[1] as f32) as usize,
+            ]
+        });
+        if let Some(p) = pixel {
+            if response.drag_started() {
+                self.drag = Some((p, p));
+            } else if let Some((_, end)) = &mut self.drag {
+                *end = p;
+            }
+        }
+        let (a, b) = self.drag?;
+        let region = [
+            a[0].min(b[0]),
+            a[1].min(b[1]),
+            a[0].max(b[0]),
+            a[1].max(b[1]),
+        ];
+        let to_screen = |x: usize, y: usize| {
+            response.rect.min
+                + eframe::egui::vec2(
+                    x as f32 / size[0] as f32 * response.rect.width(),
+                    y as f32 / size[1] as f32 * response.rect.height(),
+                )
+        };
+        ui.painter_at(response.rect).rect_stroke(
+            eframe::egui::Rect::from_min_max(
+                to_screen(region[0], region[1]),
+                to_screen(region[2], region[3]),
+            ),
+            0.0,
+            crate::dpi::stroke(2.0, Color32::YELLOW, ui.ctx().pixels_per_point()),
+            eframe::egui::StrokeKind::Middle,
+        );
+        if !response.drag_stopped() {
+            return None;
+        }
+        self.drag = None;
+        self.selecting = false;
+        match self.measure(img?, region, size) {
+            Ok(e) => {
+                self.error = None;
+                self.result = Some(e.clone());
+                Some(e)
+            }
+            Err(e) => {
+                self.error = Some(e);
+                None
+            }
+        }
+    }
+
+    /// Show the tool, returns the exposure change to apply to the pipeline when requested
+    pub fn show(
+        &mut self,
+        ui: &mut eframe::egui::Ui,
+        profile: Option<&CameraProfile>,
+    ) -> Option<f32> {
+        ui.label(tr!("gray_card.instructions"));
+        ui.add(
+            eframe::egui::Slider::new(&mut self.target, 0.01..=1.0)
+                .custom_formatter(|v, _| format!("{:.1}%", v * 100.0))
+                .text(tr!("gray_card.target")),
+        );
+        ui.toggle_value(&mut self.selecting, tr!("gray_card.select"));
+        if let Some(e) = &self.error {
+            ui.colored_label(Color32::RED, e);
+        }
+        let e = self
+            .result
+            .clone()
+            .or_else(|| profile.and_then(|p| p.gray_card.clone()))?;
+        ui.label(tr!(
+            "gray_card.result",
+            measured = format!("{:.1}", e.measured * 100.0),
+            target = format!("{:.1}", e.target * 100.0),
+            stops = format!("{:+.2}", e.stops)
+        ));
+        ui.button(tr!("gray_card.apply"))
+            .clicked()
+            .then_some(e.stops)
+    }
+}
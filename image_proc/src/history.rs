@@ -0,0 +1,290 @@
+//! A database of every completed calibration, for tracking how cameras change over time
+
+use std::path::Path;
+
+use egui_plot::{Legend, Line, Plot, PlotPoints, Points};
+use image_proc::calibration::CalibrationData;
+
+/// A single completed calibration
+#[derive(Clone, Debug)]
+pub struct CalibrationRecord {
+    /// When the calibration was done, in rfc 3339 format
+    pub timestamp: String,
+    /// The name of the camera that was calibrated
+    pub camera: String,
+    /// The rms reprojection error in pixels
+    pub rms: f64,
+    /// The number of images used for the calibration
+    pub image_count: usize,
+    /// A description of the calibration board
+    pub board: String,
+    pub calibration: CalibrationData,
+    /// The temperature when the calibration was done in degrees celsius, None when it was not recorded
+    pub temperature: Option<f64>,
+}
+
+impl CalibrationRecord {
+    /// The focal lengths and principal point, fx, fy, cx, cy
+    fn intrinsics(&self) -> [f64; 4] {
+        let m = self.calibration.camera_matrix().values();
+        if m.len() < 9 {
+            return [0.0; 4];
+        }
+        [m[0], m[4], m[2], m[5]]
+    }
+}
+
+/// The slope of the least squares line through points, None when the x values do not vary
+fn slope(points: &[[f64; 2]]) -> Option<f64> {
+    let n = points.len() as f64;
+    let mx = points.iter().map(|p| p[0]).sum::<f64>() / n;
+    let my = points.iter().map(|p| p[1]).sum::<f64>() / n;
+    let sxy: f64 = points.iter().map(|p| (p[0] - mx) * (p[1] - my)).sum();
+    let sxx: f64 = points.iter().map(|p| (p[0] - mx).powi(2)).sum();
+    (sxx > 1e-12).then(|| sxy / sxx)
+}
+
+/// The calibration history database, with the browsing panel
+pub struct CalibrationHistory {
+    conn: rusqlite::Connection,
+    /// The records as last read from the database, newest first
+    records: Vec<CalibrationRecord>,
+    /// Only show records for this camera
+    filter: Option<String>,
+}
+
+impl CalibrationHistory {
+    /// The name of the database file in the working directory
+    const FILE: &str = "calibration_history.sqlite";
+
+    /// Open the database in a directory, creating it if needed
+    pub fn open(dir: &Path) -> rusqlite::Result<Self> {
+        std::fs::create_dir_all(dir)
+            .map_err(|_| rusqlite::Error::InvalidPath(dir.to_path_buf()))?;
+        let conn = rusqlite::Connection::open(dir.join(Self::FILE))?;
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS calibrations (
+                id INTEGER PRIMARY KEY,
+                timestamp TEXT NOT NULL,
+                camera TEXT NOT NULL,
+                rms REAL NOT NULL,
+                image_count INTEGER NOT NULL,
+                board TEXT NOT NULL,
+                calibration BLOB NOT NULL
+            )",
+            (),
+        )?;
+        // Databases made before temperatures were recorded do not have the column
+        if conn
+            .prepare("SELECT temperature FROM calibrations LIMIT 0")
+            .is_err()
+        {
+            conn.execute("ALTER TABLE calibrations ADD COLUMN temperature REAL", ())?;
+        }
+        let mut s = Self {
+            conn,
+            records: Vec::new(),
+            filter: None,
+        };
+        s.refresh()?;
+        Ok(s)
+    }
+
+    /// Add a completed calibration to the history
+    pub fn record(
+        &mut self,
+        camera: &str,
+        rms: f64,
+        image_count: usize,
+        board: &str,
+        calibration: &CalibrationData,
+        temperature: Option<f64>,
+    ) -> rusqlite::Result<()> {
+        let data = bincode::serde::encode_to_vec(calibration, bincode::config::standard())
+            .map_err(|e| rusqlite::Error::ToSqlConversionFailure(Box::new(e)))?;
+        self.conn.execute(
+            "INSERT INTO calibrations (timestamp, camera, rms, image_count, board, calibration, temperature)
+                VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+            (
+                chrono::Local::now().to_rfc3339(),
+                camera,
+                rms,
+                image_count as i64,
+                board,
+                data,
+                temperature,
+            ),
+        )?;
+        self.refresh()
+    }
+
+    /// Read the records from the database again
+    fn refresh(&mut self) -> rusqlite::Result<()> {
+        let mut stmt = self.conn.prepare(
+            "SELECT timestamp, camera, rms, image_count, board, calibration, temperature
+                FROM calibrations ORDER BY timestamp DESC",
+        )?;
+        let rows = stmt.query_map((), |r| {
+            let data: Vec<u8> = r.get(5)?;
+            let calibration = bincode::serde::decode_from_slice(&data, bincode::config::standard())
+                .map(|(c, _)| c)
+                .map_err(|e| {
+                    rusqlite::Error::FromSqlConversionFailure(
+                        5,
+                        rusqlite::types::Type::Blob,
+                        Box::new(e),
+                    )
+                })?;
+            Ok(CalibrationRecord {
+                timestamp: r.get(0)?,
+                camera: r.get(1)?,
+                rms: r.get(2)?,
+                image_count: r.get::<_, i64>(3)? as usize,
+                board: r.get(4)?,
+                calibration,
+                temperature: r.get(6)?,
+            })
+        })?;
+        // Records that can no longer be decoded are skipped rather than hiding the whole history
+        self.records = rows.filter_map(|r| r.ok()).collect();
+        Ok(())
+    }
+
+    /// Show the history panel, returns a calibration when the user asks to load one
+    pub fn show(&mut self, ui: &mut eframe::egui::Ui) -> Option<CalibrationData> {
+        let mut load = None;
+        let mut cameras: Vec<&String> = self.records.iter().map(|r| &r.camera).collect();
+        cameras.sort();
+        cameras.dedup();
+        let mut filter = self.filter.clone();
+        eframe::egui::ComboBox::from_label(tr!("history.camera"))
+            .selected_text(filter.clone().unwrap_or_else(|| tr!("history.all_cameras")))
+            .show_ui(ui, |ui| {
+                ui.selectable_value(&mut filter, None, tr!("history.all_cameras"));
+                for c in cameras {
+                    ui.selectable_value(&mut filter, Some(c.clone()), c);
+                }
+            });
+        self.filter = filter;
+        let records: Vec<&CalibrationRecord> = self
+            .records
+            .iter()
+            .filter(|r| self.filter.as_ref().is_none_or(|f| *f == r.camera))
+            .collect();
+        if records.is_empty() {
+            ui.label(tr!("history.empty"));
+            return None;
+        }
+
+        // Plot the focal length and error over time, to show drift
+        let start = records
+            .iter()
+            .filter_map(|r| chrono::DateTime::parse_from_rfc3339(&r.timestamp).ok())
+            .min();
+        let days = |r: &CalibrationRecord| {
+            let t = chrono::DateTime::parse_from_rfc3339(&r.timestamp).ok()?;
+            Some((t - start?).num_seconds() as f64 / 86400.0)
+        };
+        let fx: PlotPoints = records
+            .iter()
+            .rev()
+            .filter_map(|r| Some([days(r)?, r.intrinsics()[0]]))
+            .collect();
+        let rms: PlotPoints = records
+            .iter()
+            .rev()
+            .filter_map(|r| Some([days(r)?, r.rms]))
+            .collect();
+        ui.horizontal(|ui| {
+            Plot::new("history_fx")
+                .legend(Legend::default())
+                .width(300.0)
+                .height(150.0)
+                .x_axis_label(tr!("history.days"))
+                .show(ui, |plot| {
+                    plot.line(Line::new(fx).name(tr!("history.focal_length")));
+                });
+            Plot::new("history_rms")
+                .legend(Legend::default())
+                .width(300.0)
+                .height(150.0)
+                .x_axis_label(tr!("history.days"))
+                .show(ui, |plot| {
+                    plot.line(Line::new(rms).name(tr!("history.rms")));
+                });
+        });
+
+        // Plot the intrinsics against the temperature, for the calibrations it was recorded for
+        let by_temperature = |i: usize| -> Vec<[f64; 2]> {
+            records
+                .iter()
+                .filter_map(|r| Some([r.temperature?, r.intrinsics()[i]]))
+                .collect()
+        };
+        let fx_t = by_temperature(0);
+        if fx_t.len() >= 2 {
+            let fy_t = by_temperature(1);
+            Plot::new("history_temperature")
+                .legend(Legend::default())
+                .height(150.0)
+                .x_axis_label(tr!("history.temperature"))
+                .y_axis_label(tr!("history.focal_length"))
+                .show(ui, |plot| {
+                    plot.points(Points::new(fx_t.clone()).radius(3.0).name("fx"));
+                    plot.points(Points::new(fy_t.clone()).radius(3.0).name("fy"));
+                });
+            for (name, points) in [("fx", &fx_t), ("fy", &fy_t)] {
+                if let Some(s) = slope(points) {
+                    ui.label(tr!(
+                        "history.drift",
+                        name = name,
+                        slope = format!("{:+.3}", s)
+                    ));
+                }
+            }
+        }
+
+        eframe::egui::ScrollArea::vertical()
+            .max_height(300.0)
+            .show(ui, |ui| {
+                eframe::egui::Grid::new("history")
+                    .striped(true)
+                    .show(ui, |ui| {
+                        for h in [
+                            tr!("history.time"),
+                            tr!("history.camera"),
+                            tr!("history.rms"),
+                            tr!("history.images"),
+                            "fx".to_string(),
+                            "fy".to_string(),
+                            "cx".to_string(),
+                            "cy".to_string(),
+                            tr!("history.board"),
+                            tr!("history.temperature"),
+                        ] {
+                            ui.strong(h);
+                        }
+                        ui.end_row();
+                        for r in records {
+                            ui.label(&r.timestamp);
+                            ui.label(&r.camera);
+                            ui.label(format!("{:.3}", r.rms));
+                            ui.label(r.image_count.to_string());
+                            for v in r.intrinsics() {
+                                ui.label(format!("{:.1}", v));
+                            }
+                            ui.label(&r.board);
+                            match r.temperature {
+                                Some(t) => ui.label(format!("{:.1} °C", t)),
+                                None => ui.label("-"),
+                            };
+                            if ui.button(tr!("history.load")).clicked() {
+                                load = Some(r.calibration.clone());
+                            }
+                            ui.end_row();
+                        }
+                    });
+            });
+        load
+    }
+}
@@ -0,0 +1,247 @@
+//! Checksums and signatures embedded in saved calibration files, so files that were changed or cut short are found when loading.
+//! A file starts with MAGIC, followed by an envelope holding the contents, their sha-256 and optionally an ed25519 signature of the contents.
+//! Files saved before checksums were added have no magic and are loaded unchecked.
+
+use ed25519_dalek::{Signer, SigningKey, Verifier, VerifyingKey};
+use sha2::{Digest, Sha256};
+
+/// The start of every file with a checksum
+const MAGIC: &[u8; 8] = b"IPSEALv1";
+
+#[derive(serde::Serialize, serde::Deserialize)]
+struct Envelope {
+    contents: Vec<u8>,
+    sha256: [u8; 32],
+    signature: Option<EnvelopeSignature>,
+}
+
+#[derive(serde::Serialize, serde::Deserialize)]
+struct EnvelopeSignature {
+    /// The key that verifies the signature
+    public_key: [u8; 32],
+    /// The 64 byte ed25519 signature of the contents
+    signature: Vec<u8>,
+}
+
+/// How the contents of a file were checked when it was loaded
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Verification {
+    /// The file was saved without a checksum
+    Unchecked,
+    /// The checksum matched and the file is not signed
+    Checksum,
+    /// The checksum and signature matched, trusted is true when the key is one of the trusted keys
+    Signed { public_key: [u8; 32], trusted: bool },
+}
+
+/// The signatures accepted when loading a file
+#[derive(Clone, Debug, Default)]
+pub struct Trust {
+    pub keys: Vec<VerifyingKey>,
+    /// Refuse files that are not signed by one of the keys
+    pub require_signature: bool,
+}
+
+/// Why the contents of a file could not be trusted
+#[derive(Debug)]
+pub enum IntegrityError {
+    Io(std::io::Error),
+    /// The file is not in the expected format, or is cut short
+    Malformed,
+    /// The contents do not match the checksum
+    Checksum,
+    /// The contents do not match the signature
+    Signature,
+    /// A signature from a trusted key is required and the file does not have one
+    Untrusted,
+}
+
+impl std::fmt::Display for IntegrityError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            IntegrityError::Io(e) => write!(f, "{}", e),
+            IntegrityError::Malformed => write!(f, "{}", tr!("integrity.malformed")),
+            IntegrityError::Checksum => write!(f, "{}", tr!("integrity.checksum")),
+            IntegrityError::Signature => write!(f, "{}", tr!("integrity.signature")),
+            IntegrityError::Untrusted => write!(f, "{}", tr!("integrity.untrusted")),
+        }
+    }
+}
+
+impl std::error::Error for IntegrityError {}
+
+impl From<std::io::Error> for IntegrityError {
+    fn from(value: std::io::Error) -> Self {
+        IntegrityError::Io(value)
+    }
+}
+
+/// Wrap the contents of a file with their checksum, and a signature when a key is given
+pub fn seal(contents: Vec<u8>, key: Option<&SigningKey>) -> std::io::Result<Vec<u8>> {
+    let envelope = Envelope {
+        sha256: Sha256::digest(&contents).into(),
+        signature: key.map(|k| EnvelopeSignature {
+            public_key: k.verifying_key().to_bytes(),
+            signature: k.sign(&contents).to_bytes().to_vec(),
+        }),
+        contents,
+    };
+    let mut data = MAGIC.to_vec();
+    data.extend(
+        bincode::serde::encode_to_vec(&envelope, bincode::config::standard())
+            .map_err(std::io::Error::other)?,
+    );
+    Ok(data)
+}
+
+/// Check the data of a file and return its contents
+pub fn unseal(data: &[u8], trust: &Trust) -> Result<(Vec<u8>, Verification), IntegrityError> {
+    let Some(rest) = data.strip_prefix(MAGIC) else {
+        if trust.require_signature {
+            return Err(IntegrityError::Untrusted);
+        }
+        return Ok((data.to_vec(), Verification::Unchecked));
+    };
+    let (envelope, used): (Envelope, usize) =
+        bincode::serde::decode_from_slice(rest, bincode::config::standard())
+            .map_err(|_| IntegrityError::Malformed)?;
+    if used != rest.len() {
+        return Err(IntegrityError::Malformed);
+    }
+    if <[u8; 32]>::from(Sha256::digest(&envelope.contents)) != envelope.sha256 {
+        return Err(IntegrityError::Checksum);
+    }
+    let verification = match &envelope.signature {
+        None => Verification::Checksum,
+        Some(s) => {
+            let key =
+                VerifyingKey::from_bytes(&s.public_key).map_err(|_| IntegrityError::Signature)?;
+            let signature = ed25519_dalek::Signature::from_slice(&s.signature)
+                .map_err(|_| IntegrityError::Signature)?;
+            key.verify(&envelope.contents, &signature)
+                .map_err(|_| IntegrityError::Signature)?;
+            Verification::Signed {
+                public_key: s.public_key,
+                trusted: trust.keys.contains(&key),
+            }
+        }
+    };
+    if trust.require_signature
+        && !matches!(verification, Verification::Signed { trusted: true, .. })
+    {
+        return Err(IntegrityError::Untrusted);
+    }
+    Ok((envelope.contents, verification))
+}
+
+/// Lowercase hex of some bytes, used for showing and entering keys
+pub fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// The bytes of a hex string, None when it is not valid hex
+pub fn from_hex(s: &str) -> Option<Vec<u8>> {
+    let s = s.trim();
+    if s.len() % 2 != 0 {
+        return None;
+    }
+    (0..s.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(s.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+/// A 32 byte key from hex
+pub fn key_from_hex(s: &str) -> Option<[u8; 32]> {
+    from_hex(s)?.try_into().ok()
+}
+
+/// A public key to verify signatures with, from its hex
+pub fn verifying_key_from_hex(s: &str) -> Option<VerifyingKey> {
+    VerifyingKey::from_bytes(&key_from_hex(s)?).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CONTENTS: &[u8] = b"calibration contents";
+
+    fn key(seed: u8) -> SigningKey {
+        SigningKey::from_bytes(&[seed; 32])
+    }
+
+    fn trusting(key: &SigningKey, require_signature: bool) -> Trust {
+        Trust {
+            keys: vec![key.verifying_key()],
+            require_signature,
+        }
+    }
+
+    #[test]
+    fn signed_round_trip() {
+        let k = key(1);
+        let data = seal(CONTENTS.to_vec(), Some(&k)).unwrap();
+        let (contents, verification) = unseal(&data, &trusting(&k, true)).unwrap();
+        assert_eq!(contents, CONTENTS);
+        assert_eq!(
+            verification,
+            Verification::Signed {
+                public_key: k.verifying_key().to_bytes(),
+                trusted: true,
+            }
+        );
+    }
+
+    #[test]
+    fn tampered_contents_are_refused() {
+        let k = key(1);
+        for signer in [None, Some(&k)] {
+            let mut data = seal(CONTENTS.to_vec(), signer).unwrap();
+            let at = data
+                .windows(CONTENTS.len())
+                .position(|w| w == CONTENTS)
+                .unwrap();
+            data[at] ^= 1;
+            assert!(matches!(
+                unseal(&data, &trusting(&k, false)),
+                Err(IntegrityError::Checksum)
+            ));
+        }
+    }
+
+    #[test]
+    fn untrusted_signatures_are_refused_when_required() {
+        let data = seal(CONTENTS.to_vec(), Some(&key(2))).unwrap();
+        assert!(matches!(
+            unseal(&data, &trusting(&key(1), true)),
+            Err(IntegrityError::Untrusted)
+        ));
+        let (_, verification) = unseal(&data, &trusting(&key(1), false)).unwrap();
+        assert!(matches!(
+            verification,
+            Verification::Signed { trusted: false, .. }
+        ));
+    }
+
+    #[test]
+    fn unsigned_files_are_accepted_only_when_no_signature_is_required() {
+        let k = key(1);
+        let legacy = CONTENTS.to_vec();
+        let checksummed = seal(CONTENTS.to_vec(), None).unwrap();
+        assert_eq!(
+            unseal(&legacy, &trusting(&k, false)).unwrap(),
+            (CONTENTS.to_vec(), Verification::Unchecked)
+        );
+        assert_eq!(
+            unseal(&checksummed, &trusting(&k, false)).unwrap(),
+            (CONTENTS.to_vec(), Verification::Checksum)
+        );
+        for data in [legacy, checksummed] {
+            assert!(matches!(
+                unseal(&data, &trusting(&k, true)),
+                Err(IntegrityError::Untrusted)
+            ));
+        }
+    }
+}
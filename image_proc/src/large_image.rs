@@ -0,0 +1,289 @@
+//! Viewing images too large to show as a single texture, like high resolution scans.
+//! The image is decoded once into a pyramid of levels, each half the size of the one before,
+//! and only the tiles that are visible at the current zoom are turned into textures.
+
+use std::{collections::HashMap, path::Path};
+
+use eframe::egui::{Color32, ColorImage, Pos2, Rect, Vec2};
+
+/// Images with more pixels than this are opened as large images
+pub const LARGE_PIXELS: u64 = 40_000_000;
+
+/// The width and height of a tile in pixels
+const TILE: u32 = 512;
+
+/// The largest side of the overview that is used for the preview and the pipeline
+const OVERVIEW: u32 = 4096;
+
+/// The most tile textures kept, older tiles are dropped when the view moves away
+const MAX_TILES: usize = 256;
+
+/// The number of pixels of an image file, read from its header without decoding it
+pub fn pixel_count(path: &Path) -> Option<u64> {
+    let (w, h) = image::ImageReader::open(path)
+        .ok()?
+        .with_guessed_format()
+        .ok()?
+        .into_dimensions()
+        .ok()?;
+    Some(w as u64 * h as u64)
+}
+
+/// Half the size of an image, averaging each block of 2x2 pixels
+fn half(img: &image::RgbImage) -> image::RgbImage {
+    let (w, h) = ((img.width() / 2).max(1), (img.height() / 2).max(1));
+    image::RgbImage::from_fn(w, h, |x, y| {
+        let mut sum = [0u32; 3];
+        for (dx, dy) in [(0, 0), (1, 0), (0, 1), (1, 1)] {
+            let p = img.get_pixel(
+                (2 * x + dx).min(img.width() - 1),
+                (2 * y + dy).min(img.height() - 1),
+            );
+            for (s, v) in sum.iter_mut().zip(p.0) {
+                *s += v as u32;
+            }
+        }
+        image::Rgb(sum.map(|s| ((s + 2) / 4) as u8))
+    })
+}
+
+/// A region of a level as an image for egui
+fn region(img: &image::RgbImage, x: u32, y: u32, w: u32, h: u32) -> ColorImage {
+    let mut pixels = Vec::with_capacity((w * h) as usize);
+    for row in y..y + h {
+        for col in x..x + w {
+            let p = img.get_pixel(col, row);
+            pixels.push(Color32::from_rgb(p[0], p[1], p[2]));
+        }
+    }
+    ColorImage {
+        size: [w as usize, h as usize],
+        pixels,
+    }
+}
+
+/// A decoded image with its smaller levels
+pub struct TiledImage {
+    /// The levels, full resolution first
+    levels: Vec<image::RgbImage>,
+}
+
+impl TiledImage {
+    /// Decode an image file and build its levels.
+    /// The file is read as it is decoded so the compressed data is not copied into memory as well.
+    pub fn open(path: &Path) -> Result<Self, String> {
+        let file = std::fs::File::open(path).map_err(|e| e.to_string())?;
+        let mut reader = image::ImageReader::new(std::io::BufReader::new(file))
+            .with_guessed_format()
+            .map_err(|e| e.to_string())?;
+        // The default limits refuse images this large
+        reader.no_limits();
+        let full = crate::decode_oriented(reader)
+            .map_err(|e| e.to_string())?
+            .into_rgb8();
+        let mut levels = vec![full];
+        while let Some(last) = levels.last() {
+            if last.width() <= TILE && last.height() <= TILE {
+                break;
+            }
+            let next = half(last);
+            levels.push(next);
+        }
+        Ok(Self { levels })
+    }
+
+    /// The size of the full resolution image
+    pub fn size(&self) -> [u32; 2] {
+        [self.levels[0].width(), self.levels[0].height()]
+    }
+
+    /// The largest level that fits in the size of the preview, for showing and processing the whole image
+    pub fn overview(&self) -> ColorImage {
+        let level = self
+            .levels
+            .iter()
+            .find(|l| l.width() <= OVERVIEW && l.height() <= OVERVIEW)
+            .unwrap_or_else(|| self.levels.last().unwrap());
+        region(level, 0, 0, level.width(), level.height())
+    }
+
+    /// A tile of a level, None when it is outside of the level
+    fn tile(&self, level: usize, tx: u32, ty: u32) -> Option<ColorImage> {
+        let l = self.levels.get(level)?;
+        let (x, y) = (tx * TILE, ty * TILE);
+        if x >= l.width() || y >= l.height() {
+            return None;
+        }
+        let w = TILE.min(l.width() - x);
+        let h = TILE.min(l.height() - y);
+        Some(region(l, x, y, w, h))
+    }
+}
+
+/// The large image window, which shows any part of the image at full resolution
+pub struct LargeImageViewer {
+    image: Option<TiledImage>,
+    /// The image being decoded in the background, with its path
+    loading: Option<(
+        std::path::PathBuf,
+        crossbeam::channel::Receiver<Result<TiledImage, String>>,
+    )>,
+    /// Screen points per pixel of the full resolution image
+    zoom: f32,
+    /// The pixel of the full resolution image at the center of the view
+    center: Pos2,
+    /// The tile textures by level and tile position, with the frame they were last drawn in
+    textures: HashMap<(usize, u32, u32), (eframe::egui::TextureHandle, u64)>,
+    /// Counts the frames drawn, for dropping the tiles that have not been drawn for the longest
+    frame: u64,
+    error: Option<String>,
+}
+
+impl Default for LargeImageViewer {
+    fn default() -> Self {
+        Self {
+            image: None,
+            loading: None,
+            zoom: 1.0,
+            center: Pos2::ZERO,
+            textures: HashMap::new(),
+            frame: 0,
+            error: None,
+        }
+    }
+}
+
+impl LargeImageViewer {
+    /// Start decoding an image in the background
+    pub fn open(&mut self, path: &Path) {
+        let (s, r) = crossbeam::channel::bounded(1);
+        let p = path.to_path_buf();
+        std::thread::spawn(move || {
+            let _ = s.send(TiledImage::open(&p));
+        });
+        self.loading = Some((path.to_path_buf(), r));
+        self.error = None;
+    }
+
+    /// Check if decoding finished, returns the overview of the image for the preview when it did
+    pub fn poll(&mut self) -> Option<Result<ColorImage, String>> {
+        let (_, r) = self.loading.as_ref()?;
+        let result = r.try_recv().ok()?;
+        self.loading = None;
+        self.textures.clear();
+        match result {
+            Ok(img) => {
+                let overview = img.overview();
+                let [w, h] = img.size();
+                self.center = Pos2::new(w as f32 / 2.0, h as f32 / 2.0);
+                self.zoom = 0.0;
+                self.image = Some(img);
+                Some(Ok(overview))
+            }
+            Err(e) => {
+                self.error = Some(e.clone());
+                Some(Err(e))
+            }
+        }
+    }
+
+    /// Show the image, dragging pans and scrolling zooms
+    pub fn show(&mut self, ui: &mut eframe::egui::Ui) {
+        if let Some((path, _)) = &self.loading {
+            ui.horizontal(|ui| {
+                ui.spinner();
+                ui.label(tr!("large_image.loading", path = path.display()));
+            });
+        }
+        if let Some(e) = &self.error {
+            ui.colored_label(Color32::RED, e);
+        }
+        let Some(img) = &self.image else {
+            ui.label(tr!("large_image.none"));
+            return;
+        };
+        let [w, h] = img.size();
+        let full = Vec2::new(w as f32, h as f32);
+        ui.horizontal(|ui| {
+            ui.label(format!("{}x{}", w, h));
+            if ui.button(tr!("large_image.fit")).clicked() {
+                self.zoom = 0.0;
+            }
+            if ui.button(tr!("large_image.actual_size")).clicked() {
+                self.zoom = 1.0;
+            }
+            ui.label(format!("{:.1}%", self.zoom * 100.0));
+        });
+        let (rect, response) = ui.allocate_exact_size(
+            ui.available_size().max(Vec2::splat(64.0)),
+            eframe::egui::Sense::click_and_drag(),
+        );
+        let fit = (rect.width() / full.x).min(rect.height() / full.y);
+        if self.zoom <= 0.0 {
+            self.zoom = fit;
+            self.center = (full / 2.0).to_pos2();
+        }
+        if response.dragged() {
+            self.center -= response.drag_delta() / self.zoom;
+        }
+        if response.hovered() {
+            let scroll = ui.input(|i| i.smooth_scroll_delta.y);
+            if scroll != 0.0 {
+                let pointer = response.hover_pos().unwrap_or(rect.center());
+                // Keep the pixel under the pointer in place while zooming
+                let under = self.center + (pointer - rect.center()) / self.zoom;
+                self.zoom = (self.zoom * (scroll / 200.0).exp()).clamp(fit.min(1.0), 16.0);
+                self.center = under - (pointer - rect.center()) / self.zoom;
+            }
+        }
+        self.center = self.center.clamp(Pos2::ZERO, full.to_pos2());
+
+        // The level with at least one pixel per screen point, so nothing is shown at less than full detail
+        let level = ((1.0 / self.zoom).log2().floor().max(0.0) as usize).min(img.levels.len() - 1);
+        let scale = (1u32 << level) as f32;
+        let to_screen = |p: Pos2| rect.center() + (p - self.center) * self.zoom;
+        let visible = Rect::from_min_max(
+            self.center - rect.size() / (2.0 * self.zoom),
+            self.center + rect.size() / (2.0 * self.zoom),
+        );
+        let span = TILE as f32 * scale;
+        let first = |v: f32| (v / span).floor().max(0.0) as u32;
+        let painter = ui.painter_at(rect);
+        painter.rect_filled(rect, 0.0, Color32::BLACK);
+        self.frame += 1;
+        for ty in first(visible.min.y)..=first(visible.max.y.min(full.y - 1.0)) {
+            for tx in first(visible.min.x)..=first(visible.max.x.min(full.x - 1.0)) {
+                let key = (level, tx, ty);
+                if !self.textures.contains_key(&key) {
+                    let Some(tile) = img.tile(level, tx, ty) else {
+                        continue;
+                    };
+                    let t = ui.ctx().load_texture(
+                        format!("large_image_{}_{}_{}", level, tx, ty),
+                        tile,
+                        eframe::egui::TextureOptions::LINEAR,
+                    );
+                    self.textures.insert(key, (t, self.frame));
+                }
+                let Some((t, used)) = self.textures.get_mut(&key) else {
+                    continue;
+                };
+                *used = self.frame;
+                let min = Pos2::new(tx as f32 * span, ty as f32 * span);
+                let size = t.size_vec2() * scale;
+                painter.image(
+                    t.id(),
+                    Rect::from_min_max(to_screen(min), to_screen(min + size)),
+                    Rect::from_min_max(Pos2::ZERO, Pos2::new(1.0, 1.0)),
+                    Color32::WHITE,
+                );
+            }
+        }
+        if self.textures.len() > MAX_TILES {
+            let mut ages: Vec<u64> = self.textures.values().map(|(_, f)| *f).collect();
+            ages.sort_unstable();
+            let cutoff = ages[ages.len() - MAX_TILES];
+            self.textures.retain(|_, (_, f)| *f >= cutoff);
+        }
+    }
+}
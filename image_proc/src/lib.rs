@@ -0,0 +1,30 @@
+//! The calibration core and processing pipeline of image_proc, shared by the gui, the web viewer and the optional python bindings.
+//! Everything that needs opencv is left out of wasm builds.
+
+rust_i18n::i18n!("locales", fallback = "en");
+
+/// Look up a translated user interface string, the strings are in the locales directory
+macro_rules! tr {
+    ($($arg:tt)*) => {
+        rust_i18n::t!($($arg)*).into_owned()
+    };
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+pub mod aruco;
+pub mod calibration;
+pub mod cancel;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod convert;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod frame;
+pub mod geometry;
+pub mod integrity;
+pub mod linalg;
+pub mod native;
+pub mod pipeline;
+#[cfg(feature = "python")]
+mod python;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod stereo;
+pub mod zoom;
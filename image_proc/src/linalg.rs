@@ -0,0 +1,50 @@
+//! Small linear algebra helpers for the fits and camera geometry that do not need a matrix library
+
+/// Solve a small linear system with gaussian elimination, None when it is singular
+pub fn solve<const N: usize>(mut a: [[f64; N]; N], mut b: [f64; N]) -> Option<[f64; N]> {
+    for col in 0..N {
+        let pivot = (col..N).max_by(|i, j| a[*i][col].abs().total_cmp(&a[*j][col].abs()))?;
+        if a[pivot][col].abs() < 1e-12 {
+            return None;
+        }
+        a.swap(col, pivot);
+        b.swap(col, pivot);
+        for row in col + 1..N {
+            let f = a[row][col] / a[col][col];
+            for c in col..N {
+                a[row][c] -= f * a[col][c];
+            }
+            b[row] -= f * b[col];
+        }
+    }
+    let mut x = [0.0; N];
+    for row in (0..N).rev() {
+        let s: f64 = (row + 1..N).map(|c| a[row][c] * x[c]).sum();
+        x[row] = (b[row] - s) / a[row][row];
+    }
+    Some(x)
+}
+
+/// The product of two 3x3 matrices, row by row
+pub fn mul3(a: &[f64; 9], b: &[f64; 9]) -> [f64; 9] {
+    std::array::from_fn(|i| (0..3).map(|k| a[i / 3 * 3 + k] * b[k * 3 + i % 3]).sum())
+}
+
+/// The transpose of a 3x3 matrix, row by row
+pub fn transpose3(m: &[f64; 9]) -> [f64; 9] {
+    std::array::from_fn(|i| m[i % 3 * 3 + i / 3])
+}
+
+/// The inverse of a 3x3 matrix, row by row, None when it is singular
+pub fn inverse3(m: &[f64; 9]) -> Option<[f64; 9]> {
+    // The cofactor of an element, with the sign from the cyclic order of the rows and columns left
+    let c = |r: usize, k: usize| {
+        let (r1, r2, k1, k2) = ((r + 1) % 3, (r + 2) % 3, (k + 1) % 3, (k + 2) % 3);
+        m[r1 * 3 + k1] * m[r2 * 3 + k2] - m[r1 * 3 + k2] * m[r2 * 3 + k1]
+    };
+    let det = m[0] * c(0, 0) + m[1] * c(0, 1) + m[2] * c(0, 2);
+    if det.abs() < 1e-12 {
+        return None;
+    }
+    Some(std::array::from_fn(|i| c(i % 3, i / 3) / det))
+}
@@ -1,140 +1,232 @@
+rust_i18n::i18n!("locales", fallback = "en");
+
+/// Look up a translated user interface string, the strings are in the locales directory
+macro_rules! tr {
+    ($($arg:tt)*) => {
+        rust_i18n::t!($($arg)*).into_owned()
+    };
+}
+
+mod annotation;
+mod audit;
+mod averaging;
+mod backup;
+mod board;
+mod burst;
+mod calibration_task;
+mod cli;
+mod color_management;
+mod colormap;
+mod compare;
+mod depth;
+mod detections;
+mod distance;
+mod doctor;
+mod dpi;
+mod feedback;
+mod flicker;
+mod freeze;
+mod gamepad;
+#[cfg(feature = "genicam")]
+mod genicam;
+mod gray_card;
+mod history;
+mod large_image;
+mod montage;
+mod motion;
+mod noise;
+mod pattern;
+#[cfg(target_os = "linux")]
+mod picamera;
+mod presets;
+mod probe;
+mod profile;
+mod profiler;
+mod projection;
+#[cfg(feature = "realsense")]
+mod realsense;
+mod recovery;
+mod reminders;
+mod report;
+mod residuals;
+mod review;
+mod ring_buffer;
+mod rolling_shutter;
+mod screen;
+mod sensitivity;
+mod sensor_mode;
+mod settings;
+mod sidecar;
+mod signing;
+mod status;
+mod stereo_rig;
+mod straightness;
+mod temperature;
+mod thermal;
+mod undistort;
+mod vignetting;
+mod watch;
+mod webhook;
+mod wizard;
+mod zoom_lens;
+
 use std::{
-    collections::{BTreeMap, BTreeSet},
-    io::{Read, Write},
+    collections::{BTreeMap, BTreeSet, VecDeque},
+    io::Read,
+    path::{Path, PathBuf},
     thread::JoinHandle,
-    time::Duration,
+    time::{Duration, Instant},
 };
 
-use eframe::{
-    CreationContext,
-    egui::ColorImage,
-};
+use eframe::{CreationContext, egui::ColorImage};
 use egui_plot::{Line, Plot, PlotPoints};
-use opencv::{
-    core::{MatTraitConst, MatTraitConstManual, MatTraitManual},
-    videoio::VideoCaptureTrait,
-};
-
-#[derive(Clone, Debug, serde::Serialize, serde::Deserialize)]
-struct SaveableOpencvMat {
-    width: i32,
-    height: i32,
-    typ: i32,
-    data: Vec<u8>,
-}
-
-impl From<opencv::core::Mat> for SaveableOpencvMat {
-    fn from(value: opencv::core::Mat) -> Self {
-        let s = value.size().unwrap();
-        let t = Self {
-            width: s.width,
-            height: s.height,
-            typ: value.typ(),
-            data: value.data_bytes().unwrap().to_vec(),
-        };
-        t
-    }
-}
-
-impl opencv::core::MatTraitConst for SaveableOpencvMat {
-    fn as_raw_Mat(&self) -> *const opencv::mod_prelude_sys::c_void {
-        self.data.as_ptr() as *const opencv::mod_prelude_sys::c_void
-    }
-}
+use image_proc::calibration::{CalibrationData, CalibrationDataTrait, CameraModel};
+use image_proc::frame::Frame;
+use image_proc::integrity::{IntegrityError, Verification};
+use image_proc::{convert, pipeline};
+use opencv::{core::MatTraitConst, videoio::VideoCaptureTrait};
 
-impl Into<opencv::core::Mat> for SaveableOpencvMat {
-    fn into(self) -> opencv::core::Mat {
-        let mut m = opencv::core::Mat::default();
-        let mut size = opencv::core::Size::default();
-        size.width = self.width;
-        size.height = self.height;
-        let mut orig = opencv::core::Mat::new_size_with_default(
-            size,
-            opencv::core::CV_64FC1,
-            Default::default(),
-        )
-        .unwrap();
-        let p = orig.data_bytes_mut().unwrap();
-        p.copy_from_slice(&self.data);
-        let a = orig.copy_to(&mut m);
-        println!("Copy image result is {:?}", a);
-        m
-    }
+#[derive(Debug)]
+struct OpenCvCamera {
+    cam: Option<opencv::videoio::VideoCapture>,
+    i: i32,
+    /// The video file played instead of a camera device
+    file: Option<PathBuf>,
+    /// The gstreamer pipeline used instead of a camera device
+    pipeline: Option<String>,
+    /// How the camera device is opened
+    config: presets::CameraConfig,
+    /// The time between frames of a video file
+    frame_interval: Option<Duration>,
+    last_frame: Option<Instant>,
+    /// The number of reads in a row that failed
+    failures: u32,
+    height: Option<f64>,
+    width: Option<f64>,
 }
 
+/// Something that produces frames, like a camera
 #[enum_dispatch::enum_dispatch]
-trait CalibrationDataTrait {
-    fn apply_calibration(&self, img: ColorImage) -> ColorImage;
-}
-
-#[enum_dispatch::enum_dispatch(CalibrationDataTrait)]
-#[derive(serde::Serialize, serde::Deserialize)]
-enum CalibrationData {
-    OpenCvCharuco([SaveableOpencvMat; 2]),
-}
-
-impl CalibrationDataTrait for [SaveableOpencvMat; 2] {
-    fn apply_calibration(&self, img: ColorImage) -> ColorImage {
-        println!("colorimg is {:?}", img);
-        let mut m = opencv::core::Mat::default();
-        let mut size = opencv::core::Size::default();
-        size.width = img.width() as i32;
-        size.height = img.height() as i32;
-        println!("Size2 is {:?}", size);
-        let mut orig = opencv::core::Mat::new_size_with_default(
-            size,
-            opencv::core::CV_8UC3,
-            Default::default(),
-        )
-        .unwrap();
-        let p = orig.data_bytes_mut().unwrap();
-        let cdata = &img.pixels;
-        let rdata: Vec<u8> = cdata
-            .iter()
-            .map(|a| [a.b(), a.g(), a.r()])
-            .flatten()
-            .collect();
-        p.copy_from_slice(&rdata);
-        let _ = orig.copy_to(&mut m);
-        let mat = m;
-        let mut oimg: opencv::core::Mat = Default::default();
-        let cm: opencv::core::Mat = self[0].clone().into();
-        let dc: opencv::core::Mat = self[1].clone().into();
-        println!("CM: {:?}", cm);
-        println!("DC: {:?}", dc);
-        let a = opencv::calib3d::undistort(&mat, &mut oimg, &cm, &dc, &opencv::core::no_array());
-        println!("Applied calibration {:?}", a);
-        let data = oimg.data_bytes().unwrap();
-        let dims = [oimg.cols() as usize, oimg.rows() as usize];
-        let cimg = eframe::egui::ColorImage::from_rgb(dims, data);
-        cimg
+trait FrameSourceTrait {
+    /// Start producing frames, returns true on success
+    fn open(&mut self) -> bool;
+    /// Stop producing frames
+    fn close(&mut self);
+    fn is_open(&self) -> bool;
+    /// Get the next frame, if one is ready
+    fn get_image(&mut self) -> Option<opencv::core::Mat>;
+    /// Has the source stopped producing frames
+    fn failed(&self) -> bool;
+    /// Change how the source is opened, sources that can not be configured ignore this
+    fn configure(&mut self, _config: &presets::CameraConfig) {}
+    /// The zoom the source reports, None for sources without a zoom control
+    fn zoom(&self) -> Option<f64> {
+        None
     }
 }
 
+/// All of the kinds of frame sources
+#[enum_dispatch::enum_dispatch(FrameSourceTrait)]
 #[derive(Debug)]
-struct OpenCvCamera {
-    cam: Option<opencv::videoio::VideoCapture>,
-    i: i32,
-    height: Option<f64>,
-    width: Option<f64>,
+enum FrameSource {
+    OpenCvCamera(OpenCvCamera),
+    ScreenCapture(screen::ScreenCapture),
+    ThermalCamera(thermal::ThermalCamera),
+    #[cfg(feature = "genicam")]
+    GenicamCamera(genicam::GenicamCamera),
+    #[cfg(feature = "realsense")]
+    RealSenseStream(realsense::RealSenseStream),
 }
 
+/// The width in pixels of the board images that are saved and used as captures
+const BOARD_WIDTH: i32 = 2400;
+
+/// How long closing the program waits for the camera thread and for background tasks
+const SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(5);
+
 enum ToCameraThread {
-    ValidCamera(i32, OpenCvCamera),
+    ValidCamera(i32, FrameSource),
     OpenCamera(i32),
     CloseCamera(i32),
+    SetAveraging(i32, averaging::Averaging, bool),
+    Configure(i32, presets::CameraConfig),
+    SetBackpressure(settings::Backpressure),
     Quit,
 }
 
+impl ToCameraThread {
+    /// The camera the message is about, None for messages about the thread
+    fn camera(&self) -> Option<i32> {
+        match self {
+            ToCameraThread::ValidCamera(i, _)
+            | ToCameraThread::OpenCamera(i)
+            | ToCameraThread::CloseCamera(i)
+            | ToCameraThread::SetAveraging(i, ..)
+            | ToCameraThread::Configure(i, _) => Some(*i),
+            ToCameraThread::SetBackpressure(_) | ToCameraThread::Quit => None,
+        }
+    }
+}
+
 enum FromCameraThread {
-    CameraImage(i32, Box<opencv::core::Mat>),
+    /// A frame with the time taken to read it
+    CameraImage(i32, Frame, Duration),
+    /// A camera was opened (true) or closed (false)
+    CameraState(i32, bool),
+    /// A camera could not be opened, or stopped producing images and was closed
+    CameraFailed(i32),
+    /// The zoom a camera reports changed
+    Zoom(i32, f64),
+}
+
+/// Send a frame or event to the window, following the backpressure policy when the queue is full.
+/// own is a receiver of the same queue, for taking out the oldest frame.
+fn send_from_camera(
+    snd: &crossbeam::channel::Sender<FromCameraThread>,
+    own: &crossbeam::channel::Receiver<FromCameraThread>,
+    policy: settings::Backpressure,
+    m: FromCameraThread,
+) {
+    // Only frames are dropped, the window must hear about every camera that opens, closes or fails
+    if !matches!(m, FromCameraThread::CameraImage(..)) {
+        let _ = snd.send(m);
+        return;
+    }
+    match policy {
+        settings::Backpressure::Block => {
+            let _ = snd.send(m);
+        }
+        settings::Backpressure::DropNewest => {
+            let _ = snd.try_send(m);
+        }
+        settings::Backpressure::DropOldest => {
+            let mut m = m;
+            let mut kept = Vec::new();
+            while let Err(crossbeam::channel::TrySendError::Full(r)) = snd.try_send(m) {
+                m = r;
+                match own.try_recv() {
+                    Ok(FromCameraThread::CameraImage(..)) => {}
+                    // An event is sent again after the frame, so it is late but not lost
+                    Ok(e) => kept.push(e),
+                    Err(_) => {}
+                }
+            }
+            for e in kept {
+                let _ = snd.send(e);
+            }
+        }
+    }
 }
 
 fn live_camera_thread(
     rcv: crossbeam::channel::Receiver<ToCameraThread>,
     snd: crossbeam::channel::Sender<FromCameraThread>,
+    own: crossbeam::channel::Receiver<FromCameraThread>,
+    mut policy: settings::Backpressure,
 ) {
-    let mut live_cameras: BTreeMap<i32, OpenCvCamera> = BTreeMap::new();
+    let mut live_cameras: BTreeMap<i32, FrameSource> = BTreeMap::new();
+    let mut averagers: BTreeMap<i32, averaging::FrameAverager> = BTreeMap::new();
+    let mut zooms: BTreeMap<i32, f64> = BTreeMap::new();
     loop {
         if let Ok(a) = rcv.try_recv() {
             match a {
@@ -143,36 +235,146 @@ fn live_camera_thread(
                 }
                 ToCameraThread::OpenCamera(i) => {
                     if let Some(c) = live_cameras.get_mut(&i) {
-                        c.open();
+                        let m = if c.open() {
+                            FromCameraThread::CameraState(i, true)
+                        } else {
+                            FromCameraThread::CameraFailed(i)
+                        };
+                        let _ = snd.send(m);
                     }
                 }
                 ToCameraThread::CloseCamera(i) => {
                     if let Some(c) = live_cameras.get_mut(&i) {
                         c.close();
+                        let _ = snd.send(FromCameraThread::CameraState(i, false));
                     }
                 }
+                ToCameraThread::SetAveraging(i, a, linear) => {
+                    averagers
+                        .entry(i)
+                        .or_insert_with(|| averaging::FrameAverager::new(a, linear))
+                        .set_mode(a, linear);
+                }
+                ToCameraThread::Configure(i, config) => {
+                    if let Some(c) = live_cameras.get_mut(&i) {
+                        let was_open = c.is_open();
+                        c.configure(&config);
+                        if was_open && !c.is_open() {
+                            let _ = snd.send(FromCameraThread::CameraFailed(i));
+                        }
+                    }
+                }
+                ToCameraThread::SetBackpressure(p) => policy = p,
                 ToCameraThread::Quit => {
+                    for c in live_cameras.values_mut() {
+                        if c.is_open() {
+                            c.close();
+                        }
+                    }
                     break;
                 }
             }
         }
+        let mut idle = true;
         for (i, c) in &mut live_cameras {
             if c.is_open() {
+                let start = Instant::now();
                 let m = c.get_image();
-                if let Some(m) = m {
-                    let _ = snd.send(FromCameraThread::CameraImage(*i, Box::new(m)));
+                if let Some(mut m) = m {
+                    idle = false;
+                    if let Some(a) = averagers.get_mut(i) {
+                        m = a.process(m);
+                    }
+                    if let Some(z) = c.zoom() {
+                        if zooms.insert(*i, z) != Some(z) {
+                            let _ = snd.send(FromCameraThread::Zoom(*i, z));
+                        }
+                    }
+                    send_from_camera(
+                        &snd,
+                        &own,
+                        policy,
+                        FromCameraThread::CameraImage(*i, Frame::new(m), start.elapsed()),
+                    );
+                } else if c.failed() {
+                    c.close();
+                    let _ = snd.send(FromCameraThread::CameraFailed(*i));
                 }
             }
         }
+        if idle {
+            std::thread::sleep(Duration::from_millis(1));
+        }
     }
 }
 
 impl OpenCvCamera {
     fn new(i: i32) -> Option<Self> {
+        Self::with_source(i, None, None)
+    }
+
+    /// Find the camera devices that open, searching until several indexes in a row fail.
+    /// The cameras are closed again.
+    fn detect() -> Vec<Self> {
+        let mut cameras = Vec::new();
+        let mut consecutive_fail = 0;
+        for i in 0.. {
+            if let Some(mut c) = OpenCvCamera::new(i) {
+                consecutive_fail = 0;
+                c.close();
+                cameras.push(c);
+            } else {
+                consecutive_fail += 1;
+            }
+            if consecutive_fail == 5 {
+                break;
+            }
+        }
+        cameras
+    }
+
+    /// Play a video file as if it were a camera
+    fn new_file(i: i32, file: &Path) -> Option<Self> {
+        Self::with_source(i, Some(file.to_path_buf()), None)
+    }
+
+    /// Read frames from the appsink at the end of a gstreamer pipeline
+    #[cfg_attr(not(target_os = "linux"), allow(dead_code))]
+    fn new_gstreamer(i: i32, pipeline: String) -> Option<Self> {
+        Self::with_source(i, None, Some(pipeline))
+    }
+
+    /// Set the resolution and exposure of the configuration on an opened device
+    fn apply_config(&self, c: &mut opencv::videoio::VideoCapture) {
+        use opencv::videoio::VideoCaptureProperties;
+        if let Some([w, h]) = self.config.resolution {
+            let _ = c.set(
+                VideoCaptureProperties::CAP_PROP_FRAME_WIDTH as i32,
+                w as f64,
+            );
+            let _ = c.set(
+                VideoCaptureProperties::CAP_PROP_FRAME_HEIGHT as i32,
+                h as f64,
+            );
+        }
+        if let Some(e) = self.config.exposure {
+            // 0.25 selects manual exposure on most backends
+            let _ = c.set(VideoCaptureProperties::CAP_PROP_AUTO_EXPOSURE as i32, 0.25);
+            let _ = c.set(VideoCaptureProperties::CAP_PROP_EXPOSURE as i32, e);
+        }
+    }
+
+    fn with_source(i: i32, file: Option<PathBuf>, pipeline: Option<String>) -> Option<Self> {
         use opencv::videoio::VideoCaptureTraitConst;
         let mut s = Self {
             cam: None,
             i,
+            file,
+            pipeline,
+            config: Default::default(),
+            frame_interval: None,
+            last_frame: None,
+            failures: 0,
             height: None,
             width: None,
         };
@@ -185,13 +387,48 @@ impl OpenCvCamera {
                 s.height = cam
                     .get(opencv::videoio::VideoCaptureProperties::CAP_PROP_FRAME_HEIGHT as i32)
                     .ok();
+                if s.file.is_some() {
+                    let fps = cam
+                        .get(opencv::videoio::VideoCaptureProperties::CAP_PROP_FPS as i32)
+                        .unwrap_or(30.0);
+                    let fps = if fps > 0.0 { fps } else { 30.0 };
+                    s.frame_interval = Some(Duration::from_secs_f64(1.0 / fps));
+                }
             }
         }
         s
     }
+}
+
+impl FrameSourceTrait for OpenCvCamera {
+    fn zoom(&self) -> Option<f64> {
+        use opencv::videoio::VideoCaptureTraitConst;
+        if self.file.is_some() || self.pipeline.is_some() {
+            return None;
+        }
+        // Backends give a negative value for cameras without a zoom control
+        self.cam
+            .as_ref()?
+            .get(opencv::videoio::VideoCaptureProperties::CAP_PROP_ZOOM as i32)
+            .ok()
+            .filter(|z| *z >= 0.0)
+    }
+
+    fn configure(&mut self, config: &presets::CameraConfig) {
+        self.config = config.clone();
+        if self.is_open() {
+            self.close();
+            self.open();
+        }
+    }
 
     fn close(&mut self) {
         self.cam = None;
+        self.failures = 0;
+    }
+
+    fn failed(&self) -> bool {
+        self.failures >= 30
     }
 
     fn is_open(&self) -> bool {
@@ -200,11 +437,32 @@ impl OpenCvCamera {
 
     fn get_image(&mut self) -> Option<opencv::core::Mat> {
         use opencv::videoio::VideoCaptureTrait;
+        if let (Some(interval), Some(last)) = (self.frame_interval, self.last_frame) {
+            if last.elapsed() < interval {
+                return None;
+            }
+        }
         if let Some(c) = &mut self.cam {
             let mut mat = opencv::core::Mat::default();
             if let Ok(true) = c.read(&mut mat) {
-                Some(mat)
+                self.last_frame = Some(Instant::now());
+                self.failures = 0;
+                // Backends give monochrome cameras three identical channels
+                if self.config.monochrome && mat.channels() == 3 {
+                    image_proc::calibration::to_gray(&mat).ok()
+                } else {
+                    Some(mat)
+                }
+            } else if self.file.is_some() {
+                // Loop video files back to the start
+                let _ = c.set(
+                    opencv::videoio::VideoCaptureProperties::CAP_PROP_POS_FRAMES as i32,
+                    0.0,
+                );
+                self.last_frame = Some(Instant::now());
+                None
             } else {
+                self.failures += 1;
                 None
             }
         } else {
@@ -214,10 +472,33 @@ impl OpenCvCamera {
 
     fn open(&mut self) -> bool {
         if self.cam.is_none() {
-            if let Ok(mut c) = opencv::videoio::VideoCapture::new(self.i, opencv::videoio::CAP_ANY)
+            if let Some(f) = &self.file {
+                use opencv::videoio::VideoCaptureTraitConst;
+                if let Ok(c) = opencv::videoio::VideoCapture::from_file(
+                    &f.to_string_lossy(),
+                    opencv::videoio::CAP_ANY,
+                ) {
+                    if let Ok(true) = c.is_opened() {
+                        self.cam = Some(c);
+                    }
+                }
+                self.cam.is_some()
+            } else if let Some(p) = &self.pipeline {
+                use opencv::videoio::VideoCaptureTraitConst;
+                if let Ok(c) =
+                    opencv::videoio::VideoCapture::from_file(p, opencv::videoio::CAP_GSTREAMER)
+                {
+                    if let Ok(true) = c.is_opened() {
+                        self.cam = Some(c);
+                    }
+                }
+                self.cam.is_some()
+            } else if let Ok(mut c) =
+                opencv::videoio::VideoCapture::new(self.i, self.config.backend.api())
             {
-                let r = c.open(self.i, opencv::videoio::CAP_ANY);
+                let r = c.open(self.i, self.config.backend.api());
                 if let Ok(true) = r {
+                    self.apply_config(&mut c);
                     self.cam = Some(c);
                     true
                 } else {
@@ -232,478 +513,2991 @@ impl OpenCvCamera {
     }
 }
 
+/// The processing of the images of a camera, each camera has its own
+#[derive(Clone)]
+struct CameraProcessing {
+    pipeline: pipeline::Pipeline,
+    cd: Option<CalibrationData>,
+    /// The file the calibration was loaded from or saved to
+    cd_path: Option<PathBuf>,
+    apply_cd: bool,
+    calibration_rms: Option<f64>,
+    cd_resolution: Option<[u32; 2]>,
+}
+
 struct MainData {
     scale: Vec<f64>,
+    raw_image: Option<eframe::egui::ColorImage>,
+    /// The camera image before undistortion, when it was undistorted, for the difference view.
+    /// It is in rgb like the undistorted image, so the difference is only what undistortion and processing changed.
+    original_image: Option<eframe::egui::ColorImage>,
+    /// The amplification of the difference view
+    difference_gain: f32,
     actual_image: Option<eframe::egui::ColorImage>,
     img: Option<eframe::egui::TextureHandle>,
     corrected_img: Option<eframe::egui::TextureHandle>,
+    /// Detected corners drawn over the preview
+    detections: detections::DetectionOverlay,
+    /// Screen pixels per image pixel of the preview as last shown, for choosing how its texture is filtered
+    preview_scale: f32,
     live_cameras: BTreeSet<i32>,
     selected_camera: Option<i32>,
-    charuco_images: Vec<opencv::core::Mat>,
-    charuco_board: opencv::core::Ptr<opencv::aruco::CharucoBoard>,
-    _image_thread: JoinHandle<()>,
-    image_set: BTreeMap<i32, Box<opencv::core::Mat>>,
+    charuco_images: Vec<Frame>,
+    charuco_board: image_proc::aruco::CharucoBoard,
+    /// The camera thread, taken when joining it on exit
+    image_thread: Option<JoinHandle<()>>,
+    image_set: BTreeMap<i32, Frame>,
     to_image_thread: crossbeam::channel::Sender<ToCameraThread>,
+    /// Messages for the camera thread waiting for room in its queue, oldest first
+    to_camera_pending: VecDeque<ToCameraThread>,
     from_image_thread: crossbeam::channel::Receiver<FromCameraThread>,
     cd: Option<CalibrationData>,
+    /// The file the calibration was loaded from or saved to, checks of the calibration are logged in its metadata
+    cd_path: Option<PathBuf>,
     apply_cd: bool,
+    /// The width and height of the images the calibration was made with, None when not known
+    cd_resolution: Option<[u32; 2]>,
+    /// Scale the calibration to images of a different resolution instead of not applying it
+    scale_cd: bool,
+    /// The processing of the cameras that are not selected, the selected camera uses pipeline, cd and apply_cd
+    processing: BTreeMap<Option<i32>, CameraProcessing>,
+    /// The camera that pipeline, cd and apply_cd belong to
+    processing_camera: Option<i32>,
+    view_mode: colormap::ViewMode,
+    comparison: compare::ImageComparison,
+    show_comparison: bool,
+    flicker: flicker::FlickerDetector,
+    show_flicker: bool,
+    profiles: BTreeMap<i32, profile::CameraProfile>,
+    noise: noise::NoiseProfiler,
+    show_noise: bool,
+    averaging: averaging::Averaging,
+    pipeline: pipeline::Pipeline,
+    show_pipeline: bool,
+    rolling_shutter: rolling_shutter::RollingShutterTool,
+    show_rolling_shutter: bool,
+    undistort: undistort::UndistortTool,
+    show_undistort: bool,
+    projection: projection::ProjectionPanel,
+    show_projection: bool,
+    stereo: stereo_rig::StereoRig,
+    show_stereo: bool,
+    burst: burst::BurstCapture,
+    show_burst: bool,
+    ring_buffer: ring_buffer::RingBuffer,
+    show_ring_buffer: bool,
+    motion: motion::MotionTrigger,
+    show_motion: bool,
+    /// The viewer for images too large for a single texture
+    large_image: large_image::LargeImageViewer,
+    show_large_image: bool,
+    vignetting: vignetting::VignettingTool,
+    show_vignetting: bool,
+    gray_card: gray_card::GrayCardTool,
+    show_gray_card: bool,
+    profiler: profiler::FrameProfiler,
+    show_profiler: bool,
+    /// The reprojection residuals of the last calibration
+    residuals: residuals::ResidualPlot,
+    show_residuals: bool,
+    /// Distortion coefficients changed by hand for the preview
+    distortion_explorer: sensitivity::DistortionExplorer,
+    show_distortion_explorer: bool,
+    /// Checking the calibration against lines known to be straight
+    straightness: straightness::StraightLineTool,
+    show_straightness: bool,
+    /// Checking the calibration against a distance measured by hand
+    distance: distance::DistanceTool,
+    show_distance: bool,
+    /// Editing the windows of the sensor the capture modes of the camera stream
+    show_sensor_modes: bool,
+    /// Calibrations of a zoom lens at several zoom positions
+    zoom_lens: zoom_lens::ZoomLens,
+    show_zoom_lens: bool,
+    /// The temperature recorded with calibrations
+    temperature: temperature::TemperatureInput,
+    /// The preview paused on one frame
+    freeze: freeze::Freeze,
+    /// The scale the preview is shrunk to while showing frames is too slow
+    adaptive_preview: profiler::AdaptivePreview,
+    annotations: annotation::AnnotationTool,
+    /// The preview is shown in its own native window instead of the main window
+    detached_preview: bool,
+    /// Only the processed image is shown, fullscreen
+    kiosk: bool,
+    clipboard: Option<arboard::Clipboard>,
+    settings: settings::Settings,
+    videos: BTreeMap<i32, PathBuf>,
+    /// The names of sources that are not numbered cameras, like screen captures, by camera id
+    source_names: BTreeMap<i32, String>,
+    screen_dialog: screen::ScreenCaptureDialog,
+    presets: presets::PresetBar,
+    show_screen_capture: bool,
+    show_settings: bool,
+    calibration_rms: Option<f64>,
+    /// The calibration running in the background
+    calibration_task: Option<calibration_task::CalibrationTask>,
+    wizard: wizard::Wizard,
+    show_wizard: bool,
+    capture_next: bool,
+    toasts: status::Toasts,
+    /// The long running tasks that can be cancelled
+    tasks: status::BackgroundTasks,
+    /// The database of completed calibrations, None when it could not be opened
+    history: Option<history::CalibrationHistory>,
+    show_history: bool,
+    /// The log of significant actions, None when it could not be opened
+    audit: Option<audit::AuditLog>,
+    show_audit: bool,
+    /// Saves the captures and calibration from time to time, for restoring them after a crash
+    recovery: recovery::Autosave,
+    /// A session that was not closed normally, offered for restoring until the user decides
+    recovery_offer: Option<recovery::Manifest>,
+    /// How long ago each camera was calibrated, for reminding to recalibrate
+    reminders: reminders::Reminders,
+    show_review: bool,
+    review: review::CalibrationReview,
+    watch: watch::WatchFolder,
+    show_watch: bool,
+    doctor: doctor::Doctor,
+    show_doctor: bool,
+    /// Results of tasks running in the background, like backups
+    task_done: (
+        crossbeam::channel::Sender<status::TaskResult>,
+        crossbeam::channel::Receiver<status::TaskResult>,
+    ),
+    /// The depth images of the depth sources, by camera id
+    depth_maps: BTreeMap<i32, depth::SharedDepth>,
+    /// The thermal images of the thermal cameras, by camera id
+    thermal_maps: BTreeMap<i32, thermal::SharedThermal>,
+    /// The calibration board is heated, so it is inverted in thermal images before detecting it
+    heated_board: bool,
+    feedback: feedback::CaptureFeedback,
+    /// The color transforms for the preview and exported images
+    color: color_management::ColorManager,
+    gamepads: gamepad::Gamepads,
+    /// Cameras that were just opened, their stored calibration is loaded when the first image shows the resolution
+    auto_load: BTreeSet<i32>,
+    /// The pixel of the preview image under the mouse
+    cursor_pixel: Option<[usize; 2]>,
+    /// The cameras that are currently open
+    open_cameras: BTreeSet<i32>,
+    frame_rates: BTreeMap<i32, status::FrameRate>,
+    /// The backpressure policy the camera thread was last told to use
+    backpressure: settings::Backpressure,
 }
 
 impl MainData {
-    fn new(_cc: &CreationContext) -> Self {
-        let to_thread = crossbeam::channel::bounded(5);
-        let from_thread = crossbeam::channel::bounded(5);
-        let t = std::thread::spawn(|| live_camera_thread(to_thread.1, from_thread.0));
-        let cboard = make_charuco_board().unwrap();
+    fn new(cc: &CreationContext) -> Self {
+        let mut settings = settings::Settings::load(cc.storage);
+        let queues = &settings.queues;
+        let to_thread = crossbeam::channel::bounded(queues.to_camera.max(1));
+        let from_thread = crossbeam::channel::bounded(queues.from_camera.max(1));
+        let (rcv, snd, own) = (to_thread.1, from_thread.0, from_thread.1.clone());
+        let backpressure = queues.backpressure;
+        let t = std::thread::spawn(move || live_camera_thread(rcv, snd, own, backpressure));
+        let cboard = if let Some(b) = settings.board.make_board() {
+            b
+        } else {
+            settings.board = Default::default();
+            settings.board.make_board().unwrap()
+        };
+        settings.appearance.apply(&cc.egui_ctx);
+        let mut toasts = status::Toasts::default();
+        let history = history::CalibrationHistory::open(&settings.output.working_directory)
+            .map_err(|e| toasts.error(tr!("error.open_history", error = format!("{:?}", e))))
+            .ok();
+        let audit = audit::AuditLog::open(&settings.output.working_directory)
+            .map_err(|e| toasts.error(tr!("error.open_audit", error = format!("{:?}", e))))
+            .ok();
+        let recovery = recovery::Autosave::new(&settings.output.working_directory);
+        let reminders = reminders::Reminders::new(&settings.output.working_directory);
         Self {
             scale: vec![0.0; 32],
+            raw_image: None,
+            original_image: None,
+            difference_gain: 8.0,
             actual_image: None,
             img: None,
             corrected_img: None,
+            detections: Default::default(),
+            preview_scale: 1.0,
             live_cameras: BTreeSet::new(),
             selected_camera: None,
             charuco_images: Vec::new(),
             charuco_board: cboard,
-            _image_thread: t,
+            image_thread: Some(t),
             image_set: BTreeMap::new(),
             to_image_thread: to_thread.0,
+            to_camera_pending: VecDeque::new(),
             from_image_thread: from_thread.1,
             cd: None,
+            cd_path: None,
             apply_cd: true,
+            cd_resolution: None,
+            scale_cd: false,
+            processing: BTreeMap::new(),
+            processing_camera: None,
+            view_mode: colormap::ViewMode::Normal,
+            comparison: Default::default(),
+            show_comparison: false,
+            flicker: Default::default(),
+            show_flicker: false,
+            profiles: BTreeMap::new(),
+            noise: Default::default(),
+            show_noise: false,
+            averaging: averaging::Averaging::Off,
+            pipeline: Default::default(),
+            show_pipeline: false,
+            rolling_shutter: Default::default(),
+            show_rolling_shutter: false,
+            undistort: Default::default(),
+            show_undistort: false,
+            projection: Default::default(),
+            show_projection: false,
+            stereo: Default::default(),
+            show_stereo: false,
+            burst: Default::default(),
+            show_burst: false,
+            ring_buffer: Default::default(),
+            show_ring_buffer: false,
+            motion: Default::default(),
+            show_motion: false,
+            large_image: Default::default(),
+            show_large_image: false,
+            vignetting: Default::default(),
+            show_vignetting: false,
+            gray_card: Default::default(),
+            show_gray_card: false,
+            profiler: Default::default(),
+            show_profiler: false,
+            residuals: Default::default(),
+            show_residuals: false,
+            distortion_explorer: Default::default(),
+            show_distortion_explorer: false,
+            straightness: Default::default(),
+            show_straightness: false,
+            distance: Default::default(),
+            show_distance: false,
+            show_sensor_modes: false,
+            zoom_lens: Default::default(),
+            show_zoom_lens: false,
+            temperature: Default::default(),
+            freeze: Default::default(),
+            adaptive_preview: Default::default(),
+            annotations: Default::default(),
+            detached_preview: false,
+            kiosk: false,
+            clipboard: None,
+            settings,
+            videos: BTreeMap::new(),
+            source_names: BTreeMap::new(),
+            presets: Default::default(),
+            screen_dialog: Default::default(),
+            show_screen_capture: false,
+            show_settings: false,
+            calibration_rms: None,
+            calibration_task: None,
+            wizard: Default::default(),
+            show_wizard: false,
+            capture_next: false,
+            toasts,
+            tasks: Default::default(),
+            history,
+            show_history: false,
+            audit,
+            show_audit: false,
+            recovery_offer: recovery.pending(),
+            recovery,
+            reminders,
+            show_review: false,
+            review: Default::default(),
+            watch: Default::default(),
+            show_watch: false,
+            doctor: Default::default(),
+            show_doctor: false,
+            task_done: crossbeam::channel::unbounded(),
+            depth_maps: BTreeMap::new(),
+            thermal_maps: BTreeMap::new(),
+            heated_board: false,
+            feedback: Default::default(),
+            color: Default::default(),
+            gamepads: Default::default(),
+            auto_load: BTreeSet::new(),
+            cursor_pixel: None,
+            open_cameras: BTreeSet::new(),
+            frame_rates: BTreeMap::new(),
+            backpressure,
         }
     }
 
-    fn detect_cameras(&mut self) {
-        let mut consecutive_fail = 0;
-        for i in 0.. {
-            if let Some(mut c) = OpenCvCamera::new(i) {
-                consecutive_fail = 0;
-                c.close();
-                let _ = self.to_image_thread.send(ToCameraThread::ValidCamera(i, c));
-                self.live_cameras.insert(i);
-            } else {
-                consecutive_fail += 1;
-            }
-            if consecutive_fail == 5 {
-                break;
+    /// Add an event to the audit log
+    fn audit(&mut self, event: audit::Event) {
+        if let Some(a) = &mut self.audit {
+            if let Err(e) = a.record(event) {
+                self.toasts
+                    .error(tr!("error.record_audit", error = format!("{:?}", e)));
             }
         }
-        println!("Found {} cameras", self.live_cameras.len());
     }
 
-    fn make_charuco_mat(&mut self) -> opencv::core::Mat {
-        let mut pic = opencv::core::Mat::default();
-        opencv::aruco::CharucoBoardTrait::draw(
-            &mut self.charuco_board,
-            opencv::core::Size {
-                width: 2400,
-                height: 2400,
-            },
-            &mut pic,
-            10,
-            1,
-        )
-        .unwrap();
-        pic
+    /// Send a message to the camera thread without waiting, telling the user if it has gone away.
+    /// While its queue is full messages wait in order, the window must not block on a camera thread blocked on it.
+    fn send_to_camera_thread(&mut self, m: ToCameraThread) {
+        // Frames from a camera keep arriving until it is closed, one request to close it is enough
+        if let ToCameraThread::CloseCamera(i) = m {
+            let last = self
+                .to_camera_pending
+                .iter()
+                .rev()
+                .find(|p| p.camera() == Some(i));
+            if matches!(last, Some(ToCameraThread::CloseCamera(_))) {
+                return;
+            }
+        }
+        self.to_camera_pending.push_back(m);
+        self.flush_to_camera_thread();
     }
 
-    fn save_charuco_image(&mut self) {
-        println!("Saving charuco board");
-        let pic = self.make_charuco_mat();
-        let _ = opencv::imgcodecs::imwrite("./charuco.png", &pic, &opencv::core::Vector::new());
-    }
-
-    fn calibrate_camera(&mut self, _i: i32) -> Result<(), ()> {
-        let d = get_charuco_dictionary().ok_or(())?;
-        if self.charuco_images.is_empty() {
-            return Err(());
-        }
-        let mut camera_matrix: opencv::core::Mat = Default::default();
-        let mut dist_coeffs: opencv::core::Mat = Default::default();
-        let mut all_corners: opencv::core::Vector<opencv::core::Vector<opencv::core::Point2f>> =
-            Default::default();
-        let mut all_corners_a: opencv::core::Vector<opencv::core::Point2f> = Default::default();
-        let mut all_ids: opencv::core::Vector<opencv::core::Vector<i32>> = Default::default();
-        let mut all_ids_a: opencv::core::Vector<i32> = Default::default();
-        println!("Calibrating with {} images", self.charuco_images.len());
-        for img in &self.charuco_images {
-            let mut corners: opencv::core::Vector<opencv::core::Vector<opencv::core::Point2f>> =
-                Default::default();
-            let mut a: opencv::core::Vector<opencv::core::Point2f> = Default::default();
-            a.push(Default::default());
-            a.push(Default::default());
-            a.push(Default::default());
-            a.push(Default::default());
-            let num_things = 81;
-            for _ in 0..num_things {
-                corners.push(a.clone());
-            }
-            let mut ids: opencv::core::Vector<i32> = Default::default();
-            for _ in 0..num_things {
-                ids.push(0);
-            }
-            let a = opencv::aruco::detect_markers_def(&img, &d, &mut corners, &mut ids);
-            for r in &corners {
-                println!("Accepted corner: {:?}", r);
-            }
-            println!("There are {} corners, {} ids", corners.len(), ids.len());
-            for id in &ids {
-                println!("ID: {}", id);
-            }
-            if a.is_ok() {
-                println!("Detect markers is ok");
-                let mut charuco_corners: opencv::core::Mat = Default::default();
-                let mut charuco_ids: opencv::core::Mat = Default::default();
-                let b = opencv::aruco::interpolate_corners_charuco_def(
-                    &corners,
-                    &ids,
-                    img,
-                    &self.charuco_board,
-                    &mut charuco_corners,
-                    &mut charuco_ids,
-                )
-                .map_err(|_| ())?;
-                println!("Interpolate retured {}", b);
-                println!(
-                    "charuco: There are {} corners, {} ids",
-                    charuco_corners.rows(),
-                    charuco_ids.rows()
-                );
-                println!(
-                    "Charuco corner element size {:?}",
-                    charuco_corners.elem_size()
-                );
-                let cc: Vec<Vec<opencv::core::Point2f>> = charuco_corners.to_vec_2d().unwrap();
-                for i in cc {
-                    println!("charuco corner: {:?}", i);
-                    all_corners_a.push(i[0]);
+    /// Send the messages waiting for room in the queue to the camera thread, as many as fit
+    fn flush_to_camera_thread(&mut self) {
+        while let Some(m) = self.to_camera_pending.pop_front() {
+            match self.to_image_thread.try_send(m) {
+                Ok(()) => {}
+                Err(crossbeam::channel::TrySendError::Full(m)) => {
+                    self.to_camera_pending.push_front(m);
+                    break;
                 }
-                let cc: Vec<Vec<i32>> = charuco_ids.to_vec_2d().unwrap();
-                for i in cc {
-                    println!("charuco id: {:?}", i);
-                    all_ids_a.push(i[0]);
+                Err(crossbeam::channel::TrySendError::Disconnected(_)) => {
+                    self.to_camera_pending.clear();
+                    self.toasts.error(tr!("error.camera_thread"));
+                    break;
                 }
             }
         }
-        all_corners.push(all_corners_a);
-        all_ids.push(all_ids_a);
-        let criteria = opencv::core::TermCriteria {
-            typ: opencv::core::TermCriteria_Type::EPS as i32
-                + opencv::core::TermCriteria_Type::COUNT as i32,
-            max_count: 30,
-            epsilon: 0.1,
-        };
-        let size = opencv::core::Size {
-            width: self.charuco_images[0].cols(),
-            height: self.charuco_images[0].rows(),
-        };
-        println!("Size is {:?}", size);
-        let c = opencv::aruco::calibrate_camera_charuco(
-            &all_corners,
-            &all_ids,
-            &self.charuco_board,
-            size,
-            &mut camera_matrix,
-            &mut dist_coeffs,
-            &mut opencv::core::no_array(),
-            &mut opencv::core::no_array(),
-            0,
-            criteria,
-        );
-        println!(
-            "Calibrate returned {:?} {:?} {:?}",
-            c, camera_matrix, dist_coeffs
+    }
+
+    /// Rebuild the color transforms when the color settings changed
+    fn update_color(&mut self) {
+        if let Some(e) = self.color.update(&self.settings.color) {
+            self.toasts.error(tr!("error.color_profile", error = e));
+        }
+    }
+
+    /// Set the image to display, running it through the pipeline and converting it according to the current view mode
+    fn set_image(&mut self, ctx: &eframe::egui::Context, cimg: ColorImage) {
+        let start = Instant::now();
+        self.pipeline.analyze(&cimg);
+        let processed = self.pipeline.process(cimg.clone());
+        let original = self.original_image.as_ref().unwrap_or(&cimg);
+        let shown =
+            self.view_mode
+                .apply_with_original(processed.clone(), original, self.difference_gain);
+        self.update_color();
+        let shown = self.color.display(shown);
+        self.profiler
+            .record(profiler::Stage::Curve, start.elapsed());
+        let start = Instant::now();
+        // The texture is replaced in place, so the preview keeps the same texture from frame to frame
+        let options = dpi::texture_options(self.preview_scale);
+        match &mut self.img {
+            Some(t) => t.set(shown, options),
+            None => self.img = Some(ctx.load_texture("actual_image", shown, options)),
+        }
+        self.profiler
+            .record(profiler::Stage::Upload, start.elapsed());
+        self.raw_image.replace(cimg);
+        self.actual_image.replace(processed);
+    }
+
+    /// The image as it is displayed, after processing, view mode and annotations
+    fn displayed_image(&self) -> Option<ColorImage> {
+        let original = self.original_image.as_ref().or(self.raw_image.as_ref())?;
+        let img = self.view_mode.apply_with_original(
+            self.actual_image.clone()?,
+            original,
+            self.difference_gain,
         );
-        let cm: SaveableOpencvMat = camera_matrix.into();
-        let dc: SaveableOpencvMat = dist_coeffs.into();
-        let cd = CalibrationData::OpenCvCharuco([cm, dc]);
-        let data = bincode::serde::encode_to_vec(&cd, bincode::config::standard());
-        if let Ok(data) = data {
-            let mut f = std::fs::File::create("./test.bin").unwrap();
-            f.write_all(&data).unwrap();
+        Some(self.annotations.flatten_visible(img))
+    }
+
+    /// Place the displayed image on the system clipboard
+    fn copy_view(&mut self) -> Result<(), arboard::Error> {
+        let Some(img) = self.displayed_image() else {
+            return Ok(());
+        };
+        let bytes: Vec<u8> = img
+            .pixels
+            .iter()
+            .flat_map(|p| p.to_srgba_unmultiplied())
+            .collect();
+        // The clipboard is kept around because on some platforms the contents vanish when it is dropped
+        if self.clipboard.is_none() {
+            self.clipboard = Some(arboard::Clipboard::new()?);
+        }
+        if let Some(c) = &mut self.clipboard {
+            c.set_image(arboard::ImageData {
+                width: img.width(),
+                height: img.height(),
+                bytes: bytes.into(),
+            })?;
         }
-        self.cd = Some(cd);
         Ok(())
     }
 
-    fn check_charuco_image(
-        &self,
-        img: &opencv::core::Mat,
-        debug: Option<&mut opencv::core::Mat>,
-    ) -> i32 {
-        if let Some(d) = get_charuco_dictionary() {
-            let mut corners: opencv::core::Vector<opencv::core::Vector<opencv::core::Point2f>> =
-                Default::default();
-            let mut a: opencv::core::Vector<opencv::core::Point2f> = Default::default();
-            a.push(opencv::core::Point2f::new(1.0, 2.0));
-            a.push(opencv::core::Point2f::new(1.0, 2.0));
-            a.push(opencv::core::Point2f::new(1.0, 2.0));
-            a.push(opencv::core::Point2f::new(1.0, 2.0));
-            let num_things = 81;
-            for _ in 0..num_things {
-                corners.push(a.clone());
-            }
-            let mut ids: opencv::core::Vector<i32> = Default::default();
-            for _ in 0..num_things {
-                ids.push(1);
-            }
-            let mut rejected: opencv::core::Vector<opencv::core::Vector<opencv::core::Point2f>> =
-                Default::default();
-            let mut a: opencv::core::Vector<opencv::core::Point2f> = Default::default();
-            a.push(opencv::core::Point2f::new(1.0, 2.0));
-            a.push(opencv::core::Point2f::new(1.0, 2.0));
-            a.push(opencv::core::Point2f::new(1.0, 2.0));
-            a.push(opencv::core::Point2f::new(1.0, 2.0));
-            for _ in 0..num_things {
-                rejected.push(a.clone());
-            }
-            if debug.is_some() {
-                for r in &rejected {
-                    println!("Rejected point {:?}", r);
-                }
-                for r in &corners {
-                    println!("Accepted corner: {:?}", r);
-                }
-                for i in &ids {
-                    println!("ID {}", i)
-                }
-            }
-            let a = opencv::aruco::detect_markers(
-                img,
-                &d,
-                &mut corners,
-                &mut ids,
-                &opencv::aruco::DetectorParameters::create().unwrap(),
-                &mut rejected,
-            );
-            if debug.is_some() {
-                println!("Detect markers: {:?} {} {}", a, corners.len(), ids.len());
-            }
-            if a.is_ok() {
-                let mut charuco_corners: opencv::core::Mat = Default::default();
-                let mut charuco_ids: opencv::core::Mat = Default::default();
-                let mut vimgs: opencv::core::Vector<opencv::core::Mat> =
-                    opencv::core::Vector::new();
-                vimgs.push(img.to_owned());
-                let b = opencv::aruco::interpolate_corners_charuco_def(
-                    &corners,
-                    &ids,
-                    img,
-                    &self.charuco_board,
-                    &mut charuco_corners,
-                    &mut charuco_ids,
-                );
-                if debug.is_some() {
-                    println!("interpolate is {:?}", b);
-                }
-                if b.is_ok() {
-                    if let Some(debug) = debug {
-                        let test = opencv::aruco::draw_detected_corners_charuco(
-                            debug,
-                            &charuco_corners,
-                            &opencv::core::no_array(),
-                            opencv::core::VecN([255.0, 0.0, 0.0, 255.0]),
-                        );
-                        println!("Test is {:?}", test);
-                        println!("Charuco corners channels {}", debug.channels());
-                        let asdf = opencv::imgcodecs::imwrite(
-                            "./charuco_corners.png",
-                            debug,
-                            &opencv::core::Vector::new(),
-                        );
-                        println!("Result of saving charuco corners {:?}", asdf);
-                    }
+    /// Ask the user for a file and save the original and processed image to it, side by side or as an animated gif
+    fn export_montage(&mut self) {
+        let (Some(before), Some(after)) = (
+            self.original_image.clone().or(self.raw_image.clone()),
+            self.actual_image.clone(),
+        ) else {
+            return;
+        };
+        let Some(f) = rfd::FileDialog::new()
+            .add_filter("PNG", &["png"])
+            .add_filter("JPEG", &["jpg", "jpeg"])
+            .add_filter("Animated GIF", &["gif"])
+            .set_directory("./")
+            .save_file()
+        else {
+            return;
+        };
+        let labels = [tr!("main.montage_before"), tr!("main.montage_after")];
+        let labels = [labels[0].as_str(), labels[1].as_str()];
+        let r = if f.extension().is_some_and(|e| e.eq_ignore_ascii_case("gif")) {
+            montage::save_gif(&f, &before, &after, labels, 1000)
+        } else {
+            self.update_color();
+            montage::side_by_side(&before, &after, labels)
+                .map_err(|e| e.to_string())
+                .and_then(|img| self.color.save(&f, &img).map_err(|e| e.to_string()))
+        };
+        match r {
+            Ok(()) => {
+                self.audit(audit::Event::FileWritten {
+                    kind: audit::FileKind::Montage,
+                    path: f.clone(),
+                });
+                self.toasts
+                    .info(tr!("info.exported_montage", path = f.display()))
+            }
+            Err(e) => self.toasts.error(tr!("error.export_montage", error = e)),
+        };
+    }
+
+    /// Ask the user for a file and save the displayed image to it at full resolution
+    fn export_view(&mut self) {
+        let Some(img) = self.displayed_image() else {
+            return;
+        };
+        let f = rfd::FileDialog::new()
+            .add_filter("PNG", &["png"])
+            .add_filter("JPEG", &["jpg", "jpeg"])
+            .set_directory("./")
+            .save_file();
+        if let Some(f) = f {
+            self.update_color();
+            match self.color.save(&f, &img) {
+                Ok(()) => {
+                    self.write_sidecar(&f, self.selected_camera, true);
+                    self.audit(audit::Event::FileWritten {
+                        kind: audit::FileKind::View,
+                        path: f.clone(),
+                    });
+                    self.feedback.captured(&self.settings.feedback);
+                    self.toasts
+                        .info(tr!("info.exported_view", path = f.display()))
                 }
-                if let Ok(b) = b { b } else { 0 }
+                Err(e) => self
+                    .toasts
+                    .error(tr!("error.export_view", error = format!("{:?}", e))),
+            }
+        }
+    }
+
+    /// Write the json file recording how a saved image was produced, when enabled.
+    /// processed is true when the image went through the calibration and pipeline.
+    fn write_sidecar(&mut self, image: &Path, camera: Option<i32>, processed: bool) {
+        if !self.settings.output.sidecars {
+            return;
+        }
+        let metadata = sidecar::FrameMetadata {
+            timestamp: chrono::Local::now().to_rfc3339(),
+            camera,
+            source: camera.map(|c| self.source_name(c)).unwrap_or_default(),
+            device: camera.and_then(profile::CameraProfile::device_identity),
+            exposure: sidecar::ExposureSettings {
+                camera: self.presets.camera.exposure,
+                averaging: self.averaging,
+                linear_averaging: self.pipeline.linear,
+            },
+            calibration: self
+                .cd
+                .as_ref()
+                .filter(|_| processed && self.original_image.is_some())
+                .map(|cd| cd.id()),
+            pipeline: processed.then(|| self.pipeline.description()),
+            view: if processed {
+                format!("{:?}", self.view_mode)
             } else {
-                0
+                format!("{:?}", colormap::ViewMode::Normal)
+            },
+            color_profile: self.settings.color.export_profile.clone(),
+        };
+        if let Err(e) = metadata.save(image) {
+            self.toasts
+                .error(tr!("error.save_sidecar", error = format!("{:?}", e)));
+        }
+    }
+
+    /// Save a still made from the frames of a camera to the output directory
+    fn save_still(&mut self, camera: i32, still: &ColorImage) {
+        self.update_color();
+        let output = &self.settings.output;
+        let r = output
+            .create_unique(&output.still_template, Some(camera))
+            .map_err(|e| format!("{:?}", e))
+            .and_then(|path| {
+                self.color
+                    .save(&path, still)
+                    .map(|_| path)
+                    .map_err(|e| format!("{:?}", e))
+            });
+        match r {
+            Ok(path) => {
+                self.write_sidecar(&path, Some(camera), false);
+                self.audit(audit::Event::FileWritten {
+                    kind: audit::FileKind::Still,
+                    path: path.clone(),
+                });
+                self.feedback.captured(&self.settings.feedback);
+                self.toasts
+                    .info(tr!("info.saved_still", path = path.display()));
+            }
+            Err(e) => self.toasts.error(tr!("error.save_still", error = e)),
+        }
+    }
+
+    /// Save the newest frame captured for calibration to the output directory
+    fn save_capture(&mut self, camera: i32) {
+        let Some(img) = self
+            .charuco_images
+            .last()
+            .and_then(|m| convert::bgr_mat_to_color_image(m))
+        else {
+            return;
+        };
+        let output = &self.settings.output;
+        let r = output
+            .create_unique(&output.capture_template, Some(camera))
+            .map_err(|e| format!("{:?}", e))
+            .and_then(|path| {
+                convert::save_color_image(&path, &img)
+                    .map(|_| path)
+                    .map_err(|e| format!("{:?}", e))
+            });
+        match r {
+            Ok(path) => {
+                self.write_sidecar(&path, Some(camera), false);
+                self.audit(audit::Event::FileWritten {
+                    kind: audit::FileKind::Capture,
+                    path,
+                });
+            }
+            Err(e) => self.toasts.error(tr!("error.save_capture", error = e)),
+        }
+    }
+
+    /// Save the frames held in the ring buffer to a new directory in the output directory
+    fn save_ring_buffer(&mut self) {
+        let camera = self.ring_buffer.camera();
+        let output = &self.settings.output;
+        match output.create_unique(&output.buffer_template, camera) {
+            Ok(dir) => {
+                self.write_sidecar(&dir, camera, false);
+                self.audit(audit::Event::FileWritten {
+                    kind: audit::FileKind::Frames,
+                    path: dir.clone(),
+                });
+                let cancel = self.tasks.start(tr!("status.saving_frames"));
+                self.ring_buffer.save(dir, self.task_done.0.clone(), cancel);
+            }
+            Err(e) => self
+                .toasts
+                .error(tr!("error.save_frames", error = format!("{:?}", e))),
+        }
+    }
+
+    /// Save the frames around a detected motion to a new directory in the output directory
+    fn save_motion_event(&mut self, frames: Vec<(std::time::Instant, Frame)>) {
+        let camera = self.motion.camera();
+        let output = &self.settings.output;
+        match output.create_unique(&output.motion_template, camera) {
+            Ok(dir) => {
+                self.write_sidecar(&dir, camera, false);
+                self.audit(audit::Event::FileWritten {
+                    kind: audit::FileKind::Frames,
+                    path: dir.clone(),
+                });
+                let cancel = self.tasks.start(tr!("status.saving_frames"));
+                ring_buffer::save_frames(dir, frames, self.task_done.0.clone(), cancel);
             }
+            Err(e) => self
+                .toasts
+                .error(tr!("error.save_frames", error = format!("{:?}", e))),
+        }
+    }
+
+    /// The name of a camera or video as shown to the user
+    fn source_name(&self, i: i32) -> String {
+        if let Some(v) = self.videos.get(&i) {
+            let name = v.file_name().unwrap_or(v.as_os_str());
+            tr!("main.video", name = name.to_string_lossy())
+        } else if let Some(s) = self.source_names.get(&i) {
+            s.clone()
         } else {
-            0
+            tr!("main.camera", id = i)
         }
     }
-}
 
-fn get_charuco_dictionary() -> Option<opencv::core::Ptr<opencv::aruco::Dictionary>> {
-    let dict = opencv::aruco::DICT_6X6_1000;
-    let d = opencv::aruco::Dictionary::get(dict);
-    d.ok()
-}
+    /// The id for a new source that is not a camera, these are negative so they never collide with cameras
+    fn next_virtual_id(&self) -> i32 {
+        let v = self.videos.keys().next().copied().unwrap_or(0);
+        let s = self.source_names.keys().next().copied().unwrap_or(0);
+        v.min(s) - 1
+    }
 
-fn make_charuco_board() -> Option<opencv::core::Ptr<opencv::aruco::CharucoBoard>> {
-    if let Some(d) = get_charuco_dictionary() {
-        println!("Making charuco board");
-        let board = opencv::aruco::CharucoBoard::create(10, 10, 10.0 * 0.0254, 7.0 * 0.0254, &d);
-        board.ok()
-    } else {
-        None
+    /// Add a source that is not a numbered camera, returning its id
+    fn add_named_source(&mut self, name: String, s: FrameSource) -> i32 {
+        let i = self.next_virtual_id();
+        self.source_names.insert(i, name);
+        self.send_to_camera_thread(ToCameraThread::ValidCamera(i, s));
+        self.live_cameras.insert(i);
+        i
     }
-}
 
-impl eframe::App for MainData {
-    fn on_exit(&mut self, _gl: Option<&eframe::glow::Context>) {
-        let _ = self.to_image_thread.send(ToCameraThread::Quit);
+    /// Start a screen capture and display it as if it were a camera
+    fn add_screen_capture(&mut self, s: screen::ScreenCapture) {
+        let i = self.add_named_source(s.name(), s.into());
+        self.send_to_camera_thread(ToCameraThread::OpenCamera(i));
+        self.selected_camera = Some(i);
     }
 
-    fn update(&mut self, ctx: &eframe::egui::Context, _frame: &mut eframe::Frame) {
-        ctx.request_repaint_after(Duration::from_millis(10));
-        let mut use_newest_image = false;
-        while let Ok(a) = self.from_image_thread.try_recv() {
-            match a {
-                FromCameraThread::CameraImage(i, bm) => {
-                    if let Some(j) = self.selected_camera {
-                        if j != i {
-                            let _ = self.to_image_thread.send(ToCameraThread::CloseCamera(i));
+    /// Open and display an image file
+    fn open_image(&mut self, ctx: &eframe::egui::Context, path: &Path) {
+        if large_image::pixel_count(path).is_some_and(|n| n > large_image::LARGE_PIXELS) {
+            // The preview shows a smaller version once it is decoded, the full resolution is in the large image viewer
+            self.large_image.open(path);
+            self.show_large_image = true;
+            settings::add_recent(&mut self.settings.recent.images, path);
+            return;
+        }
+        if let Some(img) = load_image_file(path) {
+            self.original_image = None;
+            self.set_image(ctx, img);
+            settings::add_recent(&mut self.settings.recent.images, path);
+        } else {
+            self.toasts
+                .error(tr!("error.open_image", path = path.display()));
+            self.settings.recent.images.retain(|p| p != path);
+        }
+    }
+
+    /// Open a video file and play it as if it were a camera
+    fn open_video(&mut self, path: &Path) {
+        if let Some((i, _)) = self.videos.iter().find(|(_, p)| *p == path) {
+            self.selected_camera = Some(*i);
+            return;
+        }
+        let i = self.next_virtual_id();
+        if let Some(c) = OpenCvCamera::new_file(i, path) {
+            self.send_to_camera_thread(ToCameraThread::ValidCamera(i, c.into()));
+            self.live_cameras.insert(i);
+            self.videos.insert(i, path.to_path_buf());
+            self.selected_camera = Some(i);
+            settings::add_recent(&mut self.settings.recent.videos, path);
+        } else {
+            self.toasts
+                .error(tr!("error.open_video", path = path.display()));
+            self.settings.recent.videos.retain(|p| p != path);
+        }
+    }
+
+    /// Load calibration data from a file
+    fn load_calibration(&mut self, path: &Path) {
+        match CalibrationData::load_verified(path, &self.settings.signing.trust()) {
+            Ok((cd, verification)) => {
+                self.audit(audit::Event::CalibrationLoaded {
+                    path: path.to_path_buf(),
+                    verification: match &verification {
+                        Verification::Unchecked => tr!("audit.unchecked"),
+                        Verification::Checksum => tr!("audit.checksum"),
+                        Verification::Signed { public_key, .. } => tr!(
+                            "audit.signed",
+                            key = image_proc::integrity::to_hex(public_key)
+                        ),
+                    },
+                });
+                match verification {
+                    Verification::Unchecked => self
+                        .toasts
+                        .info(tr!("info.calibration_unchecked", path = path.display())),
+                    Verification::Checksum => {}
+                    Verification::Signed {
+                        public_key,
+                        trusted,
+                    } => {
+                        let key = image_proc::integrity::to_hex(&public_key);
+                        if trusted {
+                            self.toasts.info(tr!("info.calibration_signed", key = key));
+                        } else {
+                            self.toasts
+                                .info(tr!("info.calibration_untrusted", key = key));
                         }
                     }
-                    self.image_set.insert(i, bm);
                 }
+                self.cd = Some(cd);
+                self.cd_path = Some(path.to_path_buf());
+                self.cd_resolution = None;
+                settings::add_recent(&mut self.settings.recent.calibrations, path);
+            }
+            Err(e) => {
+                let reason = match e {
+                    IntegrityError::Io(_) | IntegrityError::Malformed => None,
+                    IntegrityError::Checksum => Some(tr!("error.calibration_checksum")),
+                    IntegrityError::Signature => Some(tr!("error.calibration_signature")),
+                    IntegrityError::Untrusted => Some(tr!("error.calibration_untrusted")),
+                };
+                match reason {
+                    Some(reason) => self.toasts.error(tr!(
+                        "error.verify_calibration",
+                        path = path.display(),
+                        reason = reason
+                    )),
+                    None => self
+                        .toasts
+                        .error(tr!("error.load_calibration", path = path.display())),
+                }
+                self.settings.recent.calibrations.retain(|p| p != path);
             }
         }
-        eframe::egui::CentralPanel::default().show(ctx, |ui| {
-            egui_extras::install_image_loaders(ctx);
+    }
 
-            eframe::egui::ScrollArea::vertical().show(ui, |ui| {
-                ui.horizontal(|ui| {
-                    eframe::egui::ComboBox::from_label("Select a camera")
-                        .selected_text(format!("{:?}", self.selected_camera))
-                        .show_ui(ui, |ui| {
-                            for i in &self.live_cameras {
-                                ui.selectable_value(
-                                    &mut self.selected_camera,
-                                    Some(*i),
-                                    format!("Camera {}", i),
-                                );
+    /// The contents of a calibration file, signed when a signing key is set up
+    fn sealed_calibration(&self, cd: &CalibrationData) -> std::io::Result<Vec<u8>> {
+        let key = self.settings.signing.key().map_err(std::io::Error::other)?;
+        cd.sealed(key.as_ref())
+    }
+
+    /// Write calibration data to a file, signed when a signing key is set up
+    fn write_calibration(&self, cd: &CalibrationData, path: &Path) -> std::io::Result<()> {
+        std::fs::write(path, self.sealed_calibration(cd)?)
+    }
+
+    /// Save the current calibration data to a file
+    fn save_calibration(&mut self, path: &Path) {
+        let Some(cd) = &self.cd else {
+            return;
+        };
+        if let Err(e) = self.write_calibration(cd, path) {
+            self.toasts
+                .error(tr!("error.save_calibration", error = format!("{:?}", e)));
+        } else {
+            self.audit(audit::Event::FileWritten {
+                kind: audit::FileKind::Calibration,
+                path: path.to_path_buf(),
+            });
+            self.toasts
+                .info(tr!("info.saved_calibration", path = path.display()));
+            self.cd_path = Some(path.to_path_buf());
+            settings::add_recent(&mut self.settings.recent.calibrations, path);
+        }
+    }
+
+    /// Show the menu bar at the top of the window
+    fn menu_bar(&mut self, ctx: &eframe::egui::Context) {
+        let mut action = None;
+        let mut diagnostics = false;
+        let recent = &self.settings.recent;
+        eframe::egui::TopBottomPanel::top("menu").show(ctx, |ui| {
+            eframe::egui::menu::bar(ui, |ui| {
+                ui.menu_button(tr!("menu.file"), |ui| {
+                    if ui.button(tr!("menu.open_image")).clicked() {
+                        action = Some(FileAction::PickImage);
+                        ui.close_menu();
+                    }
+                    if ui.button(tr!("menu.open_video")).clicked() {
+                        action = Some(FileAction::PickVideo);
+                        ui.close_menu();
+                    }
+                    if ui.button(tr!("menu.load_calibration")).clicked() {
+                        action = Some(FileAction::PickCalibration);
+                        ui.close_menu();
+                    }
+                    if ui
+                        .add_enabled(
+                            self.cd.is_some(),
+                            eframe::egui::Button::new(tr!("menu.save_calibration")),
+                        )
+                        .clicked()
+                    {
+                        action = Some(FileAction::SaveCalibration);
+                        ui.close_menu();
+                    }
+                    if ui.button(tr!("menu.review_calibration")).clicked() {
+                        action = Some(FileAction::ReviewCalibration);
+                        ui.close_menu();
+                    }
+                    if ui.button(tr!("menu.settings")).clicked() {
+                        self.show_settings = true;
+                        ui.close_menu();
+                    }
+                    ui.separator();
+                    let lists: [(String, &Vec<PathBuf>, fn(PathBuf) -> FileAction); 3] = [
+                        (
+                            tr!("menu.recent_images"),
+                            &recent.images,
+                            FileAction::OpenImage,
+                        ),
+                        (
+                            tr!("menu.recent_videos"),
+                            &recent.videos,
+                            FileAction::OpenVideo,
+                        ),
+                        (
+                            tr!("menu.recent_calibrations"),
+                            &recent.calibrations,
+                            FileAction::LoadCalibration,
+                        ),
+                    ];
+                    for (name, list, f) in lists {
+                        ui.add_enabled_ui(!list.is_empty(), |ui| {
+                            ui.menu_button(name, |ui| {
+                                for p in list {
+                                    if ui.button(p.display().to_string()).clicked() {
+                                        action = Some(f(p.clone()));
+                                        ui.close_menu();
+                                    }
+                                }
+                            });
+                        });
+                    }
+                });
+                ui.menu_button(tr!("menu.help"), |ui| {
+                    if ui.button(tr!("menu.diagnostics")).clicked() {
+                        diagnostics = true;
+                        ui.close_menu();
+                    }
+                });
+            });
+        });
+        match action {
+            Some(FileAction::PickImage) => {
+                let f = rfd::FileDialog::new()
+                    .add_filter("Image", &["jpg", "png"])
+                    .set_directory(settings::recent_directory(
+                        &self.settings.recent.images,
+                        &self.settings.output.working_directory,
+                    ))
+                    .pick_file();
+                if let Some(f) = f {
+                    self.open_image(ctx, &f);
+                }
+            }
+            Some(FileAction::PickVideo) => {
+                let f = rfd::FileDialog::new()
+                    .add_filter("Video", &["mp4", "avi", "mkv", "mov", "webm"])
+                    .set_directory(settings::recent_directory(
+                        &self.settings.recent.videos,
+                        &self.settings.output.working_directory,
+                    ))
+                    .pick_file();
+                if let Some(f) = f {
+                    self.open_video(&f);
+                }
+            }
+            Some(FileAction::PickCalibration) => {
+                let f = rfd::FileDialog::new()
+                    .add_filter("Calibration", &["bin"])
+                    .set_directory(settings::recent_directory(
+                        &self.settings.recent.calibrations,
+                        &self.settings.output.working_directory,
+                    ))
+                    .pick_file();
+                if let Some(f) = f {
+                    self.load_calibration(&f);
+                }
+            }
+            Some(FileAction::SaveCalibration) => {
+                let f = rfd::FileDialog::new()
+                    .add_filter("Calibration", &["bin"])
+                    .set_directory(settings::recent_directory(
+                        &self.settings.recent.calibrations,
+                        &self.settings.output.working_directory,
+                    ))
+                    .save_file();
+                if let Some(f) = f {
+                    self.save_calibration(&f);
+                }
+            }
+            Some(FileAction::OpenImage(p)) => self.open_image(ctx, &p),
+            Some(FileAction::OpenVideo(p)) => self.open_video(&p),
+            Some(FileAction::LoadCalibration(p)) => self.load_calibration(&p),
+            Some(FileAction::ReviewCalibration) => self.show_review = true,
+            None => {}
+        }
+        if diagnostics {
+            self.run_doctor();
+            self.show_doctor = true;
+        }
+    }
+
+    /// Check the environment, with the cameras already found and the directories written to
+    fn run_doctor(&mut self) {
+        let output = &self.settings.output;
+        let dirs = [
+            output.output_directory.clone(),
+            output.working_directory.clone(),
+            self.settings.watch.output.clone(),
+        ];
+        self.doctor.run(self.live_cameras.len(), &dirs);
+    }
+
+    /// Show the status bar at the bottom of the window
+    fn status_bar(&self, ctx: &eframe::egui::Context) {
+        eframe::egui::TopBottomPanel::bottom("status").show(ctx, |ui| {
+            ui.horizontal(|ui| {
+                match self.selected_camera {
+                    Some(i) => {
+                        ui.label(self.source_name(i));
+                        if self.open_cameras.contains(&i) {
+                            ui.colored_label(eframe::egui::Color32::GREEN, tr!("status.open"));
+                            if let Some(fps) = self.frame_rates.get(&i).and_then(|f| f.fps()) {
+                                ui.label(tr!("status.fps", fps = format!("{:.1}", fps)));
+                            }
+                            let scale = self.adaptive_preview.scale();
+                            if scale < 1.0 {
+                                ui.colored_label(
+                                    eframe::egui::Color32::YELLOW,
+                                    tr!(
+                                        "status.preview_scale",
+                                        percent = format!("{:.0}", scale * 100.0)
+                                    ),
+                                )
+                                .on_hover_text(tr!("status.preview_scale_hint"));
+                            }
+                        } else {
+                            ui.label(tr!("status.closed"));
+                        }
+                    }
+                    None => {
+                        ui.label(tr!("status.no_camera"));
+                    }
+                }
+                ui.separator();
+                match (&self.cd, self.calibration_rms) {
+                    (Some(_), Some(rms)) => {
+                        ui.label(tr!("status.calibrated", rms = format!("{:.3}", rms)))
+                    }
+                    (Some(_), None) => ui.label(tr!("status.loaded_calibration")),
+                    (None, _) => ui.label(tr!("status.not_calibrated")),
+                };
+                if let Some(d) = self
+                    .selected_camera
+                    .and_then(|i| self.depth_maps.get(&i))
+                    .and_then(|d| {
+                        d.lock()
+                            .ok()?
+                            .as_ref()?
+                            .at(self.cursor_pixel?[0], self.cursor_pixel?[1])
+                    })
+                {
+                    ui.separator();
+                    ui.label(tr!("status.depth", mm = d));
+                }
+                if let Some(t) = self.selected_camera.and_then(|i| self.thermal_maps.get(&i)) {
+                    if let Some(t) = t.lock().ok().and_then(|t| t.clone()) {
+                        if let Some((min, max)) = t.range() {
+                            ui.separator();
+                            ui.label(tr!(
+                                "status.thermal_range",
+                                min = format!("{:.1}", min),
+                                max = format!("{:.1}", max)
+                            ));
+                        }
+                        if let Some(c) = self.cursor_pixel.and_then(|[x, y]| t.celsius_at(x, y)) {
+                            ui.separator();
+                            ui.label(tr!("status.temperature", celsius = format!("{:.1}", c)));
+                        }
+                    }
+                }
+                ui.separator();
+                ui.label(tr!("status.captures", count = self.charuco_images.len()));
+                if self.watch.is_watching() {
+                    ui.separator();
+                    ui.label(tr!("status.watching"));
+                }
+                self.tasks.show(ui);
+            });
+        });
+    }
+
+    /// Show the preview image scaled to fit the available space
+    fn fitted_preview(&mut self, ui: &mut eframe::egui::Ui) {
+        let ppp = ui.ctx().pixels_per_point();
+        if let Some(th) = &self.img {
+            let st = eframe::egui::load::SizedTexture {
+                id: th.id(),
+                size: fit_size(th.size_vec2(), ui.available_size(), ppp),
+            };
+            let scale = st.size.x * ppp / th.size_vec2().x;
+            let r = ui
+                .centered_and_justified(|ui| {
+                    ui.add(
+                        eframe::egui::Image::from_texture(st)
+                            .sense(eframe::egui::Sense::click_and_drag()),
+                    )
+                })
+                .inner;
+            self.annotations.interact(ui, &r, th.size());
+            self.detections.paint(ui, r.rect, th.size());
+            self.feedback.paint(ui, r.rect);
+            self.profiler.paint(ui, r.rect);
+            if r.hovered() {
+                self.cursor_pixel = image_pixel(&r, th.size());
+            }
+            if self.preview_shown(scale) {
+                self.refresh_preview(ui.ctx());
+            }
+        } else {
+            ui.centered_and_justified(|ui| ui.label(tr!("main.no_image")));
+        }
+    }
+
+    /// Remember how large the preview was shown, returns true when its texture should be filtered differently
+    fn preview_shown(&mut self, scale: f32) -> bool {
+        let changed = dpi::texture_options(scale) != dpi::texture_options(self.preview_scale);
+        self.preview_scale = scale;
+        changed
+    }
+
+    /// Make the preview texture again from the current image
+    fn refresh_preview(&mut self, ctx: &eframe::egui::Context) {
+        if let Some(img) = self.raw_image.clone() {
+            self.set_image(ctx, img);
+        }
+    }
+
+    /// Show the detached preview in a separate native window, so it can be moved to another screen
+    fn show_preview_viewport(&mut self, ctx: &eframe::egui::Context) {
+        if !self.detached_preview {
+            return;
+        }
+        ctx.show_viewport_immediate(
+            eframe::egui::ViewportId::from_hash_of("preview"),
+            eframe::egui::ViewportBuilder::default()
+                .with_title(tr!("window.preview"))
+                .with_inner_size([800.0, 600.0]),
+            |ctx, class| {
+                if class == eframe::egui::ViewportClass::Embedded {
+                    // The backend can not make more windows, so show it inside the main window
+                    let mut open = true;
+                    eframe::egui::Window::new(tr!("window.preview"))
+                        .open(&mut open)
+                        .show(ctx, |ui| self.fitted_preview(ui));
+                    self.detached_preview = open;
+                } else {
+                    eframe::egui::CentralPanel::default().show(ctx, |ui| self.fitted_preview(ui));
+                    if ctx.input(|i| i.viewport().close_requested()) {
+                        self.detached_preview = false;
+                    }
+                }
+            },
+        );
+    }
+
+    /// The resolution of the calibration and of the selected camera,
+    /// when they differ and the sensor modes of the camera do not relate them
+    fn resolution_mismatch(&self) -> Option<([u32; 2], [u32; 2])> {
+        let from = self.cd_resolution?;
+        let img = self.image_set.get(&self.selected_camera?)?;
+        let to = [img.cols() as u32, img.rows() as u32];
+        let cd = self.cd.as_ref()?;
+        (from != to && self.mode_calibration(cd, from, to).is_none()).then_some((from, to))
+    }
+
+    /// The calibration for images of a resolution of the selected camera from one made at another,
+    /// when the sensor modes of the camera relate them
+    fn mode_calibration(
+        &self,
+        cd: &CalibrationData,
+        from: [u32; 2],
+        to: [u32; 2],
+    ) -> Option<CalibrationData> {
+        self.profiles
+            .get(&self.selected_camera?)?
+            .sensor_modes
+            .as_ref()?
+            .calibration(cd, from, to)
+    }
+
+    /// The calibration for images of a size, moved to the sensor mode of the images or scaled when scale is set.
+    /// None when the calibration was made at another resolution and neither works.
+    fn calibration_at(
+        &self,
+        cd: &CalibrationData,
+        size: [u32; 2],
+        scale: bool,
+    ) -> Option<CalibrationData> {
+        match self.cd_resolution {
+            Some(from) if from != size => self
+                .mode_calibration(cd, from, size)
+                .or_else(|| scale.then(|| cd.scaled(from, size))),
+            _ => Some(cd.clone()),
+        }
+    }
+
+    /// The calibration to apply to images of a size, None when it should not be applied
+    fn calibration_for(&self, dims: [usize; 2]) -> Option<CalibrationData> {
+        let cd = self.cd.as_ref().filter(|_| self.apply_cd)?;
+        let size = [dims[0] as u32, dims[1] as u32];
+        let cd = self.calibration_at(cd, size, self.scale_cd)?;
+        Some(self.distortion_explorer.apply(cd))
+    }
+
+    /// Switch the pipeline and calibration to those of the selected camera.
+    /// A camera without its own starts with a copy of the ones in use.
+    fn sync_processing(&mut self) {
+        if self.selected_camera == self.processing_camera {
+            return;
+        }
+        let current = CameraProcessing {
+            pipeline: self.pipeline.clone(),
+            cd: self.cd.clone(),
+            cd_path: self.cd_path.clone(),
+            apply_cd: self.apply_cd,
+            calibration_rms: self.calibration_rms,
+            cd_resolution: self.cd_resolution,
+        };
+        let new = self
+            .processing
+            .remove(&self.selected_camera)
+            .unwrap_or_else(|| current.clone());
+        self.processing.insert(self.processing_camera, current);
+        self.pipeline = new.pipeline;
+        self.cd = new.cd;
+        self.cd_path = new.cd_path;
+        self.apply_cd = new.apply_cd;
+        self.calibration_rms = new.calibration_rms;
+        self.cd_resolution = new.cd_resolution;
+        self.processing_camera = self.selected_camera;
+        // A frame paused on belongs to the camera that was selected
+        self.freeze.resume();
+    }
+
+    /// Show the newest image from the selected camera, capture saves it for calibration
+    fn update_preview(&mut self, ctx: &eframe::egui::Context, capture: bool) {
+        self.sync_processing();
+        let mut newest = None;
+        let mut captured = None;
+        if let Some(i) = &self.selected_camera {
+            // While paused the frame paused on is shown and captured, the newest frames keep arriving in the background
+            let img = match self.freeze.shown() {
+                Some(f) => Some(f),
+                None => self.image_set.get(i),
+            };
+            if let Some(img) = img {
+                if capture {
+                    self.feedback.captured(&self.settings.feedback);
+                    let img = self.calibration_image(img);
+                    self.charuco_images.push(img.clone());
+                    captured = Some(*i);
+                    let corners = self.detect_charuco_corners(&img);
+                    self.wizard.coverage.add_view(
+                        [img.cols() as f32, img.rows() as f32],
+                        &corners,
+                        self.settings.board.corner_count() / 4,
+                    );
+                }
+                let full = [img.cols() as usize, img.rows() as usize];
+                let scale = self.adaptive_preview.scale();
+                // The frame is converted from the bgr order of the camera once here, the preview works in rgb after
+                let cimg = self.profiler.time(profiler::Stage::Convert, || {
+                    // Only the preview is shrunk, captures and recordings use the full frame
+                    if scale < 1.0 {
+                        let mut small = opencv::core::Mat::default();
+                        opencv::imgproc::resize(
+                            &**img,
+                            &mut small,
+                            opencv::core::Size::default(),
+                            scale as f64,
+                            scale as f64,
+                            opencv::imgproc::INTER_AREA,
+                        )
+                        .ok()?;
+                        convert::bgr_mat_to_color_image(&small)
+                    } else {
+                        convert::bgr_mat_to_color_image(img)
+                    }
+                });
+                if let Some(cimg) = cimg {
+                    let dims = cimg.size;
+                    let cd = self.calibration_for(full).map(|cd| {
+                        if dims == full {
+                            cd
+                        } else {
+                            let size = |d: [usize; 2]| [d[0] as u32, d[1] as u32];
+                            cd.scaled(size(full), size(dims))
+                        }
+                    });
+                    if let Some(cd) = cd {
+                        self.original_image = Some(cimg.clone());
+                        newest = Some(
+                            self.profiler
+                                .time(profiler::Stage::Undistort, || cd.apply_calibration(cimg)),
+                        );
+                    } else {
+                        self.original_image = None;
+                        newest = Some(cimg);
+                    }
+                }
+            }
+        }
+        if let Some(i) = captured {
+            self.audit(audit::Event::FrameCaptured {
+                camera: Some(i),
+                count: self.charuco_images.len(),
+            });
+            if self.settings.output.save_captures {
+                self.save_capture(i);
+            }
+        }
+        if let Some(cimg) = newest {
+            self.set_image(ctx, cimg);
+            self.adaptive_preview
+                .update(&self.settings.preview, self.profiler.display_time());
+        }
+    }
+
+    /// The image as used for calibration, the heated board shows up bright in thermal images so it is inverted
+    fn calibration_image(&self, img: &Frame) -> Frame {
+        if self.heated_board
+            && self
+                .selected_camera
+                .is_some_and(|i| self.thermal_maps.contains_key(&i))
+        {
+            let mut out = opencv::core::Mat::default();
+            if opencv::core::bitwise_not_def(&**img, &mut out).is_ok() {
+                return out.into();
+            }
+        }
+        img.clone()
+    }
+
+    /// Open a camera as a thermal camera, with its radiometric data instead of a color image
+    fn open_thermal(&mut self, i: i32) {
+        // The device can not be open as a normal camera at the same time
+        self.send_to_camera_thread(ToCameraThread::CloseCamera(i));
+        let c = thermal::ThermalCamera::new(i);
+        let t = c.thermal();
+        let j = self.add_named_source(tr!("main.thermal", id = i), c.into());
+        self.thermal_maps.insert(j, t);
+        self.send_to_camera_thread(ToCameraThread::OpenCamera(j));
+        self.selected_camera = Some(j);
+    }
+
+    /// Load the calibration stored in the profile of a camera, if it was made for the camera at this resolution
+    fn auto_load_calibration(&mut self, i: i32, resolution: [u32; 2]) {
+        let Some(cd) = self
+            .profiles
+            .get(&i)
+            .and_then(|p| p.matching_calibration(i, resolution))
+        else {
+            return;
+        };
+        self.sync_processing();
+        self.cd = Some(cd.clone());
+        self.cd_path = None;
+        self.cd_resolution = Some(resolution);
+        self.calibration_rms = None;
+        self.residuals.clear();
+        let name = self.source_name(i);
+        self.toasts.info(tr!("info.auto_calibration", name = name));
+    }
+
+    /// The calibration of a camera, from the working set when it is the selected camera
+    fn camera_calibration(&self, i: i32) -> Option<CalibrationData> {
+        if self.processing_camera == Some(i) {
+            self.cd.clone()
+        } else {
+            self.processing.get(&Some(i)).and_then(|p| p.cd.clone())
+        }
+    }
+
+    /// Save the newest image of both cameras of the stereo pair as a pair, when they were taken together
+    fn capture_stereo_pair(&mut self, c: stereo_rig::PairCapture) {
+        if let stereo_rig::PairCapture::TimedOut = c {
+            self.toasts.error(tr!("error.stereo_sync"));
+            return;
+        }
+        let images = [self.stereo.left, self.stereo.right]
+            .map(|c| c.and_then(|c| self.image_set.get(&c)).cloned());
+        if let [Some(l), Some(r)] = images {
+            self.feedback.captured(&self.settings.feedback);
+            self.stereo.pairs.push((l, r));
+        }
+    }
+
+    /// Use the calibration of the zoom lens of a camera interpolated for a zoom
+    fn apply_zoom_calibration(&mut self, i: i32, zoom: f64) {
+        let Some(zp) = self.profiles.get(&i).and_then(|p| p.zoom.as_ref()) else {
+            return;
+        };
+        let (cd, resolution) = (zp.at(zoom), zp.resolution);
+        self.zoom_lens.applied(i, zoom);
+        let Some(cd) = cd else {
+            return;
+        };
+        self.sync_processing();
+        self.cd = Some(cd);
+        self.cd_path = None;
+        self.cd_resolution = resolution;
+        self.calibration_rms = None;
+        self.residuals.clear();
+    }
+
+    /// Carry out what was asked for in the zoom lens window
+    fn zoom_action(&mut self, a: zoom_lens::ZoomAction) {
+        let Some(i) = self.selected_camera else {
+            return;
+        };
+        match a {
+            zoom_lens::ZoomAction::Add(zoom) => {
+                let Some(cd) = self.cd.clone() else {
+                    return;
+                };
+                let zp = self
+                    .profiles
+                    .entry(i)
+                    .or_default()
+                    .zoom
+                    .get_or_insert_default();
+                // Only calibrations at one resolution can be mixed
+                if zp.resolution.is_some() && zp.resolution != self.cd_resolution {
+                    self.toasts.error(tr!("zoom.resolution_mismatch"));
+                    return;
+                }
+                zp.resolution = self.cd_resolution;
+                zp.add(zoom, cd);
+                self.zoom_lens.applied(i, zoom);
+            }
+            zoom_lens::ZoomAction::Remove(n) => {
+                if let Some(zp) = self.profiles.get_mut(&i).and_then(|p| p.zoom.as_mut()) {
+                    zp.remove(n);
+                }
+            }
+            zoom_lens::ZoomAction::Apply(zoom) => {
+                self.apply_zoom_calibration(i, zoom);
+                return;
+            }
+        }
+        if let Some(p) = self.profiles.get(&i) {
+            if let Err(e) = p.save(&self.settings.output.working_directory, i) {
+                self.toasts
+                    .error(tr!("error.save_profile", error = format!("{:?}", e)));
+            }
+        }
+    }
+
+    /// Use a calibration for the stereo pair, telling the user when it can not be rectified
+    fn use_stereo_calibration(&mut self, sc: image_proc::stereo::StereoCalibration) {
+        if let Err(e) = self.stereo.set_calibration(sc) {
+            self.toasts
+                .error(tr!("error.stereo_rectify", error = e.to_string()));
+        }
+    }
+
+    /// Carry out what was asked for in the stereo window
+    fn stereo_action(&mut self, a: stereo_rig::StereoAction) {
+        match a {
+            stereo_rig::StereoAction::DepthFailed(e) => {
+                self.toasts.error(tr!("error.stereo_depth", error = e));
+            }
+            stereo_rig::StereoAction::Calibrate => {
+                let (Some(l), Some(r)) = (self.stereo.left, self.stereo.right) else {
+                    return;
+                };
+                if self.settings.board.grid().is_some() {
+                    self.toasts.error(tr!("error.stereo_charuco"));
+                    return;
+                }
+                let (Some(lc), Some(rc)) = (self.camera_calibration(l), self.camera_calibration(r))
+                else {
+                    self.toasts.error(tr!("error.stereo_intrinsics"));
+                    return;
+                };
+                let Some(d) = self.settings.board.dictionary() else {
+                    return;
+                };
+                match image_proc::stereo::calibrate_stereo_charuco(
+                    &self.stereo.pairs,
+                    &lc,
+                    &rc,
+                    &self.charuco_board,
+                    &d,
+                ) {
+                    Ok(sc) => self.use_stereo_calibration(sc),
+                    Err(e) => self
+                        .toasts
+                        .error(tr!("error.stereo_calibration", error = e.to_string())),
+                }
+            }
+            stereo_rig::StereoAction::Save => {
+                let Some(sc) = self.stereo.calibration() else {
+                    return;
+                };
+                let f = rfd::FileDialog::new()
+                    .add_filter("Stereo calibration", &["bin"])
+                    .set_directory(settings::recent_directory(
+                        &self.settings.recent.calibrations,
+                        &self.settings.output.working_directory,
+                    ))
+                    .save_file();
+                if let Some(f) = f {
+                    match sc.save(&f) {
+                        Ok(()) => self
+                            .toasts
+                            .info(tr!("info.saved_calibration", path = f.display())),
+                        Err(e) => self
+                            .toasts
+                            .error(tr!("error.save_calibration", error = format!("{:?}", e))),
+                    }
+                }
+            }
+            stereo_rig::StereoAction::ExportRectification => {
+                let Some(sc) = self.stereo.calibration() else {
+                    return;
+                };
+                let f = rfd::FileDialog::new()
+                    .add_filter("OpenCV YAML", &["yml", "yaml"])
+                    .set_directory(&self.settings.output.working_directory)
+                    .set_file_name("rectification.yml")
+                    .save_file();
+                if let Some(f) = f {
+                    match sc.rectification().and_then(|r| r.save_yaml(&f)) {
+                        Ok(()) => self
+                            .toasts
+                            .info(tr!("info.exported_rectification", path = f.display())),
+                        Err(e) => self
+                            .toasts
+                            .error(tr!("error.export_rectification", error = e.to_string())),
+                    }
+                }
+            }
+            stereo_rig::StereoAction::Load => {
+                let f = rfd::FileDialog::new()
+                    .add_filter("Stereo calibration", &["bin"])
+                    .set_directory(settings::recent_directory(
+                        &self.settings.recent.calibrations,
+                        &self.settings.output.working_directory,
+                    ))
+                    .pick_file();
+                if let Some(f) = f {
+                    match image_proc::stereo::StereoCalibration::load(
+                        &f,
+                        &self.settings.signing.trust(),
+                    ) {
+                        Some(sc) => self.use_stereo_calibration(sc),
+                        None => self
+                            .toasts
+                            .error(tr!("error.load_calibration", path = f.display())),
+                    }
+                }
+            }
+        }
+    }
+
+    /// Carry out what was asked for in the preset bar
+    fn preset_action(&mut self, ctx: &eframe::egui::Context, a: presets::PresetAction) {
+        match a {
+            presets::PresetAction::Apply(i) => {
+                let Some(p) = self.settings.presets.get(i).cloned() else {
+                    return;
+                };
+                self.pipeline = p.pipeline;
+                self.cd = p.calibration;
+                self.cd_path = None;
+                self.cd_resolution = p.calibration_resolution;
+                self.calibration_rms = None;
+                self.residuals.clear();
+                self.presets.camera = p.camera;
+                if let Some(c) = self.selected_camera {
+                    self.send_to_camera_thread(ToCameraThread::Configure(
+                        c,
+                        self.presets.camera.clone(),
+                    ));
+                }
+                if let Some(img) = self.raw_image.clone() {
+                    self.set_image(ctx, img);
+                }
+                self.toasts.info(tr!("info.preset", name = p.name));
+            }
+            presets::PresetAction::Save(name) => {
+                self.settings.presets.push(presets::SetupPreset {
+                    name,
+                    camera: self.presets.camera.clone(),
+                    calibration: self.cd.clone(),
+                    calibration_resolution: self.cd_resolution,
+                    pipeline: self.pipeline.clone(),
+                });
+            }
+            presets::PresetAction::Delete(i) => {
+                if i < self.settings.presets.len() {
+                    self.settings.presets.remove(i);
+                }
+            }
+            presets::PresetAction::Configure => {
+                if let Some(c) = self.selected_camera {
+                    self.send_to_camera_thread(ToCameraThread::Configure(
+                        c,
+                        self.presets.camera.clone(),
+                    ));
+                }
+            }
+        }
+    }
+
+    /// Select and open the camera offset places from the selected one
+    fn switch_camera(&mut self, offset: isize) {
+        let cameras: Vec<i32> = self.live_cameras.iter().copied().collect();
+        if cameras.is_empty() {
+            return;
+        }
+        let current = self
+            .selected_camera
+            .and_then(|i| cameras.iter().position(|c| *c == i))
+            .unwrap_or(0);
+        let next = (current as isize + offset).rem_euclid(cameras.len() as isize) as usize;
+        let i = cameras[next];
+        self.selected_camera = Some(i);
+        self.send_to_camera_thread(ToCameraThread::OpenCamera(i));
+        self.send_to_camera_thread(ToCameraThread::SetAveraging(
+            i,
+            self.averaging,
+            self.pipeline.linear,
+        ));
+    }
+
+    /// Calibrate the selected camera with the captured images
+    fn calibrate_selected(&mut self) {
+        if let Some(i) = self.selected_camera {
+            if self.calibrate_camera(i).is_err() {
+                self.toasts.error(tr!("error.calibration"));
+            }
+        }
+    }
+
+    /// Toggle the fullscreen mode that only shows the processed image
+    fn set_kiosk(&mut self, ctx: &eframe::egui::Context, kiosk: bool) {
+        self.kiosk = kiosk;
+        ctx.send_viewport_cmd(eframe::egui::ViewportCommand::Fullscreen(kiosk));
+    }
+
+    /// Show only the processed image filling the window, with the overlays if enabled
+    fn show_kiosk(&mut self, ctx: &eframe::egui::Context) {
+        let frame = eframe::egui::Frame::NONE.fill(eframe::egui::Color32::BLACK);
+        let ppp = ctx.pixels_per_point();
+        let mut scale = None;
+        eframe::egui::CentralPanel::default()
+            .frame(frame)
+            .show(ctx, |ui| {
+                let Some(th) = &self.img else {
+                    ui.centered_and_justified(|ui| ui.label(tr!("main.no_image")));
+                    return;
+                };
+                let st = eframe::egui::load::SizedTexture {
+                    id: th.id(),
+                    size: fit_size(th.size_vec2(), ui.available_size(), ppp),
+                };
+                scale = Some(st.size.x * ppp / th.size_vec2().x);
+                let r = ui
+                    .centered_and_justified(|ui| ui.add(eframe::egui::Image::from_texture(st)))
+                    .inner;
+                self.feedback.paint(ui, r.rect);
+                if !self.settings.appearance.kiosk_overlays {
+                    return;
+                }
+                self.annotations.interact(ui, &r, th.size());
+                self.detections.paint(ui, r.rect, th.size());
+                let mut text = self
+                    .selected_camera
+                    .map(|i| self.source_name(i))
+                    .unwrap_or_default();
+                if let Some(fps) = self
+                    .selected_camera
+                    .and_then(|i| self.frame_rates.get(&i))
+                    .and_then(|f| f.fps())
+                {
+                    text = format!(
+                        "{}  {}",
+                        text,
+                        tr!("status.fps", fps = format!("{:.1}", fps))
+                    );
+                }
+                if self.cd.is_some() {
+                    text = format!("{}  {}", text, tr!("main.calibrated"));
+                }
+                ui.painter().text(
+                    ui.max_rect().left_top() + eframe::egui::vec2(8.0, 8.0),
+                    eframe::egui::Align2::LEFT_TOP,
+                    text,
+                    eframe::egui::FontId::proportional(16.0),
+                    eframe::egui::Color32::WHITE,
+                );
+                ui.painter().text(
+                    ui.max_rect().left_bottom() + eframe::egui::vec2(8.0, -8.0),
+                    eframe::egui::Align2::LEFT_BOTTOM,
+                    tr!("main.kiosk_exit"),
+                    eframe::egui::FontId::proportional(12.0),
+                    eframe::egui::Color32::GRAY,
+                );
+            });
+        if scale.is_some_and(|s| self.preview_shown(s)) {
+            self.refresh_preview(ctx);
+        }
+    }
+
+    fn detect_cameras(&mut self) {
+        for c in OpenCvCamera::detect() {
+            let i = c.i;
+            self.send_to_camera_thread(ToCameraThread::ValidCamera(i, c.into()));
+            self.live_cameras.insert(i);
+            if let Some(p) =
+                profile::CameraProfile::load(&self.settings.output.working_directory, i)
+            {
+                self.profiles.insert(i, p);
+            }
+        }
+        #[cfg(feature = "genicam")]
+        for (name, c) in genicam::GenicamCamera::detect() {
+            self.add_named_source(name, c.into());
+        }
+        #[cfg(feature = "realsense")]
+        for (name, s) in realsense::RealSenseStream::detect() {
+            let d = s.depth();
+            let i = self.add_named_source(name, s.into());
+            if let Some(d) = d {
+                self.depth_maps.insert(i, d);
+            }
+        }
+        #[cfg(target_os = "linux")]
+        for p in picamera::PiCamera::detect() {
+            let i = self.next_virtual_id();
+            if let Some(mut c) = OpenCvCamera::new_gstreamer(i, p.pipeline()) {
+                c.close();
+                self.add_named_source(p.name(), c.into());
+            }
+        }
+        println!("Found {} cameras", self.live_cameras.len());
+    }
+
+    /// Draw the board in an image of a width in pixels
+    fn make_charuco_mat(&mut self, width: i32) -> opencv::core::Mat {
+        if let Some(g) = self.settings.board.grid() {
+            return pattern::Pattern::from(g).render_mat(width as u32).unwrap();
+        }
+        image_proc::aruco::draw_board(
+            &mut self.charuco_board,
+            self.settings.board.image_size(width),
+            10,
+            1,
+        )
+        .unwrap()
+    }
+
+    /// Start using a new calibration board, discarding images captured of the old one
+    fn set_board(&mut self, params: board::BoardParams) {
+        if let Some(b) = params.make_board() {
+            self.settings.board = params;
+            self.charuco_board = b;
+            self.charuco_images.clear();
+        }
+    }
+
+    /// The frame shown in the preview with the calibration at its resolution, for checking the calibration
+    fn validation_frame(&self) -> Option<(Frame, CalibrationData)> {
+        let frame = self
+            .freeze
+            .shown()
+            .cloned()
+            .or_else(|| self.image_set.get(&self.selected_camera?).cloned())?;
+        let cd = self.cd.as_ref()?;
+        let size = [frame.cols() as u32, frame.rows() as u32];
+        let cd = self.calibration_at(cd, size, true)?;
+        Some((frame, self.distortion_explorer.apply(cd)))
+    }
+
+    /// Measure the distance between two markers with the calibration and log the result in the metadata of the calibration
+    fn check_distance(&mut self) {
+        let r = self
+            .validation_frame()
+            .ok_or_else(|| tr!("distance.no_frame"))
+            .and_then(|(frame, cd)| {
+                distance::measure(
+                    &frame,
+                    &cd,
+                    self.distance.dictionary(),
+                    self.distance.marker_length(),
+                )
+            })
+            .map(|(markers, computed)| self.distance.check(markers, computed));
+        if let Ok(c) = &r {
+            println!(
+                "Distance check: measured {:.1} mm, computed {:.1} mm, error {:+.2}%",
+                c.measured,
+                c.computed,
+                c.error_percent()
+            );
+            match &self.cd_path {
+                Some(path) => {
+                    let mut metadata = image_proc::calibration::CalibrationMetadata::load(path)
+                        .unwrap_or_default();
+                    metadata.distance_checks.push(c.clone());
+                    if let Err(e) = metadata.save(path) {
+                        self.toasts
+                            .error(tr!("error.save_metadata", error = e.to_string()));
+                    }
+                }
+                None => self.toasts.info(tr!("distance.not_logged")),
+            }
+        }
+        self.distance.set_result(r);
+    }
+
+    /// Check the calibration against straight lines in the frame shown in the preview, at the resolution of the camera
+    fn check_straightness(
+        &self,
+        request: straightness::Request,
+    ) -> Result<Vec<straightness::LineCheck>, String> {
+        let (frame, cd) = self
+            .validation_frame()
+            .ok_or_else(|| tr!("straightness.no_frame"))?;
+        let size = [frame.cols() as u32, frame.rows() as u32];
+        let lines = match request {
+            straightness::Request::Edge(ends) => {
+                let pixel =
+                    |p: [f32; 2]| [p[0] as f64 * size[0] as f64, p[1] as f64 * size[1] as f64];
+                let points = straightness::edge_points(
+                    &frame,
+                    [pixel(ends[0]), pixel(ends[1])],
+                    self.straightness.band(),
+                )
+                .map_err(|e| e.to_string())?;
+                vec![(tr!("straightness.edge"), points)]
+            }
+            straightness::Request::Board => {
+                let result = self.detect_board(&frame).map_err(|e| e.to_string())?;
+                straightness::board_lines(&result, self.settings.board.corners_across())
+            }
+        };
+        straightness::check(lines, &cd)
+    }
+
+    /// Find the positions of the charuco corners in an image
+    fn detect_charuco_corners(&self, img: &opencv::core::Mat) -> Vec<[f32; 2]> {
+        self.detect_board(img)
+            .map(|r| r.corners.iter().map(|p| [p.x, p.y]).collect())
+            .unwrap_or_default()
+    }
+
+    /// Offer to restore a session that was not closed normally
+    fn show_recovery(&mut self, ctx: &eframe::egui::Context) {
+        let Some(m) = &self.recovery_offer else {
+            return;
+        };
+        let mut restore = false;
+        let mut discard = false;
+        eframe::egui::Window::new(tr!("window.recovery"))
+            .collapsible(false)
+            .show(ctx, |ui| {
+                ui.label(tr!("recovery.found", time = m.saved));
+                ui.label(tr!("recovery.captures", count = m.captures.len()));
+                if m.calibration.is_some() {
+                    ui.label(tr!("recovery.calibration"));
+                }
+                ui.horizontal(|ui| {
+                    restore = ui.button(tr!("recovery.restore")).clicked();
+                    discard = ui.button(tr!("recovery.discard")).clicked();
+                });
+            });
+        if restore {
+            match self.recovery.restore(m) {
+                Ok(r) => {
+                    let count = r.captures.len();
+                    // Captures taken since launching are kept after the restored ones
+                    let mut captures = r.captures;
+                    captures.append(&mut self.charuco_images);
+                    self.charuco_images = captures;
+                    if r.calibration.is_some() {
+                        self.cd = r.calibration;
+                        self.cd_path = None;
+                        self.cd_resolution = r.calibration_resolution;
+                    }
+                    self.toasts
+                        .info(tr!("info.session_restored", count = count));
+                    self.recovery_offer = None;
+                }
+                Err(e) => self.toasts.error(tr!("error.restore_session", error = e)),
+            }
+        } else if discard {
+            self.recovery.clear();
+            self.recovery_offer = None;
+        }
+    }
+
+    /// Show the calibration wizard and carry out what it asks for
+    fn show_wizard(&mut self, ctx: &eframe::egui::Context) {
+        let mut open = self.show_wizard;
+        let mut action = None;
+        let output = &self.settings.output;
+        let wc = wizard::WizardContext {
+            camera: self.selected_camera.map(|i| self.source_name(i)),
+            board: &self.settings.board,
+            board_file: Some(
+                output
+                    .expand(&output.board_template, None)
+                    .display()
+                    .to_string(),
+            ),
+            captures: self.charuco_images.len(),
+            rms: self.calibration_rms,
+            calibrated: self.cd.is_some(),
+        };
+        eframe::egui::Window::new(tr!("window.calibration_wizard"))
+            .open(&mut open)
+            .show(ctx, |ui| {
+                action = self.wizard.show(ui, wc);
+            });
+        self.show_wizard = open;
+        match action {
+            Some(wizard::WizardAction::GenerateBoard) => self.save_charuco_image(),
+            Some(wizard::WizardAction::SetBoard(b)) => self.set_board(b),
+            Some(wizard::WizardAction::Capture) => self.capture_next = true,
+            Some(wizard::WizardAction::ClearCaptures) => self.charuco_images.clear(),
+            Some(wizard::WizardAction::Calibrate) => {
+                if let Some(i) = self.selected_camera {
+                    self.cd = None;
+                    self.cd_path = None;
+                    if self.calibrate_camera(i).is_err() {
+                        self.toasts.error(tr!("error.calibration"));
+                    }
+                }
+            }
+            Some(wizard::WizardAction::SaveProfile) => {
+                if let (Some(i), Some(cd)) = (self.selected_camera, &self.cd) {
+                    let resolution = self
+                        .charuco_images
+                        .first()
+                        .or(self.image_set.get(&i))
+                        .map(|m| [m.cols() as u32, m.rows() as u32]);
+                    let p = self.profiles.entry(i).or_default();
+                    p.calibration = Some(cd.clone());
+                    p.calibration_resolution = resolution;
+                    p.identity = profile::CameraProfile::device_identity(i);
+                    if let Err(e) = p.save(&self.settings.output.working_directory, i) {
+                        self.toasts
+                            .error(tr!("error.save_profile", error = format!("{:?}", e)));
+                    }
+                }
+            }
+            None => {}
+        }
+    }
+
+    fn save_charuco_image(&mut self) {
+        println!("Saving charuco board");
+        let pic = self.make_charuco_mat(BOARD_WIDTH);
+        let output = &self.settings.output;
+        match output.create(&output.board_template, None) {
+            Ok(path) => {
+                match opencv::imgcodecs::imwrite(
+                    &path.to_string_lossy(),
+                    &pic,
+                    &opencv::core::Vector::new(),
+                ) {
+                    Ok(true) => {
+                        self.audit(audit::Event::FileWritten {
+                            kind: audit::FileKind::Board,
+                            path: path.clone(),
+                        });
+                        self.toasts
+                            .info(tr!("info.saved_board", path = path.display()));
+                        if self.settings.board.second_plane && self.settings.board.grid().is_none()
+                        {
+                            self.save_second_board(&path);
+                        }
+                    }
+                    r => self
+                        .toasts
+                        .error(tr!("error.save_board", error = format!("{:?}", r))),
+                }
+            }
+            Err(e) => self
+                .toasts
+                .error(tr!("error.save_board", error = format!("{:?}", e))),
+        }
+    }
+
+    /// Save the second board of a two board target next to the image of the first board
+    fn save_second_board(&mut self, first: &Path) {
+        let stem = first.file_stem().unwrap_or_default().to_string_lossy();
+        let ext = first.extension().unwrap_or_default().to_string_lossy();
+        let path = first.with_file_name(format!("{}_second.{}", stem, ext));
+        let r = self
+            .settings
+            .board
+            .make_second_board()
+            .ok_or_else(|| tr!("board.invalid"))
+            .and_then(|mut b| {
+                let size = self.settings.board.image_size(BOARD_WIDTH);
+                let pic = image_proc::aruco::draw_board(&mut b, size, 10, 1)
+                    .map_err(|e| e.to_string())?;
+                opencv::imgcodecs::imwrite(
+                    &path.to_string_lossy(),
+                    &pic,
+                    &opencv::core::Vector::new(),
+                )
+                .map_err(|e| e.to_string())
+            });
+        match r {
+            Ok(true) => {
+                self.audit(audit::Event::FileWritten {
+                    kind: audit::FileKind::Board,
+                    path: path.clone(),
+                });
+                self.toasts
+                    .info(tr!("info.saved_board", path = path.display()))
+            }
+            r => self
+                .toasts
+                .error(tr!("error.save_board", error = format!("{:?}", r))),
+        }
+    }
+
+    /// Start calibrating a camera from the captures in the background, the captures are handed back when it finishes
+    fn calibrate_camera(&mut self, i: i32) -> Result<(), ()> {
+        if self.calibration_task.is_some() {
+            return Ok(());
+        }
+        self.settings.board.dictionary().ok_or(())?;
+        let cancel = self.tasks.start(tr!("status.calibrating"));
+        self.calibration_task = Some(calibration_task::CalibrationTask::start(
+            i,
+            std::mem::take(&mut self.charuco_images),
+            self.settings.board.clone(),
+            self.settings.camera_model,
+            self.settings.backend,
+            self.settings.detection,
+            cancel,
+        ));
+        Ok(())
+    }
+
+    /// Use the result of a background calibration
+    fn finish_calibration(
+        &mut self,
+        i: i32,
+        outcome: calibration_task::CalibrationOutcome,
+        cancelled: bool,
+    ) {
+        let mut images = outcome.images;
+        let count = images.len();
+        // Captures made while calibrating are kept after the ones that were used
+        images.append(&mut self.charuco_images);
+        self.charuco_images = images;
+        if cancelled {
+            self.toasts.info(tr!("info.calibration_cancelled"));
+            return;
+        }
+        let r = outcome.result;
+        let camera = self.source_name(i);
+        self.audit(audit::Event::CalibrationRun {
+            camera: camera.clone(),
+            board: self.settings.board.description(),
+            images: count,
+            rms: r.as_ref().ok().map(|(_, rms, _, _)| *rms),
+        });
+        let (cd, rms, view_errors, residuals) = match r {
+            Ok(r) => r,
+            Err(e) => {
+                println!("Calibration failed {:?}", e);
+                self.toasts.error(tr!("error.calibration"));
+                return;
+            }
+        };
+        if let Some(h) = &mut self.history {
+            if let Err(e) = h.record(
+                &camera,
+                rms,
+                count,
+                &self.settings.board.description(),
+                &cd,
+                self.temperature.temperature(),
+            ) {
+                self.toasts
+                    .error(tr!("error.record_history", error = format!("{:?}", e)));
+            }
+        }
+        let metadata = image_proc::calibration::CalibrationMetadata {
+            timestamp: chrono::Local::now().to_rfc3339(),
+            camera: camera.clone(),
+            board: self.settings.board.description(),
+            resolution: self
+                .charuco_images
+                .first()
+                .map(|m| [m.cols() as u32, m.rows() as u32]),
+            rms,
+            view_errors,
+            distance_checks: Vec::new(),
+            temperature: self.temperature.temperature(),
+        };
+        if let Err(e) = self.reminders.calibrated(&camera) {
+            self.toasts
+                .error(tr!("error.save_reminders", error = e.to_string()));
+        }
+        self.residuals.set(residuals);
+        self.show_residuals = !self.residuals.is_empty();
+        let output = &self.settings.output;
+        let r = output
+            .create(&output.calibration_template, Some(i))
+            .and_then(|path| self.write_calibration(&cd, &path).map(|_| path))
+            .and_then(|path| metadata.save(&path).map(|_| path));
+        let path = match r {
+            Ok(path) => {
+                let path = std::path::absolute(&path).unwrap_or(path);
+                self.audit(audit::Event::FileWritten {
+                    kind: audit::FileKind::Calibration,
+                    path: path.clone(),
+                });
+                settings::add_recent(&mut self.settings.recent.calibrations, &path);
+                Some(path)
+            }
+            Err(e) => {
+                self.toasts
+                    .error(tr!("error.save_calibration", error = format!("{:?}", e)));
+                None
+            }
+        };
+        let file = path.as_ref().map(|p| p.display().to_string());
+        self.backup_calibration(i, &cd, path.as_deref());
+        webhook::send(
+            &self.settings.webhook,
+            webhook::CalibrationComplete::new(camera, rms, count, file),
+            self.task_done.0.clone(),
+        );
+        let resolution = self
+            .charuco_images
+            .first()
+            .map(|m| [m.cols() as u32, m.rows() as u32]);
+        // The user may have selected another camera while calibrating
+        if self.processing_camera == Some(i) {
+            self.cd = Some(cd);
+            self.cd_path = path;
+            self.cd_resolution = resolution;
+            self.calibration_rms = Some(rms);
+        } else {
+            let current = CameraProcessing {
+                pipeline: self.pipeline.clone(),
+                cd: None,
+                cd_path: None,
+                apply_cd: self.apply_cd,
+                calibration_rms: None,
+                cd_resolution: None,
+            };
+            let p = self.processing.entry(Some(i)).or_insert(current);
+            p.cd = Some(cd);
+            p.cd_path = path;
+            p.cd_resolution = resolution;
+            p.calibration_rms = Some(rms);
+        }
+    }
+
+    /// Ask the user for a file and write a report of the calibration and the captures it was made from
+    fn generate_report(&mut self) {
+        let Some(cd) = &self.cd else {
+            return;
+        };
+        let Some(f) = rfd::FileDialog::new()
+            .add_filter("HTML", &["html"])
+            .set_file_name("calibration_report.html")
+            .set_directory("./")
+            .save_file()
+        else {
+            return;
+        };
+        let input = report::ReportInput {
+            camera: self
+                .selected_camera
+                .map(|i| self.source_name(i))
+                .unwrap_or_default(),
+            board: &self.settings.board,
+            charuco_board: &self.charuco_board,
+            images: &self.charuco_images,
+            calibration: cd,
+            rms: self.calibration_rms,
+        };
+        match report::generate(&f, &input) {
+            Ok(()) => {
+                self.audit(audit::Event::FileWritten {
+                    kind: audit::FileKind::Report,
+                    path: f.clone(),
+                });
+                self.toasts
+                    .info(tr!("info.generated_report", path = f.display()))
+            }
+            Err(e) => self.toasts.error(tr!("error.generate_report", error = e)),
+        };
+    }
+
+    /// Upload a calibration and optionally the images it was made from, when backups are enabled.
+    /// The calibration is uploaded as the file it was saved to, with its checksum and signature.
+    fn backup_calibration(&mut self, i: i32, cd: &CalibrationData, path: Option<&Path>) {
+        let Some(target) = self.settings.backup.target.clone() else {
+            return;
+        };
+        let output = &self.settings.output;
+        let name = path
+            .map(Path::to_path_buf)
+            .unwrap_or_else(|| output.expand(&output.calibration_template, Some(i)))
+            .file_name()
+            .map(|n| n.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        let mut files = Vec::new();
+        match self.sealed_calibration(cd) {
+            Ok(data) => files.push(backup::BackupFile { name, data }),
+            Err(e) => {
+                self.toasts
+                    .error(tr!("error.backup", error = format!("{:?}", e)));
+                return;
+            }
+        }
+        if let Some(Ok(data)) = self.audit.as_ref().map(|a| a.contents()) {
+            files.push(backup::BackupFile {
+                name: audit::AuditLog::FILE.to_string(),
+                data,
+            });
+        }
+        if self.settings.backup.include_captures {
+            for (n, img) in self.charuco_images.iter().enumerate() {
+                let mut data = opencv::core::Vector::<u8>::new();
+                if let Ok(true) = opencv::imgcodecs::imencode(
+                    ".png",
+                    &**img,
+                    &mut data,
+                    &opencv::core::Vector::new(),
+                ) {
+                    files.push(backup::BackupFile {
+                        name: format!("capture_{:03}.png", n),
+                        data: data.to_vec(),
+                    });
+                }
+            }
+        }
+        let folder = format!(
+            "camera_{}_{}",
+            i,
+            chrono::Local::now().format("%Y%m%d-%H%M%S")
+        );
+        backup::start_backup(target, folder, files, self.task_done.0.clone());
+    }
+
+    /// Find the calibration board in an image with the detection settings
+    fn detect_board(
+        &self,
+        img: &opencv::core::Mat,
+    ) -> opencv::Result<image_proc::calibration::DetectionResult> {
+        if let Some(g) = self.settings.board.grid() {
+            return g.detect(img);
+        }
+        let d = self.settings.board.dictionary().ok_or_else(|| {
+            opencv::Error::new(opencv::core::StsBadArg, "The board dictionary is not valid")
+        })?;
+        image_proc::calibration::detect_board(img, &self.charuco_board, &d, self.settings.detection)
+    }
+
+    /// Count the charuco corners found in an image.
+    /// With debug, the corners are drawn into debug and saved.
+    fn check_charuco_image(
+        &mut self,
+        img: &opencv::core::Mat,
+        debug: Option<&mut opencv::core::Mat>,
+    ) -> i32 {
+        let result = match self.detect_board(img) {
+            Ok(r) => r,
+            Err(e) => {
+                if debug.is_some() {
+                    self.toasts
+                        .error(tr!("error.detect_board", error = e.to_string()));
+                }
+                return 0;
+            }
+        };
+        if let Some(debug) = debug {
+            let output = &self.settings.output;
+            let path = output
+                .create_unique(&output.corners_template, self.selected_camera)
+                .unwrap_or_else(|_| output.expand(&output.corners_template, None));
+            let r = image_proc::aruco::draw_corners(
+                debug,
+                &result.corners,
+                opencv::core::VecN([255.0, 0.0, 0.0, 255.0]),
+            )
+            .and_then(|_| {
+                opencv::imgcodecs::imwrite(
+                    &path.to_string_lossy(),
+                    debug,
+                    &opencv::core::Vector::new(),
+                )
+            });
+            match r {
+                Ok(true) => {}
+                Ok(false) => self.toasts.error(tr!(
+                    "error.save_corners",
+                    error = path.display().to_string()
+                )),
+                Err(e) => self
+                    .toasts
+                    .error(tr!("error.save_corners", error = e.to_string())),
+            }
+        }
+        result.corners.len() as i32
+    }
+}
+
+/// The pixel of an image under the mouse, response is the response of the image widget, size is the size of the image in pixels
+fn image_pixel(response: &eframe::egui::Response, size: [usize; 2]) -> Option<[usize; 2]> {
+    let p = response.hover_pos()? - response.rect.min;
+    let x = p.x / response.rect.width() * size[0] as f32;
+    let y = p.y / response.rect.height() * size[1] as f32;
+    if x < 0.0 || y < 0.0 || x >= size[0] as f32 || y >= size[1] as f32 {
+        return None;
+    }
+    Some([x as usize, y as usize])
+}
+
+/// Ask the user for an image file and load it
+fn pick_image_file() -> Option<ColorImage> {
+    let f = rfd::FileDialog::new()
+        .add_filter("Image", &["jpg", "png"])
+        .set_directory("./")
+        .pick_file()?;
+    load_image_file(&f)
+}
+
+/// Load an image file
+fn load_image_file(path: &Path) -> Option<ColorImage> {
+    let mut f = std::fs::File::open(path).ok()?;
+    let mut c = Vec::new();
+    f.read_to_end(&mut c).ok()?;
+    let reader = image::ImageReader::new(std::io::Cursor::new(&c))
+        .with_guessed_format()
+        .ok()?;
+    let img = decode_oriented(reader).ok()?.into_rgba8();
+    Some(ColorImage::from_rgba_unmultiplied(
+        [img.width() as usize, img.height() as usize],
+        img.as_raw(),
+    ))
+}
+
+/// Decode an image turned the way its orientation metadata says, so photos taken in portrait are not shown on their side
+fn decode_oriented<R: std::io::BufRead + std::io::Seek>(
+    reader: image::ImageReader<R>,
+) -> image::ImageResult<image::DynamicImage> {
+    let mut decoder = reader.into_decoder()?;
+    let orientation = decoder
+        .orientation()
+        .unwrap_or(image::metadata::Orientation::NoTransforms);
+    let mut img = image::DynamicImage::from_decoder(decoder)?;
+    img.apply_orientation(orientation);
+    Ok(img)
+}
+
+/// The largest size of an image that fits within bounds without changing its aspect ratio,
+/// rounded to whole screen pixels for ppp screen pixels per point
+fn fit_size(size: eframe::egui::Vec2, bounds: eframe::egui::Vec2, ppp: f32) -> eframe::egui::Vec2 {
+    dpi::snap_size(size * (bounds.x / size.x).min(bounds.y / size.y), ppp)
+}
+
+/// Actions from the file menu
+enum FileAction {
+    PickImage,
+    PickVideo,
+    PickCalibration,
+    SaveCalibration,
+    OpenImage(PathBuf),
+    OpenVideo(PathBuf),
+    LoadCalibration(PathBuf),
+    ReviewCalibration,
+}
+
+impl eframe::App for MainData {
+    fn on_exit(&mut self, _gl: Option<&eframe::glow::Context>) {
+        // An event still being recorded is saved with the frames it has so far
+        if let Some(frames) = self.motion.finish() {
+            self.save_motion_event(frames);
+        }
+        let deadline = Instant::now() + SHUTDOWN_TIMEOUT;
+        if let Some(t) = self.image_thread.take() {
+            // The quit message waits for room in its queue while frames are drained,
+            // so a camera thread blocked on a full queue to the window can get to it
+            let mut quit = Some(ToCameraThread::Quit);
+            while !t.is_finished() && Instant::now() < deadline {
+                if let Some(m) = quit.take() {
+                    if let Err(crossbeam::channel::TrySendError::Full(m)) =
+                        self.to_image_thread.try_send(m)
+                    {
+                        quit = Some(m);
+                    }
+                }
+                while self.from_image_thread.try_recv().is_ok() {}
+                std::thread::sleep(Duration::from_millis(5));
+            }
+            if t.is_finished() {
+                let _ = t.join();
+            } else {
+                println!("The camera thread did not stop in time");
+            }
+        }
+        if !self.tasks.wait(deadline) {
+            println!("Background tasks were still running on exit");
+        }
+        // A lost session the user has not decided about is kept for the next launch
+        if self.recovery_offer.is_none() {
+            self.recovery.clear();
+        }
+    }
+
+    fn save(&mut self, storage: &mut dyn eframe::Storage) {
+        self.settings.save(storage);
+    }
+
+    fn update(&mut self, ctx: &eframe::egui::Context, _frame: &mut eframe::Frame) {
+        ctx.request_repaint_after(Duration::from_millis(10));
+        let mut use_newest_image = std::mem::take(&mut self.capture_next);
+        self.sync_processing();
+        self.cursor_pixel = None;
+        self.flush_to_camera_thread();
+        while let Ok(a) = self.from_image_thread.try_recv() {
+            match a {
+                FromCameraThread::CameraImage(i, bm, capture) => {
+                    self.frame_rates.entry(i).or_default().add_frame();
+                    self.reminders.frame(i);
+                    if self.selected_camera == Some(i) {
+                        self.profiler.record(profiler::Stage::Capture, capture);
+                    }
+                    if let Some(j) = self.selected_camera {
+                        // The cameras of the stereo pair stay open together so pairs can be captured
+                        if j != i && !self.stereo.contains(i) {
+                            self.send_to_camera_thread(ToCameraThread::CloseCamera(i));
+                        } else if j == i {
+                            self.flicker.add_frame(&bm);
+                            self.freeze.add_frame(&bm);
+                            if let Some(still) = self.burst.add_frame(ctx, &bm) {
+                                self.save_still(i, &still);
+                            }
+                            self.ring_buffer.add_frame(i, &bm);
+                            if let Some(frames) = self.motion.add_frame(i, &bm) {
+                                self.save_motion_event(frames);
+                            }
+                            if let Some(n) = self.noise.add_frame(&bm) {
+                                let p = self.profiles.entry(i).or_default();
+                                p.noise = Some(n);
+                                if let Err(e) = p.save(&self.settings.output.working_directory, i) {
+                                    self.toasts.error(tr!(
+                                        "error.save_profile",
+                                        error = format!("{:?}", e)
+                                    ));
+                                }
+                            }
+                            if let Some(r) = self.rolling_shutter.add_frame(&bm) {
+                                let p = self.profiles.entry(i).or_default();
+                                p.rolling_shutter = Some(r);
+                                if let Err(e) = p.save(&self.settings.output.working_directory, i) {
+                                    self.toasts.error(tr!(
+                                        "error.save_profile",
+                                        error = format!("{:?}", e)
+                                    ));
+                                }
+                            }
+                            if let Some(v) = self.vignetting.add_frame(&bm) {
+                                let p = self.profiles.entry(i).or_default();
+                                p.vignetting = Some(v);
+                                if let Err(e) = p.save(&self.settings.output.working_directory, i) {
+                                    self.toasts.error(tr!(
+                                        "error.save_profile",
+                                        error = format!("{:?}", e)
+                                    ));
+                                }
+                            }
+                        }
+                    }
+                    if self.selected_camera == Some(i) && self.auto_load.remove(&i) {
+                        self.auto_load_calibration(i, [bm.cols() as u32, bm.rows() as u32]);
+                    }
+                    self.image_set.insert(i, bm);
+                    if let Some(c) = self.stereo.frame(i) {
+                        self.capture_stereo_pair(c);
+                    }
+                }
+                FromCameraThread::CameraState(i, true) => {
+                    self.open_cameras.insert(i);
+                    self.auto_load.insert(i);
+                    let source = self.source_name(i);
+                    self.audit(audit::Event::CameraOpened { camera: i, source });
+                }
+                FromCameraThread::CameraState(i, false) => {
+                    self.open_cameras.remove(&i);
+                    let source = self.source_name(i);
+                    self.audit(audit::Event::CameraClosed { camera: i, source });
+                    self.frame_rates.remove(&i);
+                }
+                FromCameraThread::Zoom(i, z) => self.zoom_lens.set_reported(i, z),
+                FromCameraThread::CameraFailed(i) => {
+                    self.open_cameras.remove(&i);
+                    self.frame_rates.remove(&i);
+                    let name = self.source_name(i);
+                    self.toasts.error(tr!("error.camera_failed", name = name));
+                }
+            }
+        }
+        if let Some(i) = self.selected_camera {
+            let profile = self.profiles.get(&i).and_then(|p| p.zoom.as_ref());
+            if let Some(z) = self.zoom_lens.changed(i, profile) {
+                self.apply_zoom_calibration(i, z);
+            }
+        }
+        self.tasks.remove_finished();
+        // The captures are away while calibrating, and a lost session is not overwritten before the user decides
+        if self.recovery_offer.is_none() && self.calibration_task.is_none() {
+            if let Err(e) =
+                self.recovery
+                    .tick(&self.charuco_images, self.cd.as_ref(), self.cd_resolution)
+            {
+                self.toasts.error(tr!("error.autosave", error = e));
+            }
+        }
+        if self.reminders.should_check() {
+            let names: Vec<(i32, String)> = self
+                .reminders
+                .running()
+                .map(|i| (i, self.source_name(i)))
+                .collect();
+            if let Err(e) = self.reminders.tick(&self.settings.reminders, &names) {
+                self.toasts
+                    .error(tr!("error.save_reminders", error = e.to_string()));
+            }
+        }
+        if self.settings.queues.backpressure != self.backpressure {
+            self.backpressure = self.settings.queues.backpressure;
+            self.send_to_camera_thread(ToCameraThread::SetBackpressure(self.backpressure));
+        }
+        if let Some(t) = &self.calibration_task {
+            if let Some(outcome) = t.poll() {
+                let (camera, cancelled) = (t.camera, t.cancelled());
+                self.calibration_task = None;
+                self.finish_calibration(camera, outcome, cancelled);
+            }
+        }
+        match self.large_image.poll() {
+            Some(Ok(overview)) => {
+                self.original_image = None;
+                self.set_image(ctx, overview);
+            }
+            Some(Err(e)) => self.toasts.error(tr!("error.open_large_image", error = e)),
+            None => {}
+        }
+        while let Ok(r) = self.task_done.1.try_recv() {
+            match r {
+                Ok(m) => self.toasts.info(m),
+                Err(m) => self.toasts.error(m),
+            }
+        }
+        for e in self
+            .watch
+            .update(&self.settings.watch, &self.pipeline, self.cd.as_ref())
+        {
+            self.toasts.error(e);
+        }
+        for a in self.gamepads.poll(&self.settings.gamepad) {
+            match a {
+                gamepad::GamepadAction::Capture => use_newest_image = true,
+                gamepad::GamepadAction::Calibrate => self.calibrate_selected(),
+                gamepad::GamepadAction::NextCamera => self.switch_camera(1),
+                gamepad::GamepadAction::PreviousCamera => self.switch_camera(-1),
+            }
+        }
+        if ctx.input_mut(|i| i.consume_key(eframe::egui::Modifiers::NONE, eframe::egui::Key::F11)) {
+            self.set_kiosk(ctx, !self.kiosk);
+        }
+        if self.kiosk {
+            if ctx.input_mut(|i| {
+                i.consume_key(eframe::egui::Modifiers::NONE, eframe::egui::Key::Escape)
+            }) {
+                self.set_kiosk(ctx, false);
+            }
+            self.update_preview(ctx, use_newest_image);
+            self.show_kiosk(ctx);
+            self.toasts.show(ctx);
+            return;
+        }
+        self.menu_bar(ctx);
+        self.status_bar(ctx);
+        eframe::egui::CentralPanel::default().show(ctx, |ui| {
+            egui_extras::install_image_loaders(ctx);
+
+            if let Some((from, to)) = self.resolution_mismatch() {
+                let key = if self.scale_cd {
+                    "main.resolution_scaled"
+                } else {
+                    "main.resolution_mismatch"
+                };
+                eframe::egui::Frame::new()
+                    .fill(eframe::egui::Color32::from_rgb(120, 60, 0))
+                    .inner_margin(8.0)
+                    .show(ui, |ui| {
+                        ui.set_width(ui.available_width());
+                        ui.label(
+                            eframe::egui::RichText::new(tr!(
+                                key,
+                                calibrated = format!("{}x{}", from[0], from[1]),
+                                camera = format!("{}x{}", to[0], to[1])
+                            ))
+                            .strong()
+                            .color(eframe::egui::Color32::WHITE),
+                        );
+                    });
+            }
+            eframe::egui::ScrollArea::vertical().show(ui, |ui| {
+                ui.horizontal(|ui| {
+                    let names: Vec<(i32, String)> = self
+                        .live_cameras
+                        .iter()
+                        .map(|i| (*i, self.source_name(*i)))
+                        .collect();
+                    let selected = self
+                        .selected_camera
+                        .map(|i| self.source_name(i))
+                        .unwrap_or_else(|| tr!("main.none"));
+                    eframe::egui::ComboBox::from_label(tr!("main.select_camera"))
+                        .selected_text(selected)
+                        .show_ui(ui, |ui| {
+                            for (i, name) in names {
+                                ui.selectable_value(&mut self.selected_camera, Some(i), name);
                             }
                         });
-                    if ui.button("Open camera").clicked() {
+                    if ui.button(tr!("main.open_camera")).clicked() {
+                        if let Some(i) = self.selected_camera {
+                            self.send_to_camera_thread(ToCameraThread::OpenCamera(i));
+                            self.send_to_camera_thread(ToCameraThread::SetAveraging(
+                                i,
+                                self.averaging,
+                                self.pipeline.linear,
+                            ));
+                        }
+                    }
+                    if ui.button(tr!("main.close_camera")).clicked() {
                         if let Some(i) = self.selected_camera {
-                            let _ = self.to_image_thread.send(ToCameraThread::OpenCamera(i));
+                            self.send_to_camera_thread(ToCameraThread::CloseCamera(i));
                         }
                     }
-                    if ui.button("Close camera").clicked() {
+                    if let Some(i) = self.selected_camera.filter(|i| *i >= 0) {
+                        if ui.button(tr!("main.open_thermal")).clicked() {
+                            self.open_thermal(i);
+                        }
+                    }
+                    if self
+                        .selected_camera
+                        .is_some_and(|i| self.thermal_maps.contains_key(&i))
+                    {
+                        ui.checkbox(&mut self.heated_board, tr!("main.heated_board"));
+                    }
+                    if self.averaging.show(ui) {
                         if let Some(i) = self.selected_camera {
-                            let _ = self.to_image_thread.send(ToCameraThread::CloseCamera(i));
+                            self.send_to_camera_thread(ToCameraThread::SetAveraging(
+                                i,
+                                self.averaging,
+                                self.pipeline.linear,
+                            ));
                         }
                     }
                 });
+                let mut preset_action = None;
+                ui.horizontal(|ui| {
+                    preset_action = self.presets.show(ui, &self.settings.presets);
+                });
+                if let Some(a) = preset_action {
+                    self.preset_action(ctx, a);
+                }
                 ui.horizontal(|ui| {
-                    if ui.button("Open image").clicked() {
+                    if ui.button(tr!("main.open_image")).clicked() {
                         let f = rfd::FileDialog::new()
                             .add_filter("Image", &["jpg", "png"])
-                            .set_directory("./")
+                            .set_directory(settings::recent_directory(
+                                &self.settings.recent.images,
+                                &self.settings.output.working_directory,
+                            ))
                             .pick_file();
-                        if let Some(f) = &f {
-                            let f2 = std::fs::File::open(f);
-                            if let Ok(mut f) = f2 {
-                                let mut c = Vec::new();
-                                let _ = f.read_to_end(&mut c);
-                                let img = egui_extras::image::load_image_bytes(&c);
-                                if let Ok(img) = img {
-                                    let a = ctx.load_texture(
-                                        "actual_image",
-                                        img.clone(),
-                                        eframe::egui::TextureOptions::LINEAR,
-                                    );
-                                    self.actual_image.replace(img);
-                                    self.img.replace(a);
-                                }
-                            }
+                        if let Some(f) = f {
+                            self.open_image(ctx, &f);
+                        }
+                    }
+                    ui.toggle_value(&mut self.detached_preview, tr!("main.detach_preview"));
+                    if ui.button(tr!("main.kiosk")).clicked() {
+                        self.set_kiosk(ctx, true);
+                    }
+                    if ui.button(tr!("main.copy_view")).clicked() {
+                        match self.copy_view() {
+                            Ok(()) => self.toasts.info(tr!("info.copied_view")),
+                            Err(e) => self
+                                .toasts
+                                .error(tr!("error.copy_view", error = format!("{:?}", e))),
                         }
                     }
-                    if ui.button("Generate charuco pattern").clicked() {
+                    if ui.button(tr!("main.export_view")).clicked() {
+                        self.export_view();
+                    }
+                    if ui.button(tr!("main.export_montage")).clicked() {
+                        self.export_montage();
+                    }
+                    if ui.button(tr!("main.compare_images")).clicked() {
+                        self.show_comparison = true;
+                    }
+                    if ui.button(tr!("main.flicker_detection")).clicked() {
+                        self.show_flicker = true;
+                    }
+                    if ui.button(tr!("main.noise_profile")).clicked() {
+                        self.show_noise = true;
+                    }
+                    if ui.button(tr!("main.processing_pipeline")).clicked() {
+                        self.show_pipeline = true;
+                    }
+                    if ui.button(tr!("main.rolling_shutter")).clicked() {
+                        self.show_rolling_shutter = true;
+                    }
+                    if ui.button(tr!("main.undistort_points")).clicked() {
+                        self.show_undistort = true;
+                    }
+                    if ui.button(tr!("main.projection")).clicked() {
+                        self.show_projection = true;
+                    }
+                    if ui.button(tr!("main.stereo")).clicked() {
+                        self.show_stereo = true;
+                    }
+                    if ui.button(tr!("main.burst_capture")).clicked() {
+                        self.show_burst = true;
+                    }
+                    if ui.button(tr!("main.ring_buffer")).clicked() {
+                        self.show_ring_buffer = true;
+                    }
+                    if ui.button(tr!("main.motion_trigger")).clicked() {
+                        self.show_motion = true;
+                    }
+                    if ui.button(tr!("main.large_image")).clicked() {
+                        self.show_large_image = true;
+                    }
+                    if ui.button(tr!("main.vignetting")).clicked() {
+                        self.show_vignetting = true;
+                    }
+                    if ui.button(tr!("main.gray_card")).clicked() {
+                        self.show_gray_card = true;
+                    }
+                    if ui.button(tr!("main.frame_timing")).clicked() {
+                        self.show_profiler = true;
+                    }
+                    if ui.button(tr!("main.residuals")).clicked() {
+                        self.show_residuals = true;
+                    }
+                    if ui.button(tr!("main.distortion_explorer")).clicked() {
+                        self.show_distortion_explorer = true;
+                    }
+                    if ui.button(tr!("main.straight_lines")).clicked() {
+                        self.show_straightness = true;
+                    }
+                    if ui.button(tr!("main.distance_check")).clicked() {
+                        self.show_distance = true;
+                    }
+                    if ui.button(tr!("main.sensor_modes")).clicked() {
+                        self.show_sensor_modes = true;
+                    }
+                    if ui.button(tr!("main.zoom_lens")).clicked() {
+                        self.show_zoom_lens = true;
+                    }
+                    if ui.button(tr!("main.generate_charuco")).clicked() {
                         self.save_charuco_image();
                     }
-                    if ui.button("Save charuco capture from camera").clicked() {
+                    if ui.button(tr!("main.save_charuco_capture")).clicked() {
                         use_newest_image = true;
                     }
-                    if ui.button("Use charuco mat directly").clicked() {
-                        let m = self.make_charuco_mat();
-                        self.charuco_images.push(m);
+                    if ui.button(tr!("main.use_charuco_mat")).clicked() {
+                        let m = self.make_charuco_mat(BOARD_WIDTH);
+                        self.charuco_images.push(m.into());
+                        self.audit(audit::Event::FrameCaptured {
+                            camera: None,
+                            count: self.charuco_images.len(),
+                        });
                     }
-                    if ui.button("Clear saved images").clicked() {
+                    if ui.button(tr!("main.clear_saved_images")).clicked() {
                         self.charuco_images.clear();
                     }
-                    if ui.button("Do calibration").clicked() {
-                        if let Some(i) = self.selected_camera {
-                            let _ = self.calibrate_camera(i);
+                    if ui.button(tr!("main.calibration_wizard")).clicked() {
+                        self.wizard.restart(&self.settings.board);
+                        self.show_wizard = true;
+                    }
+                    if ui.button(tr!("main.screen_capture")).clicked() {
+                        self.show_screen_capture = true;
+                    }
+                    if ui.button(tr!("main.watch_folder")).clicked() {
+                        self.show_watch = true;
+                    }
+                    if ui.button(tr!("main.calibration_history")).clicked() {
+                        self.show_history = true;
+                    }
+                    if ui.button(tr!("main.audit_log")).clicked() {
+                        self.show_audit = true;
+                    }
+                    if let Some(t) = &self.calibration_task {
+                        ui.spinner();
+                        ui.label(tr!("main.calibrating"));
+                        if ui
+                            .add_enabled(
+                                !t.cancelled(),
+                                eframe::egui::Button::new(tr!("main.cancel")),
+                            )
+                            .clicked()
+                        {
+                            t.cancel();
+                        }
+                    } else {
+                        let model = &mut self.settings.camera_model;
+                        eframe::egui::ComboBox::from_id_salt("camera_model")
+                            .selected_text(match model {
+                                CameraModel::Pinhole => tr!("main.model_pinhole"),
+                                CameraModel::Fisheye => tr!("main.model_fisheye"),
+                                CameraModel::Telecentric => tr!("main.model_telecentric"),
+                            })
+                            .show_ui(ui, |ui| {
+                                ui.selectable_value(
+                                    model,
+                                    CameraModel::Pinhole,
+                                    tr!("main.model_pinhole"),
+                                );
+                                ui.selectable_value(
+                                    model,
+                                    CameraModel::Fisheye,
+                                    tr!("main.model_fisheye"),
+                                );
+                                ui.selectable_value(
+                                    model,
+                                    CameraModel::Telecentric,
+                                    tr!("main.model_telecentric"),
+                                );
+                            });
+                        #[cfg(feature = "native")]
+                        {
+                            use image_proc::calibration::Backend;
+                            let backend = &mut self.settings.backend;
+                            eframe::egui::ComboBox::from_id_salt("calibration_backend")
+                                .selected_text(match backend {
+                                    Backend::OpenCv => tr!("main.backend_opencv"),
+                                    Backend::Native => tr!("main.backend_native"),
+                                })
+                                .show_ui(ui, |ui| {
+                                    ui.selectable_value(
+                                        backend,
+                                        Backend::OpenCv,
+                                        tr!("main.backend_opencv"),
+                                    );
+                                    ui.selectable_value(
+                                        backend,
+                                        Backend::Native,
+                                        tr!("main.backend_native"),
+                                    );
+                                });
+                        }
+                        if ui.button(tr!("main.do_calibration")).clicked() {
+                            self.calibrate_selected();
                         }
                     }
+                    self.temperature.show(ui, &self.settings.thermometer);
+                    if ui
+                        .add_enabled(
+                            self.cd.is_some() && !self.charuco_images.is_empty(),
+                            eframe::egui::Button::new(tr!("main.generate_report")),
+                        )
+                        .clicked()
+                    {
+                        self.generate_report();
+                    }
                 });
                 if ui.button("Debug1").clicked() {
-                    let m = Box::new(self.make_charuco_mat());
-                    let mut newmat = self.make_charuco_mat();
+                    // Drawn at the size the preview shows it in screen pixels, so it is not scaled and stays sharp
+                    let width = (ui.available_width() * 0.5 * ctx.pixels_per_point()) as i32;
+                    let m = self.make_charuco_mat(width.max(200));
+                    let mut newmat = m.clone();
                     self.check_charuco_image(&m, Some(&mut newmat));
-                    let data = newmat.data_bytes().unwrap();
-                    let dims = [newmat.cols() as usize, newmat.rows() as usize];
-                    let data: Vec<u8> = data.iter().map(|a| [*a, *a, *a]).flatten().collect();
-                    let cimg = eframe::egui::ColorImage::from_rgb(dims, &data);
-                    let a = ctx.load_texture(
-                        "actual_image",
-                        cimg.clone(),
-                        eframe::egui::TextureOptions::LINEAR,
-                    );
-                    self.actual_image.replace(cimg);
-                    self.img.replace(a);
+                    let dims = [m.cols() as usize, m.rows() as usize];
+                    let cimg = convert::mat_to_color_image(&m).unwrap();
+                    self.original_image = None;
+                    self.set_image(ctx, cimg);
+                    // The corners are drawn over the preview instead of into the image
+                    let corners = self.detect_charuco_corners(&m);
+                    self.detections.set(dims, corners);
                 }
-                ui.checkbox(&mut self.apply_cd, "Apply calibration");
-                ui.label(format!(
-                    "There are {} saved charuco images",
-                    self.charuco_images.len()
-                ));
-                if let Some(i) = &self.selected_camera {
-                    if let Some(img) = self.image_set.get(i) {
-                        if use_newest_image {
-                            self.charuco_images.push(*img.clone());
-                        }
-                        if let Some(cd) = &self.cd {
-                            if let Ok(data) = img.data_bytes() {
-                                let dims = [img.cols() as usize, img.rows() as usize];
-                                let egui_img = eframe::egui::ColorImage::from_rgb(dims, data);
-                                let cimg = cd.apply_calibration(egui_img);
-                                let a = ctx.load_texture(
-                                    "actual_image",
-                                    cimg.clone(),
-                                    eframe::egui::TextureOptions::LINEAR,
-                                );
-                                self.actual_image.replace(cimg);
-                                self.img.replace(a);
-                            }
-                        } else {
-                            if let Ok(data) = img.data_bytes() {
-                                let dims = [img.cols() as usize, img.rows() as usize];
-                                let cimg = eframe::egui::ColorImage::from_rgb(dims, data);
-                                let a = ctx.load_texture(
-                                    "actual_image",
-                                    cimg.clone(),
-                                    eframe::egui::TextureOptions::LINEAR,
-                                );
-                                self.actual_image.replace(cimg);
-                                self.img.replace(a);
+                ui.horizontal(|ui| {
+                    let mismatch = self.resolution_mismatch();
+                    ui.add_enabled(
+                        mismatch.is_none() || self.scale_cd,
+                        eframe::egui::Checkbox::new(
+                            &mut self.apply_cd,
+                            tr!("main.apply_calibration"),
+                        ),
+                    );
+                    if mismatch.is_some() {
+                        ui.checkbox(&mut self.scale_cd, tr!("main.scale_calibration"));
+                    }
+                    let old_mode = self.view_mode;
+                    eframe::egui::ComboBox::from_label(tr!("main.view_mode"))
+                        .selected_text(self.view_mode.name())
+                        .show_ui(ui, |ui| {
+                            for m in [colormap::ViewMode::Normal, colormap::ViewMode::Luminance]
+                                .into_iter()
+                                .chain(
+                                    colormap::Colormap::ALL
+                                        .into_iter()
+                                        .map(colormap::ViewMode::FalseColor),
+                                )
+                                .chain([colormap::ViewMode::Difference])
+                            {
+                                ui.selectable_value(&mut self.view_mode, m, m.name());
                             }
+                        });
+                    let mut gain_changed = false;
+                    if self.view_mode == colormap::ViewMode::Difference {
+                        gain_changed = ui
+                            .add(
+                                eframe::egui::Slider::new(&mut self.difference_gain, 1.0..=64.0)
+                                    .logarithmic(true)
+                                    .text(tr!("main.difference_gain")),
+                            )
+                            .changed();
+                    }
+                    if old_mode != self.view_mode || gain_changed {
+                        if let Some(img) = self.raw_image.clone() {
+                            self.set_image(ctx, img);
                         }
                     }
-                }
-                let w = ui.available_width();
+                });
+                ui.label(tr!(
+                    "main.saved_charuco_images",
+                    count = self.charuco_images.len()
+                ));
+                let newest = self.selected_camera.and_then(|i| self.image_set.get(&i));
+                ui.horizontal(|ui| self.freeze.show(ui, newest));
+                self.update_preview(ctx, use_newest_image);
+                self.annotations
+                    .show_toolbar(ui, self.actual_image.as_ref());
+                // Each preview gets half of the width and at most as much height, so portrait images are not taller than the window
+                let bounds = eframe::egui::Vec2::splat(ui.available_width() * 0.5);
+                let ppp = ctx.pixels_per_point();
+                let mut scale = None;
+                let mut picked = false;
+                let mut gray_card = None;
                 ui.horizontal(|ui| {
-                    if let Some(th) = &self.img {
-                        let z = w / th.size_vec2().x;
+                    if self.detached_preview {
+                        ui.label(tr!("main.preview_detached"));
+                    } else if let Some(th) = &self.img {
                         let st = eframe::egui::load::SizedTexture {
                             id: th.id(),
-                            size: th.size_vec2() * z * 0.5,
+                            size: fit_size(th.size_vec2(), bounds, ppp),
                         };
-                        ui.add(eframe::egui::Image::from_texture(st));
+                        scale = Some(st.size.x * ppp / th.size_vec2().x);
+                        let r = ui.add(
+                            eframe::egui::Image::from_texture(st)
+                                .sense(eframe::egui::Sense::click_and_drag()),
+                        );
+                        self.annotations.interact(ui, &r, th.size());
+                        self.detections.paint(ui, r.rect, th.size());
+                        self.feedback.paint(ui, r.rect);
+                        self.profiler.paint(ui, r.rect);
+                        if r.hovered() {
+                            self.cursor_pixel = image_pixel(&r, th.size());
+                        }
+                        if self.show_projection {
+                            self.projection.paint(ui, r.rect, th.size());
+                        }
+                        if self.undistort.picking && r.clicked() {
+                            if let Some(p) = image_pixel(&r, th.size()) {
+                                self.undistort.add_point(p);
+                            }
+                        }
+                        if self.show_straightness {
+                            self.straightness.paint(ui, r.rect);
+                            if self.straightness.picking && r.clicked() {
+                                if let Some(p) = image_pixel(&r, th.size()) {
+                                    let [w, h] = th.size();
+                                    self.straightness.add_point([
+                                        (p[0] as f32 + 0.5) / w as f32,
+                                        (p[1] as f32 + 0.5) / h as f32,
+                                    ]);
+                                }
+                            }
+                        }
+                        if self.pipeline.picking() && r.clicked() {
+                            if let (Some(p), Some(raw)) =
+                                (image_pixel(&r, th.size()), self.raw_image.clone())
+                            {
+                                let [w, h] = th.size();
+                                self.pipeline.pick(
+                                    raw,
+                                    [
+                                        (p[0] as f32 + 0.5) / w as f32,
+                                        (p[1] as f32 + 0.5) / h as f32,
+                                    ],
+                                );
+                                picked = true;
+                            }
+                        }
+                        if let Some(g) =
+                            self.gray_card
+                                .interact(ui, &r, th.size(), self.raw_image.as_ref())
+                        {
+                            gray_card = Some(g);
+                        }
+                        self.motion.interact(ui, &r);
                     }
 
                     if let Some(th) = &self.corrected_img {
-                        let z = w / th.size_vec2().x;
                         let st = eframe::egui::load::SizedTexture {
                             id: th.id(),
-                            size: th.size_vec2() * z * 0.5,
+                            size: fit_size(th.size_vec2(), bounds, ppp),
                         };
                         ui.add(eframe::egui::Image::from_texture(st));
                     }
                 });
+                if scale.is_some_and(|s| self.preview_shown(s)) {
+                    picked = true;
+                }
+                if picked {
+                    self.refresh_preview(ctx);
+                }
+                if let (Some(g), Some(i)) = (gray_card, self.selected_camera) {
+                    let p = self.profiles.entry(i).or_default();
+                    p.gray_card = Some(g);
+                    if let Err(e) = p.save(&self.settings.output.working_directory, i) {
+                        self.toasts
+                            .error(tr!("error.save_profile", error = format!("{:?}", e)));
+                    }
+                }
 
                 let less_points = &self.scale;
                 let s = (self.scale.len() - 1) as f64;
@@ -768,10 +3562,363 @@ impl eframe::App for MainData {
                 }
             });
         });
+
+        self.show_preview_viewport(ctx);
+        self.show_wizard(ctx);
+        self.show_recovery(ctx);
+        if self.reminders.show(ctx) {
+            self.wizard.restart(&self.settings.board);
+            self.show_wizard = true;
+        }
+
+        let mut open = self.show_settings;
+        eframe::egui::Window::new(tr!("window.settings"))
+            .open(&mut open)
+            .show(ctx, |ui| {
+                self.settings.show(ui);
+            });
+        self.show_settings = open;
+
+        let mut open = self.show_screen_capture;
+        let mut capture = None;
+        eframe::egui::Window::new(tr!("window.screen_capture"))
+            .open(&mut open)
+            .show(ctx, |ui| {
+                capture = self.screen_dialog.show(ui);
+            });
+        self.show_screen_capture = open;
+        if let Some(s) = capture {
+            self.add_screen_capture(s);
+        }
+
+        let mut open = self.show_watch;
+        let mut error = None;
+        eframe::egui::Window::new(tr!("window.watch_folder"))
+            .open(&mut open)
+            .show(ctx, |ui| {
+                error = self.watch.show(ui, &mut self.settings.watch);
+            });
+        self.show_watch = open;
+        if let Some(e) = error {
+            self.toasts.error(e);
+        }
+
+        let mut open = self.show_doctor;
+        let mut again = false;
+        eframe::egui::Window::new(tr!("window.diagnostics"))
+            .open(&mut open)
+            .show(ctx, |ui| {
+                again = self.doctor.show(ui);
+            });
+        self.show_doctor = open;
+        if again {
+            self.run_doctor();
+        }
+
+        let mut open = self.show_history;
+        let mut load = None;
+        eframe::egui::Window::new(tr!("window.calibration_history"))
+            .open(&mut open)
+            .show(ctx, |ui| match &mut self.history {
+                Some(h) => load = h.show(ui),
+                None => {
+                    ui.label(tr!("history.unavailable"));
+                }
+            });
+        self.show_history = open;
+        if let Some(cd) = load {
+            self.cd = Some(cd);
+            self.cd_path = None;
+            self.cd_resolution = None;
+        }
+
+        let mut open = self.show_large_image;
+        eframe::egui::Window::new(tr!("window.large_image"))
+            .open(&mut open)
+            .default_size([800.0, 600.0])
+            .show(ctx, |ui| {
+                self.large_image.show(ui);
+            });
+        self.show_large_image = open;
+
+        let mut open = self.show_audit;
+        eframe::egui::Window::new(tr!("window.audit_log"))
+            .open(&mut open)
+            .show(ctx, |ui| match &mut self.audit {
+                Some(a) => a.show(ui),
+                None => {
+                    ui.label(tr!("audit.unavailable"));
+                }
+            });
+        self.show_audit = open;
+
+        let mut open = self.show_review;
+        let mut pick = None;
+        eframe::egui::Window::new(tr!("window.review_calibration"))
+            .open(&mut open)
+            .show(ctx, |ui| {
+                pick = self.review.show(ui);
+            });
+        self.show_review = open;
+        if let Some(f) = pick {
+            self.review.open(&f, &self.settings.signing.trust());
+        }
+
+        let mut open = self.show_comparison;
+        eframe::egui::Window::new(tr!("window.image_comparison"))
+            .open(&mut open)
+            .show(ctx, |ui| {
+                self.comparison
+                    .show(ui, self.actual_image.as_ref(), self.raw_image.as_ref());
+            });
+        self.show_comparison = open;
+
+        let mut open = self.show_flicker;
+        eframe::egui::Window::new(tr!("window.flicker_detection"))
+            .open(&mut open)
+            .show(ctx, |ui| {
+                self.flicker.show(ui);
+            });
+        self.show_flicker = open;
+
+        let mut open = self.show_profiler;
+        eframe::egui::Window::new(tr!("window.frame_timing"))
+            .open(&mut open)
+            .show(ctx, |ui| {
+                self.profiler.show(ui);
+            });
+        self.show_profiler = open;
+
+        let mut open = self.show_residuals;
+        eframe::egui::Window::new(tr!("window.residuals"))
+            .open(&mut open)
+            .show(ctx, |ui| {
+                self.residuals.show(ui);
+            });
+        self.show_residuals = open;
+
+        let mut open = self.show_distortion_explorer;
+        eframe::egui::Window::new(tr!("window.distortion_explorer"))
+            .open(&mut open)
+            .show(ctx, |ui| {
+                self.distortion_explorer.show(ui, self.cd.as_ref());
+            });
+        self.show_distortion_explorer = open;
+
+        let mut open = self.show_straightness;
+        let mut request = None;
+        eframe::egui::Window::new(tr!("window.straight_lines"))
+            .open(&mut open)
+            .show(ctx, |ui| {
+                request = self.straightness.show(ui, self.cd.is_some());
+            });
+        self.show_straightness = open;
+        if !open {
+            self.straightness.picking = false;
+        }
+        if let Some(r) = request {
+            let results = self.check_straightness(r);
+            self.straightness.set_results(results);
+        }
+
+        let mut open = self.show_distance;
+        let mut check = false;
+        eframe::egui::Window::new(tr!("window.distance_check"))
+            .open(&mut open)
+            .show(ctx, |ui| {
+                check = self.distance.show(ui, self.cd.is_some());
+            });
+        self.show_distance = open;
+        if check {
+            self.check_distance();
+        }
+
+        let mut open = self.show_sensor_modes;
+        let mut changed = false;
+        eframe::egui::Window::new(tr!("window.sensor_modes"))
+            .open(&mut open)
+            .show(ctx, |ui| {
+                let Some(i) = self.selected_camera else {
+                    ui.label(tr!("sensor_mode.no_camera"));
+                    return;
+                };
+                let current = self
+                    .image_set
+                    .get(&i)
+                    .map(|f| [f.cols() as u32, f.rows() as u32]);
+                let modes = self
+                    .profiles
+                    .entry(i)
+                    .or_default()
+                    .sensor_modes
+                    .get_or_insert_default();
+                changed = modes.show(ui, current);
+            });
+        self.show_sensor_modes = open;
+        if changed {
+            if let Some(i) = self.selected_camera {
+                let p = self.profiles.entry(i).or_default();
+                if let Err(e) = p.save(&self.settings.output.working_directory, i) {
+                    self.toasts
+                        .error(tr!("error.save_profile", error = format!("{:?}", e)));
+                }
+            }
+        }
+
+        let mut open = self.show_zoom_lens;
+        let mut action = None;
+        eframe::egui::Window::new(tr!("window.zoom_lens"))
+            .open(&mut open)
+            .show(ctx, |ui| {
+                let camera = self.selected_camera;
+                let profile = camera
+                    .and_then(|i| self.profiles.get(&i))
+                    .and_then(|p| p.zoom.as_ref());
+                action = self.zoom_lens.show(ui, camera, profile, self.cd.is_some());
+            });
+        self.show_zoom_lens = open;
+        if let Some(a) = action {
+            self.zoom_action(a);
+        }
+
+        let mut open = self.show_noise;
+        eframe::egui::Window::new(tr!("window.noise_profile"))
+            .open(&mut open)
+            .show(ctx, |ui| {
+                let p = self.selected_camera.and_then(|i| self.profiles.get(&i));
+                self.noise.show(ui, p);
+            });
+        self.show_noise = open;
+
+        let mut open = self.show_pipeline;
+        let mut changed = false;
+        let linear = self.pipeline.linear;
+        eframe::egui::Window::new(tr!("window.processing_pipeline"))
+            .open(&mut open)
+            .show(ctx, |ui| {
+                changed = self.pipeline.show(ui);
+            });
+        self.show_pipeline = open;
+        if linear != self.pipeline.linear {
+            if let Some(i) = self.selected_camera {
+                self.send_to_camera_thread(ToCameraThread::SetAveraging(
+                    i,
+                    self.averaging,
+                    self.pipeline.linear,
+                ));
+            }
+        }
+
+        let mut open = self.show_rolling_shutter;
+        eframe::egui::Window::new(tr!("window.rolling_shutter"))
+            .open(&mut open)
+            .show(ctx, |ui| {
+                let p = self.selected_camera.and_then(|i| self.profiles.get(&i));
+                self.rolling_shutter.show(ui, p);
+            });
+        self.show_rolling_shutter = open;
+
+        let mut open = self.show_undistort;
+        eframe::egui::Window::new(tr!("window.undistort_points"))
+            .open(&mut open)
+            .show(ctx, |ui| {
+                self.undistort.show(ui, self.cd.as_ref());
+            });
+        self.show_undistort = open;
+
+        let mut open = self.show_projection;
+        eframe::egui::Window::new(tr!("window.projection"))
+            .open(&mut open)
+            .show(ctx, |ui| {
+                self.projection.show(ui, self.cd.as_ref());
+            });
+        self.show_projection = open;
+
+        let mut open = self.show_stereo;
+        let mut action = None;
+        let cameras: Vec<(i32, String)> = self
+            .live_cameras
+            .iter()
+            .map(|i| (*i, self.source_name(*i)))
+            .collect();
+        eframe::egui::Window::new(tr!("window.stereo"))
+            .open(&mut open)
+            .show(ctx, |ui| {
+                let images = [self.stereo.left, self.stereo.right]
+                    .map(|c| c.and_then(|c| self.image_set.get(&c)).map(|f| &**f));
+                action = self.stereo.show(ui, &cameras, images);
+            });
+        self.show_stereo = open;
+
+        let mut open = self.show_burst;
+        eframe::egui::Window::new(tr!("window.burst_capture"))
+            .open(&mut open)
+            .show(ctx, |ui| {
+                self.burst.show(ui);
+            });
+        self.show_burst = open;
+
+        let mut open = self.show_ring_buffer;
+        let mut save = false;
+        eframe::egui::Window::new(tr!("window.ring_buffer"))
+            .open(&mut open)
+            .show(ctx, |ui| {
+                save = self.ring_buffer.show(ui);
+            });
+        self.show_ring_buffer = open;
+        if save {
+            self.save_ring_buffer();
+        }
+
+        let mut open = self.show_motion;
+        eframe::egui::Window::new(tr!("window.motion_trigger"))
+            .open(&mut open)
+            .show(ctx, |ui| {
+                self.motion.show(ui);
+            });
+        self.show_motion = open;
+
+        let mut open = self.show_vignetting;
+        eframe::egui::Window::new(tr!("window.vignetting"))
+            .open(&mut open)
+            .show(ctx, |ui| {
+                let p = self.selected_camera.and_then(|i| self.profiles.get(&i));
+                self.vignetting.show(ui, p);
+            });
+        self.show_vignetting = open;
+
+        let mut open = self.show_gray_card;
+        eframe::egui::Window::new(tr!("window.gray_card"))
+            .open(&mut open)
+            .show(ctx, |ui| {
+                let p = self.selected_camera.and_then(|i| self.profiles.get(&i));
+                if let Some(stops) = self.gray_card.show(ui, p) {
+                    self.pipeline.set_exposure(stops);
+                    changed = true;
+                }
+            });
+        self.show_gray_card = open;
+        if let Some(a) = action {
+            self.stereo_action(a);
+        }
+        if changed {
+            if let Some(img) = self.raw_image.clone() {
+                self.set_image(ctx, img);
+            }
+        }
+
+        self.toasts.show(ctx);
     }
 }
 
 fn main() {
+    if let Some(command) = <cli::Cli as clap::Parser>::parse().command {
+        if let Err(e) = command.run() {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+        return;
+    }
     let options = eframe::NativeOptions {
         viewport: eframe::egui::ViewportBuilder::default(),
         ..Default::default()
@@ -0,0 +1,118 @@
+//! Before and after comparisons of an image for reports, side by side or as an animation
+
+use eframe::egui::ColorImage;
+use opencv::core::MatTraitConst;
+
+use crate::convert::{color_image_to_mat, mat_to_color_image};
+
+/// The height of the label bar above each image, in pixels
+const LABEL_HEIGHT: i32 = 48;
+
+/// An error for images that could not be converted
+fn conversion_error() -> opencv::Error {
+    opencv::Error::new(opencv::core::StsError, "The image could not be converted")
+}
+
+/// Resize an image to a height, keeping its aspect ratio
+fn fit_height(m: &opencv::core::Mat, height: i32) -> opencv::Result<opencv::core::Mat> {
+    if m.rows() == height {
+        return Ok(m.clone());
+    }
+    let width = (m.cols() as f64 * height as f64 / m.rows().max(1) as f64).round() as i32;
+    let mut out = opencv::core::Mat::default();
+    opencv::imgproc::resize(
+        m,
+        &mut out,
+        opencv::core::Size::new(width.max(1), height),
+        0.0,
+        0.0,
+        opencv::imgproc::INTER_AREA,
+    )?;
+    Ok(out)
+}
+
+/// Put a bar with a label above an image
+fn labeled(m: &opencv::core::Mat, label: &str) -> opencv::Result<opencv::core::Mat> {
+    let mut out = opencv::core::Mat::default();
+    opencv::core::copy_make_border(
+        m,
+        &mut out,
+        LABEL_HEIGHT,
+        0,
+        0,
+        0,
+        opencv::core::BORDER_CONSTANT,
+        opencv::core::Scalar::all(0.0),
+    )?;
+    opencv::imgproc::put_text(
+        &mut out,
+        label,
+        opencv::core::Point::new(12, LABEL_HEIGHT - 14),
+        opencv::imgproc::FONT_HERSHEY_SIMPLEX,
+        1.0,
+        opencv::core::Scalar::all(255.0),
+        2,
+        opencv::imgproc::LINE_AA,
+        false,
+    )?;
+    Ok(out)
+}
+
+/// The before and after images, labeled and scaled to the height of the before image
+fn frames(
+    before: &ColorImage,
+    after: &ColorImage,
+    labels: [&str; 2],
+) -> opencv::Result<[opencv::core::Mat; 2]> {
+    let b = color_image_to_mat(before).ok_or_else(conversion_error)?;
+    let a = color_image_to_mat(after).ok_or_else(conversion_error)?;
+    let a = fit_height(&a, b.rows())?;
+    Ok([labeled(&b, labels[0])?, labeled(&a, labels[1])?])
+}
+
+/// The before and after images next to each other, each with a label
+pub fn side_by_side(
+    before: &ColorImage,
+    after: &ColorImage,
+    labels: [&str; 2],
+) -> opencv::Result<ColorImage> {
+    let [b, a] = frames(before, after, labels)?;
+    let mut out = opencv::core::Mat::default();
+    opencv::core::hconcat2(&b, &a, &mut out)?;
+    mat_to_color_image(&out).ok_or_else(conversion_error)
+}
+
+/// Save an animated gif switching between the before and after images, each shown for delay milliseconds
+pub fn save_gif(
+    path: &std::path::Path,
+    before: &ColorImage,
+    after: &ColorImage,
+    labels: [&str; 2],
+    delay: u32,
+) -> Result<(), String> {
+    let frames = frames(before, after, labels).map_err(|e| e.to_string())?;
+    let f = std::fs::File::create(path).map_err(|e| e.to_string())?;
+    let mut encoder = image::codecs::gif::GifEncoder::new_with_speed(f, 10);
+    encoder
+        .set_repeat(image::codecs::gif::Repeat::Infinite)
+        .map_err(|e| e.to_string())?;
+    // The gif has a single size, so the after image is stretched to the before image
+    let size = frames[0].size().map_err(|e| e.to_string())?;
+    for m in &frames {
+        let mut sized = opencv::core::Mat::default();
+        opencv::imgproc::resize(m, &mut sized, size, 0.0, 0.0, opencv::imgproc::INTER_AREA)
+            .map_err(|e| e.to_string())?;
+        let img = mat_to_color_image(&sized).ok_or("The image could not be converted")?;
+        let rgba: Vec<u8> = img
+            .pixels
+            .iter()
+            .flat_map(|p| p.to_srgba_unmultiplied())
+            .collect();
+        let buffer = image::RgbaImage::from_raw(img.width() as u32, img.height() as u32, rgba)
+            .ok_or("The image could not be converted")?;
+        let frame =
+            image::Frame::from_parts(buffer, 0, 0, image::Delay::from_numer_denom_ms(delay, 1));
+        encoder.encode_frame(frame).map_err(|e| e.to_string())?;
+    }
+    Ok(())
+}
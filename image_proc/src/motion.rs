@@ -0,0 +1,295 @@
+//! Saving frames when something moves in a region of the view, for unattended monitoring
+
+use std::{
+    collections::VecDeque,
+    time::{Duration, Instant},
+};
+
+use eframe::egui::{Color32, Pos2, Rect};
+use image_proc::frame::Frame;
+use opencv::core::MatTraitConst;
+
+/// A frame with the time it arrived
+type TimedFrame = (Instant, Frame);
+
+/// The longest a single event may record, so a scene that never stops moving does not fill the memory
+const MAX_EVENT: Duration = Duration::from_secs(120);
+
+/// An event being recorded
+struct Event {
+    frames: Vec<TimedFrame>,
+    /// When recording stops unless there is more motion
+    until: Instant,
+    started: Instant,
+}
+
+/// Watches a region of the frames for changes and collects the frames around them
+pub struct MotionTrigger {
+    /// True while watching for motion
+    armed: bool,
+    /// The watched region as fractions of the frame, left, top, right, bottom. None watches the whole frame.
+    region: Option<[f32; 4]>,
+    /// True while the region is being selected on the preview
+    pub selecting: bool,
+    /// Where the drag selecting the region started and currently is, as fractions of the frame
+    drag: Option<(Pos2, Pos2)>,
+    /// The mean change of the region between frames that triggers, from 0 to 255
+    threshold: f32,
+    /// Seconds of frames saved from before the motion started
+    pre_roll: f32,
+    /// Seconds of frames saved after the motion stopped
+    post_roll: f32,
+    /// The previous frame of the region in grayscale
+    previous: Option<opencv::core::Mat>,
+    /// The frames for the pre roll, oldest first
+    history: VecDeque<TimedFrame>,
+    event: Option<Event>,
+    /// The change measured in the last frame
+    level: f32,
+    /// The number of events saved since arming
+    events: usize,
+    /// The camera the frames came from
+    camera: Option<i32>,
+    error: Option<String>,
+}
+
+impl Default for MotionTrigger {
+    fn default() -> Self {
+        Self {
+            armed: false,
+            region: None,
+            selecting: false,
+            drag: None,
+            threshold: 8.0,
+            pre_roll: 2.0,
+            post_roll: 3.0,
+            previous: None,
+            history: VecDeque::new(),
+            event: None,
+            level: 0.0,
+            events: 0,
+            camera: None,
+            error: None,
+        }
+    }
+}
+
+impl MotionTrigger {
+    /// The watched region of a frame in grayscale, scaled down to reduce the effect of noise
+    fn region_of(&self, img: &opencv::core::Mat) -> opencv::Result<opencv::core::Mat> {
+        let (w, h) = (img.cols(), img.rows());
+        let [l, t, r, b] = self.region.unwrap_or([0.0, 0.0, 1.0, 1.0]);
+        let x = ((l * w as f32) as i32).clamp(0, w - 1);
+        let y = ((t * h as f32) as i32).clamp(0, h - 1);
+        let rect = opencv::core::Rect::new(
+            x,
+            y,
+            (((r - l) * w as f32) as i32).clamp(1, w - x),
+            (((b - t) * h as f32) as i32).clamp(1, h - y),
+        );
+        let roi = img.roi(rect)?.try_clone()?;
+        let mut gray = opencv::core::Mat::default();
+        if roi.channels() == 3 {
+            opencv::imgproc::cvt_color_def(&roi, &mut gray, opencv::imgproc::COLOR_BGR2GRAY)?;
+        } else {
+            roi.copy_to(&mut gray)?;
+        }
+        let mut small = opencv::core::Mat::default();
+        opencv::imgproc::resize(
+            &gray,
+            &mut small,
+            opencv::core::Size::default(),
+            0.25,
+            0.25,
+            opencv::imgproc::INTER_AREA,
+        )?;
+        Ok(small)
+    }
+
+    /// The mean change of the region since the previous frame
+    fn measure(&mut self, img: &opencv::core::Mat) -> opencv::Result<f32> {
+        let current = self.region_of(img)?;
+        let level = match &self.previous {
+            Some(p) if p.size()? == current.size()? => {
+                let mut diff = opencv::core::Mat::default();
+                opencv::core::absdiff(p, &current, &mut diff)?;
+                opencv::core::mean_def(&diff)?[0] as f32
+            }
+            _ => 0.0,
+        };
+        self.previous = Some(current);
+        Ok(level)
+    }
+
+    /// Forget the frames and any event being recorded
+    fn reset(&mut self) {
+        self.previous = None;
+        self.history.clear();
+        self.event = None;
+        self.level = 0.0;
+    }
+
+    /// Add a frame from a camera, returns the frames of an event when it has finished.
+    /// Frames from another camera start over.
+    pub fn add_frame(&mut self, camera: i32, img: &Frame) -> Option<Vec<TimedFrame>> {
+        if !self.armed {
+            return None;
+        }
+        if self.camera != Some(camera) {
+            self.reset();
+            self.camera = Some(camera);
+        }
+        let now = Instant::now();
+        self.level = match self.measure(img) {
+            Ok(l) => l,
+            Err(e) => {
+                self.error = Some(e.to_string());
+                self.armed = false;
+                return None;
+            }
+        };
+        let moving = self.level > self.threshold;
+        let post_roll = Duration::from_secs_f32(self.post_roll);
+        if let Some(e) = &mut self.event {
+            e.frames.push((now, img.clone()));
+            if moving {
+                e.until = now + post_roll;
+            }
+            if now < e.until && now.duration_since(e.started) < MAX_EVENT {
+                return None;
+            }
+            self.events += 1;
+            return self.event.take().map(|e| e.frames);
+        }
+        self.history.push_back((now, img.clone()));
+        let keep = Duration::from_secs_f32(self.pre_roll);
+        while self
+            .history
+            .front()
+            .is_some_and(|(t, _)| now.duration_since(*t) > keep)
+        {
+            self.history.pop_front();
+        }
+        if moving {
+            self.event = Some(Event {
+                frames: self.history.drain(..).collect(),
+                until: now + post_roll,
+                started: now,
+            });
+        }
+        None
+    }
+
+    /// Stop recording the current event, returning its frames so they are not lost
+    pub fn finish(&mut self) -> Option<Vec<TimedFrame>> {
+        let e = self.event.take()?;
+        self.events += 1;
+        Some(e.frames)
+    }
+
+    /// The camera the frames came from
+    pub fn camera(&self) -> Option<i32> {
+        self.camera
+    }
+
+    /// Handle selecting the region on the preview and draw it.
+    /// response is the response of the image widget.
+    pub fn interact(&mut self, ui: &eframe::egui::Ui, response: &eframe::egui::Response) {
+        let rect = response.rect;
+        let to_fraction = |p: Pos2| {
+            Pos2::new(
+                ((p.x - rect.min.x) / rect.width()).clamp(0.0, 1.0),
+                ((p.y - rect.min.y) / rect.height()).clamp(0.0, 1.0),
+            )
+        };
+        if self.selecting {
+            if let Some(p) = response.interact_pointer_pos().map(to_fraction) {
+                if response.drag_started() {
+                    self.drag = Some((p, p));
+                } else if let Some((_, end)) = &mut self.drag {
+                    *end = p;
+                }
+            }
+            if let Some((a, b)) = self.drag {
+                let r = Rect::from_two_pos(a, b);
+                if response.drag_stopped() {
+                    self.drag = None;
+                    self.selecting = false;
+                    if r.width() > 0.0 && r.height() > 0.0 {
+                        self.region = Some([r.min.x, r.min.y, r.max.x, r.max.y]);
+                        self.previous = None;
+                    }
+                }
+            }
+        }
+        if !self.armed && !self.selecting {
+            return;
+        }
+        let shown = match self.drag {
+            Some((a, b)) => Some(Rect::from_two_pos(a, b)),
+            None => self
+                .region
+                .map(|[l, t, r, b]| Rect::from_min_max(Pos2::new(l, t), Pos2::new(r, b))),
+        };
+        if let Some(r) = shown {
+            let to_screen = |p: Pos2| rect.min + (p.to_vec2() * rect.size());
+            let color = if self.event.is_some() {
+                Color32::RED
+            } else {
+                Color32::LIGHT_BLUE
+            };
+            ui.painter_at(rect).rect_stroke(
+                Rect::from_min_max(to_screen(r.min), to_screen(r.max)),
+                0.0,
+                crate::dpi::stroke(2.0, color, ui.ctx().pixels_per_point()),
+                eframe::egui::StrokeKind::Middle,
+            );
+        }
+    }
+
+    /// Show the trigger controls
+    pub fn show(&mut self, ui: &mut eframe::egui::Ui) {
+        ui.label(tr!("motion.instructions"));
+        ui.horizontal(|ui| {
+            ui.toggle_value(&mut self.selecting, tr!("motion.select"));
+            if self.region.is_some() && ui.button(tr!("motion.whole_frame")).clicked() {
+                self.region = None;
+                self.previous = None;
+            }
+        });
+        ui.add(
+            eframe::egui::Slider::new(&mut self.threshold, 0.5..=64.0)
+                .logarithmic(true)
+                .text(tr!("motion.threshold")),
+        );
+        ui.add(
+            eframe::egui::Slider::new(&mut self.pre_roll, 0.0..=30.0)
+                .suffix(" s")
+                .text(tr!("motion.pre_roll")),
+        );
+        ui.add(
+            eframe::egui::Slider::new(&mut self.post_roll, 0.0..=30.0)
+                .suffix(" s")
+                .text(tr!("motion.post_roll")),
+        );
+        if ui.checkbox(&mut self.armed, tr!("motion.armed")).changed() {
+            self.reset();
+            self.events = 0;
+            self.error = None;
+        }
+        if self.armed {
+            // The level against the threshold, full at twice the threshold
+            ui.add(
+                eframe::egui::ProgressBar::new(self.level / (2.0 * self.threshold))
+                    .text(tr!("motion.level", level = format!("{:.1}", self.level))),
+            );
+            if self.event.is_some() {
+                ui.colored_label(Color32::RED, tr!("motion.recording"));
+            }
+            ui.label(tr!("motion.events", count = self.events));
+        }
+        if let Some(e) = &self.error {
+            ui.colored_label(Color32::RED, e);
+        }
+    }
+}
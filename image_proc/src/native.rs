@@ -0,0 +1,219 @@
+//! A calibration backend in pure rust, for platforms where building opencv is impractical.
+//! The calibrations it makes are stored like the opencv ones and applied without opencv,
+//! the detection and solving are behind the native feature.
+
+#[cfg(feature = "native")]
+mod chessboard;
+#[cfg(feature = "native")]
+mod zhang;
+
+#[cfg(feature = "native")]
+pub use chessboard::find_chessboard;
+#[cfg(feature = "native")]
+pub use zhang::{NativeError, calibrate_chessboard, calibrate_points};
+
+use eframe::egui::{Color32, ColorImage};
+
+use crate::calibration::{CalibrationDataTrait, Error, SaveableOpencvMat, UndistortedPoint};
+
+/// A camera calibrated by the pure rust backend, the 3x3 camera matrix and the distortion coefficients k1 k2 p1 p2 k3
+#[derive(Clone, Debug, serde::Serialize, serde::Deserialize)]
+pub struct NativeCalibration(pub [SaveableOpencvMat; 2]);
+
+impl NativeCalibration {
+    /// A calibration from the focal lengths and principal point in pixels and the distortion coefficients
+    pub fn new(intrinsics: [f64; 4], distortion: [f64; 5]) -> Self {
+        let [fx, fy, cx, cy] = intrinsics;
+        Self([
+            SaveableOpencvMat::from_values(3, 3, &[fx, 0.0, cx, 0.0, fy, cy, 0.0, 0.0, 1.0]),
+            SaveableOpencvMat::from_values(5, 1, &distortion),
+        ])
+    }
+
+    /// The focal lengths and principal point, fx fy cx cy
+    pub fn intrinsics(&self) -> [f64; 4] {
+        let k = self.0[0].values();
+        if k.len() < 9 {
+            return [1.0, 1.0, 0.0, 0.0];
+        }
+        [k[0], k[4], k[2], k[5]]
+    }
+
+    /// The distortion coefficients k1 k2 p1 p2 k3, the missing ones are zero
+    pub fn distortion(&self) -> [f64; 5] {
+        let mut d = [0.0; 5];
+        for (d, v) in d.iter_mut().zip(self.0[1].values()) {
+            *d = v;
+        }
+        d
+    }
+}
+
+/// Apply the lens distortion to normalized image coordinates
+pub(crate) fn distort(d: &[f64; 5], x: f64, y: f64) -> [f64; 2] {
+    let r2 = x * x + y * y;
+    let radial = 1.0 + d[0] * r2 + d[1] * r2 * r2 + d[4] * r2 * r2 * r2;
+    [
+        x * radial + 2.0 * d[2] * x * y + d[3] * (r2 + 2.0 * x * x),
+        y * radial + d[2] * (r2 + 2.0 * y * y) + 2.0 * d[3] * x * y,
+    ]
+}
+
+/// Remove the lens distortion from normalized image coordinates, by the same fixed point iteration as opencv
+pub(crate) fn undistort(d: &[f64; 5], xd: f64, yd: f64) -> [f64; 2] {
+    let (mut x, mut y) = (xd, yd);
+    for _ in 0..20 {
+        let r2 = x * x + y * y;
+        let radial = 1.0 + d[0] * r2 + d[1] * r2 * r2 + d[4] * r2 * r2 * r2;
+        let dx = 2.0 * d[2] * x * y + d[3] * (r2 + 2.0 * x * x);
+        let dy = d[2] * (r2 + 2.0 * y * y) + 2.0 * d[3] * x * y;
+        x = (xd - dx) / radial;
+        y = (yd - dy) / radial;
+    }
+    [x, y]
+}
+
+/// The color of an image between pixels, black outside it
+fn sample(img: &ColorImage, x: f64, y: f64) -> Color32 {
+    let [w, h] = img.size;
+    if x < 0.0 || y < 0.0 || x > (w - 1) as f64 || y > (h - 1) as f64 {
+        return Color32::BLACK;
+    }
+    let (x0, y0) = (x.floor() as usize, y.floor() as usize);
+    let (x1, y1) = ((x0 + 1).min(w - 1), (y0 + 1).min(h - 1));
+    let (fx, fy) = ((x - x0 as f64) as f32, (y - y0 as f64) as f32);
+    let p = |x: usize, y: usize| img.pixels[y * w + x].to_array();
+    let [a, b, c, d] = [p(x0, y0), p(x1, y0), p(x0, y1), p(x1, y1)];
+    let mix = |i: usize| {
+        let top = a[i] as f32 * (1.0 - fx) + b[i] as f32 * fx;
+        let bottom = c[i] as f32 * (1.0 - fx) + d[i] as f32 * fx;
+        (top * (1.0 - fy) + bottom * fy).round() as u8
+    };
+    Color32::from_rgba_premultiplied(mix(0), mix(1), mix(2), mix(3))
+}
+
+impl CalibrationDataTrait for NativeCalibration {
+    fn apply_calibration(&self, img: ColorImage) -> ColorImage {
+        let [fx, fy, cx, cy] = self.intrinsics();
+        let d = self.distortion();
+        let [w, h] = img.size;
+        let mut pixels = Vec::with_capacity(w * h);
+        // Each pixel of the result is looked up where the lens put it in the distorted image
+        for v in 0..h {
+            for u in 0..w {
+                let [x, y] = distort(&d, (u as f64 - cx) / fx, (v as f64 - cy) / fy);
+                pixels.push(sample(&img, fx * x + cx, fy * y + cy));
+            }
+        }
+        ColorImage {
+            size: [w, h],
+            pixels,
+        }
+    }
+
+    fn undistort_points(&self, points: &[[f64; 2]]) -> Result<Vec<UndistortedPoint>, Error> {
+        let [fx, fy, cx, cy] = self.intrinsics();
+        let d = self.distortion();
+        Ok(points
+            .iter()
+            .map(|p| {
+                let [x, y] = undistort(&d, (p[0] - cx) / fx, (p[1] - cy) / fy);
+                UndistortedPoint {
+                    pixel: [fx * x + cx, fy * y + cy],
+                    normalized: [x, y],
+                }
+            })
+            .collect())
+    }
+}
+
+/// Without opencv the calibrations made with it are applied the same way as the pure rust ones, the lens model is the same
+#[cfg(target_arch = "wasm32")]
+impl CalibrationDataTrait for [SaveableOpencvMat; 2] {
+    fn apply_calibration(&self, img: ColorImage) -> ColorImage {
+        NativeCalibration(self.clone()).apply_calibration(img)
+    }
+
+    fn undistort_points(&self, points: &[[f64; 2]]) -> Result<Vec<UndistortedPoint>, Error> {
+        NativeCalibration(self.clone()).undistort_points(points)
+    }
+}
+
+/// Apply the equidistant fisheye distortion k1 k2 k3 k4 to normalized image coordinates, like opencv's fisheye model
+#[cfg(target_arch = "wasm32")]
+fn fisheye_distort(k: &[f64; 4], x: f64, y: f64) -> [f64; 2] {
+    let r = x.hypot(y);
+    if r < 1e-12 {
+        return [x, y];
+    }
+    let t = r.atan();
+    let t2 = t * t;
+    let td = t * (1.0 + t2 * (k[0] + t2 * (k[1] + t2 * (k[2] + t2 * k[3]))));
+    [x * td / r, y * td / r]
+}
+
+/// Remove the fisheye distortion from normalized image coordinates, solving for the angle by newton's method
+#[cfg(target_arch = "wasm32")]
+fn fisheye_undistort(k: &[f64; 4], xd: f64, yd: f64) -> [f64; 2] {
+    let td = xd.hypot(yd);
+    if td < 1e-12 {
+        return [xd, yd];
+    }
+    let mut t = td;
+    for _ in 0..20 {
+        let t2 = t * t;
+        let f = t * (1.0 + t2 * (k[0] + t2 * (k[1] + t2 * (k[2] + t2 * k[3])))) - td;
+        let df = 1.0 + t2 * (3.0 * k[0] + t2 * (5.0 * k[1] + t2 * (7.0 * k[2] + t2 * 9.0 * k[3])));
+        t -= f / df;
+    }
+    let s = t.tan() / td;
+    [xd * s, yd * s]
+}
+
+/// Without opencv the fisheye calibrations are applied with the same model in rust
+#[cfg(target_arch = "wasm32")]
+impl CalibrationDataTrait for crate::calibration::FisheyeCalibration {
+    fn apply_calibration(&self, img: ColorImage) -> ColorImage {
+        let [fx, fy, cx, cy] = NativeCalibration(self.0.clone()).intrinsics();
+        let k = self.coefficients();
+        let [w, h] = img.size;
+        let mut pixels = Vec::with_capacity(w * h);
+        for v in 0..h {
+            for u in 0..w {
+                let [x, y] = fisheye_distort(&k, (u as f64 - cx) / fx, (v as f64 - cy) / fy);
+                pixels.push(sample(&img, fx * x + cx, fy * y + cy));
+            }
+        }
+        ColorImage {
+            size: [w, h],
+            pixels,
+        }
+    }
+
+    fn undistort_points(&self, points: &[[f64; 2]]) -> Result<Vec<UndistortedPoint>, Error> {
+        let [fx, fy, cx, cy] = NativeCalibration(self.0.clone()).intrinsics();
+        let k = self.coefficients();
+        Ok(points
+            .iter()
+            .map(|p| {
+                let [x, y] = fisheye_undistort(&k, (p[0] - cx) / fx, (p[1] - cy) / fy);
+                UndistortedPoint {
+                    pixel: [fx * x + cx, fy * y + cy],
+                    normalized: [x, y],
+                }
+            })
+            .collect())
+    }
+}
+
+#[cfg(target_arch = "wasm32")]
+impl crate::calibration::FisheyeCalibration {
+    /// The distortion coefficients k1 k2 k3 k4, the missing ones are zero
+    fn coefficients(&self) -> [f64; 4] {
+        let mut k = [0.0; 4];
+        for (k, v) in k.iter_mut().zip(self.0[1].values()) {
+            *k = v;
+        }
+        k
+    }
+}
@@ -0,0 +1,366 @@
+//! Finding the inner corners of a chessboard without opencv.
+//! Corners are the saddle points picked out by the ChESS response, they are linked into a grid by
+//! stepping from corner to corner, and refined to a fraction of a pixel from the image gradient.
+
+use std::collections::{HashMap, VecDeque};
+
+use eframe::egui::ColorImage;
+
+/// The ring the ChESS response is sampled on, 16 points 5 pixels from the center.
+/// Point n and point n + 8 are opposite, point n + 4 is a quarter turn on.
+const RING: [(i32, i32); 16] = [
+    (0, 5),
+    (2, 5),
+    (3, 3),
+    (5, 2),
+    (5, 0),
+    (5, -2),
+    (3, -3),
+    (2, -5),
+    (0, -5),
+    (-2, -5),
+    (-3, -3),
+    (-5, -2),
+    (-5, 0),
+    (-5, 2),
+    (-3, 3),
+    (-2, 5),
+];
+
+/// A brightness image with floating point pixels
+struct Gray {
+    width: usize,
+    height: usize,
+    pixels: Vec<f32>,
+}
+
+impl Gray {
+    /// The brightness of an image, lightly blurred to take the edge off noise
+    fn new(img: &ColorImage) -> Self {
+        let [width, height] = img.size;
+        let pixels: Vec<f32> = img
+            .pixels
+            .iter()
+            .map(|p| 0.299 * p.r() as f32 + 0.587 * p.g() as f32 + 0.114 * p.b() as f32)
+            .collect();
+        let mut g = Self {
+            width,
+            height,
+            pixels,
+        };
+        g.blur();
+        g
+    }
+
+    fn at(&self, x: i32, y: i32) -> f32 {
+        let x = x.clamp(0, self.width as i32 - 1) as usize;
+        let y = y.clamp(0, self.height as i32 - 1) as usize;
+        self.pixels[y * self.width + x]
+    }
+
+    /// A 1 2 1 blur in both directions
+    fn blur(&mut self) {
+        let (w, h) = (self.width as i32, self.height as i32);
+        let mut across = vec![0.0; self.pixels.len()];
+        for y in 0..h {
+            for x in 0..w {
+                across[(y * w + x) as usize] =
+                    (self.at(x - 1, y) + 2.0 * self.at(x, y) + self.at(x + 1, y)) / 4.0;
+            }
+        }
+        self.pixels = across;
+        let mut down = vec![0.0; self.pixels.len()];
+        for y in 0..h {
+            for x in 0..w {
+                down[(y * w + x) as usize] =
+                    (self.at(x, y - 1) + 2.0 * self.at(x, y) + self.at(x, y + 1)) / 4.0;
+            }
+        }
+        self.pixels = down;
+    }
+
+    /// The ChESS response at a pixel, high where four squares meet and low on edges and plain areas
+    fn chess(&self, x: i32, y: i32) -> f32 {
+        let i = RING.map(|(dx, dy)| self.at(x + dx, y + dy));
+        let mut sum = 0.0;
+        for n in 0..4 {
+            sum += ((i[n] + i[n + 8]) - (i[n + 4] + i[n + 12])).abs();
+        }
+        let mut diff = 0.0;
+        for n in 0..8 {
+            diff += (i[n] - i[n + 8]).abs();
+        }
+        let ring_mean = i.iter().sum::<f32>() / 16.0;
+        let local_mean = (self.at(x, y)
+            + self.at(x - 1, y)
+            + self.at(x + 1, y)
+            + self.at(x, y - 1)
+            + self.at(x, y + 1))
+            / 5.0;
+        sum - diff - 16.0 * (ring_mean - local_mean).abs()
+    }
+
+    /// Move a corner to where the image gradient around it points through it, like opencv's cornerSubPix
+    fn refine(&self, p: [f64; 2]) -> [f64; 2] {
+        const RADIUS: i32 = 4;
+        let mut p = p;
+        for _ in 0..10 {
+            let (cx, cy) = (p[0].round() as i32, p[1].round() as i32);
+            let (mut a, mut b, mut c, mut bx, mut by) = (0.0, 0.0, 0.0, 0.0, 0.0);
+            for y in cy - RADIUS..=cy + RADIUS {
+                for x in cx - RADIUS..=cx + RADIUS {
+                    let gx = ((self.at(x + 1, y) - self.at(x - 1, y)) / 2.0) as f64;
+                    let gy = ((self.at(x, y + 1) - self.at(x, y - 1)) / 2.0) as f64;
+                    let (xx, xy, yy) = (gx * gx, gx * gy, gy * gy);
+                    a += xx;
+                    b += xy;
+                    c += yy;
+                    bx += xx * x as f64 + xy * y as f64;
+                    by += xy * x as f64 + yy * y as f64;
+                }
+            }
+            let det = a * c - b * b;
+            if det.abs() < 1e-9 {
+                break;
+            }
+            let q = [(c * bx - b * by) / det, (a * by - b * bx) / det];
+            if (q[0] - p[0]).abs() > RADIUS as f64 || (q[1] - p[1]).abs() > RADIUS as f64 {
+                break;
+            }
+            let moved = (q[0] - p[0]).hypot(q[1] - p[1]);
+            p = q;
+            if moved < 0.01 {
+                break;
+            }
+        }
+        p
+    }
+}
+
+/// The saddle points of the image, strongest first
+fn candidates(g: &Gray, limit: usize) -> Vec<[f64; 2]> {
+    let (w, h) = (g.width as i32, g.height as i32);
+    let mut response = vec![0.0f32; g.pixels.len()];
+    let mut max = 0.0f32;
+    for y in 5..h - 5 {
+        for x in 5..w - 5 {
+            let r = g.chess(x, y);
+            response[(y * w + x) as usize] = r;
+            max = max.max(r);
+        }
+    }
+    if max <= 0.0 {
+        return Vec::new();
+    }
+    let threshold = max * 0.1;
+    let mut found = Vec::new();
+    // Only the strongest response within 3 pixels is kept
+    for y in 8..h - 8 {
+        for x in 8..w - 8 {
+            let r = response[(y * w + x) as usize];
+            if r < threshold {
+                continue;
+            }
+            let strongest = (-3..=3).all(|dy| {
+                (-3..=3).all(|dx| {
+                    let o = response[((y + dy) * w + x + dx) as usize];
+                    o < r || (o == r && (dy, dx) >= (0, 0))
+                })
+            });
+            if strongest {
+                found.push((r, [x as f64, y as f64]));
+            }
+        }
+    }
+    found.sort_by(|a, b| b.0.total_cmp(&a.0));
+    found.truncate(limit);
+    found.into_iter().map(|(_, p)| p).collect()
+}
+
+fn sub(a: [f64; 2], b: [f64; 2]) -> [f64; 2] {
+    [a[0] - b[0], a[1] - b[1]]
+}
+
+fn length(a: [f64; 2]) -> f64 {
+    a[0].hypot(a[1])
+}
+
+/// Link the candidates into a grid, starting from one of them and stepping to its neighbors.
+/// Returns the candidate at each grid position.
+fn grow(points: &[[f64; 2]], seed: usize) -> Option<HashMap<(i32, i32), usize>> {
+    let nearest = |p: [f64; 2], exclude: &dyn Fn(usize) -> bool| {
+        points
+            .iter()
+            .enumerate()
+            .filter(|(n, _)| !exclude(*n))
+            .map(|(n, q)| (n, length(sub(*q, p))))
+            .min_by(|a, b| a.1.total_cmp(&b.1))
+    };
+    // The two directions of the grid from the seed's nearest neighbors
+    let s = points[seed];
+    let (first, _) = nearest(s, &|n| n == seed)?;
+    let a = sub(points[first], s);
+    let b = points
+        .iter()
+        .enumerate()
+        .filter(|(n, _)| *n != seed && *n != first)
+        .map(|(n, q)| (n, sub(*q, s)))
+        .filter(|(_, v)| {
+            let cos = (v[0] * a[0] + v[1] * a[1]) / (length(*v) * length(a));
+            cos.abs() < 0.5
+        })
+        .min_by(|x, y| length(x.1).total_cmp(&length(y.1)))?
+        .1;
+    if length(b) > 2.0 * length(a) || length(a) > 2.0 * length(b) {
+        return None;
+    }
+    let mut grid = HashMap::new();
+    let mut used = vec![false; points.len()];
+    grid.insert((0, 0), seed);
+    used[seed] = true;
+    let mut queue = VecDeque::from([(0, 0)]);
+    while let Some((i, j)) = queue.pop_front() {
+        let p = points[grid[&(i, j)]];
+        for (di, dj) in [(1, 0), (-1, 0), (0, 1), (0, -1)] {
+            let target = (i + di, j + dj);
+            if grid.contains_key(&target) {
+                continue;
+            }
+            let at = |k: (i32, i32)| grid.get(&k).map(|n: &usize| points[*n]);
+            // The step to the next corner, from the corners already found nearby when there are some
+            let step = if let Some(back) = at((i - di, j - dj)) {
+                sub(p, back)
+            } else if let Some(side) = at((i + dj, j + di)).zip(at((i + di + dj, j + dj + di))) {
+                sub(side.1, side.0)
+            } else if let Some(side) = at((i - dj, j - di)).zip(at((i + di - dj, j + dj - di))) {
+                sub(side.1, side.0)
+            } else if dj == 0 {
+                [a[0] * di as f64, a[1] * di as f64]
+            } else {
+                [b[0] * dj as f64, b[1] * dj as f64]
+            };
+            let predicted = [p[0] + step[0], p[1] + step[1]];
+            if let Some((n, d)) = nearest(predicted, &|n| used[n]) {
+                if d < 0.35 * length(step) {
+                    grid.insert(target, n);
+                    used[n] = true;
+                    queue.push_back(target);
+                }
+            }
+        }
+    }
+    Some(grid)
+}
+
+/// The rows of a complete grid of cols by rows corners in what was found, either way around
+fn complete(
+    grid: &HashMap<(i32, i32), usize>,
+    cols: usize,
+    rows: usize,
+) -> Option<Vec<Vec<usize>>> {
+    let min_i = grid.keys().map(|k| k.0).min()?;
+    let max_i = grid.keys().map(|k| k.0).max()?;
+    let min_j = grid.keys().map(|k| k.1).min()?;
+    let max_j = grid.keys().map(|k| k.1).max()?;
+    for (across, down) in [(cols, rows), (rows, cols)] {
+        let (across, down) = (across as i32, down as i32);
+        let mut windows = Vec::new();
+        for i0 in min_i..=max_i - across + 1 {
+            for j0 in min_j..=max_j - down + 1 {
+                let full =
+                    (0..down).all(|j| (0..across).all(|i| grid.contains_key(&(i0 + i, j0 + j))));
+                if full {
+                    windows.push((i0, j0));
+                }
+            }
+        }
+        // More than one complete window means the board can not be told apart from its surroundings
+        if let [(i0, j0)] = windows[..] {
+            let mut out: Vec<Vec<usize>> = (0..down)
+                .map(|j| (0..across).map(|i| grid[&(i0 + i, j0 + j)]).collect())
+                .collect();
+            if across as usize != cols {
+                // Found the other way around, cols rows of rows corners
+                out = (0..rows)
+                    .map(|r| (0..cols).map(|c| out[c][r]).collect())
+                    .collect();
+            }
+            return Some(out);
+        }
+    }
+    None
+}
+
+/// Find the inner corners of a chessboard with cols by rows inner corners in an image.
+/// The corners are in rows of cols corners, starting from the corner nearest the top left of the image.
+/// None when the whole board is not found.
+pub fn find_chessboard(img: &ColorImage, cols: usize, rows: usize) -> Option<Vec<[f64; 2]>> {
+    if cols < 2 || rows < 2 || img.size[0] < 20 || img.size[1] < 20 {
+        return None;
+    }
+    let g = Gray::new(img);
+    let points = candidates(&g, cols * rows * 4 + 64);
+    if points.len() < cols * rows {
+        return None;
+    }
+    // A few of the strongest corners are tried as the start, in case one is not on the board
+    for seed in 0..points.len().min(8) {
+        let Some(grid) = grow(&points, seed) else {
+            continue;
+        };
+        if grid.len() < cols * rows {
+            continue;
+        }
+        let Some(found) = complete(&grid, cols, rows) else {
+            continue;
+        };
+        let pick = |r: usize, c: usize| points[found[r][c]];
+        // Of the ways the board can be turned, the one starting nearest the top left of the image
+        let mut best: Option<(f64, Vec<[f64; 2]>)> = None;
+        let mut orders = vec![];
+        for flip_r in [false, true] {
+            for flip_c in [false, true] {
+                orders.push(
+                    (0..rows)
+                        .flat_map(|r| (0..cols).map(move |c| (r, c)))
+                        .map(|(r, c)| {
+                            let r = if flip_r { rows - 1 - r } else { r };
+                            let c = if flip_c { cols - 1 - c } else { c };
+                            pick(r, c)
+                        })
+                        .collect::<Vec<_>>(),
+                );
+            }
+        }
+        if cols == rows {
+            let transposed: Vec<Vec<[f64; 2]>> = orders
+                .iter()
+                .map(|o| {
+                    (0..rows)
+                        .flat_map(|r| (0..cols).map(move |c| (r, c)))
+                        .map(|(r, c)| o[c * cols + r])
+                        .collect()
+                })
+                .collect();
+            orders.extend(transposed);
+        }
+        for o in orders {
+            let first = o[0];
+            let along = sub(o[1], o[0]);
+            // Rows that run across the image win over rows that run down it
+            let score = first[0]
+                + first[1]
+                + if along[0].abs() < along[1].abs() {
+                    1e-3
+                } else {
+                    0.0
+                };
+            if best.as_ref().is_none_or(|b| score < b.0) {
+                best = Some((score, o));
+            }
+        }
+        let (_, corners) = best?;
+        return Some(corners.into_iter().map(|p| g.refine(p)).collect());
+    }
+    None
+}
@@ -0,0 +1,391 @@
+//! Zhang's camera calibration from views of a flat target.
+//! The intrinsics come in closed form from the homography of each view, then the intrinsics,
+//! the distortion and the pose of every view are refined together with Levenberg-Marquardt.
+
+use eframe::egui::ColorImage;
+use levenberg_marquardt::{LeastSquaresProblem, LevenbergMarquardt};
+use nalgebra::{DMatrix, DVector, Dyn, Matrix3, Owned, Rotation3, SymmetricEigen, Vector3};
+
+use super::{NativeCalibration, distort};
+use crate::calibration::CalibrationData;
+use crate::geometry::Pose;
+
+/// The number of parameters of the camera, fx fy cx cy and the five distortion coefficients
+const CAMERA_PARAMS: usize = 9;
+
+/// Why a camera could not be calibrated
+#[derive(Debug)]
+pub enum NativeError {
+    /// The whole target was found in fewer views than needed, with the number it was found in
+    TooFewViews(usize),
+    /// The views do not pin down the camera, like when the target is seen from the same angle in all of them
+    Degenerate,
+    /// The refinement ran off to numbers that are not finite
+    NoConvergence,
+}
+
+impl std::fmt::Display for NativeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            NativeError::TooFewViews(n) => {
+                write!(
+                    f,
+                    "The board was found in {} images, at least 3 are needed",
+                    n
+                )
+            }
+            NativeError::Degenerate => write!(
+                f,
+                "The images do not show the board from enough different angles"
+            ),
+            NativeError::NoConvergence => write!(f, "The calibration did not converge"),
+        }
+    }
+}
+
+impl std::error::Error for NativeError {}
+
+/// The eigenvector of the smallest eigenvalue of a^T a, the least squares solution of a x = 0 with |x| = 1
+fn null_vector(a: &DMatrix<f64>) -> DVector<f64> {
+    let e = SymmetricEigen::new(a.transpose() * a);
+    let (n, _) = e
+        .eigenvalues
+        .iter()
+        .enumerate()
+        .min_by(|a, b| a.1.total_cmp(b.1))
+        .unwrap();
+    e.eigenvectors.column(n).into_owned()
+}
+
+/// A transform moving points to have their center at the origin and an average distance of sqrt 2 from it
+fn normalizer(points: &[[f64; 2]]) -> Matrix3<f64> {
+    let n = points.len() as f64;
+    let cx = points.iter().map(|p| p[0]).sum::<f64>() / n;
+    let cy = points.iter().map(|p| p[1]).sum::<f64>() / n;
+    let d = points
+        .iter()
+        .map(|p| (p[0] - cx).hypot(p[1] - cy))
+        .sum::<f64>()
+        / n;
+    let s = if d > 0.0 { 2f64.sqrt() / d } else { 1.0 };
+    Matrix3::new(s, 0.0, -s * cx, 0.0, s, -s * cy, 0.0, 0.0, 1.0)
+}
+
+fn transform(m: &Matrix3<f64>, p: [f64; 2]) -> [f64; 2] {
+    let v = m * Vector3::new(p[0], p[1], 1.0);
+    [v.x / v.z, v.y / v.z]
+}
+
+/// The homography from points on the target to pixels, by the normalized direct linear transform
+fn homography(object: &[[f64; 2]], image: &[[f64; 2]]) -> Option<Matrix3<f64>> {
+    let to = normalizer(object);
+    let ti = normalizer(image);
+    let mut a = DMatrix::zeros(2 * object.len(), 9);
+    for (n, (o, i)) in object.iter().zip(image).enumerate() {
+        let [x, y] = transform(&to, *o);
+        let [u, v] = transform(&ti, *i);
+        let r = 2 * n;
+        a.row_mut(r)
+            .copy_from_slice(&[-x, -y, -1.0, 0.0, 0.0, 0.0, u * x, u * y, u]);
+        a.row_mut(r + 1)
+            .copy_from_slice(&[0.0, 0.0, 0.0, -x, -y, -1.0, v * x, v * y, v]);
+    }
+    let h = null_vector(&a);
+    let hn = Matrix3::from_row_slice(h.as_slice());
+    let h = ti.try_inverse()? * hn * to;
+    Some(h / h[(2, 2)])
+}
+
+/// The row of Zhang's constraint for columns i and j of a homography
+fn constraint(h: &Matrix3<f64>, i: usize, j: usize) -> [f64; 6] {
+    [
+        h[(0, i)] * h[(0, j)],
+        h[(0, i)] * h[(1, j)] + h[(1, i)] * h[(0, j)],
+        h[(1, i)] * h[(1, j)],
+        h[(2, i)] * h[(0, j)] + h[(0, i)] * h[(2, j)],
+        h[(2, i)] * h[(1, j)] + h[(1, i)] * h[(2, j)],
+        h[(2, i)] * h[(2, j)],
+    ]
+}
+
+/// The focal lengths and principal point from the homographies of the views, assuming square pixel axes
+fn intrinsics(homographies: &[Matrix3<f64>]) -> Option<[f64; 4]> {
+    let mut v = DMatrix::zeros(2 * homographies.len() + 1, 6);
+    for (n, h) in homographies.iter().enumerate() {
+        let v11 = constraint(h, 0, 0);
+        let v22 = constraint(h, 1, 1);
+        v.row_mut(2 * n).copy_from_slice(&constraint(h, 0, 1));
+        let d: Vec<f64> = v11.iter().zip(&v22).map(|(a, b)| a - b).collect();
+        v.row_mut(2 * n + 1).copy_from_slice(&d);
+    }
+    // No skew, B12 = 0
+    v[(2 * homographies.len(), 1)] = 1.0;
+    let b = null_vector(&v);
+    let (b11, b12, b22, b13, b23, b33) = (b[0], b[1], b[2], b[3], b[4], b[5]);
+    let den = b11 * b22 - b12 * b12;
+    if den.abs() < f64::EPSILON || b11.abs() < f64::EPSILON {
+        return None;
+    }
+    let v0 = (b12 * b13 - b11 * b23) / den;
+    let lambda = b33 - (b13 * b13 + v0 * (b12 * b13 - b11 * b23)) / b11;
+    let fx2 = lambda / b11;
+    let fy2 = lambda * b11 / den;
+    if fx2 <= 0.0 || fy2 <= 0.0 {
+        return None;
+    }
+    let (fx, fy) = (fx2.sqrt(), fy2.sqrt());
+    let u0 = -b13 * fx2 / lambda;
+    [fx, fy, u0, v0]
+        .iter()
+        .all(|v| v.is_finite())
+        .then_some([fx, fy, u0, v0])
+}
+
+/// The pose of a view from its homography and the intrinsics
+fn pose(h: &Matrix3<f64>, k: [f64; 4]) -> Option<Pose> {
+    let [fx, fy, cx, cy] = k;
+    let ki = Matrix3::new(fx, 0.0, cx, 0.0, fy, cy, 0.0, 0.0, 1.0).try_inverse()?;
+    let m = ki * h;
+    let scale = 1.0 / m.column(0).norm();
+    let mut r1 = m.column(0) * scale;
+    let mut r2 = m.column(1) * scale;
+    let mut t = m.column(2) * scale;
+    // The target is in front of the camera
+    if t.z < 0.0 {
+        r1 = -r1;
+        r2 = -r2;
+        t = -t;
+    }
+    let r3 = r1.cross(&r2);
+    let q = Matrix3::from_columns(&[r1, r2, r3]);
+    // The nearest true rotation, the columns from the homography are not quite orthonormal
+    let svd = q.svd(true, true);
+    let mut r = svd.u? * svd.v_t?;
+    if r.determinant() < 0.0 {
+        r = -r;
+    }
+    let rotation = Rotation3::from_matrix_unchecked(r).scaled_axis();
+    Some(Pose {
+        rotation: [rotation.x, rotation.y, rotation.z],
+        translation: [t.x, t.y, t.z],
+    })
+}
+
+/// Where a point on the target lands in the image
+fn project(camera: &[f64], pose: &Pose, p: [f64; 2]) -> [f64; 2] {
+    let r = pose.rotation_matrix();
+    let t = pose.translation;
+    let c = [0, 1, 2].map(|i| r[i][0] * p[0] + r[i][1] * p[1] + t[i]);
+    let d = [camera[4], camera[5], camera[6], camera[7], camera[8]];
+    let [x, y] = distort(&d, c[0] / c[2], c[1] / c[2]);
+    [camera[0] * x + camera[2], camera[1] * y + camera[3]]
+}
+
+/// The reprojection errors of all the views, for Levenberg-Marquardt
+struct Reprojection<'a> {
+    views: &'a [(Vec<[f64; 2]>, Vec<[f64; 2]>)],
+    /// The camera parameters followed by the rotation and translation of each view
+    params: DVector<f64>,
+}
+
+impl Reprojection<'_> {
+    fn view_pose(params: &DVector<f64>, v: usize) -> Pose {
+        let o = CAMERA_PARAMS + 6 * v;
+        Pose {
+            rotation: [params[o], params[o + 1], params[o + 2]],
+            translation: [params[o + 3], params[o + 4], params[o + 5]],
+        }
+    }
+
+    /// The residuals of one view, the x and y error of each point
+    fn view_residuals(&self, params: &DVector<f64>, v: usize, out: &mut [f64]) {
+        let pose = Self::view_pose(params, v);
+        let (object, image) = &self.views[v];
+        for (n, (o, i)) in object.iter().zip(image).enumerate() {
+            let p = project(&params.as_slice()[..CAMERA_PARAMS], &pose, *o);
+            out[2 * n] = p[0] - i[0];
+            out[2 * n + 1] = p[1] - i[1];
+        }
+    }
+
+    /// Where the residuals of each view start
+    fn offsets(&self) -> Vec<usize> {
+        let mut offsets = Vec::with_capacity(self.views.len() + 1);
+        let mut o = 0;
+        offsets.push(0);
+        for (object, _) in self.views {
+            o += 2 * object.len();
+            offsets.push(o);
+        }
+        offsets
+    }
+
+    fn all_residuals(&self, params: &DVector<f64>) -> DVector<f64> {
+        let offsets = self.offsets();
+        let mut r = DVector::zeros(offsets[self.views.len()]);
+        for v in 0..self.views.len() {
+            self.view_residuals(params, v, &mut r.as_mut_slice()[offsets[v]..offsets[v + 1]]);
+        }
+        r
+    }
+}
+
+impl LeastSquaresProblem<f64, Dyn, Dyn> for Reprojection<'_> {
+    type ResidualStorage = Owned<f64, Dyn>;
+    type JacobianStorage = Owned<f64, Dyn, Dyn>;
+    type ParameterStorage = Owned<f64, Dyn>;
+
+    fn set_params(&mut self, x: &DVector<f64>) {
+        self.params.copy_from(x);
+    }
+
+    fn params(&self) -> DVector<f64> {
+        self.params.clone()
+    }
+
+    fn residuals(&self) -> Option<DVector<f64>> {
+        Some(self.all_residuals(&self.params))
+    }
+
+    fn jacobian(&self) -> Option<DMatrix<f64>> {
+        // Central differences. The pose of a view only moves the residuals of that view.
+        let offsets = self.offsets();
+        let rows = offsets[self.views.len()];
+        let mut j = DMatrix::zeros(rows, self.params.len());
+        let step = |x: f64| 1e-6 * x.abs().max(1e-3);
+        for c in 0..CAMERA_PARAMS {
+            let h = step(self.params[c]);
+            let mut p = self.params.clone();
+            p[c] += h;
+            let plus = self.all_residuals(&p);
+            p[c] -= 2.0 * h;
+            let minus = self.all_residuals(&p);
+            j.set_column(c, &((plus - minus) / (2.0 * h)));
+        }
+        for v in 0..self.views.len() {
+            let (start, end) = (offsets[v], offsets[v + 1]);
+            let mut plus = vec![0.0; end - start];
+            let mut minus = vec![0.0; end - start];
+            for k in 0..6 {
+                let c = CAMERA_PARAMS + 6 * v + k;
+                let h = step(self.params[c]);
+                let mut p = self.params.clone();
+                p[c] += h;
+                self.view_residuals(&p, v, &mut plus);
+                p[c] -= 2.0 * h;
+                self.view_residuals(&p, v, &mut minus);
+                for (r, (a, b)) in plus.iter().zip(&minus).enumerate() {
+                    j[(start + r, c)] = (a - b) / (2.0 * h);
+                }
+            }
+        }
+        Some(j)
+    }
+}
+
+/// Calibrate a camera from views of a flat target, each the points on the target and the pixels they were seen at.
+/// Returns the calibration, the rms reprojection error in pixels and the pose of the target in each view.
+pub fn calibrate_points(
+    views: &[(Vec<[f64; 2]>, Vec<[f64; 2]>)],
+) -> Result<(NativeCalibration, f64, Vec<Pose>), NativeError> {
+    let views: Vec<_> = views
+        .iter()
+        .filter(|(o, i)| o.len() >= 4 && o.len() == i.len())
+        .cloned()
+        .collect();
+    if views.len() < 3 {
+        return Err(NativeError::TooFewViews(views.len()));
+    }
+    let homographies = views
+        .iter()
+        .map(|(o, i)| homography(o, i))
+        .collect::<Option<Vec<_>>>()
+        .ok_or(NativeError::Degenerate)?;
+    let k = intrinsics(&homographies).ok_or(NativeError::Degenerate)?;
+    let mut params = vec![k[0], k[1], k[2], k[3], 0.0, 0.0, 0.0, 0.0, 0.0];
+    for h in &homographies {
+        let p = pose(h, k).ok_or(NativeError::Degenerate)?;
+        params.extend(p.rotation);
+        params.extend(p.translation);
+    }
+    let problem = Reprojection {
+        views: &views,
+        params: DVector::from_vec(params),
+    };
+    let (problem, report) = LevenbergMarquardt::new().minimize(problem);
+    let params = &problem.params;
+    if !report.objective_function.is_finite() || params.iter().any(|p| !p.is_finite()) {
+        return Err(NativeError::NoConvergence);
+    }
+    let points: usize = views.iter().map(|(o, _)| o.len()).sum();
+    let rms = (problem.all_residuals(params).norm_squared() / points as f64).sqrt();
+    let calibration = NativeCalibration::new(
+        [params[0], params[1], params[2], params[3]],
+        [params[4], params[5], params[6], params[7], params[8]],
+    );
+    let poses = (0..views.len())
+        .map(|v| Reprojection::view_pose(params, v))
+        .collect();
+    Ok((calibration, rms, poses))
+}
+
+/// Calibrate a camera from images of a chessboard with cols by rows inner corners and squares of a side length in meters.
+/// Images where the whole board is not found are skipped. Returns the calibration and the rms reprojection error in pixels.
+pub fn calibrate_chessboard(
+    images: &[ColorImage],
+    cols: usize,
+    rows: usize,
+    square_length: f64,
+) -> Result<(CalibrationData, f64), NativeError> {
+    let object: Vec<[f64; 2]> = (0..rows)
+        .flat_map(|r| (0..cols).map(move |c| [c as f64 * square_length, r as f64 * square_length]))
+        .collect();
+    let views: Vec<_> = images
+        .iter()
+        .filter_map(|img| super::find_chessboard(img, cols, rows))
+        .map(|corners| (object.clone(), corners))
+        .collect();
+    let (calibration, rms, _) = calibrate_points(&views)?;
+    Ok((CalibrationData::Native(calibration), rms))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn calibrates_synthetic_views() {
+        let camera = [800.0, 780.0, 320.0, 240.0, -0.05, 0.01, 0.001, -0.0005, 0.0];
+        let object: Vec<[f64; 2]> = (0..6)
+            .flat_map(|r| (0..8).map(move |c| [c as f64 * 0.03, r as f64 * 0.03]))
+            .collect();
+        let poses = [
+            ([0.1, -0.2, 0.05], [-0.1, -0.08, 0.5]),
+            ([-0.3, 0.1, 0.0], [-0.12, -0.05, 0.6]),
+            ([0.2, 0.3, -0.1], [-0.08, -0.1, 0.55]),
+            ([0.0, -0.35, 0.2], [-0.1, -0.07, 0.45]),
+        ];
+        let views: Vec<_> = poses
+            .iter()
+            .map(|(rotation, translation)| {
+                let pose = Pose {
+                    rotation: *rotation,
+                    translation: *translation,
+                };
+                let image = object.iter().map(|o| project(&camera, &pose, *o)).collect();
+                (object.clone(), image)
+            })
+            .collect();
+        let (calibration, rms, poses) = calibrate_points(&views).unwrap();
+        assert_eq!(poses.len(), views.len());
+        assert!(rms < 1e-4, "rms {}", rms);
+        for (found, expected) in calibration.intrinsics().iter().zip(&camera[..4]) {
+            assert!(
+                (found - expected).abs() < 1e-3 * expected,
+                "found {} expected {}",
+                found,
+                expected
+            );
+        }
+    }
+}
@@ -0,0 +1,223 @@
+//! Temporal noise profiling of a camera looking at a static scene
+
+use egui_plot::{Legend, Line, Plot, PlotPoints};
+use opencv::core::{MatTraitConst, MatTraitConstManual};
+
+use crate::profile::CameraProfile;
+
+/// The number of brightness bins used for the snr curve
+const BINS: usize = 32;
+
+/// The temporal noise of a single channel
+#[derive(Clone, Debug, serde::Serialize, serde::Deserialize)]
+pub struct ChannelNoise {
+    /// The mean value of the channel over all pixels and frames
+    pub mean: f64,
+    /// The mean of the per pixel temporal standard deviation
+    pub sigma: f64,
+    /// Signal to noise ratio in decibels versus pixel brightness
+    pub snr_curve: Vec<[f64; 2]>,
+}
+
+/// The temporal noise characteristics of a camera
+#[derive(Clone, Debug, serde::Serialize, serde::Deserialize)]
+pub struct NoiseProfile {
+    /// The number of frames used to make the profile
+    pub frames: usize,
+    /// The width of the frames
+    pub width: i32,
+    /// The height of the frames
+    pub height: i32,
+    /// The noise of each channel, in the channel order of the camera
+    pub channels: Vec<ChannelNoise>,
+}
+
+/// Accumulates per pixel statistics over a series of frames
+struct NoiseCapture {
+    target: usize,
+    frames: usize,
+    width: i32,
+    height: i32,
+    channels: usize,
+    sum: Vec<f64>,
+    sum_sq: Vec<f64>,
+}
+
+impl NoiseCapture {
+    fn new(target: usize) -> Self {
+        Self {
+            target,
+            frames: 0,
+            width: 0,
+            height: 0,
+            channels: 0,
+            sum: Vec::new(),
+            sum_sq: Vec::new(),
+        }
+    }
+
+    fn add_frame(&mut self, img: &opencv::core::Mat) -> Result<(), String> {
+        if img.depth() != opencv::core::CV_8U {
+            return Err(tr!("noise.not_8_bit"));
+        }
+        let data = img.data_bytes().map_err(|e| e.to_string())?;
+        if self.frames == 0 {
+            self.width = img.cols();
+            self.height = img.rows();
+            self.channels = img.channels() as usize;
+            self.sum = vec![0.0; data.len()];
+            self.sum_sq = vec![0.0; data.len()];
+        } else if self.width != img.cols()
+            || self.height != img.rows()
+            || self.sum.len() != data.len()
+        {
+            return Err(tr!("noise.size_changed"));
+        }
+        for ((s, q), d) in self.sum.iter_mut().zip(&mut self.sum_sq).zip(data) {
+            let d = *d as f64;
+            *s += d;
+            *q += d * d;
+        }
+        self.frames += 1;
+        Ok(())
+    }
+
+    fn finish(&self) -> NoiseProfile {
+        let n = self.frames as f64;
+        let channels = (0..self.channels)
+            .map(|c| {
+                let mut bin_sigma = [0.0; BINS];
+                let mut bin_count = [0usize; BINS];
+                let mut total_mean = 0.0;
+                let mut total_sigma = 0.0;
+                let mut pixels = 0;
+                for (s, q) in self
+                    .sum
+                    .iter()
+                    .zip(&self.sum_sq)
+                    .skip(c)
+                    .step_by(self.channels)
+                {
+                    let mean = s / n;
+                    let sigma = (q / n - mean * mean).max(0.0).sqrt();
+                    let bin = ((mean / 256.0) * BINS as f64) as usize;
+                    bin_sigma[bin.min(BINS - 1)] += sigma;
+                    bin_count[bin.min(BINS - 1)] += 1;
+                    total_mean += mean;
+                    total_sigma += sigma;
+                    pixels += 1;
+                }
+                let snr_curve = (0..BINS)
+                    .filter(|b| bin_count[*b] > 0 && bin_sigma[*b] > 0.0)
+                    .map(|b| {
+                        let level = (b as f64 + 0.5) * 256.0 / BINS as f64;
+                        let sigma = bin_sigma[b] / bin_count[b] as f64;
+                        [level, 20.0 * (level / sigma).log10()]
+                    })
+                    .collect();
+                ChannelNoise {
+                    mean: total_mean / pixels.max(1) as f64,
+                    sigma: total_sigma / pixels.max(1) as f64,
+                    snr_curve,
+                }
+            })
+            .collect();
+        NoiseProfile {
+            frames: self.frames,
+            width: self.width,
+            height: self.height,
+            channels,
+        }
+    }
+}
+
+/// The noise profiling tool
+pub struct NoiseProfiler {
+    frames: usize,
+    capture: Option<NoiseCapture>,
+    error: Option<String>,
+}
+
+impl Default for NoiseProfiler {
+    fn default() -> Self {
+        Self {
+            frames: 32,
+            capture: None,
+            error: None,
+        }
+    }
+}
+
+impl NoiseProfiler {
+    /// Add a frame from the camera being profiled, returns the profile once enough frames are captured
+    pub fn add_frame(&mut self, img: &opencv::core::Mat) -> Option<NoiseProfile> {
+        let c = self.capture.as_mut()?;
+        if let Err(e) = c.add_frame(img) {
+            self.error = Some(e);
+            self.capture = None;
+            return None;
+        }
+        if c.frames >= c.target {
+            let p = c.finish();
+            self.capture = None;
+            Some(p)
+        } else {
+            None
+        }
+    }
+
+    /// Show the tool, along with the stored noise profile of the selected camera
+    pub fn show(&mut self, ui: &mut eframe::egui::Ui, profile: Option<&CameraProfile>) {
+        ui.label(tr!("noise.instructions"));
+        ui.horizontal(|ui| {
+            ui.add(eframe::egui::Slider::new(&mut self.frames, 8..=256).text(tr!("noise.frames")));
+            if let Some(c) = &self.capture {
+                ui.label(tr!("noise.progress", count = c.frames, total = c.target));
+                if ui.button(tr!("noise.cancel")).clicked() {
+                    self.capture = None;
+                }
+            } else if ui.button(tr!("noise.start")).clicked() {
+                self.error = None;
+                self.capture = Some(NoiseCapture::new(self.frames));
+            }
+        });
+        if let Some(e) = &self.error {
+            ui.colored_label(eframe::egui::Color32::RED, e);
+        }
+        if let Some(p) = profile.and_then(|p| p.noise.as_ref()) {
+            ui.label(tr!(
+                "noise.summary",
+                frames = p.frames,
+                width = p.width,
+                height = p.height
+            ));
+            let names = if p.channels.len() == 3 {
+                vec![tr!("noise.blue"), tr!("noise.green"), tr!("noise.red")]
+            } else {
+                vec![tr!("noise.gray"); p.channels.len()]
+            };
+            eframe::egui::Grid::new("noise_channels").show(ui, |ui| {
+                ui.label(tr!("noise.channel"));
+                ui.label(tr!("noise.mean"));
+                ui.label("σ");
+                ui.end_row();
+                for (name, c) in names.iter().zip(&p.channels) {
+                    ui.label(name);
+                    ui.label(format!("{:.2}", c.mean));
+                    ui.label(format!("{:.3}", c.sigma));
+                    ui.end_row();
+                }
+            });
+            Plot::new("noise_snr")
+                .view_aspect(2.0)
+                .legend(Legend::default())
+                .x_axis_label(tr!("noise.brightness"))
+                .y_axis_label(tr!("noise.snr"))
+                .show(ui, |plot_ui| {
+                    for (name, c) in names.iter().zip(&p.channels) {
+                        plot_ui.line(Line::new(PlotPoints::from(c.snr_curve.clone())).name(name));
+                    }
+                });
+        }
+    }
+}
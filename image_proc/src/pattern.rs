@@ -0,0 +1,335 @@
+//! Printable calibration patterns at a physical size, written as png images or single page pdf files
+
+use std::io::Write;
+use std::path::Path;
+
+use image::GrayImage;
+use opencv::core::{MatTraitConst, MatTraitConstManual};
+
+use image_proc::calibration::Grid;
+
+use crate::board::BoardParams;
+
+/// A calibration pattern, the lengths are in meters
+#[derive(Clone, Copy, Debug)]
+pub enum Pattern {
+    /// A chessboard with a marker in each white square
+    Charuco(BoardParams),
+    /// A plain chessboard of cols by rows squares
+    Chessboard { cols: u32, rows: u32, square: f64 },
+    /// Rows of cols circles, every other row shifted right by spacing, the rows are spacing apart
+    CircleGrid {
+        cols: u32,
+        rows: u32,
+        spacing: f64,
+        diameter: f64,
+    },
+    /// The kalibr grid of apriltags with black squares between the tags,
+    /// the spacing is the gap between tags relative to the tag size
+    AprilGrid {
+        tags_x: u32,
+        tags_y: u32,
+        tag: f64,
+        spacing: f64,
+    },
+}
+
+/// A length in meters as a number of pixels at a resolution in dots per inch
+fn pixels(meters: f64, dpi: f64) -> u32 {
+    (meters / 0.0254 * dpi).round() as u32
+}
+
+/// Fill a rectangle of an image, the end is not included
+fn fill(img: &mut GrayImage, start: [u32; 2], end: [u32; 2], value: u8) {
+    for y in start[1]..end[1].min(img.height()) {
+        for x in start[0]..end[0].min(img.width()) {
+            img.put_pixel(x, y, image::Luma([value]));
+        }
+    }
+}
+
+/// Fill a circle of an image black, the center and radius are in pixels
+fn fill_circle(img: &mut GrayImage, center: [f64; 2], radius: f64) {
+    let y0 = (center[1] - radius).floor().max(0.0) as u32;
+    let x0 = (center[0] - radius).floor().max(0.0) as u32;
+    let y1 = ((center[1] + radius).ceil() as u32).min(img.height());
+    let x1 = ((center[0] + radius).ceil() as u32).min(img.width());
+    for y in y0..y1 {
+        for x in x0..x1 {
+            // Measured from the middle of the pixel
+            let (dx, dy) = (x as f64 + 0.5 - center[0], y as f64 + 0.5 - center[1]);
+            if dx * dx + dy * dy <= radius * radius {
+                img.put_pixel(x, y, image::Luma([0]));
+            }
+        }
+    }
+}
+
+/// Copy a single channel opencv image into an image at a position
+fn paste(img: &mut GrayImage, mat: &opencv::core::Mat, at: [u32; 2]) -> Result<(), String> {
+    let data = mat.data_bytes().map_err(|e| e.to_string())?;
+    let (w, h) = (mat.cols() as u32, mat.rows() as u32);
+    for y in 0..h {
+        for x in 0..w {
+            let (px, py) = (at[0] + x, at[1] + y);
+            if px < img.width() && py < img.height() {
+                img.put_pixel(px, py, image::Luma([data[(y * w + x) as usize]]));
+            }
+        }
+    }
+    Ok(())
+}
+
+impl From<Grid> for Pattern {
+    fn from(g: Grid) -> Self {
+        match g {
+            Grid::Chessboard { cols, rows, square } => Pattern::Chessboard {
+                cols: cols as u32,
+                rows: rows as u32,
+                square: square as f64,
+            },
+            Grid::AsymmetricCircles {
+                cols,
+                rows,
+                spacing,
+                diameter,
+            } => Pattern::CircleGrid {
+                cols: cols as u32,
+                rows: rows as u32,
+                spacing: spacing as f64,
+                diameter: diameter as f64,
+            },
+        }
+    }
+}
+
+impl Pattern {
+    /// The white margin a target without markers needs around it to be found, one square or row spacing
+    fn margin(&self) -> f64 {
+        match self {
+            Pattern::Chessboard { square, .. } => *square,
+            Pattern::CircleGrid { spacing, .. } => *spacing,
+            Pattern::Charuco(_) | Pattern::AprilGrid { .. } => 0.0,
+        }
+    }
+
+    /// Draw the pattern with the margin it needs into an opencv image about a number of pixels wide
+    pub fn render_mat(&self, width: u32) -> Result<opencv::core::Mat, String> {
+        let margin = self.margin();
+        let dpi = width as f64 / ((self.size()[0] + 2.0 * margin) / 0.0254);
+        let img = self.render(dpi, margin)?;
+        image_proc::convert::bytes_to_mat(
+            img.width() as usize,
+            img.height() as usize,
+            opencv::core::CV_8UC1,
+            img.as_raw(),
+        )
+        .ok_or_else(|| "The pattern could not be converted".to_string())
+    }
+
+    /// The width and height of the pattern, in meters
+    pub fn size(&self) -> [f64; 2] {
+        match self {
+            Pattern::Charuco(b) => [
+                b.squares_x as f64 * b.square_length as f64,
+                b.squares_y as f64 * b.square_length as f64,
+            ],
+            Pattern::Chessboard { cols, rows, square } => {
+                [*cols as f64 * square, *rows as f64 * square]
+            }
+            Pattern::CircleGrid {
+                cols,
+                rows,
+                spacing,
+                diameter,
+            } => [
+                (2 * cols).saturating_sub(1) as f64 * spacing + diameter,
+                rows.saturating_sub(1) as f64 * spacing + diameter,
+            ],
+            Pattern::AprilGrid {
+                tags_x,
+                tags_y,
+                tag,
+                spacing,
+            } => {
+                let gap = tag * spacing;
+                [
+                    *tags_x as f64 * (tag + gap) + gap,
+                    *tags_y as f64 * (tag + gap) + gap,
+                ]
+            }
+        }
+    }
+
+    /// Draw the pattern at a resolution in dots per inch, surrounded by a white margin in meters
+    pub fn render(&self, dpi: f64, margin: f64) -> Result<GrayImage, String> {
+        let [w, h] = self.size();
+        let m = pixels(margin, dpi);
+        let mut img = GrayImage::from_pixel(
+            pixels(w, dpi) + 2 * m,
+            pixels(h, dpi) + 2 * m,
+            image::Luma([255]),
+        );
+        // Each edge is rounded to a pixel on its own so the rounding errors do not add up across the pattern
+        let at = |meters: f64| m + pixels(meters, dpi);
+        match self {
+            Pattern::Charuco(b) => {
+                // The second board of a two board target is drawn with its own markers
+                let board = if b.second_plane {
+                    b.make_second_board()
+                } else {
+                    b.make_board()
+                };
+                let mut board =
+                    board.ok_or_else(|| "The board parameters are not valid".to_string())?;
+                let size = opencv::core::Size::new(pixels(w, dpi) as i32, pixels(h, dpi) as i32);
+                let mat = image_proc::aruco::draw_board(&mut board, size, 0, 1)
+                    .map_err(|e| e.to_string())?;
+                paste(&mut img, &mat, [m, m])?;
+            }
+            Pattern::Chessboard { cols, rows, square } => {
+                for y in 0..*rows {
+                    for x in (y % 2..*cols).step_by(2) {
+                        let start = [at(x as f64 * square), at(y as f64 * square)];
+                        let end = [at((x + 1) as f64 * square), at((y + 1) as f64 * square)];
+                        fill(&mut img, start, end, 0);
+                    }
+                }
+            }
+            Pattern::CircleGrid {
+                cols,
+                rows,
+                spacing,
+                diameter,
+            } => {
+                // The circles are where the calibration looks for them
+                let grid = Grid::AsymmetricCircles {
+                    cols: *cols as i32,
+                    rows: *rows as i32,
+                    spacing: *spacing as f32,
+                    diameter: *diameter as f32,
+                };
+                let r = diameter / 2.0;
+                for p in grid.points() {
+                    let center = [r + p.x as f64, r + p.y as f64];
+                    fill_circle(
+                        &mut img,
+                        center.map(|c| m as f64 + c / 0.0254 * dpi),
+                        r / 0.0254 * dpi,
+                    );
+                }
+            }
+            Pattern::AprilGrid {
+                tags_x,
+                tags_y,
+                tag,
+                spacing,
+            } => {
+                let gap = tag * spacing;
+                let step = tag + gap;
+                for y in 0..=*tags_y {
+                    for x in 0..=*tags_x {
+                        let start = [at(x as f64 * step), at(y as f64 * step)];
+                        let end = [at(x as f64 * step + gap), at(y as f64 * step + gap)];
+                        fill(&mut img, start, end, 0);
+                    }
+                }
+                let dictionary =
+                    image_proc::aruco::dictionary(image_proc::aruco::DICT_APRILTAG_36H11)
+                        .map_err(|e| e.to_string())?;
+                let side = pixels(*tag, dpi) as i32;
+                for y in 0..*tags_y {
+                    for x in 0..*tags_x {
+                        // Kalibr numbers the tags from the bottom left corner, row by row
+                        let id = ((tags_y - 1 - y) * tags_x + x) as i32;
+                        // The kalibr tags have a black border two bits wide
+                        let mat = image_proc::aruco::draw_marker(&dictionary, id, side, 2)
+                            .map_err(|e| e.to_string())?;
+                        let start = [at(x as f64 * step + gap), at(y as f64 * step + gap)];
+                        paste(&mut img, &mat, start)?;
+                    }
+                }
+            }
+        }
+        Ok(img)
+    }
+}
+
+/// Save a rendered pattern, as a pdf page of the physical size when the extension is pdf and as an image otherwise
+pub fn save(img: &GrayImage, dpi: f64, path: &Path) -> Result<(), String> {
+    let pdf = path
+        .extension()
+        .and_then(|e| e.to_str())
+        .is_some_and(|e| e.eq_ignore_ascii_case("pdf"));
+    if pdf {
+        std::fs::write(path, pdf_page(img, dpi)?).map_err(|e| e.to_string())
+    } else {
+        img.save(path).map_err(|e| e.to_string())
+    }
+}
+
+/// A pdf file of one page holding the image at its physical size
+fn pdf_page(img: &GrayImage, dpi: f64) -> Result<Vec<u8>, String> {
+    let mut encoder = flate2::write::ZlibEncoder::new(Vec::new(), flate2::Compression::default());
+    encoder.write_all(img.as_raw()).map_err(|e| e.to_string())?;
+    let pixels = encoder.finish().map_err(|e| e.to_string())?;
+    // Pdf lengths are in points, 72 to the inch
+    let width = img.width() as f64 * 72.0 / dpi;
+    let height = img.height() as f64 * 72.0 / dpi;
+    let contents = format!("q {:.3} 0 0 {:.3} 0 0 cm /Im0 Do Q", width, height);
+
+    let mut out = b"%PDF-1.4\n".to_vec();
+    let mut offsets = Vec::new();
+    let mut object = |out: &mut Vec<u8>, dict: String, stream: Option<&[u8]>| {
+        offsets.push(out.len());
+        write!(out, "{} 0 obj\n{}\n", offsets.len(), dict).unwrap();
+        if let Some(s) = stream {
+            out.extend_from_slice(b"stream\n");
+            out.extend_from_slice(s);
+            out.extend_from_slice(b"\nendstream\n");
+        }
+        out.extend_from_slice(b"endobj\n");
+    };
+    object(&mut out, "<< /Type /Catalog /Pages 2 0 R >>".into(), None);
+    object(
+        &mut out,
+        "<< /Type /Pages /Kids [3 0 R] /Count 1 >>".into(),
+        None,
+    );
+    object(
+        &mut out,
+        format!(
+            "<< /Type /Page /Parent 2 0 R /MediaBox [0 0 {:.3} {:.3}] /Resources << /XObject << /Im0 4 0 R >> >> /Contents 5 0 R >>",
+            width, height
+        ),
+        None,
+    );
+    object(
+        &mut out,
+        format!(
+            "<< /Type /XObject /Subtype /Image /Width {} /Height {} /ColorSpace /DeviceGray /BitsPerComponent 8 /Interpolate false /Filter /FlateDecode /Length {} >>",
+            img.width(),
+            img.height(),
+            pixels.len()
+        ),
+        Some(&pixels),
+    );
+    object(
+        &mut out,
+        format!("<< /Length {} >>", contents.len()),
+        Some(contents.as_bytes()),
+    );
+    let xref = out.len();
+    write!(out, "xref\n0 {}\n0000000000 65535 f \n", offsets.len() + 1).unwrap();
+    for o in &offsets {
+        write!(out, "{:010} 00000 n \n", o).unwrap();
+    }
+    write!(
+        out,
+        "trailer\n<< /Size {} /Root 1 0 R >>\nstartxref\n{}\n%%EOF\n",
+        offsets.len() + 1,
+        xref
+    )
+    .unwrap();
+    Ok(out)
+}
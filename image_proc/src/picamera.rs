@@ -0,0 +1,61 @@
+//! Raspberry Pi CSI cameras, which only work through libcamera on recent versions of Raspberry Pi OS
+
+/// A camera found by libcamera
+pub struct PiCamera {
+    /// The index in the list of cameras
+    index: usize,
+    /// The sensor model, like imx708
+    model: String,
+    /// The libcamera id, like /base/axi/pcie@120000/rp1/i2c@88000/imx708@1a
+    id: String,
+}
+
+impl PiCamera {
+    /// The resolution frames are requested at, the sensor scales to this
+    pub const SIZE: [u32; 2] = [1920, 1080];
+
+    /// Find the cameras with the rpicam tools, returns nothing when they are not installed
+    pub fn detect() -> Vec<PiCamera> {
+        // The tools were renamed, older systems only have the libcamera name
+        let output = ["rpicam-hello", "libcamera-hello"]
+            .into_iter()
+            .find_map(|p| {
+                std::process::Command::new(p)
+                    .arg("--list-cameras")
+                    .output()
+                    .ok()
+                    .filter(|o| o.status.success())
+            });
+        let Some(output) = output else {
+            return Vec::new();
+        };
+        String::from_utf8_lossy(&output.stdout)
+            .lines()
+            .filter_map(Self::parse)
+            .collect()
+    }
+
+    /// Parse a camera from a line like "0 : imx708 [4608x2592 10-bit RGGB] (/base/soc/i2c0mux/i2c@1/imx708@1a)"
+    fn parse(line: &str) -> Option<PiCamera> {
+        let (index, rest) = line.trim().split_once(" : ")?;
+        let index = index.parse().ok()?;
+        let model = rest.split_whitespace().next()?.to_string();
+        let id = rest.rsplit_once('(')?.1.strip_suffix(')')?.to_string();
+        Some(PiCamera { index, model, id })
+    }
+
+    /// The name shown to the user
+    pub fn name(&self) -> String {
+        tr!("main.pi_camera", index = self.index, model = self.model)
+    }
+
+    /// The gstreamer pipeline that produces bgr frames from the camera
+    pub fn pipeline(&self) -> String {
+        format!(
+            "libcamerasrc camera-name=\"{}\" ! video/x-raw,width={},height={} ! videoconvert ! video/x-raw,format=BGR ! appsink drop=true max-buffers=1",
+            self.id,
+            Self::SIZE[0],
+            Self::SIZE[1]
+        )
+    }
+}
@@ -0,0 +1,362 @@
+//! The processing pipeline, a user configurable list of stages applied to every displayed image.
+//! The stages that need opencv or the script engine are left out of wasm builds.
+
+mod chromatic;
+mod color;
+#[cfg(not(target_arch = "wasm32"))]
+mod deconvolve;
+mod desqueeze;
+mod exposure;
+mod levels;
+mod rotate;
+#[cfg(not(target_arch = "wasm32"))]
+mod script;
+mod white_balance;
+
+pub use chromatic::ChromaticAberration;
+pub use color::{Saturation, SplitTone};
+#[cfg(not(target_arch = "wasm32"))]
+pub use deconvolve::Deconvolution;
+pub use desqueeze::Desqueeze;
+use eframe::egui::{
+    Color32, ColorImage,
+    ecolor::{gamma_u8_from_linear_f32, linear_f32_from_gamma_u8},
+};
+pub use exposure::Exposure;
+pub use levels::{Contrast, Levels};
+pub use rotate::Rotate;
+#[cfg(not(target_arch = "wasm32"))]
+pub use script::Script;
+pub use white_balance::WhiteBalance;
+
+/// An image in linear light with floating point channels, so processing does not lose precision between stages
+#[derive(Clone, Debug)]
+pub struct LinearImage {
+    pub size: [usize; 2],
+    pub pixels: Vec<[f32; 3]>,
+}
+
+impl From<&ColorImage> for LinearImage {
+    fn from(img: &ColorImage) -> Self {
+        let lut: Vec<f32> = (0..=255u8).map(linear_f32_from_gamma_u8).collect();
+        Self {
+            size: img.size,
+            pixels: img
+                .pixels
+                .iter()
+                .map(|p| {
+                    [
+                        lut[p.r() as usize],
+                        lut[p.g() as usize],
+                        lut[p.b() as usize],
+                    ]
+                })
+                .collect(),
+        }
+    }
+}
+
+impl From<&LinearImage> for ColorImage {
+    fn from(img: &LinearImage) -> Self {
+        Self {
+            size: img.size,
+            pixels: img
+                .pixels
+                .iter()
+                .map(|p| {
+                    Color32::from_rgb(
+                        gamma_u8_from_linear_f32(p[0]),
+                        gamma_u8_from_linear_f32(p[1]),
+                        gamma_u8_from_linear_f32(p[2]),
+                    )
+                })
+                .collect(),
+        }
+    }
+}
+
+/// A single step of the processing pipeline
+#[enum_dispatch::enum_dispatch]
+pub trait PipelineStageTrait {
+    /// The name of the stage as shown in the ui, in the language of the ui
+    fn name(&self) -> String;
+    /// A name of the stage that does not change with the language, for recording how an image was processed
+    fn key(&self) -> &'static str;
+    /// Process an image
+    fn process(&self, img: ColorImage) -> ColorImage;
+    /// Process an image in linear light, stages that only work on gamma encoded images are given a converted copy
+    fn process_linear(&self, img: LinearImage) -> LinearImage {
+        LinearImage::from(&self.process(ColorImage::from(&img)))
+    }
+    /// Show the parameters of the stage, returns true when a parameter changed
+    fn show(&mut self, ui: &mut eframe::egui::Ui) -> bool;
+    /// True when the stage is waiting for a color to be picked from the preview
+    fn picking(&self) -> bool {
+        false
+    }
+    /// Receive the picked color, in linear light, as it was before this stage
+    fn pick(&mut self, _color: [f32; 3]) {}
+    /// True when the stage needs to see an image to compute its parameters
+    fn analyzing(&self) -> bool {
+        false
+    }
+    /// Compute the parameters of the stage from the image as it reaches this stage
+    fn analyze(&mut self, _img: &ColorImage) {}
+}
+
+/// All of the kinds of pipeline stages
+#[enum_dispatch::enum_dispatch(PipelineStageTrait)]
+#[derive(Clone, Debug, serde::Serialize, serde::Deserialize)]
+pub enum PipelineStage {
+    ChromaticAberration(ChromaticAberration),
+    Contrast(Contrast),
+    #[cfg(not(target_arch = "wasm32"))]
+    Deconvolution(Deconvolution),
+    Desqueeze(Desqueeze),
+    Exposure(Exposure),
+    Levels(Levels),
+    Rotate(Rotate),
+    Saturation(Saturation),
+    #[cfg(not(target_arch = "wasm32"))]
+    Script(Script),
+    SplitTone(SplitTone),
+    WhiteBalance(WhiteBalance),
+}
+
+impl PipelineStage {
+    /// A default instance of every kind of stage, used for adding stages to the pipeline
+    fn all() -> Vec<PipelineStage> {
+        vec![
+            ChromaticAberration::default().into(),
+            Contrast::default().into(),
+            #[cfg(not(target_arch = "wasm32"))]
+            Deconvolution::default().into(),
+            Desqueeze::default().into(),
+            Exposure::default().into(),
+            Levels::default().into(),
+            Rotate::default().into(),
+            Saturation::default().into(),
+            #[cfg(not(target_arch = "wasm32"))]
+            Script::default().into(),
+            SplitTone::default().into(),
+            WhiteBalance::default().into(),
+        ]
+    }
+}
+
+/// A stage of the pipeline that can be disabled without removing it
+#[derive(Clone, Debug, serde::Serialize, serde::Deserialize)]
+struct PipelineEntry {
+    enabled: bool,
+    stage: PipelineStage,
+}
+
+/// An ordered list of processing stages
+#[derive(Clone, Debug, Default, serde::Serialize, serde::Deserialize)]
+pub struct Pipeline {
+    stages: Vec<PipelineEntry>,
+    /// Process in linear light, converting back to srgb only at the end
+    #[serde(default)]
+    pub linear: bool,
+}
+
+impl Pipeline {
+    /// Run an image through every enabled stage of the pipeline
+    pub fn process(&self, img: ColorImage) -> ColorImage {
+        let mut stages = self.stages.iter().filter(|s| s.enabled).peekable();
+        if !self.linear || stages.peek().is_none() {
+            return stages.fold(img, |img, s| s.stage.process(img));
+        }
+        let linear = stages.fold(LinearImage::from(&img), |img, s| {
+            s.stage.process_linear(img)
+        });
+        ColorImage::from(&linear)
+    }
+
+    /// Set the exposure stage of the pipeline, adding one after any desqueezing or rotating when there is none
+    pub fn set_exposure(&mut self, stops: f32) {
+        for s in &mut self.stages {
+            if let PipelineStage::Exposure(e) = &mut s.stage {
+                e.stops = stops;
+                s.enabled = true;
+                return;
+            }
+        }
+        let i = self
+            .stages
+            .iter()
+            .take_while(|s| {
+                matches!(
+                    s.stage,
+                    PipelineStage::Desqueeze(_) | PipelineStage::Rotate(_)
+                )
+            })
+            .count();
+        self.stages.insert(
+            i,
+            PipelineEntry {
+                enabled: true,
+                stage: Exposure { stops }.into(),
+            },
+        );
+    }
+
+    /// The enabled stages in order with their settings, for recording how an image was processed
+    pub fn description(&self) -> serde_json::Value {
+        let stages: Vec<serde_json::Value> = self
+            .stages
+            .iter()
+            .filter(|s| s.enabled)
+            .map(|s| serde_json::json!({ "name": s.stage.key(), "settings": s.stage }))
+            .collect();
+        serde_json::json!({ "linear": self.linear, "stages": stages })
+    }
+
+    /// Let the stages that asked for it compute their parameters from the image as it reaches them
+    pub fn analyze(&mut self, img: &ColorImage) {
+        if !self.stages.iter().any(|s| s.enabled && s.stage.analyzing()) {
+            return;
+        }
+        let mut img = img.clone();
+        for s in self.stages.iter_mut().filter(|s| s.enabled) {
+            if s.stage.analyzing() {
+                s.stage.analyze(&img);
+            }
+            img = s.stage.process(img);
+        }
+    }
+
+    /// True when an enabled stage is waiting for a color to be picked from the preview
+    pub fn picking(&self) -> bool {
+        self.stages.iter().any(|s| s.enabled && s.stage.picking())
+    }
+
+    /// Pick a color for the stage waiting for one. The position is a fraction of the width and height of the processed image,
+    /// the color is averaged over a small area of the image as it reaches that stage.
+    pub fn pick(&mut self, img: ColorImage, pos: [f32; 2]) {
+        let mut img = img;
+        for s in self.stages.iter_mut().filter(|s| s.enabled) {
+            if s.stage.picking() {
+                let [w, h] = img.size;
+                let cx = (pos[0] * w as f32) as isize;
+                let cy = (pos[1] * h as f32) as isize;
+                let mut sum = [0.0; 3];
+                let mut count = 0;
+                for y in (cy - 2).max(0)..(cy + 3).min(h as isize) {
+                    for x in (cx - 2).max(0)..(cx + 3).min(w as isize) {
+                        let p = img.pixels[y as usize * w + x as usize];
+                        let l = eframe::egui::Rgba::from(p);
+                        sum[0] += l.r();
+                        sum[1] += l.g();
+                        sum[2] += l.b();
+                        count += 1;
+                    }
+                }
+                if count > 0 {
+                    s.stage.pick(sum.map(|c| c / count as f32));
+                }
+                return;
+            }
+            img = s.stage.process(img);
+        }
+    }
+
+    /// Show the pipeline editor, returns true when the pipeline changed
+    pub fn show(&mut self, ui: &mut eframe::egui::Ui) -> bool {
+        let mut changed = ui
+            .checkbox(&mut self.linear, tr!("pipeline.linear"))
+            .on_hover_text(tr!("pipeline.linear_hint"))
+            .changed();
+        ui.separator();
+        let mut action = None;
+        let count = self.stages.len();
+        for (i, s) in self.stages.iter_mut().enumerate() {
+            ui.push_id(i, |ui| {
+                ui.horizontal(|ui| {
+                    changed |= ui.checkbox(&mut s.enabled, s.stage.name()).changed();
+                    if ui
+                        .add_enabled(i > 0, eframe::egui::Button::new(tr!("pipeline.up")))
+                        .clicked()
+                    {
+                        action = Some((i, -1));
+                    }
+                    if ui
+                        .add_enabled(
+                            i + 1 < count,
+                            eframe::egui::Button::new(tr!("pipeline.down")),
+                        )
+                        .clicked()
+                    {
+                        action = Some((i, 1));
+                    }
+                    if ui.button(tr!("pipeline.remove")).clicked() {
+                        action = Some((i, 0));
+                    }
+                });
+                ui.indent("stage", |ui| {
+                    changed |= s.stage.show(ui);
+                });
+            });
+            ui.separator();
+        }
+        match action {
+            Some((i, 0)) => {
+                self.stages.remove(i);
+                changed = true;
+            }
+            Some((i, d)) => {
+                self.stages.swap(i, (i as isize + d) as usize);
+                changed = true;
+            }
+            None => {}
+        }
+        eframe::egui::ComboBox::from_label(tr!("pipeline.add"))
+            .selected_text("")
+            .show_ui(ui, |ui| {
+                for s in PipelineStage::all() {
+                    if ui.selectable_label(false, s.name()).clicked() {
+                        let entry = PipelineEntry {
+                            enabled: true,
+                            stage: s,
+                        };
+                        // Desqueezing and rotating happen before any other processing
+                        if matches!(
+                            entry.stage,
+                            PipelineStage::Desqueeze(_) | PipelineStage::Rotate(_)
+                        ) {
+                            self.stages.insert(0, entry);
+                        } else {
+                            self.stages.push(entry);
+                        }
+                        changed = true;
+                    }
+                }
+            });
+        changed
+    }
+}
+
+/// Calculate the rec. 601 luma of every pixel of an image
+pub fn luminance(img: &ColorImage) -> Vec<u8> {
+    img.pixels
+        .iter()
+        .map(|p| (0.299 * p.r() as f32 + 0.587 * p.g() as f32 + 0.114 * p.b() as f32).round() as u8)
+        .collect()
+}
+
+/// Sample a channel of an image at a fractional position with bilinear interpolation
+pub fn sample_bilinear(img: &ColorImage, x: f32, y: f32, channel: usize) -> u8 {
+    let [w, h] = img.size;
+    let x = x.clamp(0.0, (w - 1) as f32);
+    let y = y.clamp(0.0, (h - 1) as f32);
+    let x0 = x.floor() as usize;
+    let y0 = y.floor() as usize;
+    let x1 = (x0 + 1).min(w - 1);
+    let y1 = (y0 + 1).min(h - 1);
+    let fx = x - x0 as f32;
+    let fy = y - y0 as f32;
+    let p = |x: usize, y: usize| img.pixels[y * w + x].to_array()[channel] as f32;
+    let top = p(x0, y0) * (1.0 - fx) + p(x1, y0) * fx;
+    let bottom = p(x0, y1) * (1.0 - fx) + p(x1, y1) * fx;
+    (top * (1.0 - fy) + bottom * fy).round() as u8
+}
@@ -0,0 +1,154 @@
+//! Correction of lateral chromatic aberration by radially scaling the red and blue channels
+
+use eframe::egui::{Color32, ColorImage};
+
+use super::{PipelineStageTrait, sample_bilinear};
+
+/// The radial scaling of a single channel, using the lensfun poly3 tca model.
+/// The scale at radius r is v + c * r + b * r^2, where r is 1 at half the shorter image side.
+#[derive(Clone, Copy, Debug, serde::Serialize, serde::Deserialize)]
+pub struct TcaCoefficients {
+    pub v: f32,
+    pub c: f32,
+    pub b: f32,
+}
+
+impl Default for TcaCoefficients {
+    fn default() -> Self {
+        Self {
+            v: 1.0,
+            c: 0.0,
+            b: 0.0,
+        }
+    }
+}
+
+impl TcaCoefficients {
+    fn scale(&self, r: f32) -> f32 {
+        self.v + self.c * r + self.b * r * r
+    }
+
+    fn show(&mut self, ui: &mut eframe::egui::Ui, name: &str) -> bool {
+        ui.horizontal(|ui| {
+            ui.label(name);
+            let mut changed = false;
+            for (label, v) in [("v", &mut self.v), ("c", &mut self.c), ("b", &mut self.b)] {
+                ui.label(label);
+                changed |= ui
+                    .add(
+                        eframe::egui::DragValue::new(v)
+                            .speed(0.0001)
+                            .fixed_decimals(5),
+                    )
+                    .changed();
+            }
+            changed
+        })
+        .inner
+    }
+}
+
+/// Find the value of an attribute in an xml element
+fn attribute(element: &str, name: &str) -> Option<f32> {
+    let start = element.find(&format!(" {}=\"", name))? + name.len() + 3;
+    let len = element[start..].find('"')?;
+    element[start..start + len].trim().parse().ok()
+}
+
+/// Scales the red and blue channels about the center of the image to line them up with green
+#[derive(Clone, Debug, Default, serde::Serialize, serde::Deserialize)]
+pub struct ChromaticAberration {
+    pub red: TcaCoefficients,
+    pub blue: TcaCoefficients,
+    /// A lensfun tca element to import coefficients from
+    #[serde(skip)]
+    lensfun: String,
+}
+
+impl ChromaticAberration {
+    /// Parse a lensfun tca element, like <tca model="poly3" focal="18" vr="1.0002" vb="0.9998"/>
+    pub fn from_lensfun(element: &str) -> Option<Self> {
+        let (red, blue) = if element.contains("model=\"linear\"") {
+            (
+                TcaCoefficients {
+                    v: attribute(element, "kr")?,
+                    ..Default::default()
+                },
+                TcaCoefficients {
+                    v: attribute(element, "kb")?,
+                    ..Default::default()
+                },
+            )
+        } else {
+            (
+                TcaCoefficients {
+                    v: attribute(element, "vr").unwrap_or(1.0),
+                    c: attribute(element, "cr").unwrap_or(0.0),
+                    b: attribute(element, "br").unwrap_or(0.0),
+                },
+                TcaCoefficients {
+                    v: attribute(element, "vb").unwrap_or(1.0),
+                    c: attribute(element, "cb").unwrap_or(0.0),
+                    b: attribute(element, "bb").unwrap_or(0.0),
+                },
+            )
+        };
+        Some(Self {
+            red,
+            blue,
+            lensfun: String::new(),
+        })
+    }
+}
+
+impl PipelineStageTrait for ChromaticAberration {
+    fn name(&self) -> String {
+        tr!("pipeline.stage.chromatic_aberration")
+    }
+
+    fn key(&self) -> &'static str {
+        "Chromatic aberration"
+    }
+
+    fn process(&self, img: ColorImage) -> ColorImage {
+        let [w, h] = img.size;
+        let cx = (w as f32 - 1.0) / 2.0;
+        let cy = (h as f32 - 1.0) / 2.0;
+        let norm = 2.0 / w.min(h) as f32;
+        let mut pixels = Vec::with_capacity(img.pixels.len());
+        for y in 0..h {
+            for x in 0..w {
+                let dx = x as f32 - cx;
+                let dy = y as f32 - cy;
+                let r = (dx * dx + dy * dy).sqrt() * norm;
+                let sr = self.red.scale(r);
+                let sb = self.blue.scale(r);
+                let red = sample_bilinear(&img, cx + dx * sr, cy + dy * sr, 0);
+                let blue = sample_bilinear(&img, cx + dx * sb, cy + dy * sb, 2);
+                let green = img.pixels[y * w + x].g();
+                pixels.push(Color32::from_rgb(red, green, blue));
+            }
+        }
+        ColorImage {
+            size: img.size,
+            pixels,
+        }
+    }
+
+    fn show(&mut self, ui: &mut eframe::egui::Ui) -> bool {
+        let mut changed = self.red.show(ui, &tr!("chromatic.red"));
+        changed |= self.blue.show(ui, &tr!("chromatic.blue"));
+        ui.horizontal(|ui| {
+            ui.label(tr!("chromatic.lensfun"));
+            ui.text_edit_singleline(&mut self.lensfun);
+            if ui.button(tr!("chromatic.import")).clicked() {
+                if let Some(c) = Self::from_lensfun(&self.lensfun) {
+                    self.red = c.red;
+                    self.blue = c.blue;
+                    changed = true;
+                }
+            }
+        });
+        changed
+    }
+}
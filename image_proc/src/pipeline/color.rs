@@ -0,0 +1,186 @@
+//! Adjustments of the color of the image, saturation and toning of the shadows and highlights
+
+use eframe::egui::{Color32, ColorImage, ecolor::Hsva};
+
+use super::{LinearImage, PipelineStageTrait};
+
+/// The luminance of a pixel, from 0 to 1
+fn luminance(p: [f32; 3]) -> f32 {
+    (0.2126 * p[0] + 0.7152 * p[1] + 0.0722 * p[2]) / 255.0
+}
+
+/// Apply a function to the color channels of every pixel of an image
+fn map_pixels(img: ColorImage, f: impl Fn([f32; 3]) -> [f32; 3]) -> ColorImage {
+    let pixels = img
+        .pixels
+        .iter()
+        .map(|p| {
+            let [r, g, b] = f([p.r() as f32, p.g() as f32, p.b() as f32])
+                .map(|c| c.round().clamp(0.0, 255.0) as u8);
+            Color32::from_rgba_unmultiplied(r, g, b, p.a())
+        })
+        .collect();
+    ColorImage {
+        size: img.size,
+        pixels,
+    }
+}
+
+/// Saturation of every color, and vibrance which mostly affects the colors that are not already saturated
+#[derive(Clone, Debug, Default, serde::Serialize, serde::Deserialize)]
+pub struct Saturation {
+    /// The change in saturation, -1 is grayscale
+    pub saturation: f32,
+    /// The change in saturation of the duller colors
+    pub vibrance: f32,
+}
+
+impl Saturation {
+    /// Saturate a pixel, with channels of any scale
+    fn saturate(&self, p: [f32; 3]) -> [f32; 3] {
+        let l = 0.2126 * p[0] + 0.7152 * p[1] + 0.0722 * p[2];
+        let max = p.iter().copied().fold(0.0, f32::max);
+        let min = p.iter().copied().fold(f32::MAX, f32::min);
+        let current = if max > 0.0 { (max - min) / max } else { 0.0 };
+        let k = (1.0 + self.saturation) * (1.0 + self.vibrance * (1.0 - current));
+        p.map(|c| (l + (c - l) * k.max(0.0)).max(0.0))
+    }
+}
+
+impl PipelineStageTrait for Saturation {
+    fn name(&self) -> String {
+        tr!("pipeline.stage.saturation")
+    }
+
+    fn key(&self) -> &'static str {
+        "Saturation and vibrance"
+    }
+
+    fn process(&self, img: ColorImage) -> ColorImage {
+        map_pixels(img, |p| self.saturate(p))
+    }
+
+    fn process_linear(&self, mut img: LinearImage) -> LinearImage {
+        for p in &mut img.pixels {
+            *p = self.saturate(*p);
+        }
+        img
+    }
+
+    fn show(&mut self, ui: &mut eframe::egui::Ui) -> bool {
+        let mut changed = false;
+        changed |= ui
+            .add(
+                eframe::egui::Slider::new(&mut self.saturation, -1.0..=1.0)
+                    .text(tr!("toning.saturation")),
+            )
+            .changed();
+        changed |= ui
+            .add(
+                eframe::egui::Slider::new(&mut self.vibrance, -1.0..=1.0)
+                    .text(tr!("toning.vibrance")),
+            )
+            .changed();
+        changed
+    }
+}
+
+/// A tint for one end of the tonal range
+#[derive(Clone, Copy, Debug, serde::Serialize, serde::Deserialize)]
+pub struct Tone {
+    /// The hue of the tint, from 0 to 1
+    pub hue: f32,
+    /// How strongly the tint is applied, from 0 to 1
+    pub strength: f32,
+}
+
+impl Tone {
+    /// The offset added to a pixel fully in this end of the range, with no change in luminance
+    fn offset(&self) -> [f32; 3] {
+        let c = Color32::from(Hsva::new(self.hue, 1.0, 1.0, 1.0));
+        let c = [c.r() as f32, c.g() as f32, c.b() as f32];
+        let l = luminance(c) * 255.0;
+        c.map(|v| (v - l) * self.strength)
+    }
+
+    fn show(&mut self, ui: &mut eframe::egui::Ui, name: &str) -> bool {
+        ui.horizontal(|ui| {
+            ui.label(name);
+            let mut changed = ui
+                .add(eframe::egui::Slider::new(&mut self.hue, 0.0..=1.0).text(tr!("toning.hue")))
+                .changed();
+            changed |= ui
+                .add(
+                    eframe::egui::Slider::new(&mut self.strength, 0.0..=1.0)
+                        .text(tr!("toning.strength")),
+                )
+                .changed();
+            let swatch = Color32::from(Hsva::new(self.hue, self.strength, 1.0, 1.0));
+            let (r, _) = ui
+                .allocate_exact_size(eframe::egui::vec2(16.0, 16.0), eframe::egui::Sense::hover());
+            ui.painter().rect_filled(r, 2.0, swatch);
+            changed
+        })
+        .inner
+    }
+}
+
+/// Tints the shadows and highlights with separate colors
+#[derive(Clone, Debug, serde::Serialize, serde::Deserialize)]
+pub struct SplitTone {
+    pub shadows: Tone,
+    pub highlights: Tone,
+    /// Moves the split between shadows and highlights, positive gives more of the range to the highlights
+    pub balance: f32,
+}
+
+impl Default for SplitTone {
+    fn default() -> Self {
+        Self {
+            shadows: Tone {
+                hue: 0.6,
+                strength: 0.0,
+            },
+            highlights: Tone {
+                hue: 0.1,
+                strength: 0.0,
+            },
+            balance: 0.0,
+        }
+    }
+}
+
+impl PipelineStageTrait for SplitTone {
+    fn name(&self) -> String {
+        tr!("pipeline.stage.split_tone")
+    }
+
+    fn key(&self) -> &'static str {
+        "Split toning"
+    }
+
+    fn process(&self, img: ColorImage) -> ColorImage {
+        let shadows = self.shadows.offset();
+        let highlights = self.highlights.offset();
+        let split = (0.5 - self.balance * 0.5).clamp(0.01, 0.99);
+        map_pixels(img, |p| {
+            let l = luminance(p);
+            // Each tint fades out towards the split point
+            let ws = (1.0 - l / split).clamp(0.0, 1.0);
+            let wh = ((l - split) / (1.0 - split)).clamp(0.0, 1.0);
+            [0, 1, 2].map(|c| p[c] + shadows[c] * ws + highlights[c] * wh)
+        })
+    }
+
+    fn show(&mut self, ui: &mut eframe::egui::Ui) -> bool {
+        let mut changed = self.shadows.show(ui, &tr!("toning.shadows"));
+        changed |= self.highlights.show(ui, &tr!("toning.highlights"));
+        changed |= ui
+            .add(
+                eframe::egui::Slider::new(&mut self.balance, -1.0..=1.0)
+                    .text(tr!("toning.balance")),
+            )
+            .changed();
+        changed
+    }
+}
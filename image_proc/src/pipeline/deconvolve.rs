@@ -0,0 +1,352 @@
+//! Sharpening by deconvolution with a gaussian point spread function, for slightly defocused captures
+
+use std::sync::{Arc, Mutex};
+
+use eframe::egui::ColorImage;
+use opencv::core::{MatTraitConst, MatTraitConstManual, MatTraitManual};
+
+use super::PipelineStageTrait;
+use crate::convert::{color_image_to_mat, mat_to_color_image};
+
+/// How the blur is undone
+#[derive(Clone, Copy, Debug, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub enum DeconvolutionMethod {
+    /// A single pass in the frequency domain
+    Wiener,
+    /// Iterative, slower but with fewer ringing artifacts
+    RichardsonLucy,
+}
+
+/// Undoes a gaussian blur of the image
+#[derive(Clone, Debug, serde::Serialize, serde::Deserialize)]
+pub struct Deconvolution {
+    pub method: DeconvolutionMethod,
+    /// The standard deviation of the point spread function in pixels
+    pub sigma: f32,
+    /// The noise to signal power ratio for the Wiener filter, larger is less sharp but less noisy
+    pub noise: f32,
+    /// The number of Richardson-Lucy iterations
+    pub iterations: u32,
+    /// The result of the last point spread function estimate
+    #[serde(skip)]
+    estimate: Option<String>,
+    /// The error from the most recent deconvolution, shared because processing does not have mutable access
+    #[serde(skip)]
+    error: Arc<Mutex<Option<String>>>,
+}
+
+impl Default for Deconvolution {
+    fn default() -> Self {
+        Self {
+            method: DeconvolutionMethod::Wiener,
+            sigma: 1.0,
+            noise: 0.01,
+            iterations: 10,
+            estimate: None,
+            error: Default::default(),
+        }
+    }
+}
+
+/// The weighted variance of offsets from the center of a window
+fn variance(weights: impl Iterator<Item = (f32, f32)>) -> Option<f32> {
+    let (mut sum, mut total) = (0.0, 0.0);
+    for (d2, w) in weights {
+        sum += d2 * w;
+        total += w;
+    }
+    (total > 0.0).then(|| sum / total)
+}
+
+/// Estimate the point spread function from an image of a small bright point on a dark background
+pub fn estimate_from_point(gray: &[f32], size: [usize; 2]) -> Option<f32> {
+    let [w, h] = size;
+    let (peak, _) = gray.iter().enumerate().max_by(|a, b| a.1.total_cmp(b.1))?;
+    let (px, py) = ((peak % w) as isize, (peak / w) as isize);
+    let r = 15;
+    let window: Vec<(isize, isize, f32)> = (-r..=r)
+        .flat_map(|dy| (-r..=r).map(move |dx| (dx, dy)))
+        .filter_map(|(dx, dy)| {
+            let (x, y) = (px + dx, py + dy);
+            (x >= 0 && y >= 0 && (x as usize) < w && (y as usize) < h)
+                .then(|| (dx, dy, gray[y as usize * w + x as usize]))
+        })
+        .collect();
+    // The background is taken from the edge of the window
+    let mut border: Vec<f32> = window
+        .iter()
+        .filter(|(dx, dy, _)| dx.abs() == r || dy.abs() == r)
+        .map(|p| p.2)
+        .collect();
+    border.sort_by(|a, b| a.total_cmp(b));
+    let background = *border.get(border.len() / 2)?;
+    // A 2d gaussian has a mean squared radius of twice its variance
+    let v = variance(
+        window
+            .iter()
+            .map(|(dx, dy, v)| ((dx * dx + dy * dy) as f32, (v - background).max(0.0))),
+    )?;
+    Some((v / 2.0).sqrt())
+}
+
+/// Estimate the point spread function from an image of a sharp edge, which should be roughly vertical or horizontal
+pub fn estimate_from_edge(gray: &[f32], size: [usize; 2]) -> Option<f32> {
+    let [w, h] = size;
+    // The spread of the gradient across the edge along lines of length n, at(line, i) is the value at position i
+    let spread = |lines: usize, n: usize, at: &dyn Fn(usize, usize) -> f32| {
+        let r = 10isize;
+        let mut profile = vec![0.0f32; (2 * r + 1) as usize];
+        let mut energy = 0.0;
+        for l in 0..lines {
+            let d: Vec<f32> = (0..n - 1)
+                .map(|i| (at(l, i + 1) - at(l, i)).abs())
+                .collect();
+            let Some((peak, _)) = d.iter().enumerate().max_by(|a, b| a.1.total_cmp(b.1)) else {
+                continue;
+            };
+            for (k, p) in profile.iter_mut().enumerate() {
+                let i = peak as isize + k as isize - r;
+                if i >= 0 && (i as usize) < d.len() {
+                    *p += d[i as usize];
+                    energy += d[i as usize];
+                }
+            }
+        }
+        let min = profile.iter().copied().fold(f32::MAX, f32::min);
+        let v = variance(
+            profile
+                .iter()
+                .enumerate()
+                .map(|(k, p)| (((k as isize - r) * (k as isize - r)) as f32, p - min)),
+        )?;
+        Some((v.sqrt(), energy))
+    };
+    if w < 2 || h < 2 {
+        return None;
+    }
+    let across = spread(h, w, &|l, i| gray[l * w + i]);
+    let down = spread(w, h, &|l, i| gray[i * w + l]);
+    // The edge is where the gradient is strongest
+    match (across, down) {
+        (Some(a), Some(d)) => Some(if a.1 >= d.1 { a.0 } else { d.0 }),
+        (a, d) => a.or(d).map(|s| s.0),
+    }
+}
+
+impl Deconvolution {
+    /// Estimate the point spread function from an image file of a point or an edge
+    fn estimate(&mut self, point: bool) {
+        let Some(f) = rfd::FileDialog::new()
+            .add_filter("Image", &["png", "jpg", "jpeg"])
+            .pick_file()
+        else {
+            return;
+        };
+        let img = match image::open(&f) {
+            Ok(i) => i.to_luma32f(),
+            Err(e) => {
+                self.estimate = Some(tr!("deconvolve.open_failed", path = f.display(), error = e));
+                return;
+            }
+        };
+        let size = [img.width() as usize, img.height() as usize];
+        let r = if point {
+            estimate_from_point(img.as_raw(), size)
+        } else {
+            estimate_from_edge(img.as_raw(), size)
+        };
+        self.estimate = Some(match r {
+            Some(s) => {
+                self.sigma = s.max(0.1);
+                tr!("deconvolve.estimated", blur = format!("{:.2}", s))
+            }
+            None => tr!("deconvolve.not_found"),
+        });
+    }
+
+    fn wiener(&self, channel: &opencv::core::Mat) -> opencv::Result<opencv::core::Mat> {
+        let mut spectrum = opencv::core::Mat::default();
+        opencv::core::dft(channel, &mut spectrum, opencv::core::DFT_COMPLEX_OUTPUT, 0)?;
+        let (w, h) = (spectrum.cols() as usize, spectrum.rows() as usize);
+        let s2 = (self.sigma as f64).powi(2);
+        let freq = |k: usize, n: usize| {
+            let k = if k > n / 2 {
+                k as f64 - n as f64
+            } else {
+                k as f64
+            };
+            k / n as f64
+        };
+        for (i, c) in spectrum
+            .data_typed_mut::<opencv::core::Vec2f>()?
+            .iter_mut()
+            .enumerate()
+        {
+            let (fx, fy) = (freq(i % w, w), freq(i / w, h));
+            // The fourier transform of a gaussian is a gaussian
+            let g = (-2.0 * std::f64::consts::PI.powi(2) * s2 * (fx * fx + fy * fy)).exp();
+            let k = (g / (g * g + self.noise as f64)) as f32;
+            c[0] *= k;
+            c[1] *= k;
+        }
+        let mut out = opencv::core::Mat::default();
+        opencv::core::idft(
+            &spectrum,
+            &mut out,
+            opencv::core::DFT_SCALE | opencv::core::DFT_REAL_OUTPUT,
+            0,
+        )?;
+        Ok(out)
+    }
+
+    fn richardson_lucy(&self, img: &opencv::core::Mat) -> opencv::Result<opencv::core::Mat> {
+        let blur = |m: &opencv::core::Mat| -> opencv::Result<opencv::core::Mat> {
+            let mut out = opencv::core::Mat::default();
+            opencv::imgproc::gaussian_blur_def(
+                m,
+                &mut out,
+                opencv::core::Size::default(),
+                self.sigma as f64,
+            )?;
+            Ok(out)
+        };
+        let mut estimate = img.clone();
+        for _ in 0..self.iterations {
+            let mut blurred = opencv::core::Mat::default();
+            opencv::core::add_def(
+                &blur(&estimate)?,
+                &opencv::core::Scalar::all(1e-3),
+                &mut blurred,
+            )?;
+            let mut ratio = opencv::core::Mat::default();
+            opencv::core::divide2_def(img, &blurred, &mut ratio)?;
+            let mut next = opencv::core::Mat::default();
+            opencv::core::multiply_def(&estimate, &blur(&ratio)?, &mut next)?;
+            estimate = next;
+        }
+        Ok(estimate)
+    }
+
+    fn deconvolve(&self, img: &ColorImage) -> opencv::Result<Option<ColorImage>> {
+        let Some(m) = color_image_to_mat(img) else {
+            return Ok(None);
+        };
+        // The borders are extended so the image does not wrap around in the frequency domain
+        let pad = (self.sigma * 3.0).ceil() as i32 + 1;
+        let mut padded = opencv::core::Mat::default();
+        opencv::core::copy_make_border_def(
+            &m,
+            &mut padded,
+            pad,
+            pad,
+            pad,
+            pad,
+            opencv::core::BORDER_REFLECT,
+        )?;
+        let mut f = opencv::core::Mat::default();
+        padded.convert_to(&mut f, opencv::core::CV_32F, 1.0, 0.0)?;
+        let sharp = match self.method {
+            DeconvolutionMethod::Wiener => {
+                let mut channels = opencv::core::Vector::<opencv::core::Mat>::new();
+                opencv::core::split(&f, &mut channels)?;
+                let mut out = opencv::core::Vector::<opencv::core::Mat>::new();
+                for c in &channels {
+                    out.push(self.wiener(&c)?);
+                }
+                let mut merged = opencv::core::Mat::default();
+                opencv::core::merge(&out, &mut merged)?;
+                merged
+            }
+            DeconvolutionMethod::RichardsonLucy => self.richardson_lucy(&f)?,
+        };
+        let inner = opencv::core::Rect::new(pad, pad, m.cols(), m.rows());
+        let cropped = sharp.roi(inner)?.try_clone()?;
+        let mut out = opencv::core::Mat::default();
+        cropped.convert_to(&mut out, opencv::core::CV_8U, 1.0, 0.0)?;
+        Ok(mat_to_color_image(&out))
+    }
+}
+
+impl PipelineStageTrait for Deconvolution {
+    fn name(&self) -> String {
+        tr!("pipeline.stage.deconvolution")
+    }
+
+    fn key(&self) -> &'static str {
+        "Deconvolution sharpening"
+    }
+
+    fn process(&self, img: ColorImage) -> ColorImage {
+        let (img, error) = match self.deconvolve(&img) {
+            Ok(Some(sharp)) => (sharp, None),
+            Ok(None) => (img, None),
+            Err(e) => (img, Some(e.to_string())),
+        };
+        if let Ok(mut g) = self.error.lock() {
+            *g = error;
+        }
+        img
+    }
+
+    fn show(&mut self, ui: &mut eframe::egui::Ui) -> bool {
+        let mut changed = false;
+        ui.horizontal(|ui| {
+            changed |= ui
+                .radio_value(
+                    &mut self.method,
+                    DeconvolutionMethod::Wiener,
+                    tr!("deconvolve.wiener"),
+                )
+                .changed();
+            changed |= ui
+                .radio_value(
+                    &mut self.method,
+                    DeconvolutionMethod::RichardsonLucy,
+                    tr!("deconvolve.richardson_lucy"),
+                )
+                .changed();
+        });
+        changed |= ui
+            .add(
+                eframe::egui::Slider::new(&mut self.sigma, 0.1..=10.0).text(tr!("deconvolve.blur")),
+            )
+            .changed();
+        match self.method {
+            DeconvolutionMethod::Wiener => {
+                changed |= ui
+                    .add(
+                        eframe::egui::Slider::new(&mut self.noise, 0.0001..=0.5)
+                            .logarithmic(true)
+                            .text(tr!("deconvolve.noise")),
+                    )
+                    .changed();
+            }
+            DeconvolutionMethod::RichardsonLucy => {
+                changed |= ui
+                    .add(
+                        eframe::egui::Slider::new(&mut self.iterations, 1..=50)
+                            .text(tr!("deconvolve.iterations")),
+                    )
+                    .changed();
+            }
+        }
+        ui.horizontal(|ui| {
+            ui.label(tr!("deconvolve.estimate_from"));
+            if ui.button(tr!("deconvolve.point")).clicked() {
+                self.estimate(true);
+                changed = true;
+            }
+            if ui.button(tr!("deconvolve.edge")).clicked() {
+                self.estimate(false);
+                changed = true;
+            }
+        });
+        if let Some(e) = &self.estimate {
+            ui.label(e);
+        }
+        if let Some(e) = self.error.lock().ok().and_then(|e| e.clone()) {
+            ui.colored_label(eframe::egui::Color32::RED, e);
+        }
+        changed
+    }
+}
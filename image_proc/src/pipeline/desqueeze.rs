@@ -0,0 +1,89 @@
+//! Stretching of footage from anamorphic lenses and adapters back to the correct aspect ratio
+
+use eframe::egui::{Color32, ColorImage};
+
+use super::{PipelineStageTrait, sample_bilinear};
+
+/// Common anamorphic squeeze factors
+const PRESETS: [f32; 4] = [1.33, 1.5, 1.8, 2.0];
+
+/// Stretches the image along one axis
+#[derive(Clone, Debug, serde::Serialize, serde::Deserialize)]
+pub struct Desqueeze {
+    /// The amount to stretch by
+    pub factor: f32,
+    /// Stretch vertically instead of horizontally
+    pub vertical: bool,
+}
+
+impl Default for Desqueeze {
+    fn default() -> Self {
+        Self {
+            factor: 1.33,
+            vertical: false,
+        }
+    }
+}
+
+impl PipelineStageTrait for Desqueeze {
+    fn name(&self) -> String {
+        tr!("pipeline.stage.desqueeze")
+    }
+
+    fn key(&self) -> &'static str {
+        "Anamorphic desqueeze"
+    }
+
+    fn process(&self, img: ColorImage) -> ColorImage {
+        let (fx, fy) = if self.vertical {
+            (1.0, self.factor)
+        } else {
+            (self.factor, 1.0)
+        };
+        let w = (img.width() as f32 * fx).round().max(1.0) as usize;
+        let h = (img.height() as f32 * fy).round().max(1.0) as usize;
+        let mut pixels = Vec::with_capacity(w * h);
+        for y in 0..h {
+            let sy = (y as f32 + 0.5) / fy - 0.5;
+            for x in 0..w {
+                let sx = (x as f32 + 0.5) / fx - 0.5;
+                pixels.push(Color32::from_rgb(
+                    sample_bilinear(&img, sx, sy, 0),
+                    sample_bilinear(&img, sx, sy, 1),
+                    sample_bilinear(&img, sx, sy, 2),
+                ));
+            }
+        }
+        ColorImage {
+            size: [w, h],
+            pixels,
+        }
+    }
+
+    fn show(&mut self, ui: &mut eframe::egui::Ui) -> bool {
+        let mut changed = false;
+        ui.horizontal(|ui| {
+            for p in PRESETS {
+                if ui
+                    .selectable_label(self.factor == p, format!("{}x", p))
+                    .clicked()
+                {
+                    self.factor = p;
+                    changed = true;
+                }
+            }
+            changed |= ui
+                .add(
+                    eframe::egui::DragValue::new(&mut self.factor)
+                        .speed(0.01)
+                        .range(1.0..=4.0)
+                        .suffix("x"),
+                )
+                .changed();
+        });
+        changed |= ui
+            .checkbox(&mut self.vertical, tr!("desqueeze.vertical"))
+            .changed();
+        changed
+    }
+}
@@ -0,0 +1,66 @@
+//! Brightening or darkening of the image by a number of stops, applied in linear light
+
+use eframe::egui::{
+    Color32, ColorImage,
+    ecolor::{gamma_u8_from_linear_f32, linear_f32_from_gamma_u8},
+};
+
+use super::{LinearImage, PipelineStageTrait};
+
+/// Multiplies the linear light of every pixel by a power of two
+#[derive(Clone, Debug, Default, serde::Serialize, serde::Deserialize)]
+pub struct Exposure {
+    /// The change in exposure, in stops
+    pub stops: f32,
+}
+
+impl PipelineStageTrait for Exposure {
+    fn name(&self) -> String {
+        tr!("pipeline.stage.exposure")
+    }
+
+    fn key(&self) -> &'static str {
+        "Exposure"
+    }
+
+    fn process(&self, img: ColorImage) -> ColorImage {
+        let gain = 2.0f32.powf(self.stops);
+        let mut lut = [0u8; 256];
+        for (i, v) in lut.iter_mut().enumerate() {
+            *v = gamma_u8_from_linear_f32(linear_f32_from_gamma_u8(i as u8) * gain);
+        }
+        let pixels = img
+            .pixels
+            .iter()
+            .map(|p| {
+                Color32::from_rgba_unmultiplied(
+                    lut[p.r() as usize],
+                    lut[p.g() as usize],
+                    lut[p.b() as usize],
+                    p.a(),
+                )
+            })
+            .collect();
+        ColorImage {
+            size: img.size,
+            pixels,
+        }
+    }
+
+    fn process_linear(&self, mut img: LinearImage) -> LinearImage {
+        let gain = 2.0f32.powf(self.stops);
+        for p in &mut img.pixels {
+            *p = p.map(|c| c * gain);
+        }
+        img
+    }
+
+    fn show(&mut self, ui: &mut eframe::egui::Ui) -> bool {
+        ui.add(
+            eframe::egui::Slider::new(&mut self.stops, -5.0..=5.0)
+                .suffix(" EV")
+                .text(tr!("exposure.exposure")),
+        )
+        .changed()
+    }
+}
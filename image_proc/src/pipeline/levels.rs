@@ -0,0 +1,240 @@
+//! Stretching of the tonal range, with black and white points found from the histogram of the image
+
+use eframe::egui::{Color32, ColorImage};
+
+use super::PipelineStageTrait;
+
+/// The value below which a fraction of the histogram lies
+fn percentile(histogram: &[u64; 256], fraction: f32) -> f32 {
+    let total: u64 = histogram.iter().sum();
+    let target = (total as f64 * fraction as f64).round() as u64;
+    let mut sum = 0;
+    for (i, h) in histogram.iter().enumerate() {
+        sum += h;
+        if sum > target {
+            return i as f32;
+        }
+    }
+    255.0
+}
+
+/// The black and white points of a histogram, ignoring clip percent of the pixels at each end
+fn auto_range(histogram: &[u64; 256], clip: f32) -> (f32, f32) {
+    let black = percentile(histogram, clip / 100.0);
+    let white = percentile(histogram, 1.0 - clip / 100.0);
+    if white > black {
+        (black, white)
+    } else {
+        (0.0, 255.0)
+    }
+}
+
+/// A lookup table mapping black to 0 and white to 255, with a gamma adjustment of the midtones
+fn levels_lut(black: f32, white: f32, gamma: f32) -> [u8; 256] {
+    let mut lut = [0; 256];
+    let range = (white - black).max(1.0);
+    for (i, v) in lut.iter_mut().enumerate() {
+        let x = ((i as f32 - black) / range).clamp(0.0, 1.0);
+        *v = (x.powf(1.0 / gamma) * 255.0).round() as u8;
+    }
+    lut
+}
+
+/// Apply a lookup table to each channel of an image
+fn apply(img: ColorImage, luts: [&[u8; 256]; 3]) -> ColorImage {
+    let pixels = img
+        .pixels
+        .iter()
+        .map(|p| {
+            Color32::from_rgba_unmultiplied(
+                luts[0][p.r() as usize],
+                luts[1][p.g() as usize],
+                luts[2][p.b() as usize],
+                p.a(),
+            )
+        })
+        .collect();
+    ColorImage {
+        size: img.size,
+        pixels,
+    }
+}
+
+/// The clip percentage slider and auto button shared by the stages, returns true when auto was clicked
+fn show_auto(ui: &mut eframe::egui::Ui, clip: &mut f32, label: &str) -> bool {
+    ui.horizontal(|ui| {
+        ui.add(
+            eframe::egui::Slider::new(clip, 0.0..=5.0)
+                .suffix("%")
+                .text(tr!("levels.clip")),
+        );
+        ui.button(label).clicked()
+    })
+    .inner
+}
+
+/// Black point, white point and gamma for each channel separately, which also removes color casts
+#[derive(Clone, Debug, serde::Serialize, serde::Deserialize)]
+pub struct Levels {
+    pub black: [f32; 3],
+    pub white: [f32; 3],
+    pub gamma: f32,
+    /// The percentage of pixels allowed to clip at each end when finding the points automatically
+    pub clip: f32,
+    /// True when the points should be found from the next image
+    #[serde(skip)]
+    auto: bool,
+}
+
+impl Default for Levels {
+    fn default() -> Self {
+        Self {
+            black: [0.0; 3],
+            white: [255.0; 3],
+            gamma: 1.0,
+            clip: 0.5,
+            auto: false,
+        }
+    }
+}
+
+impl PipelineStageTrait for Levels {
+    fn name(&self) -> String {
+        tr!("pipeline.stage.levels")
+    }
+
+    fn key(&self) -> &'static str {
+        "Levels"
+    }
+
+    fn process(&self, img: ColorImage) -> ColorImage {
+        let luts: Vec<[u8; 256]> = (0..3)
+            .map(|c| levels_lut(self.black[c], self.white[c], self.gamma))
+            .collect();
+        apply(img, [&luts[0], &luts[1], &luts[2]])
+    }
+
+    fn show(&mut self, ui: &mut eframe::egui::Ui) -> bool {
+        let mut changed = false;
+        eframe::egui::Grid::new("levels").show(ui, |ui| {
+            for (c, name) in ["levels.red", "levels.green", "levels.blue"]
+                .iter()
+                .enumerate()
+            {
+                ui.label(tr!(*name));
+                changed |= ui
+                    .add(
+                        eframe::egui::DragValue::new(&mut self.black[c])
+                            .range(0.0..=254.0)
+                            .prefix(tr!("levels.black_prefix")),
+                    )
+                    .changed();
+                changed |= ui
+                    .add(
+                        eframe::egui::DragValue::new(&mut self.white[c])
+                            .range(1.0..=255.0)
+                            .prefix(tr!("levels.white_prefix")),
+                    )
+                    .changed();
+                ui.end_row();
+            }
+        });
+        changed |= ui
+            .add(
+                eframe::egui::Slider::new(&mut self.gamma, 0.1..=10.0)
+                    .logarithmic(true)
+                    .text(tr!("levels.gamma")),
+            )
+            .changed();
+        if show_auto(ui, &mut self.clip, &tr!("levels.auto_levels")) {
+            self.auto = true;
+            changed = true;
+        }
+        changed
+    }
+
+    fn analyzing(&self) -> bool {
+        self.auto
+    }
+
+    fn analyze(&mut self, img: &ColorImage) {
+        self.auto = false;
+        let mut histograms = [[0u64; 256]; 3];
+        for p in &img.pixels {
+            histograms[0][p.r() as usize] += 1;
+            histograms[1][p.g() as usize] += 1;
+            histograms[2][p.b() as usize] += 1;
+        }
+        for (c, h) in histograms.iter().enumerate() {
+            (self.black[c], self.white[c]) = auto_range(h, self.clip);
+        }
+        self.gamma = 1.0;
+    }
+}
+
+/// The same black and white point for every channel, found from the luminance so colors are kept
+#[derive(Clone, Debug, serde::Serialize, serde::Deserialize)]
+pub struct Contrast {
+    pub black: f32,
+    pub white: f32,
+    /// The percentage of pixels allowed to clip at each end when finding the points automatically
+    pub clip: f32,
+    /// True when the points should be found from the next image
+    #[serde(skip)]
+    auto: bool,
+}
+
+impl Default for Contrast {
+    fn default() -> Self {
+        Self {
+            black: 0.0,
+            white: 255.0,
+            clip: 0.5,
+            auto: false,
+        }
+    }
+}
+
+impl PipelineStageTrait for Contrast {
+    fn name(&self) -> String {
+        tr!("pipeline.stage.contrast")
+    }
+
+    fn key(&self) -> &'static str {
+        "Contrast stretch"
+    }
+
+    fn process(&self, img: ColorImage) -> ColorImage {
+        let lut = levels_lut(self.black, self.white, 1.0);
+        apply(img, [&lut; 3])
+    }
+
+    fn show(&mut self, ui: &mut eframe::egui::Ui) -> bool {
+        let mut changed = false;
+        changed |= ui
+            .add(eframe::egui::Slider::new(&mut self.black, 0.0..=254.0).text(tr!("levels.black")))
+            .changed();
+        changed |= ui
+            .add(eframe::egui::Slider::new(&mut self.white, 1.0..=255.0).text(tr!("levels.white")))
+            .changed();
+        if show_auto(ui, &mut self.clip, &tr!("levels.auto_contrast")) {
+            self.auto = true;
+            changed = true;
+        }
+        changed
+    }
+
+    fn analyzing(&self) -> bool {
+        self.auto
+    }
+
+    fn analyze(&mut self, img: &ColorImage) {
+        self.auto = false;
+        let mut histogram = [0u64; 256];
+        for p in &img.pixels {
+            let l = 0.299 * p.r() as f32 + 0.587 * p.g() as f32 + 0.114 * p.b() as f32;
+            histogram[(l.round() as usize).min(255)] += 1;
+        }
+        (self.black, self.white) = auto_range(&histogram, self.clip);
+    }
+}
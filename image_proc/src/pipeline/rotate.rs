@@ -0,0 +1,69 @@
+//! Turning the image of a camera mounted on its side or upside down the right way up
+
+use eframe::egui::ColorImage;
+
+use super::PipelineStageTrait;
+
+/// Rotates the image by a multiple of 90 degrees and optionally mirrors it
+#[derive(Clone, Debug, Default, serde::Serialize, serde::Deserialize)]
+pub struct Rotate {
+    /// The number of quarter turns clockwise, from 0 to 3
+    pub quarter_turns: u8,
+    /// Mirror the image left to right after rotating it
+    pub mirror: bool,
+}
+
+impl PipelineStageTrait for Rotate {
+    fn name(&self) -> String {
+        tr!("pipeline.stage.rotate")
+    }
+
+    fn key(&self) -> &'static str {
+        "Rotate"
+    }
+
+    fn process(&self, img: ColorImage) -> ColorImage {
+        let turns = self.quarter_turns % 4;
+        if turns == 0 && !self.mirror {
+            return img;
+        }
+        let [w, h] = img.size;
+        let (ow, oh) = if turns % 2 == 1 { (h, w) } else { (w, h) };
+        let mut pixels = Vec::with_capacity(w * h);
+        for y in 0..oh {
+            for x in 0..ow {
+                let x = if self.mirror { ow - 1 - x } else { x };
+                let (sx, sy) = match turns {
+                    1 => (y, h - 1 - x),
+                    2 => (w - 1 - x, h - 1 - y),
+                    3 => (w - 1 - y, x),
+                    _ => (x, y),
+                };
+                pixels.push(img.pixels[sy * w + sx]);
+            }
+        }
+        ColorImage {
+            size: [ow, oh],
+            pixels,
+        }
+    }
+
+    fn show(&mut self, ui: &mut eframe::egui::Ui) -> bool {
+        let mut changed = false;
+        ui.horizontal(|ui| {
+            for t in 0..4u8 {
+                if ui
+                    .selectable_label(self.quarter_turns % 4 == t, format!("{}°", t as u32 * 90))
+                    .clicked()
+                {
+                    self.quarter_turns = t;
+                    changed = true;
+                }
+            }
+        });
+        changed |= ui
+            .checkbox(&mut self.mirror, tr!("rotate.mirror"))
+            .changed();
+        changed
+    }
+}
@@ -0,0 +1,365 @@
+//! A pipeline stage running a user supplied rhai script, for prototyping processing without recompiling
+//!
+//! The script gets the image in the variable `image` and whatever is left in that variable afterwards is the output.
+//! Images have `width` and `height` properties, and these functions:
+//! * `get(x, y)` returns the pixel as `[r, g, b]`, `set(x, y, [r, g, b])` changes it
+//! * `region(x, y, w, h)` copies part of the image, `paste(x, y, region)` puts it back
+//! * `blur(sigma)`, `gray()`, `invert()` and `threshold(level)` are the built in filters
+//! * `detect_markers()` finds aruco markers, returning an array of `#{id, corners}` where corners is an array of `[x, y]`
+//!
+//! `new_image(w, h)` makes a new black image.
+//!
+//! Scripts run on the thread processing the images, so they are stopped when they run for too long or grow too large.
+
+use std::cell::Cell;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use eframe::egui::{Color32, ColorImage};
+
+use super::PipelineStageTrait;
+
+/// The image type as seen by scripts
+#[derive(Clone)]
+struct ScriptImage {
+    img: ColorImage,
+    /// The aruco dictionary used for marker detection
+    dictionary: i32,
+}
+
+type ScriptResult<T> = Result<T, Box<rhai::EvalAltResult>>;
+
+/// The longest a script runs on one image before it is stopped
+const MAX_TIME: Duration = Duration::from_secs(2);
+/// The most operations a script runs on one image
+const MAX_OPERATIONS: u64 = 500_000_000;
+/// The deepest function calls can be nested
+const MAX_CALL_LEVELS: usize = 64;
+/// The most elements in an array
+const MAX_ARRAY_SIZE: usize = 1_000_000;
+/// The longest string
+const MAX_STRING_SIZE: usize = 1_000_000;
+/// The most pixels in an image made by a script
+const MAX_PIXELS: i64 = 100_000_000;
+
+thread_local! {
+    /// When the script running on this thread was started
+    static STARTED: Cell<Option<Instant>> = const { Cell::new(None) };
+}
+
+impl ScriptImage {
+    fn index(&self, x: i64, y: i64) -> ScriptResult<usize> {
+        let [w, h] = self.img.size;
+        if x < 0 || y < 0 || x as usize >= w || y as usize >= h {
+            return Err(format!("Pixel {},{} is outside of the {}x{} image", x, y, w, h).into());
+        }
+        Ok(y as usize * w + x as usize)
+    }
+
+    fn get(&mut self, x: i64, y: i64) -> ScriptResult<rhai::Array> {
+        let p = self.img.pixels[self.index(x, y)?];
+        Ok(vec![
+            (p.r() as i64).into(),
+            (p.g() as i64).into(),
+            (p.b() as i64).into(),
+        ])
+    }
+
+    fn set(&mut self, x: i64, y: i64, rgb: rhai::Array) -> ScriptResult<()> {
+        let i = self.index(x, y)?;
+        let c: Vec<u8> = rgb
+            .iter()
+            .map(|v| v.as_int().map(|v| v.clamp(0, 255) as u8))
+            .collect::<Result<_, _>>()
+            .map_err(|t| format!("Pixel values must be integers, not {}", t))?;
+        if c.len() != 3 {
+            return Err("Pixels must be set with [r, g, b]".into());
+        }
+        self.img.pixels[i] = Color32::from_rgb(c[0], c[1], c[2]);
+        Ok(())
+    }
+
+    fn region(&mut self, x: i64, y: i64, w: i64, h: i64) -> ScriptResult<ScriptImage> {
+        if w <= 0 || h <= 0 {
+            return Err("Regions must have a positive size".into());
+        }
+        self.index(x, y)?;
+        let (Some(right), Some(bottom)) = (x.checked_add(w - 1), y.checked_add(h - 1)) else {
+            return Err("The region is outside of the image".into());
+        };
+        self.index(right, bottom)?;
+        let width = self.img.width();
+        let mut pixels = Vec::with_capacity((w * h) as usize);
+        for row in y..y + h {
+            let start = row as usize * width + x as usize;
+            pixels.extend_from_slice(&self.img.pixels[start..start + w as usize]);
+        }
+        Ok(ScriptImage {
+            img: ColorImage {
+                size: [w as usize, h as usize],
+                pixels,
+            },
+            dictionary: self.dictionary,
+        })
+    }
+
+    fn paste(&mut self, x: i64, y: i64, region: ScriptImage) -> ScriptResult<()> {
+        let [w, h] = region.img.size;
+        self.index(x, y)?;
+        let (Some(right), Some(bottom)) =
+            (x.checked_add(w as i64 - 1), y.checked_add(h as i64 - 1))
+        else {
+            return Err("The region is outside of the image".into());
+        };
+        self.index(right, bottom)?;
+        let width = self.img.width();
+        for row in 0..h {
+            let start = (y as usize + row) * width + x as usize;
+            self.img.pixels[start..start + w]
+                .copy_from_slice(&region.img.pixels[row * w..(row + 1) * w]);
+        }
+        Ok(())
+    }
+
+    fn blur(&mut self, sigma: f64) -> ScriptResult<ScriptImage> {
+        let m = crate::convert::color_image_to_mat(&self.img).ok_or("Failed to convert image")?;
+        let mut out = opencv::core::Mat::default();
+        opencv::imgproc::gaussian_blur_def(&m, &mut out, opencv::core::Size::new(0, 0), sigma)
+            .map_err(|e| e.to_string())?;
+        let img = crate::convert::mat_to_color_image(&out).ok_or("Failed to convert image")?;
+        Ok(ScriptImage {
+            img,
+            dictionary: self.dictionary,
+        })
+    }
+
+    fn gray(&mut self) -> ScriptImage {
+        let l = super::luminance(&self.img);
+        ScriptImage {
+            img: ColorImage::from_gray(self.img.size, &l),
+            dictionary: self.dictionary,
+        }
+    }
+
+    fn invert(&mut self) -> ScriptImage {
+        let mut img = self.img.clone();
+        for p in &mut img.pixels {
+            *p = Color32::from_rgb(255 - p.r(), 255 - p.g(), 255 - p.b());
+        }
+        ScriptImage {
+            img,
+            dictionary: self.dictionary,
+        }
+    }
+
+    fn threshold(&mut self, level: i64) -> ScriptImage {
+        let l: Vec<u8> = super::luminance(&self.img)
+            .into_iter()
+            .map(|v| if v as i64 >= level { 255 } else { 0 })
+            .collect();
+        ScriptImage {
+            img: ColorImage::from_gray(self.img.size, &l),
+            dictionary: self.dictionary,
+        }
+    }
+
+    fn detect_markers(&mut self) -> ScriptResult<rhai::Array> {
+        let m = crate::convert::color_image_to_mat(&self.img).ok_or("Failed to convert image")?;
+        let d = crate::aruco::dictionary(self.dictionary).map_err(|e| e.to_string())?;
+        let (corners, ids, _) = crate::aruco::detect_markers(&m, &d).map_err(|e| e.to_string())?;
+        Ok(ids
+            .iter()
+            .zip(corners.iter())
+            .map(|(id, c)| {
+                let mut marker = rhai::Map::new();
+                marker.insert("id".into(), (id as i64).into());
+                let c: rhai::Array = c
+                    .iter()
+                    .map(|p| {
+                        let p: rhai::Array = vec![(p.x as f64).into(), (p.y as f64).into()];
+                        p.into()
+                    })
+                    .collect();
+                marker.insert("corners".into(), c.into());
+                marker.into()
+            })
+            .collect())
+    }
+}
+
+/// Make a new black image for a script
+fn new_image(w: i64, h: i64) -> ScriptResult<ScriptImage> {
+    let (w, h) = (w.max(1), h.max(1));
+    if w.checked_mul(h).is_none_or(|p| p > MAX_PIXELS) {
+        return Err(format!("A {}x{} image is too large", w, h).into());
+    }
+    Ok(ScriptImage {
+        img: ColorImage::new([w as usize, h as usize], Color32::BLACK),
+        dictionary: crate::aruco::DICT_6X6_1000,
+    })
+}
+
+/// Make the script engine with the image api registered
+fn make_engine() -> rhai::Engine {
+    let mut e = rhai::Engine::new();
+    e.set_max_operations(MAX_OPERATIONS)
+        .set_max_call_levels(MAX_CALL_LEVELS)
+        .set_max_array_size(MAX_ARRAY_SIZE)
+        .set_max_string_size(MAX_STRING_SIZE)
+        .on_progress(|_| {
+            STARTED
+                .get()
+                .is_some_and(|s| s.elapsed() > MAX_TIME)
+                .then_some(rhai::Dynamic::UNIT)
+        });
+    e.register_type_with_name::<ScriptImage>("Image")
+        .register_get("width", |i: &mut ScriptImage| i.img.width() as i64)
+        .register_get("height", |i: &mut ScriptImage| i.img.height() as i64)
+        .register_fn("get", ScriptImage::get)
+        .register_fn("set", ScriptImage::set)
+        .register_fn("region", ScriptImage::region)
+        .register_fn("paste", ScriptImage::paste)
+        .register_fn("blur", ScriptImage::blur)
+        .register_fn("gray", ScriptImage::gray)
+        .register_fn("invert", ScriptImage::invert)
+        .register_fn("threshold", ScriptImage::threshold)
+        .register_fn("detect_markers", ScriptImage::detect_markers)
+        .register_fn("new_image", new_image);
+    e
+}
+
+thread_local! {
+    static ENGINE: rhai::Engine = make_engine();
+}
+
+/// Runs a rhai script on every image
+#[derive(Clone, Debug, serde::Serialize, serde::Deserialize)]
+pub struct Script {
+    /// The source code of the script
+    pub source: String,
+    /// The aruco dictionary for marker detection in the script
+    pub dictionary: i32,
+    /// The compiled script, None when it has not been compiled yet
+    #[serde(skip)]
+    ast: Option<Arc<rhai::AST>>,
+    /// The error from compiling the script
+    #[serde(skip)]
+    compile_error: Option<String>,
+    /// The error from the most recent run of the script, shared because processing does not have mutable access
+    #[serde(skip)]
+    run_error: Arc<Mutex<Option<String>>>,
+}
+
+impl Default for Script {
+    fn default() -> Self {
+        Self {
+            source: "image = image.blur(2.0);\n".to_string(),
+            dictionary: crate::aruco::DICT_6X6_1000,
+            ast: None,
+            compile_error: None,
+            run_error: Default::default(),
+        }
+    }
+}
+
+impl Script {
+    fn compile(&self) -> Result<rhai::AST, String> {
+        ENGINE.with(|e| e.compile(&self.source).map_err(|e| e.to_string()))
+    }
+
+    fn set_run_error(&self, e: Option<String>) {
+        if let Ok(mut g) = self.run_error.lock() {
+            *g = e;
+        }
+    }
+
+    fn run(&self, img: ColorImage) -> Result<ColorImage, String> {
+        let ast = match &self.ast {
+            Some(a) => a.clone(),
+            None => Arc::new(self.compile()?),
+        };
+        let mut scope = rhai::Scope::new();
+        scope.push(
+            "image",
+            ScriptImage {
+                img,
+                dictionary: self.dictionary,
+            },
+        );
+        STARTED.set(Some(Instant::now()));
+        let result = ENGINE.with(|e| e.run_ast_with_scope(&mut scope, &ast));
+        STARTED.set(None);
+        result.map_err(|e| match *e {
+            rhai::EvalAltResult::ErrorTerminated(..)
+            | rhai::EvalAltResult::ErrorTooManyOperations(..) => tr!("script.too_long"),
+            e => e.to_string(),
+        })?;
+        scope
+            .get_value::<ScriptImage>("image")
+            .map(|i| i.img)
+            .ok_or_else(|| tr!("script.no_image"))
+    }
+}
+
+impl PipelineStageTrait for Script {
+    fn name(&self) -> String {
+        tr!("pipeline.stage.script")
+    }
+
+    fn key(&self) -> &'static str {
+        "Script"
+    }
+
+    fn process(&self, img: ColorImage) -> ColorImage {
+        match self.run(img.clone()) {
+            Ok(out) => {
+                self.set_run_error(None);
+                out
+            }
+            Err(e) => {
+                self.set_run_error(Some(e));
+                img
+            }
+        }
+    }
+
+    fn show(&mut self, ui: &mut eframe::egui::Ui) -> bool {
+        let mut changed = false;
+        let name = crate::aruco::DICTIONARIES
+            .iter()
+            .find(|d| d.0 == self.dictionary)
+            .map(|d| d.1.to_string())
+            .unwrap_or_else(|| tr!("script.unknown"));
+        eframe::egui::ComboBox::from_label(tr!("script.dictionary"))
+            .selected_text(name)
+            .show_ui(ui, |ui| {
+                for (d, name) in crate::aruco::DICTIONARIES {
+                    changed |= ui.selectable_value(&mut self.dictionary, d, name).changed();
+                }
+            });
+        let r = ui.add(
+            eframe::egui::TextEdit::multiline(&mut self.source)
+                .code_editor()
+                .desired_rows(8)
+                .desired_width(f32::INFINITY),
+        );
+        // Compile when editing is done, so a half typed script does not replace the working one
+        if r.lost_focus() || (self.ast.is_none() && self.compile_error.is_none()) {
+            match self.compile() {
+                Ok(a) => {
+                    self.ast = Some(Arc::new(a));
+                    self.compile_error = None;
+                    changed = true;
+                }
+                Err(e) => self.compile_error = Some(e),
+            }
+        }
+        if let Some(e) = &self.compile_error {
+            ui.colored_label(Color32::RED, e);
+        } else if let Some(e) = self.run_error.lock().ok().and_then(|e| e.clone()) {
+            ui.colored_label(Color32::RED, e);
+        }
+        ui.label(tr!("script.help"));
+        changed
+    }
+}
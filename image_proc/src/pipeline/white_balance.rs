@@ -0,0 +1,242 @@
+//! White balance set as a color temperature in Kelvin and a tint, instead of gains for each channel
+
+use eframe::egui::{
+    Color32, ColorImage,
+    ecolor::{gamma_u8_from_linear_f32, linear_f32_from_gamma_u8},
+};
+
+use super::{LinearImage, PipelineStageTrait};
+
+/// The range of temperatures the planckian locus approximation is valid for
+const TEMPERATURES: std::ops::RangeInclusive<f32> = 1667.0..=25000.0;
+
+/// The temperature that is left unchanged, close to the white point of srgb
+const NEUTRAL: f32 = 6504.0;
+
+/// How far a tint of 1 moves the white point off the planckian locus, in CIE 1960 uv units
+const TINT_SCALE: f64 = 0.001;
+
+/// The CIE 1960 uv chromaticity of a black body at a temperature, with Kang et al's approximation of the planckian locus
+fn planckian_uv(t: f64) -> [f64; 2] {
+    let t = t.clamp(*TEMPERATURES.start() as f64, *TEMPERATURES.end() as f64);
+    let (t2, t3) = (t * t, t * t * t);
+    let x = if t <= 4000.0 {
+        -0.2661239e9 / t3 - 0.2343589e6 / t2 + 0.8776956e3 / t + 0.179910
+    } else {
+        -3.0258469e9 / t3 + 2.1070379e6 / t2 + 0.2226347e3 / t + 0.240390
+    };
+    let (x2, x3) = (x * x, x * x * x);
+    let y = if t <= 2222.0 {
+        -1.1063814 * x3 - 1.34811020 * x2 + 2.18555832 * x - 0.20219683
+    } else if t <= 4000.0 {
+        -0.9549476 * x3 - 1.37418593 * x2 + 2.09137015 * x - 0.16748867
+    } else {
+        3.0817580 * x3 - 5.87338670 * x2 + 3.75112997 * x - 0.37001483
+    };
+    let d = -2.0 * x + 12.0 * y + 3.0;
+    [4.0 * x / d, 6.0 * y / d]
+}
+
+/// The unit vector perpendicular to the planckian locus at a temperature, pointing towards green
+fn locus_normal(t: f64) -> [f64; 2] {
+    let a = planckian_uv(t - 10.0);
+    let b = planckian_uv(t + 10.0);
+    let d = [b[0] - a[0], b[1] - a[1]];
+    let l = (d[0] * d[0] + d[1] * d[1]).sqrt();
+    [d[1] / l, -d[0] / l]
+}
+
+/// The uv chromaticity of a temperature and tint, a positive tint is towards magenta
+fn white_point(t: f64, tint: f64) -> [f64; 2] {
+    let uv = planckian_uv(t);
+    let n = locus_normal(t);
+    let offset = -tint * TINT_SCALE;
+    [uv[0] + n[0] * offset, uv[1] + n[1] * offset]
+}
+
+/// The linear srgb color of a uv chromaticity with a luminance of 1
+fn uv_to_rgb(uv: [f64; 2]) -> [f64; 3] {
+    let d = 2.0 * uv[0] - 8.0 * uv[1] + 4.0;
+    let (x, y) = (3.0 * uv[0] / d, 2.0 * uv[1] / d);
+    let (cx, cy, cz) = (x / y, 1.0, (1.0 - x - y) / y);
+    [
+        3.2406 * cx - 1.5372 * cy - 0.4986 * cz,
+        -0.9689 * cx + 1.8758 * cy + 0.0415 * cz,
+        0.0557 * cx - 0.2040 * cy + 1.0570 * cz,
+    ]
+}
+
+/// The uv chromaticity of a linear srgb color
+fn rgb_to_uv(rgb: [f64; 3]) -> Option<[f64; 2]> {
+    let [r, g, b] = rgb;
+    let x = 0.4124 * r + 0.3576 * g + 0.1805 * b;
+    let y = 0.2126 * r + 0.7152 * g + 0.0722 * b;
+    let z = 0.0193 * r + 0.1192 * g + 0.9505 * b;
+    let d = x + 15.0 * y + 3.0 * z;
+    (d > 0.0).then(|| [4.0 * x / d, 6.0 * y / d])
+}
+
+/// The temperature and tint that make a linear srgb color neutral
+fn neutral_temperature(rgb: [f64; 3]) -> Option<(f32, f32)> {
+    let uv = rgb_to_uv(rgb)?;
+    let distance = |t: f64| {
+        let p = planckian_uv(t);
+        (uv[0] - p[0]).powi(2) + (uv[1] - p[1]).powi(2)
+    };
+    // The locus is searched evenly in mireds, a coarse pass followed by a fine one
+    let (lo, hi) = (
+        1e6 / *TEMPERATURES.end() as f64,
+        1e6 / *TEMPERATURES.start() as f64,
+    );
+    let search = |lo: f64, hi: f64| {
+        (0..=200)
+            .map(|i| lo + (hi - lo) * i as f64 / 200.0)
+            .min_by(|a, b| distance(1e6 / a).total_cmp(&distance(1e6 / b)))
+            .unwrap_or(lo)
+    };
+    let coarse = search(lo, hi);
+    let step = (hi - lo) / 200.0;
+    let mired = search((coarse - step).max(lo), (coarse + step).min(hi));
+    let t = 1e6 / mired;
+    let p = planckian_uv(t);
+    let n = locus_normal(t);
+    let duv = (uv[0] - p[0]) * n[0] + (uv[1] - p[1]) * n[1];
+    Some((t as f32, (-duv / TINT_SCALE) as f32))
+}
+
+/// Corrects the colors of a scene lit by light of a known color temperature and tint
+#[derive(Clone, Debug, serde::Serialize, serde::Deserialize)]
+pub struct WhiteBalance {
+    /// The color temperature of the light in the scene, in Kelvin
+    pub temperature: f32,
+    /// The offset of the light from the planckian locus, positive is magenta and negative is green
+    pub tint: f32,
+    /// True while waiting for a click on something neutral in the preview
+    #[serde(skip)]
+    picking: bool,
+}
+
+impl Default for WhiteBalance {
+    fn default() -> Self {
+        Self {
+            temperature: NEUTRAL,
+            tint: 0.0,
+            picking: false,
+        }
+    }
+}
+
+impl WhiteBalance {
+    /// The gain of each channel in linear light, normalized so green is 1
+    pub fn gains(&self) -> [f32; 3] {
+        let neutral = uv_to_rgb(planckian_uv(NEUTRAL as f64));
+        let white = uv_to_rgb(white_point(self.temperature as f64, self.tint as f64));
+        let g = white[1] / neutral[1];
+        [0, 1, 2].map(|i| {
+            if white[i] > 0.0 {
+                (g * neutral[i] / white[i]) as f32
+            } else {
+                1.0
+            }
+        })
+    }
+}
+
+impl PipelineStageTrait for WhiteBalance {
+    fn name(&self) -> String {
+        tr!("pipeline.stage.white_balance")
+    }
+
+    fn key(&self) -> &'static str {
+        "White balance"
+    }
+
+    fn process(&self, img: ColorImage) -> ColorImage {
+        let gains = self.gains();
+        let lut: Vec<[u8; 256]> = gains
+            .iter()
+            .map(|g| {
+                let mut l = [0; 256];
+                for (i, v) in l.iter_mut().enumerate() {
+                    *v = gamma_u8_from_linear_f32(linear_f32_from_gamma_u8(i as u8) * g);
+                }
+                l
+            })
+            .collect();
+        let pixels = img
+            .pixels
+            .iter()
+            .map(|p| {
+                Color32::from_rgba_unmultiplied(
+                    lut[0][p.r() as usize],
+                    lut[1][p.g() as usize],
+                    lut[2][p.b() as usize],
+                    p.a(),
+                )
+            })
+            .collect();
+        ColorImage {
+            size: img.size,
+            pixels,
+        }
+    }
+
+    fn process_linear(&self, mut img: LinearImage) -> LinearImage {
+        let gains = self.gains();
+        for p in &mut img.pixels {
+            *p = [p[0] * gains[0], p[1] * gains[1], p[2] * gains[2]];
+        }
+        img
+    }
+
+    fn show(&mut self, ui: &mut eframe::egui::Ui) -> bool {
+        let mut changed = false;
+        changed |= ui
+            .add(
+                eframe::egui::Slider::new(&mut self.temperature, TEMPERATURES)
+                    .logarithmic(true)
+                    .suffix(" K")
+                    .text(tr!("white_balance.temperature")),
+            )
+            .changed();
+        changed |= ui
+            .add(
+                eframe::egui::Slider::new(&mut self.tint, -100.0..=100.0)
+                    .text(tr!("white_balance.tint")),
+            )
+            .changed();
+        ui.horizontal(|ui| {
+            if ui.button(tr!("white_balance.as_shot")).clicked() {
+                *self = Self::default();
+                changed = true;
+            }
+            ui.toggle_value(&mut self.picking, tr!("white_balance.pick"));
+        });
+        if self.picking {
+            ui.label(tr!("white_balance.pick_hint"));
+        }
+        let g = self.gains();
+        ui.label(tr!(
+            "white_balance.gains",
+            red = format!("{:.3}", g[0]),
+            green = format!("{:.3}", g[1]),
+            blue = format!("{:.3}", g[2])
+        ));
+        changed
+    }
+
+    fn picking(&self) -> bool {
+        self.picking
+    }
+
+    fn pick(&mut self, color: [f32; 3]) {
+        self.picking = false;
+        // The color is from before this stage, so it is the color of the light relative to the neutral temperature
+        let neutral = uv_to_rgb(planckian_uv(NEUTRAL as f64));
+        let rgb = [0, 1, 2].map(|i| color[i] as f64 * neutral[i]);
+        if let Some((t, tint)) = neutral_temperature(rgb) {
+            self.temperature = t.clamp(*TEMPERATURES.start(), *TEMPERATURES.end());
+            self.tint = tint.clamp(-100.0, 100.0);
+        }
+    }
+}
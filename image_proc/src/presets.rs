@@ -0,0 +1,201 @@
+//! Named camera setups that can be switched between quickly, like one for inspection and one for measurement
+
+use image_proc::calibration::CalibrationData;
+
+use crate::pipeline::Pipeline;
+
+/// The capture api used to open a camera
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub enum Backend {
+    /// Let opencv choose
+    #[default]
+    Any,
+    V4l2,
+    DirectShow,
+    MediaFoundation,
+    AvFoundation,
+    GStreamer,
+}
+
+impl Backend {
+    pub const ALL: [Backend; 6] = [
+        Backend::Any,
+        Backend::V4l2,
+        Backend::DirectShow,
+        Backend::MediaFoundation,
+        Backend::AvFoundation,
+        Backend::GStreamer,
+    ];
+
+    /// The opencv api preference
+    pub fn api(&self) -> i32 {
+        match self {
+            Backend::Any => opencv::videoio::CAP_ANY,
+            Backend::V4l2 => opencv::videoio::CAP_V4L2,
+            Backend::DirectShow => opencv::videoio::CAP_DSHOW,
+            Backend::MediaFoundation => opencv::videoio::CAP_MSMF,
+            Backend::AvFoundation => opencv::videoio::CAP_AVFOUNDATION,
+            Backend::GStreamer => opencv::videoio::CAP_GSTREAMER,
+        }
+    }
+
+    pub fn name(&self) -> String {
+        match self {
+            Backend::Any => tr!("presets.automatic"),
+            Backend::V4l2 => "V4L2".to_string(),
+            Backend::DirectShow => "DirectShow".to_string(),
+            Backend::MediaFoundation => "Media Foundation".to_string(),
+            Backend::AvFoundation => "AVFoundation".to_string(),
+            Backend::GStreamer => "GStreamer".to_string(),
+        }
+    }
+}
+
+/// How a camera device is opened
+#[derive(Clone, Debug, Default, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
+pub struct CameraConfig {
+    pub backend: Backend,
+    /// The requested width and height, None uses the default of the camera
+    pub resolution: Option<[u32; 2]>,
+    /// The manual exposure in the units of the backend, None uses automatic exposure
+    pub exposure: Option<f64>,
+    /// Deliver single channel frames, for monochrome cameras
+    pub monochrome: bool,
+}
+
+impl CameraConfig {
+    /// Show the configuration for editing
+    pub fn show(&mut self, ui: &mut eframe::egui::Ui) {
+        eframe::egui::ComboBox::from_label(tr!("presets.backend"))
+            .selected_text(self.backend.name())
+            .show_ui(ui, |ui| {
+                for b in Backend::ALL {
+                    ui.selectable_value(&mut self.backend, b, b.name());
+                }
+            });
+        ui.horizontal(|ui| {
+            let mut set = self.resolution.is_some();
+            ui.checkbox(&mut set, tr!("presets.resolution"));
+            match (set, &mut self.resolution) {
+                (true, Some([w, h])) => {
+                    ui.add(eframe::egui::DragValue::new(w));
+                    ui.label("x");
+                    ui.add(eframe::egui::DragValue::new(h));
+                }
+                (true, r) => *r = Some([1920, 1080]),
+                (false, r) => *r = None,
+            }
+        });
+        ui.horizontal(|ui| {
+            let mut set = self.exposure.is_some();
+            ui.checkbox(&mut set, tr!("presets.exposure"));
+            match (set, &mut self.exposure) {
+                (true, Some(e)) => {
+                    ui.add(eframe::egui::DragValue::new(e).speed(0.1));
+                }
+                (true, e) => *e = Some(-6.0),
+                (false, e) => *e = None,
+            }
+        });
+        ui.checkbox(&mut self.monochrome, tr!("presets.monochrome"));
+    }
+}
+
+/// A named camera setup
+#[derive(Clone, Debug, serde::Serialize, serde::Deserialize)]
+pub struct SetupPreset {
+    pub name: String,
+    pub camera: CameraConfig,
+    /// The calibration applied, None leaves the camera uncalibrated
+    pub calibration: Option<CalibrationData>,
+    /// The width and height of the images the calibration was made with
+    #[serde(default)]
+    pub calibration_resolution: Option<[u32; 2]>,
+    pub pipeline: Pipeline,
+}
+
+/// What the user asked for in the preset bar
+pub enum PresetAction {
+    /// Switch to the preset with this index
+    Apply(usize),
+    /// Save the current setup as a new preset with this name
+    Save(String),
+    /// Remove the preset with this index
+    Delete(usize),
+    /// Reopen the selected camera with the camera configuration
+    Configure,
+}
+
+/// The presets dropdown along with the state of its controls
+#[derive(Default)]
+pub struct PresetBar {
+    /// The preset that was applied last
+    selected: Option<usize>,
+    /// The name for a new preset
+    name: String,
+    /// The configuration of the selected camera, saved with new presets
+    pub camera: CameraConfig,
+    show_camera: bool,
+}
+
+impl PresetBar {
+    /// Show the preset controls in a row
+    pub fn show(
+        &mut self,
+        ui: &mut eframe::egui::Ui,
+        presets: &[SetupPreset],
+    ) -> Option<PresetAction> {
+        let mut action = None;
+        self.selected = self.selected.filter(|s| *s < presets.len());
+        let name = self
+            .selected
+            .map(|s| presets[s].name.clone())
+            .unwrap_or_else(|| tr!("presets.none"));
+        eframe::egui::ComboBox::from_label(tr!("presets.preset"))
+            .selected_text(name)
+            .show_ui(ui, |ui| {
+                for (i, p) in presets.iter().enumerate() {
+                    if ui
+                        .selectable_label(self.selected == Some(i), &p.name)
+                        .clicked()
+                    {
+                        self.selected = Some(i);
+                        action = Some(PresetAction::Apply(i));
+                    }
+                }
+            });
+        if let Some(s) = self.selected {
+            if ui.button(tr!("presets.delete")).clicked() {
+                self.selected = None;
+                action = Some(PresetAction::Delete(s));
+            }
+        }
+        ui.separator();
+        ui.add(
+            eframe::egui::TextEdit::singleline(&mut self.name)
+                .hint_text(tr!("presets.name"))
+                .desired_width(120.0),
+        );
+        if ui
+            .add_enabled(
+                !self.name.trim().is_empty(),
+                eframe::egui::Button::new(tr!("presets.save")),
+            )
+            .clicked()
+        {
+            self.selected = Some(presets.len());
+            action = Some(PresetAction::Save(
+                std::mem::take(&mut self.name).trim().to_string(),
+            ));
+        }
+        ui.toggle_value(&mut self.show_camera, tr!("presets.camera_settings"));
+        if self.show_camera {
+            self.camera.show(ui);
+            if ui.button(tr!("presets.apply")).clicked() {
+                action = Some(PresetAction::Configure);
+            }
+        }
+        action
+    }
+}
@@ -0,0 +1,109 @@
+//! Listing the cameras and what they support, for setup scripts checking the hardware before starting the program
+
+use opencv::videoio::{VideoCaptureProperties, VideoCaptureTrait, VideoCaptureTraitConst};
+
+use crate::{FrameSourceTrait, OpenCvCamera};
+
+/// The resolutions tried on each camera
+const RESOLUTIONS: [[u32; 2]; 8] = [
+    [640, 480],
+    [800, 600],
+    [1280, 720],
+    [1280, 960],
+    [1600, 1200],
+    [1920, 1080],
+    [2560, 1440],
+    [3840, 2160],
+];
+
+/// A camera that was found
+#[derive(serde::Serialize)]
+pub struct ProbedCamera {
+    /// The opencv device index, None for cameras opened another way
+    pub index: Option<i32>,
+    pub name: String,
+    /// The capture api, like V4L2 or MSMF
+    pub backend: String,
+    /// The width and height the camera opens with
+    pub resolution: Option<[u32; 2]>,
+    /// The resolutions of the common ones the camera accepted
+    pub resolutions: Vec<[u32; 2]>,
+}
+
+impl ProbedCamera {
+    /// Open a camera and ask it for each of the common resolutions
+    fn opencv(mut c: OpenCvCamera) -> Option<Self> {
+        if !c.open() {
+            return None;
+        }
+        let resolution = c.width.zip(c.height).map(|(w, h)| [w as u32, h as u32]);
+        let cam = c.cam.as_mut()?;
+        let backend = cam.get_backend_name().unwrap_or_default();
+        let get = |cam: &opencv::videoio::VideoCapture, p: VideoCaptureProperties| {
+            cam.get(p as i32).unwrap_or_default() as u32
+        };
+        let resolutions = RESOLUTIONS
+            .into_iter()
+            .filter(|&[w, h]| {
+                let _ = cam.set(
+                    VideoCaptureProperties::CAP_PROP_FRAME_WIDTH as i32,
+                    w as f64,
+                );
+                let _ = cam.set(
+                    VideoCaptureProperties::CAP_PROP_FRAME_HEIGHT as i32,
+                    h as f64,
+                );
+                // Backends choose the closest resolution they have when the one asked for is not supported
+                get(cam, VideoCaptureProperties::CAP_PROP_FRAME_WIDTH) == w
+                    && get(cam, VideoCaptureProperties::CAP_PROP_FRAME_HEIGHT) == h
+            })
+            .collect();
+        let index = c.i;
+        c.close();
+        Some(Self {
+            index: Some(index),
+            name: tr!("main.camera", id = index),
+            backend,
+            resolution,
+            resolutions,
+        })
+    }
+
+    /// A camera that is not probed for resolutions
+    #[cfg_attr(not(target_os = "linux"), allow(dead_code))]
+    fn named(name: String, backend: &str, resolution: Option<[u32; 2]>) -> Self {
+        Self {
+            index: None,
+            name,
+            backend: backend.to_string(),
+            resolution,
+            resolutions: resolution.into_iter().collect(),
+        }
+    }
+}
+
+/// Find the cameras the same way the program does when it starts
+pub fn probe() -> Vec<ProbedCamera> {
+    #[cfg_attr(not(target_os = "linux"), allow(unused_mut))]
+    let mut cameras: Vec<ProbedCamera> = OpenCvCamera::detect()
+        .into_iter()
+        .filter_map(ProbedCamera::opencv)
+        .collect();
+    #[cfg(feature = "genicam")]
+    for (name, _) in crate::genicam::GenicamCamera::detect() {
+        cameras.push(ProbedCamera::named(name, "Aravis", None));
+    }
+    #[cfg(feature = "realsense")]
+    for (name, _) in crate::realsense::RealSenseStream::detect() {
+        cameras.push(ProbedCamera::named(name, "librealsense", None));
+    }
+    #[cfg(target_os = "linux")]
+    for p in crate::picamera::PiCamera::detect() {
+        cameras.push(ProbedCamera::named(
+            p.name(),
+            "GStreamer",
+            Some(crate::picamera::PiCamera::SIZE),
+        ));
+    }
+    cameras
+}
@@ -0,0 +1,94 @@
+//! Per camera profiles, holding everything that has been measured about a camera
+
+use std::{
+    io::{Read, Write},
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    gray_card::GrayCardExposure, noise::NoiseProfile, rolling_shutter::RollingShutterProfile,
+    vignetting::VignettingProfile,
+};
+
+/// Everything that has been measured about a single camera
+#[derive(Clone, Debug, Default, serde::Serialize, serde::Deserialize)]
+pub struct CameraProfile {
+    /// The stored calibration of the camera
+    pub calibration: Option<crate::CalibrationData>,
+    /// The width and height of the images the calibration was made with
+    pub calibration_resolution: Option<[u32; 2]>,
+    /// The name the device reports, to tell apart cameras that were plugged in at the same index
+    pub identity: Option<String>,
+    /// The temporal noise characteristics of the camera
+    pub noise: Option<NoiseProfile>,
+    /// The rolling shutter characteristics of the camera
+    pub rolling_shutter: Option<RollingShutterProfile>,
+    /// The vignetting model of the camera, fitted from flat field captures
+    #[serde(default)]
+    pub vignetting: Option<VignettingProfile>,
+    /// The exposure correction measured from a gray card
+    #[serde(default)]
+    pub gray_card: Option<GrayCardExposure>,
+    /// The windows of the sensor the capture modes of the camera stream
+    #[serde(default)]
+    pub sensor_modes: Option<crate::sensor_mode::SensorModes>,
+    /// The calibrations of a zoom lens at several zoom positions
+    #[serde(default)]
+    pub zoom: Option<image_proc::zoom::ZoomProfile>,
+}
+
+impl CameraProfile {
+    /// The name the camera device reports, where the platform makes it available
+    pub fn device_identity(camera: i32) -> Option<String> {
+        if camera < 0 {
+            return None;
+        }
+        std::fs::read_to_string(format!("/sys/class/video4linux/video{}/name", camera))
+            .ok()
+            .map(|n| n.trim().to_string())
+    }
+
+    /// The stored calibration, if it was made for this device at this resolution
+    pub fn matching_calibration(
+        &self,
+        camera: i32,
+        resolution: [u32; 2],
+    ) -> Option<&crate::CalibrationData> {
+        if self.identity.is_some() && self.identity != Self::device_identity(camera) {
+            return None;
+        }
+        if self.calibration_resolution != Some(resolution) {
+            return None;
+        }
+        self.calibration.as_ref()
+    }
+
+    /// The file the profile for a camera is stored in
+    fn path(dir: &Path, camera: i32) -> PathBuf {
+        dir.join(format!("camera_{}.profile", camera))
+    }
+
+    /// Load the stored profile for a camera from a directory
+    pub fn load(dir: &Path, camera: i32) -> Option<Self> {
+        Self::load_file(&Self::path(dir, camera))
+    }
+
+    /// Load a profile from a file
+    pub fn load_file(path: &Path) -> Option<Self> {
+        let mut f = std::fs::File::open(path).ok()?;
+        let mut c = Vec::new();
+        f.read_to_end(&mut c).ok()?;
+        bincode::serde::decode_from_slice(&c, bincode::config::standard())
+            .ok()
+            .map(|(p, _)| p)
+    }
+
+    /// Store the profile for a camera in a directory
+    pub fn save(&self, dir: &Path, camera: i32) -> std::io::Result<()> {
+        let data = bincode::serde::encode_to_vec(self, bincode::config::standard())
+            .map_err(std::io::Error::other)?;
+        std::fs::create_dir_all(dir)?;
+        let mut f = std::fs::File::create(Self::path(dir, camera))?;
+        f.write_all(&data)
+    }
+}
@@ -0,0 +1,255 @@
+//! Timing the stages each frame goes through on its way to the screen, to find the one that limits the frame rate
+
+use std::time::{Duration, Instant};
+
+/// The stages of showing a frame, in the order a frame passes through them
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Stage {
+    /// Reading the frame from the camera, including averaging
+    Capture,
+    /// Converting the opencv image for display
+    Convert,
+    /// Applying the calibration
+    Undistort,
+    /// The processing pipeline with its curves, the view mode and the color management
+    Curve,
+    /// Handing the image to the texture of the preview
+    Upload,
+}
+
+impl Stage {
+    pub const ALL: [Stage; 5] = [
+        Stage::Capture,
+        Stage::Convert,
+        Stage::Undistort,
+        Stage::Curve,
+        Stage::Upload,
+    ];
+
+    pub fn name(&self) -> String {
+        match self {
+            Stage::Capture => tr!("profiler.capture"),
+            Stage::Convert => tr!("profiler.convert"),
+            Stage::Undistort => tr!("profiler.undistort"),
+            Stage::Curve => tr!("profiler.curve"),
+            Stage::Upload => tr!("profiler.upload"),
+        }
+    }
+}
+
+/// The time of each stage, averaged over recent frames
+#[derive(Default)]
+pub struct FrameProfiler {
+    /// The average time of each stage in milliseconds, None for stages no frame has gone through recently
+    times: [Option<f64>; 5],
+    /// When each stage last ran, stages that stop running are dropped from the display
+    last: [Option<Instant>; 5],
+    /// Show the times over the preview
+    pub overlay: bool,
+}
+
+impl FrameProfiler {
+    /// How much each new frame counts in the average
+    const SMOOTHING: f64 = 0.1;
+    /// Stages that have not run for this long are not shown
+    const STALE: Duration = Duration::from_secs(2);
+
+    /// Record how long a stage took for a frame
+    pub fn record(&mut self, stage: Stage, time: Duration) {
+        let i = stage as usize;
+        let ms = time.as_secs_f64() * 1000.0;
+        let fresh = self.last[i].is_some_and(|t| t.elapsed() < Self::STALE);
+        self.times[i] = match self.times[i] {
+            Some(t) if fresh => Some(t + (ms - t) * Self::SMOOTHING),
+            _ => Some(ms),
+        };
+        self.last[i] = Some(Instant::now());
+    }
+
+    /// Run a stage and record how long it took
+    pub fn time<T>(&mut self, stage: Stage, f: impl FnOnce() -> T) -> T {
+        let start = Instant::now();
+        let r = f();
+        self.record(stage, start.elapsed());
+        r
+    }
+
+    /// The stages that ran recently with their average times in milliseconds
+    fn current(&self) -> Vec<(Stage, f64)> {
+        Stage::ALL
+            .into_iter()
+            .filter_map(|s| {
+                let i = s as usize;
+                let fresh = self.last[i].is_some_and(|t| t.elapsed() < Self::STALE);
+                Some((s, self.times[i].filter(|_| fresh)?))
+            })
+            .collect()
+    }
+
+    /// Show the times of the stages as bars, the slowest stage is highlighted
+    pub fn show(&mut self, ui: &mut eframe::egui::Ui) {
+        let current = self.current();
+        if current.is_empty() {
+            ui.label(tr!("profiler.no_frames"));
+        }
+        let total: f64 = current.iter().map(|(_, t)| t).sum();
+        let slowest = current
+            .iter()
+            .max_by(|a, b| a.1.total_cmp(&b.1))
+            .map(|(s, _)| *s);
+        eframe::egui::Grid::new("profiler").show(ui, |ui| {
+            for (s, t) in &current {
+                ui.label(s.name());
+                let mut bar = eframe::egui::ProgressBar::new((t / total.max(1e-9)) as f32)
+                    .desired_width(160.0)
+                    .text(format!("{:.2} ms", t));
+                if Some(*s) == slowest {
+                    bar = bar.fill(eframe::egui::Color32::DARK_RED);
+                }
+                ui.add(bar);
+                ui.end_row();
+            }
+        });
+        if !current.is_empty() {
+            ui.label(tr!(
+                "profiler.total",
+                ms = format!("{:.2}", total),
+                fps = format!("{:.0}", 1000.0 / total.max(1e-9))
+            ));
+        }
+        ui.checkbox(&mut self.overlay, tr!("profiler.overlay"));
+    }
+
+    /// The average time of showing a frame in milliseconds, every stage after capturing it
+    pub fn display_time(&self) -> Option<f64> {
+        let times: Vec<f64> = self
+            .current()
+            .into_iter()
+            .filter(|(s, _)| *s != Stage::Capture)
+            .map(|(_, t)| t)
+            .collect();
+        (!times.is_empty()).then(|| times.iter().sum())
+    }
+
+    /// Draw the times in the corner of the preview, when the overlay is on
+    pub fn paint(&self, ui: &eframe::egui::Ui, rect: eframe::egui::Rect) {
+        if !self.overlay {
+            return;
+        }
+        let text: Vec<String> = self
+            .current()
+            .iter()
+            .map(|(s, t)| format!("{} {:.2} ms", s.name(), t))
+            .collect();
+        let painter = ui.painter_at(rect);
+        let galley = painter.layout_no_wrap(
+            text.join("\n"),
+            eframe::egui::FontId::monospace(12.0),
+            eframe::egui::Color32::WHITE,
+        );
+        let pos = rect.right_top() + eframe::egui::vec2(-8.0 - galley.size().x, 8.0);
+        painter.rect_filled(
+            eframe::egui::Rect::from_min_size(pos, galley.size()).expand(4.0),
+            4.0,
+            eframe::egui::Color32::from_black_alpha(160),
+        );
+        painter.galley(pos, galley, eframe::egui::Color32::WHITE);
+    }
+}
+
+/// Shrinking the preview when showing frames takes too long, frames are still captured and recorded at full resolution
+#[derive(Clone, Debug, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
+pub struct AdaptivePreviewSettings {
+    pub enabled: bool,
+    /// The longest showing a frame may take, in milliseconds
+    pub budget_ms: f64,
+    /// The smallest the preview is shrunk to
+    pub min_scale: f32,
+}
+
+impl Default for AdaptivePreviewSettings {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            budget_ms: 33.0,
+            min_scale: 0.25,
+        }
+    }
+}
+
+impl AdaptivePreviewSettings {
+    pub fn show(&mut self, ui: &mut eframe::egui::Ui) {
+        ui.checkbox(&mut self.enabled, tr!("settings.adaptive_preview"));
+        ui.add_enabled_ui(self.enabled, |ui| {
+            eframe::egui::Grid::new("adaptive_preview").show(ui, |ui| {
+                ui.label(tr!("settings.frame_budget"));
+                ui.add(
+                    eframe::egui::DragValue::new(&mut self.budget_ms)
+                        .range(1.0..=1000.0)
+                        .suffix(" ms"),
+                );
+                ui.end_row();
+                ui.label(tr!("settings.min_preview_scale"));
+                ui.add(eframe::egui::Slider::new(&mut self.min_scale, 0.1..=1.0));
+                ui.end_row();
+            });
+        });
+    }
+}
+
+/// The scale the preview is currently shown at
+pub struct AdaptivePreview {
+    scale: f32,
+    /// Frames shown since the scale last changed, the times need a while to settle after a change
+    frames: u32,
+}
+
+impl Default for AdaptivePreview {
+    fn default() -> Self {
+        Self {
+            scale: 1.0,
+            frames: 0,
+        }
+    }
+}
+
+impl AdaptivePreview {
+    /// How much the scale changes in one step
+    const STEP: f32 = 0.75;
+    /// How many frames are shown between changes of the scale
+    const SETTLE: u32 = 15;
+
+    /// The scale the next frame is shown at
+    pub fn scale(&self) -> f32 {
+        self.scale
+    }
+
+    /// Adjust the scale after a frame was shown, with the average time of showing a frame
+    pub fn update(&mut self, settings: &AdaptivePreviewSettings, frame_ms: Option<f64>) {
+        if !settings.enabled {
+            self.scale = 1.0;
+            return;
+        }
+        self.frames += 1;
+        let Some(ms) = frame_ms else {
+            return;
+        };
+        if self.frames < Self::SETTLE {
+            return;
+        }
+        // Growing waits until there is plenty of room, so the scale does not go back and forth
+        let scale = if ms > settings.budget_ms {
+            self.scale * Self::STEP
+        } else if ms < settings.budget_ms * 0.4 {
+            self.scale / Self::STEP
+        } else {
+            self.scale
+        };
+        let scale = scale.clamp(settings.min_scale.min(1.0), 1.0);
+        if scale != self.scale {
+            self.scale = scale;
+            self.frames = 0;
+        }
+    }
+}
@@ -0,0 +1,180 @@
+//! A panel for projecting world points into the image and image points back onto a plane, for checking poses
+
+use image_proc::{
+    calibration::CalibrationData,
+    geometry::{Plane, Pose},
+};
+
+use crate::undistort::parse_points;
+
+/// The pose, plane and points being checked
+#[derive(Default)]
+pub struct ProjectionPanel {
+    pose: Pose,
+    plane: Plane,
+    /// The world points, one x, y, z triple per line
+    world: String,
+    /// The pixels of the distorted image, one x, y pair per line
+    pixels: String,
+    /// Draw the projected world points over the preview
+    overlay: bool,
+    /// The pixels of the world points, from the last time the panel was shown
+    projected: Vec<[f64; 2]>,
+}
+
+/// Edit three values in a row
+fn vector(ui: &mut eframe::egui::Ui, label: String, v: &mut [f64; 3], speed: f64) {
+    ui.label(label);
+    for x in v.iter_mut() {
+        ui.add(eframe::egui::DragValue::new(x).speed(speed).max_decimals(6));
+    }
+    ui.end_row();
+}
+
+impl ProjectionPanel {
+    /// Show the panel
+    pub fn show(&mut self, ui: &mut eframe::egui::Ui, cd: Option<&CalibrationData>) {
+        self.projected.clear();
+        eframe::egui::Grid::new("projection_pose").show(ui, |ui| {
+            vector(
+                ui,
+                tr!("projection.rotation"),
+                &mut self.pose.rotation,
+                0.01,
+            );
+            vector(
+                ui,
+                tr!("projection.translation"),
+                &mut self.pose.translation,
+                0.01,
+            );
+            vector(
+                ui,
+                tr!("projection.plane_normal"),
+                &mut self.plane.normal,
+                0.01,
+            );
+            ui.label(tr!("projection.plane_offset"));
+            ui.add(eframe::egui::DragValue::new(&mut self.plane.offset).speed(0.01));
+            ui.end_row();
+        });
+        let c = self.pose.camera_position();
+        ui.label(tr!(
+            "projection.camera_position",
+            position = format!("{:.4}, {:.4}, {:.4}", c[0], c[1], c[2])
+        ));
+        let Some(cd) = cd else {
+            ui.label(tr!("undistort.no_calibration"));
+            return;
+        };
+        ui.separator();
+        ui.heading(tr!("projection.world_to_image"));
+        ui.add(
+            eframe::egui::TextEdit::multiline(&mut self.world)
+                .hint_text("0.1, 0.05, 0")
+                .desired_rows(3),
+        );
+        ui.checkbox(&mut self.overlay, tr!("projection.overlay"));
+        match parse_points::<3>(&self.world) {
+            Ok(points) => match image_proc::geometry::project_points(cd, &points, &self.pose) {
+                Ok(pixels) => {
+                    eframe::egui::Grid::new("projected_points")
+                        .striped(true)
+                        .show(ui, |ui| {
+                            ui.strong(tr!("projection.world"));
+                            ui.strong(tr!("undistort.pixel"));
+                            ui.end_row();
+                            for (w, p) in points.iter().zip(&pixels) {
+                                ui.label(format!("{:.4}, {:.4}, {:.4}", w[0], w[1], w[2]));
+                                ui.label(format!("{:.2}, {:.2}", p[0], p[1]));
+                                ui.end_row();
+                            }
+                        });
+                    self.projected = pixels;
+                }
+                Err(e) => {
+                    ui.colored_label(
+                        eframe::egui::Color32::RED,
+                        tr!("undistort.failed", error = e.to_string()),
+                    );
+                }
+            },
+            Err(line) => {
+                ui.colored_label(
+                    eframe::egui::Color32::RED,
+                    tr!("undistort.bad_line", line = line),
+                );
+            }
+        }
+        ui.separator();
+        ui.heading(tr!("projection.image_to_plane"));
+        ui.add(
+            eframe::egui::TextEdit::multiline(&mut self.pixels)
+                .hint_text("320, 240")
+                .desired_rows(3),
+        );
+        match parse_points::<2>(&self.pixels) {
+            Ok(pixels) => {
+                match image_proc::geometry::back_project_points(
+                    cd,
+                    &pixels,
+                    &self.pose,
+                    &self.plane,
+                ) {
+                    Ok(world) => {
+                        eframe::egui::Grid::new("back_projected_points")
+                            .striped(true)
+                            .show(ui, |ui| {
+                                ui.strong(tr!("undistort.pixel"));
+                                ui.strong(tr!("projection.world"));
+                                ui.end_row();
+                                for (p, w) in pixels.iter().zip(&world) {
+                                    ui.label(format!("{:.2}, {:.2}", p[0], p[1]));
+                                    match w {
+                                        Some(w) => ui.label(format!(
+                                            "{:.4}, {:.4}, {:.4}",
+                                            w[0], w[1], w[2]
+                                        )),
+                                        None => ui.label(tr!("projection.misses_plane")),
+                                    };
+                                    ui.end_row();
+                                }
+                            });
+                    }
+                    Err(e) => {
+                        ui.colored_label(
+                            eframe::egui::Color32::RED,
+                            tr!("undistort.failed", error = e.to_string()),
+                        );
+                    }
+                }
+            }
+            Err(line) => {
+                ui.colored_label(
+                    eframe::egui::Color32::RED,
+                    tr!("undistort.bad_line", line = line),
+                );
+            }
+        }
+    }
+
+    /// Draw the projected world points over the preview, rect is where the image of size pixels is shown
+    pub fn paint(&self, ui: &eframe::egui::Ui, rect: eframe::egui::Rect, size: [usize; 2]) {
+        if !self.overlay {
+            return;
+        }
+        let ppp = ui.ctx().pixels_per_point();
+        let stroke = crate::dpi::stroke(2.0, eframe::egui::Color32::LIGHT_GREEN, ppp);
+        for p in &self.projected {
+            let pos = rect.min
+                + eframe::egui::vec2(
+                    p[0] as f32 / size[0] as f32 * rect.width(),
+                    p[1] as f32 / size[1] as f32 * rect.height(),
+                );
+            if rect.contains(pos) {
+                ui.painter()
+                    .circle_stroke(crate::dpi::snap_pos(pos, ppp), 5.0, stroke);
+            }
+        }
+    }
+}
@@ -0,0 +1,162 @@
+//! Python bindings for the calibration core, built with maturin and the python feature
+
+use std::path::PathBuf;
+
+use eframe::egui::ColorImage;
+use numpy::{IntoPyArray, PyArray3, PyReadonlyArray3};
+use opencv::core::MatTraitManual;
+use pyo3::{exceptions::PyRuntimeError, prelude::*};
+
+use crate::calibration::{CalibrationData, CalibrationDataTrait, SaveableOpencvMat};
+use crate::integrity::{self, Trust};
+
+fn opencv_error(e: opencv::Error) -> PyErr {
+    PyRuntimeError::new_err(e.to_string())
+}
+
+/// Get the pixels of a height x width x 3 rgb array
+fn array_pixels(image: &PyReadonlyArray3<u8>) -> PyResult<([usize; 2], Vec<u8>)> {
+    let a = image.as_array();
+    let (h, w, c) = a.dim();
+    if c != 3 {
+        return Err(PyRuntimeError::new_err(
+            "Images must be height x width x 3 rgb arrays",
+        ));
+    }
+    Ok(([w, h], a.iter().copied().collect()))
+}
+
+/// Split a matrix into rows of values
+fn matrix_rows(m: &SaveableOpencvMat) -> Vec<Vec<f64>> {
+    let (cols, _) = m.size();
+    m.values()
+        .chunks(cols.max(1) as usize)
+        .map(|r| r.to_vec())
+        .collect()
+}
+
+/// Calibration data for a camera
+#[pyclass(name = "CalibrationData")]
+#[derive(Clone)]
+struct PyCalibrationData(CalibrationData);
+
+#[pymethods]
+impl PyCalibrationData {
+    /// Load calibration data saved by the gui, trusting signatures by the public keys given in hex
+    #[staticmethod]
+    #[pyo3(signature = (path, trusted_keys = Vec::new(), require_signature = false))]
+    fn load(path: PathBuf, trusted_keys: Vec<String>, require_signature: bool) -> PyResult<Self> {
+        let keys = trusted_keys
+            .iter()
+            .map(|k| {
+                integrity::verifying_key_from_hex(k).ok_or_else(|| {
+                    PyRuntimeError::new_err(format!("Not a valid public key: {}", k))
+                })
+            })
+            .collect::<PyResult<_>>()?;
+        let trust = Trust {
+            keys,
+            require_signature,
+        };
+        CalibrationData::load_verified(&path, &trust)
+            .map(|(cd, _)| Self(cd))
+            .map_err(|e| {
+                PyRuntimeError::new_err(format!("Failed to load {}: {}", path.display(), e))
+            })
+    }
+
+    /// Save the calibration data in the format used by the gui
+    fn save(&self, path: PathBuf) -> PyResult<()> {
+        self.0.save(&path).map_err(PyErr::from)
+    }
+
+    /// The 3x3 camera matrix, as a list of rows
+    fn camera_matrix(&self) -> Vec<Vec<f64>> {
+        matrix_rows(self.0.camera_matrix())
+    }
+
+    /// The distortion coefficients
+    fn distortion(&self) -> Vec<f64> {
+        self.0.distortion().values()
+    }
+
+    /// Remove the lens distortion from a height x width x 3 rgb image
+    fn undistort<'py>(
+        &self,
+        py: Python<'py>,
+        image: PyReadonlyArray3<'py, u8>,
+    ) -> PyResult<Bound<'py, PyArray3<u8>>> {
+        let (size, data) = array_pixels(&image)?;
+        let out = self.0.apply_calibration(ColorImage::from_rgb(size, &data));
+        let [w, h] = out.size;
+        let data: Vec<u8> = out
+            .pixels
+            .iter()
+            .flat_map(|p| [p.r(), p.g(), p.b()])
+            .collect();
+        numpy::ndarray::Array3::from_shape_vec((h, w, 3), data)
+            .map(|a| a.into_pyarray(py))
+            .map_err(|e| PyRuntimeError::new_err(e.to_string()))
+    }
+
+    /// Remove the lens distortion from a list of (x, y) pixels.
+    /// Returns the undistorted pixel and the normalized coordinates of every point.
+    fn undistort_points(&self, points: Vec<(f64, f64)>) -> PyResult<Vec<((f64, f64), (f64, f64))>> {
+        let points: Vec<[f64; 2]> = points.into_iter().map(|(x, y)| [x, y]).collect();
+        let out = self.0.undistort_points(&points).map_err(opencv_error)?;
+        Ok(out
+            .into_iter()
+            .map(|p| ((p.pixel[0], p.pixel[1]), (p.normalized[0], p.normalized[1])))
+            .collect())
+    }
+}
+
+/// Calibrate a camera from height x width x 3 rgb images of a charuco board, returning the calibration and the rms error.
+/// The lengths are in meters and the dictionary is one of the DICT_ constants.
+#[pyfunction]
+#[pyo3(signature = (images, squares_x, squares_y, square_length, marker_length, dictionary = crate::aruco::DICT_6X6_1000))]
+fn calibrate_charuco(
+    images: Vec<PyReadonlyArray3<u8>>,
+    squares_x: i32,
+    squares_y: i32,
+    square_length: f32,
+    marker_length: f32,
+    dictionary: i32,
+) -> PyResult<(PyCalibrationData, f64)> {
+    let board = crate::aruco::CharucoParams {
+        squares_x,
+        squares_y,
+        square_length,
+        marker_length,
+        dictionary,
+        first_id: 0,
+    };
+    let mut mats = Vec::with_capacity(images.len());
+    for image in &images {
+        let ([w, h], data) = array_pixels(image)?;
+        let mut m = opencv::core::Mat::new_rows_cols_with_default(
+            h as i32,
+            w as i32,
+            opencv::core::CV_8UC3,
+            Default::default(),
+        )
+        .map_err(opencv_error)?;
+        m.data_bytes_mut()
+            .map_err(opencv_error)?
+            .copy_from_slice(&data);
+        mats.push(m);
+    }
+    let (cd, rms) = crate::calibration::calibrate_charuco(&mats, &board).map_err(opencv_error)?;
+    Ok((PyCalibrationData(cd), rms))
+}
+
+#[pymodule]
+fn image_proc(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    m.add_class::<PyCalibrationData>()?;
+    m.add_function(wrap_pyfunction!(calibrate_charuco, m)?)?;
+    m.add("DICT_4X4_1000", crate::aruco::DICT_4X4_1000)?;
+    m.add("DICT_5X5_1000", crate::aruco::DICT_5X5_1000)?;
+    m.add("DICT_6X6_1000", crate::aruco::DICT_6X6_1000)?;
+    m.add("DICT_7X7_1000", crate::aruco::DICT_7X7_1000)?;
+    Ok(())
+}
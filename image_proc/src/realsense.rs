@@ -0,0 +1,266 @@
+//! Intel RealSense depth cameras, with the color and depth streams as separate sources
+
+use std::{
+    collections::HashSet,
+    ffi::CString,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use realsense_rust::{
+    config::Config,
+    context::Context,
+    frame::{ColorFrame, DepthFrame, FrameEx, PixelKind},
+    kind::{Rs2CameraInfo, Rs2Format, Rs2StreamKind},
+    pipeline::InactivePipeline,
+};
+
+use crate::{
+    FrameSourceTrait,
+    depth::{DepthImage, SharedDepth},
+};
+
+/// The state of a device, shared by its color and depth sources
+#[derive(Debug, Default)]
+struct DeviceState {
+    /// The number of sources using the device, the device stops when this is 0
+    users: usize,
+    running: bool,
+    failed: bool,
+    color: Option<opencv::core::Mat>,
+    depth: Option<DepthImage>,
+}
+
+/// A RealSense camera, the device runs in its own thread because the library types can not be sent between threads
+#[derive(Debug)]
+struct Device {
+    serial: String,
+    state: Mutex<DeviceState>,
+}
+
+impl Device {
+    /// The resolution of both streams
+    const SIZE: [usize; 2] = [640, 480];
+    const FPS: usize = 30;
+
+    /// Start using the device, starting the thread if it is not running
+    fn start(self: &Arc<Self>) {
+        let Ok(mut s) = self.state.lock() else {
+            return;
+        };
+        s.users += 1;
+        s.failed = false;
+        if s.running {
+            return;
+        }
+        s.running = true;
+        let d = self.clone();
+        std::thread::spawn(move || {
+            if let Err(e) = d.run() {
+                println!("RealSense {} failed: {}", d.serial, e);
+                if let Ok(mut s) = d.state.lock() {
+                    s.failed = true;
+                }
+            }
+            if let Ok(mut s) = d.state.lock() {
+                s.running = false;
+            }
+        });
+    }
+
+    /// Stop using the device, the thread stops when nothing uses it
+    fn stop(&self) {
+        if let Ok(mut s) = self.state.lock() {
+            s.users = s.users.saturating_sub(1);
+        }
+    }
+
+    fn run(&self) -> Result<(), String> {
+        let context = Context::new().map_err(|e| e.to_string())?;
+        let pipeline = InactivePipeline::try_from(&context).map_err(|e| e.to_string())?;
+        let serial = CString::new(self.serial.clone()).map_err(|e| e.to_string())?;
+        let mut config = Config::new();
+        config
+            .enable_device_from_serial(&serial)
+            .and_then(|c| c.disable_all_streams())
+            .and_then(|c| {
+                c.enable_stream(
+                    Rs2StreamKind::Color,
+                    None,
+                    Self::SIZE[0],
+                    Self::SIZE[1],
+                    Rs2Format::Bgr8,
+                    Self::FPS,
+                )
+            })
+            .and_then(|c| {
+                c.enable_stream(
+                    Rs2StreamKind::Depth,
+                    None,
+                    Self::SIZE[0],
+                    Self::SIZE[1],
+                    Rs2Format::Z16,
+                    Self::FPS,
+                )
+            })
+            .map_err(|e| e.to_string())?;
+        let mut pipeline = pipeline.start(Some(config)).map_err(|e| e.to_string())?;
+        loop {
+            if self.state.lock().map(|s| s.users == 0).unwrap_or(true) {
+                break;
+            }
+            let frames = match pipeline.wait(Some(Duration::from_millis(500))) {
+                Ok(f) => f,
+                Err(e) => {
+                    pipeline.stop();
+                    return Err(e.to_string());
+                }
+            };
+            let color = frames
+                .frames_of_type::<ColorFrame>()
+                .first()
+                .and_then(Self::color_mat);
+            let depth = frames
+                .frames_of_type::<DepthFrame>()
+                .first()
+                .map(Self::depth_image);
+            if let Ok(mut s) = self.state.lock() {
+                if color.is_some() {
+                    s.color = color;
+                }
+                if depth.is_some() {
+                    s.depth = depth;
+                }
+            }
+        }
+        pipeline.stop();
+        Ok(())
+    }
+
+    fn color_mat(f: &ColorFrame) -> Option<opencv::core::Mat> {
+        let data: Vec<u8> = f
+            .iter()
+            .flat_map(|p| match p {
+                PixelKind::Bgr8 { b, g, r } => [*b, *g, *r],
+                _ => [0, 0, 0],
+            })
+            .collect();
+        crate::convert::bytes_to_mat(f.width(), f.height(), opencv::core::CV_8UC3, &data)
+    }
+
+    fn depth_image(f: &DepthFrame) -> DepthImage {
+        // The units are usually a millimeter, but can be changed in the device
+        let scale = f.depth_units().unwrap_or(0.001) * 1000.0;
+        let millimeters = f
+            .iter()
+            .map(|p| match p {
+                PixelKind::Z16 { depth } => (*depth as f32 * scale).min(u16::MAX as f32) as u16,
+                _ => 0,
+            })
+            .collect();
+        DepthImage {
+            size: [f.width(), f.height()],
+            millimeters,
+        }
+    }
+}
+
+/// One of the streams of a RealSense camera
+#[derive(Debug)]
+pub struct RealSenseStream {
+    device: Arc<Device>,
+    /// This is the depth stream, otherwise the color stream
+    depth: bool,
+    open: bool,
+    /// The most recent depth image, for measuring
+    latest_depth: SharedDepth,
+}
+
+impl RealSenseStream {
+    /// Depth beyond this is shown as black, in millimeters
+    const RANGE: u16 = 4000;
+
+    /// Find the connected cameras, returns the name and source of every stream
+    pub fn detect() -> Vec<(String, RealSenseStream)> {
+        let Ok(context) = Context::new() else {
+            return Vec::new();
+        };
+        let mut streams = Vec::new();
+        for d in context.query_devices(HashSet::new()) {
+            let Some(serial) = d.info(Rs2CameraInfo::SerialNumber) else {
+                continue;
+            };
+            let serial = serial.to_string_lossy().to_string();
+            let name = d
+                .info(Rs2CameraInfo::Name)
+                .map(|n| n.to_string_lossy().to_string())
+                .unwrap_or_else(|| serial.clone());
+            let device = Arc::new(Device {
+                serial,
+                state: Default::default(),
+            });
+            for depth in [false, true] {
+                let key = if depth {
+                    "main.realsense_depth"
+                } else {
+                    "main.realsense_color"
+                };
+                streams.push((
+                    tr!(key, name = name),
+                    RealSenseStream {
+                        device: device.clone(),
+                        depth,
+                        open: false,
+                        latest_depth: Arc::new(Mutex::new(None)),
+                    },
+                ));
+            }
+        }
+        streams
+    }
+
+    /// The depth images of the stream, None for the color stream
+    pub fn depth(&self) -> Option<SharedDepth> {
+        self.depth.then(|| self.latest_depth.clone())
+    }
+}
+
+impl FrameSourceTrait for RealSenseStream {
+    fn open(&mut self) -> bool {
+        if !self.open {
+            self.device.start();
+            self.open = true;
+        }
+        true
+    }
+
+    fn close(&mut self) {
+        if self.open {
+            self.device.stop();
+            self.open = false;
+        }
+    }
+
+    fn is_open(&self) -> bool {
+        self.open
+    }
+
+    fn get_image(&mut self) -> Option<opencv::core::Mat> {
+        let mut s = self.device.state.lock().ok()?;
+        if self.depth {
+            let d = s.depth.take()?;
+            drop(s);
+            let m = d.to_mat(Self::RANGE);
+            if let Ok(mut l) = self.latest_depth.lock() {
+                *l = Some(d);
+            }
+            m
+        } else {
+            s.color.take()
+        }
+    }
+
+    fn failed(&self) -> bool {
+        self.device.state.lock().map(|s| s.failed).unwrap_or(true)
+    }
+}
@@ -0,0 +1,164 @@
+//! Saving the captures and calibration of the session as it goes, so they can be restored after a crash.
+//! The recovery files are removed when the program closes normally, so finding them on launch means the last session was lost.
+
+use std::{
+    path::{Path, PathBuf},
+    time::{Duration, Instant},
+};
+
+use image_proc::calibration::CalibrationData;
+use image_proc::frame::Frame;
+use opencv::core::MatTraitConst;
+
+/// How often the session is saved
+const INTERVAL: Duration = Duration::from_secs(30);
+
+/// The session as it was last saved
+#[derive(Clone, Debug, serde::Serialize, serde::Deserialize)]
+pub struct Manifest {
+    /// When the session was saved, in rfc 3339 format
+    pub saved: String,
+    /// The files of the captures, in the order they were captured
+    pub captures: Vec<String>,
+    /// The calibration of the session, it may not have been saved anywhere else
+    pub calibration: Option<CalibrationData>,
+    /// The width and height of the images the calibration was made with
+    pub calibration_resolution: Option<[u32; 2]>,
+}
+
+/// What is restored from a lost session
+pub struct Recovered {
+    pub captures: Vec<Frame>,
+    pub calibration: Option<CalibrationData>,
+    pub calibration_resolution: Option<[u32; 2]>,
+}
+
+/// Saves the session to the recovery directory from time to time
+pub struct Autosave {
+    dir: PathBuf,
+    /// When the session was last saved
+    last: Instant,
+    /// Where the pixels of each written capture are in memory, to find the captures that were replaced since
+    written: Vec<usize>,
+    /// The calibration last written as json, to skip saving when nothing changed
+    previous: Option<String>,
+}
+
+impl Autosave {
+    /// The name of the recovery directory in the working directory
+    const DIR: &str = "recovery";
+    /// The name of the manifest in the recovery directory
+    const MANIFEST: &str = "session.json";
+
+    pub fn new(working_directory: &Path) -> Self {
+        Self {
+            dir: working_directory.join(Self::DIR),
+            last: Instant::now(),
+            written: Vec::new(),
+            previous: None,
+        }
+    }
+
+    /// The manifest of a session that was not closed normally
+    pub fn pending(&self) -> Option<Manifest> {
+        let c = std::fs::read_to_string(self.dir.join(Self::MANIFEST)).ok()?;
+        let m: Manifest = serde_json::from_str(&c).ok()?;
+        if m.captures.is_empty() && m.calibration.is_none() {
+            return None;
+        }
+        Some(m)
+    }
+
+    /// Read the captures of a lost session back
+    pub fn restore(&self, m: &Manifest) -> Result<Recovered, String> {
+        let mut captures = Vec::with_capacity(m.captures.len());
+        for name in &m.captures {
+            let p = self.dir.join(name);
+            let img = opencv::imgcodecs::imread(
+                &p.to_string_lossy(),
+                opencv::imgcodecs::IMREAD_UNCHANGED,
+            )
+            .map_err(|e| e.to_string())?;
+            if img.empty() {
+                return Err(tr!("recovery.missing", path = p.display()));
+            }
+            captures.push(img.into());
+        }
+        Ok(Recovered {
+            captures,
+            calibration: m.calibration.clone(),
+            calibration_resolution: m.calibration_resolution,
+        })
+    }
+
+    /// Save the session when it is time to and something changed
+    pub fn tick(
+        &mut self,
+        captures: &[Frame],
+        calibration: Option<&CalibrationData>,
+        calibration_resolution: Option<[u32; 2]>,
+    ) -> Result<(), String> {
+        if self.last.elapsed() < INTERVAL {
+            return Ok(());
+        }
+        self.last = Instant::now();
+        self.save(captures, calibration, calibration_resolution)
+    }
+
+    /// Save the session now. Only the captures added since the last save are written.
+    pub fn save(
+        &mut self,
+        captures: &[Frame],
+        calibration: Option<&CalibrationData>,
+        calibration_resolution: Option<[u32; 2]>,
+    ) -> Result<(), String> {
+        let addresses: Vec<usize> = captures.iter().map(|c| c.data() as usize).collect();
+        let state = serde_json::to_string(&(calibration, calibration_resolution))
+            .map_err(|e| e.to_string())?;
+        if self.written == addresses && self.previous.as_ref() == Some(&state) {
+            return Ok(());
+        }
+        std::fs::create_dir_all(&self.dir).map_err(|e| e.to_string())?;
+        // Only the captures from the first one that changed are written again
+        let same = self
+            .written
+            .iter()
+            .zip(&addresses)
+            .take_while(|(a, b)| a == b)
+            .count();
+        self.written.truncate(same);
+        for (n, img) in captures.iter().enumerate().skip(same) {
+            let p = self.dir.join(Self::capture_name(n));
+            match opencv::imgcodecs::imwrite_def(&p.to_string_lossy(), &**img) {
+                Ok(true) => {}
+                Ok(false) => return Err(tr!("recovery.write_failed", path = p.display())),
+                Err(e) => return Err(e.to_string()),
+            }
+            self.written.push(addresses[n]);
+        }
+        let m = Manifest {
+            saved: chrono::Local::now().to_rfc3339(),
+            captures: (0..captures.len()).map(Self::capture_name).collect(),
+            calibration: calibration.cloned(),
+            calibration_resolution,
+        };
+        let json = serde_json::to_string_pretty(&m).map_err(|e| e.to_string())?;
+        // Written beside the manifest first, so a crash while saving leaves the previous manifest whole
+        let tmp = self.dir.join(format!("{}.tmp", Self::MANIFEST));
+        std::fs::write(&tmp, json).map_err(|e| e.to_string())?;
+        std::fs::rename(&tmp, self.dir.join(Self::MANIFEST)).map_err(|e| e.to_string())?;
+        self.previous = Some(state);
+        Ok(())
+    }
+
+    /// Remove the recovery files, after closing normally or when the user does not want them
+    pub fn clear(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.dir);
+        self.written.clear();
+        self.previous = None;
+    }
+
+    fn capture_name(n: usize) -> String {
+        format!("capture_{:04}.png", n)
+    }
+}
@@ -0,0 +1,232 @@
+//! Reminding to recalibrate cameras after a number of days or hours of use, so cameras do not keep running on old calibrations
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    path::{Path, PathBuf},
+    time::{Duration, Instant},
+};
+
+/// When cameras are due for recalibration
+#[derive(Clone, Debug, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
+pub struct ReminderSettings {
+    pub enabled: bool,
+    /// Days after a calibration that a camera is due, 0 for no limit
+    pub days: u32,
+    /// Hours a camera runs after a calibration before it is due, 0 for no limit
+    pub hours: f64,
+}
+
+impl Default for ReminderSettings {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            days: 90,
+            hours: 0.0,
+        }
+    }
+}
+
+impl ReminderSettings {
+    pub fn show(&mut self, ui: &mut eframe::egui::Ui) {
+        ui.checkbox(&mut self.enabled, tr!("reminders.enabled"));
+        ui.add_enabled_ui(self.enabled, |ui| {
+            eframe::egui::Grid::new("reminder_settings").show(ui, |ui| {
+                ui.label(tr!("reminders.days"));
+                ui.add(eframe::egui::DragValue::new(&mut self.days).range(0..=3650));
+                ui.end_row();
+                ui.label(tr!("reminders.hours"));
+                ui.add(
+                    eframe::egui::DragValue::new(&mut self.hours)
+                        .range(0.0..=100000.0)
+                        .speed(1.0),
+                );
+                ui.end_row();
+            });
+            ui.label(tr!("reminders.zero_hint"));
+        });
+    }
+}
+
+/// How old the calibration of a camera is
+#[derive(Clone, Debug, Default, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
+struct CameraAge {
+    /// When the camera was last calibrated, in rfc 3339 format
+    calibrated: String,
+    /// How long the camera has run since, in seconds
+    runtime: f64,
+}
+
+/// A camera that is due for recalibration
+pub struct Due {
+    pub camera: String,
+    pub reason: String,
+}
+
+/// The age of the calibration of each camera, stored in the working directory
+pub struct Reminders {
+    path: PathBuf,
+    /// By the name of the camera
+    cameras: BTreeMap<String, CameraAge>,
+    /// When each camera last sent a frame
+    last_frame: HashMap<i32, Instant>,
+    /// Runtime of each camera not yet added to its age, in seconds
+    pending: HashMap<i32, f64>,
+    last_check: Option<Instant>,
+    /// Reminders are not shown again before this
+    snoozed: Option<Instant>,
+    due: Vec<Due>,
+}
+
+impl Reminders {
+    /// The name of the file in the working directory
+    const FILE: &str = "recalibration.json";
+    /// How often the runtime is saved and the cameras are checked
+    const INTERVAL: Duration = Duration::from_secs(60);
+    /// Gaps between frames longer than this are not counted as running
+    const MAX_GAP: Duration = Duration::from_secs(5);
+    /// How long reminding later waits
+    const SNOOZE: Duration = Duration::from_secs(4 * 3600);
+
+    pub fn new(working_directory: &Path) -> Self {
+        let path = working_directory.join(Self::FILE);
+        let cameras = std::fs::read_to_string(&path)
+            .ok()
+            .and_then(|c| serde_json::from_str(&c).ok())
+            .unwrap_or_default();
+        Self {
+            path,
+            cameras,
+            last_frame: HashMap::new(),
+            pending: HashMap::new(),
+            last_check: None,
+            snoozed: None,
+            due: Vec::new(),
+        }
+    }
+
+    fn save(&self) -> std::io::Result<()> {
+        let c = serde_json::to_string_pretty(&self.cameras).map_err(std::io::Error::other)?;
+        std::fs::write(&self.path, c)
+    }
+
+    /// Count the time since the last frame of a camera as running time
+    pub fn frame(&mut self, i: i32) {
+        let now = Instant::now();
+        if let Some(t) = self.last_frame.insert(i, now) {
+            let gap = now - t;
+            if gap < Self::MAX_GAP {
+                *self.pending.entry(i).or_default() += gap.as_secs_f64();
+            }
+        }
+    }
+
+    /// True when it is time to call tick
+    pub fn should_check(&self) -> bool {
+        self.last_check
+            .is_none_or(|t| t.elapsed() >= Self::INTERVAL)
+    }
+
+    /// The cameras that ran since the last check
+    pub fn running(&self) -> impl Iterator<Item = i32> + '_ {
+        self.pending.keys().copied()
+    }
+
+    /// Add the running time to the cameras, with the names of the cameras that ran, and find the cameras that are due
+    pub fn tick(
+        &mut self,
+        settings: &ReminderSettings,
+        names: &[(i32, String)],
+    ) -> std::io::Result<()> {
+        self.last_check = Some(Instant::now());
+        let mut changed = false;
+        for (i, name) in names {
+            let Some(t) = self.pending.remove(i) else {
+                continue;
+            };
+            // Only cameras that were calibrated since reminders were kept have an age
+            if let Some(age) = self.cameras.get_mut(name) {
+                age.runtime += t;
+                changed = true;
+            }
+        }
+        self.pending.clear();
+        self.due = if settings.enabled {
+            self.cameras
+                .iter()
+                .filter_map(|(camera, age)| {
+                    Some(Due {
+                        camera: camera.clone(),
+                        reason: Self::reason(settings, age)?,
+                    })
+                })
+                .collect()
+        } else {
+            Vec::new()
+        };
+        if changed { self.save() } else { Ok(()) }
+    }
+
+    /// Why a camera is due, None when it is not
+    fn reason(settings: &ReminderSettings, age: &CameraAge) -> Option<String> {
+        let days = chrono::DateTime::parse_from_rfc3339(&age.calibrated)
+            .ok()
+            .map(|t| (chrono::Local::now().fixed_offset() - t).num_days());
+        if let Some(d) = days.filter(|d| settings.days > 0 && *d >= settings.days as i64) {
+            return Some(tr!("reminders.days_reason", days = d));
+        }
+        let hours = age.runtime / 3600.0;
+        if settings.hours > 0.0 && hours >= settings.hours {
+            return Some(tr!(
+                "reminders.hours_reason",
+                hours = format!("{:.0}", hours)
+            ));
+        }
+        None
+    }
+
+    /// Start the age of a camera over after calibrating it
+    pub fn calibrated(&mut self, camera: &str) -> std::io::Result<()> {
+        self.cameras.insert(
+            camera.to_string(),
+            CameraAge {
+                calibrated: chrono::Local::now().to_rfc3339(),
+                runtime: 0.0,
+            },
+        );
+        self.due.retain(|d| d.camera != camera);
+        self.save()
+    }
+
+    /// Show the cameras that are due, returns true when the user asks to start the calibration wizard
+    pub fn show(&mut self, ctx: &eframe::egui::Context) -> bool {
+        if self.due.is_empty() || self.snoozed.is_some_and(|t| Instant::now() < t) {
+            return false;
+        }
+        let mut wizard = false;
+        let mut later = false;
+        eframe::egui::Window::new(tr!("window.recalibration"))
+            .collapsible(false)
+            .show(ctx, |ui| {
+                ui.label(tr!("reminders.due"));
+                eframe::egui::Grid::new("recalibration_due")
+                    .striped(true)
+                    .show(ui, |ui| {
+                        for d in &self.due {
+                            ui.strong(&d.camera);
+                            ui.label(&d.reason);
+                            ui.end_row();
+                        }
+                    });
+                ui.horizontal(|ui| {
+                    wizard = ui.button(tr!("reminders.start_wizard")).clicked();
+                    later = ui.button(tr!("reminders.later")).clicked();
+                });
+            });
+        if wizard || later {
+            self.snoozed = Some(Instant::now() + Self::SNOOZE);
+        }
+        wizard
+    }
+}
@@ -0,0 +1,298 @@
+//! A report of a calibration as a self contained html page, with the images embedded.
+//! A pdf can be made by printing the page from a browser.
+
+use std::fmt::Write;
+
+use eframe::egui::ColorImage;
+use image_proc::calibration::{CalibrationData, CalibrationDataTrait};
+use image_proc::frame::Frame;
+use opencv::core::MatTraitConst;
+
+use crate::board::BoardParams;
+use crate::convert::mat_to_color_image;
+use crate::wizard::Coverage;
+
+/// The width of the capture thumbnails, in pixels
+const THUMBNAIL_WIDTH: i32 = 320;
+
+/// The width of the undistortion samples, in pixels
+const SAMPLE_WIDTH: i32 = 640;
+
+/// The number of captures shown before and after undistortion
+const SAMPLES: usize = 3;
+
+/// The names of the opencv distortion coefficients, in order
+pub const DISTORTION_NAMES: [&str; 14] = [
+    "k1", "k2", "p1", "p2", "k3", "k4", "k5", "k6", "s1", "s2", "s3", "s4", "τx", "τy",
+];
+
+/// Everything the report is made from
+pub struct ReportInput<'a> {
+    /// The name of the camera that was calibrated
+    pub camera: String,
+    pub board: &'a BoardParams,
+    pub charuco_board: &'a image_proc::aruco::CharucoBoard,
+    /// The captures the calibration was made from
+    pub images: &'a [Frame],
+    pub calibration: &'a CalibrationData,
+    /// The rms reprojection error over all captures
+    pub rms: Option<f64>,
+}
+
+/// Escape text for html
+fn escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// An image as a png data uri
+fn data_uri(img: &ColorImage) -> Result<String, String> {
+    let data: Vec<u8> = img
+        .pixels
+        .iter()
+        .flat_map(|p| [p.r(), p.g(), p.b()])
+        .collect();
+    let mut png = Vec::new();
+    image::ImageEncoder::write_image(
+        image::codecs::png::PngEncoder::new(&mut png),
+        &data,
+        img.width() as u32,
+        img.height() as u32,
+        image::ExtendedColorType::Rgb8,
+    )
+    .map_err(|e| e.to_string())?;
+    Ok(format!(
+        "data:image/png;base64,{}",
+        base64::Engine::encode(&base64::engine::general_purpose::STANDARD, png)
+    ))
+}
+
+/// A 3 channel rgb copy of a capture, scaled to a width
+fn scaled_rgb(img: &opencv::core::Mat, width: i32) -> opencv::Result<opencv::core::Mat> {
+    let mut rgb = opencv::core::Mat::default();
+    if img.channels() == 1 {
+        opencv::imgproc::cvt_color_def(img, &mut rgb, opencv::imgproc::COLOR_GRAY2RGB)?;
+    } else {
+        opencv::imgproc::cvt_color_def(img, &mut rgb, opencv::imgproc::COLOR_BGR2RGB)?;
+    }
+    let height = (rgb.rows() as f64 * width as f64 / rgb.cols().max(1) as f64).round() as i32;
+    let mut out = opencv::core::Mat::default();
+    opencv::imgproc::resize(
+        &rgb,
+        &mut out,
+        opencv::core::Size::new(width, height.max(1)),
+        0.0,
+        0.0,
+        opencv::imgproc::INTER_AREA,
+    )?;
+    Ok(out)
+}
+
+/// A thumbnail of a capture with the detected corners marked
+fn thumbnail(img: &opencv::core::Mat, corners: &[[f32; 2]]) -> Result<String, String> {
+    let mut m = scaled_rgb(img, THUMBNAIL_WIDTH).map_err(|e| e.to_string())?;
+    let scale = THUMBNAIL_WIDTH as f32 / img.cols().max(1) as f32;
+    for c in corners {
+        opencv::imgproc::circle(
+            &mut m,
+            opencv::core::Point::new((c[0] * scale) as i32, (c[1] * scale) as i32),
+            3,
+            opencv::core::Scalar::new(0.0, 255.0, 0.0, 0.0),
+            1,
+            opencv::imgproc::LINE_AA,
+            0,
+        )
+        .map_err(|e| e.to_string())?;
+    }
+    let img = mat_to_color_image(&m).ok_or("The image could not be converted")?;
+    data_uri(&img)
+}
+
+/// A capture next to the same capture with the lens distortion removed
+fn undistortion_sample(img: &opencv::core::Mat, cd: &CalibrationData) -> Result<String, String> {
+    let m = scaled_rgb(img, img.cols()).map_err(|e| e.to_string())?;
+    let before = mat_to_color_image(&m).ok_or("The image could not be converted")?;
+    let after = cd.apply_calibration(before.clone());
+    let labels = [tr!("main.montage_before"), tr!("main.montage_after")];
+    let sample = crate::montage::side_by_side(&before, &after, [&labels[0], &labels[1]])
+        .map_err(|e| e.to_string())?;
+    let sample = crate::convert::color_image_to_bgr_mat(&sample)
+        .ok_or("The image could not be converted")?;
+    let sample = scaled_rgb(&sample, SAMPLE_WIDTH * 2).map_err(|e| e.to_string())?;
+    data_uri(&mat_to_color_image(&sample).ok_or("The image could not be converted")?)
+}
+
+/// The background color of a coverage cell, matching the coverage shown in the wizard
+fn coverage_color(count: u32) -> &'static str {
+    match count {
+        0 => "#a02828",
+        1..=20 => "#c8a028",
+        _ => "#28a028",
+    }
+}
+
+/// Write the report for a calibration to an html file
+pub fn generate(path: &std::path::Path, input: &ReportInput) -> Result<(), String> {
+    let dictionary = input
+        .board
+        .dictionary()
+        .ok_or("The board dictionary is not valid")?;
+    let mut detections = Vec::with_capacity(input.images.len());
+    let mut coverage = Coverage::default();
+    for img in input.images {
+        let (corners, _) = if let Some(g) = input.board.grid() {
+            g.detect(img).map(|r| (r.corners, r.corner_ids))
+        } else {
+            image_proc::calibration::detect_charuco(img, input.charuco_board, &dictionary)
+        }
+        .map_err(|e| e.to_string())?;
+        let corners: Vec<[f32; 2]> = corners.iter().map(|p| [p.x, p.y]).collect();
+        coverage.add_view(
+            [img.cols() as f32, img.rows() as f32],
+            &corners,
+            input.board.corner_count() / 4,
+        );
+        detections.push(corners);
+    }
+    let errors = if let Some(g) = input.board.grid() {
+        image_proc::calibration::grid_residuals(input.images, input.calibration, g).map(|r| {
+            r.iter()
+                .map(|r| r.as_deref().map(image_proc::calibration::rms_error))
+                .collect()
+        })
+    } else {
+        image_proc::calibration::view_errors(
+            input.images,
+            input.calibration,
+            &input.board.charuco(),
+        )
+    }
+    .map_err(|e| e.to_string())?;
+
+    // Writing to a string can not fail, so the results are ignored
+    let mut html = String::new();
+    let _ = write!(
+        html,
+        "<!DOCTYPE html>\n<html><head><meta charset=\"utf-8\"><title>{title}</title><style>\
+         body{{font-family:sans-serif;margin:2em}}table{{border-collapse:collapse}}\
+         td,th{{border:1px solid #888;padding:4px 8px;text-align:left}}\
+         .coverage td{{width:3em;height:2em;text-align:center;color:#fff}}\
+         .view{{display:inline-block;margin:4px;vertical-align:top}}\
+         @media print{{.view,img{{break-inside:avoid}}}}</style></head><body>\n\
+         <h1>{title}</h1>\n<p>{camera}</p>\n",
+        title = escape(&tr!("report.title")),
+        camera = escape(&tr!(
+            "report.camera",
+            camera = input.camera,
+            captures = input.images.len()
+        )),
+    );
+
+    let _ = write!(html, "<h2>{}</h2>\n<table>\n", escape(&tr!("report.board")));
+    let b = input.board;
+    for (name, value) in [
+        (tr!("board.squares_across"), b.squares_x.to_string()),
+        (tr!("board.squares_down"), b.squares_y.to_string()),
+        (
+            tr!("board.square_size"),
+            format!("{:.2} mm", b.square_length * 1000.0),
+        ),
+        (
+            tr!("board.marker_size"),
+            format!("{:.2} mm", b.marker_length * 1000.0),
+        ),
+        (tr!("report.description"), b.description()),
+    ] {
+        let _ = writeln!(
+            html,
+            "<tr><th>{}</th><td>{}</td></tr>",
+            escape(&name),
+            escape(&value)
+        );
+    }
+    html.push_str("</table>\n");
+
+    let _ = write!(
+        html,
+        "<h2>{}</h2>\n<table>\n",
+        escape(&tr!("report.intrinsics"))
+    );
+    let k = input.calibration.camera_matrix().values();
+    if k.len() == 9 {
+        for (name, value) in [
+            ("fx", k[0]),
+            ("fy", k[4]),
+            ("cx", k[2]),
+            ("cy", k[5]),
+            ("skew", k[1]),
+        ] {
+            let _ = writeln!(html, "<tr><th>{}</th><td>{:.4}</td></tr>", name, value);
+        }
+    }
+    for (name, value) in DISTORTION_NAMES
+        .iter()
+        .zip(input.calibration.distortion().values())
+    {
+        let _ = writeln!(html, "<tr><th>{}</th><td>{:.6}</td></tr>", name, value);
+    }
+    if let Some(rms) = input.rms {
+        let _ = writeln!(
+            html,
+            "<tr><th>{}</th><td>{:.4} px</td></tr>",
+            escape(&tr!("report.rms")),
+            rms
+        );
+    }
+    html.push_str("</table>\n");
+
+    let _ = write!(
+        html,
+        "<h2>{}</h2>\n<p>{}</p>\n<table class=\"coverage\">\n",
+        escape(&tr!("report.coverage")),
+        escape(&tr!(
+            "wizard.coverage",
+            percent = format!("{:.0}", coverage.fraction() * 100.0)
+        ))
+    );
+    for row in coverage.rows() {
+        html.push_str("<tr>");
+        for count in row {
+            let _ = write!(
+                html,
+                "<td style=\"background:{}\">{}</td>",
+                coverage_color(*count),
+                count
+            );
+        }
+        html.push_str("</tr>\n");
+    }
+    html.push_str("</table>\n");
+
+    let _ = writeln!(html, "<h2>{}</h2>", escape(&tr!("report.views")));
+    for (i, (img, corners)) in input.images.iter().zip(&detections).enumerate() {
+        let error = match errors.get(i).copied().flatten() {
+            Some(e) => tr!("report.view_error", error = format!("{:.3}", e)),
+            None => tr!("report.no_error"),
+        };
+        let _ = writeln!(
+            html,
+            "<div class=\"view\"><img src=\"{}\"><br>{}<br>{}</div>",
+            thumbnail(img, corners)?,
+            escape(&tr!("report.view", number = i + 1, corners = corners.len())),
+            escape(&error)
+        );
+    }
+
+    let _ = writeln!(html, "<h2>{}</h2>", escape(&tr!("report.undistortion")));
+    for img in input.images.iter().take(SAMPLES) {
+        let _ = writeln!(
+            html,
+            "<p><img src=\"{}\"></p>",
+            undistortion_sample(img, input.calibration)?
+        );
+    }
+    html.push_str("</body></html>\n");
+    std::fs::write(path, html).map_err(|e| e.to_string())
+}
@@ -0,0 +1,138 @@
+//! Plots of the reprojection residuals of a calibration, residuals that depend on where they are on the sensor show that the model does not fit
+
+use egui_plot::{Arrows, Bar, BarChart, Plot, Points};
+use image_proc::calibration::Residual;
+
+/// The residuals of the last calibration
+pub struct ResidualPlot {
+    residuals: Vec<Residual>,
+    /// How many times longer the residual vectors are drawn than they are
+    exaggeration: f64,
+}
+
+impl Default for ResidualPlot {
+    fn default() -> Self {
+        Self {
+            residuals: Vec::new(),
+            exaggeration: 50.0,
+        }
+    }
+}
+
+impl ResidualPlot {
+    /// How many bars the distribution has
+    const BINS: usize = 30;
+
+    pub fn set(&mut self, residuals: Vec<Residual>) {
+        self.residuals = residuals;
+    }
+
+    pub fn clear(&mut self) {
+        self.residuals.clear();
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.residuals.is_empty()
+    }
+
+    /// The length of each residual in pixels
+    fn magnitudes(&self) -> Vec<f64> {
+        self.residuals
+            .iter()
+            .map(|r| (r.error[0] as f64).hypot(r.error[1] as f64))
+            .collect()
+    }
+
+    pub fn show(&mut self, ui: &mut eframe::egui::Ui) {
+        if self.residuals.is_empty() {
+            ui.label(tr!("residuals.none"));
+            return;
+        }
+        let magnitudes = self.magnitudes();
+        let n = magnitudes.len() as f64;
+        let mean = [
+            self.residuals
+                .iter()
+                .map(|r| r.error[0] as f64)
+                .sum::<f64>()
+                / n,
+            self.residuals
+                .iter()
+                .map(|r| r.error[1] as f64)
+                .sum::<f64>()
+                / n,
+        ];
+        let max = magnitudes.iter().cloned().fold(0.0, f64::max);
+        eframe::egui::Grid::new("residual_stats").show(ui, |ui| {
+            ui.label(tr!("residuals.corners"));
+            ui.monospace(magnitudes.len().to_string());
+            ui.end_row();
+            ui.label(tr!("history.rms"));
+            ui.monospace(format!(
+                "{:.4} px",
+                image_proc::calibration::rms_error(&self.residuals)
+            ));
+            ui.end_row();
+            ui.label(tr!("residuals.max"));
+            ui.monospace(format!("{:.4} px", max));
+            ui.end_row();
+            ui.label(tr!("residuals.mean"));
+            ui.monospace(format!("{:.4}, {:.4} px", mean[0], mean[1]));
+            ui.end_row();
+        });
+
+        ui.label(tr!("residuals.distribution"));
+        let width = (max / Self::BINS as f64).max(1e-6);
+        let mut counts = [0u32; Self::BINS];
+        for m in &magnitudes {
+            counts[((m / width) as usize).min(Self::BINS - 1)] += 1;
+        }
+        let bars: Vec<Bar> = counts
+            .iter()
+            .enumerate()
+            .map(|(i, c)| Bar::new((i as f64 + 0.5) * width, *c as f64).width(width))
+            .collect();
+        Plot::new("residual_distribution")
+            .height(150.0)
+            .x_axis_label(tr!("review.error_pixels"))
+            .y_axis_label(tr!("residuals.count"))
+            .show(ui, |plot| {
+                plot.bar_chart(BarChart::new(bars).name(tr!("residuals.corners")));
+            });
+
+        ui.horizontal(|ui| {
+            ui.label(tr!("residuals.sensor"));
+            ui.add(
+                eframe::egui::DragValue::new(&mut self.exaggeration)
+                    .range(1.0..=1000.0)
+                    .prefix("x"),
+            );
+        });
+        // The y axis is flipped so the plot is oriented like the image
+        let origins: Vec<[f64; 2]> = self
+            .residuals
+            .iter()
+            .map(|r| [r.found[0] as f64, -r.found[1] as f64])
+            .collect();
+        let tips: Vec<[f64; 2]> = self
+            .residuals
+            .iter()
+            .zip(&origins)
+            .map(|(r, o)| {
+                [
+                    o[0] + r.error[0] as f64 * self.exaggeration,
+                    o[1] - r.error[1] as f64 * self.exaggeration,
+                ]
+            })
+            .collect();
+        Plot::new("residual_vectors")
+            .height(300.0)
+            .data_aspect(1.0)
+            .x_axis_label(tr!("residuals.x"))
+            .y_axis_label(tr!("residuals.y"))
+            .show(ui, |plot| {
+                plot.points(Points::new(origins.clone()).radius(1.5));
+                plot.arrows(Arrows::new(origins, tips).name(tr!("residuals.vectors")));
+            });
+    }
+}
@@ -0,0 +1,312 @@
+//! Looking through saved calibrations and camera profiles without changing anything, for auditing results away from the cameras
+
+use std::path::{Path, PathBuf};
+
+use egui_plot::{Bar, BarChart, Plot};
+use image_proc::{
+    calibration::{CalibrationData, CalibrationMetadata},
+    integrity::{IntegrityError, Trust, Verification},
+    stereo::StereoCalibration,
+};
+
+use crate::profile::CameraProfile;
+
+/// A file opened for review
+enum Document {
+    Calibration {
+        calibration: CalibrationData,
+        verification: Verification,
+        metadata: Option<CalibrationMetadata>,
+    },
+    Profile(Box<CameraProfile>),
+    Stereo(Box<StereoCalibration>),
+}
+
+/// Show the intrinsics of a calibration, with the field of view when the resolution is known
+fn show_intrinsics(
+    ui: &mut eframe::egui::Ui,
+    id: &str,
+    cd: &CalibrationData,
+    resolution: Option<[u32; 2]>,
+) {
+    let k = cd.camera_matrix().values();
+    eframe::egui::Grid::new(id).striped(true).show(ui, |ui| {
+        if k.len() == 9 {
+            for (name, value) in [
+                ("fx", k[0]),
+                ("fy", k[4]),
+                ("cx", k[2]),
+                ("cy", k[5]),
+                ("skew", k[1]),
+            ] {
+                ui.label(name);
+                ui.monospace(format!("{:.4}", value));
+                ui.end_row();
+            }
+            if let Some([w, h]) = resolution {
+                let fov = |size: u32, f: f64| (2.0 * (size as f64 / (2.0 * f)).atan()).to_degrees();
+                ui.label(tr!("review.field_of_view"));
+                ui.monospace(format!("{:.2}° x {:.2}°", fov(w, k[0]), fov(h, k[4])));
+                ui.end_row();
+            }
+        }
+        for (name, value) in crate::report::DISTORTION_NAMES
+            .iter()
+            .zip(cd.distortion().values())
+        {
+            ui.label(*name);
+            ui.monospace(format!("{:.6}", value));
+            ui.end_row();
+        }
+    });
+}
+
+/// Show a row of a grid
+fn row(ui: &mut eframe::egui::Ui, name: String, value: impl Into<eframe::egui::WidgetText>) {
+    ui.label(name);
+    ui.label(value);
+    ui.end_row();
+}
+
+/// Show how a calibration was made and the error of each view
+fn show_metadata(ui: &mut eframe::egui::Ui, m: &CalibrationMetadata) {
+    eframe::egui::Grid::new("review_metadata")
+        .striped(true)
+        .show(ui, |ui| {
+            row(ui, tr!("history.time"), &m.timestamp);
+            row(ui, tr!("history.camera"), &m.camera);
+            row(ui, tr!("history.board"), &m.board);
+            if let Some([w, h]) = m.resolution {
+                row(ui, tr!("review.resolution"), format!("{}x{}", w, h));
+            }
+            row(ui, tr!("history.images"), m.view_errors.len().to_string());
+            row(ui, tr!("history.rms"), format!("{:.4} px", m.rms));
+            if let Some(t) = m.temperature {
+                row(ui, tr!("history.temperature"), format!("{:.1} °C", t));
+            }
+            for c in &m.distance_checks {
+                row(
+                    ui,
+                    tr!("review.distance_check"),
+                    format!("{:.1} mm, {:+.2} %", c.measured, c.error_percent()),
+                );
+            }
+        });
+    if m.view_errors.is_empty() {
+        return;
+    }
+    ui.label(tr!("review.view_errors"));
+    let bars: Vec<Bar> = m
+        .view_errors
+        .iter()
+        .enumerate()
+        .filter_map(|(i, e)| Some(Bar::new((i + 1) as f64, (*e)?)))
+        .collect();
+    Plot::new("review_view_errors")
+        .height(150.0)
+        .x_axis_label(tr!("review.view"))
+        .y_axis_label(tr!("review.error_pixels"))
+        .show(ui, |plot| {
+            plot.bar_chart(BarChart::new(bars).name(tr!("history.rms")));
+        });
+    eframe::egui::ScrollArea::vertical()
+        .max_height(200.0)
+        .show(ui, |ui| {
+            eframe::egui::Grid::new("review_views")
+                .striped(true)
+                .show(ui, |ui| {
+                    for (i, e) in m.view_errors.iter().enumerate() {
+                        ui.label(tr!("review.view_number", number = i + 1));
+                        match e {
+                            Some(e) => ui.monospace(format!("{:.4} px", e)),
+                            None => ui.label(tr!("report.no_error")),
+                        };
+                        ui.end_row();
+                    }
+                });
+        });
+}
+
+/// Show what a signature check found
+fn show_verification(ui: &mut eframe::egui::Ui, v: &Verification) {
+    match v {
+        Verification::Unchecked => {
+            ui.colored_label(eframe::egui::Color32::YELLOW, tr!("review.unchecked"));
+        }
+        Verification::Checksum => {
+            ui.label(tr!("review.checksum"));
+        }
+        Verification::Signed {
+            public_key,
+            trusted,
+        } => {
+            let key = image_proc::integrity::to_hex(public_key);
+            if *trusted {
+                ui.colored_label(
+                    eframe::egui::Color32::GREEN,
+                    tr!("info.calibration_signed", key = key),
+                );
+            } else {
+                ui.colored_label(
+                    eframe::egui::Color32::YELLOW,
+                    tr!("info.calibration_untrusted", key = key),
+                );
+            }
+        }
+    }
+}
+
+/// The review window, which never changes the calibration in use
+/// The rotation between the cameras of a pair, as the total angle and the angles about each axis
+pub fn rotation_text(s: &StereoCalibration) -> String {
+    let [x, y, z] = s.rotation_euler();
+    tr!(
+        "stereo.rotation",
+        angle = format!("{:.2}", s.rotation_angle()),
+        x = format!("{:.2}", x),
+        y = format!("{:.2}", y),
+        z = format!("{:.2}", z)
+    )
+}
+
+#[derive(Default)]
+pub struct CalibrationReview {
+    /// The file being reviewed
+    document: Option<(PathBuf, Document)>,
+    error: Option<String>,
+}
+
+impl CalibrationReview {
+    /// Open a calibration, stereo calibration or camera profile for review
+    pub fn open(&mut self, path: &Path, trust: &Trust) {
+        self.error = None;
+        let is_profile = path.extension().is_some_and(|e| e == "profile");
+        let document = if is_profile {
+            CameraProfile::load_file(path).map(|p| Document::Profile(Box::new(p)))
+        } else {
+            match CalibrationData::load_verified(path, trust) {
+                Ok((CalibrationData::Stereo(s), _)) => Some(Document::Stereo(Box::new(s))),
+                Ok((calibration, verification)) => Some(Document::Calibration {
+                    calibration,
+                    verification,
+                    metadata: CalibrationMetadata::load(path),
+                }),
+                Err(IntegrityError::Malformed) => {
+                    StereoCalibration::load(path, trust).map(|s| Document::Stereo(Box::new(s)))
+                }
+                Err(e) => {
+                    self.error = Some(e.to_string());
+                    self.document = None;
+                    return;
+                }
+            }
+        };
+        match document {
+            Some(d) => self.document = Some((path.to_path_buf(), d)),
+            None => {
+                self.document = None;
+                self.error = Some(tr!("error.load_calibration", path = path.display()));
+            }
+        }
+    }
+
+    /// Show the window contents, returns a file to open when the user picked one
+    pub fn show(&mut self, ui: &mut eframe::egui::Ui) -> Option<PathBuf> {
+        let mut pick = None;
+        if ui.button(tr!("review.open")).clicked() {
+            pick = rfd::FileDialog::new()
+                .add_filter(tr!("review.files"), &["bin", "profile"])
+                .pick_file();
+        }
+        if let Some(e) = &self.error {
+            ui.colored_label(eframe::egui::Color32::RED, e);
+        }
+        let Some((path, document)) = &self.document else {
+            ui.label(tr!("review.nothing_open"));
+            return pick;
+        };
+        ui.strong(path.display().to_string());
+        eframe::egui::ScrollArea::vertical().show(ui, |ui| match document {
+            Document::Calibration {
+                calibration,
+                verification,
+                metadata,
+            } => {
+                show_verification(ui, verification);
+                ui.heading(tr!("report.intrinsics"));
+                show_intrinsics(
+                    ui,
+                    "review_intrinsics",
+                    calibration,
+                    metadata.as_ref().and_then(|m| m.resolution),
+                );
+                ui.heading(tr!("review.metadata"));
+                match metadata {
+                    Some(m) => show_metadata(ui, m),
+                    None => {
+                        ui.label(tr!("review.no_metadata"));
+                    }
+                }
+            }
+            Document::Profile(p) => {
+                eframe::egui::Grid::new("review_profile")
+                    .striped(true)
+                    .show(ui, |ui| {
+                        let measured = |b: bool| {
+                            if b {
+                                tr!("review.measured")
+                            } else {
+                                tr!("review.not_measured")
+                            }
+                        };
+                        row(
+                            ui,
+                            tr!("review.identity"),
+                            p.identity.clone().unwrap_or_default(),
+                        );
+                        row(ui, tr!("main.noise_profile"), measured(p.noise.is_some()));
+                        if let Some(r) = &p.rolling_shutter {
+                            row(
+                                ui,
+                                tr!("main.rolling_shutter"),
+                                format!("{:.3} ms", r.readout_time * 1000.0),
+                            );
+                        } else {
+                            row(ui, tr!("main.rolling_shutter"), measured(false));
+                        }
+                        row(ui, tr!("main.vignetting"), measured(p.vignetting.is_some()));
+                        if let Some(g) = &p.gray_card {
+                            row(ui, tr!("main.gray_card"), format!("{:+.2} EV", g.stops));
+                        } else {
+                            row(ui, tr!("main.gray_card"), measured(false));
+                        }
+                    });
+                ui.heading(tr!("report.intrinsics"));
+                match &p.calibration {
+                    Some(cd) => {
+                        show_intrinsics(ui, "review_intrinsics", cd, p.calibration_resolution)
+                    }
+                    None => {
+                        ui.label(tr!("review.not_measured"));
+                    }
+                }
+            }
+            Document::Stereo(s) => {
+                eframe::egui::Grid::new("review_stereo")
+                    .striped(true)
+                    .show(ui, |ui| {
+                        let [w, h] = s.resolution;
+                        row(ui, tr!("review.resolution"), format!("{}x{}", w, h));
+                        row(ui, tr!("history.rms"), format!("{:.4} px", s.rms));
+                        row(ui, tr!("review.baseline"), format!("{:.4}", s.baseline()));
+                        row(ui, tr!("review.rotation"), rotation_text(s));
+                    });
+                ui.heading(tr!("review.left"));
+                show_intrinsics(ui, "review_left", &s.left, Some(s.resolution));
+                ui.heading(tr!("review.right"));
+                show_intrinsics(ui, "review_right", &s.right, Some(s.resolution));
+            }
+        });
+        pick
+    }
+}
@@ -0,0 +1,188 @@
+//! Keeping the last few seconds of frames in memory, so the moments before something happened can be saved
+
+use std::{
+    collections::VecDeque,
+    path::PathBuf,
+    time::{Duration, Instant},
+};
+
+use image_proc::{cancel::CancelToken, frame::Frame};
+use opencv::core::MatTraitConst;
+
+/// Records the newest frames of the selected camera
+pub struct RingBuffer {
+    /// True while frames are being recorded
+    recording: bool,
+    /// How many seconds of frames are kept
+    seconds: f32,
+    /// The most memory the frames may use, in megabytes, older frames are dropped first
+    max_megabytes: usize,
+    /// The frames with the time they arrived, oldest first
+    frames: VecDeque<(Instant, Frame)>,
+    /// The memory used by the frames, in bytes
+    bytes: usize,
+    /// The camera the frames came from
+    camera: Option<i32>,
+}
+
+impl Default for RingBuffer {
+    fn default() -> Self {
+        Self {
+            recording: false,
+            seconds: 10.0,
+            max_megabytes: 1024,
+            frames: VecDeque::new(),
+            bytes: 0,
+            camera: None,
+        }
+    }
+}
+
+/// The memory used by the pixels of a frame
+fn frame_bytes(m: &opencv::core::Mat) -> usize {
+    m.total() * m.elem_size().unwrap_or(1)
+}
+
+/// Write frames to a directory as png files, with a list of when each frame arrived relative to the newest.
+/// When cancelled the frames written so far are kept and listed.
+fn write_frames(
+    dir: &std::path::Path,
+    frames: &[(Instant, Frame)],
+    cancel: &CancelToken,
+) -> Result<usize, String> {
+    std::fs::create_dir_all(dir).map_err(|e| e.to_string())?;
+    let newest = frames.last().map(|(t, _)| *t).unwrap_or_else(Instant::now);
+    let mut list = String::from("file,seconds\n");
+    let mut written = 0;
+    for (n, (t, m)) in frames.iter().enumerate() {
+        if cancel.is_cancelled() {
+            break;
+        }
+        let name = format!("frame_{:05}.png", n);
+        let img =
+            crate::convert::bgr_mat_to_color_image(m).ok_or("The frame could not be converted")?;
+        crate::convert::save_color_image(&dir.join(&name), &img).map_err(|e| e.to_string())?;
+        let offset = newest.duration_since(*t).as_secs_f64();
+        list.push_str(&format!("{},{:.4}\n", name, -offset));
+        written += 1;
+    }
+    std::fs::write(dir.join("frames.csv"), list).map_err(|e| e.to_string())?;
+    Ok(written)
+}
+
+/// Save frames to a directory in the background, the message for the user is sent when done
+pub fn save_frames(
+    dir: PathBuf,
+    frames: Vec<(Instant, Frame)>,
+    done: crossbeam::channel::Sender<crate::status::TaskResult>,
+    cancel: CancelToken,
+) {
+    std::thread::spawn(move || {
+        let r = write_frames(&dir, &frames, &cancel)
+            .map(|count| {
+                if cancel.is_cancelled() {
+                    tr!(
+                        "info.save_frames_cancelled",
+                        count = count,
+                        path = dir.display()
+                    )
+                } else {
+                    tr!("info.saved_frames", count = count, path = dir.display())
+                }
+            })
+            .map_err(|e| tr!("error.save_frames", error = e));
+        let _ = done.send(r);
+    });
+}
+
+impl RingBuffer {
+    /// Add a frame from a camera, dropping the frames that are too old or do not fit.
+    /// Frames from another camera start the buffer over.
+    pub fn add_frame(&mut self, camera: i32, img: &Frame) {
+        if !self.recording {
+            return;
+        }
+        if self.camera != Some(camera) {
+            self.clear();
+            self.camera = Some(camera);
+        }
+        let now = Instant::now();
+        self.bytes += frame_bytes(img);
+        self.frames.push_back((now, img.clone()));
+        let keep = Duration::from_secs_f32(self.seconds);
+        let max = self.max_megabytes * 1024 * 1024;
+        while let Some((t, m)) = self.frames.front() {
+            if now.duration_since(*t) <= keep && self.bytes <= max {
+                break;
+            }
+            self.bytes -= frame_bytes(m);
+            self.frames.pop_front();
+        }
+    }
+
+    /// Forget every recorded frame
+    pub fn clear(&mut self) {
+        self.frames.clear();
+        self.bytes = 0;
+    }
+
+    /// The camera the recorded frames came from
+    pub fn camera(&self) -> Option<i32> {
+        self.camera
+    }
+
+    /// Save the recorded frames to a directory in the background, the buffer starts filling again afterwards.
+    /// The message for the user is sent when done.
+    pub fn save(
+        &mut self,
+        dir: PathBuf,
+        done: crossbeam::channel::Sender<crate::status::TaskResult>,
+        cancel: CancelToken,
+    ) {
+        let frames: Vec<_> = self.frames.drain(..).collect();
+        self.bytes = 0;
+        save_frames(dir, frames, done, cancel);
+    }
+
+    /// The number of seconds of frames currently held
+    fn held(&self) -> f32 {
+        match (self.frames.front(), self.frames.back()) {
+            (Some((a, _)), Some((b, _))) => b.duration_since(*a).as_secs_f32(),
+            _ => 0.0,
+        }
+    }
+
+    /// Show the buffer controls, returns true when the buffer should be saved
+    pub fn show(&mut self, ui: &mut eframe::egui::Ui) -> bool {
+        ui.label(tr!("ring_buffer.instructions"));
+        ui.add(
+            eframe::egui::Slider::new(&mut self.seconds, 1.0..=120.0)
+                .suffix(" s")
+                .text(tr!("ring_buffer.seconds")),
+        );
+        ui.add(
+            eframe::egui::Slider::new(&mut self.max_megabytes, 64..=8192)
+                .logarithmic(true)
+                .suffix(" MB")
+                .text(tr!("ring_buffer.memory")),
+        );
+        if ui
+            .checkbox(&mut self.recording, tr!("ring_buffer.record"))
+            .changed()
+            && !self.recording
+        {
+            self.clear();
+        }
+        ui.label(tr!(
+            "ring_buffer.status",
+            frames = self.frames.len(),
+            seconds = format!("{:.1}", self.held()),
+            megabytes = self.bytes / (1024 * 1024)
+        ));
+        ui.add_enabled(
+            !self.frames.is_empty(),
+            eframe::egui::Button::new(tr!("ring_buffer.save")),
+        )
+        .clicked()
+    }
+}
@@ -0,0 +1,277 @@
+//! Measurement of the rolling shutter readout time of a camera
+
+use std::time::Instant;
+
+use opencv::core::{MatTraitConst, MatTraitConstManual};
+
+use crate::profile::CameraProfile;
+
+/// The measured rolling shutter characteristics of a camera
+#[derive(Clone, Debug, serde::Serialize, serde::Deserialize)]
+pub struct RollingShutterProfile {
+    /// The time between the start of exposure of the first and last rows, in seconds
+    pub readout_time: f64,
+    /// The time between the start of exposure of consecutive rows, in seconds
+    pub line_time: f64,
+    /// The height of the frames used for the measurement
+    pub height: i32,
+    /// True when rows are read from the bottom of the image to the top
+    pub bottom_to_top: bool,
+}
+
+impl RollingShutterProfile {
+    fn new(line_time: f64, height: i32) -> Self {
+        Self {
+            readout_time: line_time.abs() * height as f64,
+            line_time: line_time.abs(),
+            height,
+            bottom_to_top: line_time < 0.0,
+        }
+    }
+}
+
+/// A grayscale frame and when it arrived
+struct Sample {
+    time: Instant,
+    width: usize,
+    height: usize,
+    gray: Vec<f32>,
+}
+
+/// Least squares fit of a line to a set of points, returning slope and intercept
+fn fit_line(points: &[(f64, f64)]) -> Option<(f64, f64)> {
+    let n = points.len() as f64;
+    if points.len() < 2 {
+        return None;
+    }
+    let mx = points.iter().map(|p| p.0).sum::<f64>() / n;
+    let my = points.iter().map(|p| p.1).sum::<f64>() / n;
+    let sxx: f64 = points.iter().map(|p| (p.0 - mx) * (p.0 - mx)).sum();
+    let sxy: f64 = points.iter().map(|p| (p.0 - mx) * (p.1 - my)).sum();
+    if sxx == 0.0 {
+        return None;
+    }
+    let slope = sxy / sxx;
+    Some((slope, my - slope * mx))
+}
+
+impl Sample {
+    /// The subpixel horizontal position of the strongest vertical edge of every row that has one
+    fn edge_positions(&self) -> Vec<(f64, f64)> {
+        let mut points = Vec::new();
+        for y in 0..self.height {
+            let row = &self.gray[y * self.width..(y + 1) * self.width];
+            let grad: Vec<f32> = (1..self.width - 1)
+                .map(|x| (row[x + 1] - row[x - 1]).abs())
+                .collect();
+            let best = grad.iter().enumerate().max_by(|a, b| a.1.total_cmp(b.1));
+            if let Some((i, g)) = best {
+                if *g < 20.0 || i == 0 || i + 1 >= grad.len() {
+                    continue;
+                }
+                let (gm, gp) = (grad[i - 1], grad[i + 1]);
+                let d = gm - 2.0 * g + gp;
+                let offset = if d != 0.0 { 0.5 * (gm - gp) / d } else { 0.0 };
+                points.push((y as f64, (i + 1) as f64 + offset as f64));
+            }
+        }
+        points
+    }
+
+    /// The period in rows of the strongest horizontal banding in the image
+    fn band_period(&self) -> Option<f64> {
+        let rows: Vec<f64> = (0..self.height)
+            .map(|y| {
+                self.gray[y * self.width..(y + 1) * self.width]
+                    .iter()
+                    .map(|v| *v as f64)
+                    .sum::<f64>()
+                    / self.width as f64
+            })
+            .collect();
+        let n = rows.len();
+        let mean = rows.iter().sum::<f64>() / n as f64;
+        (2..n / 4)
+            .map(|k| {
+                let (mut re, mut im) = (0.0, 0.0);
+                for (j, v) in rows.iter().enumerate() {
+                    let phase = -2.0 * std::f64::consts::PI * (k * j) as f64 / n as f64;
+                    re += (v - mean) * phase.cos();
+                    im += (v - mean) * phase.sin();
+                }
+                (k, re * re + im * im)
+            })
+            .max_by(|a, b| a.1.total_cmp(&b.1))
+            .map(|(k, _)| n as f64 / k as f64)
+    }
+}
+
+/// The tool for measuring the rolling shutter of a camera
+pub struct RollingShutterTool {
+    /// Use a blinking led instead of a moving edge
+    led: bool,
+    /// The blink frequency of the led in Hz
+    led_frequency: f64,
+    frames: usize,
+    capturing: bool,
+    samples: Vec<Sample>,
+    error: Option<String>,
+}
+
+impl Default for RollingShutterTool {
+    fn default() -> Self {
+        Self {
+            led: false,
+            led_frequency: 1000.0,
+            frames: 10,
+            capturing: false,
+            samples: Vec::new(),
+            error: None,
+        }
+    }
+}
+
+impl RollingShutterTool {
+    /// Add a frame from the camera being measured, returns the measurement once enough frames are captured
+    pub fn add_frame(&mut self, img: &opencv::core::Mat) -> Option<RollingShutterProfile> {
+        if !self.capturing {
+            return None;
+        }
+        let mut gray = opencv::core::Mat::default();
+        if img.channels() == 3 {
+            opencv::imgproc::cvt_color_def(img, &mut gray, opencv::imgproc::COLOR_BGR2GRAY).ok()?;
+        } else {
+            gray = img.clone();
+        }
+        let data = gray.data_bytes().ok()?;
+        self.samples.push(Sample {
+            time: Instant::now(),
+            width: gray.cols() as usize,
+            height: gray.rows() as usize,
+            gray: data.iter().map(|v| *v as f32).collect(),
+        });
+        if self.samples.len() < self.frames {
+            return None;
+        }
+        self.capturing = false;
+        let r = if self.led {
+            self.measure_led()
+        } else {
+            self.measure_edge()
+        };
+        self.samples.clear();
+        match r {
+            Ok(p) => Some(p),
+            Err(e) => {
+                self.error = Some(e);
+                None
+            }
+        }
+    }
+
+    /// Measure using a vertical edge moving horizontally across the frame.
+    /// The speed of the edge comes from its movement between frames, and the skew of the edge
+    /// then gives the time between rows.
+    fn measure_edge(&self) -> Result<RollingShutterProfile, String> {
+        let start = self.samples[0].time;
+        let height = self.samples[0].height;
+        let mut skews = Vec::new();
+        let mut centers = Vec::new();
+        for s in &self.samples {
+            let points = s.edge_positions();
+            if points.len() < height / 2 {
+                continue;
+            }
+            if let Some((slope, intercept)) = fit_line(&points) {
+                skews.push(slope);
+                centers.push((
+                    s.time.duration_since(start).as_secs_f64(),
+                    intercept + slope * height as f64 / 2.0,
+                ));
+            }
+        }
+        if centers.len() < 2 {
+            return Err(tr!("rolling_shutter.no_edge"));
+        }
+        let (speed, _) = fit_line(&centers).ok_or_else(|| tr!("rolling_shutter.no_speed"))?;
+        if speed.abs() < 1.0 {
+            return Err(tr!("rolling_shutter.too_slow"));
+        }
+        let skew = skews.iter().sum::<f64>() / skews.len() as f64;
+        Ok(RollingShutterProfile::new(skew / speed, height as i32))
+    }
+
+    /// Measure using an led blinking at a known frequency, which appears as horizontal bands
+    fn measure_led(&self) -> Result<RollingShutterProfile, String> {
+        let periods: Vec<f64> = self
+            .samples
+            .iter()
+            .filter_map(|s| s.band_period())
+            .collect();
+        if periods.is_empty() {
+            return Err(tr!("rolling_shutter.no_banding"));
+        }
+        let period = periods.iter().sum::<f64>() / periods.len() as f64;
+        let line_time = 1.0 / (self.led_frequency * period);
+        Ok(RollingShutterProfile::new(
+            line_time,
+            self.samples[0].height as i32,
+        ))
+    }
+
+    /// Show the tool, along with the stored measurement of the selected camera
+    pub fn show(&mut self, ui: &mut eframe::egui::Ui, profile: Option<&CameraProfile>) {
+        ui.horizontal(|ui| {
+            ui.radio_value(&mut self.led, false, tr!("rolling_shutter.edge"));
+            ui.radio_value(&mut self.led, true, tr!("rolling_shutter.led"));
+        });
+        if self.led {
+            ui.label(tr!("rolling_shutter.led_instructions"));
+            ui.add(
+                eframe::egui::DragValue::new(&mut self.led_frequency)
+                    .range(1.0..=100000.0)
+                    .suffix(" Hz"),
+            );
+        } else {
+            ui.label(tr!("rolling_shutter.edge_instructions"));
+        }
+        ui.horizontal(|ui| {
+            ui.add(
+                eframe::egui::Slider::new(&mut self.frames, 2..=60)
+                    .text(tr!("rolling_shutter.frames")),
+            );
+            if self.capturing {
+                ui.label(tr!(
+                    "rolling_shutter.progress",
+                    count = self.samples.len(),
+                    total = self.frames
+                ));
+                if ui.button(tr!("rolling_shutter.cancel")).clicked() {
+                    self.capturing = false;
+                    self.samples.clear();
+                }
+            } else if ui.button(tr!("rolling_shutter.measure")).clicked() {
+                self.error = None;
+                self.samples.clear();
+                self.capturing = true;
+            }
+        });
+        if let Some(e) = &self.error {
+            ui.colored_label(eframe::egui::Color32::RED, e);
+        }
+        if let Some(p) = profile.and_then(|p| p.rolling_shutter.as_ref()) {
+            ui.label(tr!(
+                "rolling_shutter.readout",
+                time = format!("{:.3}", p.readout_time * 1000.0),
+                rows = p.height
+            ));
+            ui.label(tr!(
+                "rolling_shutter.line_time",
+                time = format!("{:.3}", p.line_time * 1e6)
+            ));
+            if p.bottom_to_top {
+                ui.label(tr!("rolling_shutter.bottom_to_top"));
+            }
+        }
+    }
+}
@@ -0,0 +1,213 @@
+//! Capturing a monitor or window as a frame source, to process the output of other software
+
+use std::time::{Duration, Instant};
+
+use crate::FrameSourceTrait;
+
+/// What is captured
+#[derive(Clone, Debug, PartialEq)]
+pub enum ScreenTarget {
+    /// A monitor, by name
+    Monitor(String),
+    /// A window, by title
+    Window(String),
+}
+
+impl ScreenTarget {
+    /// Every monitor and window that can currently be captured
+    pub fn all() -> Vec<ScreenTarget> {
+        let monitors = xcap::Monitor::all().unwrap_or_default();
+        let windows = xcap::Window::all().unwrap_or_default();
+        monitors
+            .iter()
+            .map(|m| ScreenTarget::Monitor(m.name().unwrap_or_default()))
+            .chain(
+                windows
+                    .iter()
+                    .filter(|w| !w.is_minimized().unwrap_or(false))
+                    .map(|w| ScreenTarget::Window(w.title().unwrap_or_default()))
+                    .filter(|t| *t != ScreenTarget::Window(String::new())),
+            )
+            .collect()
+    }
+
+    /// The name shown to the user
+    pub fn name(&self) -> String {
+        match self {
+            ScreenTarget::Monitor(n) => tr!("screen.monitor", name = n),
+            ScreenTarget::Window(n) => tr!("screen.window", name = n),
+        }
+    }
+
+    fn capture(&self) -> Option<image::RgbaImage> {
+        match self {
+            ScreenTarget::Monitor(n) => xcap::Monitor::all()
+                .ok()?
+                .into_iter()
+                .find(|m| m.name().is_ok_and(|m| m == *n))?
+                .capture_image()
+                .ok(),
+            ScreenTarget::Window(n) => xcap::Window::all()
+                .ok()?
+                .into_iter()
+                .find(|w| w.title().is_ok_and(|t| t == *n))?
+                .capture_image()
+                .ok(),
+        }
+    }
+}
+
+/// A region of the screen captured at a fixed rate
+#[derive(Debug)]
+pub struct ScreenCapture {
+    target: ScreenTarget,
+    /// The captured region as x, y, width and height, None captures everything
+    region: Option<[u32; 4]>,
+    /// The time between frames
+    interval: Duration,
+    last_frame: Option<Instant>,
+    open: bool,
+    /// The number of captures in a row that failed
+    failures: u32,
+}
+
+impl ScreenCapture {
+    pub fn new(target: ScreenTarget, region: Option<[u32; 4]>, fps: f64) -> Self {
+        Self {
+            target,
+            region,
+            interval: Duration::from_secs_f64(1.0 / fps.max(0.1)),
+            last_frame: None,
+            open: false,
+            failures: 0,
+        }
+    }
+
+    /// The name shown to the user
+    pub fn name(&self) -> String {
+        self.target.name()
+    }
+
+    /// Convert a captured image to a bgr matrix, like cameras produce
+    fn to_mat(&self, img: image::RgbaImage) -> Option<opencv::core::Mat> {
+        let img = match self.region {
+            Some([x, y, w, h]) => {
+                let w = w.min(img.width().checked_sub(x)?);
+                let h = h.min(img.height().checked_sub(y)?);
+                if w == 0 || h == 0 {
+                    return None;
+                }
+                image::imageops::crop_imm(&img, x, y, w, h).to_image()
+            }
+            None => img,
+        };
+        let data: Vec<u8> = img.pixels().flat_map(|p| [p[2], p[1], p[0]]).collect();
+        crate::convert::bytes_to_mat(
+            img.width() as usize,
+            img.height() as usize,
+            opencv::core::CV_8UC3,
+            &data,
+        )
+    }
+}
+
+impl FrameSourceTrait for ScreenCapture {
+    fn open(&mut self) -> bool {
+        self.open = true;
+        self.failures = 0;
+        true
+    }
+
+    fn close(&mut self) {
+        self.open = false;
+    }
+
+    fn is_open(&self) -> bool {
+        self.open
+    }
+
+    fn get_image(&mut self) -> Option<opencv::core::Mat> {
+        if let Some(last) = self.last_frame {
+            if last.elapsed() < self.interval {
+                return None;
+            }
+        }
+        self.last_frame = Some(Instant::now());
+        let m = self.target.capture().and_then(|img| self.to_mat(img));
+        if m.is_some() {
+            self.failures = 0;
+        } else {
+            self.failures += 1;
+        }
+        m
+    }
+
+    fn failed(&self) -> bool {
+        // Captures are slow, so give up sooner than for cameras
+        self.failures >= 5
+    }
+}
+
+/// The window for choosing what to capture
+pub struct ScreenCaptureDialog {
+    targets: Vec<ScreenTarget>,
+    selected: Option<usize>,
+    whole: bool,
+    region: [u32; 4],
+    fps: f64,
+}
+
+impl Default for ScreenCaptureDialog {
+    fn default() -> Self {
+        Self {
+            targets: Vec::new(),
+            selected: None,
+            whole: true,
+            region: [0, 0, 640, 480],
+            fps: 10.0,
+        }
+    }
+}
+
+impl ScreenCaptureDialog {
+    /// Show the dialog, returns a capture when the user adds one
+    pub fn show(&mut self, ui: &mut eframe::egui::Ui) -> Option<ScreenCapture> {
+        if self.targets.is_empty() || ui.button(tr!("screen.refresh")).clicked() {
+            self.targets = ScreenTarget::all();
+            self.selected = self.selected.filter(|s| *s < self.targets.len());
+        }
+        let name = self
+            .selected
+            .map(|s| self.targets[s].name())
+            .unwrap_or_default();
+        eframe::egui::ComboBox::from_label(tr!("screen.capture"))
+            .selected_text(name)
+            .show_ui(ui, |ui| {
+                for (i, t) in self.targets.iter().enumerate() {
+                    ui.selectable_value(&mut self.selected, Some(i), t.name());
+                }
+            });
+        ui.checkbox(&mut self.whole, tr!("screen.whole"));
+        if !self.whole {
+            ui.horizontal(|ui| {
+                for (name, v) in ["x", "y", "w", "h"].into_iter().zip(self.region.iter_mut()) {
+                    ui.label(name);
+                    ui.add(eframe::egui::DragValue::new(v));
+                }
+            });
+        }
+        ui.add(eframe::egui::Slider::new(&mut self.fps, 1.0..=30.0).text(tr!("screen.fps")));
+        let target = self.selected.map(|s| self.targets[s].clone());
+        if ui
+            .add_enabled(
+                target.is_some(),
+                eframe::egui::Button::new(tr!("screen.add")),
+            )
+            .clicked()
+        {
+            let region = (!self.whole).then_some(self.region);
+            return target.map(|t| ScreenCapture::new(t, region, self.fps));
+        }
+        None
+    }
+}
@@ -0,0 +1,91 @@
+//! Changing the distortion coefficients of a calibration by hand while watching the undistorted preview,
+//! to see which term is responsible for curvature left in the image
+
+use image_proc::calibration::CalibrationData;
+
+/// The names of the distortion coefficients in the order opencv stores them
+const NAMES: [&str; 14] = [
+    "k1", "k2", "p1", "p2", "k3", "k4", "k5", "k6", "s1", "s2", "s3", "s4", "τx", "τy",
+];
+
+/// The distortion coefficients being tried in place of the solved ones
+#[derive(Default)]
+pub struct DistortionExplorer {
+    /// The coefficients of the calibration, the sliders start from them
+    solved: Vec<f64>,
+    /// The coefficients used for the preview
+    values: Vec<f64>,
+    /// Use the changed coefficients for the preview
+    pub enabled: bool,
+}
+
+impl DistortionExplorer {
+    /// Start from the coefficients of a calibration when it is not the one the sliders were set from
+    fn sync(&mut self, cd: &CalibrationData) {
+        let solved = cd.distortion().values();
+        if solved != self.solved {
+            self.values = solved.clone();
+            self.solved = solved;
+        }
+    }
+
+    /// The calibration with the changed coefficients, when they are in use and were set from this calibration.
+    /// Calibrations scaled to another resolution keep their coefficients, so they are changed too.
+    pub fn apply(&self, cd: CalibrationData) -> CalibrationData {
+        if !self.enabled || self.values == self.solved || cd.distortion().values() != self.solved {
+            return cd;
+        }
+        cd.with_distortion(&self.values)
+    }
+
+    /// The range of a slider, wide enough to go well past the solved value on either side
+    fn range(solved: f64) -> std::ops::RangeInclusive<f64> {
+        let r = (solved.abs() * 3.0).max(if solved.abs() > 1.0 { 10.0 } else { 0.5 });
+        (solved - r)..=(solved + r)
+    }
+
+    pub fn show(&mut self, ui: &mut eframe::egui::Ui, cd: Option<&CalibrationData>) {
+        let Some(cd) = cd else {
+            ui.label(tr!("sensitivity.no_calibration"));
+            return;
+        };
+        self.sync(cd);
+        ui.checkbox(&mut self.enabled, tr!("sensitivity.enabled"));
+        ui.add_enabled_ui(self.enabled, |ui| {
+            eframe::egui::Grid::new("distortion_explorer").show(ui, |ui| {
+                for (i, v) in self.values.iter_mut().enumerate() {
+                    let solved = self.solved[i];
+                    ui.label(NAMES.get(i).copied().unwrap_or("?"));
+                    ui.add(
+                        eframe::egui::Slider::new(v, Self::range(solved))
+                            .max_decimals(6)
+                            .clamping(eframe::egui::SliderClamping::Never),
+                    );
+                    if ui
+                        .add_enabled(
+                            *v != solved,
+                            eframe::egui::Button::new(tr!("sensitivity.reset")),
+                        )
+                        .clicked()
+                    {
+                        *v = solved;
+                    }
+                    if ui
+                        .add_enabled(
+                            *v != 0.0,
+                            eframe::egui::Button::new(tr!("sensitivity.zero")),
+                        )
+                        .clicked()
+                    {
+                        *v = 0.0;
+                    }
+                    ui.weak(format!("{:.6}", solved));
+                    ui.end_row();
+                }
+            });
+            if ui.button(tr!("sensitivity.reset_all")).clicked() {
+                self.values = self.solved.clone();
+            }
+        });
+    }
+}
@@ -0,0 +1,159 @@
+//! Capture modes that stream a window cut out of the sensor, binned or not. The calibration made with the full sensor
+//! is moved to the window by its offset and shrunk by the binning, instead of calibrating again for each mode,
+//! and a calibration made in a mode is carried back to the full sensor the same way. The modes are stored in the camera profile.
+
+use image_proc::calibration::CalibrationData;
+
+/// A capture mode of a camera that streams a window of the sensor, with its pixels binned or not
+#[derive(Clone, Debug, Default, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct SensorMode {
+    pub name: String,
+    /// The width and height of the images of the mode
+    pub resolution: [u32; 2],
+    /// Where the top left corner of the window is on the sensor, in pixels of the full sensor
+    pub offset: [u32; 2],
+    /// How many pixels of the sensor are summed into one across and down, 1 when the mode is not binned
+    #[serde(default = "no_binning")]
+    pub binning: u32,
+}
+
+fn no_binning() -> u32 {
+    1
+}
+
+/// The full sensor of a camera and the windows of it that the camera streams
+#[derive(Clone, Debug, Default, serde::Serialize, serde::Deserialize)]
+pub struct SensorModes {
+    /// The width and height of the images of the full sensor
+    pub sensor: [u32; 2],
+    pub modes: Vec<SensorMode>,
+}
+
+impl SensorModes {
+    /// Where the images of a resolution start on the sensor and their binning,
+    /// None when it is neither the full sensor nor a mode
+    fn placement(&self, resolution: [u32; 2]) -> Option<([f64; 2], f64)> {
+        if resolution == self.sensor {
+            return Some(([0.0, 0.0], 1.0));
+        }
+        self.modes
+            .iter()
+            .find(|m| m.resolution == resolution)
+            .map(|m| (m.offset.map(f64::from), m.binning.max(1) as f64))
+    }
+
+    /// The calibration for images of a resolution from one made with images of another,
+    /// when both are the full sensor or one of the modes. It goes through the full sensor.
+    pub fn calibration(
+        &self,
+        cd: &CalibrationData,
+        from: [u32; 2],
+        to: [u32; 2],
+    ) -> Option<CalibrationData> {
+        let (a, from_binning) = self.placement(from)?;
+        let (b, to_binning) = self.placement(to)?;
+        let window = |r: [u32; 2], binning: f64| r.map(|v| (v as f64 * binning).round() as u32);
+        let full = cd
+            .binned(1.0 / from_binning, window(from, from_binning))
+            .cropped(a.map(|o| -o), self.sensor);
+        Some(
+            full.cropped(b, window(to, to_binning))
+                .binned(to_binning, to),
+        )
+    }
+
+    /// Show the sensor and its modes for editing, current is the resolution the camera streams now.
+    /// Returns true when something was changed.
+    pub fn show(&mut self, ui: &mut eframe::egui::Ui, current: Option<[u32; 2]>) -> bool {
+        let mut changed = false;
+        ui.label(tr!("sensor_mode.instructions"));
+        ui.horizontal(|ui| {
+            ui.label(tr!("sensor_mode.sensor"));
+            for v in &mut self.sensor {
+                changed |= ui
+                    .add(eframe::egui::DragValue::new(v).range(0..=65535))
+                    .changed();
+            }
+            if let Some(c) = current {
+                if ui.button(tr!("sensor_mode.use_current")).clicked() {
+                    self.sensor = c;
+                    changed = true;
+                }
+            }
+        });
+        let mut remove = None;
+        eframe::egui::Grid::new("sensor_modes")
+            .striped(true)
+            .show(ui, |ui| {
+                ui.strong(tr!("sensor_mode.name"));
+                ui.strong(tr!("sensor_mode.resolution"));
+                ui.strong(tr!("sensor_mode.offset"));
+                ui.strong(tr!("sensor_mode.binning"));
+                ui.label("");
+                ui.end_row();
+                for (n, m) in self.modes.iter_mut().enumerate() {
+                    changed |= ui.text_edit_singleline(&mut m.name).changed();
+                    ui.horizontal(|ui| {
+                        for v in &mut m.resolution {
+                            changed |= ui
+                                .add(eframe::egui::DragValue::new(v).range(0..=65535))
+                                .changed();
+                        }
+                    });
+                    ui.horizontal(|ui| {
+                        for v in &mut m.offset {
+                            changed |= ui
+                                .add(eframe::egui::DragValue::new(v).range(0..=65535))
+                                .changed();
+                        }
+                    });
+                    changed |= ui
+                        .add(
+                            eframe::egui::DragValue::new(&mut m.binning)
+                                .range(1..=8)
+                                .suffix("x"),
+                        )
+                        .changed();
+                    if ui.small_button(tr!("sensor_mode.remove")).clicked() {
+                        remove = Some(n);
+                    }
+                    ui.end_row();
+                    let end = [0, 1].map(|i| m.offset[i] + m.resolution[i] * m.binning.max(1));
+                    if end[0] > self.sensor[0] || end[1] > self.sensor[1] {
+                        ui.colored_label(eframe::egui::Color32::YELLOW, tr!("sensor_mode.outside"));
+                        ui.end_row();
+                    }
+                }
+            });
+        if let Some(n) = remove {
+            self.modes.remove(n);
+            changed = true;
+        }
+        if let Some(c) = current.filter(|c| *c != self.sensor) {
+            if ui
+                .add_enabled(
+                    self.modes.iter().all(|m| m.resolution != c),
+                    eframe::egui::Button::new(tr!(
+                        "sensor_mode.add",
+                        resolution = format!("{}x{}", c[0], c[1])
+                    )),
+                )
+                .clicked()
+            {
+                // A mode that divides the sensor evenly is taken to be binned, any other to be a window
+                // centered on the sensor. These are the most common, the user corrects them when they are not right.
+                let binning = (2..=4)
+                    .find(|b| c[0] * b == self.sensor[0] && c[1] * b == self.sensor[1])
+                    .unwrap_or(1);
+                self.modes.push(SensorMode {
+                    name: format!("{}x{}", c[0], c[1]),
+                    resolution: c,
+                    offset: [0, 1].map(|i| self.sensor[i].saturating_sub(c[i] * binning) / 2),
+                    binning,
+                });
+                changed = true;
+            }
+        }
+        changed
+    }
+}
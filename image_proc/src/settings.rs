@@ -0,0 +1,469 @@
+//! Settings that persist between runs of the application
+
+use std::path::{Path, PathBuf};
+
+/// The maximum number of entries in each recent file list
+const MAX_RECENT: usize = 10;
+
+/// Files that have been recently used, most recent first
+#[derive(Clone, Debug, Default, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
+pub struct RecentFiles {
+    pub images: Vec<PathBuf>,
+    pub videos: Vec<PathBuf>,
+    pub calibrations: Vec<PathBuf>,
+}
+
+/// Add a path to the front of a recent file list
+pub fn add_recent(list: &mut Vec<PathBuf>, path: &Path) {
+    list.retain(|p| p != path);
+    list.insert(0, path.to_path_buf());
+    list.truncate(MAX_RECENT);
+}
+
+/// The directory a file dialog should start in, the directory of the most recent file in the list
+pub fn recent_directory(list: &[PathBuf], fallback: &Path) -> PathBuf {
+    list.first()
+        .and_then(|p| p.parent())
+        .map(|p| p.to_path_buf())
+        .unwrap_or_else(|| fallback.to_path_buf())
+}
+
+/// Where generated files are written and what they are named
+#[derive(Clone, Debug, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
+pub struct OutputSettings {
+    /// The directory generated files are written to
+    pub output_directory: PathBuf,
+    /// The directory camera profiles are kept in, file dialogs also start here
+    pub working_directory: PathBuf,
+    /// The filename template for generated charuco boards
+    pub board_template: String,
+    /// The filename template for images of detected charuco corners
+    pub corners_template: String,
+    /// The filename template for calibration results
+    pub calibration_template: String,
+    /// The filename template for stills merged from several frames
+    pub still_template: String,
+    /// The directory name template for frames saved from the ring buffer
+    pub buffer_template: String,
+    /// The directory name template for frames saved when motion is detected
+    pub motion_template: String,
+    /// The filename template for frames captured for calibration
+    pub capture_template: String,
+    /// Write each frame captured for calibration to the output directory
+    pub save_captures: bool,
+    /// Add a number to the names of captures and snapshots that already exist instead of replacing them
+    pub unique_names: bool,
+    /// Write a json file next to saved images recording how they were produced
+    pub sidecars: bool,
+}
+
+impl Default for OutputSettings {
+    fn default() -> Self {
+        Self {
+            output_directory: PathBuf::from("./"),
+            working_directory: PathBuf::from("./"),
+            board_template: "charuco.png".to_string(),
+            corners_template: "charuco_corners.png".to_string(),
+            calibration_template: "calibration_{camera}.bin".to_string(),
+            still_template: "still_{camera}_{timestamp}.png".to_string(),
+            buffer_template: "buffer_{camera}_{timestamp}".to_string(),
+            motion_template: "motion_{camera}_{timestamp}".to_string(),
+            capture_template: "capture_{camera}_{seq}.png".to_string(),
+            save_captures: false,
+            unique_names: true,
+            sidecars: true,
+        }
+    }
+}
+
+impl OutputSettings {
+    /// The placeholders that can be used in filename templates
+    const PLACEHOLDERS: &str = "{camera}, {timestamp}, {iso}, {date}, {time}, {seq}";
+
+    /// Expand a filename template with a sequence number
+    fn expand_with(
+        &self,
+        template: &str,
+        camera: Option<i32>,
+        now: &chrono::DateTime<chrono::Local>,
+        seq: u32,
+    ) -> PathBuf {
+        let camera = camera
+            .map(|c| c.to_string())
+            .unwrap_or_else(|| "none".to_string());
+        let name = template
+            .replace("{camera}", &camera)
+            .replace("{timestamp}", &now.format("%Y%m%d-%H%M%S").to_string())
+            // The basic iso 8601 format, which has no colons so it is a valid filename everywhere
+            .replace("{iso}", &now.format("%Y%m%dT%H%M%S%z").to_string())
+            .replace("{date}", &now.format("%Y-%m-%d").to_string())
+            .replace("{time}", &now.format("%H%M%S").to_string())
+            .replace("{seq}", &format!("{:04}", seq));
+        self.output_directory.join(name)
+    }
+
+    /// Expand a filename template into a path in the output directory.
+    /// {seq} becomes the lowest number that does not name an existing file.
+    pub fn expand(&self, template: &str, camera: Option<i32>) -> PathBuf {
+        let now = chrono::Local::now();
+        let mut seq = 1;
+        loop {
+            let path = self.expand_with(template, camera, &now, seq);
+            if !template.contains("{seq}") || !path.exists() || seq == u32::MAX {
+                return path;
+            }
+            seq += 1;
+        }
+    }
+
+    /// Expand a filename template, creating the output directory if needed
+    pub fn create(&self, template: &str, camera: Option<i32>) -> std::io::Result<PathBuf> {
+        std::fs::create_dir_all(&self.output_directory)?;
+        Ok(self.expand(template, camera))
+    }
+
+    /// Expand a filename template for a capture or snapshot, creating the output directory if needed.
+    /// When unique names are enabled and the file exists, a number is added to the name, like still_2.png.
+    pub fn create_unique(&self, template: &str, camera: Option<i32>) -> std::io::Result<PathBuf> {
+        let path = self.create(template, camera)?;
+        if !self.unique_names || !path.exists() {
+            return Ok(path);
+        }
+        let stem = path
+            .file_stem()
+            .map(|s| s.to_string_lossy().to_string())
+            .unwrap_or_default();
+        let extension = path
+            .extension()
+            .map(|e| format!(".{}", e.to_string_lossy()))
+            .unwrap_or_default();
+        (2..)
+            .map(|n| path.with_file_name(format!("{}_{}{}", stem, n, extension)))
+            .find(|p| !p.exists())
+            .ok_or_else(|| std::io::Error::other("No unused file name"))
+    }
+
+    fn show(&mut self, ui: &mut eframe::egui::Ui) {
+        eframe::egui::Grid::new("output_settings").show(ui, |ui| {
+            for (name, dir) in [
+                (tr!("settings.output_directory"), &mut self.output_directory),
+                (
+                    tr!("settings.working_directory"),
+                    &mut self.working_directory,
+                ),
+            ] {
+                ui.label(name);
+                let mut text = dir.display().to_string();
+                if ui.text_edit_singleline(&mut text).changed() {
+                    *dir = PathBuf::from(text);
+                }
+                if ui.button(tr!("settings.browse")).clicked() {
+                    if let Some(d) = rfd::FileDialog::new().set_directory(&*dir).pick_folder() {
+                        *dir = d;
+                    }
+                }
+                ui.end_row();
+            }
+            for (name, template) in [
+                (tr!("settings.board_template"), &mut self.board_template),
+                (tr!("settings.corners_template"), &mut self.corners_template),
+                (
+                    tr!("settings.calibration_template"),
+                    &mut self.calibration_template,
+                ),
+                (tr!("settings.still_template"), &mut self.still_template),
+                (tr!("settings.buffer_template"), &mut self.buffer_template),
+                (tr!("settings.motion_template"), &mut self.motion_template),
+                (tr!("settings.capture_template"), &mut self.capture_template),
+            ] {
+                ui.label(name);
+                ui.text_edit_singleline(template);
+                ui.end_row();
+            }
+        });
+        ui.label(tr!("settings.placeholders", list = Self::PLACEHOLDERS));
+        ui.checkbox(&mut self.save_captures, tr!("settings.save_captures"));
+        ui.checkbox(&mut self.unique_names, tr!("settings.unique_names"));
+        ui.checkbox(&mut self.sidecars, tr!("settings.sidecars"));
+    }
+}
+
+/// What the camera thread does with a frame when the window has not taken the previous ones yet
+#[derive(Clone, Copy, Debug, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub enum Backpressure {
+    /// Wait until there is room, which slows every camera down to the speed of the window
+    Block,
+    /// Drop the oldest waiting frame, which keeps the latency low
+    DropOldest,
+    /// Drop the new frame
+    DropNewest,
+}
+
+/// The queues between the window and the camera thread
+#[derive(Clone, Debug, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
+pub struct QueueSettings {
+    /// How many commands for the camera thread can wait, used at the next start
+    pub to_camera: usize,
+    /// How many frames and camera events can wait for the window, used at the next start
+    pub from_camera: usize,
+    pub backpressure: Backpressure,
+}
+
+impl Default for QueueSettings {
+    fn default() -> Self {
+        Self {
+            to_camera: 5,
+            from_camera: 5,
+            backpressure: Backpressure::Block,
+        }
+    }
+}
+
+impl QueueSettings {
+    fn show(&mut self, ui: &mut eframe::egui::Ui) {
+        eframe::egui::Grid::new("queue_settings").show(ui, |ui| {
+            ui.label(tr!("settings.to_camera"));
+            ui.add(eframe::egui::DragValue::new(&mut self.to_camera).range(1..=256));
+            ui.end_row();
+            ui.label(tr!("settings.from_camera"));
+            ui.add(eframe::egui::DragValue::new(&mut self.from_camera).range(1..=256));
+            ui.end_row();
+        });
+        ui.label(tr!("settings.queue_restart"));
+        ui.horizontal(|ui| {
+            ui.label(tr!("settings.backpressure"));
+            for (b, name) in [
+                (Backpressure::Block, tr!("settings.block")),
+                (Backpressure::DropOldest, tr!("settings.drop_oldest")),
+                (Backpressure::DropNewest, tr!("settings.drop_newest")),
+            ] {
+                ui.selectable_value(&mut self.backpressure, b, name);
+            }
+        });
+    }
+}
+
+/// Show the options for searching for the board markers
+fn show_detection(
+    ui: &mut eframe::egui::Ui,
+    options: &mut image_proc::calibration::DetectionOptions,
+) {
+    ui.horizontal(|ui| {
+        let mut shrink = options.max_width.is_some();
+        ui.checkbox(&mut shrink, tr!("settings.shrink_detection"));
+        match (shrink, &mut options.max_width) {
+            (true, Some(w)) => {
+                ui.add(
+                    eframe::egui::DragValue::new(w)
+                        .range(320..=8192)
+                        .suffix(" px"),
+                );
+            }
+            (true, w) => *w = Some(1280),
+            (false, w) => *w = None,
+        }
+    });
+    ui.label(tr!("settings.shrink_detection_hint"));
+}
+
+/// The color theme of the user interface
+#[derive(Clone, Copy, Debug, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub enum Theme {
+    /// Follow the operating system
+    System,
+    Dark,
+    Light,
+}
+
+/// How the user interface looks
+#[derive(Clone, Debug, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
+pub struct AppearanceSettings {
+    pub theme: Theme,
+    /// The scale factor applied to the whole user interface
+    pub scale: f32,
+    /// The language code of the user interface, like "en"
+    pub language: String,
+    /// Show the annotations and camera information in the fullscreen mode
+    pub kiosk_overlays: bool,
+}
+
+impl Default for AppearanceSettings {
+    fn default() -> Self {
+        Self {
+            theme: Theme::System,
+            scale: 1.0,
+            language: "en".to_string(),
+            kiosk_overlays: true,
+        }
+    }
+}
+
+impl AppearanceSettings {
+    /// Apply the appearance to the user interface
+    pub fn apply(&self, ctx: &eframe::egui::Context) {
+        ctx.set_theme(match self.theme {
+            Theme::System => eframe::egui::ThemePreference::System,
+            Theme::Dark => eframe::egui::ThemePreference::Dark,
+            Theme::Light => eframe::egui::ThemePreference::Light,
+        });
+        ctx.set_zoom_factor(self.scale);
+        rust_i18n::set_locale(&self.language);
+    }
+
+    /// Show the appearance settings, returns true when they changed
+    fn show(&mut self, ui: &mut eframe::egui::Ui) -> bool {
+        let mut changed = false;
+        ui.horizontal(|ui| {
+            ui.label(tr!("settings.language"));
+            // Each language names itself, so that it can be found without understanding the current one
+            let name = |l: &str| rust_i18n::t!("language.name", locale = l).into_owned();
+            eframe::egui::ComboBox::from_id_salt("language")
+                .selected_text(name(&self.language))
+                .show_ui(ui, |ui| {
+                    for l in rust_i18n::available_locales!() {
+                        changed |= ui
+                            .selectable_value(&mut self.language, l.to_string(), name(l))
+                            .changed();
+                    }
+                });
+        });
+        ui.horizontal(|ui| {
+            ui.label(tr!("settings.theme"));
+            for (t, name) in [
+                (Theme::System, tr!("settings.theme_system")),
+                (Theme::Dark, tr!("settings.theme_dark")),
+                (Theme::Light, tr!("settings.theme_light")),
+            ] {
+                changed |= ui.selectable_value(&mut self.theme, t, name).changed();
+            }
+        });
+        ui.horizontal(|ui| {
+            ui.label(tr!("settings.scale"));
+            for p in [1.0, 1.5, 2.0, 3.0] {
+                if ui
+                    .selectable_label(self.scale == p, format!("{}x", p))
+                    .clicked()
+                {
+                    self.scale = p;
+                    changed = true;
+                }
+            }
+            // Only apply the custom scale when editing is done, otherwise the widget moves while dragging
+            let r = ui.add(
+                eframe::egui::DragValue::new(&mut self.scale)
+                    .range(0.5..=4.0)
+                    .speed(0.01)
+                    .suffix("x"),
+            );
+            changed |= r.drag_stopped() || r.lost_focus();
+        });
+        ui.checkbox(&mut self.kiosk_overlays, tr!("settings.kiosk_overlays"));
+        changed
+    }
+}
+
+/// All of the persistent settings
+#[derive(Clone, Debug, Default, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
+pub struct Settings {
+    pub recent: RecentFiles,
+    pub output: OutputSettings,
+    pub appearance: AppearanceSettings,
+    /// The calibration board in use
+    pub board: crate::board::BoardParams,
+    /// Uploading of calibration results
+    pub backup: crate::backup::BackupSettings,
+    /// Notification of completed calibrations
+    pub webhook: crate::webhook::WebhookSettings,
+    /// The folders of the watch folder mode
+    pub watch: crate::watch::WatchSettings,
+    /// How captures are signalled
+    pub feedback: crate::feedback::FeedbackSettings,
+    /// The buttons of the gamepad
+    pub gamepad: crate::gamepad::GamepadSettings,
+    /// The named camera setups
+    pub presets: Vec<crate::presets::SetupPreset>,
+    /// The color profiles of the monitor and exported images
+    pub color: crate::color_management::ColorSettings,
+    /// Signing of saved calibrations and the keys trusted when loading them
+    pub signing: crate::signing::SigningSettings,
+    /// The queues of the camera thread
+    pub queues: QueueSettings,
+    /// How the markers of the board are searched for
+    pub detection: image_proc::calibration::DetectionOptions,
+    /// Shrinking the preview when the window can not keep up
+    pub preview: crate::profiler::AdaptivePreviewSettings,
+    /// The serial thermometer read for the temperature of calibrations
+    pub thermometer: crate::temperature::ThermometerSettings,
+    /// When cameras are due for recalibration
+    pub reminders: crate::reminders::ReminderSettings,
+    /// The lens model cameras are calibrated with
+    pub camera_model: image_proc::calibration::CameraModel,
+    /// The library cameras are calibrated with
+    pub backend: image_proc::calibration::Backend,
+}
+
+impl Settings {
+    /// The key the settings are stored under
+    const KEY: &str = "settings";
+
+    /// Load the settings from storage, or the defaults when there are none
+    pub fn load(storage: Option<&dyn eframe::Storage>) -> Self {
+        storage
+            .and_then(|s| eframe::get_value(s, Self::KEY))
+            .unwrap_or_default()
+    }
+
+    /// Save the settings to storage
+    pub fn save(&self, storage: &mut dyn eframe::Storage) {
+        eframe::set_value(storage, Self::KEY, self);
+    }
+
+    /// Show the settings page
+    pub fn show(&mut self, ui: &mut eframe::egui::Ui) {
+        ui.heading(tr!("settings.appearance"));
+        if self.appearance.show(ui) {
+            self.appearance.apply(ui.ctx());
+        }
+        ui.separator();
+        ui.heading(tr!("settings.feedback"));
+        self.feedback.show(ui);
+        ui.separator();
+        ui.heading(tr!("settings.gamepad"));
+        self.gamepad.show(ui);
+        ui.separator();
+        ui.heading(tr!("settings.output"));
+        self.output.show(ui);
+        ui.separator();
+        ui.heading(tr!("settings.queues"));
+        self.queues.show(ui);
+        ui.separator();
+        ui.heading(tr!("settings.preview"));
+        self.preview.show(ui);
+        ui.separator();
+        ui.heading(tr!("settings.detection"));
+        show_detection(ui, &mut self.detection);
+        ui.separator();
+        ui.heading(tr!("settings.thermometer"));
+        self.thermometer.show(ui);
+        ui.separator();
+        ui.heading(tr!("settings.reminders"));
+        self.reminders.show(ui);
+        ui.separator();
+        ui.heading(tr!("settings.color"));
+        self.color.show(ui);
+        ui.separator();
+        ui.heading(tr!("settings.signing"));
+        self.signing.show(ui);
+        ui.separator();
+        ui.heading(tr!("settings.backup"));
+        self.backup.show(ui);
+        ui.separator();
+        ui.heading(tr!("settings.webhook"));
+        self.webhook.show(ui);
+    }
+}
@@ -0,0 +1,52 @@
+//! Json files saved next to snapshots, recording how each image was produced for later analysis
+
+use std::path::{Path, PathBuf};
+
+/// The exposure related settings in effect when an image was captured
+#[derive(Clone, Debug, serde::Serialize)]
+pub struct ExposureSettings {
+    /// The manual exposure requested from the camera in the units of the backend, None is automatic exposure
+    pub camera: Option<f64>,
+    /// How frames were averaged before the image was made
+    pub averaging: crate::averaging::Averaging,
+    /// True when frames were averaged in linear light
+    pub linear_averaging: bool,
+}
+
+/// How a snapshot was produced
+#[derive(Clone, Debug, serde::Serialize)]
+pub struct FrameMetadata {
+    /// When the image was saved, in rfc 3339 format
+    pub timestamp: String,
+    /// The id of the source, negative for videos and other sources that are not numbered cameras
+    pub camera: Option<i32>,
+    /// The name of the camera or file as shown to the user
+    pub source: String,
+    /// The name the camera device reports
+    pub device: Option<String>,
+    pub exposure: ExposureSettings,
+    /// The id of the calibration that removed the lens distortion, None when the image is not undistorted.
+    /// It matches the checksum stored in the calibration file.
+    pub calibration: Option<String>,
+    /// The enabled processing stages and their settings, None when the image was not processed
+    pub pipeline: Option<serde_json::Value>,
+    /// How the image was presented, like a false color view
+    pub view: String,
+    /// The icc profile the image was converted to, None is srgb
+    pub color_profile: Option<PathBuf>,
+}
+
+impl FrameMetadata {
+    /// The sidecar file of an image, the name of the image with .json added
+    pub fn path(image: &Path) -> PathBuf {
+        let mut name = image.as_os_str().to_owned();
+        name.push(".json");
+        PathBuf::from(name)
+    }
+
+    /// Write the sidecar file of an image
+    pub fn save(&self, image: &Path) -> std::io::Result<()> {
+        let c = serde_json::to_string_pretty(self).map_err(std::io::Error::other)?;
+        std::fs::write(Self::path(image), c)
+    }
+}
@@ -0,0 +1,143 @@
+//! Signing saved calibrations with a private key, and the public keys trusted when loading them
+
+use std::path::{Path, PathBuf};
+
+use ed25519_dalek::SigningKey;
+use image_proc::integrity::{self, Trust};
+
+/// The signing settings
+#[derive(Clone, Debug, Default, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
+pub struct SigningSettings {
+    /// The file holding the private key calibrations are signed with, None saves them unsigned
+    pub key_file: Option<PathBuf>,
+    /// The public keys of trusted signers, in hex
+    pub trusted_keys: Vec<String>,
+    /// Refuse to load calibrations that are not signed by a trusted key
+    pub require_signature: bool,
+    /// The public key being entered
+    #[serde(skip)]
+    new_key: String,
+    /// The problem with the key file or the entered key
+    #[serde(skip)]
+    error: Option<String>,
+}
+
+/// Read a private key file, which holds the 32 byte seed of the key in hex
+fn read_key(path: &Path) -> Result<SigningKey, String> {
+    let text = std::fs::read_to_string(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+    integrity::key_from_hex(&text)
+        .map(|seed| SigningKey::from_bytes(&seed))
+        .ok_or_else(|| tr!("signing.invalid_key_file", path = path.display()))
+}
+
+/// Write a new random private key to a file that only the user can read
+fn generate_key(path: &Path) -> Result<SigningKey, String> {
+    let mut seed = [0u8; 32];
+    getrandom::fill(&mut seed).map_err(|e| e.to_string())?;
+    let mut options = std::fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+    let mut f = options.open(path).map_err(|e| e.to_string())?;
+    std::io::Write::write_all(&mut f, integrity::to_hex(&seed).as_bytes())
+        .map_err(|e| e.to_string())?;
+    Ok(SigningKey::from_bytes(&seed))
+}
+
+impl SigningSettings {
+    /// The key to sign with, None when signing is not set up
+    pub fn key(&self) -> Result<Option<SigningKey>, String> {
+        self.key_file.as_deref().map(read_key).transpose()
+    }
+
+    /// The signatures accepted when loading, keys that are not valid are left out
+    pub fn trust(&self) -> Trust {
+        Trust {
+            keys: self
+                .trusted_keys
+                .iter()
+                .filter_map(|k| integrity::verifying_key_from_hex(k))
+                .collect(),
+            require_signature: self.require_signature,
+        }
+    }
+
+    /// Show the signing settings for editing
+    pub fn show(&mut self, ui: &mut eframe::egui::Ui) {
+        ui.horizontal(|ui| {
+            ui.label(tr!("signing.key_file"));
+            let name = self
+                .key_file
+                .as_ref()
+                .map(|f| f.display().to_string())
+                .unwrap_or_else(|| tr!("signing.unsigned"));
+            ui.label(name);
+            if ui.button(tr!("settings.browse")).clicked() {
+                if let Some(f) = rfd::FileDialog::new().pick_file() {
+                    self.error = read_key(&f).err();
+                    self.key_file = Some(f);
+                }
+            }
+            if ui.button(tr!("signing.generate")).clicked() {
+                if let Some(f) = rfd::FileDialog::new()
+                    .set_file_name("calibration_signing.key")
+                    .save_file()
+                {
+                    match generate_key(&f) {
+                        Ok(_) => {
+                            self.error = None;
+                            self.key_file = Some(f);
+                        }
+                        Err(e) => self.error = Some(e),
+                    }
+                }
+            }
+            if self.key_file.is_some() && ui.button(tr!("signing.stop")).clicked() {
+                self.key_file = None;
+                self.error = None;
+            }
+        });
+        if let Ok(Some(k)) = self.key() {
+            ui.horizontal(|ui| {
+                let public = integrity::to_hex(&k.verifying_key().to_bytes());
+                ui.label(tr!("signing.public_key", key = public));
+                if ui.button(tr!("signing.copy")).clicked() {
+                    ui.ctx().copy_text(public);
+                }
+            });
+        }
+        ui.label(tr!("signing.trusted_keys"));
+        let mut remove = None;
+        for (i, k) in self.trusted_keys.iter().enumerate() {
+            ui.horizontal(|ui| {
+                ui.monospace(k);
+                if ui.button(tr!("signing.remove")).clicked() {
+                    remove = Some(i);
+                }
+            });
+        }
+        if let Some(i) = remove {
+            self.trusted_keys.remove(i);
+        }
+        ui.horizontal(|ui| {
+            ui.text_edit_singleline(&mut self.new_key);
+            if ui.button(tr!("signing.add")).clicked() {
+                let key = self.new_key.trim().to_lowercase();
+                if integrity::verifying_key_from_hex(&key).is_some() {
+                    if !self.trusted_keys.contains(&key) {
+                        self.trusted_keys.push(key);
+                    }
+                    self.new_key.clear();
+                    self.error = None;
+                } else {
+                    self.error = Some(tr!("signing.invalid_public_key"));
+                }
+            }
+        });
+        ui.checkbox(&mut self.require_signature, tr!("signing.require"));
+        if let Some(e) = &self.error {
+            ui.colored_label(eframe::egui::Color32::RED, e);
+        }
+    }
+}
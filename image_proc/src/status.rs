@@ -0,0 +1,205 @@
+//! Reporting what the application is doing, with short lived notifications and the frame rate of cameras
+
+use std::{
+    collections::VecDeque,
+    time::{Duration, Instant},
+};
+
+/// The outcome of a task run in the background, as a message for the user
+pub type TaskResult = Result<String, String>;
+
+/// How important a notification is
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ToastLevel {
+    Info,
+    Error,
+}
+
+impl ToastLevel {
+    /// How long notifications of this level are shown
+    fn duration(&self) -> Duration {
+        match self {
+            ToastLevel::Info => Duration::from_secs(4),
+            ToastLevel::Error => Duration::from_secs(10),
+        }
+    }
+
+    fn color(&self) -> eframe::egui::Color32 {
+        match self {
+            ToastLevel::Info => eframe::egui::Color32::LIGHT_GREEN,
+            ToastLevel::Error => eframe::egui::Color32::LIGHT_RED,
+        }
+    }
+}
+
+/// A single notification
+struct Toast {
+    level: ToastLevel,
+    message: String,
+    created: Instant,
+}
+
+/// Notifications shown in the corner of the window for a few seconds
+#[derive(Default)]
+pub struct Toasts {
+    toasts: Vec<Toast>,
+}
+
+impl Toasts {
+    /// The most notifications shown at once, older ones are dropped
+    const MAX: usize = 5;
+
+    fn add(&mut self, level: ToastLevel, message: String) {
+        self.toasts.push(Toast {
+            level,
+            message,
+            created: Instant::now(),
+        });
+        if self.toasts.len() > Self::MAX {
+            self.toasts.remove(0);
+        }
+    }
+
+    /// Tell the user that something worked
+    pub fn info(&mut self, message: impl Into<String>) {
+        self.add(ToastLevel::Info, message.into());
+    }
+
+    /// Tell the user that something went wrong, the message is also printed to the console
+    pub fn error(&mut self, message: impl Into<String>) {
+        let message = message.into();
+        println!("{}", message);
+        self.add(ToastLevel::Error, message);
+    }
+
+    /// Show the notifications, clicking one dismisses it
+    pub fn show(&mut self, ctx: &eframe::egui::Context) {
+        self.toasts
+            .retain(|t| t.created.elapsed() < t.level.duration());
+        if self.toasts.is_empty() {
+            return;
+        }
+        let mut dismissed = None;
+        eframe::egui::Area::new(eframe::egui::Id::new("toasts"))
+            .anchor(eframe::egui::Align2::RIGHT_BOTTOM, [-10.0, -40.0])
+            .order(eframe::egui::Order::Foreground)
+            .show(ctx, |ui| {
+                for (i, t) in self.toasts.iter().enumerate() {
+                    eframe::egui::Frame::popup(ui.style()).show(ui, |ui| {
+                        let text = eframe::egui::RichText::new(&t.message).color(t.level.color());
+                        if ui
+                            .add(eframe::egui::Label::new(text).sense(eframe::egui::Sense::click()))
+                            .clicked()
+                        {
+                            dismissed = Some(i);
+                        }
+                    });
+                }
+            });
+        if let Some(i) = dismissed {
+            self.toasts.remove(i);
+        }
+        ctx.request_repaint_after(Duration::from_millis(250));
+    }
+}
+
+/// Measures how often frames arrive from a camera
+#[derive(Default)]
+pub struct FrameRate {
+    times: VecDeque<Instant>,
+}
+
+impl FrameRate {
+    /// The frame rate is averaged over this long
+    const WINDOW: Duration = Duration::from_secs(2);
+
+    /// Record the arrival of a frame
+    pub fn add_frame(&mut self) {
+        let now = Instant::now();
+        self.times.push_back(now);
+        while let Some(t) = self.times.front() {
+            if now.duration_since(*t) > Self::WINDOW {
+                self.times.pop_front();
+            } else {
+                break;
+            }
+        }
+    }
+
+    /// The current frame rate in frames per second, if frames are still arriving
+    pub fn fps(&self) -> Option<f64> {
+        let first = self.times.front()?;
+        let last = self.times.back()?;
+        if last.elapsed() > Self::WINDOW || self.times.len() < 2 {
+            return None;
+        }
+        let span = last.duration_since(*first).as_secs_f64();
+        if span > 0.0 {
+            Some((self.times.len() - 1) as f64 / span)
+        } else {
+            None
+        }
+    }
+}
+
+/// A task running in another thread that can be cancelled from the status bar
+struct BackgroundTask {
+    name: String,
+    cancel: image_proc::cancel::CancelToken,
+}
+
+/// The long running tasks, shown in the status bar with a button to cancel each
+#[derive(Default)]
+pub struct BackgroundTasks {
+    tasks: Vec<BackgroundTask>,
+}
+
+impl BackgroundTasks {
+    /// Register a task, the returned token is given to the task.
+    /// The task is forgotten once it has dropped every copy of the token.
+    pub fn start(&mut self, name: String) -> image_proc::cancel::CancelToken {
+        let cancel = image_proc::cancel::CancelToken::new();
+        self.tasks.push(BackgroundTask {
+            name,
+            cancel: cancel.clone(),
+        });
+        cancel
+    }
+
+    /// Wait for the tasks to end, returns false when some were still running at the deadline
+    pub fn wait(&mut self, deadline: std::time::Instant) -> bool {
+        loop {
+            self.remove_finished();
+            if self.tasks.is_empty() {
+                return true;
+            }
+            if std::time::Instant::now() >= deadline {
+                return false;
+            }
+            std::thread::sleep(std::time::Duration::from_millis(10));
+        }
+    }
+
+    /// Forget the tasks that have ended
+    pub fn remove_finished(&mut self) {
+        self.tasks.retain(|t| !t.cancel.is_finished());
+    }
+
+    /// Show the running tasks in the status bar
+    pub fn show(&self, ui: &mut eframe::egui::Ui) {
+        for t in &self.tasks {
+            ui.separator();
+            ui.spinner();
+            ui.label(&t.name);
+            if ui
+                .add_enabled(
+                    !t.cancel.is_cancelled(),
+                    eframe::egui::Button::new(tr!("main.cancel")),
+                )
+                .clicked()
+            {
+                t.cancel.cancel();
+            }
+        }
+    }
+}
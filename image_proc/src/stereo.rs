@@ -0,0 +1,395 @@
+//! Calibration of a pair of cameras looking at the same scene
+
+use std::{borrow::Borrow, path::Path};
+
+use opencv::{
+    calib3d::StereoMatcherTrait,
+    core::{FileStorageTrait, FileStorageTraitConst, MatTraitConst},
+};
+
+use crate::aruco::{self, CharucoBoard, Dictionary};
+use crate::calibration::{CalibrationData, SaveableOpencvMat, detect_charuco};
+pub use crate::calibration::{StereoCalibration, StereoSide};
+use crate::linalg::{inverse3, mul3, transpose3};
+
+/// The transforms that make the epipolar lines of a stereo pair horizontal
+pub struct Rectification {
+    /// The rotations of the left and right cameras into the rectified coordinates
+    pub rotations: [opencv::core::Mat; 2],
+    /// The projection matrices of the left and right cameras in the rectified coordinates
+    pub projections: [opencv::core::Mat; 2],
+    /// The disparity to depth matrix, row by row
+    pub q: [f64; 16],
+    /// The x and y maps for remapping the left and right images
+    pub maps: [[opencv::core::Mat; 2]; 2],
+    /// The width and height of the images
+    pub size: [u32; 2],
+}
+
+impl Rectification {
+    /// Rectify an image of one of the cameras
+    pub fn remap(
+        &self,
+        side: StereoSide,
+        img: &opencv::core::Mat,
+    ) -> opencv::Result<opencv::core::Mat> {
+        let [x, y] = match side {
+            StereoSide::Left => &self.maps[0],
+            StereoSide::Right => &self.maps[1],
+        };
+        let mut out = opencv::core::Mat::default();
+        opencv::imgproc::remap_def(img, &mut out, x, y, opencv::imgproc::INTER_LINEAR)?;
+        Ok(out)
+    }
+
+    /// The disparity in pixels of every pixel of the rectified left image, found with semi global block matching.
+    /// Pixels without a match have a negative disparity.
+    pub fn disparity(
+        &self,
+        left: &opencv::core::Mat,
+        right: &opencv::core::Mat,
+    ) -> opencv::Result<opencv::core::Mat> {
+        let mut gray = [opencv::core::Mat::default(), opencv::core::Mat::default()];
+        for (img, g) in [left, right].into_iter().zip(&mut gray) {
+            if img.channels() == 1 {
+                *g = img.clone();
+            } else {
+                opencv::imgproc::cvt_color_def(img, g, opencv::imgproc::COLOR_BGR2GRAY)?;
+            }
+        }
+        let block = 5;
+        let mut matcher = opencv::calib3d::StereoSGBM::create(
+            0,
+            128,
+            block,
+            8 * block * block,
+            32 * block * block,
+            1,
+            63,
+            10,
+            100,
+            2,
+            opencv::calib3d::StereoSGBM_MODE_SGBM,
+        )?;
+        let mut fixed = opencv::core::Mat::default();
+        matcher.compute(&gray[0], &gray[1], &mut fixed)?;
+        // The matcher gives sixteenths of a pixel
+        let mut disparity = opencv::core::Mat::default();
+        fixed.convert_to(&mut disparity, opencv::core::CV_32F, 1.0 / 16.0, 0.0)?;
+        Ok(disparity)
+    }
+
+    /// The distance along the optical axis of the rectified left camera to the point seen at a pixel with a disparity,
+    /// in the units of the board. None when the disparity is not a match.
+    pub fn depth(&self, pixel: [f64; 2], disparity: f64) -> Option<f64> {
+        if disparity <= 0.0 {
+            return None;
+        }
+        let v = [pixel[0], pixel[1], disparity, 1.0];
+        let row = |r: usize| (0..4).map(|c| self.q[r * 4 + c] * v[c]).sum::<f64>();
+        let w = row(3);
+        (w != 0.0).then(|| row(2) / w).filter(|z| *z > 0.0)
+    }
+
+    /// Save the maps and matrices to an opencv yaml file
+    pub fn save_yaml(&self, path: &Path) -> opencv::Result<()> {
+        let mut fs = opencv::core::FileStorage::new_def(
+            &path.to_string_lossy(),
+            opencv::core::FileStorage_Mode::WRITE as i32,
+        )?;
+        fs.write_i32("image_width", self.size[0] as i32)?;
+        fs.write_i32("image_height", self.size[1] as i32)?;
+        fs.write_mat("R1", &self.rotations[0])?;
+        fs.write_mat("R2", &self.rotations[1])?;
+        fs.write_mat("P1", &self.projections[0])?;
+        fs.write_mat("P2", &self.projections[1])?;
+        let q: opencv::core::Mat = SaveableOpencvMat::from_values(4, 4, &self.q).into();
+        fs.write_mat("Q", &q)?;
+        fs.write_mat("left_map_x", &self.maps[0][0])?;
+        fs.write_mat("left_map_y", &self.maps[0][1])?;
+        fs.write_mat("right_map_x", &self.maps[1][0])?;
+        fs.write_mat("right_map_y", &self.maps[1][1])?;
+        if fs.is_opened()? {
+            fs.release()?;
+        }
+        Ok(())
+    }
+}
+
+/// True when both cameras of a pair were calibrated with the fisheye model, false when both were with the pinhole model.
+/// Other pairs can not be calibrated or rectified together.
+fn fisheye_pair(left: &CalibrationData, right: &CalibrationData) -> opencv::Result<bool> {
+    let fisheye = |cd: &CalibrationData| match cd {
+        CalibrationData::OpenCvCharuco(_) | CalibrationData::Native(_) => Some(false),
+        CalibrationData::OpenCvFisheye(_) => Some(true),
+        CalibrationData::Stereo(_) | CalibrationData::Telecentric(_) => None,
+    };
+    match (fisheye(left), fisheye(right)) {
+        (Some(l), Some(r)) if l == r => Ok(l),
+        _ => Err(opencv::Error::new(
+            opencv::core::StsBadArg,
+            "Only two pinhole or two fisheye cameras can be used as a stereo pair",
+        )),
+    }
+}
+
+/// The essential and fundamental matrices of a pair from the pose of the right camera relative to the left,
+/// the fundamental matrix is for pixels undistorted with the camera matrices of the cameras
+fn epipolar(
+    rotation: &[f64; 9],
+    translation: &[f64; 3],
+    left: &CalibrationData,
+    right: &CalibrationData,
+) -> opencv::Result<([f64; 9], [f64; 9])> {
+    let [x, y, z] = *translation;
+    let essential = mul3(&[0.0, -z, y, z, 0.0, -x, -y, x, 0.0], rotation);
+    let inverse = |cd: &CalibrationData| {
+        cd.camera_matrix()
+            .values()
+            .try_into()
+            .ok()
+            .and_then(|k: [f64; 9]| inverse3(&k))
+            .ok_or_else(|| {
+                opencv::Error::new(opencv::core::StsBadArg, "The camera matrix is not valid")
+            })
+    };
+    let fundamental = mul3(
+        &mul3(&transpose3(&inverse(right)?), &essential),
+        &inverse(left)?,
+    );
+    Ok((essential, fundamental))
+}
+
+/// The elements of a 64 bit floating point matrix with N elements
+fn mat_values<const N: usize>(m: opencv::core::Mat) -> opencv::Result<[f64; N]> {
+    SaveableOpencvMat::from(m).values().try_into().map_err(|_| {
+        opencv::Error::new(
+            opencv::core::StsBadSize,
+            "The stereo calibration returned a matrix of the wrong size",
+        )
+    })
+}
+
+impl StereoCalibration {
+    /// Load a stereo calibration from a file, checking the checksum and signature against the trusted keys.
+    /// Files saved before stereo calibrations were stored as calibration data are read too.
+    pub fn load(path: &Path, trust: &crate::integrity::Trust) -> Option<Self> {
+        let c = std::fs::read(path).ok()?;
+        let (c, _) = crate::integrity::unseal(&c, trust).ok()?;
+        match bincode::serde::decode_from_slice(&c, bincode::config::standard()) {
+            Ok((CalibrationData::Stereo(s), _)) => Some(s),
+            _ => bincode::serde::decode_from_slice(&c, bincode::config::standard())
+                .ok()
+                .map(|(s, _)| s),
+        }
+    }
+
+    /// Save the stereo calibration to a file as calibration data, with a checksum
+    pub fn save(&self, path: &Path) -> std::io::Result<()> {
+        CalibrationData::Stereo(self.clone()).save(path)
+    }
+
+    /// Compute the rectification of the pair
+    pub fn rectification(&self) -> opencv::Result<Rectification> {
+        let size = opencv::core::Size {
+            width: self.resolution[0] as i32,
+            height: self.resolution[1] as i32,
+        };
+        let k1: opencv::core::Mat = self.left.camera_matrix().clone().into();
+        let d1: opencv::core::Mat = self.left.distortion().clone().into();
+        let k2: opencv::core::Mat = self.right.camera_matrix().clone().into();
+        let d2: opencv::core::Mat = self.right.distortion().clone().into();
+        let r: opencv::core::Mat = SaveableOpencvMat::from_values(3, 3, &self.rotation).into();
+        let t: opencv::core::Mat = SaveableOpencvMat::from_values(1, 3, &self.translation).into();
+        let mut r1 = opencv::core::Mat::default();
+        let mut r2 = opencv::core::Mat::default();
+        let mut p1 = opencv::core::Mat::default();
+        let mut p2 = opencv::core::Mat::default();
+        let mut q = opencv::core::Mat::default();
+        let fisheye = fisheye_pair(&self.left, &self.right)?;
+        if fisheye {
+            opencv::calib3d::fisheye_stereo_rectify_def(
+                &k1,
+                &d1,
+                &k2,
+                &d2,
+                size,
+                &r,
+                &t,
+                &mut r1,
+                &mut r2,
+                &mut p1,
+                &mut p2,
+                &mut q,
+                opencv::calib3d::CALIB_ZERO_DISPARITY,
+            )?;
+        } else {
+            opencv::calib3d::stereo_rectify_def(
+                &k1, &d1, &k2, &d2, size, &r, &t, &mut r1, &mut r2, &mut p1, &mut p2, &mut q,
+            )?;
+        }
+        let mut maps: [[opencv::core::Mat; 2]; 2] = Default::default();
+        for ((k, d, rot, p), m) in [(&k1, &d1, &r1, &p1), (&k2, &d2, &r2, &p2)]
+            .into_iter()
+            .zip(&mut maps)
+        {
+            let [x, y] = m;
+            if fisheye {
+                opencv::calib3d::fisheye_init_undistort_rectify_map(
+                    k,
+                    d,
+                    rot,
+                    p,
+                    size,
+                    opencv::core::CV_32FC1,
+                    x,
+                    y,
+                )?;
+            } else {
+                opencv::calib3d::init_undistort_rectify_map(
+                    k,
+                    d,
+                    rot,
+                    p,
+                    size,
+                    opencv::core::CV_32FC1,
+                    x,
+                    y,
+                )?;
+            }
+        }
+        Ok(Rectification {
+            rotations: [r1, r2],
+            projections: [p1, p2],
+            q: mat_values(q)?,
+            maps,
+            size: self.resolution,
+        })
+    }
+}
+
+/// Calibrate the relative position of two cameras from pairs of images of a charuco board,
+/// taken at the same time by both cameras. The calibrations of the cameras themselves are kept.
+/// Both cameras must have been calibrated with the pinhole model or both with the fisheye model.
+pub fn calibrate_stereo_charuco<M: Borrow<opencv::core::Mat>>(
+    pairs: &[(M, M)],
+    left: &CalibrationData,
+    right: &CalibrationData,
+    board: &CharucoBoard,
+    dictionary: &Dictionary,
+) -> opencv::Result<StereoCalibration> {
+    let Some(first): Option<&opencv::core::Mat> = pairs.first().map(|(l, _)| l.borrow()) else {
+        return Err(opencv::Error::new(
+            opencv::core::StsBadArg,
+            "There are no image pairs to calibrate with",
+        ));
+    };
+    let fisheye = fisheye_pair(left, right)?;
+    let board_corners = aruco::chessboard_corners(board)?;
+    let mut object_points: opencv::core::Vector<opencv::core::Vector<opencv::core::Point3f>> =
+        Default::default();
+    let mut left_points: opencv::core::Vector<opencv::core::Vector<opencv::core::Point2f>> =
+        Default::default();
+    let mut right_points: opencv::core::Vector<opencv::core::Vector<opencv::core::Point2f>> =
+        Default::default();
+    for (l, r) in pairs {
+        let (lc, li) = detect_charuco(l.borrow(), board, dictionary)?;
+        let (rc, ri) = detect_charuco(r.borrow(), board, dictionary)?;
+        let mut o = opencv::core::Vector::new();
+        let mut a = opencv::core::Vector::new();
+        let mut b = opencv::core::Vector::new();
+        // Only the corners seen by both cameras are used
+        for (j, id) in li.iter().enumerate() {
+            if let Some(k) = ri.iter().position(|x| x == id) {
+                o.push(board_corners.get(id as usize)?);
+                a.push(lc.get(j)?);
+                b.push(rc.get(k)?);
+            }
+        }
+        if o.len() >= 6 {
+            object_points.push(o);
+            left_points.push(a);
+            right_points.push(b);
+        }
+    }
+    if object_points.is_empty() {
+        return Err(opencv::Error::new(
+            opencv::core::StsBadArg,
+            "The board was not seen by both cameras in any of the image pairs",
+        ));
+    }
+    let size = opencv::core::Size {
+        width: first.cols(),
+        height: first.rows(),
+    };
+    let mut k1: opencv::core::Mat = left.camera_matrix().clone().into();
+    let mut d1: opencv::core::Mat = left.distortion().clone().into();
+    let mut k2: opencv::core::Mat = right.camera_matrix().clone().into();
+    let mut d2: opencv::core::Mat = right.distortion().clone().into();
+    let mut r = opencv::core::Mat::default();
+    let mut t = opencv::core::Mat::default();
+    let mut e = opencv::core::Mat::default();
+    let mut f = opencv::core::Mat::default();
+    let criteria = opencv::core::TermCriteria {
+        typ: opencv::core::TermCriteria_Type::EPS as i32
+            + opencv::core::TermCriteria_Type::COUNT as i32,
+        max_count: 30,
+        epsilon: 1e-6,
+    };
+    let (rms, rotation, translation, essential, fundamental) = if fisheye {
+        let rms = opencv::calib3d::fisheye_stereo_calibrate(
+            &object_points,
+            &left_points,
+            &right_points,
+            &mut k1,
+            &mut d1,
+            &mut k2,
+            &mut d2,
+            size,
+            &mut r,
+            &mut t,
+            &mut opencv::core::no_array(),
+            &mut opencv::core::no_array(),
+            opencv::calib3d::fisheye_CALIB_FIX_INTRINSIC,
+            criteria,
+        )?;
+        let (rotation, translation) = (mat_values(r)?, mat_values(t)?);
+        // The fisheye calibration only finds the pose of the right camera
+        let (essential, fundamental) = epipolar(&rotation, &translation, left, right)?;
+        (rms, rotation, translation, essential, fundamental)
+    } else {
+        let rms = opencv::calib3d::stereo_calibrate(
+            &object_points,
+            &left_points,
+            &right_points,
+            &mut k1,
+            &mut d1,
+            &mut k2,
+            &mut d2,
+            size,
+            &mut r,
+            &mut t,
+            &mut e,
+            &mut f,
+            opencv::calib3d::CALIB_FIX_INTRINSIC,
+            criteria,
+        )?;
+        (
+            rms,
+            mat_values(r)?,
+            mat_values(t)?,
+            mat_values(e)?,
+            mat_values(f)?,
+        )
+    };
+    Ok(StereoCalibration {
+        left: Box::new(left.clone()),
+        right: Box::new(right.clone()),
+        rotation,
+        translation,
+        essential,
+        fundamental,
+        rms,
+        resolution: [size.width as u32, size.height as u32],
+    })
+}
@@ -0,0 +1,454 @@
+//! Calibrating a pair of cameras and checking the result with epipolar lines
+
+use std::time::{Duration, Instant};
+
+use image_proc::{
+    calibration::CalibrationDataTrait,
+    frame::Frame,
+    stereo::{Rectification, StereoCalibration, StereoSide},
+};
+use opencv::core::{MatTraitConst, MatTraitConstManual};
+
+use crate::depth::DepthImage;
+
+/// What the user asked for in the stereo window
+pub enum StereoAction {
+    Calibrate,
+    Save,
+    Load,
+    /// Save the rectification maps for use by other programs
+    ExportRectification,
+    /// Measuring the depth where the user clicked failed
+    DepthFailed(String),
+}
+
+/// What to do with a frame that arrived while a pair is being captured
+pub enum PairCapture {
+    /// The newest frames of both cameras were taken close enough together to be a pair
+    Take,
+    /// The cameras did not send frames close enough together in time
+    TimedOut,
+}
+
+/// How the images of the pair are shown
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum StereoView {
+    /// The undistorted images, clicking one draws the epipolar line in the other
+    #[default]
+    Epipolar,
+    /// The rectified images next to each other with horizontal lines across both
+    SideBySide,
+    /// The rectified images on top of each other, the left in red and the right in cyan
+    Anaglyph,
+    /// The rectified left image and the depth from the disparity, clicking measures the depth
+    Depth,
+}
+
+impl StereoView {
+    const ALL: [StereoView; 4] = [
+        StereoView::Epipolar,
+        StereoView::SideBySide,
+        StereoView::Anaglyph,
+        StereoView::Depth,
+    ];
+
+    fn name(&self) -> String {
+        match self {
+            StereoView::Epipolar => tr!("stereo.view_epipolar"),
+            StereoView::SideBySide => tr!("stereo.view_side_by_side"),
+            StereoView::Anaglyph => tr!("stereo.view_anaglyph"),
+            StereoView::Depth => tr!("stereo.view_depth"),
+        }
+    }
+}
+
+/// The cameras of a stereo pair and their calibration
+#[derive(Default)]
+pub struct StereoRig {
+    pub left: Option<i32>,
+    pub right: Option<i32>,
+    /// Images of the board taken by both cameras
+    pub pairs: Vec<(Frame, Frame)>,
+    calibration: Option<StereoCalibration>,
+    /// The rectification of the calibration, None when it could not be computed
+    rectification: Option<Rectification>,
+    view: StereoView,
+    /// The depth from the last measurement
+    depth: Option<DepthImage>,
+    /// The pixel of the last measurement and its depth in millimeters
+    measured: Option<([usize; 2], Option<u16>)>,
+    /// The undistorted pixel that was clicked, and the camera it was clicked in
+    clicked: Option<(StereoSide, [f64; 2])>,
+    /// When the newest frame of the left and right cameras arrived
+    arrivals: [Option<Instant>; 2],
+    /// When capturing a pair was asked for, the pair is taken from the next frames of both cameras that arrive close together
+    capture: Option<Instant>,
+}
+
+/// The two ends of a line a x + b y + c = 0 where it crosses an image of a size
+fn line_ends(l: [f64; 3], size: [usize; 2]) -> Option<[[f64; 2]; 2]> {
+    let [a, b, c] = l;
+    let [w, h] = [size[0] as f64, size[1] as f64];
+    if b.abs() > a.abs() {
+        Some([[0.0, -c / b], [w, -(a * w + c) / b]])
+    } else if a != 0.0 {
+        Some([[-c / a, 0.0], [-(b * h + c) / a, h]])
+    } else {
+        None
+    }
+}
+
+/// An image for showing, from a color or monochrome camera image
+fn color_image(img: &opencv::core::Mat) -> Option<eframe::egui::ColorImage> {
+    crate::convert::bgr_mat_to_color_image(img)
+}
+
+/// Show an image filling a width, returning the response of the image
+fn show_image(
+    ui: &mut eframe::egui::Ui,
+    name: &str,
+    img: eframe::egui::ColorImage,
+    width: f32,
+    sense: eframe::egui::Sense,
+) -> eframe::egui::Response {
+    let tex = ui
+        .ctx()
+        .load_texture(name, img, eframe::egui::TextureOptions::LINEAR);
+    let st = eframe::egui::load::SizedTexture {
+        id: tex.id(),
+        size: tex.size_vec2() * (width / tex.size_vec2().x),
+    };
+    ui.add(eframe::egui::Image::from_texture(st).sense(sense))
+}
+
+/// Draw horizontal lines across an area, for checking that features are at the same height
+fn paint_rows(ui: &eframe::egui::Ui, rect: eframe::egui::Rect) {
+    let stroke = eframe::egui::Stroke::new(1.0, eframe::egui::Color32::GREEN.gamma_multiply(0.6));
+    let rows = 16;
+    for i in 1..rows {
+        let y = rect.top() + rect.height() * i as f32 / rows as f32;
+        ui.painter().hline(rect.x_range(), y, stroke);
+    }
+}
+
+impl StereoRig {
+    /// Depth beyond this is shown as black, in millimeters
+    const DEPTH_RANGE: u16 = 10000;
+    /// The most the frames of a pair may be apart, the board may move between them
+    const MAX_SKEW: Duration = Duration::from_millis(20);
+    /// How long capturing a pair waits for frames close together
+    const CAPTURE_TIMEOUT: Duration = Duration::from_secs(3);
+
+    /// True when a camera is one of the pair
+    pub fn contains(&self, i: i32) -> bool {
+        self.left == Some(i) || self.right == Some(i)
+    }
+
+    /// Note a frame from a camera, returns what to do when a pair is being captured
+    pub fn frame(&mut self, i: i32) -> Option<PairCapture> {
+        let now = Instant::now();
+        if self.left == Some(i) {
+            self.arrivals[0] = Some(now);
+        }
+        if self.right == Some(i) {
+            self.arrivals[1] = Some(now);
+        }
+        let asked = self.capture?;
+        if let [Some(l), Some(r)] = self.arrivals {
+            if l > asked && r > asked && l.max(r) - l.min(r) <= Self::MAX_SKEW {
+                self.capture = None;
+                return Some(PairCapture::Take);
+            }
+        }
+        if now - asked > Self::CAPTURE_TIMEOUT {
+            self.capture = None;
+            return Some(PairCapture::TimedOut);
+        }
+        None
+    }
+
+    /// The calibration of the pair
+    pub fn calibration(&self) -> Option<&StereoCalibration> {
+        self.calibration.as_ref()
+    }
+
+    /// Use a calibration for the pair, the error is from making the rectification which is then not shown
+    pub fn set_calibration(&mut self, sc: StereoCalibration) -> opencv::Result<()> {
+        self.rectification = None;
+        self.clicked = None;
+        self.depth = None;
+        self.measured = None;
+        let rectification = sc.rectification();
+        self.calibration = Some(sc);
+        self.rectification = Some(rectification?);
+        Ok(())
+    }
+
+    /// The depth of every pixel of the rectified left image
+    fn measure_depth(
+        rect: &Rectification,
+        left: &opencv::core::Mat,
+        right: &opencv::core::Mat,
+    ) -> opencv::Result<DepthImage> {
+        let disparity = rect.disparity(left, right)?;
+        let width = disparity.cols() as usize;
+        let millimeters = disparity
+            .data_typed::<f32>()?
+            .iter()
+            .enumerate()
+            .map(|(i, d)| {
+                let pixel = [(i % width) as f64, (i / width) as f64];
+                rect.depth(pixel, *d as f64)
+                    .map(|z| (z * 1000.0).clamp(0.0, u16::MAX as f64) as u16)
+                    .unwrap_or(0)
+            })
+            .collect();
+        Ok(DepthImage {
+            size: [width, disparity.rows() as usize],
+            millimeters,
+        })
+    }
+
+    /// Show the rectified images of the pair
+    fn show_rectified(&mut self, ui: &mut eframe::egui::Ui, images: [&opencv::core::Mat; 2]) {
+        let Some(rect) = &self.rectification else {
+            ui.label(tr!("stereo.no_rectification"));
+            return;
+        };
+        let [Some(lm), Some(rm)] = [
+            (StereoSide::Left, images[0]),
+            (StereoSide::Right, images[1]),
+        ]
+        .map(|(side, img)| rect.remap(side, img).ok()) else {
+            return;
+        };
+        let (Some(l), Some(r)) = (color_image(&lm), color_image(&rm)) else {
+            return;
+        };
+        let w = ui.available_width();
+        match self.view {
+            StereoView::SideBySide => {
+                let w = w / 2.0 - ui.spacing().item_spacing.x;
+                let area = ui
+                    .horizontal(|ui| {
+                        show_image(ui, "stereo_left", l, w, eframe::egui::Sense::hover());
+                        show_image(ui, "stereo_right", r, w, eframe::egui::Sense::hover());
+                    })
+                    .response
+                    .rect;
+                paint_rows(ui, area);
+            }
+            StereoView::Anaglyph => {
+                if l.size != r.size {
+                    return;
+                }
+                let mut a = l;
+                for (p, q) in a.pixels.iter_mut().zip(&r.pixels) {
+                    *p = eframe::egui::Color32::from_rgb(p.r(), q.g(), q.b());
+                }
+                let area =
+                    show_image(ui, "stereo_anaglyph", a, w, eframe::egui::Sense::hover()).rect;
+                paint_rows(ui, area);
+            }
+            StereoView::Depth => {
+                ui.label(tr!("stereo.depth_hint"));
+                let w = w / 2.0 - ui.spacing().item_spacing.x;
+                let size = l.size;
+                let mut clicked = None;
+                ui.horizontal(|ui| {
+                    let r = show_image(ui, "stereo_left", l, w, eframe::egui::Sense::click());
+                    if r.clicked() {
+                        clicked = crate::image_pixel(&r, size);
+                    }
+                    let shown = self
+                        .depth
+                        .as_ref()
+                        .and_then(|d| d.to_mat(Self::DEPTH_RANGE))
+                        .as_ref()
+                        .and_then(color_image);
+                    if let Some(d) = shown {
+                        let r = show_image(ui, "stereo_depth", d, w, eframe::egui::Sense::click());
+                        if r.clicked() {
+                            clicked = crate::image_pixel(&r, size);
+                        }
+                    }
+                });
+                if let Some(p) = clicked {
+                    match Self::measure_depth(rect, &lm, &rm) {
+                        Ok(d) => {
+                            self.measured = Some((p, d.at(p[0], p[1])));
+                            self.depth = Some(d);
+                        }
+                        Err(e) => action = Some(StereoAction::DepthFailed(e.to_string())),
+                    }
+                }
+                match self.measured {
+                    Some((p, Some(mm))) => {
+                        ui.label(tr!(
+                            "stereo.depth",
+                            x = p[0],
+                            y = p[1],
+                            depth = format!("{:.3}", mm as f64 / 1000.0)
+                        ));
+                    }
+                    Some((p, None)) => {
+                        ui.label(tr!("stereo.no_depth", x = p[0], y = p[1]));
+                    }
+                    None => {}
+                }
+            }
+            StereoView::Epipolar => {}
+        }
+    }
+
+    /// Show the cameras, controls and epipolar view.
+    /// cameras are the ids and names of the cameras, images are the newest images of the left and right cameras.
+    pub fn show(
+        &mut self,
+        ui: &mut eframe::egui::Ui,
+        cameras: &[(i32, String)],
+        images: [Option<&opencv::core::Mat>; 2],
+    ) -> Option<StereoAction> {
+        let mut action = None;
+        ui.horizontal(|ui| {
+            for (label, cam) in [
+                (tr!("stereo.left"), &mut self.left),
+                (tr!("stereo.right"), &mut self.right),
+            ] {
+                let name = cameras
+                    .iter()
+                    .find(|(i, _)| Some(*i) == *cam)
+                    .map(|(_, n)| n.clone())
+                    .unwrap_or_else(|| tr!("main.none"));
+                eframe::egui::ComboBox::from_label(label)
+                    .selected_text(name)
+                    .show_ui(ui, |ui| {
+                        for (i, n) in cameras {
+                            ui.selectable_value(cam, Some(*i), n);
+                        }
+                    });
+            }
+        });
+        ui.horizontal(|ui| {
+            let both = images.iter().all(Option::is_some);
+            if self.capture.is_some() {
+                ui.spinner();
+                ui.label(tr!("stereo.waiting"));
+            } else if ui
+                .add_enabled(both, eframe::egui::Button::new(tr!("stereo.capture_pair")))
+                .clicked()
+            {
+                self.capture = Some(Instant::now());
+            }
+            ui.label(tr!("stereo.pairs", count = self.pairs.len()));
+            if ui.button(tr!("main.clear_saved_images")).clicked() {
+                self.pairs.clear();
+            }
+            if ui
+                .add_enabled(
+                    !self.pairs.is_empty(),
+                    eframe::egui::Button::new(tr!("stereo.calibrate")),
+                )
+                .clicked()
+            {
+                action = Some(StereoAction::Calibrate);
+            }
+            if ui.button(tr!("stereo.load")).clicked() {
+                action = Some(StereoAction::Load);
+            }
+            if self.calibration.is_some() {
+                if ui.button(tr!("stereo.save")).clicked() {
+                    action = Some(StereoAction::Save);
+                }
+                if ui.button(tr!("stereo.export_rectification")).clicked() {
+                    action = Some(StereoAction::ExportRectification);
+                }
+            }
+        });
+        let Some(sc) = &self.calibration else {
+            ui.label(tr!("stereo.not_calibrated"));
+            return action;
+        };
+        ui.label(tr!(
+            "stereo.summary",
+            rms = format!("{:.3}", sc.rms),
+            baseline = format!("{:.4}", sc.baseline())
+        ));
+        ui.label(crate::review::rotation_text(sc));
+        let [tx, ty, tz] = sc.translation;
+        ui.label(tr!(
+            "stereo.translation",
+            x = format!("{:.4}", tx),
+            y = format!("{:.4}", ty),
+            z = format!("{:.4}", tz)
+        ));
+        ui.horizontal(|ui| {
+            for v in StereoView::ALL {
+                ui.selectable_value(&mut self.view, v, v.name());
+            }
+        });
+        if self.view != StereoView::Epipolar {
+            if let [Some(l), Some(r)] = images {
+                self.show_rectified(ui, [l, r]);
+            }
+            return action;
+        }
+        ui.label(tr!("stereo.epipolar_hint"));
+        let w = ui.available_width() / 2.0 - ui.spacing().item_spacing.x;
+        let mut shown = Vec::new();
+        ui.horizontal(|ui| {
+            for (side, img) in [StereoSide::Left, StereoSide::Right]
+                .into_iter()
+                .zip(images)
+            {
+                let Some(img) = img else {
+                    ui.label(tr!("stereo.no_image"));
+                    continue;
+                };
+                let Some(cimg) = color_image(img) else {
+                    continue;
+                };
+                let size = cimg.size;
+                let r = show_image(
+                    ui,
+                    &format!("stereo_{:?}", side),
+                    sc.camera(side).apply_calibration(cimg),
+                    w,
+                    eframe::egui::Sense::click(),
+                );
+                if r.clicked() {
+                    if let Some(p) = crate::image_pixel(&r, size) {
+                        self.clicked = Some((side, [p[0] as f64, p[1] as f64]));
+                    }
+                }
+                shown.push((side, r.rect, size));
+            }
+        });
+        let Some((clicked_side, p)) = self.clicked else {
+            return action;
+        };
+        let painter = ui.painter();
+        let to_screen = |rect: eframe::egui::Rect, size: [usize; 2], q: [f64; 2]| {
+            rect.min
+                + eframe::egui::vec2(
+                    q[0] as f32 / size[0] as f32 * rect.width(),
+                    q[1] as f32 / size[1] as f32 * rect.height(),
+                )
+        };
+        for (side, rect, size) in shown {
+            if side == clicked_side {
+                painter.circle_stroke(
+                    to_screen(rect, size, p),
+                    5.0,
+                    eframe::egui::Stroke::new(2.0, eframe::egui::Color32::YELLOW),
+                );
+            } else if let Some([a, b]) = line_ends(sc.epipolar_line(clicked_side, p), size) {
+                painter.with_clip_rect(rect).line_segment(
+                    [to_screen(rect, size, a), to_screen(rect, size, b)],
+                    eframe::egui::Stroke::new(2.0, eframe::egui::Color32::YELLOW),
+                );
+            }
+        }
+        action
+    }
+}
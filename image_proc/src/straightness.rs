@@ -0,0 +1,286 @@
+//! Checking a calibration with lines that are known to be straight, a straight edge or the rows of the board.
+//! Points along each line are fitted with a straight line before and after undistorting them,
+//! what is left of the curvature after undistorting shows how well the calibration removes the distortion.
+
+use image_proc::calibration::{CalibrationData, CalibrationDataTrait, DetectionResult};
+use image_proc::geometry::{LineFit, fit_line};
+use opencv::core::{Mat, MatTraitConst};
+
+/// What the user asked to check
+pub enum Request {
+    /// The straight edge between two points of the image, as fractions of its width and height
+    Edge([[f32; 2]; 2]),
+    /// The rows and columns of corners of the board
+    Board,
+}
+
+/// The fit of one line before and after undistorting it
+pub struct LineCheck {
+    pub name: String,
+    pub points: usize,
+    pub before: LineFit,
+    pub after: LineFit,
+}
+
+/// The straight line validation window
+pub struct StraightLineTool {
+    /// Clicking on the preview picks the ends of the edge
+    pub picking: bool,
+    /// The ends of the edge, as fractions of the width and height of the image
+    ends: Vec<[f32; 2]>,
+    /// How far from the picked line the edge is searched for, in pixels
+    band: f64,
+    /// The most a line may deviate from straight after undistorting, in pixels
+    tolerance: f64,
+    results: Result<Vec<LineCheck>, String>,
+}
+
+impl Default for StraightLineTool {
+    fn default() -> Self {
+        Self {
+            picking: false,
+            ends: Vec::new(),
+            band: 15.0,
+            tolerance: 0.5,
+            results: Ok(Vec::new()),
+        }
+    }
+}
+
+/// The brightness of a pixel of a floating point image, None outside of it
+fn pixel(img: &Mat, x: i32, y: i32) -> Option<f64> {
+    if x < 0 || y < 0 || x >= img.cols() || y >= img.rows() {
+        return None;
+    }
+    img.at_2d::<f32>(y, x).ok().map(|v| *v as f64)
+}
+
+/// Find points of a straight edge near the line between two pixels.
+/// Across the line, the edge is where the brightness changes the most, found to a fraction of a pixel.
+pub fn edge_points(img: &Mat, ends: [[f64; 2]; 2], band: f64) -> opencv::Result<Vec<[f64; 2]>> {
+    let gray = image_proc::calibration::to_gray(img)?;
+    let mut f = Mat::default();
+    gray.convert_to_def(&mut f, opencv::core::CV_32F)?;
+    let [a, b] = ends;
+    let (dx, dy) = (b[0] - a[0], b[1] - a[1]);
+    let length = dx.hypot(dy);
+    if length < 2.0 {
+        return Ok(Vec::new());
+    }
+    let dir = [dx / length, dy / length];
+    let normal = [-dir[1], dir[0]];
+    let reach = band.round().max(2.0) as i32;
+    let mut points = Vec::new();
+    for step in 0..(length / 2.0) as i32 {
+        let along = step as f64 * 2.0;
+        let base = [a[0] + dir[0] * along, a[1] + dir[1] * along];
+        let sample = |o: i32| {
+            let p = [
+                base[0] + normal[0] * o as f64,
+                base[1] + normal[1] * o as f64,
+            ];
+            pixel(&f, p[0].round() as i32, p[1].round() as i32)
+        };
+        let Some(profile) = (-reach..=reach).map(sample).collect::<Option<Vec<f64>>>() else {
+            continue;
+        };
+        let gradient: Vec<f64> = profile.windows(3).map(|w| (w[2] - w[0]) / 2.0).collect();
+        let Some((i, g)) = gradient
+            .iter()
+            .enumerate()
+            .max_by(|x, y| x.1.abs().total_cmp(&y.1.abs()))
+        else {
+            continue;
+        };
+        // Flat areas have no edge to find
+        if g.abs() < 8.0 || i == 0 || i + 1 == gradient.len() {
+            continue;
+        }
+        let (l, c, r) = (gradient[i - 1].abs(), g.abs(), gradient[i + 1].abs());
+        let denom = l - 2.0 * c + r;
+        let sub = if denom.abs() > 1e-9 {
+            0.5 * (l - r) / denom
+        } else {
+            0.0
+        };
+        let offset = (i as i32 + 1 - reach) as f64 + sub;
+        points.push([base[0] + normal[0] * offset, base[1] + normal[1] * offset]);
+    }
+    Ok(points)
+}
+
+/// The corners found on each row and column of a board with across corners in a row
+pub fn board_lines(result: &DetectionResult, across: i32) -> Vec<(String, Vec<[f64; 2]>)> {
+    let across = across.max(1);
+    let mut rows: std::collections::BTreeMap<i32, Vec<[f64; 2]>> = Default::default();
+    let mut cols: std::collections::BTreeMap<i32, Vec<[f64; 2]>> = Default::default();
+    for (p, id) in result.corners.iter().zip(result.corner_ids.iter()) {
+        let p = [p.x as f64, p.y as f64];
+        rows.entry(id / across).or_default().push(p);
+        cols.entry(id % across).or_default().push(p);
+    }
+    let rows = rows
+        .into_iter()
+        .map(|(i, p)| (tr!("straightness.row", number = i + 1), p));
+    let cols = cols
+        .into_iter()
+        .map(|(i, p)| (tr!("straightness.column", number = i + 1), p));
+    rows.chain(cols).filter(|(_, p)| p.len() >= 3).collect()
+}
+
+/// Fit each line before and after undistorting its points
+pub fn check(
+    lines: Vec<(String, Vec<[f64; 2]>)>,
+    cd: &CalibrationData,
+) -> Result<Vec<LineCheck>, String> {
+    let mut checks = Vec::new();
+    for (name, points) in lines {
+        let undistorted: Vec<[f64; 2]> = cd
+            .undistort_points(&points)
+            .map_err(|e| e.to_string())?
+            .iter()
+            .map(|p| p.pixel)
+            .collect();
+        if let (Some(before), Some(after)) = (fit_line(&points), fit_line(&undistorted)) {
+            checks.push(LineCheck {
+                name,
+                points: points.len(),
+                before,
+                after,
+            });
+        }
+    }
+    if checks.is_empty() {
+        return Err(tr!("straightness.no_lines"));
+    }
+    Ok(checks)
+}
+
+impl StraightLineTool {
+    /// Pick an end of the edge, picking a third starts a new edge
+    pub fn add_point(&mut self, p: [f32; 2]) {
+        if self.ends.len() >= 2 {
+            self.ends.clear();
+        }
+        self.ends.push(p);
+    }
+
+    pub fn set_results(&mut self, results: Result<Vec<LineCheck>, String>) {
+        self.results = results;
+    }
+
+    /// The width of the band the edge is searched in, in pixels
+    pub fn band(&self) -> f64 {
+        self.band
+    }
+
+    /// Draw the picked edge over the preview
+    pub fn paint(&self, ui: &eframe::egui::Ui, rect: eframe::egui::Rect) {
+        let painter = ui.painter_at(rect);
+        let pos = |p: &[f32; 2]| rect.min + rect.size() * eframe::egui::vec2(p[0], p[1]);
+        let stroke = eframe::egui::Stroke::new(1.5, eframe::egui::Color32::LIGHT_GREEN);
+        for p in &self.ends {
+            painter.circle_stroke(pos(p), 4.0, stroke);
+        }
+        if let [a, b] = self.ends[..] {
+            painter.line_segment([pos(&a), pos(&b)], stroke);
+        }
+    }
+
+    /// Show the settings and results, returns what the user asked to check
+    pub fn show(&mut self, ui: &mut eframe::egui::Ui, calibrated: bool) -> Option<Request> {
+        let mut request = None;
+        ui.label(tr!("straightness.instructions"));
+        if !calibrated {
+            ui.label(tr!("straightness.no_calibration"));
+            return None;
+        }
+        eframe::egui::Grid::new("straightness_settings").show(ui, |ui| {
+            ui.label(tr!("straightness.band"));
+            ui.add(
+                eframe::egui::DragValue::new(&mut self.band)
+                    .range(2.0..=200.0)
+                    .suffix(" px"),
+            );
+            ui.end_row();
+            ui.label(tr!("straightness.tolerance"));
+            ui.add(
+                eframe::egui::DragValue::new(&mut self.tolerance)
+                    .range(0.01..=20.0)
+                    .speed(0.01)
+                    .suffix(" px"),
+            );
+            ui.end_row();
+        });
+        ui.horizontal(|ui| {
+            ui.toggle_value(&mut self.picking, tr!("straightness.pick"));
+            if ui
+                .add_enabled(
+                    self.ends.len() == 2,
+                    eframe::egui::Button::new(tr!("straightness.check_edge")),
+                )
+                .clicked()
+            {
+                request = Some(Request::Edge([self.ends[0], self.ends[1]]));
+            }
+            if ui.button(tr!("straightness.check_board")).clicked() {
+                request = Some(Request::Board);
+            }
+        });
+        if self.picking {
+            ui.label(tr!("straightness.pick_hint"));
+        }
+        let checks = match &self.results {
+            Ok(c) => c,
+            Err(e) => {
+                ui.colored_label(eframe::egui::Color32::RED, e);
+                return request;
+            }
+        };
+        if checks.is_empty() {
+            return request;
+        }
+        let worst = checks.iter().map(|c| c.after.max).fold(0.0, f64::max);
+        if worst <= self.tolerance {
+            ui.colored_label(
+                eframe::egui::Color32::GREEN,
+                tr!("straightness.pass", max = format!("{:.3}", worst)),
+            );
+        } else {
+            ui.colored_label(
+                eframe::egui::Color32::RED,
+                tr!("straightness.fail", max = format!("{:.3}", worst)),
+            );
+        }
+        eframe::egui::ScrollArea::vertical()
+            .max_height(300.0)
+            .show(ui, |ui| {
+                eframe::egui::Grid::new("straightness_results")
+                    .striped(true)
+                    .show(ui, |ui| {
+                        ui.strong(tr!("straightness.line"));
+                        ui.strong(tr!("straightness.points"));
+                        ui.strong(tr!("straightness.before"));
+                        ui.strong(tr!("straightness.after"));
+                        ui.end_row();
+                        for c in checks {
+                            ui.label(&c.name);
+                            ui.label(c.points.to_string());
+                            ui.monospace(format!("{:.3} / {:.3} px", c.before.rms, c.before.max));
+                            let color = if c.after.max <= self.tolerance {
+                                eframe::egui::Color32::GREEN
+                            } else {
+                                eframe::egui::Color32::RED
+                            };
+                            ui.colored_label(
+                                color,
+                                format!("{:.3} / {:.3} px", c.after.rms, c.after.max),
+                            );
+                            ui.end_row();
+                        }
+                    });
+            });
+        ui.weak(tr!("straightness.legend"));
+        request
+    }
+}
@@ -0,0 +1,132 @@
+//! The temperature at the time of a calibration, entered by hand or read from a serial thermometer.
+//! Stored with each calibration so drift of the intrinsics with heat can be tracked.
+
+use std::io::BufRead;
+use std::time::Duration;
+
+/// The serial thermometer
+#[derive(Clone, Debug, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
+pub struct ThermometerSettings {
+    /// The serial port of the thermometer, like /dev/ttyUSB0 or COM3, empty when there is none
+    pub port: String,
+    pub baud: u32,
+}
+
+impl Default for ThermometerSettings {
+    fn default() -> Self {
+        Self {
+            port: String::new(),
+            baud: 9600,
+        }
+    }
+}
+
+impl ThermometerSettings {
+    pub fn show(&mut self, ui: &mut eframe::egui::Ui) {
+        eframe::egui::Grid::new("thermometer_settings").show(ui, |ui| {
+            ui.label(tr!("temperature.port"));
+            ui.add(eframe::egui::TextEdit::singleline(&mut self.port).hint_text("/dev/ttyUSB0"));
+            ui.end_row();
+            ui.label(tr!("temperature.baud"));
+            ui.add(eframe::egui::DragValue::new(&mut self.baud).range(300..=921600));
+            ui.end_row();
+        });
+        ui.label(tr!("temperature.help"));
+    }
+}
+
+/// The first number in a line of text from a thermometer, like "T=23.5C" or "23.50,45.2"
+fn first_number(line: &str) -> Option<f64> {
+    line.split(|c: char| !(c.is_ascii_digit() || c == '.' || c == '-'))
+        .find_map(|s| s.parse().ok())
+}
+
+/// Read a temperature in degrees celsius from a thermometer that sends one reading per line.
+/// The first line may have started before the port was opened, so the reading is taken from the second.
+fn read(settings: &ThermometerSettings) -> Result<f64, String> {
+    let port = serialport::new(&settings.port, settings.baud)
+        .timeout(Duration::from_secs(3))
+        .open()
+        .map_err(|e| e.to_string())?;
+    let mut reader = std::io::BufReader::new(port);
+    let mut line = String::new();
+    for _ in 0..2 {
+        line.clear();
+        reader.read_line(&mut line).map_err(|e| e.to_string())?;
+    }
+    first_number(&line).ok_or_else(|| tr!("temperature.bad_reading", line = line.trim()))
+}
+
+/// The temperature recorded with the next calibration
+pub struct TemperatureInput {
+    /// Record the temperature with calibrations
+    record: bool,
+    /// The temperature in degrees celsius
+    value: f64,
+    /// A thermometer reading in progress
+    reading: Option<crossbeam::channel::Receiver<Result<f64, String>>>,
+    error: Option<String>,
+}
+
+impl Default for TemperatureInput {
+    fn default() -> Self {
+        Self {
+            record: false,
+            value: 20.0,
+            reading: None,
+            error: None,
+        }
+    }
+}
+
+impl TemperatureInput {
+    /// The temperature to record with a calibration, None when it is not recorded
+    pub fn temperature(&self) -> Option<f64> {
+        self.record.then_some(self.value)
+    }
+
+    /// Read the thermometer in the background, reading can take a few seconds
+    fn start_reading(&mut self, settings: &ThermometerSettings) {
+        let (s, r) = crossbeam::channel::bounded(1);
+        let settings = settings.clone();
+        std::thread::spawn(move || {
+            let _ = s.send(read(&settings));
+        });
+        self.reading = Some(r);
+        self.error = None;
+    }
+
+    /// Show the temperature for editing, with a button reading it from the thermometer when there is one
+    pub fn show(&mut self, ui: &mut eframe::egui::Ui, settings: &ThermometerSettings) {
+        if let Some(r) = self.reading.as_ref().and_then(|r| r.try_recv().ok()) {
+            self.reading = None;
+            match r {
+                Ok(t) => {
+                    self.value = t;
+                    self.record = true;
+                }
+                Err(e) => self.error = Some(e),
+            }
+        }
+        ui.checkbox(&mut self.record, tr!("temperature.record"));
+        ui.add_enabled(
+            self.record,
+            eframe::egui::DragValue::new(&mut self.value)
+                .range(-60.0..=150.0)
+                .speed(0.1)
+                .suffix(" °C"),
+        );
+        if self.reading.is_some() {
+            ui.spinner();
+        } else if !settings.port.is_empty() && ui.button(tr!("temperature.read")).clicked() {
+            self.start_reading(settings);
+        }
+        if let Some(e) = &self.error {
+            ui.colored_label(
+                eframe::egui::Color32::RED,
+                tr!("temperature.read_failed", error = e),
+            );
+        }
+    }
+}
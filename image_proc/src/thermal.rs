@@ -0,0 +1,171 @@
+//! Thermal cameras producing 16 bit radiometric images, like the FLIR Lepton on a PureThermal board
+
+use std::sync::{Arc, Mutex};
+
+use opencv::{
+    core::{MatTraitConst, MatTraitConstManual},
+    videoio::{VideoCaptureTrait, VideoCaptureTraitConst},
+};
+
+use crate::FrameSourceTrait;
+
+/// A thermal image, shared between the camera thread producing it and the user interface
+pub type SharedThermal = Arc<Mutex<Option<ThermalImage>>>;
+
+/// The temperature of every pixel
+#[derive(Clone, Debug)]
+pub struct ThermalImage {
+    /// The width and height in pixels
+    pub size: [usize; 2],
+    /// The temperature of every pixel in hundredths of a kelvin, row by row
+    pub centikelvin: Vec<u16>,
+}
+
+impl ThermalImage {
+    fn to_celsius(v: u16) -> f32 {
+        v as f32 / 100.0 - 273.15
+    }
+
+    /// The temperature at a pixel in degrees celsius
+    pub fn celsius_at(&self, x: usize, y: usize) -> Option<f32> {
+        if x >= self.size[0] || y >= self.size[1] {
+            return None;
+        }
+        Some(Self::to_celsius(self.centikelvin[y * self.size[0] + x]))
+    }
+
+    /// The coldest and hottest temperatures in the image in degrees celsius
+    pub fn range(&self) -> Option<(f32, f32)> {
+        let min = self.centikelvin.iter().min()?;
+        let max = self.centikelvin.iter().max()?;
+        Some((Self::to_celsius(*min), Self::to_celsius(*max)))
+    }
+
+    /// Convert to a bgr image for display, scaled so the coldest pixel is black and the hottest is white.
+    /// The result is grayscale, so the colormaps of the view modes can be applied to it.
+    fn to_mat(&self) -> Option<opencv::core::Mat> {
+        let min = *self.centikelvin.iter().min()? as u32;
+        let max = *self.centikelvin.iter().max()? as u32;
+        let span = (max - min).max(1);
+        let data: Vec<u8> = self
+            .centikelvin
+            .iter()
+            .flat_map(|v| {
+                let v = ((*v as u32 - min) * 255 / span) as u8;
+                [v, v, v]
+            })
+            .collect();
+        crate::convert::bytes_to_mat(self.size[0], self.size[1], opencv::core::CV_8UC3, &data)
+    }
+
+    /// Get the radiometric values out of a frame, which is either 16 bit or the raw bytes of a 16 bit image
+    fn from_mat(m: &opencv::core::Mat) -> Option<Self> {
+        if !m.is_continuous() {
+            return Self::from_mat(&m.try_clone().ok()?);
+        }
+        let bytes = m.data_bytes().ok()?;
+        let centikelvin: Vec<u16> = bytes
+            .chunks_exact(2)
+            .map(|b| u16::from_le_bytes([b[0], b[1]]))
+            .collect();
+        let size = match m.typ() {
+            opencv::core::CV_16UC1 => [m.cols() as usize, m.rows() as usize],
+            opencv::core::CV_8UC2 => [m.cols() as usize, m.rows() as usize],
+            // Some drivers give a single row of raw bytes
+            opencv::core::CV_8UC1 => {
+                let w = m.cols() as usize / 2;
+                [w, centikelvin.len() / w.max(1)]
+            }
+            _ => return None,
+        };
+        if size[0] * size[1] != centikelvin.len() {
+            return None;
+        }
+        Some(Self { size, centikelvin })
+    }
+}
+
+/// A thermal camera sending 16 bit radiometric frames over uvc
+#[derive(Debug)]
+pub struct ThermalCamera {
+    /// The index of the camera device
+    i: i32,
+    cam: Option<opencv::videoio::VideoCapture>,
+    /// The number of reads in a row that failed
+    failures: u32,
+    /// The most recent image, for reading temperatures
+    latest: SharedThermal,
+}
+
+impl ThermalCamera {
+    pub fn new(i: i32) -> Self {
+        Self {
+            i,
+            cam: None,
+            failures: 0,
+            latest: Arc::new(Mutex::new(None)),
+        }
+    }
+
+    /// The thermal images of the camera
+    pub fn thermal(&self) -> SharedThermal {
+        self.latest.clone()
+    }
+}
+
+impl FrameSourceTrait for ThermalCamera {
+    fn open(&mut self) -> bool {
+        let Ok(mut c) = opencv::videoio::VideoCapture::new(self.i, opencv::videoio::CAP_ANY) else {
+            return false;
+        };
+        // Ask for the raw 16 bit values instead of a color image made from them
+        if let Ok(y16) = opencv::videoio::VideoWriter::fourcc('Y', '1', '6', ' ') {
+            let _ = c.set(
+                opencv::videoio::VideoCaptureProperties::CAP_PROP_FOURCC as i32,
+                y16 as f64,
+            );
+        }
+        let _ = c.set(
+            opencv::videoio::VideoCaptureProperties::CAP_PROP_CONVERT_RGB as i32,
+            0.0,
+        );
+        if let Ok(true) = c.is_opened() {
+            self.cam = Some(c);
+            self.failures = 0;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn close(&mut self) {
+        self.cam = None;
+    }
+
+    fn is_open(&self) -> bool {
+        self.cam.is_some()
+    }
+
+    fn get_image(&mut self) -> Option<opencv::core::Mat> {
+        let c = self.cam.as_mut()?;
+        let mut mat = opencv::core::Mat::default();
+        let t = match c.read(&mut mat) {
+            Ok(true) => ThermalImage::from_mat(&mat),
+            _ => None,
+        };
+        let Some(t) = t else {
+            self.failures += 1;
+            return None;
+        };
+        self.failures = 0;
+        let m = t.to_mat();
+        if let Ok(mut l) = self.latest.lock() {
+            *l = Some(t);
+        }
+        m
+    }
+
+    fn failed(&self) -> bool {
+        self.failures >= 30
+    }
+}
@@ -0,0 +1,105 @@
+//! Spot checking the undistortion of individual pixels, for testing geometry code that works on points
+
+use image_proc::calibration::{CalibrationData, CalibrationDataTrait, UndistortedPoint};
+
+/// Pixels entered by the user along with their undistorted coordinates
+#[derive(Default)]
+pub struct UndistortTool {
+    /// The pixels, one x, y pair per line
+    text: String,
+    /// Clicking on the preview adds the pixel under the mouse
+    pub picking: bool,
+}
+
+/// Get points of N coordinates out of text with one point per line, the error is the line that could not be read
+pub fn parse_points<const N: usize>(text: &str) -> Result<Vec<[f64; N]>, String> {
+    let mut points = Vec::new();
+    for line in text.lines().map(str::trim).filter(|l| !l.is_empty()) {
+        let v: Vec<f64> = line
+            .split(|c: char| c == ',' || c == ';' || c.is_whitespace())
+            .filter(|s| !s.is_empty())
+            .map(str::parse)
+            .collect::<Result<_, _>>()
+            .map_err(|_| line.to_string())?;
+        points.push(v.try_into().map_err(|_| line.to_string())?);
+    }
+    Ok(points)
+}
+
+impl UndistortTool {
+    /// Add a pixel of the preview
+    pub fn add_point(&mut self, p: [usize; 2]) {
+        if !self.text.is_empty() && !self.text.ends_with('\n') {
+            self.text.push('\n');
+        }
+        self.text.push_str(&format!("{}, {}\n", p[0], p[1]));
+    }
+
+    /// Show the pixels and their undistorted coordinates
+    pub fn show(&mut self, ui: &mut eframe::egui::Ui, cd: Option<&CalibrationData>) {
+        ui.label(tr!("undistort.instructions"));
+        ui.horizontal(|ui| {
+            ui.toggle_value(&mut self.picking, tr!("undistort.pick"));
+            if ui.button(tr!("undistort.clear")).clicked() {
+                self.text.clear();
+            }
+        });
+        if self.picking {
+            ui.label(tr!("undistort.pick_hint"));
+        }
+        ui.add(
+            eframe::egui::TextEdit::multiline(&mut self.text)
+                .hint_text("320, 240")
+                .desired_rows(4),
+        );
+        let Some(cd) = cd else {
+            ui.label(tr!("undistort.no_calibration"));
+            return;
+        };
+        let points = match parse_points::<2>(&self.text) {
+            Ok(p) => p,
+            Err(line) => {
+                ui.colored_label(
+                    eframe::egui::Color32::RED,
+                    tr!("undistort.bad_line", line = line),
+                );
+                return;
+            }
+        };
+        let results: Vec<UndistortedPoint> = match cd.undistort_points(&points) {
+            Ok(r) => r,
+            Err(e) => {
+                ui.colored_label(
+                    eframe::egui::Color32::RED,
+                    tr!("undistort.failed", error = e.to_string()),
+                );
+                return;
+            }
+        };
+        eframe::egui::Grid::new("undistorted_points")
+            .striped(true)
+            .show(ui, |ui| {
+                ui.strong(tr!("undistort.pixel"));
+                ui.strong(tr!("undistort.undistorted"));
+                ui.strong(tr!("undistort.normalized"));
+                ui.end_row();
+                for (p, u) in points.iter().zip(&results) {
+                    ui.label(format!("{:.2}, {:.2}", p[0], p[1]));
+                    ui.label(format!("{:.3}, {:.3}", u.pixel[0], u.pixel[1]));
+                    ui.label(format!("{:.6}, {:.6}", u.normalized[0], u.normalized[1]));
+                    ui.end_row();
+                }
+            });
+        if ui.button(tr!("undistort.copy")).clicked() {
+            let mut csv =
+                String::from("x,y,undistorted_x,undistorted_y,normalized_x,normalized_y\n");
+            for (p, u) in points.iter().zip(&results) {
+                csv.push_str(&format!(
+                    "{},{},{},{},{},{}\n",
+                    p[0], p[1], u.pixel[0], u.pixel[1], u.normalized[0], u.normalized[1]
+                ));
+            }
+            ui.ctx().copy_text(csv);
+        }
+    }
+}
@@ -0,0 +1,252 @@
+//! Measuring the vignetting of a camera from flat field captures, as a radial polynomial model
+
+use egui_plot::{Line, Plot, PlotPoints};
+use image_proc::linalg::solve;
+use opencv::core::{MatTraitConst, MatTraitConstManual};
+
+use crate::profile::CameraProfile;
+
+/// The vignetting of a camera as the polynomial model used by lensfun ("pa").
+/// The brightness relative to the center is 1 + k1 r^2 + k2 r^4 + k3 r^6, where r is 1 at the corners of the image.
+#[derive(Clone, Debug, serde::Serialize, serde::Deserialize)]
+pub struct VignettingProfile {
+    pub k: [f64; 3],
+    /// The number of frames averaged for the fit
+    pub frames: usize,
+    pub width: i32,
+    pub height: i32,
+    /// The rms difference between the model and the measured brightness, relative to the center
+    pub rms: f64,
+}
+
+impl VignettingProfile {
+    /// The brightness relative to the center at a radius
+    pub fn falloff(&self, r: f64) -> f64 {
+        let r2 = r * r;
+        1.0 + self.k[0] * r2 + self.k[1] * r2 * r2 + self.k[2] * r2 * r2 * r2
+    }
+
+    /// The coefficients as a lensfun vignetting element
+    pub fn lensfun(&self) -> String {
+        format!(
+            "<vignetting model=\"pa\" focal=\"\" aperture=\"\" distance=\"\" k1=\"{:.6}\" k2=\"{:.6}\" k3=\"{:.6}\"/>",
+            self.k[0], self.k[1], self.k[2]
+        )
+    }
+}
+
+/// The sum of the brightness of every pixel over the frames captured so far
+struct FlatFieldCapture {
+    target: usize,
+    frames: usize,
+    width: i32,
+    height: i32,
+    sum: Vec<f64>,
+}
+
+impl FlatFieldCapture {
+    fn add_frame(&mut self, img: &opencv::core::Mat) -> Result<(), String> {
+        if img.depth() != opencv::core::CV_8U {
+            return Err("Only 8 bit images are supported".to_string());
+        }
+        let mut gray = opencv::core::Mat::default();
+        if img.channels() == 3 {
+            opencv::imgproc::cvt_color_def(img, &mut gray, opencv::imgproc::COLOR_BGR2GRAY)
+                .map_err(|e| e.to_string())?;
+        } else {
+            gray = img.clone();
+        }
+        let data = gray.data_bytes().map_err(|e| e.to_string())?;
+        if self.frames == 0 {
+            self.width = gray.cols();
+            self.height = gray.rows();
+            self.sum = vec![0.0; data.len()];
+        } else if self.width != gray.cols() || self.height != gray.rows() {
+            return Err("The frame size changed during capture".to_string());
+        }
+        for (s, d) in self.sum.iter_mut().zip(data) {
+            *s += *d as f64;
+        }
+        self.frames += 1;
+        Ok(())
+    }
+
+    /// Fit the model by least squares, with the brightness at the center as a fourth unknown
+    fn finish(&self) -> Result<VignettingProfile, String> {
+        let (w, h) = (self.width as usize, self.height as usize);
+        let (cx, cy) = ((w as f64 - 1.0) / 2.0, (h as f64 - 1.0) / 2.0);
+        let corner = (cx * cx + cy * cy).sqrt().max(1.0);
+        let n = self.frames.max(1) as f64;
+        let mut samples = Vec::new();
+        // Every pixel is not needed for a smooth model
+        for y in (0..h).step_by(4) {
+            for x in (0..w).step_by(4) {
+                let r2 = ((x as f64 - cx).powi(2) + (y as f64 - cy).powi(2)) / (corner * corner);
+                let v = self.sum[y * w + x] / n;
+                // Clipped pixels do not follow the falloff
+                if v > 1.0 && v < 254.0 {
+                    samples.push(([1.0, r2, r2 * r2, r2 * r2 * r2], v));
+                }
+            }
+        }
+        let mut ata = [[0.0; 4]; 4];
+        let mut atb = [0.0; 4];
+        for (f, v) in &samples {
+            for i in 0..4 {
+                for j in 0..4 {
+                    ata[i][j] += f[i] * f[j];
+                }
+                atb[i] += f[i] * v;
+            }
+        }
+        let c = solve(ata, atb)
+            .ok_or("The flat field could not be fitted, is it evenly lit and not clipped?")?;
+        if c[0] <= 0.0 {
+            return Err("The flat field is too dark".to_string());
+        }
+        let rms = (samples
+            .iter()
+            .map(|(f, v)| {
+                let model: f64 = f.iter().zip(&c).map(|(a, b)| a * b).sum();
+                ((model - v) / c[0]).powi(2)
+            })
+            .sum::<f64>()
+            / samples.len().max(1) as f64)
+            .sqrt();
+        Ok(VignettingProfile {
+            k: [c[1] / c[0], c[2] / c[0], c[3] / c[0]],
+            frames: self.frames,
+            width: self.width,
+            height: self.height,
+            rms,
+        })
+    }
+}
+
+/// The vignetting measurement tool
+pub struct VignettingTool {
+    frames: usize,
+    capture: Option<FlatFieldCapture>,
+    error: Option<String>,
+}
+
+impl Default for VignettingTool {
+    fn default() -> Self {
+        Self {
+            frames: 16,
+            capture: None,
+            error: None,
+        }
+    }
+}
+
+impl VignettingTool {
+    /// Add a frame from the camera being measured, returns the model once enough frames are captured
+    pub fn add_frame(&mut self, img: &opencv::core::Mat) -> Option<VignettingProfile> {
+        let c = self.capture.as_mut()?;
+        if let Err(e) = c.add_frame(img) {
+            self.error = Some(e);
+            self.capture = None;
+            return None;
+        }
+        if c.frames < c.target {
+            return None;
+        }
+        let r = c.finish();
+        self.capture = None;
+        match r {
+            Ok(p) => Some(p),
+            Err(e) => {
+                self.error = Some(e);
+                None
+            }
+        }
+    }
+
+    /// Save the model to a file chosen by the user, as a lensfun element or json
+    fn export(&mut self, p: &VignettingProfile) {
+        let Some(f) = rfd::FileDialog::new()
+            .add_filter("Lensfun xml", &["xml"])
+            .add_filter("JSON", &["json"])
+            .save_file()
+        else {
+            return;
+        };
+        let json = f.extension().is_some_and(|e| e == "json");
+        let text = if json {
+            serde_json::to_string_pretty(p).map_err(|e| e.to_string())
+        } else {
+            Ok(p.lensfun())
+        };
+        if let Err(e) = text.and_then(|t| std::fs::write(&f, t).map_err(|e| e.to_string())) {
+            self.error = Some(e);
+        }
+    }
+
+    /// Show the tool, along with the stored vignetting model of the selected camera
+    pub fn show(&mut self, ui: &mut eframe::egui::Ui, profile: Option<&CameraProfile>) {
+        ui.label(tr!("vignetting.instructions"));
+        ui.horizontal(|ui| {
+            ui.add(
+                eframe::egui::Slider::new(&mut self.frames, 1..=128).text(tr!("vignetting.frames")),
+            );
+            if let Some(c) = &self.capture {
+                ui.label(tr!(
+                    "vignetting.progress",
+                    count = c.frames,
+                    total = c.target
+                ));
+                if ui.button(tr!("vignetting.cancel")).clicked() {
+                    self.capture = None;
+                }
+            } else if ui.button(tr!("vignetting.start")).clicked() {
+                self.error = None;
+                self.capture = Some(FlatFieldCapture {
+                    target: self.frames,
+                    frames: 0,
+                    width: 0,
+                    height: 0,
+                    sum: Vec::new(),
+                });
+            }
+        });
+        if let Some(e) = &self.error {
+            ui.colored_label(eframe::egui::Color32::RED, e);
+        }
+        let Some(p) = profile.and_then(|p| p.vignetting.as_ref()) else {
+            return;
+        };
+        ui.label(tr!(
+            "vignetting.summary",
+            frames = p.frames,
+            width = p.width,
+            height = p.height,
+            rms = format!("{:.2}", p.rms * 100.0)
+        ));
+        ui.label(format!(
+            "k1 = {:.6}, k2 = {:.6}, k3 = {:.6}",
+            p.k[0], p.k[1], p.k[2]
+        ));
+        ui.horizontal(|ui| {
+            if ui.button(tr!("vignetting.copy")).clicked() {
+                ui.ctx().copy_text(p.lensfun());
+            }
+            if ui.button(tr!("vignetting.export")).clicked() {
+                self.export(p);
+            }
+        });
+        let falloff: PlotPoints = (0..=100)
+            .map(|i| {
+                let r = i as f64 / 100.0;
+                [r, p.falloff(r)]
+            })
+            .collect();
+        Plot::new("vignetting_falloff")
+            .view_aspect(2.0)
+            .x_axis_label(tr!("vignetting.radius"))
+            .y_axis_label(tr!("vignetting.brightness"))
+            .show(ui, |plot_ui| {
+                plot_ui.line(Line::new(falloff));
+            });
+    }
+}
@@ -0,0 +1,293 @@
+//! Watching a folder and processing every image that appears in it, like a hot folder service
+
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+    time::{Duration, Instant},
+};
+
+use image_proc::{
+    calibration::{CalibrationData, CalibrationDataTrait},
+    cancel::CancelToken,
+};
+
+use crate::pipeline::Pipeline;
+
+/// The folders used for watching
+#[derive(Clone, Debug, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
+pub struct WatchSettings {
+    /// The folder watched for new images
+    pub input: PathBuf,
+    /// Where the processed images are written
+    pub output: PathBuf,
+    /// Apply the current calibration before the pipeline
+    pub undistort: bool,
+}
+
+impl Default for WatchSettings {
+    fn default() -> Self {
+        Self {
+            input: PathBuf::from("./watch"),
+            output: PathBuf::from("./watch/processed"),
+            undistort: true,
+        }
+    }
+}
+
+/// An image to process, with the processing as it was when the image appeared
+struct Job {
+    path: PathBuf,
+    output: PathBuf,
+    pipeline: Pipeline,
+    calibration: Option<CalibrationData>,
+}
+
+impl Job {
+    fn run(self) -> Result<PathBuf, String> {
+        let img = crate::load_image_file(&self.path)
+            .ok_or_else(|| format!("Failed to load {}", self.path.display()))?;
+        let img = match &self.calibration {
+            Some(cd) => cd.apply_calibration(img),
+            None => img,
+        };
+        let img = self.pipeline.process(img);
+        let name = self
+            .path
+            .file_name()
+            .ok_or_else(|| format!("{} has no file name", self.path.display()))?;
+        std::fs::create_dir_all(&self.output).map_err(|e| e.to_string())?;
+        let out = self.output.join(name);
+        crate::convert::save_color_image(&out, &img).map_err(|e| e.to_string())?;
+        Ok(out)
+    }
+}
+
+/// A running watch of a folder
+struct Watching {
+    /// Dropping the watcher stops the watch
+    _watcher: notify::RecommendedWatcher,
+    events: crossbeam::channel::Receiver<PathBuf>,
+    jobs: crossbeam::channel::Sender<Job>,
+    results: crossbeam::channel::Receiver<Result<PathBuf, String>>,
+    /// Stops the worker before the next queued image
+    cancel: CancelToken,
+}
+
+impl Drop for Watching {
+    fn drop(&mut self) {
+        // Images still queued are not processed after the watch stops
+        self.cancel.cancel();
+    }
+}
+
+/// The watch folder mode and its window
+#[derive(Default)]
+pub struct WatchFolder {
+    watching: Option<Watching>,
+    /// Files that changed recently, with the time of the last change, waiting for writing to finish
+    pending: BTreeMap<PathBuf, Instant>,
+    processed: usize,
+    failed: usize,
+    last: Option<PathBuf>,
+}
+
+impl WatchFolder {
+    /// How long a file must be unchanged before it is considered complete
+    const SETTLE: Duration = Duration::from_secs(1);
+
+    fn is_image(p: &Path) -> bool {
+        p.extension()
+            .and_then(|e| e.to_str())
+            .is_some_and(|e| ["jpg", "jpeg", "png"].contains(&e.to_lowercase().as_str()))
+    }
+
+    /// Start watching the input folder
+    fn start(&mut self, settings: &WatchSettings) -> notify::Result<()> {
+        use notify::Watcher;
+        std::fs::create_dir_all(&settings.input)?;
+        let (event_snd, events) = crossbeam::channel::unbounded();
+        let mut watcher = notify::recommended_watcher(move |r: notify::Result<notify::Event>| {
+            if let Ok(e) = r {
+                if matches!(
+                    e.kind,
+                    notify::EventKind::Create(_) | notify::EventKind::Modify(_)
+                ) {
+                    for p in e.paths {
+                        let _ = event_snd.send(p);
+                    }
+                }
+            }
+        })?;
+        watcher.watch(&settings.input, notify::RecursiveMode::NonRecursive)?;
+        let (jobs, job_rcv) = crossbeam::channel::unbounded::<Job>();
+        let (result_snd, results) = crossbeam::channel::unbounded();
+        let cancel = CancelToken::new();
+        let c = cancel.clone();
+        std::thread::spawn(move || {
+            // Ends when the watch is stopped
+            while let Ok(j) = job_rcv.recv() {
+                if c.is_cancelled() || result_snd.send(j.run()).is_err() {
+                    break;
+                }
+            }
+        });
+        self.watching = Some(Watching {
+            _watcher: watcher,
+            events,
+            jobs,
+            results,
+            cancel,
+        });
+        self.pending.clear();
+        Ok(())
+    }
+
+    /// Handle files that appeared, returns errors for the user
+    pub fn update(
+        &mut self,
+        settings: &WatchSettings,
+        pipeline: &Pipeline,
+        calibration: Option<&CalibrationData>,
+    ) -> Vec<String> {
+        let mut errors = Vec::new();
+        let Some(w) = &self.watching else {
+            return errors;
+        };
+        while let Ok(p) = w.events.try_recv() {
+            // Ignore the output folder, in case it is inside the input folder
+            if Self::is_image(&p) && !p.starts_with(&settings.output) {
+                self.pending.insert(p, Instant::now());
+            }
+        }
+        let ready: Vec<PathBuf> = self
+            .pending
+            .iter()
+            .filter(|(_, t)| t.elapsed() > Self::SETTLE)
+            .map(|(p, _)| p.clone())
+            .collect();
+        for p in ready {
+            self.pending.remove(&p);
+            if !p.exists() {
+                continue;
+            }
+            let _ = w.jobs.send(Job {
+                path: p,
+                output: settings.output.clone(),
+                pipeline: pipeline.clone(),
+                calibration: calibration.filter(|_| settings.undistort).cloned(),
+            });
+        }
+        while let Ok(r) = w.results.try_recv() {
+            match r {
+                Ok(p) => {
+                    self.processed += 1;
+                    self.last = Some(p);
+                }
+                Err(e) => {
+                    self.failed += 1;
+                    errors.push(e);
+                }
+            }
+        }
+        errors
+    }
+
+    /// Is the folder being watched
+    pub fn is_watching(&self) -> bool {
+        self.watching.is_some()
+    }
+
+    /// Show the watch folder window contents
+    pub fn show(
+        &mut self,
+        ui: &mut eframe::egui::Ui,
+        settings: &mut WatchSettings,
+    ) -> Option<String> {
+        let mut error = None;
+        ui.add_enabled_ui(self.watching.is_none(), |ui| {
+            eframe::egui::Grid::new("watch_settings").show(ui, |ui| {
+                for (name, dir) in [
+                    (tr!("watch.input"), &mut settings.input),
+                    (tr!("watch.output"), &mut settings.output),
+                ] {
+                    ui.label(name);
+                    let mut text = dir.display().to_string();
+                    if ui.text_edit_singleline(&mut text).changed() {
+                        *dir = PathBuf::from(text);
+                    }
+                    if ui.button(tr!("settings.browse")).clicked() {
+                        if let Some(d) = rfd::FileDialog::new().set_directory(&*dir).pick_folder() {
+                            *dir = d;
+                        }
+                    }
+                    ui.end_row();
+                }
+            });
+        });
+        ui.checkbox(&mut settings.undistort, tr!("watch.undistort"));
+        ui.label(tr!("watch.help"));
+        if self.watching.is_some() {
+            if ui.button(tr!("watch.stop")).clicked() {
+                self.watching = None;
+            }
+        } else if ui.button(tr!("watch.start")).clicked() {
+            if let Err(e) = self.start(settings) {
+                error = Some(tr!("error.watch", error = e.to_string()));
+            }
+        }
+        ui.label(tr!(
+            "watch.counts",
+            processed = self.processed,
+            failed = self.failed,
+            pending = self.pending.len()
+        ));
+        if let Some(p) = &self.last {
+            ui.label(tr!("watch.last", path = p.display()));
+        }
+        error
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use eframe::egui::{Color32, ColorImage};
+    use image_proc::calibration::{FisheyeCalibration, SaveableOpencvMat};
+
+    #[test]
+    fn undistorting_keeps_the_colors() {
+        let dir = std::env::temp_dir().join(format!("image_proc_watch_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("red.png");
+        crate::convert::save_color_image(&path, &ColorImage::new([32, 24], Color32::RED)).unwrap();
+        let camera = SaveableOpencvMat::from_values(
+            3,
+            3,
+            &[100.0, 0.0, 16.0, 0.0, 100.0, 12.0, 0.0, 0.0, 1.0],
+        );
+        let calibrations = [
+            CalibrationData::OpenCvCharuco([
+                camera.clone(),
+                SaveableOpencvMat::from_values(5, 1, &[0.0; 5]),
+            ]),
+            CalibrationData::OpenCvFisheye(FisheyeCalibration([
+                camera,
+                SaveableOpencvMat::from_values(4, 1, &[0.0; 4]),
+            ])),
+        ];
+        for cd in calibrations {
+            let out = Job {
+                path: path.clone(),
+                output: dir.join("processed"),
+                pipeline: Pipeline::default(),
+                calibration: Some(cd),
+            }
+            .run()
+            .unwrap();
+            let img = crate::load_image_file(&out).unwrap();
+            assert_eq!(img[(16, 12)], Color32::RED);
+        }
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
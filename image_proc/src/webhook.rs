@@ -0,0 +1,78 @@
+//! Notifying other systems over http when a calibration completes
+
+/// The webhook settings
+#[derive(Clone, Debug, Default, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
+pub struct WebhookSettings {
+    /// The url that is sent a POST request, empty disables the webhook
+    pub url: String,
+    /// Sent in the Authorization header when not empty, like "Bearer abc123"
+    pub authorization: String,
+}
+
+impl WebhookSettings {
+    /// Show the webhook settings for editing
+    pub fn show(&mut self, ui: &mut eframe::egui::Ui) {
+        eframe::egui::Grid::new("webhook_settings").show(ui, |ui| {
+            ui.label(tr!("webhook.url"));
+            ui.text_edit_singleline(&mut self.url);
+            ui.end_row();
+            ui.label(tr!("webhook.authorization"));
+            ui.add(eframe::egui::TextEdit::singleline(&mut self.authorization).password(true));
+            ui.end_row();
+        });
+        ui.label(tr!("webhook.help"));
+    }
+}
+
+/// The json body sent when a calibration completes
+#[derive(Clone, Debug, serde::Serialize)]
+pub struct CalibrationComplete {
+    /// Always "calibration_complete"
+    pub event: &'static str,
+    /// When the calibration completed, in rfc 3339 format
+    pub timestamp: String,
+    pub camera: String,
+    /// The rms reprojection error in pixels
+    pub rms: f64,
+    pub image_count: usize,
+    /// The absolute path of the saved calibration file, None when saving failed
+    pub file: Option<String>,
+}
+
+impl CalibrationComplete {
+    pub fn new(camera: String, rms: f64, image_count: usize, file: Option<String>) -> Self {
+        Self {
+            event: "calibration_complete",
+            timestamp: chrono::Local::now().to_rfc3339(),
+            camera,
+            rms,
+            image_count,
+            file,
+        }
+    }
+}
+
+/// Send the notification in a background thread, the message for the user is sent when done
+pub fn send(
+    settings: &WebhookSettings,
+    payload: CalibrationComplete,
+    done: crossbeam::channel::Sender<crate::status::TaskResult>,
+) {
+    if settings.url.is_empty() {
+        return;
+    }
+    let settings = settings.clone();
+    std::thread::spawn(move || {
+        let mut r = ureq::post(&settings.url).header("Content-Type", "application/json");
+        if !settings.authorization.is_empty() {
+            r = r.header("Authorization", &settings.authorization);
+        }
+        let r = serde_json::to_string(&payload)
+            .map_err(|e| e.to_string())
+            .and_then(|body| r.send(body).map_err(|e| e.to_string()))
+            .map(|_| tr!("info.webhook", url = settings.url))
+            .map_err(|e| tr!("error.webhook", error = e));
+        let _ = done.send(r);
+    });
+}
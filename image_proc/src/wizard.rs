@@ -0,0 +1,309 @@
+//! A step by step guide through calibrating a camera
+
+use eframe::egui::{Color32, Rect, Sense, Vec2};
+
+use crate::board::BoardParams;
+
+/// The steps of the wizard, in order
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum WizardStep {
+    PrintBoard,
+    BoardParameters,
+    Capture,
+    Calibrate,
+    Review,
+    Save,
+}
+
+impl WizardStep {
+    const ALL: [WizardStep; 6] = [
+        WizardStep::PrintBoard,
+        WizardStep::BoardParameters,
+        WizardStep::Capture,
+        WizardStep::Calibrate,
+        WizardStep::Review,
+        WizardStep::Save,
+    ];
+
+    fn title(&self) -> String {
+        match self {
+            WizardStep::PrintBoard => tr!("wizard.print_board"),
+            WizardStep::BoardParameters => tr!("wizard.board_parameters"),
+            WizardStep::Capture => tr!("wizard.capture"),
+            WizardStep::Calibrate => tr!("wizard.calibrate"),
+            WizardStep::Review => tr!("wizard.review"),
+            WizardStep::Save => tr!("wizard.save"),
+        }
+    }
+
+    fn index(&self) -> usize {
+        Self::ALL.iter().position(|s| s == self).unwrap_or(0)
+    }
+}
+
+/// Something the wizard needs the application to do
+pub enum WizardAction {
+    /// Write an image of the board to the output directory
+    GenerateBoard,
+    /// Start using a new board
+    SetBoard(BoardParams),
+    /// Capture the newest frame from the selected camera
+    Capture,
+    /// Forget all captured frames
+    ClearCaptures,
+    /// Run the calibration
+    Calibrate,
+    /// Store the calibration in the profile of the selected camera
+    SaveProfile,
+}
+
+/// Tracks which areas of the image have had board corners detected in them
+pub struct Coverage {
+    cols: usize,
+    rows: usize,
+    cells: Vec<u32>,
+    /// The number of views that had enough corners to be useful
+    pub good_views: usize,
+}
+
+impl Default for Coverage {
+    fn default() -> Self {
+        Self {
+            cols: 8,
+            rows: 6,
+            cells: vec![0; 48],
+            good_views: 0,
+        }
+    }
+}
+
+impl Coverage {
+    /// Add the detected corners of a view of the given image size
+    pub fn add_view(&mut self, size: [f32; 2], corners: &[[f32; 2]], minimum: usize) {
+        if corners.len() >= minimum {
+            self.good_views += 1;
+        }
+        for c in corners {
+            let x = ((c[0] / size[0]) * self.cols as f32) as usize;
+            let y = ((c[1] / size[1]) * self.rows as f32) as usize;
+            if x < self.cols && y < self.rows {
+                self.cells[y * self.cols + x] += 1;
+            }
+        }
+    }
+
+    /// The fraction of the image that has had corners detected in it
+    pub fn fraction(&self) -> f32 {
+        self.cells.iter().filter(|c| **c > 0).count() as f32 / self.cells.len() as f32
+    }
+
+    /// The number of corners detected in each cell, a row at a time from the top
+    pub fn rows(&self) -> std::slice::Chunks<'_, u32> {
+        self.cells.chunks(self.cols)
+    }
+
+    /// Draw the coverage as a grid, uncovered cells are red
+    pub fn show(&self, ui: &mut eframe::egui::Ui) {
+        let width = ui.available_width().min(320.0);
+        let size = Vec2::new(width, width * self.rows as f32 / self.cols as f32);
+        let (rect, _) = ui.allocate_exact_size(size, Sense::hover());
+        let painter = ui.painter_at(rect);
+        let cell = Vec2::new(
+            rect.width() / self.cols as f32,
+            rect.height() / self.rows as f32,
+        );
+        for y in 0..self.rows {
+            for x in 0..self.cols {
+                let count = self.cells[y * self.cols + x];
+                let color = match count {
+                    0 => Color32::from_rgb(160, 40, 40),
+                    1..=20 => Color32::from_rgb(200, 160, 40),
+                    _ => Color32::from_rgb(40, 160, 40),
+                };
+                let min = rect.min + Vec2::new(x as f32 * cell.x, y as f32 * cell.y);
+                painter.rect_filled(Rect::from_min_size(min, cell).shrink(1.0), 0.0, color);
+            }
+        }
+    }
+}
+
+/// The calibration wizard
+pub struct Wizard {
+    step: WizardStep,
+    /// The board parameters being edited
+    board: BoardParams,
+    /// The number of good views to capture
+    target_views: usize,
+    pub coverage: Coverage,
+}
+
+impl Default for Wizard {
+    fn default() -> Self {
+        Self {
+            step: WizardStep::PrintBoard,
+            board: Default::default(),
+            target_views: 15,
+            coverage: Default::default(),
+        }
+    }
+}
+
+/// The state of the application that the wizard shows
+pub struct WizardContext<'a> {
+    pub camera: Option<String>,
+    pub board: &'a BoardParams,
+    pub board_file: Option<String>,
+    pub captures: usize,
+    pub rms: Option<f64>,
+    pub calibrated: bool,
+}
+
+impl Wizard {
+    /// Restart the wizard from the first step
+    pub fn restart(&mut self, board: &BoardParams) {
+        self.step = WizardStep::PrintBoard;
+        self.board = *board;
+        self.coverage = Default::default();
+    }
+
+    /// Show the wizard, returning what the application needs to do
+    pub fn show(&mut self, ui: &mut eframe::egui::Ui, c: WizardContext) -> Option<WizardAction> {
+        let mut action = None;
+        ui.label(tr!(
+            "wizard.step",
+            step = self.step.index() + 1,
+            total = WizardStep::ALL.len(),
+            title = self.step.title()
+        ));
+        ui.separator();
+        let mut can_continue = true;
+        match self.step {
+            WizardStep::PrintBoard => {
+                ui.label(tr!("wizard.print_instructions"));
+                ui.label(tr!("wizard.mount_instructions"));
+                if ui.button(tr!("wizard.generate_board")).clicked() {
+                    action = Some(WizardAction::GenerateBoard);
+                }
+                if let Some(f) = &c.board_file {
+                    ui.label(tr!("wizard.board_file", file = f));
+                }
+            }
+            WizardStep::BoardParameters => {
+                ui.label(tr!("wizard.measure_instructions"));
+                ui.label(tr!("wizard.measure_hint"));
+                self.board.show(ui);
+                can_continue = self.board.make_board().is_some();
+            }
+            WizardStep::Capture => {
+                match &c.camera {
+                    Some(name) => {
+                        ui.label(tr!("wizard.capturing_from", name = name));
+                    }
+                    None => {
+                        ui.colored_label(Color32::RED, tr!("wizard.no_camera"));
+                    }
+                }
+                ui.label(tr!("wizard.capture_instructions"));
+                ui.label(tr!("wizard.tilt_instructions"));
+                ui.add(
+                    eframe::egui::Slider::new(&mut self.target_views, 5..=60)
+                        .text(tr!("wizard.views_to_capture")),
+                );
+                ui.horizontal(|ui| {
+                    if ui
+                        .add_enabled(
+                            c.camera.is_some(),
+                            eframe::egui::Button::new(tr!("wizard.capture_button")),
+                        )
+                        .clicked()
+                    {
+                        action = Some(WizardAction::Capture);
+                    }
+                    if ui.button(tr!("wizard.start_over")).clicked() {
+                        self.coverage = Default::default();
+                        action = Some(WizardAction::ClearCaptures);
+                    }
+                });
+                ui.label(tr!(
+                    "wizard.views_captured",
+                    captures = c.captures,
+                    good = self.coverage.good_views,
+                    target = self.target_views
+                ));
+                ui.label(tr!(
+                    "wizard.coverage",
+                    percent = format!("{:.0}", self.coverage.fraction() * 100.0)
+                ));
+                self.coverage.show(ui);
+                if self.coverage.fraction() < 0.7 {
+                    ui.label(tr!("wizard.move_board"));
+                }
+                can_continue = self.coverage.good_views >= self.target_views;
+            }
+            WizardStep::Calibrate => {
+                ui.label(tr!("wizard.calibrate_instructions"));
+                if ui.button(tr!("wizard.calibrate_button")).clicked() {
+                    action = Some(WizardAction::Calibrate);
+                }
+                if c.calibrated {
+                    ui.label(tr!("wizard.calibration_complete"));
+                }
+                can_continue = c.calibrated;
+            }
+            WizardStep::Review => match c.rms {
+                Some(rms) => {
+                    ui.label(tr!(
+                        "wizard.reprojection_error",
+                        rms = format!("{:.3}", rms)
+                    ));
+                    if rms < 0.5 {
+                        ui.colored_label(Color32::GREEN, tr!("wizard.good"));
+                    } else if rms < 1.0 {
+                        ui.colored_label(Color32::YELLOW, tr!("wizard.acceptable"));
+                    } else {
+                        ui.colored_label(Color32::RED, tr!("wizard.poor"));
+                    }
+                    ui.label(tr!("wizard.check_lines"));
+                }
+                None => {
+                    ui.colored_label(Color32::RED, tr!("wizard.no_result"));
+                    can_continue = false;
+                }
+            },
+            WizardStep::Save => {
+                ui.label(tr!("wizard.save_instructions"));
+                if ui
+                    .add_enabled(
+                        c.camera.is_some() && c.calibrated,
+                        eframe::egui::Button::new(tr!("wizard.save_profile")),
+                    )
+                    .clicked()
+                {
+                    action = Some(WizardAction::SaveProfile);
+                }
+                can_continue = false;
+            }
+        }
+        ui.separator();
+        ui.horizontal(|ui| {
+            let i = self.step.index();
+            if ui
+                .add_enabled(i > 0, eframe::egui::Button::new(tr!("wizard.back")))
+                .clicked()
+            {
+                self.step = WizardStep::ALL[i - 1];
+            }
+            if ui
+                .add_enabled(can_continue, eframe::egui::Button::new(tr!("wizard.next")))
+                .clicked()
+            {
+                if self.step == WizardStep::BoardParameters && self.board != *c.board {
+                    self.coverage = Default::default();
+                    action = Some(WizardAction::SetBoard(self.board));
+                }
+                self.step = WizardStep::ALL[i + 1];
+            }
+        });
+        action
+    }
+}
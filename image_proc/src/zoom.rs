@@ -0,0 +1,103 @@
+//! Calibrations of a zoom lens at several zoom positions, with the intrinsics and distortion between them interpolated.
+//! The zoom is the value the camera reports for its zoom control, in whatever units the camera uses.
+
+use crate::calibration::{CalibrationData, SaveableOpencvMat};
+
+/// A calibration made at one zoom position
+#[derive(Clone, Debug, serde::Serialize, serde::Deserialize)]
+pub struct ZoomPoint {
+    /// The zoom the camera reported when it was calibrated
+    pub zoom: f64,
+    pub calibration: CalibrationData,
+}
+
+/// The calibrations of a zoom lens, sorted by zoom.
+/// Each element of the camera matrix and each distortion coefficient is interpolated on its own,
+/// linearly between the neighbouring zoom positions. Outside the calibrated range the nearest calibration is used,
+/// and so is it between two calibrations made with different lens models.
+#[derive(Clone, Debug, Default, serde::Serialize, serde::Deserialize)]
+pub struct ZoomProfile {
+    points: Vec<ZoomPoint>,
+    /// The width and height of the images the calibrations were made with
+    pub resolution: Option<[u32; 2]>,
+}
+
+/// The values of a and b mixed, t = 0 is a and t = 1 is b. The shorter one is padded with zeros.
+fn mix(a: &[f64], b: &[f64], t: f64) -> Vec<f64> {
+    (0..a.len().max(b.len()))
+        .map(|i| {
+            let (a, b) = (
+                a.get(i).copied().unwrap_or(0.0),
+                b.get(i).copied().unwrap_or(0.0),
+            );
+            a + (b - a) * t
+        })
+        .collect()
+}
+
+/// The shape of the longer of two matrices, so the mixed values fit
+fn larger(a: &SaveableOpencvMat, b: &SaveableOpencvMat) -> (i32, i32) {
+    let (sa, sb) = (a.size(), b.size());
+    if sb.0 * sb.1 > sa.0 * sa.1 { sb } else { sa }
+}
+
+impl ZoomProfile {
+    /// The calibrated zoom positions, lowest zoom first
+    pub fn points(&self) -> &[ZoomPoint] {
+        &self.points
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.points.is_empty()
+    }
+
+    /// Add a calibration at a zoom, replacing one already at that zoom
+    pub fn add(&mut self, zoom: f64, calibration: CalibrationData) {
+        self.points.retain(|p| p.zoom != zoom);
+        let at = self.points.partition_point(|p| p.zoom < zoom);
+        self.points.insert(at, ZoomPoint { zoom, calibration });
+    }
+
+    /// Remove the calibration at an index of points
+    pub fn remove(&mut self, index: usize) {
+        if index < self.points.len() {
+            self.points.remove(index);
+        }
+    }
+
+    /// The calibration at a zoom, None when nothing has been calibrated
+    pub fn at(&self, zoom: f64) -> Option<CalibrationData> {
+        let first = self.points.first()?;
+        let last = self.points.last()?;
+        if zoom <= first.zoom {
+            return Some(first.calibration.clone());
+        }
+        if zoom >= last.zoom {
+            return Some(last.calibration.clone());
+        }
+        let above = self.points.partition_point(|p| p.zoom <= zoom);
+        let (a, b) = (&self.points[above - 1], &self.points[above]);
+        let t = (zoom - a.zoom) / (b.zoom - a.zoom);
+        let (ca, cb) = (&a.calibration, &b.calibration);
+        if std::mem::discriminant(ca) != std::mem::discriminant(cb) {
+            return Some(if t < 0.5 { ca.clone() } else { cb.clone() });
+        }
+        let (cols, rows) = larger(ca.camera_matrix(), cb.camera_matrix());
+        let cm = SaveableOpencvMat::from_values(
+            cols,
+            rows,
+            &mix(
+                &ca.camera_matrix().values(),
+                &cb.camera_matrix().values(),
+                t,
+            ),
+        );
+        let (cols, rows) = larger(ca.distortion(), cb.distortion());
+        let dc = SaveableOpencvMat::from_values(
+            cols,
+            rows,
+            &mix(&ca.distortion().values(), &cb.distortion().values(), t),
+        );
+        Some(ca.with_intrinsics(cm, dc))
+    }
+}
@@ -0,0 +1,135 @@
+//! Calibrating a motorized zoom lens at several zoom positions and following the zoom the camera reports,
+//! using the calibration interpolated for it
+
+use std::collections::HashMap;
+
+use image_proc::zoom::ZoomProfile;
+
+/// What the user asked for in the zoom lens window
+pub enum ZoomAction {
+    /// Store the current calibration as the one at a zoom
+    Add(f64),
+    /// Forget the calibration at an index of the zoom positions
+    Remove(usize),
+    /// Use the calibration interpolated for a zoom
+    Apply(f64),
+}
+
+/// The zoom of each camera and whether the calibration follows it
+#[derive(Default)]
+pub struct ZoomLens {
+    /// The zoom each camera last reported
+    reported: HashMap<i32, f64>,
+    /// Use the manual zoom instead of the one the camera reports, for cameras that do not report it
+    use_manual: bool,
+    manual: f64,
+    /// Change the calibration when the zoom changes
+    follow: bool,
+    /// The camera and zoom of the calibration in use, so it is only changed when the zoom changes
+    applied: Option<(i32, f64)>,
+}
+
+impl ZoomLens {
+    /// Note the zoom a camera reported
+    pub fn set_reported(&mut self, camera: i32, zoom: f64) {
+        self.reported.insert(camera, zoom);
+    }
+
+    /// The zoom of a camera, None when it does not report one and no manual zoom is used
+    pub fn zoom(&self, camera: i32) -> Option<f64> {
+        if self.use_manual {
+            Some(self.manual)
+        } else {
+            self.reported.get(&camera).copied()
+        }
+    }
+
+    /// The zoom to use the calibration of, when following the zoom and it changed since the calibration was applied
+    pub fn changed(&self, camera: i32, profile: Option<&ZoomProfile>) -> Option<f64> {
+        if !self.follow || profile.is_none_or(|p| p.is_empty()) {
+            return None;
+        }
+        let zoom = self.zoom(camera)?;
+        (self.applied != Some((camera, zoom))).then_some(zoom)
+    }
+
+    /// Note that the calibration for a zoom is in use
+    pub fn applied(&mut self, camera: i32, zoom: f64) {
+        self.applied = Some((camera, zoom));
+    }
+
+    /// Show the zoom and the calibrated zoom positions of the selected camera.
+    /// calibrated is true when there is a calibration to add.
+    pub fn show(
+        &mut self,
+        ui: &mut eframe::egui::Ui,
+        camera: Option<i32>,
+        profile: Option<&ZoomProfile>,
+        calibrated: bool,
+    ) -> Option<ZoomAction> {
+        ui.label(tr!("zoom.instructions"));
+        let Some(camera) = camera else {
+            ui.label(tr!("zoom.no_camera"));
+            return None;
+        };
+        let mut action = None;
+        eframe::egui::Grid::new("zoom_lens").show(ui, |ui| {
+            ui.label(tr!("zoom.reported"));
+            match self.reported.get(&camera) {
+                Some(z) => ui.monospace(format!("{}", z)),
+                None => ui.label(tr!("zoom.not_reported")),
+            };
+            ui.end_row();
+            ui.checkbox(&mut self.use_manual, tr!("zoom.manual"));
+            ui.add_enabled(
+                self.use_manual,
+                eframe::egui::DragValue::new(&mut self.manual).speed(1.0),
+            );
+            ui.end_row();
+        });
+        let zoom = self.zoom(camera);
+        ui.horizontal(|ui| {
+            if ui
+                .add_enabled(
+                    calibrated && zoom.is_some(),
+                    eframe::egui::Button::new(tr!("zoom.add")),
+                )
+                .clicked()
+            {
+                action = zoom.map(ZoomAction::Add);
+            }
+            ui.checkbox(&mut self.follow, tr!("zoom.follow"));
+        });
+        let Some(profile) = profile.filter(|p| !p.is_empty()) else {
+            ui.label(tr!("zoom.empty"));
+            return action;
+        };
+        eframe::egui::Grid::new("zoom_points")
+            .striped(true)
+            .show(ui, |ui| {
+                ui.strong(tr!("zoom.zoom"));
+                ui.strong("fx");
+                ui.strong("fy");
+                ui.label("");
+                ui.end_row();
+                for (n, p) in profile.points().iter().enumerate() {
+                    let k = p.calibration.camera_matrix().values();
+                    ui.monospace(format!("{}", p.zoom));
+                    ui.monospace(format!("{:.1}", k.first().copied().unwrap_or_default()));
+                    ui.monospace(format!("{:.1}", k.get(4).copied().unwrap_or_default()));
+                    if ui.small_button(tr!("zoom.remove")).clicked() {
+                        action = Some(ZoomAction::Remove(n));
+                    }
+                    ui.end_row();
+                }
+            });
+        if !self.follow {
+            if let Some(z) = zoom {
+                if ui.button(tr!("zoom.apply", zoom = z)).clicked() {
+                    action = Some(ZoomAction::Apply(z));
+                }
+            }
+        }
+        action
+    }
+}